@@ -0,0 +1,44 @@
+-- This file should undo anything in `up.sql`
+
+-- Accounts and transactions belong to a plan again, the first plan of their user. Those of users
+-- without plans can't be kept.
+DELETE FROM accounts WHERE NOT EXISTS (SELECT 1 FROM plans WHERE plans.user_id = accounts.user_id);
+
+DROP INDEX transactions_account_id_occurred_at_idx;
+
+ALTER TABLE transactions
+    ADD COLUMN plan_name VARCHAR(64) REFERENCES plans(name) ON DELETE CASCADE,
+    ADD COLUMN type VARCHAR(64),
+    ADD COLUMN from_account INT REFERENCES accounts(id) ON DELETE CASCADE,
+    ADD COLUMN to_account INT REFERENCES accounts(id) ON DELETE CASCADE,
+    ADD COLUMN statement TEXT,
+    ADD COLUMN is_cancelled BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE transactions SET
+    plan_name = (
+        SELECT plans.name FROM plans JOIN accounts ON accounts.user_id = plans.user_id
+        WHERE accounts.id = transactions.account_id
+        ORDER BY plans.name LIMIT 1
+    ),
+    type = CASE WHEN amount < 0 THEN 'expense' ELSE 'income' END,
+    from_account = CASE WHEN amount < 0 THEN account_id END,
+    to_account = CASE WHEN amount < 0 THEN NULL ELSE account_id END,
+    amount = ABS(amount),
+    statement = NULLIF(description, '');
+
+ALTER TABLE transactions
+    ALTER COLUMN plan_name SET NOT NULL,
+    ALTER COLUMN type SET NOT NULL,
+    DROP COLUMN account_id,
+    DROP COLUMN description,
+    DROP COLUMN occurred_at,
+    -- Currencies of transactions added since aren't checked, they may not be in `currencies`
+    ADD FOREIGN KEY (currency) REFERENCES currencies(code) NOT VALID;
+
+ALTER TABLE accounts RENAME COLUMN opening_balance TO balance;
+ALTER TABLE accounts ADD COLUMN plan_name VARCHAR(64) REFERENCES plans(name) ON DELETE CASCADE;
+UPDATE accounts SET plan_name = (
+    SELECT name FROM plans WHERE plans.user_id = accounts.user_id ORDER BY name LIMIT 1
+);
+ALTER TABLE accounts ALTER COLUMN plan_name SET NOT NULL;
+ALTER TABLE accounts DROP COLUMN user_id;
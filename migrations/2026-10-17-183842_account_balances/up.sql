@@ -0,0 +1,43 @@
+-- Your SQL goes here
+
+-- Accounts belong to users rather than plans, and keep the balance they were opened with: their
+-- balance is the opening balance plus the amounts of their transactions
+ALTER TABLE accounts ADD COLUMN user_id INT REFERENCES users(id) ON DELETE CASCADE;
+UPDATE accounts SET user_id = plans.user_id FROM plans WHERE plans.name = accounts.plan_name;
+ALTER TABLE accounts ALTER COLUMN user_id SET NOT NULL;
+ALTER TABLE accounts DROP COLUMN plan_name;
+ALTER TABLE accounts RENAME COLUMN balance TO opening_balance;
+
+-- Transactions belong to one account and are signed, negative amounts leave the account. A
+-- transaction from one account to another becomes a withdrawal from the first and a deposit to
+-- the second. Cancelled transactions never moved money, so they aren't kept.
+DELETE FROM transactions WHERE is_cancelled OR (from_account IS NULL AND to_account IS NULL);
+
+ALTER TABLE transactions
+    ADD COLUMN account_id INT REFERENCES accounts(id) ON DELETE CASCADE,
+    ADD COLUMN description TEXT NOT NULL DEFAULT '',
+    ADD COLUMN occurred_at DATE;
+
+INSERT INTO transactions (plan_name, type, to_account, amount, currency, statement, created_at)
+SELECT plan_name, type, to_account, amount, currency, statement, created_at
+FROM transactions
+WHERE from_account IS NOT NULL AND to_account IS NOT NULL;
+
+UPDATE transactions SET
+    account_id = COALESCE(from_account, to_account),
+    amount = CASE WHEN from_account IS NOT NULL THEN -amount ELSE amount END,
+    description = COALESCE(statement, ''),
+    occurred_at = created_at::DATE;
+
+ALTER TABLE transactions
+    ALTER COLUMN account_id SET NOT NULL,
+    ALTER COLUMN occurred_at SET NOT NULL,
+    DROP CONSTRAINT transactions_currency_fkey,
+    DROP COLUMN plan_name,
+    DROP COLUMN type,
+    DROP COLUMN from_account,
+    DROP COLUMN to_account,
+    DROP COLUMN statement,
+    DROP COLUMN is_cancelled;
+
+CREATE INDEX transactions_account_id_occurred_at_idx ON transactions (account_id, occurred_at);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+-- Imported rows held back for review because they look like a duplicate of `duplicate_of`
+CREATE TABLE import_pending (
+    id SERIAL PRIMARY KEY,
+    account_id INT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    amount DECIMAL(10, 2) NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    occurred_at DATE NOT NULL,
+    duplicate_of INT REFERENCES transactions(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TABLE categories (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(64) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, name)
+);
+
+ALTER TABLE transactions ADD COLUMN category_id INT REFERENCES categories(id) ON DELETE SET NULL;
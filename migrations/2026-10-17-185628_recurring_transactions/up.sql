@@ -0,0 +1,23 @@
+-- Your SQL goes here
+
+CREATE TABLE recurring_transactions (
+    id SERIAL PRIMARY KEY,
+    account_id INT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    category_id INT REFERENCES categories(id) ON DELETE SET NULL,
+    amount DECIMAL(10, 2) NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    cadence VARCHAR(16) NOT NULL CHECK (cadence IN ('weekly', 'monthly', 'yearly')),
+    -- Day of the month for monthly and yearly cadences, clamped to the last day of short months
+    day_of_month SMALLINT CHECK (day_of_month BETWEEN 1 AND 31),
+    -- Day of the week for weekly cadences, 0 is Monday
+    weekday SMALLINT CHECK (weekday BETWEEN 0 AND 6),
+    next_run_on DATE NOT NULL,
+    end_on DATE DEFAULT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- The recurring transaction a transaction was generated from, at most once per date
+ALTER TABLE transactions
+    ADD COLUMN recurring_id INT REFERENCES recurring_transactions(id) ON DELETE SET NULL,
+    ADD UNIQUE (recurring_id, occurred_at);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+CREATE TABLE goals (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(64) NOT NULL,
+    target_amount DECIMAL(10, 2) NOT NULL CHECK (target_amount > 0),
+    target_date DATE NOT NULL,
+    -- When set, progress is the balance of the account instead of the sum of tagged transactions
+    linked_account_id INT REFERENCES accounts(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE transactions ADD COLUMN goal_id INT REFERENCES goals(id) ON DELETE SET NULL;
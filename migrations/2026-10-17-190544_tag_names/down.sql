@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX tags_user_id_name_idx;
+
+UPDATE tags SET icon = '' WHERE icon IS NULL;
+ALTER TABLE tags
+    ALTER COLUMN name TYPE VARCHAR(64),
+    ALTER COLUMN icon DROP DEFAULT,
+    ALTER COLUMN icon SET NOT NULL;
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+
+ALTER TABLE tags
+    ALTER COLUMN name TYPE VARCHAR(40) USING LEFT(name, 40),
+    ALTER COLUMN icon DROP NOT NULL,
+    ALTER COLUMN icon SET DEFAULT NULL;
+
+-- Tags of a user whose names only differ by case are merged into the oldest of them
+CREATE TEMPORARY TABLE merged_tags ON COMMIT DROP AS
+SELECT id, kept FROM (
+    SELECT id, MIN(id) OVER (PARTITION BY user_id, LOWER(name)) AS kept FROM tags
+) duplicates
+WHERE id <> kept;
+
+INSERT INTO account_tags (account_id, tag_id)
+SELECT account_tags.account_id, merged_tags.kept
+FROM account_tags JOIN merged_tags ON merged_tags.id = account_tags.tag_id
+ON CONFLICT DO NOTHING;
+
+INSERT INTO transaction_tags (transaction_id, tag_id)
+SELECT transaction_tags.transaction_id, merged_tags.kept
+FROM transaction_tags JOIN merged_tags ON merged_tags.id = transaction_tags.tag_id
+ON CONFLICT DO NOTHING;
+
+DELETE FROM tags WHERE id IN (SELECT id FROM merged_tags);
+
+-- Tag names are unique per user regardless of case
+CREATE UNIQUE INDEX tags_user_id_name_idx ON tags (user_id, LOWER(name));
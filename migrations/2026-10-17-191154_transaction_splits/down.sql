@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE transaction_splits;
+
+ALTER TABLE budgets
+    DROP COLUMN category_id,
+    DROP CONSTRAINT budgets_amount_check,
+    DROP CONSTRAINT budgets_interval_check,
+    -- Currencies of budgets added since aren't checked, they may not be in `currencies`
+    ADD FOREIGN KEY (currency) REFERENCES currencies(code) NOT VALID;
@@ -0,0 +1,35 @@
+-- Your SQL goes here
+
+-- Budgets limit the spending of a category. Each budget gets the category of its user named like
+-- it, which is created if the user has none.
+ALTER TABLE budgets ADD COLUMN category_id INT REFERENCES categories(id) ON DELETE CASCADE;
+
+INSERT INTO categories (user_id, name)
+SELECT DISTINCT plans.user_id, budgets.name
+FROM budgets JOIN plans ON plans.name = budgets.plan_name
+ON CONFLICT (user_id, name) DO NOTHING;
+
+UPDATE budgets SET category_id = categories.id
+FROM plans, categories
+WHERE plans.name = budgets.plan_name
+    AND categories.user_id = plans.user_id
+    AND categories.name = budgets.name;
+
+UPDATE budgets SET interval = LOWER(interval);
+
+ALTER TABLE budgets
+    ALTER COLUMN category_id SET NOT NULL,
+    ADD CHECK (amount > 0),
+    ADD CHECK (interval IN ('monthly', 'yearly')),
+    DROP CONSTRAINT budgets_currency_fkey;
+
+-- Parts of a transaction's amount attributed to other categories. When a transaction has splits,
+-- they add up to its amount and reports use them instead of the transaction's category.
+CREATE TABLE transaction_splits (
+    id SERIAL PRIMARY KEY,
+    transaction_id INT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+    category_id INT REFERENCES categories(id) ON DELETE SET NULL,
+    amount DECIMAL(10, 2) NOT NULL
+);
+
+CREATE INDEX transaction_splits_transaction_id_idx ON transaction_splits (transaction_id);
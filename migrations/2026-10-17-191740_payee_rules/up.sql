@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+-- Rules that normalize bank descriptions into payees and categorize transactions from them
+CREATE TABLE payee_rules (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    pattern VARCHAR(200) NOT NULL,
+    match_kind VARCHAR(16) NOT NULL CHECK (match_kind IN ('contains', 'prefix', 'regex')),
+    normalized_payee VARCHAR(64) NOT NULL,
+    default_category_id INT REFERENCES categories(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- The payee of a matching payee rule, if any
+ALTER TABLE transactions ADD COLUMN payee VARCHAR(64) DEFAULT NULL;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE exchange_rates;
+
+ALTER TABLE users
+    DROP COLUMN preferred_currency,
+    DROP COLUMN is_admin;
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+ALTER TABLE users
+    -- The currency reports are converted into
+    ADD COLUMN preferred_currency VARCHAR(3) NOT NULL DEFAULT 'CAD',
+    ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Rates maintained by administrators, at most one per pair and day. A rate is the amount of the
+-- quote currency that one unit of the base currency buys.
+CREATE TABLE exchange_rates (
+    base VARCHAR(3) NOT NULL,
+    quote VARCHAR(3) NOT NULL,
+    as_of DATE NOT NULL,
+    rate DECIMAL(20, 10) NOT NULL CHECK (rate > 0),
+    PRIMARY KEY (base, quote, as_of),
+    CHECK (base <> quote)
+);
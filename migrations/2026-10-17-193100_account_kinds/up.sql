@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Balances of credit accounts are owed, and count as liabilities in net worth
+ALTER TABLE accounts
+    ADD COLUMN kind VARCHAR(16) NOT NULL DEFAULT 'asset' CHECK (kind IN ('asset', 'credit'));
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+-- Receipts and other files attached to transactions, stored under the server's data directory
+CREATE TABLE attachments (
+    id SERIAL PRIMARY KEY,
+    transaction_id INT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(64) NOT NULL,
+    size_bytes BIGINT NOT NULL CHECK (size_bytes >= 0),
+    -- Path of the file relative to the attachments directory
+    storage_path VARCHAR(255) NOT NULL UNIQUE,
+    uploaded_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX attachments_transaction_id_idx ON attachments (transaction_id);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+-- Notes written on a plan, such as why a budget changed. Bodies are plain text.
+CREATE TABLE plan_notes (
+    id SERIAL PRIMARY KEY,
+    plan_name VARCHAR(64) NOT NULL REFERENCES plans(name) ON DELETE CASCADE,
+    author_user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    body TEXT NOT NULL CHECK (char_length(body) BETWEEN 1 AND 10000),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX plan_notes_plan_name_created_at_idx ON plan_notes (plan_name, created_at);
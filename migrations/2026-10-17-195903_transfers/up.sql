@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+-- Shared by the two legs of a transfer between accounts, which reports leave out
+ALTER TABLE transactions ADD COLUMN transfer_id UUID DEFAULT NULL;
+
+CREATE INDEX transactions_transfer_id_idx ON transactions (transfer_id) WHERE transfer_id IS NOT NULL;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+-- Security-relevant events, kept when the user they are about is deleted
+CREATE TABLE audit_events (
+    id SERIAL PRIMARY KEY,
+    user_id INT DEFAULT NULL,
+    event VARCHAR(32) NOT NULL CHECK (event IN ('login_success', 'login_failure', 'lockout', 'password_change', 'session_revoked', 'user_deleted', 'plan_deleted')),
+    metadata JSONB NOT NULL DEFAULT '{}',
+    ip VARCHAR(45) DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX audit_events_user_id_created_at_idx ON audit_events (user_id, created_at);
+CREATE INDEX audit_events_created_at_idx ON audit_events (created_at);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+-- Endpoints that are sent the events of a user, such as created transactions
+CREATE TABLE webhooks (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    url VARCHAR(2048) NOT NULL,
+    secret VARCHAR(128) NOT NULL,
+    events TEXT[] NOT NULL CHECK (cardinality(events) > 0),
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    -- Deliveries that failed in a row, the webhook is disabled after too many
+    failed_deliveries INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX webhooks_user_id_idx ON webhooks (user_id);
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE category_alerts;
+
+-- Notifications about no plan can't be kept
+DELETE FROM notifications WHERE plan_name IS NULL;
+
+ALTER TABLE notifications
+    DROP COLUMN user_id,
+    DROP COLUMN data,
+    DROP CONSTRAINT notifications_status_check,
+    ALTER COLUMN plan_name SET NOT NULL;
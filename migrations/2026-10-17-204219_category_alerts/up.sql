@@ -0,0 +1,29 @@
+-- Your SQL goes here
+
+-- Notifications are shown to a user, such as a category alert, and only some are about a plan.
+-- `data` holds the details as JSON.
+ALTER TABLE notifications ADD COLUMN user_id INT REFERENCES users(id) ON DELETE CASCADE;
+UPDATE notifications SET user_id = plans.user_id FROM plans WHERE plans.name = notifications.plan_name;
+UPDATE notifications SET status = 'read' WHERE status NOT IN ('unread', 'read');
+
+ALTER TABLE notifications
+    ALTER COLUMN user_id SET NOT NULL,
+    ALTER COLUMN plan_name DROP NOT NULL,
+    ADD COLUMN data JSONB NOT NULL DEFAULT '{}',
+    ADD CHECK (status IN ('unread', 'read'));
+
+CREATE INDEX notifications_user_id_created_at_idx ON notifications (user_id, created_at);
+
+-- Monthly spending limits of categories. `last_alerted_month` is the first day of the last month
+-- the alert fired in, so it fires at most once a month.
+CREATE TABLE category_alerts (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category_id INT NOT NULL UNIQUE REFERENCES categories(id) ON DELETE CASCADE,
+    monthly_limit DECIMAL(10, 2) NOT NULL CHECK (monthly_limit > 0),
+    notify_at_percent INT NOT NULL DEFAULT 100 CHECK (notify_at_percent BETWEEN 1 AND 100),
+    last_alerted_month DATE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX category_alerts_user_id_idx ON category_alerts (user_id);
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+
+-- Reconciliations of accounts against bank statements. The opening balance is the ending balance
+-- of the previous finished reconciliation, or the opening balance of the account.
+CREATE TABLE reconciliations (
+    id SERIAL PRIMARY KEY,
+    account_id INT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    statement_date DATE NOT NULL,
+    opening_balance DECIMAL(10, 2) NOT NULL,
+    ending_balance DECIMAL(10, 2) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMP DEFAULT NULL
+);
+
+-- An account has at most one reconciliation in progress
+CREATE UNIQUE INDEX reconciliations_open_account_id_idx ON reconciliations (account_id)
+    WHERE finished_at IS NULL;
+
+-- Transactions cleared in a reconciliation, which can't be changed once it is finished
+CREATE TABLE cleared_transactions (
+    transaction_id INT PRIMARY KEY REFERENCES transactions(id) ON DELETE CASCADE,
+    reconciliation_id INT NOT NULL REFERENCES reconciliations(id) ON DELETE CASCADE
+);
+
+CREATE INDEX cleared_transactions_reconciliation_id_idx ON cleared_transactions (reconciliation_id);
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+
+-- Reports rendered on a schedule and written to the reports directory. Budget reports compare
+-- the budgets of `plan_name`, other reports have none.
+CREATE TABLE scheduled_reports (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(32) NOT NULL CHECK (kind IN ('monthly_summary', 'budget_vs_actual')),
+    plan_name VARCHAR(64) REFERENCES plans(name) ON DELETE CASCADE,
+    cadence VARCHAR(16) NOT NULL CHECK (cadence IN ('daily', 'weekly', 'monthly')),
+    format VARCHAR(8) NOT NULL CHECK (format IN ('csv', 'json')),
+    destination VARCHAR(16) NOT NULL DEFAULT 'file' CHECK (destination IN ('file')),
+    next_run_at TIMESTAMP NOT NULL,
+    last_run_at TIMESTAMP DEFAULT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'succeeded', 'failed')),
+    last_error TEXT DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CHECK ((kind = 'budget_vs_actual') = (plan_name IS NOT NULL))
+);
+
+CREATE INDEX scheduled_reports_user_id_idx ON scheduled_reports (user_id);
+CREATE INDEX scheduled_reports_next_run_at_idx ON scheduled_reports (next_run_at);
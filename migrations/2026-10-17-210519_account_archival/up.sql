@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- Archived accounts are closed: hidden from listings and current figures, kept in history
+ALTER TABLE accounts ADD COLUMN archived_at TIMESTAMP DEFAULT NULL;
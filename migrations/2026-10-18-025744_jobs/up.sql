@@ -0,0 +1,21 @@
+-- Your SQL goes here
+
+-- Work done in the background, such as webhook deliveries, claimed by one worker at a time
+CREATE TABLE jobs (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR(32) NOT NULL CHECK (kind IN ('webhook_delivery')),
+    payload JSONB NOT NULL DEFAULT '{}',
+    -- The job isn't claimed before, retries are scheduled later each time
+    run_at TIMESTAMP NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- The worker running the job, which is claimed again if it doesn't finish in time
+    locked_by VARCHAR(64) DEFAULT NULL,
+    locked_at TIMESTAMP DEFAULT NULL,
+    completed_at TIMESTAMP DEFAULT NULL,
+    -- Set once every attempt failed, the job is kept for inspection but never run again
+    failed_at TIMESTAMP DEFAULT NULL,
+    last_error TEXT DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX jobs_pending_run_at_idx ON jobs (run_at) WHERE completed_at IS NULL AND failed_at IS NULL;
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE import_jobs;
+
+DELETE FROM jobs WHERE kind = 'import';
+ALTER TABLE jobs
+    DROP CONSTRAINT jobs_kind_check,
+    ADD CONSTRAINT jobs_kind_check CHECK (kind IN ('webhook_delivery'));
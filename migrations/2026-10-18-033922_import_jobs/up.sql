@@ -0,0 +1,29 @@
+-- Your SQL goes here
+
+ALTER TABLE jobs
+    DROP CONSTRAINT jobs_kind_check,
+    ADD CONSTRAINT jobs_kind_check CHECK (kind IN ('webhook_delivery', 'import'));
+
+-- Imports of transactions, run in batches by the workers of the job queue
+CREATE TABLE import_jobs (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    account_id INT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    status VARCHAR(16) NOT NULL DEFAULT 'queued'
+        CHECK (status IN ('queued', 'running', 'done', 'failed', 'cancelled')),
+    rows_total INTEGER NOT NULL,
+    -- Rows of the batches committed so far, the import resumes after them
+    rows_processed INTEGER NOT NULL DEFAULT 0,
+    inserted INTEGER NOT NULL DEFAULT 0,
+    flagged INTEGER NOT NULL DEFAULT 0,
+    skipped INTEGER NOT NULL DEFAULT 0,
+    errors JSONB NOT NULL DEFAULT '[]',
+    -- Set by the user, the import stops before its next batch
+    cancel_requested BOOLEAN NOT NULL DEFAULT FALSE,
+    error TEXT DEFAULT NULL,
+    started_at TIMESTAMP DEFAULT NULL,
+    finished_at TIMESTAMP DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX import_jobs_account_id_idx ON import_jobs (account_id);
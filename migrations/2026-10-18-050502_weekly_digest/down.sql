@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM jobs WHERE kind = 'weekly_digest';
+ALTER TABLE jobs
+    DROP CONSTRAINT jobs_kind_check,
+    ADD CONSTRAINT jobs_kind_check CHECK (kind IN ('webhook_delivery', 'import'));
+
+ALTER TABLE users DROP COLUMN digest_enabled;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+
+-- Whether a summary of the week is sent as a notification every Monday
+ALTER TABLE users ADD COLUMN digest_enabled BOOLEAN NOT NULL DEFAULT TRUE;
+
+ALTER TABLE jobs
+    DROP CONSTRAINT jobs_kind_check,
+    ADD CONSTRAINT jobs_kind_check CHECK (kind IN ('webhook_delivery', 'import', 'weekly_digest'));
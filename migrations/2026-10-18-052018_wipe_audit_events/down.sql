@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM audit_events WHERE event IN ('transactions_wiped', 'data_wiped');
+ALTER TABLE audit_events
+    DROP CONSTRAINT audit_events_event_check,
+    ADD CONSTRAINT audit_events_event_check CHECK (event IN ('login_success', 'login_failure', 'lockout', 'password_change', 'session_revoked', 'user_deleted', 'plan_deleted'));
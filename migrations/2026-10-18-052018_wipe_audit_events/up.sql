@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE audit_events
+    DROP CONSTRAINT audit_events_event_check,
+    ADD CONSTRAINT audit_events_event_check CHECK (event IN ('login_success', 'login_failure', 'lockout', 'password_change', 'session_revoked', 'user_deleted', 'plan_deleted', 'transactions_wiped', 'data_wiped'));
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE budgets DROP COLUMN version;
+ALTER TABLE plans DROP COLUMN version;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+
+-- `version` is incremented whenever the plan or one of its budgets or notes changes, and is sent
+-- as the ETag of the plan
+ALTER TABLE plans ADD COLUMN version INT NOT NULL DEFAULT 1;
+
+-- Incremented by every update, which must be made against the current version
+ALTER TABLE budgets ADD COLUMN version INT NOT NULL DEFAULT 1;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users
+    DROP COLUMN week_starts_on,
+    DROP COLUMN fiscal_month_start_day;
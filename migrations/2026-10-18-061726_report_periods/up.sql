@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+ALTER TABLE users
+    -- The day weekly reports start on
+    ADD COLUMN week_starts_on VARCHAR(16) NOT NULL DEFAULT 'monday' CHECK (
+        week_starts_on IN ('monday', 'tuesday', 'wednesday', 'thursday', 'friday', 'saturday', 'sunday')
+    ),
+    -- The day of the month monthly reports start on, at most 28 so every month has it
+    ADD COLUMN fiscal_month_start_day INTEGER NOT NULL DEFAULT 1 CHECK (fiscal_month_start_day BETWEEN 1 AND 28);
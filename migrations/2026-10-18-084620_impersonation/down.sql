@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM audit_events WHERE event IN ('impersonation_started', 'impersonated_request');
+ALTER TABLE audit_events
+    DROP CONSTRAINT audit_events_event_check,
+    ADD CONSTRAINT audit_events_event_check CHECK (event IN ('login_success', 'login_failure', 'lockout', 'password_change', 'session_revoked', 'user_deleted', 'plan_deleted', 'transactions_wiped', 'data_wiped'));
+
+-- Support sessions can't be told apart from the user's own anymore, so they end
+DELETE FROM sessions WHERE impersonator_user_id IS NOT NULL;
+ALTER TABLE sessions DROP COLUMN impersonator_user_id;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+-- The administrator acting as the user, for support sessions
+ALTER TABLE sessions
+    ADD COLUMN impersonator_user_id INT DEFAULT NULL REFERENCES users(id) ON DELETE CASCADE;
+
+ALTER TABLE audit_events
+    DROP CONSTRAINT audit_events_event_check,
+    ADD CONSTRAINT audit_events_event_check CHECK (event IN ('login_success', 'login_failure', 'lockout', 'password_change', 'session_revoked', 'user_deleted', 'plan_deleted', 'transactions_wiped', 'data_wiped', 'impersonation_started', 'impersonated_request'));
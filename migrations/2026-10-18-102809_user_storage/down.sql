@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE user_storage;
+
+ALTER TABLE attachments DROP COLUMN status;
+
+DELETE FROM jobs WHERE kind = 'attachment_cleanup';
+ALTER TABLE jobs
+    DROP CONSTRAINT jobs_kind_check,
+    ADD CONSTRAINT jobs_kind_check CHECK (kind IN ('webhook_delivery', 'import', 'weekly_digest'));
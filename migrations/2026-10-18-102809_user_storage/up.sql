@@ -0,0 +1,23 @@
+-- Your SQL goes here
+
+ALTER TABLE jobs
+    DROP CONSTRAINT jobs_kind_check,
+    ADD CONSTRAINT jobs_kind_check CHECK (kind IN ('webhook_delivery', 'import', 'weekly_digest', 'attachment_cleanup'));
+
+-- Set to 'missing' when the cleanup job finds no file at the storage path
+ALTER TABLE attachments
+    ADD COLUMN status VARCHAR(16) NOT NULL DEFAULT 'stored' CHECK (status IN ('stored', 'missing'));
+
+-- Total size of the files attached to the transactions of each user, updated in the transactions
+-- adding and deleting attachments so quotas don't sum them on every upload
+CREATE TABLE user_storage (
+    user_id INT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    attachment_bytes_used BIGINT NOT NULL DEFAULT 0 CHECK (attachment_bytes_used >= 0)
+);
+
+INSERT INTO user_storage (user_id, attachment_bytes_used)
+SELECT accounts.user_id, SUM(attachments.size_bytes)
+FROM attachments
+    JOIN transactions ON transactions.id = attachments.transaction_id
+    JOIN accounts ON accounts.id = transactions.account_id
+GROUP BY accounts.user_id;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- Whether what's left of the amount of a month, or overspent, carries into the next months
+ALTER TABLE budgets ADD COLUMN rollover BOOLEAN NOT NULL DEFAULT FALSE;
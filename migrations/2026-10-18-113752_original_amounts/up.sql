@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+-- Amounts of transactions are in the currency of their account, which balances and reports use.
+-- The amount in the currency the transaction was made in, when it isn't the account's, is kept
+-- for display.
+ALTER TABLE transactions
+    ADD COLUMN original_amount DECIMAL(10, 2) DEFAULT NULL,
+    ADD COLUMN original_currency VARCHAR(3) DEFAULT NULL,
+    ADD CHECK ((original_amount IS NULL) = (original_currency IS NULL));
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE notification_channels;
+
+DELETE FROM jobs WHERE kind = 'notification_push';
+ALTER TABLE jobs
+    DROP CONSTRAINT jobs_kind_check,
+    ADD CONSTRAINT jobs_kind_check CHECK (kind IN ('webhook_delivery', 'import', 'weekly_digest', 'attachment_cleanup'));
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+ALTER TABLE jobs
+    DROP CONSTRAINT jobs_kind_check,
+    ADD CONSTRAINT jobs_kind_check CHECK (kind IN ('webhook_delivery', 'import', 'weekly_digest', 'attachment_cleanup', 'notification_push'));
+
+-- Where the notifications of a user are pushed besides the app, at most one channel per user.
+-- `auth_header` is sent as the `Authorization` header of pushes and never returned.
+CREATE TABLE notification_channels (
+    user_id INT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    url VARCHAR(2048) NOT NULL,
+    auth_header VARCHAR(1024) DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+-- The amounts of a budget over time, each in effect from its day until the next one. The amount
+-- of the budget is the one in effect last, months before the first one use the first one.
+CREATE TABLE budget_amounts (
+    id SERIAL PRIMARY KEY,
+    budget_id INT NOT NULL REFERENCES budgets(id) ON DELETE CASCADE,
+    amount DECIMAL(10, 2) NOT NULL CHECK (amount > 0),
+    effective_from DATE NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (budget_id, effective_from)
+);
+
+-- Existing budgets have had their current amount since they started
+INSERT INTO budget_amounts (budget_id, amount, effective_from)
+SELECT id, amount, start_date FROM budgets;
@@ -0,0 +1,61 @@
+use chrono::{Datelike, NaiveDate};
+
+use crate::database::{
+    connection::DbConn,
+    models::{category_alerts::CategoryAlert, webhooks::WebhookEvent},
+};
+use crate::events::{EventBus, UserEvent};
+use crate::jobs::webhooks::WebhookDispatcher;
+
+/// Fire the alerts of the categories transactions were created in
+///
+/// Alerts watch the spending of the current month, so transactions dated in other months are
+/// ignored. The notification of each alert that fires is pushed to the event streams of the user
+/// and queued for their webhooks. Evaluating never fails the operation that created the
+/// transactions, errors are only logged.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `webhooks` - The dispatcher of the events of users to their webhooks
+/// * `events` - The bus publishing the events of users to their open streams
+/// * `user_id` - ID of the user the transactions belong to
+/// * `created` - The category and date of each created transaction, or of each of its splits
+pub fn check(
+    conn: &mut DbConn,
+    webhooks: &WebhookDispatcher,
+    events: &EventBus,
+    user_id: i32,
+    created: impl IntoIterator<Item = (i32, NaiveDate)>,
+) {
+    let today = chrono::Local::now().date_naive();
+    let mut category_ids: Vec<i32> = created
+        .into_iter()
+        .filter(|(_, date)| date.year() == today.year() && date.month() == today.month())
+        .map(|(category_id, _)| category_id)
+        .collect();
+    category_ids.sort_unstable();
+    category_ids.dedup();
+    if category_ids.is_empty() {
+        return;
+    }
+
+    let alerts = match CategoryAlert::for_categories(conn, user_id, &category_ids) {
+        Ok(alerts) => alerts,
+        Err(e) => {
+            tracing::warn!("Category alerts of user {user_id} weren't evaluated ({e})");
+            return;
+        }
+    };
+
+    for alert in alerts {
+        match alert.evaluate(conn, today) {
+            Ok(Some(notification)) => {
+                webhooks.notify(conn, user_id, WebhookEvent::CategoryAlert, &notification);
+                events.publish(user_id, UserEvent::CategoryAlert, &notification);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Category alert {} wasn't evaluated ({e})", alert.id()),
+        }
+    }
+}
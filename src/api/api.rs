@@ -1,71 +1,895 @@
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
-use axum::http::HeaderValue;
-use axum::Router;
+use axum::http::{HeaderName, HeaderValue};
+use axum::{
+    extract::{DefaultBodyLimit, Request},
+    middleware, Extension, Router,
+};
 
 use axum::http::{header, Method};
+use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot::Receiver;
 
-use tracing::info;
-use utoipa::OpenApi;
+use utoipa::openapi::header::HeaderBuilder;
+use utoipa::openapi::path::{OperationBuilder, PathItemType};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::{
+    ContentBuilder, ObjectBuilder, Ref, RefOr, ResponseBuilder, SchemaType, Server,
+};
+use utoipa::{Modify, OpenApi, ToSchema};
 use utoipa_swagger_ui::SwaggerUi;
 
+use crate::api::legacy;
+use crate::api::listener::RestListener;
+use crate::api::pagination::{PlanPage, UserPage};
+use crate::api::responses::MessageResponse;
+use crate::api::state::AppState;
+use crate::api::tls::TlsCertificates;
+use crate::config::build_info::BuildInfo;
+use crate::config::startup::{Startup, StartupPhase};
 use crate::database::connection::DbPool;
-use crate::routes::auth::LoginInfo;
-use crate::routes::users::{CreateUser, UpdateUser};
-use crate::routes::vitals::Vitals;
+use crate::database::models::accounts::{Account, AccountKind, BalancePoint, Granularity};
+use crate::database::models::attachments::{Attachment, AttachmentStatus};
+use crate::database::models::audit_events::{AuditEvent, AuditEventKind};
+use crate::database::models::budgets::{Budget, BudgetInterval, EffectiveAmount};
+use crate::database::models::categories::Category;
+use crate::database::models::category_alerts::CategoryAlert;
+use crate::database::models::exchange_rates::ExchangeRate;
+use crate::database::models::goals::GoalProgress;
+use crate::database::models::import_jobs::{ImportJob, ImportStatus};
+use crate::database::models::import_pending::PendingImport;
+use crate::database::models::notification_channels::ChannelConfig;
+use crate::database::models::notifications::Notification;
+use crate::database::models::payee_rules::{MatchKind, PayeeRule};
+use crate::database::models::plan_notes::PlanNote;
+use crate::database::models::plans::{Plan, PlanSummary};
+use crate::database::models::reconciliations::{Reconciliation, ReconciliationCandidate};
+use crate::database::models::recurring_transactions::{Cadence, RecurringTransaction};
+use crate::database::models::scheduled_reports::{
+    ReportCadence, ReportDestination, ReportFormat, ReportKind, RunStatus, ScheduledReport,
+};
+use crate::database::models::sessions::keys::JwtKeys;
+use crate::database::models::tags::{Tag, TagUsage};
+use crate::database::models::transactions::{
+    SplitInput, SplitTransaction, Transaction, TransactionSplit,
+};
+use crate::database::models::transfers::Transfer;
+use crate::database::models::users::{UserPublic, UserSort};
+use crate::database::models::webhooks::{Webhook, WebhookEvent};
+use crate::database::schema_check::SchemaStatus;
+use crate::errors::{ErrorBody, ErrorCode};
+use crate::events::EventBus;
+use crate::export::{statement::StatementFormat, transactions::ExportFormat};
+use crate::import::bootstrap::{
+    BootstrapAccount, BootstrapFile, BootstrapTransaction, DuplicateNames,
+};
+use crate::import::csv::{AmountColumns, ColumnMapping, ColumnRef, RowError};
+use crate::jobs::queue::Services;
+use crate::jobs::webhooks::WebhookDispatcher;
+use crate::maintenance::{Maintenance, MaintenanceMode, MaintenanceStatus};
+use crate::merge::CategoryMerge;
+use crate::metrics::Metrics;
+use crate::middleware::access_log::{AccessLog, AccessLogConfig};
+use crate::middleware::body_limit::BodyLimits;
+use crate::middleware::forwarded::TrustedProxies;
+use crate::middleware::request_id::REQUEST_ID_HEADER;
+use crate::middleware::security_headers::SecurityHeadersConfig;
+use crate::middleware::timeout::Timeouts;
+use crate::quotas::{ResourceUsage, Usage};
+use crate::rate_limit::{RateLimitConfig, RateLimiter};
+use crate::reports::anomalies::Anomaly;
+use crate::reports::budgets::BudgetStatus;
+use crate::reports::categories::{CategoryBreakdown, CategoryShare};
+use crate::reports::forecast::{AccountProjection, ForecastMonth};
+use crate::reports::monthly::{CategorySummary, MonthlySummary};
+use crate::reports::net_worth::{NetWorth, NetWorthPoint};
+use crate::routes::accounts::{
+    AccountBalance, AccountImport, CreateAccount, FailedAccount, ImportedAccount, SaveTransaction,
+};
+use crate::routes::admin::{
+    AuditPage, Impersonation, SaveExchangeRates, SavedExchangeRates, SetMaintenance,
+};
+use crate::routes::attachments::AttachmentForm;
+use crate::routes::auth::{LoginInfo, SessionConfig, SessionInfo};
+use crate::routes::budgets::SaveBudget;
+use crate::routes::categories::{CreateCategory, MergeCategory, SaveAlert};
+use crate::routes::goals::SaveGoal;
+use crate::routes::imports::ImportForm;
+use crate::routes::index::ApiIndex;
+use crate::routes::notes::SaveNote;
+use crate::routes::notifications::SetNotificationChannel;
+use crate::routes::reconciliations::{
+    ClearTransactions, ReconciliationDetails, StartReconciliation,
+};
+use crate::routes::recurring::{CreateRecurring, UpdateRecurring};
+use crate::routes::rules::{RuleApplication, SaveRule};
+use crate::routes::scheduled_reports::SaveScheduledReport;
+use crate::routes::transactions::{BulkCategorization, BulkCategorize, BulkFilter};
+use crate::routes::transfers::{CreateTransfer, UpdateTransfer};
+use crate::routes::users::{
+    ChangePassword, CreateUser, SetDigest, SetPeriods, SetPreferredCurrency, UpdateUser,
+    UserCreatedResponse,
+};
+use crate::routes::vitals::{Readiness, ReplicaVitals, Shutdown, Vitals};
+use crate::routes::webhooks::SaveWebhook;
+use crate::search::SearchResults;
+use crate::storage::attachments::AttachmentStore;
+use crate::wipe::{DataWipe, TransactionWipe};
 use crate::{errors::AppError, routes};
-use tower_http::cors::CorsLayer;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::decompression::RequestDecompressionLayer;
+
+/// Prefix of the paths of the current version of the API
+pub const API_PREFIX: &str = "/api/v1";
+
+/// Paths served at the root rather than under `API_PREFIX`, as infrastructure expects them there
+const ROOT_PATHS: [&str; 6] = [
+    "/vitals", "/livez", "/readyz", "/hello", "/metrics", "/version",
+];
 
 #[derive(OpenApi)]
 #[openapi(
-  components(schemas(Vitals, CreateUser, UpdateUser, LoginInfo)),
+  servers((url = "/api/v1", description = "The current version of the API")),
+  modifiers(&SecurityAddon, &RootPathsAddon, &InvalidBodyAddon, &ErrorBodyAddon, &OptionsAddon),
+  components(schemas(
+    ErrorCode, ErrorBody, ApiIndex, BuildInfo, Vitals, ReplicaVitals, Readiness, StartupPhase, SchemaStatus, CreateUser, UpdateUser, ChangePassword, LoginInfo, SessionInfo, Impersonation, CreateAccount, SaveTransaction, AccountBalance,
+    ColumnMapping, ColumnRef, AmountColumns, RowError, ImportJob, ImportStatus, CreateCategory,
+    CreateRecurring, UpdateRecurring, SaveGoal, GoalProgress, TagUsage,
+    SplitInput, SplitTransaction, TransactionSplit, SaveBudget, BudgetStatus, EffectiveAmount, MonthlySummary,
+    CategorySummary, SaveRule, RuleApplication, SetPreferredCurrency, SetDigest, SetPeriods, ExchangeRate,
+    SaveExchangeRates, SavedExchangeRates, NetWorth, NetWorthPoint, ForecastMonth,
+    AccountProjection, Anomaly, Attachment, SaveNote, PlanNote, CreateTransfer, UpdateTransfer,
+    Transfer, SearchResults, AuditEvent, AuditPage, SaveWebhook, Webhook, WebhookEvent,
+    SaveAlert, CategoryAlert, Notification, SetNotificationChannel, ChannelConfig, StartReconciliation, ClearTransactions, Reconciliation,
+    ReconciliationCandidate, ReconciliationDetails, SaveScheduledReport, ScheduledReport, ReportKind,
+    ReportCadence, ReportFormat, ReportDestination, RunStatus, CategoryBreakdown, CategoryShare,
+    SetMaintenance, MaintenanceMode, MaintenanceStatus, ApiDocs, Plan, PlanSummary, PlanPage, UserPublic, UserPage, Usage, ResourceUsage,
+    MessageResponse, UserCreatedResponse, Account, BalancePoint, Transaction, Budget, Category,
+    RecurringTransaction, PayeeRule, Tag, PendingImport, TransactionWipe, DataWipe, BulkFilter,
+    BulkCategorize, BulkCategorization, MergeCategory, CategoryMerge, BootstrapFile,
+    BootstrapAccount, BootstrapTransaction, DuplicateNames, AccountImport, ImportedAccount,
+    FailedAccount, AccountKind, Granularity, AuditEventKind, BudgetInterval, MatchKind, Cadence,
+    UserSort, ExportFormat, StatementFormat, ImportForm, AttachmentForm, AttachmentStatus
+  )),
   paths(
+    // Index
+    crate::routes::index::get_index,
     // Vitals
-    crate::routes::vitals::get_vitals, crate::routes::vitals::hello,
+    crate::routes::vitals::get_vitals, crate::routes::vitals::get_liveness,
+    crate::routes::vitals::get_readiness, crate::routes::vitals::hello,
+    crate::routes::version::get_version,
+    // Metrics
+    crate::routes::metrics::get_metrics,
     // Users
     crate::routes::users::get_user, crate::routes::users::create_user, crate::routes::users::update_user, crate::routes::users::delete_user,
+    crate::routes::users::change_password,
+    crate::routes::users::set_preferred_currency, crate::routes::users::set_digest,
+    crate::routes::users::set_periods,
+    crate::routes::users::get_usage, crate::routes::users::wipe_data,
+    crate::routes::users::export_archive,
     // Auth
-    crate::routes::auth::login, crate::routes::auth::logout, crate::routes::auth::refresh,
+    crate::routes::auth::login, crate::routes::auth::logout, crate::routes::auth::all_sessions,
     // Plans
-    crate::routes::plans::all_plans, crate::routes::plans::create_plan, crate::routes::plans::delete_plan
+    crate::routes::plans::all_plans, crate::routes::plans::get_plan, crate::routes::plans::create_plan,
+    crate::routes::plans::delete_plan,
+    // Plan notes
+    crate::routes::notes::all_notes, crate::routes::notes::create_note, crate::routes::notes::update_note,
+    crate::routes::notes::delete_note,
+    // Budgets
+    crate::routes::budgets::all_budgets, crate::routes::budgets::create_budget, crate::routes::budgets::get_budget,
+    crate::routes::budgets::update_budget, crate::routes::budgets::delete_budget, crate::routes::budgets::get_budget_report,
+    crate::routes::budgets::budget_history,
+    // Accounts
+    crate::routes::accounts::all_accounts, crate::routes::accounts::create_account, crate::routes::accounts::archive_account,
+    crate::routes::accounts::unarchive_account, crate::routes::accounts::get_balance,
+    crate::routes::accounts::get_balance_history, crate::routes::accounts::all_transactions, crate::routes::accounts::create_transaction,
+    crate::routes::accounts::update_transaction, crate::routes::accounts::delete_transaction,
+    crate::routes::accounts::delete_transactions, crate::routes::accounts::import_accounts,
+    crate::routes::transactions::bulk_categorize,
+    // Transfers
+    crate::routes::transfers::create_transfer, crate::routes::transfers::update_transfer,
+    // Reconciliations
+    crate::routes::reconciliations::all_reconciliations, crate::routes::reconciliations::start_reconciliation,
+    crate::routes::reconciliations::get_reconciliation, crate::routes::reconciliations::clear_transactions,
+    crate::routes::reconciliations::finish_reconciliation,
+    // Attachments
+    crate::routes::attachments::all_attachments, crate::routes::attachments::upload_attachment,
+    crate::routes::attachments::get_attachment, crate::routes::attachments::delete_attachment,
+    // Imports
+    crate::routes::imports::import_transactions, crate::routes::imports::all_pending,
+    crate::routes::imports::confirm_pending, crate::routes::imports::discard_pending,
+    crate::routes::imports::all_imports, crate::routes::imports::get_import,
+    crate::routes::imports::cancel_import,
+    // Exports
+    crate::routes::exports::export_transactions,
+    crate::routes::exports::get_statement,
+    // Categories
+    crate::routes::categories::all_categories, crate::routes::categories::create_category,
+    crate::routes::categories::all_alerts, crate::routes::categories::set_alert,
+    crate::routes::categories::delete_alert, crate::routes::categories::merge_category,
+    // Notifications
+    crate::routes::notifications::all_notifications, crate::routes::notifications::read_notification,
+    crate::routes::notifications::get_notification_channel,
+    crate::routes::notifications::set_notification_channel,
+    crate::routes::notifications::delete_notification_channel,
+    crate::routes::notifications::test_notification_channel,
+    // Recurring transactions
+    crate::routes::recurring::all_recurring, crate::routes::recurring::create_recurring,
+    crate::routes::recurring::update_recurring, crate::routes::recurring::delete_recurring,
+    // Goals
+    crate::routes::goals::all_goals, crate::routes::goals::get_goal, crate::routes::goals::create_goal,
+    crate::routes::goals::update_goal, crate::routes::goals::delete_goal,
+    // Tags
+    crate::routes::tags::all_tags, crate::routes::tags::add_tags, crate::routes::tags::remove_tags,
+    // Reports
+    crate::routes::reports::get_monthly_summary, crate::routes::reports::get_weekly_summary,
+    crate::routes::reports::get_category_breakdown,
+    crate::routes::reports::get_net_worth,
+    crate::routes::reports::get_net_worth_history, crate::routes::reports::get_forecast,
+    // Scheduled reports
+    crate::routes::scheduled_reports::all_scheduled_reports, crate::routes::scheduled_reports::get_scheduled_report,
+    crate::routes::scheduled_reports::create_scheduled_report, crate::routes::scheduled_reports::update_scheduled_report,
+    crate::routes::scheduled_reports::delete_scheduled_report,
+    // Payee rules
+    crate::routes::rules::all_rules, crate::routes::rules::create_rule, crate::routes::rules::update_rule,
+    crate::routes::rules::delete_rule, crate::routes::rules::apply_rule,
+    // Webhooks
+    crate::routes::webhooks::all_webhooks, crate::routes::webhooks::create_webhook,
+    crate::routes::webhooks::update_webhook, crate::routes::webhooks::delete_webhook,
+    // Event stream
+    crate::routes::events::stream_events,
+    // Search
+    crate::routes::search::search,
+    // Administration
+    crate::routes::admin::save_exchange_rates, crate::routes::admin::get_audit_events,
+    crate::routes::admin::set_maintenance, crate::routes::admin::all_users,
+    crate::routes::admin::impersonate_user
   ),
   tags(
+    (name="index", description="Endpoint listing the resources of the API"),
     (name="vitals", description="Endpoints for retrieving system vitals"),
+    (name="metrics", description="Endpoint for scraping the metrics of the server"),
     (name="users", description="Endpoints for managing users"),
     (name="auth", description="Endpoints for user authentication"),
-    (name="plans", description="Endpoints for managing user plans")
+    (name="plans", description="Endpoints for managing user plans"),
+    (name="notes", description="Endpoints for managing the notes of plans"),
+    (name="budgets", description="Endpoints for managing the budgets of plans"),
+    (name="accounts", description="Endpoints for managing accounts and their transactions"),
+    (name="transactions", description="Endpoints for changing transactions across accounts"),
+    (name="transfers", description="Endpoints for moving money between accounts"),
+    (name="reconciliations", description="Endpoints for reconciling accounts against bank statements"),
+    (name="attachments", description="Endpoints for managing files attached to transactions"),
+    (name="categories", description="Endpoints for managing transaction categories"),
+    (name="notifications", description="Endpoints for reading the notifications of users"),
+    (name="goals", description="Endpoints for managing savings goals"),
+    (name="tags", description="Endpoints for tagging transactions"),
+    (name="reports", description="Endpoints for reporting on transactions"),
+    (name="scheduled-reports", description="Endpoints for managing reports that are rendered on a schedule"),
+    (name="rules", description="Endpoints for managing payee rules"),
+    (name="webhooks", description="Endpoints for managing webhooks that are sent the events of users"),
+    (name="events", description="Endpoints for streaming changes to the data of users"),
+    (name="search", description="Endpoints for searching across transactions, plans and notes"),
+    (name="admin", description="Endpoints for administrators")
   )
 )]
 struct ApiDoc;
 
+/// Adds the ways requests are authenticated to the OpenAPI document
+///
+/// Users are authenticated by the `token` cookie set on login, and metrics scrapers by the
+/// metrics token when the server is configured with one.
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "cookieAuth",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("token"))),
+        );
+        components.add_security_scheme(
+            "metricsToken",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+/// Get the OpenAPI document of the API, served at `/api-docs/openapi.json`
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+/// Documents that the vitals and metrics are served at the root, not under `API_PREFIX`
+struct RootPathsAddon;
+
+impl Modify for RootPathsAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            if ROOT_PATHS.contains(&path.as_str()) {
+                item.servers = Some(vec![Server::new("/")]);
+            }
+        }
+    }
+}
+
+/// Documents that JSON request bodies that can't be read are answered with `422`, see
+/// `ValidatedJson`
+struct InvalidBodyAddon;
+
+impl Modify for InvalidBodyAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let response = ResponseBuilder::new()
+            .description("The JSON body can't be read, or is missing a field")
+            .content(
+                "application/json",
+                ContentBuilder::new()
+                    .schema(Ref::from_schema_name("ErrorBody"))
+                    .build(),
+            )
+            .build();
+        let operations = openapi
+            .paths
+            .paths
+            .values_mut()
+            .flat_map(|item| item.operations.values_mut());
+        for operation in operations {
+            let takes_json = operation
+                .request_body
+                .as_ref()
+                .is_some_and(|body| body.content.contains_key("application/json"));
+            if takes_json {
+                operation
+                    .responses
+                    .responses
+                    .entry("422".to_owned())
+                    .or_insert_with(|| response.clone().into());
+            }
+        }
+    }
+}
+
+/// Documents that errors are answered with an `ErrorBody`, for the error responses of routes that
+/// don't give their body
+struct ErrorBodyAddon;
+
+impl Modify for ErrorBodyAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let content = ContentBuilder::new()
+            .schema(Ref::from_schema_name("ErrorBody"))
+            .build();
+        let responses = openapi
+            .paths
+            .paths
+            .values_mut()
+            .flat_map(|item| item.operations.values_mut())
+            .flat_map(|operation| operation.responses.responses.iter_mut());
+        for (status, response) in responses {
+            let is_error = status.starts_with('4') || status.starts_with('5');
+            if let (true, RefOr::T(response)) = (is_error, response) {
+                if response.content.is_empty() {
+                    response
+                        .content
+                        .insert("application/json".to_owned(), content.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Documents that every path answers `OPTIONS` with the methods it accepts, see
+/// `middleware::method_not_allowed::options`
+struct OptionsAddon;
+
+impl Modify for OptionsAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let allow = HeaderBuilder::new()
+            .schema(ObjectBuilder::new().schema_type(SchemaType::String))
+            .description(Some(
+                "The methods the path accepts, e.g. `GET, HEAD, OPTIONS`",
+            ))
+            .build();
+        let operation = OperationBuilder::new()
+            .summary(Some("List the methods the path accepts"))
+            .response(
+                "204",
+                ResponseBuilder::new()
+                    .description("The methods are listed in the `Allow` header")
+                    .header("Allow", allow),
+            )
+            .build();
+        for item in openapi.paths.paths.values_mut() {
+            item.operations
+                .entry(PathItemType::Options)
+                .or_insert_with(|| operation.clone());
+        }
+    }
+}
+
 /// Creates a new instance of the REST application.
 ///
+/// Routes of the API are served under `API_PREFIX`, but for the vitals, metrics and documentation,
+/// which is served to everyone, to administrators or not at all, as configured.
+/// The paths from before the API was versioned are still served for one release, by forwarding
+/// them to the versioned routes, with responses marked as deprecated. Unknown paths and methods
+/// are answered with the JSON errors of the API, and `OPTIONS` requests with the methods of their
+/// route.
+///
+/// # Arguments
+///
+/// * `state` - The state shared by the routes, including how requests are limited and timed out,
+///   and responses are compressed.
+/// * `attachments` - The store of files attached to transactions.
+/// * `webhooks` - The dispatcher of the events of users to their webhooks.
+/// * `shutdown` - Whether the server is shutting down, reported by the readiness endpoint.
+/// * `startup` - The phases of startup completed so far, reported by the readiness endpoint.
+/// * `metrics` - The metrics of the server, recorded for every request.
+/// * `maintenance` - Which requests the server answers, set by administrators.
+///
 /// # Returns
 ///
 /// * `Router` - The router with the REST API endpoints.
-pub fn app(pool: Arc<DbPool>) -> Router {
-    let cors = CorsLayer::new()
-        .allow_origin("http://localhost:3000".parse::<HeaderValue>().unwrap()) // Replace with your frontend's URL
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
-        .allow_credentials(true);
-    Router::new()
-        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
-        .merge(routes::vitals::create_route())
-        .merge(routes::users::create_route())
-        .merge(routes::auth::create_route(pool.clone()))
-        .merge(routes::plans::create_route(pool.clone()))
-        .layer(cors)
-        .with_state(pool)
+pub fn app(
+    state: AppState,
+    attachments: Arc<AttachmentStore>,
+    webhooks: Arc<WebhookDispatcher>,
+    shutdown: Arc<Shutdown>,
+    startup: Arc<Startup>,
+    metrics: Arc<Metrics>,
+    maintenance: Arc<Maintenance>,
+) -> Router {
+    let http = state.config.clone();
+    let live = state.live.clone();
+    let timeout =
+        |duration| middleware::from_fn_with_state(duration, crate::middleware::timeout::timeout);
+    // Routes answer within the default timeout, but for vitals, which must answer faster, and
+    // uploads, which can take longer
+    let routes = Router::new()
+        .merge(routes::index::create_route())
+        .merge(routes::users::create_route(state.clone()))
+        .merge(routes::auth::create_route(state.clone()))
+        .merge(routes::plans::create_route(state.clone()))
+        .merge(routes::notes::create_route(state.clone()))
+        .merge(routes::accounts::create_route(state.clone()))
+        .merge(routes::exports::create_route(state.clone()))
+        .merge(routes::categories::create_route(state.clone()))
+        .merge(routes::recurring::create_route(state.clone()))
+        .merge(routes::goals::create_route(state.clone()))
+        .merge(routes::tags::create_route(state.clone()))
+        .merge(routes::budgets::create_route(state.clone()))
+        .merge(routes::reports::create_route(state.clone()))
+        .merge(routes::scheduled_reports::create_route(state.clone()))
+        .merge(routes::rules::create_route(state.clone()))
+        .merge(routes::admin::create_route(state.clone()))
+        .merge(routes::transactions::create_route(state.clone()))
+        .merge(routes::transfers::create_route(state.clone()))
+        .merge(routes::reconciliations::create_route(state.clone()))
+        .merge(routes::search::create_route(state.clone()))
+        .merge(routes::webhooks::create_route(state.clone()))
+        .merge(routes::events::create_route(state.clone()))
+        .merge(routes::notifications::create_route(state.clone()))
+        .layer(timeout(http.timeouts.default));
+    let uploads = Router::new()
+        .merge(routes::imports::create_route(
+            state.clone(),
+            http.body.uploads,
+        ))
+        .merge(routes::attachments::create_route(
+            state.clone(),
+            http.body.uploads,
+        ))
+        .layer(timeout(http.timeouts.uploads));
+    let api = Router::new()
+        .merge(routes)
+        .merge(uploads)
+        .fallback(legacy::unknown_route);
+    let versioned = Router::new()
+        .nest(API_PREFIX, api.clone())
+        .layer(middleware::from_fn(legacy::keep_matched_path))
+        .with_state(state.clone());
+
+    let docs = ApiDocs::router(http.api_docs, state.clone());
+
+    let app = Router::new()
+        .merge(docs)
+        .merge(routes::vitals::create_route().layer(timeout(http.timeouts.vitals)))
+        .merge(routes::version::create_route().layer(timeout(http.timeouts.vitals)))
+        .merge(routes::metrics::create_route().layer(timeout(http.timeouts.default)))
+        .nest(API_PREFIX, api)
+        .fallback(move |req: Request| legacy::forward(versioned.clone(), req))
+        .layer(Extension(attachments))
+        .layer(Extension(webhooks))
+        .layer(Extension(shutdown))
+        .layer(Extension(startup))
+        .layer(Extension(maintenance.clone()))
+        .layer(Extension(metrics.clone()))
+        .layer(DefaultBodyLimit::max(http.body.default))
+        .layer(middleware::from_fn(
+            crate::middleware::body_limit::payload_too_large,
+        ))
+        .layer(middleware::from_fn(
+            crate::middleware::method_not_allowed::method_not_allowed,
+        ))
+        .layer(middleware::from_fn_with_state(
+            maintenance,
+            crate::middleware::maintenance::maintenance,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.live.rate_limiter(),
+            crate::middleware::rate_limit::rate_limit,
+        ))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(http.trusted_proxies),
+            crate::middleware::forwarded::forwarded,
+        ))
+        .layer(middleware::from_fn_with_state(
+            metrics,
+            crate::middleware::metrics::track_requests,
+        ))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(AccessLog::new(http.access_log)),
+            crate::middleware::access_log::access_log,
+        ))
+        .layer(middleware::from_fn(crate::middleware::locale::locale))
+        .layer(middleware::from_fn(
+            crate::middleware::request_id::request_id,
+        ))
+        .layer(RequestDecompressionLayer::new())
+        .layer(http.compression.layer())
+        .with_state(state);
+
+    app.clone()
+        .layer(CorsConfig::layer(live))
+        .layer(middleware::from_fn_with_state(
+            app,
+            crate::middleware::method_not_allowed::options,
+        ))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(http.security_headers),
+            crate::middleware::security_headers::security_headers,
+        ))
+}
+
+/// Options of the REST server
+#[derive(Debug)]
+pub struct RestOptions {
+    /// The webhooks, events and reports cache, shared with the worker of the queue
+    pub services: Services,
+    /// The bearer token required to read the metrics, if any
+    pub metrics_token: Option<String>,
+    /// The keys signing and verifying the tokens of sessions
+    pub jwt_keys: JwtKeys,
+    /// How requests are limited and timed out, and responses are compressed
+    pub http: HttpConfig,
+    /// The certificate HTTPS is served with, plain HTTP is served without one
+    pub tls: Option<Arc<TlsCertificates>>,
+}
+
+/// How the server handles the requests of clients
+#[derive(Debug, Clone, Default)]
+pub struct HttpConfig {
+    /// How many requests each client can make
+    pub rate: RateLimitConfig,
+    /// How large request bodies can be
+    pub body: BodyLimits,
+    /// Which responses are compressed
+    pub compression: CompressionConfig,
+    /// How long requests have to be answered
+    pub timeouts: Timeouts,
+    /// How long sessions last and how their cookies are set
+    pub sessions: SessionConfig,
+    /// Which origins browsers can call the API from
+    pub cors: CorsConfig,
+    /// Which requests are logged
+    pub access_log: AccessLogConfig,
+    /// Which security headers are set on responses
+    pub security_headers: SecurityHeadersConfig,
+    /// The reverse proxies whose forwarded headers tell the address and scheme of clients
+    pub trusted_proxies: TrustedProxies,
+    /// Who the Swagger UI and the OpenAPI document are served to
+    pub api_docs: ApiDocs,
+}
+
+/// The settings of the server that change when the configuration is reloaded, read by the
+/// middlewares on every request, see `config::reload`
+#[derive(Debug)]
+pub struct LiveConfig {
+    rate_limiter: Arc<RateLimiter>,
+    allowed_origins: RwLock<Vec<HeaderValue>>,
+}
+
+impl LiveConfig {
+    /// Start from the settings the server is configured with at startup
+    pub fn new(http: &HttpConfig) -> Self {
+        Self {
+            rate_limiter: Arc::new(RateLimiter::new(http.rate)),
+            allowed_origins: RwLock::new(http.cors.allowed_origins.clone()),
+        }
+    }
+
+    /// Get the limiter of the requests of each client
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        self.rate_limiter.clone()
+    }
+
+    /// Replace how many requests each client can make
+    pub fn set_rate_limits(&self, rate: RateLimitConfig) {
+        self.rate_limiter.set_config(rate);
+    }
+
+    /// Get the origins browsers can call the API from
+    pub fn allowed_origins(&self) -> Vec<HeaderValue> {
+        self.allowed_origins
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Replace the origins browsers can call the API from
+    pub fn set_cors(&self, cors: CorsConfig) {
+        *self
+            .allowed_origins
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = cors.allowed_origins;
+    }
+
+    /// Whether browsers can call the API from an origin
+    fn allows_origin(&self, origin: &HeaderValue) -> bool {
+        self.allowed_origins
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(origin)
+    }
+}
+
+/// The origins browsers can call the API from, with the cookie of the session
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// The allowed origins, e.g. `https://finance.example.com`
+    pub allowed_origins: Vec<HeaderValue>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![HeaderValue::from_static("http://localhost:3000")],
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Build the layer answering the preflight requests of browsers, from the origins allowed
+    /// when the request is made
+    fn layer(live: Arc<LiveConfig>) -> CorsLayer {
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::predicate(move |origin, _| {
+                live.allows_origin(origin)
+            }))
+            .allow_methods([
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ])
+            .allow_headers([
+                header::CONTENT_TYPE,
+                header::AUTHORIZATION,
+                HeaderName::from_static(REQUEST_ID_HEADER),
+            ])
+            .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)])
+            .allow_credentials(true)
+    }
+}
+
+/// Who the Swagger UI, at `/swagger-ui`, and the OpenAPI document, at `/api-docs/openapi.json`,
+/// are served to
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiDocs {
+    /// Served to everyone
+    #[default]
+    Open,
+    /// Served to the sessions of administrators only
+    Admin,
+    /// Not served, their paths are unknown
+    Disabled,
+}
+
+impl ApiDocs {
+    /// Build the routes serving the documentation
+    ///
+    /// Requests without a session are answered with `401` and those of other users with `403`
+    /// when the documentation is for administrators only.
+    fn router(self, state: AppState) -> Router<AppState> {
+        let docs = Router::new()
+            .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", openapi()));
+        match self {
+            ApiDocs::Open => docs,
+            // Layers run from the last one added, so the session is set before the user is checked
+            ApiDocs::Admin => docs
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    crate::middleware::auth::admin_auth,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state,
+                    crate::middleware::auth::jwt_auth,
+                )),
+            ApiDocs::Disabled => Router::new(),
+        }
+    }
+}
+
+/// Compression of responses, with gzip or brotli as the client accepts
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionConfig {
+    /// Whether responses are compressed at all
+    pub enabled: bool,
+    /// Size in bytes a response must be over to be compressed, streamed responses of unknown size
+    /// are always compressed
+    pub min_size: u16,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_size: 1024,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Build the layer compressing responses
+    ///
+    /// Event streams, images and metrics are never compressed, event streams so each event is sent
+    /// as soon as it is published and metrics as scrapers expect them as they are.
+    fn layer(&self) -> CompressionLayer<impl Predicate> {
+        CompressionLayer::new()
+            .gzip(self.enabled)
+            .br(self.enabled)
+            .deflate(false)
+            .zstd(false)
+            .compress_when(
+                SizeAbove::new(self.min_size)
+                    .and(NotForContentType::GRPC)
+                    .and(NotForContentType::IMAGES)
+                    .and(NotForContentType::SSE)
+                    .and(NotForContentType::const_new(routes::metrics::CONTENT_TYPE))
+                    // Archives are compressed already
+                    .and(NotForContentType::const_new(
+                        crate::export::archive::CONTENT_TYPE,
+                    )),
+            )
+    }
+}
+
+/// The REST application, built before the server listens so that it only accepts requests it can
+/// answer
+pub struct RestApp {
+    router: Router,
+    live: Arc<LiveConfig>,
+    events: Arc<EventBus>,
+    tls: Option<Arc<TlsCertificates>>,
+}
+
+impl RestApp {
+    /// Build the REST application, with the state shared by its routes
+    ///
+    /// # Arguments
+    ///
+    /// * `data_dir` - The directory in which uploaded files and the maintenance mode are stored.
+    /// * `pool` - The database connection pool.
+    /// * `options` - The services shared with the worker of the queue, how metrics are read and how
+    ///   requests are limited.
+    /// * `shutdown` - Whether the server is shutting down, reported by the readiness endpoint.
+    /// * `startup` - The phases of startup completed so far, reported by the readiness endpoint.
+    ///
+    /// # Returns
+    ///
+    /// The application, or an error if the maintenance mode couldn't be loaded
+    pub fn build(
+        data_dir: &str,
+        pool: Arc<DbPool>,
+        options: RestOptions,
+        shutdown: Arc<Shutdown>,
+        startup: Arc<Startup>,
+    ) -> Result<Self, AppError> {
+        let maintenance = Arc::new(Maintenance::load(
+            std::path::Path::new(data_dir).join("maintenance.json"),
+        )?);
+        if maintenance.status().mode != MaintenanceMode::Off {
+            tracing::warn!(
+                "Starting in {:?} maintenance, set by an administrator",
+                maintenance.status().mode
+            );
+        }
+        let Services {
+            webhooks,
+            events,
+            reports,
+            quotas,
+            attachments,
+            clock,
+        } = options.services;
+        let live = Arc::new(LiveConfig::new(&options.http));
+        let state = AppState {
+            pool,
+            live: live.clone(),
+            config: options.http,
+            jwt_keys: Arc::new(options.jwt_keys),
+            events: events.clone(),
+            reports,
+            quotas,
+            clock,
+        };
+        let router = app(
+            state,
+            attachments,
+            webhooks,
+            shutdown,
+            startup,
+            Arc::new(Metrics::new(options.metrics_token)),
+            maintenance,
+        );
+
+        Ok(Self {
+            router,
+            live,
+            events,
+            tls: options.tls,
+        })
+    }
+
+    /// Get the router of the application
+    pub fn router(&self) -> Router {
+        self.router.clone()
+    }
+
+    /// Get the settings that change when the configuration is reloaded
+    pub fn live_config(&self) -> Arc<LiveConfig> {
+        self.live.clone()
+    }
+
+    /// Serve the application on a listener, over TCP or a Unix socket, with TLS if a certificate
+    /// was given
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The listener the REST server serves, see `RestListener::bind`.
+    /// * `rx` - A Receiver from a one-shot channel for shutdown signal communication.
+    ///
+    /// # Returns
+    ///
+    /// Once a message is received over the channel and the requests in progress are answered,
+    /// `Ok(())`, or the error the server failed with
+    pub async fn serve(self, listener: RestListener, rx: Receiver<()>) -> Result<(), AppError> {
+        let events = self.events;
+        // Event streams are ended on shutdown so the server isn't held up by them
+        listener
+            .serve(self.router, self.tls, async move {
+                rx.await.ok();
+                events.close();
+            })
+            .await
+    }
 }
 
 /// Starts the REST server.
 ///
 /// # Arguments
 ///
-/// * `rest_port` - The port number on which the REST server will listen.
+/// * `listener` - The listener the REST server serves, see `RestListener::bind`.
+/// * `data_dir` - The directory in which uploaded files and the maintenance mode are stored.
 /// * `rx` - A Receiver from a one-shot channel for shutdown signal communication.
+/// * `pool` - The database connection pool.
+/// * `options` - The services shared with the worker of the queue, how metrics are read and how
+///   requests are limited.
+/// * `shutdown` - Whether the server is shutting down, reported by the readiness endpoint.
 ///
 /// # Returns
 ///
@@ -73,37 +897,340 @@ pub fn app(pool: Arc<DbPool>) -> Router {
 ///
 /// # Behavior
 ///
-/// This function first formats the bind address using the provided port number.
-/// It then creates a TCP listener bound to this address.
-///
-/// The function creates a new instance of the REST application using the `rest_app` function.
+/// The function builds the REST application, see `RestApp::build`, for a server whose listener
+/// is already bound, so its startup is finished.
 ///
-/// It then creates a new server using the `axum::serve` function, passing in the listener and the application.
+/// It then serves the application on the listener, over TCP or a Unix socket, with TLS if a
+/// certificate is given.
 /// The server is configured to shut down gracefully when a message is received over the one-shot channel.
 ///
 /// The function then starts the server and waits for it to complete.
 /// If the server encounters an error, it is converted to an `anyhow::Error` and returned.
 pub async fn start_rest_server(
-    rest_port: u16,
+    listener: RestListener,
+    data_dir: &str,
     rx: Receiver<()>,
     pool: Arc<DbPool>,
+    options: RestOptions,
+    shutdown: Arc<Shutdown>,
 ) -> Result<(), AppError> {
-    let bind_address = format!("0.0.0.0:{rest_port}");
-    info!("Listening on http://localhost:{rest_port}");
-    let listener = tokio::net::TcpListener::bind(bind_address).await?;
+    RestApp::build(
+        data_dir,
+        pool,
+        options,
+        shutdown,
+        Arc::new(Startup::finished()),
+    )?
+    .serve(listener, rx)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+
+    use super::*;
+    use crate::api::test_utils::{TestApp, TestServer};
+
+    #[tokio::test]
+    async fn test_start_rest_server() {
+        // Port `0` picks a free port, known before the server starts
+        let TestServer { addr, stop, task } = TestServer::start().await;
+        assert_ne!(addr.port(), 0);
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!("http://{addr}/livez"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        // Requests go through the middleware of the API, and down to the database
+        let response = client
+            .get(format!("http://{addr}/api/v1/accounts"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        assert!(response.headers().contains_key(REQUEST_ID_HEADER));
+        let body: serde_json::Value =
+            serde_json::from_str(&response.text().await.unwrap()).unwrap();
+        assert_eq!(body["error"], "invalid_token");
+        let response = client
+            .get(format!("http://{addr}/vitals"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        stop.send(()).unwrap();
+        task.await.unwrap().unwrap();
+        assert!(client
+            .get(format!("http://{addr}/livez"))
+            .send()
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_openapi_document() {
+        let app = TestApp::new();
+
+        let (status, doc) = app
+            .request(Method::GET, "/api-docs/openapi.json", None)
+            .await;
+        assert_eq!(status, 200);
+        // Every route is documented, and only routes are
+        let paths = doc["paths"].as_object().unwrap();
+        assert_eq!(paths.len(), 88);
+        assert!(paths.contains_key("/"));
+        assert!(paths.contains_key("/auth/login"));
+        assert!(paths.contains_key("/plans/{name}"));
+        assert!(!paths.contains_key("/auth/refresh"));
+
+        // Paths are relative to the versioned API, but for the vitals and metrics
+        assert_eq!(doc["servers"][0]["url"], "/api/v1");
+        assert_eq!(paths["/metrics"]["servers"][0]["url"], "/");
+        assert_eq!(paths["/readyz"]["servers"][0]["url"], "/");
+        assert_eq!(paths["/version"]["servers"][0]["url"], "/");
+        assert!(paths["/accounts"]["servers"].is_null());
+
+        // Operations taking JSON document how unreadable bodies are answered
+        let invalid_body = &paths["/accounts"]["post"]["responses"]["422"];
+        assert_eq!(
+            invalid_body["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ErrorBody"
+        );
+        assert!(paths["/accounts"]["get"]["responses"]["422"].is_null());
+
+        // Every path lists the methods it accepts
+        assert!(paths.values().all(|item| item["options"].is_object()));
+        assert!(
+            paths["/users/{id}"]["options"]["responses"]["204"]["headers"]["Allow"].is_object()
+        );
+
+        // The codes of errors are listed with their slugs
+        let schemas = &doc["components"]["schemas"];
+        assert_eq!(schemas["ErrorCode"]["type"], "integer");
+        assert!(schemas["ErrorCode"]["enum"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!(40006)));
+        assert!(schemas["ErrorCode"]["description"]
+            .as_str()
+            .unwrap()
+            .contains("| 40006 | `locked` | 423 |"));
+        assert_eq!(
+            schemas["ErrorBody"]["properties"]["code"]["$ref"],
+            "#/components/schemas/ErrorCode"
+        );
+
+        let schemes = &doc["components"]["securitySchemes"];
+        assert_eq!(schemes["cookieAuth"]["type"], "apiKey");
+        assert_eq!(schemes["cookieAuth"]["in"], "cookie");
+        assert_eq!(schemes["cookieAuth"]["name"], "token");
 
-    let app = app(pool);
+        // Protected operations require the cookie, public ones don't
+        let security = |path: &str, method: &str| paths[path][method]["security"].clone();
+        assert_eq!(
+            security("/accounts", "get")[0]["cookieAuth"],
+            serde_json::json!([])
+        );
+        assert_eq!(
+            security("/auth/logout", "get")[0]["cookieAuth"],
+            serde_json::json!([])
+        );
+        assert!(security("/auth/login", "post").is_null());
+        assert!(security("/vitals", "get").is_null());
+    }
+
+    /// Build a request without the cookie of a session
+    fn anonymous(uri: &str) -> axum::http::Request<axum::body::Body> {
+        axum::http::Request::get(uri)
+            .body(axum::body::Body::empty())
+            .unwrap()
+    }
+
+    /// Create the application serving the documentation to some users
+    fn with_api_docs(api_docs: ApiDocs) -> TestApp {
+        TestApp::with_config(HttpConfig {
+            rate: RateLimitConfig::disabled(),
+            api_docs,
+            ..HttpConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_api_docs_are_open_by_default() {
+        let app = TestApp::new();
+
+        for uri in ["/swagger-ui/", "/api-docs/openapi.json"] {
+            let (status, _, _) = app.send(anonymous(uri)).await;
+            assert_eq!(status, 200, "{uri}");
+        }
+        let (_, vitals) = app.request(Method::GET, "/vitals", None).await;
+        assert_eq!(vitals["api_docs"], "open");
+    }
 
-    // Start the server
-    let server = axum::serve(listener, app.into_make_service()).with_graceful_shutdown(async {
-        rx.await.ok();
-    });
+    #[tokio::test]
+    async fn test_api_docs_for_administrators() {
+        let app = with_api_docs(ApiDocs::Admin);
 
-    if let Err(err) = server.await {
-        return Err(AppError::from(err));
+        for uri in ["/swagger-ui/", "/api-docs/openapi.json"] {
+            let (status, _, _) = app.send(anonymous(uri)).await;
+            assert_eq!(status, 401, "{uri}");
+            let (status, _, _) = app.download(uri).await;
+            assert_eq!(status, 403, "{uri}");
+        }
+        app.make_admin();
+        for uri in ["/swagger-ui/", "/api-docs/openapi.json"] {
+            let (status, _, _) = app.download(uri).await;
+            assert_eq!(status, 200, "{uri}");
+        }
+        let (_, vitals) = app.request(Method::GET, "/vitals", None).await;
+        assert_eq!(vitals["api_docs"], "admin");
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn test_api_docs_disabled() {
+        let app = with_api_docs(ApiDocs::Disabled);
+        app.make_admin();
+
+        // Not served, not even to administrators
+        for uri in ["/swagger-ui/", "/api-docs/openapi.json"] {
+            let (status, _, body) = app.download(uri).await;
+            assert_eq!(status, 404, "{uri}");
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"], "not_found", "{uri}");
+        }
+        let (_, vitals) = app.request(Method::GET, "/vitals", None).await;
+        assert_eq!(vitals["api_docs"], "disabled");
+    }
+
+    #[tokio::test]
+    async fn test_successful_responses_have_schemas() {
+        let app = TestApp::new();
+        let (_, doc) = app
+            .request(Method::GET, "/api-docs/openapi.json", None)
+            .await;
+
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        let mut untyped = vec![];
+        for (path, item) in doc["paths"].as_object().unwrap() {
+            for (method, operation) in item.as_object().unwrap() {
+                let Some(responses) = operation["responses"].as_object() else {
+                    continue;
+                };
+                for (status, response) in responses {
+                    if !(status.starts_with('2') || status.starts_with('4')) || status == "204" {
+                        continue;
+                    }
+                    // JSON bodies are named schemas or lists of them, other bodies have a type
+                    let named = response["content"].as_object().is_some_and(|content| {
+                        content.iter().all(|(content_type, media)| {
+                            let schema = &media["schema"];
+                            let name = schema["$ref"]
+                                .as_str()
+                                .or(schema["items"]["$ref"].as_str())
+                                .and_then(|name| name.strip_prefix("#/components/schemas/"));
+                            content_type != "application/json"
+                                || name.is_some_and(|name| schemas.contains_key(name))
+                        })
+                    });
+                    if !named {
+                        untyped.push(format!("{method} {path} {status}"));
+                    }
+                }
+            }
+        }
+        assert!(untyped.is_empty(), "{untyped:#?}");
+
+        // Errors are answered with their code
+        let paths = &doc["paths"];
+        assert_eq!(
+            paths["/plans/{name}"]["delete"]["responses"]["401"]["content"]["application/json"]
+                ["schema"]["$ref"],
+            "#/components/schemas/ErrorBody"
+        );
+        assert_eq!(
+            paths["/users"]["post"]["responses"]["201"]["content"]["application/json"]["schema"]
+                ["$ref"],
+            "#/components/schemas/UserCreatedResponse"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_bodies_have_examples() {
+        let app = TestApp::new();
+        let (_, doc) = app
+            .request(Method::GET, "/api-docs/openapi.json", None)
+            .await;
+
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        let mut missing = vec![];
+        for (path, item) in doc["paths"].as_object().unwrap() {
+            for (method, operation) in item.as_object().unwrap() {
+                let Some(content) = operation["requestBody"]["content"].as_object() else {
+                    continue;
+                };
+                for (content_type, media) in content {
+                    // Forms describe their fields, JSON bodies show an example of themselves
+                    let documented = match media["schema"]["$ref"]
+                        .as_str()
+                        .and_then(|name| name.strip_prefix("#/components/schemas/"))
+                    {
+                        Some(name) if content_type == "application/json" => {
+                            media["example"].is_object() || schemas[name]["example"].is_object()
+                        }
+                        Some(name) => schemas.contains_key(name),
+                        None => !media["example"].is_null(),
+                    };
+                    if !documented {
+                        missing.push(format!("{method} {path} {content_type}"));
+                    }
+                }
+            }
+        }
+        assert!(missing.is_empty(), "{missing:#?}");
+
+        // Examples are bodies the server reads
+        let example = schemas["CreateAccount"]["example"].clone();
+        let (status, _) = app.request(Method::POST, "/accounts", Some(example)).await;
+        assert_eq!(status, 201);
+
+        // Enums are schemas listing their variants, which fields refer to
+        for (name, variant) in [
+            ("AccountKind", "credit"),
+            ("BudgetInterval", "monthly"),
+            ("MatchKind", "contains"),
+            ("Cadence", "weekly"),
+            ("ExportFormat", "csv"),
+        ] {
+            let variants = schemas[name]["enum"].as_array().unwrap();
+            assert!(variants.contains(&serde_json::json!(variant)), "{name}");
+        }
+        assert_eq!(
+            schemas["CreateAccount"]["properties"]["kind"]["allOf"][0]["$ref"],
+            "#/components/schemas/AccountKind"
+        );
+        assert_eq!(
+            schemas["SaveBudget"]["properties"]["interval"]["$ref"],
+            "#/components/schemas/BudgetInterval"
+        );
+
+        // Payloads taking one of several shapes say so
+        assert!(schemas["AmountColumns"]["oneOf"].is_array());
+        assert_eq!(
+            schemas["ImportForm"]["properties"]["mapping"]["$ref"],
+            "#/components/schemas/ColumnMapping"
+        );
+        assert_eq!(
+            schemas["ImportForm"]["properties"]["file"]["format"],
+            "binary"
+        );
+    }
 }
 
 // #[cfg(test)]
@@ -120,7 +1247,7 @@ pub async fn start_rest_server(
 
 //     let request = Request::builder()
 //       .method("GET")
-//       .uri(Uri::from_static("/analytic/hello"))
+//       .uri(Uri::from_static("/hello"))
 //       .body(Body::empty())
 //       .unwrap();
 
@@ -136,23 +1263,4 @@ pub async fn start_rest_server(
 //       "Should return the correct greeting."
 //     );
 //   }
-
-//   #[tokio::test]
-//   async fn test_start_rest_server() {
-//     let (tx, rx) = tokio::sync::oneshot::channel::<()>();
-
-//     let rest_port = 8080;
-//     let server = start_rest_server(rest_port, rx);
-
-//     tx.send(()).expect("Failed to send shutdown signal");
-
-//     tokio::select! {
-//       result = server => {
-//         result.expect("Server encountered an error");
-//       }
-//       () = tokio::time::sleep(std::time::Duration::from_secs(1)) => {
-//         // Server shut down successfully
-//       }
-//     }
-//   }
 // }
@@ -0,0 +1,469 @@
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+};
+use serde::de::DeserializeOwned;
+use serde_json::error::Category;
+
+use crate::errors::{AppError, InvalidBody, InvalidQuery};
+
+/// Rules a request body must follow beyond its shape, checked before the handler runs
+///
+/// Only rules that don't need the database belong here, such as lengths and formats. Bodies
+/// without such rules implement it with the default method.
+pub trait Validate {
+    /// Check the body
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the body is valid, otherwise `AppError::InvalidInput` telling why
+    fn validate(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self) -> Result<(), AppError> {
+        self.iter().try_for_each(Validate::validate)
+    }
+}
+
+impl Validate for String {}
+
+/// A JSON request body, which is then validated
+///
+/// Unlike `axum::Json`, bodies that can't be read are answered with the JSON errors of the API,
+/// `422` with the `invalid_body` code telling which field or position is at fault, and bodies that
+/// break the rules of `Validate` with `400`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !is_json(req.headers()) {
+            return Err(AppError::InvalidBody(InvalidBody {
+                message: "Expected a request body with `Content-Type: application/json`".to_owned(),
+                reason: None,
+                kind: "content_type",
+                field: None,
+                line: None,
+                column: None,
+            }));
+        }
+
+        let bytes = Bytes::from_request(req, state).await.map_err(|rejection| {
+            match rejection.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge,
+                _ => AppError::InvalidInput(rejection.body_text()),
+            }
+        })?;
+        let value: T = parse(&bytes)?;
+        value.validate()?;
+
+        Ok(ValidatedJson(value))
+    }
+}
+
+/// Query parameters, which are then validated
+///
+/// Unlike `axum::extract::Query`, query strings that can't be read are answered with the JSON
+/// errors of the API, `400` with the `invalid_query` code telling which parameters are at fault,
+/// and so are parameters that break the rules of `Validate`, e.g. a range that ends before it
+/// starts. Parameter structs deny unknown fields, so typos such as `form=` aren't ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let value: T = parse_query(parts.uri.query().unwrap_or_default())?;
+        value.validate()?;
+
+        Ok(ValidatedQuery(value))
+    }
+}
+
+/// Parse a query string, telling which parameter is at fault if it can't be
+fn parse_query<T: DeserializeOwned>(query: &str) -> Result<T, InvalidQuery> {
+    let deserializer =
+        serde_urlencoded::Deserializer::new(url::form_urlencoded::parse(query.as_bytes()));
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        invalid_query((path != ".").then_some(path), e.into_inner().to_string())
+    })
+}
+
+/// Describe why a query string can't be parsed
+///
+/// # Arguments
+///
+/// * `field` - The parameter being parsed, if any
+/// * `reason` - The error of parsing it
+fn invalid_query(field: Option<String>, reason: String) -> InvalidQuery {
+    let (message, kind, field) = match (missing_field(&reason), unknown_field(&reason), field) {
+        (Some(name), _, _) => (
+            format!("Missing query parameter `{name}`"),
+            "missing_field",
+            Some(name.to_owned()),
+        ),
+        (_, Some((name, expected)), _) => (
+            format!("Unknown query parameter `{name}`, {expected}"),
+            "unknown_field",
+            Some(name.to_owned()),
+        ),
+        (None, None, Some(field)) => (
+            format!("Invalid query parameter `{field}`: {reason}"),
+            "invalid_field",
+            Some(field),
+        ),
+        (None, None, None) => (format!("Invalid query: {reason}"), "invalid_field", None),
+    };
+
+    InvalidQuery {
+        message,
+        reason: Some(reason),
+        kind,
+        fields: field.into_iter().collect(),
+    }
+}
+
+/// Get the name of the unknown field of a serde message, and the fields it expected, e.g.
+/// "unknown field `form`, expected one of `from`, `to`"
+fn unknown_field(reason: &str) -> Option<(&str, &str)> {
+    let rest = reason.strip_prefix("unknown field `")?;
+    let (name, expected) = rest.split_once('`')?;
+    Some((name, expected.trim_start_matches(", ")))
+}
+
+/// Whether the content type of a request is JSON, e.g. `application/json` or
+/// `application/merge-patch+json`
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    essence == "application/json"
+        || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
+
+/// Parse a JSON body, telling which field or position is at fault if it can't be
+fn parse<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, InvalidBody> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    let value = serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(|e| invalid_body(&e.path().to_string(), e.into_inner()))?;
+    // Nothing but whitespace can follow the value
+    deserializer.end().map_err(|e| invalid_body(".", e))?;
+
+    Ok(value)
+}
+
+/// Describe why a body can't be parsed
+///
+/// # Arguments
+///
+/// * `path` - The path of the field being parsed, `.` for the body itself
+/// * `e` - The error of parsing it
+fn invalid_body(path: &str, e: serde_json::Error) -> InvalidBody {
+    let (line, column) = (e.line(), e.column());
+    // Messages of serde end with the position, which is a field of its own here
+    let error = e.to_string();
+    let reason = error
+        .strip_suffix(&format!(" at line {line} column {column}"))
+        .unwrap_or(&error);
+    let field = (path != ".").then(|| path.to_owned());
+
+    let (message, kind, field) = match e.classify() {
+        Category::Data => match (missing_field(reason), field) {
+            (Some(name), field) => {
+                let field = field.map_or(name.to_owned(), |parent| format!("{parent}.{name}"));
+                (
+                    format!("Missing field `{field}`"),
+                    "missing_field",
+                    Some(field),
+                )
+            }
+            (None, Some(field)) => (
+                format!("Invalid field `{field}`: {reason}"),
+                "invalid_field",
+                Some(field),
+            ),
+            (None, None) => (format!("Invalid body: {reason}"), "invalid_field", None),
+        },
+        Category::Syntax | Category::Eof | Category::Io => (
+            format!("Invalid JSON at line {line} column {column}: {reason}"),
+            "syntax",
+            None,
+        ),
+    };
+
+    InvalidBody {
+        message,
+        reason: Some(reason.to_owned()),
+        kind,
+        field,
+        line: Some(line),
+        column: Some(column),
+    }
+}
+
+/// Get the name of the missing field of a serde message, e.g. "missing field `name`"
+fn missing_field(reason: &str) -> Option<&str> {
+    reason
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.strip_suffix('`'))
+}
+
+/// The version of a resource an update was made against, from the `If-Match` header
+///
+/// The header must hold a single strong ETag as sent by the `GET` of the resource, e.g. `"3"`.
+/// Requests without it are answered with `428 Precondition Required`, so clients can't overwrite
+/// changes they haven't seen by leaving it out. `*` isn't supported for the same reason.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IfMatch(pub i32);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let value = parts
+            .headers
+            .get(header::IF_MATCH)
+            .ok_or(AppError::PreconditionRequired)?;
+
+        value
+            .to_str()
+            .ok()
+            .map(str::trim)
+            .and_then(|tag| tag.strip_prefix('"')?.strip_suffix('"'))
+            .and_then(|version| version.parse().ok())
+            .map(IfMatch)
+            .ok_or_else(|| {
+                AppError::InvalidInput(
+                    "`If-Match` must be the ETag of the resource, e.g. `\"3\"`".to_owned(),
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Method};
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+
+    /// Send a raw body as the logged in user, returning the status and JSON body of the response
+    async fn post(app: &TestApp, uri: &str, content_type: &str, body: &str) -> (u16, Value) {
+        let request = axum::http::Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(axum::http::header::COOKIE, app.cookie())
+            .header(axum::http::header::CONTENT_TYPE, content_type)
+            .body(Body::from(body.to_owned()))
+            .unwrap();
+        let (status, _, bytes) = app.send(request).await;
+        (status.as_u16(), serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_bodies() {
+        let app = TestApp::new();
+        let uri = "/api/v1/accounts";
+
+        // Malformed JSON tells where it breaks
+        let (status, body) = post(&app, uri, "application/json", "{\"name\": \"Broken\",}").await;
+        assert_eq!(status, 422, "{body}");
+        assert_eq!(body["code"], 40015);
+        assert_eq!(body["error"], "invalid_body");
+        assert_eq!(
+            body["details"],
+            json!({"kind": "syntax", "line": 1, "column": 19})
+        );
+        assert!(body["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid JSON at line 1 column 19"));
+
+        // Missing and wrongly typed fields are named
+        let (status, body) = post(&app, uri, "application/json", "{\"name\": \"Empty\"}").await;
+        assert_eq!(status, 422, "{body}");
+        assert_eq!(body["details"]["kind"], "missing_field");
+        assert_eq!(body["details"]["field"], "opening_balance");
+        assert_eq!(body["message"], "Missing field `opening_balance`");
+
+        let transaction = json!({
+            "amount": "10",
+            "description": "Split",
+            "occurred_at": "2024-06-01",
+            "splits": [{"category_id": "groceries", "amount": "10"}]
+        });
+        let (status, body) = post(
+            &app,
+            "/api/v1/accounts/1/transactions",
+            "application/json",
+            &transaction.to_string(),
+        )
+        .await;
+        assert_eq!(status, 422, "{body}");
+        assert_eq!(body["details"]["kind"], "invalid_field");
+        assert_eq!(body["details"]["field"], "splits[0].category_id");
+        let message = body["message"].as_str().unwrap();
+        assert!(
+            message.starts_with("Invalid field `splits[0].category_id`: invalid type: string"),
+            "{message}"
+        );
+
+        // Bodies must be JSON
+        let (status, body) = post(&app, uri, "text/plain", "{}").await;
+        assert_eq!(status, 422, "{body}");
+        assert_eq!(body["details"], json!({"kind": "content_type"}));
+    }
+
+    #[tokio::test]
+    async fn test_failing_validation() {
+        let app = TestApp::new();
+
+        let (status, body) = post(
+            &app,
+            "/api/v1/users",
+            "application/json",
+            &json!({"name": "short", "password": "short"}).to_string(),
+        )
+        .await;
+        assert_eq!(status, 400, "{body}");
+        assert_eq!(body["error"], "invalid_input");
+        assert_eq!(
+            body["message"],
+            "The password must be at least 8 characters long"
+        );
+
+        let (status, body) = post(
+            &app,
+            "/api/v1/auth/login",
+            "application/merge-patch+json",
+            &json!({"username": " ", "password": "password"}).to_string(),
+        )
+        .await;
+        assert_eq!(status, 400, "{body}");
+        assert_eq!(body["message"], "The username can't be empty");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_queries() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/api/v1/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        let transactions = format!("/api/v1/accounts/{}/transactions", account["id"]);
+
+        let (status, body) = app
+            .request(
+                Method::GET,
+                &format!("{transactions}?from=2024-06-01&to=2024-06-30&uncategorized_only=true"),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200, "{body}");
+
+        // Values of the wrong type name their parameter
+        let (status, body) = app
+            .request(Method::GET, &format!("{transactions}?from=yesterday"), None)
+            .await;
+        assert_eq!(status, 400, "{body}");
+        assert_eq!(body["code"], 40019);
+        assert_eq!(body["error"], "invalid_query");
+        assert_eq!(
+            body["details"],
+            json!({"kind": "invalid_field", "fields": ["from"]})
+        );
+        assert!(body["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid query parameter `from`: "));
+        let (status, body) = app
+            .request(
+                Method::GET,
+                "/api/v1/reports/monthly?year=2024&month=june",
+                None,
+            )
+            .await;
+        assert_eq!(status, 400, "{body}");
+        assert_eq!(body["details"]["fields"], json!(["month"]));
+
+        let (status, body) = app
+            .request(Method::GET, "/api/v1/reports/monthly?year=2024", None)
+            .await;
+        assert_eq!(status, 400, "{body}");
+        assert_eq!(
+            body["details"],
+            json!({"kind": "missing_field", "fields": ["month"]})
+        );
+        assert_eq!(body["message"], "Missing query parameter `month`");
+
+        // Ranges that end before they start name both ends
+        let (status, body) = app
+            .request(
+                Method::GET,
+                &format!("{transactions}?from=2024-06-02&to=2024-06-01"),
+                None,
+            )
+            .await;
+        assert_eq!(status, 400, "{body}");
+        assert_eq!(
+            body["details"],
+            json!({"kind": "invalid_range", "fields": ["from", "to"]})
+        );
+        assert_eq!(
+            body["message"],
+            "Invalid range of `from` and `to`: it starts on 2024-06-02, after it ends on 2024-06-01"
+        );
+
+        // Typos aren't ignored
+        let (status, body) = app
+            .request(
+                Method::GET,
+                &format!("{transactions}?form=2024-06-01"),
+                None,
+            )
+            .await;
+        assert_eq!(status, 400, "{body}");
+        assert_eq!(
+            body["details"],
+            json!({"kind": "unknown_field", "fields": ["form"]})
+        );
+        let message = body["message"].as_str().unwrap();
+        assert!(
+            message.starts_with("Unknown query parameter `form`, expected one of `tag`"),
+            "{message}"
+        );
+    }
+}
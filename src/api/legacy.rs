@@ -0,0 +1,137 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header, HeaderName, HeaderValue, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Router,
+};
+use tower::ServiceExt;
+
+use crate::{api::api::API_PREFIX, errors::AppError};
+
+/// Header marking a response to a deprecated path
+pub const DEPRECATION_HEADER: &str = "deprecation";
+
+/// Marks a response of the versioned API to a request that matched none of its routes
+#[derive(Debug, Clone, Copy)]
+pub struct UnknownRoute;
+
+/// Responds to requests that match none of the routes of the versioned API.
+pub async fn unknown_route() -> impl IntoResponse {
+    (Extension(UnknownRoute), AppError::not_found())
+}
+
+/// Keeps the route a request matched in its response, so middleware wrapping the forwarding of
+/// legacy paths can see which route answered.
+pub async fn keep_matched_path(req: Request, next: Next) -> Response {
+    let matched = req.extensions().get::<MatchedPath>().cloned();
+    let mut response = next.run(req).await;
+    if let Some(matched) = matched {
+        response.extensions_mut().insert(matched);
+    }
+    response
+}
+
+/// Answers a request to a path from before the API was versioned, e.g. `/accounts`, as the
+/// request to its versioned path, e.g. `/api/v1/accounts`.
+///
+/// The response is marked as deprecated, with a link to the versioned path. Paths that aren't
+/// routes of the versioned API either are answered with a `404` that isn't marked.
+///
+/// # Arguments
+///
+/// * `api` - The versioned API, with the state of its routes
+/// * `req` - The request to the legacy path
+pub async fn forward(api: Router, mut req: Request) -> Response {
+    let path = req.uri().path().to_owned();
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map_or(path.as_str(), |p| p.as_str());
+    let Ok(uri) = format!("{API_PREFIX}{path_and_query}").parse::<Uri>() else {
+        return AppError::not_found().into_response();
+    };
+    *req.uri_mut() = uri;
+
+    let mut response = api
+        .oneshot(req)
+        .await
+        .unwrap_or_else(|never| match never {});
+    if response.extensions_mut().remove::<UnknownRoute>().is_some() {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    headers.insert(
+        HeaderName::from_static(DEPRECATION_HEADER),
+        HeaderValue::from_static("true"),
+    );
+    if let Ok(link) =
+        HeaderValue::from_str(&format!("<{API_PREFIX}{path}>; rel=\"successor-version\""))
+    {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, Method, StatusCode};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_legacy_paths() {
+        let app = TestApp::new();
+
+        let (status, account) = app
+            .request(
+                Method::POST,
+                "/api/v1/accounts",
+                Some(json!({"name": "Checking", "opening_balance": "10", "currency": "USD"})),
+            )
+            .await;
+        assert_eq!(status, 201, "{account}");
+
+        // The legacy path answers as the versioned one, marked as deprecated
+        let (status, headers, versioned) =
+            app.download("/api/v1/accounts?include_archived=true").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers.get(DEPRECATION_HEADER).is_none());
+        let (status, headers, legacy) = app.download("/accounts?include_archived=true").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[DEPRECATION_HEADER], "true");
+        assert_eq!(
+            headers[header::LINK],
+            "</api/v1/accounts>; rel=\"successor-version\""
+        );
+        assert_eq!(versioned, legacy);
+        let accounts: Value = serde_json::from_slice(&legacy).unwrap();
+        assert!(accounts
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|a| a["name"] == "Checking"));
+
+        // Errors of routes are deprecated too, unknown paths are just not found
+        let (status, headers, _) = app.download("/accounts/0/balance").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(headers[DEPRECATION_HEADER], "true");
+        for uri in ["/unknown", "/api/v1/unknown", "/api/v1/vitals"] {
+            let (status, headers, body) = app.download(uri).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+            assert!(headers.get(DEPRECATION_HEADER).is_none(), "{uri}");
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"], "not_found", "{uri}");
+        }
+
+        // Operational endpoints stay at the root
+        for uri in ["/vitals", "/metrics", "/api-docs/openapi.json"] {
+            let (status, headers, _) = app.download(uri).await;
+            assert_eq!(status, StatusCode::OK, "{uri}");
+            assert!(headers.get(DEPRECATION_HEADER).is_none(), "{uri}");
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{extract::ConnectInfo, http::uri::Scheme, Extension, Router};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::{GracefulShutdown, Watcher};
+use hyper_util::service::TowerToHyperService;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+use crate::api::tls::TlsCertificates;
+use crate::errors::AppError;
+
+/// Permissions of the Unix socket, so a reverse proxy in the group of the server can connect
+const SOCKET_MODE: u32 = 0o660;
+
+/// Time a client has to complete the TLS handshake before its connection is closed
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the REST server listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindAddress {
+    /// An IP address and port
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket
+    Unix(PathBuf),
+}
+
+impl fmt::Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindAddress::Tcp(addr) => write!(f, "http://{addr}"),
+            BindAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A listener of the REST server, bound but not serving yet
+#[derive(Debug)]
+pub enum RestListener {
+    Tcp(TcpListener),
+    Unix {
+        listener: UnixListener,
+        path: PathBuf,
+    },
+}
+
+impl RestListener {
+    /// Bind the listener of the REST server
+    ///
+    /// A Unix socket replaces a socket left at its path by a server that didn't shut down
+    /// cleanly, and is made accessible to the group of the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Where to listen, port `0` picks a free port
+    pub async fn bind(address: &BindAddress) -> Result<Self, AppError> {
+        match address {
+            BindAddress::Tcp(addr) => Ok(RestListener::Tcp(TcpListener::bind(addr).await?)),
+            BindAddress::Unix(path) => {
+                remove_socket(path)?;
+                let listener = UnixListener::bind(path)?;
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(SOCKET_MODE))?;
+                Ok(RestListener::Unix {
+                    listener,
+                    path: path.clone(),
+                })
+            }
+        }
+    }
+
+    /// Get the address the listener is bound to, with the port picked if `0` was asked for
+    pub fn local_addr(&self) -> Result<BindAddress, AppError> {
+        match self {
+            RestListener::Tcp(listener) => Ok(BindAddress::Tcp(listener.local_addr()?)),
+            RestListener::Unix { path, .. } => Ok(BindAddress::Unix(path.clone())),
+        }
+    }
+
+    /// Serve the application until the shutdown future completes, then wait for the requests in
+    /// progress to be answered
+    ///
+    /// The socket file of a Unix listener is removed once the server stops.
+    ///
+    /// # Arguments
+    ///
+    /// * `app` - The application answering the requests
+    /// * `tls` - The certificate to terminate TLS with, plain HTTP is served without one
+    /// * `shutdown` - Completes when the server should stop listening
+    pub async fn serve(
+        self,
+        app: Router,
+        tls: Option<Arc<TlsCertificates>>,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(), AppError> {
+        match (self, tls) {
+            (RestListener::Tcp(listener), None) => {
+                axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .with_graceful_shutdown(shutdown)
+                .await?;
+                Ok(())
+            }
+            (RestListener::Tcp(listener), tls) => {
+                serve_connections(listener, app, tls, shutdown).await
+            }
+            (RestListener::Unix { listener, path }, tls) => {
+                let result = serve_connections(listener, app, tls, shutdown).await;
+                remove_socket(&path)?;
+                result
+            }
+        }
+    }
+}
+
+/// A listener connections are accepted from
+trait Accept {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Accept a connection, with the address of the client if it has one
+    fn accept_connection(
+        &self,
+    ) -> impl Future<Output = io::Result<(Self::Stream, Option<SocketAddr>)>> + Send;
+}
+
+impl Accept for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept_connection(&self) -> io::Result<(TcpStream, Option<SocketAddr>)> {
+        let (stream, addr) = self.accept().await?;
+        Ok((stream, Some(addr)))
+    }
+}
+
+impl Accept for UnixListener {
+    type Stream = UnixStream;
+
+    async fn accept_connection(&self) -> io::Result<(UnixStream, Option<SocketAddr>)> {
+        let (stream, _) = self.accept().await?;
+        Ok((stream, None))
+    }
+}
+
+/// Serve the connections of a listener until the shutdown future completes
+///
+/// TLS handshakes happen in the task of their connection, so a slow client doesn't hold up the
+/// others.
+async fn serve_connections(
+    listener: impl Accept,
+    app: Router,
+    tls: Option<Arc<TlsCertificates>>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<(), AppError> {
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept_connection() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    tracing::warn!("Failed to accept a connection ({e})");
+                    continue;
+                }
+            },
+            () = &mut shutdown => break,
+        };
+
+        // The address of the client is known to the routes as it is with `axum::serve`
+        let app = match remote {
+            Some(addr) => app.clone().layer(Extension(ConnectInfo(addr))),
+            None => app.clone(),
+        };
+        // Requests over the TLS of the server are known to be HTTPS, see `Client`
+        let app = match tls {
+            Some(_) => app.layer(Extension(Scheme::HTTPS)),
+            None => app,
+        };
+        let builder = builder.clone();
+        let watcher = graceful.watcher();
+        let acceptor = tls.as_ref().map(|tls| tls.acceptor());
+        tokio::spawn(async move {
+            let Some(acceptor) = acceptor else {
+                return serve_connection(&builder, watcher, stream, app).await;
+            };
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => serve_connection(&builder, watcher, stream, app).await,
+                Ok(Err(e)) => tracing::debug!("TLS handshake failed ({e})"),
+                Err(_) => tracing::debug!("TLS handshake timed out"),
+            }
+        });
+    }
+
+    // Connections stop taking requests, and are closed once their requests are answered
+    drop(listener);
+    graceful.shutdown().await;
+
+    Ok(())
+}
+
+/// Serve the requests of a connection until it is closed, or the server shuts down
+async fn serve_connection(
+    builder: &auto::Builder<TokioExecutor>,
+    watcher: Watcher,
+    stream: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    app: Router,
+) {
+    let service = TowerToHyperService::new(app);
+    let connection = builder
+        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+        .into_owned();
+    if let Err(e) = watcher.watch(connection).await {
+        tracing::debug!("Connection closed with an error ({e})");
+    }
+}
+
+/// Remove the socket at a path, if there is one
+fn remove_socket(path: &Path) -> Result<(), AppError> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => Ok(std::fs::remove_file(path)?),
+        Ok(_) => Err(AppError::InvalidInput(format!(
+            "{} exists and isn't a socket",
+            path.display()
+        ))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_listen_on_tcp() {
+        let app = TestApp::new();
+        let listener = RestListener::bind(&BindAddress::Tcp(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            0,
+        )))
+        .await
+        .unwrap();
+
+        // The port picked is the one listened on
+        let BindAddress::Tcp(addr) = listener.local_addr().unwrap() else {
+            panic!("Not bound to a TCP address");
+        };
+        assert_ne!(addr.port(), 0);
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(listener.serve(app.router(), None, async move {
+            rx.await.ok();
+        }));
+
+        let response = reqwest::get(format!("http://{addr}/livez")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_listen_on_unix_socket() {
+        let app = TestApp::new();
+        let path = std::env::temp_dir().join(format!("finance-fusion-{}.sock", std::process::id()));
+        let listener = RestListener::bind(&BindAddress::Unix(path.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            listener.local_addr().unwrap().to_string(),
+            format!("unix:{}", path.display())
+        );
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, SOCKET_MODE);
+
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(listener.serve(app.router(), None, async move {
+            rx.await.ok();
+        }));
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET /livez HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("ok"), "{response}");
+
+        // The socket is removed once the server stops
+        tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+}
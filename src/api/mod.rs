@@ -1 +1,12 @@
+#[allow(clippy::module_inception)]
 pub mod api;
+pub mod extract;
+pub mod legacy;
+pub mod listener;
+pub mod owned;
+pub mod pagination;
+pub mod responses;
+pub mod state;
+#[cfg(test)]
+pub(crate) mod test_utils;
+pub mod tls;
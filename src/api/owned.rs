@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path},
+    http::request::Parts,
+};
+
+use crate::database::{
+    connection::DbPool,
+    models::{plans::Plan, sessions::manager::Session},
+};
+use crate::errors::{AppError, AuthenticateError};
+
+/// A plan named by the `name` parameter of the path, owned by the authenticated user
+///
+/// Extracting it loads the plan from the primary, so handlers get the row without querying it
+/// themselves. It fails with `AppError::NotFound` whether the plan doesn't exist or belongs to
+/// another user, so other users' plans can't be told apart from missing ones. Routes extracting
+/// it must be layered with `jwt_auth`, which provides the session.
+#[derive(Debug, Clone)]
+pub struct OwnedPlan(pub Plan);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for OwnedPlan
+where
+    Arc<DbPool>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user_id = session_user_id(parts)?;
+        let name = path_param(parts, state, "name").await?;
+
+        Arc::<DbPool>::from_ref(state)
+            .run(move |conn| Plan::from_name(conn, &name, user_id))
+            .await
+            .map(OwnedPlan)
+    }
+}
+
+/// Get the ID of the user of the session `jwt_auth` put in the request
+fn session_user_id(parts: &Parts) -> Result<i32, AppError> {
+    parts
+        .extensions
+        .get::<Session>()
+        .map(Session::user_id)
+        .ok_or(AppError::Authenticate(AuthenticateError::InvalidToken))
+}
+
+/// Get a parameter of the path by name, whatever the other parameters of the route are
+async fn path_param<S: Send + Sync>(
+    parts: &mut Parts,
+    state: &S,
+    name: &str,
+) -> Result<String, AppError> {
+    let Path(mut params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+        .await
+        .map_err(|rejection| AppError::InvalidInput(rejection.body_text()))?;
+    params.remove(name).ok_or_else(|| {
+        tracing::error!(
+            "The route of {} has no `{name}` parameter",
+            parts.uri.path()
+        );
+        AppError::not_found()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{Request, StatusCode},
+        routing::get,
+        Extension, Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::database::models::users::User;
+
+    #[tokio::test]
+    async fn test_owned_plan_is_loaded() {
+        let pool = Arc::new(DbPool::new_test_shared());
+        let session = {
+            let conn = &mut pool.get().unwrap();
+            let owner = User::new(conn, "owned_plan_owner", "password").unwrap();
+            let other = User::new(conn, "owned_plan_other", "password").unwrap();
+            Plan::new(conn, "Owned", owner.id()).unwrap();
+            Plan::new(conn, "Foreign", other.id()).unwrap();
+            Session::new(
+                conn,
+                owner.id(),
+                chrono::Duration::hours(1),
+                chrono::Utc::now().naive_utc(),
+            )
+            .unwrap()
+        };
+
+        // The handler only answers with the row it was given
+        let app = Router::new()
+            .route(
+                "/plans/:name/notes/:id",
+                get(|OwnedPlan(plan): OwnedPlan| async move {
+                    format!("{} of user {}", plan.name(), plan.user_id())
+                }),
+            )
+            .layer(Extension(session.clone()))
+            .with_state(pool);
+
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        };
+        let response = get("/plans/Owned/notes/1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, format!("Owned of user {}", session.user_id()));
+
+        for uri in ["/plans/Foreign/notes/1", "/plans/Missing/notes/1"] {
+            let response = get(uri).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, OriginalUri, Query},
+    http::{header, request::Parts, HeaderValue, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::database::models::{plans::PlanSummary, users::UserPublic};
+use crate::errors::AppError;
+
+/// Number of items in a page when `per_page` isn't given
+pub const DEFAULT_PER_PAGE: u64 = 20;
+
+/// Maximum number of items in a page
+pub const MAX_PER_PAGE: u64 = 100;
+
+/// Page query parameters, documented by the routes listing pages
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// The page to list, from 1 (1 by default)
+    page: Option<u64>,
+    /// Number of items in a page (20 by default, at most 100)
+    per_page: Option<u64>,
+}
+
+/// The page a request lists, along with the URI it was requested with to link other pages
+///
+/// Extracting it fails with `AppError::InvalidInput` if `page` is 0 or `per_page` isn't between 1
+/// and `MAX_PER_PAGE`.
+#[derive(Debug, Clone)]
+pub struct PageParams {
+    page: u64,
+    per_page: u64,
+    uri: Uri,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for PageParams {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<PageQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| AppError::InvalidInput(rejection.body_text()))?;
+        // Routes nested under the API prefix only see the rest of the path
+        let OriginalUri(uri) = OriginalUri::from_request_parts(parts, state)
+            .await
+            .unwrap_or_else(|e| match e {});
+
+        let page = query.page.unwrap_or(1);
+        if page == 0 {
+            return Err(AppError::InvalidInput(
+                "The page must be at least 1".to_string(),
+            ));
+        }
+        let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE);
+        if !(1..=MAX_PER_PAGE).contains(&per_page) {
+            return Err(AppError::InvalidInput(format!(
+                "The number of items per page must be between 1 and {MAX_PER_PAGE}"
+            )));
+        }
+
+        Ok(Self {
+            page,
+            per_page,
+            uri,
+        })
+    }
+}
+
+impl PageParams {
+    /// Get the page, from 1
+    pub fn page(&self) -> u64 {
+        self.page
+    }
+
+    /// Get the number of items in a page
+    pub fn per_page(&self) -> u64 {
+        self.per_page
+    }
+
+    /// Get the number of items to load, for the `LIMIT` of a query
+    pub fn limit(&self) -> i64 {
+        self.per_page as i64
+    }
+
+    /// Get the number of items before the page, for the `OFFSET` of a query
+    pub fn offset(&self) -> i64 {
+        ((self.page - 1) * self.per_page) as i64
+    }
+
+    /// Build the page of the items loaded with `limit` and `offset`
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The items of the page
+    /// * `total` - The number of items of all pages
+    pub fn into_page<T>(self, items: Vec<T>, total: i64) -> Page<T> {
+        let total = total.max(0) as u64;
+        Page {
+            items,
+            page: self.page,
+            per_page: self.per_page,
+            total,
+            total_pages: total.div_ceil(self.per_page),
+            uri: self.uri,
+        }
+    }
+}
+
+/// A page of a list
+///
+/// Responses have a `Link` header with the `first`, `prev`, `next` and `last` pages, keeping the
+/// other query parameters of the request.
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(PlanPage = Page<PlanSummary>, UserPage = Page<UserPublic>)]
+pub struct Page<T> {
+    /// The items of the page
+    pub items: Vec<T>,
+    /// The page, from 1
+    pub page: u64,
+    /// Number of items in a page
+    pub per_page: u64,
+    /// Number of items of all pages
+    pub total: u64,
+    /// Number of pages, 0 when there are no items
+    pub total_pages: u64,
+    /// The URI the page was requested with
+    #[serde(skip)]
+    uri: Uri,
+}
+
+impl<T> Page<T> {
+    /// Get the `Link` header linking the other pages, as in RFC 8288
+    fn links(&self) -> String {
+        let last = self.total_pages.max(1);
+        let mut links = vec![(1, "first")];
+        if self.page > 1 {
+            links.push(((self.page - 1).min(last), "prev"));
+        }
+        if self.page < last {
+            links.push((self.page + 1, "next"));
+        }
+        links.push((last, "last"));
+
+        links
+            .into_iter()
+            .map(|(page, rel)| format!("<{}>; rel=\"{rel}\"", self.uri_of(page)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Get the URI of a page, with the other query parameters of the request as they were
+    fn uri_of(&self, page: u64) -> String {
+        let mut query: Vec<String> = self
+            .uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| {
+                let name = pair.split('=').next().unwrap_or_default();
+                !pair.is_empty() && name != "page" && name != "per_page"
+            })
+            .map(str::to_owned)
+            .collect();
+        query.push(format!("page={page}"));
+        query.push(format!("per_page={}", self.per_page));
+
+        format!("{}?{}", self.uri.path(), query.join("&"))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Page<T> {
+    fn into_response(self) -> Response {
+        let links = HeaderValue::from_str(&self.links());
+        let mut response = Json(&self).into_response();
+        if let Ok(links) = links {
+            response.headers_mut().insert(header::LINK, links);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(uri: &'static str, page: u64, per_page: u64) -> PageParams {
+        PageParams {
+            page,
+            per_page,
+            uri: Uri::from_static(uri),
+        }
+    }
+
+    #[test]
+    fn test_links() {
+        let page = params("/api/v1/admin/users?q=al&page=2&sort=-created_at", 2, 10)
+            .into_page(vec![0; 10], 35);
+        assert_eq!(page.total_pages, 4);
+        assert_eq!(
+            page.links(),
+            "</api/v1/admin/users?q=al&sort=-created_at&page=1&per_page=10>; rel=\"first\", \
+             </api/v1/admin/users?q=al&sort=-created_at&page=1&per_page=10>; rel=\"prev\", \
+             </api/v1/admin/users?q=al&sort=-created_at&page=3&per_page=10>; rel=\"next\", \
+             </api/v1/admin/users?q=al&sort=-created_at&page=4&per_page=10>; rel=\"last\""
+        );
+
+        // A single page has no previous or next page, even when it is empty
+        let page = params("/api/v1/plans", 1, 20).into_page(Vec::<u8>::new(), 0);
+        assert_eq!(page.total_pages, 0);
+        assert_eq!(
+            page.links(),
+            "</api/v1/plans?page=1&per_page=20>; rel=\"first\", \
+             </api/v1/plans?page=1&per_page=20>; rel=\"last\""
+        );
+
+        // Pages past the last link back to the last
+        let page = params("/api/v1/plans?page=9", 9, 20).into_page(Vec::<u8>::new(), 30);
+        assert!(page
+            .links()
+            .contains("</api/v1/plans?page=2&per_page=20>; rel=\"prev\""));
+    }
+}
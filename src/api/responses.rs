@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A response telling what was done, for routes that have nothing else to return
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MessageResponse {
+    /// What was done, e.g. `Plan deleted`
+    pub message: String,
+}
+
+impl MessageResponse {
+    /// Create a response with the given message
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+
+use crate::api::api::{HttpConfig, LiveConfig};
+use crate::clock::Clock;
+use crate::database::{connection::DbPool, models::sessions::keys::JwtKeys};
+use crate::events::EventBus;
+use crate::quotas::Quotas;
+use crate::reports::cache::ReportCache;
+use crate::routes::auth::SessionConfig;
+
+/// The state shared by the routes of the API, built once when the server starts
+///
+/// Routes extract the parts they need, e.g. `State<Arc<DbPool>>`, through `FromRef`.
+#[derive(Clone)]
+pub struct AppState {
+    /// The database connection pool
+    pub pool: Arc<DbPool>,
+    /// How the server handles the requests of clients, as configured at startup
+    pub config: HttpConfig,
+    /// The settings that change when the configuration is reloaded, e.g. the rate limits
+    pub live: Arc<LiveConfig>,
+    /// The keys signing and verifying the tokens of sessions
+    pub jwt_keys: Arc<JwtKeys>,
+    /// The bus publishing the events of users to their open streams
+    pub events: Arc<EventBus>,
+    /// The reports of users computed recently, served again until their data changes
+    pub reports: Arc<ReportCache>,
+    /// How much data each user can store
+    pub quotas: Quotas,
+    /// The clock routes read the current time from
+    pub clock: Arc<dyn Clock>,
+}
+
+impl FromRef<AppState> for Arc<DbPool> {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for HttpConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<LiveConfig> {
+    fn from_ref(state: &AppState) -> Self {
+        state.live.clone()
+    }
+}
+
+impl FromRef<AppState> for SessionConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.sessions
+    }
+}
+
+impl FromRef<AppState> for Arc<JwtKeys> {
+    fn from_ref(state: &AppState) -> Self {
+        state.jwt_keys.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<EventBus> {
+    fn from_ref(state: &AppState) -> Self {
+        state.events.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ReportCache> {
+    fn from_ref(state: &AppState) -> Self {
+        state.reports.clone()
+    }
+}
+
+impl FromRef<AppState> for Quotas {
+    fn from_ref(state: &AppState) -> Self {
+        state.quotas
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn Clock> {
+    fn from_ref(state: &AppState) -> Self {
+        state.clock.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        extract::State,
+        http::{Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::clock::SystemClock;
+
+    #[tokio::test]
+    async fn test_routes_extract_parts_of_the_state() {
+        let state = AppState {
+            pool: Arc::new(DbPool::new_unreachable()),
+            config: HttpConfig::default(),
+            live: Arc::new(LiveConfig::new(&HttpConfig::default())),
+            jwt_keys: Arc::new(JwtKeys::from_secret(b"test-secret")),
+            events: Arc::new(EventBus::new()),
+            reports: Arc::new(ReportCache::default()),
+            quotas: Quotas::default(),
+            clock: Arc::new(SystemClock),
+        };
+        let pool = state.pool.clone();
+
+        // A route only needing the pool gets the pool of the state
+        let app = Router::new()
+            .route(
+                "/pool",
+                get(move |State(extracted): State<Arc<DbPool>>| async move {
+                    if Arc::ptr_eq(&extracted, &pool) {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    }
+                }),
+            )
+            .route(
+                "/config",
+                get(|State(config): State<HttpConfig>| async move {
+                    config.timeouts.default.as_secs().to_string()
+                }),
+            )
+            .with_state(state);
+
+        let request = |uri| Request::get(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request("/pool")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(request("/config")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,545 @@
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap, HeaderName, Method, Request, StatusCode},
+    routing::post,
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tower::ServiceExt;
+
+use crate::api::api::{app, start_rest_server, HttpConfig, LiveConfig, RestOptions};
+use crate::api::listener::{BindAddress, RestListener};
+use crate::api::state::AppState;
+use crate::clock::{Clock, FixedClock};
+use crate::config::startup::Startup;
+use crate::database::{
+    connection::DbPool,
+    models::{
+        sessions::{keys::JwtKeys, manager::Session},
+        users::User,
+    },
+};
+use crate::errors::AppError;
+use crate::events::EventBus;
+use crate::jobs::queue::{self, QueueConfig, Services, Worker};
+use crate::jobs::webhooks::WebhookConfig;
+use crate::maintenance::Maintenance;
+use crate::metrics::Metrics;
+use crate::quotas::Quotas;
+use crate::rate_limit::RateLimitConfig;
+use crate::reports::cache::ReportCache;
+use crate::routes::vitals::Shutdown;
+use crate::storage::attachments::AttachmentStore;
+
+/// Number of applications created by this process, to give each its own data directory
+static APP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The REST application backed by a test database, with a logged in user
+///
+/// Nothing a test does through the application is committed, see `DbPool::new_test_shared`.
+/// Uploaded files are stored in a temporary data directory, removed when the application is
+/// dropped.
+pub struct TestApp {
+    app: Router,
+    pool: Arc<DbPool>,
+    data_dir: PathBuf,
+    events: Arc<EventBus>,
+    shutdown: Arc<Shutdown>,
+    reports: Arc<ReportCache>,
+    live: Arc<LiveConfig>,
+    clock: Arc<FixedClock>,
+    user_id: i32,
+    cookie: String,
+    /// Stops the worker of the queue once the application is dropped
+    _stop_jobs: watch::Sender<bool>,
+}
+
+impl TestApp {
+    /// Create the application without rate limits and log the default test user in
+    pub fn new() -> Self {
+        Self::with_config(HttpConfig {
+            rate: RateLimitConfig::disabled(),
+            ..HttpConfig::default()
+        })
+    }
+
+    /// Create the application with a configuration of requests and log the default test user in
+    pub fn with_config(http: HttpConfig) -> Self {
+        Self::build(http, Quotas::default())
+    }
+
+    /// Create the application without rate limits, with quotas of the data of users, and log the
+    /// default test user in
+    pub fn with_quotas(quotas: Quotas) -> Self {
+        Self::build(
+            HttpConfig {
+                rate: RateLimitConfig::disabled(),
+                ..HttpConfig::default()
+            },
+            quotas,
+        )
+    }
+
+    fn build(http: HttpConfig, quotas: Quotas) -> Self {
+        let pool = Arc::new(DbPool::new_test_shared());
+        let jwt_keys = Arc::new(JwtKeys::from_secret(b"test-secret"));
+        let clock = Arc::new(FixedClock::at_system_time());
+
+        let (user_id, cookie) = {
+            let mut conn = pool.get().unwrap();
+            let user = User::default(&mut conn).unwrap();
+            let session = Session::new(
+                &mut conn,
+                user.id(),
+                chrono::Duration::days(1),
+                clock.now_utc(),
+            )
+            .unwrap();
+            (
+                user.id(),
+                format!("token={}", session.token(&jwt_keys).unwrap()),
+            )
+        };
+
+        let data_dir = std::env::temp_dir().join(format!(
+            "finance-fusion-test-{}-{}",
+            std::process::id(),
+            APP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        // Deliveries go to servers of the tests on the loopback address, retried without waiting
+        let services = Services::new(
+            WebhookConfig {
+                allow_insecure: true,
+            },
+            quotas,
+            AttachmentStore::new(&data_dir),
+        );
+        let worker = Worker::new(
+            pool.clone(),
+            QueueConfig {
+                poll_interval: Duration::from_millis(5),
+                retry_delay: Duration::from_millis(1),
+                ..QueueConfig::default()
+            },
+            services.clone(),
+        )
+        .unwrap();
+        let (stop_jobs, jobs_stopped) = watch::channel(false);
+        tokio::spawn(queue::run(Arc::new(worker), jobs_stopped));
+
+        let Services {
+            webhooks,
+            events,
+            reports,
+            quotas,
+            attachments,
+            ..
+        } = services;
+        let shutdown = Arc::new(Shutdown::default());
+        let live = Arc::new(LiveConfig::new(&http));
+
+        Self {
+            app: app(
+                AppState {
+                    pool: pool.clone(),
+                    config: http,
+                    live: live.clone(),
+                    jwt_keys,
+                    events: events.clone(),
+                    reports: reports.clone(),
+                    quotas,
+                    clock: clock.clone(),
+                },
+                attachments,
+                webhooks,
+                shutdown.clone(),
+                Arc::new(Startup::finished()),
+                Arc::new(Metrics::new(None)),
+                Arc::new(Maintenance::load(data_dir.join("maintenance.json")).unwrap()),
+            ),
+            events,
+            shutdown,
+            reports,
+            live,
+            clock,
+            pool,
+            data_dir,
+            user_id,
+            cookie,
+            _stop_jobs: stop_jobs,
+        }
+    }
+
+    /// Get the application, to serve it on a listener
+    pub fn router(&self) -> Router {
+        self.app.clone()
+    }
+
+    /// Get the directory uploaded files are stored in
+    pub fn data_dir(&self) -> &PathBuf {
+        &self.data_dir
+    }
+
+    /// Get the pool of connections to the test database, for running jobs against it
+    pub fn pool(&self) -> &Arc<DbPool> {
+        &self.pool
+    }
+
+    /// Get the bus publishing the events of users to their streams
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Get whether the application is shutting down, as a shutdown signal would set it
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    /// Get the cache of the reports of users
+    pub fn reports(&self) -> &ReportCache {
+        &self.reports
+    }
+
+    /// Get the settings that change when the configuration is reloaded
+    pub fn live_config(&self) -> &LiveConfig {
+        &self.live
+    }
+
+    /// Get the clock routes read the current time from, stopped at the time the application was
+    /// created until a test moves it
+    ///
+    /// The worker of the queue reads the time of the system, so jobs retried later still run.
+    pub fn clock(&self) -> &FixedClock {
+        &self.clock
+    }
+
+    /// Get the cookie of the session of the logged in user, for requests built by tests
+    pub fn cookie(&self) -> &str {
+        &self.cookie
+    }
+
+    /// Get the ID of the logged in user
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// Make the logged in user an administrator
+    pub fn make_admin(&self) {
+        let mut conn = self.pool.get().unwrap();
+        User::from_id(&mut conn, self.user_id)
+            .unwrap()
+            .set_admin(&mut conn, true)
+            .unwrap();
+    }
+
+    /// Send a request as the logged in user
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The HTTP method
+    /// * `uri` - The path and query of the request
+    /// * `body` - The JSON body of the request, if any
+    ///
+    /// # Returns
+    ///
+    /// The status of the response, and its body as JSON (or as a JSON string if it isn't JSON)
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let (status, _, body) = self
+            .request_with_cookie(&self.cookie, method, uri, body)
+            .await;
+        (status, body)
+    }
+
+    /// Send a request with a cookie, e.g. the one of another session
+    ///
+    /// # Arguments
+    ///
+    /// * `cookie` - The `Cookie` header of the request, e.g. `token=...`
+    /// * `method` - The HTTP method
+    /// * `uri` - The path and query of the request
+    /// * `body` - The JSON body of the request, if any
+    ///
+    /// # Returns
+    ///
+    /// The status and headers of the response, and its body as JSON (or as a JSON string if it
+    /// isn't JSON)
+    pub async fn request_with_cookie(
+        &self,
+        cookie: &str,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, HeaderMap, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::COOKIE, cookie);
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        let (status, headers, bytes) = self.send(request).await;
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+
+        (status, headers, body)
+    }
+
+    /// Upload a file as the `file` part of a `multipart/form-data` request, as the logged in user
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The path of the request
+    /// * `filename` - The name of the file
+    /// * `content_type` - The content type of the part
+    /// * `bytes` - The contents of the file
+    ///
+    /// # Returns
+    ///
+    /// The status of the response, and its body as JSON (or as a JSON string if it isn't JSON)
+    pub async fn upload(
+        &self,
+        uri: &str,
+        filename: &str,
+        content_type: &str,
+        bytes: &[u8],
+    ) -> (StatusCode, Value) {
+        let boundary = "finance-fusion-test-boundary";
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\nContent-Type: {content_type}\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(bytes);
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(header::COOKIE, &self.cookie)
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let (status, _, bytes) = self.send(request).await;
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+
+        (status, body)
+    }
+
+    /// Send a `GET` request as the logged in user, keeping the body as is
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The path and query of the request
+    ///
+    /// # Returns
+    ///
+    /// The status, headers and body of the response
+    pub async fn download(&self, uri: &str) -> (StatusCode, HeaderMap, Bytes) {
+        self.download_with(uri, &[]).await
+    }
+
+    /// Send a `GET` request with extra headers as the logged in user, keeping the body as is
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The path and query of the request
+    /// * `headers` - Headers added to the request
+    ///
+    /// # Returns
+    ///
+    /// The status, headers and body of the response
+    pub async fn download_with(
+        &self,
+        uri: &str,
+        headers: &[(HeaderName, &str)],
+    ) -> (StatusCode, HeaderMap, Bytes) {
+        let mut request = Request::builder()
+            .method(Method::GET)
+            .uri(uri)
+            .header(header::COOKIE, &self.cookie);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+
+        self.send(request.body(Body::empty()).unwrap()).await
+    }
+
+    /// Send a `GET` request as the logged in user, leaving the body unread, for streams
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The path and query of the request
+    ///
+    /// # Returns
+    ///
+    /// The status, headers and body of the response
+    pub async fn open(&self, uri: &str) -> (StatusCode, HeaderMap, Body) {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(uri)
+            .header(header::COOKIE, &self.cookie)
+            .body(Body::empty())
+            .unwrap();
+
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+
+        (status, headers, response.into_body())
+    }
+
+    /// Send a request to the application as is, without logging in, and collect its response
+    pub async fn send(&self, request: Request<Body>) -> (StatusCode, HeaderMap, Bytes) {
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+
+        (status, headers, bytes)
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+/// Get the cookie of the session a response set, as a request sends it back, e.g. `token=...`
+pub fn session_cookie(headers: &HeaderMap) -> String {
+    headers[header::SET_COOKIE]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string()
+}
+
+/// Requests received by a test endpoint
+pub type Received = Arc<Mutex<Vec<(HeaderMap, Bytes)>>>;
+
+/// Serve an endpoint on the loopback address that answers with the given statuses in turn, then
+/// with `fallback`
+///
+/// # Returns
+///
+/// The URL of the endpoint, and the requests it received
+pub async fn endpoint(statuses: Vec<u16>, fallback: u16) -> (String, Received) {
+    let statuses = Arc::new(Mutex::new(VecDeque::from(statuses)));
+    let received = Received::default();
+    let router = Router::new()
+        .route(
+            "/hook",
+            post(
+                move |State((statuses, received)): State<(Arc<Mutex<VecDeque<u16>>>, Received)>,
+                      headers: HeaderMap,
+                      body: Bytes| async move {
+                    received.lock().unwrap().push((headers, body));
+                    let status = statuses.lock().unwrap().pop_front().unwrap_or(fallback);
+                    StatusCode::from_u16(status).unwrap()
+                },
+            ),
+        )
+        .with_state((statuses, received.clone()));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (url, received)
+}
+
+/// Wait for a condition to hold, failing the test after a few seconds
+pub async fn eventually<F: std::future::Future<Output = bool>>(mut condition: impl FnMut() -> F) {
+    for _ in 0..200 {
+        if condition().await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    panic!("The condition didn't hold in time");
+}
+
+/// The REST server started with `start_rest_server`, listening on a port picked by the system
+pub struct TestServer {
+    /// The address the server listens on
+    pub addr: SocketAddr,
+    /// Stops the server once its requests in progress are answered
+    pub stop: oneshot::Sender<()>,
+    /// The task serving the requests
+    pub task: JoinHandle<Result<(), AppError>>,
+}
+
+impl TestServer {
+    /// Start the server without rate limits on the loopback address
+    pub async fn start() -> Self {
+        let listener = RestListener::bind(&BindAddress::Tcp(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            0,
+        )))
+        .await
+        .unwrap();
+        let BindAddress::Tcp(addr) = listener.local_addr().unwrap() else {
+            panic!("Not bound to a TCP address");
+        };
+
+        let data_dir = std::env::temp_dir().join(format!(
+            "finance-fusion-server-{}-{}",
+            std::process::id(),
+            APP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let (stop, rx) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            start_rest_server(
+                listener,
+                data_dir.to_str().unwrap(),
+                rx,
+                Arc::new(DbPool::new_test_shared()),
+                RestOptions {
+                    services: Services::new(
+                        WebhookConfig::default(),
+                        Quotas::default(),
+                        AttachmentStore::new(&data_dir),
+                    ),
+                    metrics_token: None,
+                    jwt_keys: JwtKeys::from_secret(b"test-secret"),
+                    http: HttpConfig {
+                        rate: RateLimitConfig::disabled(),
+                        ..HttpConfig::default()
+                    },
+                    tls: None,
+                },
+                Arc::new(Shutdown::default()),
+            )
+            .await
+        });
+
+        Self { addr, stop, task }
+    }
+}
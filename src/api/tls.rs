@@ -0,0 +1,260 @@
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+use crate::errors::AppError;
+
+/// Paths of the PEM files the server terminates TLS with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsPaths {
+    /// The certificate chain, the certificate of the server first
+    pub cert: PathBuf,
+    /// The private key of the certificate
+    pub key: PathBuf,
+}
+
+/// The certificate the server terminates TLS with, which can be reloaded from its files
+///
+/// Connections keep the certificate they were accepted with, so reloading doesn't drop them.
+pub struct TlsCertificates {
+    paths: TlsPaths,
+    config: RwLock<Arc<ServerConfig>>,
+}
+
+impl fmt::Debug for TlsCertificates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsCertificates")
+            .field("paths", &self.paths)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TlsCertificates {
+    /// Load the certificate and its private key
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The PEM files of the certificate chain and the private key
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::InvalidInput` naming the file that can't be read or has no usable
+    /// certificate or key.
+    pub fn load(paths: TlsPaths) -> Result<Self, AppError> {
+        let config = server_config(&paths)?;
+        Ok(Self {
+            paths,
+            config: RwLock::new(Arc::new(config)),
+        })
+    }
+
+    /// Get the paths the certificate is loaded from
+    pub fn paths(&self) -> &TlsPaths {
+        &self.paths
+    }
+
+    /// Load the certificate from its files again, e.g. once it was renewed
+    ///
+    /// The current certificate is kept if the files are invalid.
+    pub fn reload(&self) -> Result<(), AppError> {
+        let config = server_config(&self.paths)?;
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+        Ok(())
+    }
+
+    /// Get an acceptor of connections with the current certificate
+    pub fn acceptor(&self) -> TlsAcceptor {
+        let config = self.config.read().unwrap_or_else(|e| e.into_inner());
+        TlsAcceptor::from(config.clone())
+    }
+}
+
+/// Build the TLS configuration of the server from the PEM files
+fn server_config(paths: &TlsPaths) -> Result<ServerConfig, AppError> {
+    let certs = read_certs(&paths.cert)?;
+    let key = read_key(&paths.key)?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| {
+            AppError::InvalidInput(format!(
+                "The TLS key {} can't be used with the certificate {} ({e})",
+                paths.key.display(),
+                paths.cert.display()
+            ))
+        })?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+/// Read the certificate chain of a PEM file
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, AppError> {
+    let invalid = |reason: String| {
+        AppError::InvalidInput(format!(
+            "Invalid TLS certificate {}: {reason}",
+            path.display()
+        ))
+    };
+
+    let file = File::open(path).map_err(|e| invalid(e.to_string()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(e.to_string()))?;
+    if certs.is_empty() {
+        return Err(invalid("no PEM certificate found".to_owned()));
+    }
+
+    Ok(certs)
+}
+
+/// Read the first private key of a PEM file
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, AppError> {
+    let invalid = |reason: String| {
+        AppError::InvalidInput(format!("Invalid TLS key {}: {reason}", path.display()))
+    };
+
+    let file = File::open(path).map_err(|e| invalid(e.to_string()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| invalid(e.to_string()))?
+        .ok_or_else(|| invalid("no PEM private key found".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::api::listener::{BindAddress, RestListener};
+    use crate::api::test_utils::TestApp;
+
+    /// Write a self-signed certificate for `localhost` to a directory, returning it as PEM
+    fn write_certificate(dir: &Path) -> (TlsPaths, String) {
+        let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let pem = certificate.serialize_pem().unwrap();
+        let paths = TlsPaths {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+        };
+        std::fs::write(&paths.cert, &pem).unwrap();
+        std::fs::write(&paths.key, certificate.serialize_private_key_pem()).unwrap();
+        (paths, pem)
+    }
+
+    /// A client trusting only the certificate, resolving `localhost` to the server
+    fn client(pem: &str, addr: SocketAddr) -> reqwest::Client {
+        reqwest::Client::builder()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(reqwest::Certificate::from_pem(pem.as_bytes()).unwrap())
+            .resolve("localhost", addr)
+            .build()
+            .unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("finance-fusion-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let dir = temp_dir("tls-invalid");
+        let (paths, _) = write_certificate(&dir);
+        assert!(TlsCertificates::load(paths.clone()).is_ok());
+
+        // Errors name the file at fault
+        let missing = TlsPaths {
+            cert: dir.join("missing.pem"),
+            ..paths.clone()
+        };
+        let error = TlsCertificates::load(missing).unwrap_err().to_string();
+        assert!(
+            error.contains("Invalid TLS certificate") && error.contains("missing.pem"),
+            "{error}"
+        );
+
+        let garbage = dir.join("garbage.pem");
+        std::fs::write(&garbage, "not a certificate").unwrap();
+        let error = TlsCertificates::load(TlsPaths {
+            cert: garbage.clone(),
+            ..paths.clone()
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("no PEM certificate found"), "{error}");
+
+        // A certificate isn't a key
+        let error = TlsCertificates::load(TlsPaths {
+            cert: paths.cert.clone(),
+            key: paths.cert.clone(),
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.contains("Invalid TLS key") && error.contains("no PEM private key found"),
+            "{error}"
+        );
+
+        // A reload with invalid files keeps the current certificate
+        let tls = TlsCertificates::load(paths.clone()).unwrap();
+        std::fs::write(&paths.key, "").unwrap();
+        assert!(tls.reload().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_serve_https() {
+        let dir = temp_dir("tls-serve");
+        let (paths, first) = write_certificate(&dir);
+        let tls = Arc::new(TlsCertificates::load(paths.clone()).unwrap());
+
+        let app = TestApp::new();
+        let listener = RestListener::bind(&BindAddress::Tcp(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            0,
+        )))
+        .await
+        .unwrap();
+        let BindAddress::Tcp(addr) = listener.local_addr().unwrap() else {
+            panic!("Not bound to a TCP address");
+        };
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(listener.serve(app.router(), Some(tls.clone()), async move {
+            rx.await.ok();
+        }));
+
+        let url = format!("https://localhost:{}/livez", addr.port());
+        let trusting_first = client(&first, addr);
+        let response = trusting_first.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        // Plain HTTP isn't answered
+        assert!(reqwest::get(format!("http://{addr}/livez")).await.is_err());
+
+        // Reloading serves the new certificate, the open connection keeps working
+        let (_, second) = write_certificate(&dir);
+        tls.reload().unwrap();
+        let response = client(&second, addr).get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response = trusting_first.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(client(&first, addr).get(&url).send().await.is_err());
+
+        tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
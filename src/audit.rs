@@ -0,0 +1,21 @@
+use crate::database::{
+    connection::DbConn,
+    models::audit_events::{AuditEvent, NewAuditEvent},
+};
+
+/// Record a security-relevant event
+///
+/// Recording never fails the operation the event is about. The insert runs in a savepoint when
+/// the connection is in a transaction, so a failure doesn't abort it, and is only logged.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `event` - The event to record
+pub fn record(conn: &mut DbConn, event: NewAuditEvent) {
+    use diesel::Connection;
+
+    if let Err(e) = conn.transaction(|conn| AuditEvent::new(conn, &event)) {
+        tracing::warn!("Audit event {:?} wasn't recorded ({e})", event.event());
+    }
+}
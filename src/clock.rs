@@ -0,0 +1,109 @@
+//! The time of the server, read through a clock so that tests can set it
+//!
+//! Routes, jobs and schedulers read the time from the `Clock` of the server rather than from the
+//! system, and pass it down to the models, which take the time they compare against as an
+//! argument. Tests move a `FixedClock` forward instead of waiting or backdating rows.
+
+use std::fmt::Debug;
+#[cfg(any(test, feature = "test-utils"))]
+use std::sync::Mutex;
+
+#[cfg(any(test, feature = "test-utils"))]
+use chrono::SubsecRound;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Utc};
+
+/// A source of the current time
+pub trait Clock: Debug + Send + Sync {
+    /// Get the current instant
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Get the current time in UTC, as timestamps are stored
+    fn now_utc(&self) -> NaiveDateTime {
+        self.now().naive_utc()
+    }
+
+    /// Get the current time in the time zone of the server, as schedules are set
+    fn now_local(&self) -> NaiveDateTime {
+        self.now().with_timezone(&Local).naive_local()
+    }
+
+    /// Get the current day in the time zone of the server
+    fn today(&self) -> NaiveDate {
+        self.now_local().date()
+    }
+}
+
+/// The clock of the system, used by the server
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to, for tests
+#[cfg(any(test, feature = "test-utils"))]
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl FixedClock {
+    /// Create a clock stopped at an instant
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Create a clock stopped at the current time of the system, to the microsecond like the
+    /// timestamps of the database
+    ///
+    /// Tokens are still checked against the time of the system, so they stay valid as long as the
+    /// clock isn't moved back.
+    pub fn at_system_time() -> Self {
+        Self::new(Utc::now().trunc_subsecs(6))
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: chrono::Duration) {
+        *self.now.lock().expect("Poisoned clock") += duration;
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("Poisoned clock")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_clock() {
+        let start = DateTime::parse_from_rfc3339("2024-06-30T23:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let clock = FixedClock::new(start);
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.now(), start);
+
+        clock.advance(chrono::Duration::hours(1));
+        assert_eq!(
+            clock.now_utc(),
+            start.naive_utc() + chrono::Duration::hours(1)
+        );
+        assert_eq!(
+            clock.today(),
+            (start + chrono::Duration::hours(1))
+                .with_timezone(&Local)
+                .date_naive()
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::config::VERSION;
+
+/// Output of `git describe` for the commit the server was built from, `-dirty` if it had changes
+pub const GIT_DESCRIBE: &str =
+    git_version::git_version!(args = ["--always", "--dirty"], fallback = "unknown");
+
+/// What exactly was built and deployed, captured by the build script
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildInfo {
+    /// Version of the server
+    pub version: String,
+    /// Output of `git describe` for the commit the server was built from
+    pub git_describe: String,
+    /// The timestamp when the server was built
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    pub built_at: NaiveDateTime,
+    /// Version of the compiler the server was built with
+    pub rustc_version: String,
+    /// The Cargo features the server was built with
+    pub features: Vec<String>,
+}
+
+impl BuildInfo {
+    /// Get the metadata of the running build
+    pub fn current() -> Self {
+        let built_at = env!("FF_BUILT_AT").parse::<i64>().unwrap_or_default();
+        Self {
+            version: VERSION.to_owned(),
+            git_describe: GIT_DESCRIBE.to_owned(),
+            built_at: chrono::DateTime::from_timestamp(built_at, 0)
+                .unwrap_or_default()
+                .naive_utc(),
+            rustc_version: env!("FF_RUSTC_VERSION").to_owned(),
+            features: env!("FF_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        }
+    }
+}
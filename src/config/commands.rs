@@ -0,0 +1,232 @@
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use crate::api::api;
+use crate::database::{connection::DbPool, models::users::User};
+use crate::dev::seed::{self, SeedSummary};
+use crate::errors::AppError;
+use crate::routes::users::validate_credentials;
+
+/// Apply the migrations the database is missing, for the `migrate` subcommand
+pub async fn migrate(pool: &Arc<DbPool>) -> Result<(), AppError> {
+    pool.migrate().await
+}
+
+/// Create an administrator, or make an existing user one, for the `create-admin` subcommand
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool
+/// * `username` - The username of the administrator
+/// * `password` - Gets the password of a new administrator, only called when the user doesn't
+///   exist, e.g. by prompting for it
+///
+/// # Returns
+///
+/// The administrator, and whether it was created rather than promoted, or
+/// `AppError::InvalidInput` if the username or password of a new administrator is invalid
+pub async fn create_admin<F>(
+    pool: &Arc<DbPool>,
+    username: &str,
+    password: F,
+) -> Result<(User, bool), AppError>
+where
+    F: FnOnce() -> Result<String, AppError> + Send + 'static,
+{
+    let username = username.to_owned();
+    let (user, created) = pool
+        .transaction(move |conn| {
+            let (user, created) = match User::from_username(conn, &username) {
+                Ok(user) => (user, false),
+                Err(AppError::NotFound(_)) => {
+                    let password = password()?;
+                    validate_credentials(&username, &password)?;
+                    (User::new(conn, &username, &password)?, true)
+                }
+                Err(e) => return Err(e),
+            };
+            Ok((user.set_admin(conn, true)?, created))
+        })
+        .await?;
+
+    if created {
+        tracing::info!("Created administrator {}.", user.id());
+    } else {
+        tracing::info!("Made user {} an administrator.", user.id());
+    }
+    Ok((user, created))
+}
+
+/// Read a password, without echoing it when the input is a terminal
+///
+/// # Arguments
+///
+/// * `input` - Where the password is read from, a line without its line break
+/// * `output` - Where the prompt is written to
+pub fn prompt_password(
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Result<String, AppError> {
+    write!(output, "Password: ")?;
+    output.flush()?;
+
+    let mut password = String::new();
+    input.read_line(&mut password)?;
+    writeln!(output)?;
+
+    Ok(password.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+/// Read a password from the terminal for the `create-admin` subcommand, see `prompt_password`
+pub fn prompt_password_on_terminal() -> Result<String, AppError> {
+    let stdin = std::io::stdin();
+    let echo = |flag: &str| {
+        if stdin.is_terminal() {
+            let _ = Command::new("stty")
+                .arg(flag)
+                .stdin(Stdio::inherit())
+                .status();
+        }
+    };
+
+    echo("-echo");
+    let password = prompt_password(stdin.lock(), std::io::stderr());
+    echo("echo");
+    password
+}
+
+/// Write the OpenAPI document of the API, for the `generate-openapi` subcommand
+///
+/// # Arguments
+///
+/// * `out` - The file the document is written to, replaced if it exists
+pub fn generate_openapi(out: &Path) -> Result<(), AppError> {
+    let document = api::openapi()
+        .to_pretty_json()
+        .map_err(std::io::Error::from)?;
+    std::fs::write(out, document + "\n")?;
+    tracing::info!("Wrote the OpenAPI document to {}.", out.display());
+
+    Ok(())
+}
+
+/// Create the demo user and their data, for the `seed` subcommand
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool
+/// * `url` - The URL of the database, whose name must contain `dev` or `test`
+/// * `force` - Seed the database whatever its name
+///
+/// # Returns
+///
+/// What was seeded, `AppError::InvalidInput` if the database isn't for development or tests, or
+/// `AppError::Conflict` if it is already seeded
+pub async fn seed(pool: &Arc<DbPool>, url: &str, force: bool) -> Result<SeedSummary, AppError> {
+    seed::ensure_seedable(url, force)?;
+    let today = chrono::Local::now().date_naive();
+    let summary = pool.run(move |conn| seed::seed(conn, today)).await?;
+    tracing::info!(
+        "Seeded user \"{}\" with {} plans, {} budgets, {} accounts, {} categories and {} transactions.",
+        seed::DEMO_USERNAME,
+        summary.plans,
+        summary.budgets,
+        summary.accounts,
+        summary.categories,
+        summary.transactions
+    );
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let pool = Arc::new(DbPool::new_test_shared());
+        migrate(&pool).await.unwrap();
+        // Nothing is left to apply
+        migrate(&pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_admin() {
+        let pool = Arc::new(DbPool::new_test_shared());
+
+        let (admin, created) = create_admin(&pool, "root", || Ok("root_password".to_owned()))
+            .await
+            .unwrap();
+        assert!(created);
+        assert!(admin.is_admin());
+        let mut conn = pool.get().unwrap();
+        let user = User::from_username(&mut conn, "root").unwrap();
+        assert!(user.is_admin());
+        assert!(user.check_password("root_password"));
+        drop(conn);
+
+        // Existing users are promoted, without asking for a password
+        let user = {
+            let mut conn = pool.get().unwrap();
+            User::new(&mut conn, "alice", "alice_password").unwrap()
+        };
+        assert!(!user.is_admin());
+        let (admin, created) = create_admin(&pool, "alice", || panic!("Asked for a password"))
+            .await
+            .unwrap();
+        assert!(!created);
+        assert_eq!(admin.id(), user.id());
+        assert!(admin.is_admin());
+
+        // New administrators need valid credentials
+        let result = create_admin(&pool, "bob", || Ok("short".to_owned())).await;
+        assert!(matches!(result, Err(AppError::InvalidInput(_))));
+        let mut conn = pool.get().unwrap();
+        assert!(User::from_username(&mut conn, "bob").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_seed() {
+        let pool = Arc::new(DbPool::new_test_shared());
+        let refused = seed(&pool, "postgres://u:p@db/finance_fusion", false).await;
+        assert!(matches!(refused, Err(AppError::InvalidInput(_))));
+        let mut conn = pool.get().unwrap();
+        assert!(User::from_username(&mut conn, seed::DEMO_USERNAME).is_err());
+        drop(conn);
+
+        let summary = seed(&pool, "postgres://u:p@db/finance_fusion_dev", false)
+            .await
+            .unwrap();
+        assert!(!summary.user.is_admin());
+        let mut conn = pool.get().unwrap();
+        assert_eq!(
+            User::from_username(&mut conn, seed::DEMO_USERNAME)
+                .unwrap()
+                .id(),
+            summary.user.id()
+        );
+    }
+
+    #[test]
+    fn test_prompt_password() {
+        let mut prompt = vec![];
+        let password = prompt_password(&b"p4ssw0rd  \r\nrest"[..], &mut prompt).unwrap();
+        assert_eq!(password, "p4ssw0rd  ");
+        assert_eq!(prompt, b"Password: \n");
+    }
+
+    #[test]
+    fn test_generate_openapi() {
+        let out = std::env::temp_dir().join(format!("openapi-{}.json", std::process::id()));
+        generate_openapi(&out).unwrap();
+
+        let document: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert!(document["paths"]["/plans"]["get"].is_object());
+        assert_eq!(document["servers"][0]["url"], "/api/v1");
+    }
+}
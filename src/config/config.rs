@@ -1,35 +1,244 @@
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
 
-use crate::api::api;
-use crate::database::connection::DbPool;
+use crate::api::api::{ApiDocs, CompressionConfig, HttpConfig, RestApp, RestOptions};
+use crate::api::listener::BindAddress;
+use crate::api::tls::{TlsCertificates, TlsPaths};
+use crate::config::{
+    build_info::BuildInfo,
+    logging::{LogFormat, LogLevel},
+    reload,
+    settings::Config,
+    startup::{self, Startup, StartupPhase},
+};
+use crate::database::{connection::DbPool, models::sessions::keys::JwtKeys};
 use crate::errors::AppError;
+use crate::jobs;
+use crate::jobs::queue::{QueueConfig, Services, Worker};
+use crate::jobs::webhooks::WebhookConfig;
+use crate::middleware::{
+    body_limit::BodyLimits, security_headers::SecurityHeadersConfig, timeout::Timeouts,
+};
+use crate::routes::{auth::SessionConfig, vitals::Shutdown};
+use crate::storage::attachments::AttachmentStore;
 /// Compile-time version string. Defaults to 0.0.0-a.0-0-g0 if git is not available
 pub const VERSION: &str =
     git_version::git_version!(args = ["--always", "--long"], fallback = "0.0.0-a.0-0-g0");
 
 /// A server that listens to Finance Fusion's output and generates analytics of various types.
-#[derive(Parser, Debug)]
+///
+/// The options come before the subcommand, e.g. `finance-fusion-server --rest-port 8080 serve`.
+#[derive(Parser, Debug, Clone)]
 #[command(version = VERSION)]
 #[command(author, about, long_about = None)]
 pub struct Args {
-    /// Directory in which the configuration file is stored
+    /// What to do, `serve` when none is given
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Directory in which the configuration file, `config.toml`, is stored
     #[arg(short, long, default_value = "/etc/finance-fusion")]
     pub config_dir: String,
 
-    /// The rest port to listen on
-    #[arg(short, long, default_value = "5000")]
-    pub rest_port: u16,
+    /// How log lines are formatted
+    #[arg(long, value_enum, default_value = "pretty")]
+    pub log_format: LogFormat,
+
+    /// Which events are logged, e.g. `debug` or `finance_fusion=debug,tower_http=warn`, instead
+    /// of `RUST_LOG`
+    #[arg(long)]
+    pub log_level: Option<String>,
+
+    /// Directory in which uploaded files, such as transaction attachments, are stored
+    #[arg(short, long, default_value = "/var/lib/finance-fusion")]
+    pub data_dir: String,
+
+    /// Directory in which scheduled reports are written, `reports` in the data directory by default
+    #[arg(long)]
+    pub reports_dir: Option<String>,
+
+    /// The rest port to listen on, 5000 by default
+    #[arg(short, long)]
+    pub rest_port: Option<u16>,
+
+    /// The IP address to listen on, e.g. `127.0.0.1` behind a reverse proxy on the same host
+    #[arg(long, default_value = "0.0.0.0")]
+    pub bind_addr: IpAddr,
+
+    /// Path of a Unix domain socket to listen on instead of the IP address and port
+    #[arg(long)]
+    pub bind_uds: Option<PathBuf>,
+
+    /// PEM certificate chain to serve HTTPS with, reloaded on SIGHUP, plain HTTP is served
+    /// without one
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the TLS certificate
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Seconds the server keeps taking requests after a shutdown signal while it reports not being
+    /// ready, so load balancers stop routing to it before it stops listening
+    #[arg(long, default_value = "5")]
+    pub shutdown_delay: u64,
+
+    /// Seconds the requests in progress, and the background tasks, have to finish once the server
+    /// stops listening, before they are aborted
+    #[arg(long, default_value = "30")]
+    pub drain_timeout_secs: u64,
+
+    /// Bearer token required to read the metrics, which are open when it isn't set
+    #[arg(long)]
+    pub metrics_token: Option<String>,
+
+    /// Who the Swagger UI and the OpenAPI document are served to, everyone by default
+    #[arg(long, value_enum)]
+    pub api_docs: Option<ApiDocs>,
+
+    /// Requests a client can make per minute, across all routes, 300 by default
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rate_limit: Option<u32>,
+
+    /// Login attempts a client can make per minute, 10 by default
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub login_rate_limit: Option<u32>,
+
+    /// Users a client can create per minute, 3 by default
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub user_creation_rate_limit: Option<u32>,
+
+    /// Don't limit the requests of clients, e.g. when a reverse proxy limits them
+    #[arg(long)]
+    pub disable_rate_limit: bool,
+
+    /// Largest request body accepted, in KiB, except for imports and attachments
+    #[arg(long, default_value = "1024", value_parser = clap::value_parser!(u32).range(1..))]
+    pub body_limit_kib: u32,
+
+    /// Largest transaction import or attachment upload request body accepted, in KiB
+    #[arg(long, default_value = "10304", value_parser = clap::value_parser!(u32).range(1..))]
+    pub upload_body_limit_kib: u32,
+
+    /// Don't compress responses, e.g. when a reverse proxy compresses them
+    #[arg(long)]
+    pub disable_compression: bool,
+
+    /// Size in bytes a response must be over to be compressed
+    #[arg(long, default_value = "1024")]
+    pub compression_min_bytes: u16,
+
+    /// Seconds a request has to be answered before it fails with a timeout
+    #[arg(long, default_value = "30", value_parser = clap::value_parser!(u64).range(1..))]
+    pub request_timeout_secs: u64,
+
+    /// Seconds a vitals, liveness or readiness request has to be answered
+    #[arg(long, default_value = "5", value_parser = clap::value_parser!(u64).range(1..))]
+    pub vitals_timeout_secs: u64,
+
+    /// Seconds a transaction import or attachment upload has to be answered
+    #[arg(long, default_value = "120", value_parser = clap::value_parser!(u64).range(1..))]
+    pub upload_timeout_secs: u64,
+
+    /// Reverse proxies whose `X-Forwarded-For` and `X-Forwarded-Proto` are trusted, comma
+    /// separated CIDR blocks or addresses, and `unix` for the peers of the Unix socket, none by
+    /// default
+    #[arg(long, value_delimiter = ',')]
+    pub trusted_proxies: Option<Vec<String>>,
+
+    /// Clients reach the server over HTTPS through a proxy terminating TLS, so responses are sent
+    /// with `Strict-Transport-Security` as they are when the server serves HTTPS
+    #[arg(long)]
+    pub behind_tls_proxy: bool,
+
+    /// Allow webhooks on plain HTTP and private network addresses, for development only
+    #[arg(long)]
+    pub allow_insecure_webhooks: bool,
+
+    /// Apply the migrations the database is missing before starting
+    #[arg(long)]
+    pub migrate: bool,
+
+    /// Apply the migrations the database is missing, then exit without starting, as `migrate` does
+    #[arg(long)]
+    pub migrate_only: bool,
+
+    /// Most connections the database pool opens, 10 by default
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub db_max_connections: Option<u32>,
+
+    /// Idle connections the database pool keeps open, as many as the most connections by default
+    #[arg(long)]
+    pub db_min_idle: Option<u32>,
+
+    /// Seconds a request waits for a database connection before failing, 2 by default
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub db_connection_timeout_secs: Option<u64>,
+
+    /// Seconds a database statement can run before it is cancelled, unlimited by default
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub db_statement_timeout_secs: Option<u64>,
+
+    /// Seconds to keep retrying to connect to the database at startup before giving up, 60 by
+    /// default
+    #[arg(long)]
+    pub db_startup_max_wait_secs: Option<u64>,
+}
+
+/// The subcommands of the server
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Serve the REST API and run the background tasks until a shutdown signal
+    Serve,
+    /// Apply the migrations the database is missing, then exit
+    Migrate,
+    /// Create an administrator, or make an existing user one, without starting the server
+    CreateAdmin {
+        /// The username of the administrator
+        #[arg(long)]
+        username: String,
+        /// The password of a new administrator, prompted for when it isn't given
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Write the OpenAPI document of the API to a file, without connecting to the database
+    GenerateOpenapi {
+        /// The file the document is written to, e.g. `openapi.json`
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Create a demo user with a year of data, only in development or test databases
+    Seed {
+        /// Seed the database even though its name contains neither `dev` nor `test`
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+impl Args {
+    /// Get the subcommand to run, `serve` when none was given
+    pub fn command(&self) -> Command {
+        self.command.clone().unwrap_or(Command::Serve)
+    }
 }
 
 /// Asynchronously runs the server with the provided arguments.
 ///
 /// # Arguments
 ///
-/// * `args` - The arguments for the server, including the REST port to listen on.
+/// * `args` - The arguments for the server, including the directory to store uploaded files in.
+/// * `config` - The configuration of the server, including the REST port to listen on, see
+///   `Config::load`.
+/// * `pool` - The database connection pool, connected in the `connect` phase of startup.
+/// * `startup` - The phases of startup completed so far, see `startup::start`.
+/// * `log_level` - Changes which events are logged when the configuration is reloaded.
 ///
 /// # Returns
 ///
@@ -37,45 +246,397 @@ pub struct Args {
 ///
 /// # Behavior
 ///
-/// This function first creates a one-shot channel for shutdown signal communication.
-/// It then spawns a new asynchronous task to start the REST server, listening on the provided port.
+/// This function first logs the configuration, with its secrets redacted, and applies the
+/// migrations of the database if `--migrate-only` is set, returning right after.
+///
+/// It then goes through the phases of startup, see `startup::start`, each logged with how long
+/// it took: it applies the migrations if `--migrate` is set, refuses to serve if migrations are
+/// pending or a table the models map is missing a column, see `check_schema`, loads the
+/// certificate of `--tls-cert`, if set, and builds the application, and only then binds the
+/// listener. The first phase that fails is returned, and the server never accepts a request.
+///
+/// It then reloads the configuration on SIGHUP, see `reload::reload`, and the certificate if
+/// there is one, creates a one-shot channel for shutdown signal
+/// communication, and spawns a new asynchronous task serving the REST application, a background
+/// task that materializes due recurring transactions once a day, and one that writes
+/// scheduled reports when they are due.
 ///
 /// The function also sets up Unix signal listeners for SIGINT (Ctrl+C) and SIGTERM (termination request).
-/// If either of these signals is received, a shutdown signal is sent to the server.
+/// If either of these signals is received, the server starts reporting that it isn't ready and,
+/// after the shutdown delay, a shutdown signal is sent to the server.
 ///
 /// The function then waits for either the server task to complete, or for a shutdown signal to be received.
-/// If a shutdown signal is received, it sends a message over the one-shot channel to signal the
-/// rest server to shut down.
+/// If a shutdown signal is received, it waits for the shutdown delay, or for the server task to
+/// complete, then sends a message over the one-shot channel to signal the rest server to shut down.
+/// The server then has the drain timeout to answer the requests in progress, see
+/// `stop_rest_server`.
 ///
-/// If the server task completes (either normally or due to an error), the function returns.
-/// If a shutdown signal is received, the function waits for the server task to shut down before returning.
+/// Once the server task completes, the background tasks are signaled to stop and are waited for,
+/// for at most the drain timeout too, and the result of the server is returned.
 ///
 /// # Errors
-/// If an error occurs while starting the REST server, it is converted to an `anyhow::Error` and
-/// returned.
-pub async fn run(args: Args, pool: Arc<DbPool>) -> Result<(), AppError> {
+/// If an error occurs while starting the REST server, or it fails while running or shutting down,
+/// the error is returned.
+pub async fn run(
+    args: Args,
+    config: Config,
+    pool: Arc<DbPool>,
+    startup: Arc<Startup>,
+    log_level: LogLevel,
+) -> Result<(), AppError> {
+    let build = BuildInfo::current();
+    tracing::info!(
+        version = build.version,
+        git_describe = build.git_describe,
+        built_at = %build.built_at,
+        rustc_version = build.rustc_version,
+        features = build.features.join(","),
+        "Build"
+    );
+    tracing::info!("Effective configuration: {config:?}");
+
+    if args.migrate_only {
+        startup.run(StartupPhase::Migrate, pool.migrate()).await?;
+        return Ok(());
+    }
+
+    let services = Services::new(
+        WebhookConfig {
+            allow_insecure: args.allow_insecure_webhooks,
+        },
+        config.quotas(),
+        AttachmentStore::new(&args.data_dir),
+    );
+    let shutdown = Arc::new(Shutdown::default());
+    let shutdown_delay = Duration::from_secs(args.shutdown_delay);
+    let drain_timeout = Duration::from_secs(args.drain_timeout_secs);
+    let address = match &args.bind_uds {
+        Some(path) => BindAddress::Unix(path.clone()),
+        None => BindAddress::Tcp(SocketAddr::new(args.bind_addr, config.rest_port)),
+    };
+
+    // Build everything the server needs before it binds, so it never accepts a request it can't
+    // answer
+    let build = || {
+        // Fail before starting anything if the certificate can't be used
+        let tls = match (&args.tls_cert, &args.tls_key) {
+            (Some(cert), Some(key)) => Some(Arc::new(TlsCertificates::load(TlsPaths {
+                cert: cert.clone(),
+                key: key.clone(),
+            })?)),
+            _ => None,
+        };
+        let http = HttpConfig {
+            rate: config.rate_limits(),
+            body: BodyLimits {
+                default: args.body_limit_kib as usize * 1024,
+                uploads: args.upload_body_limit_kib as usize * 1024,
+            },
+            compression: CompressionConfig {
+                enabled: !args.disable_compression,
+                min_size: args.compression_min_bytes,
+            },
+            timeouts: Timeouts {
+                default: Duration::from_secs(args.request_timeout_secs),
+                vitals: Duration::from_secs(args.vitals_timeout_secs),
+                uploads: Duration::from_secs(args.upload_timeout_secs),
+            },
+            // Cookies are only sent over HTTPS when the server serves it
+            sessions: SessionConfig {
+                secure_cookie: config.cookie.secure || tls.is_some(),
+                ..config.sessions()
+            },
+            cors: config.cors(),
+            access_log: config.access_log(),
+            trusted_proxies: config.trusted_proxies(),
+            api_docs: config.api_docs,
+            // Browsers are only told to stick to HTTPS when clients reach the server over it
+            security_headers: SecurityHeadersConfig {
+                strict_transport_security: tls.is_some() || args.behind_tls_proxy,
+                ..config.security_headers()
+            },
+        };
+        let jwt_keys = match &config.session.jwt_secret {
+            Some(secret) => JwtKeys::from_secret(secret.expose().as_bytes()),
+            None => JwtKeys::from_env(),
+        };
+        let app = RestApp::build(
+            &args.data_dir,
+            pool.clone(),
+            RestOptions {
+                services: services.clone(),
+                metrics_token: config
+                    .metrics_token
+                    .as_ref()
+                    .map(|token| token.expose().to_owned()),
+                jwt_keys,
+                http,
+                tls: tls.clone(),
+            },
+            shutdown.clone(),
+            startup.clone(),
+        )?;
+        // The worker running the jobs of the queue, e.g. webhook deliveries and imports
+        let worker = Worker::new(pool.clone(), QueueConfig::default(), services.clone())
+            .map_err(std::io::Error::other)?;
+        Ok((app, worker, tls))
+    };
+    let ((app, worker, tls), listener) =
+        startup::start(&startup, &pool, args.migrate, build, &address).await?;
+    match &tls {
+        Some(tls) => tracing::info!(
+            "Listening on {} with TLS, certificate {}",
+            listener.local_addr()?,
+            tls.paths().cert.display()
+        ),
+        None => tracing::info!("Listening on {}", listener.local_addr()?),
+    }
+
+    let reload_task = tokio::spawn(reload::reload_on_hangup(
+        args.clone(),
+        config.clone(),
+        app.live_config(),
+        Some(log_level),
+        tls.clone(),
+        signal(SignalKind::hangup())?,
+    ));
+
     // Create a one-shot channel for shutdown signal communication
     let (tx, rx) = oneshot::channel();
+    let (stop_jobs, jobs_stopped) = watch::channel(false);
+
+    // Spawn the background task that materializes recurring transactions
+    let recurring_task = tokio::spawn(jobs::recurring::run(
+        pool.clone(),
+        services.clock.clone(),
+        jobs_stopped.clone(),
+    ));
+
+    // Spawn the background task that writes scheduled reports
+    let reports_dir = match &args.reports_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&args.data_dir).join("reports"),
+    };
+    let reports_task = tokio::spawn(jobs::scheduled_reports::run(
+        pool.clone(),
+        services.clock.clone(),
+        reports_dir,
+        jobs_stopped.clone(),
+    ));
+
+    // Spawn the worker running the jobs of the queue
+    let queue_task = tokio::spawn(jobs::queue::run(Arc::new(worker), jobs_stopped));
+    // Queue this week's digest, each digest queues the next week's once it runs
+    let now = services.clock.now_utc();
+    if let Err(e) = pool
+        .run(move |conn| jobs::digest::schedule(conn, now))
+        .await
+    {
+        tracing::error!("Failed scheduling the weekly digest ({e})");
+    }
+    // Queue tomorrow's cleanup of the attachments, each cleanup queues the next day's
+    if let Err(e) = pool
+        .run(move |conn| jobs::attachments::schedule(conn, now))
+        .await
+    {
+        tracing::error!("Failed scheduling the cleanup of attachments ({e})");
+    }
 
-    // Spawn a new asynchronous task to start the REST server
-    let rest_server_task =
-        tokio::spawn(async move { api::start_rest_server(args.rest_port, rx, pool).await });
+    // Spawn a new asynchronous task to serve the REST application
+    let mut rest_server_task = tokio::spawn(app.serve(listener, rx));
 
     let mut sigint = signal(SignalKind::interrupt())?;
     let mut sigterm = signal(SignalKind::terminate())?;
 
     // Wait for either the REST server task to complete, or for a shutdown signal to be received
-    tokio::select! {
-      _ = rest_server_task => {},
+    let stopped = tokio::select! {
+      result = &mut rest_server_task => Some(result),
       _ = sigint.recv() => {
         println!("Received SIGINT");
-        let _ = tx.send(());
+        None
       },
       _ = sigterm.recv() => {
         println!("Received SIGTERM");
-        let _ = tx.send(());
+        None
       },
+    };
+
+    let result = match stopped {
+        // The server stopped on its own, e.g. it failed
+        Some(result) => result.map_err(AppError::from).and_then(|result| result),
+        None => {
+            // Report not being ready while requests are drained, then stop listening
+            shutdown.start();
+            tokio::select! {
+              result = &mut rest_server_task => {
+                result.map_err(AppError::from).and_then(|result| result)
+              },
+              _ = tokio::time::sleep(shutdown_delay) => {
+                stop_rest_server(tx, &mut rest_server_task, drain_timeout).await
+              },
+            }
+        }
+    };
+
+    // Background tasks finish the run in progress, if any
+    let _ = stop_jobs.send(true);
+    for (name, mut task) in [
+        ("recurring transactions", recurring_task),
+        ("scheduled reports", reports_task),
+        ("job queue", queue_task),
+    ] {
+        if tokio::time::timeout(drain_timeout, &mut task)
+            .await
+            .is_err()
+        {
+            tracing::warn!("The {name} task didn't stop within the drain timeout, aborting it");
+            task.abort();
+        }
+    }
+    reload_task.abort();
+
+    result
+}
+
+/// Stop the REST server, waiting for it to answer the requests in progress
+///
+/// # Arguments
+///
+/// * `tx` - The sender of the one-shot channel the server shuts down on
+/// * `server` - The task of the server
+/// * `drain_timeout` - How long the requests in progress have to be answered, the server is
+///   aborted after
+///
+/// # Errors
+///
+/// Returns the error the server stopped with, if any.
+pub async fn stop_rest_server(
+    tx: oneshot::Sender<()>,
+    server: &mut JoinHandle<Result<(), AppError>>,
+    drain_timeout: Duration,
+) -> Result<(), AppError> {
+    let _ = tx.send(());
+    match tokio::time::timeout(drain_timeout, &mut *server).await {
+        Ok(result) => result?,
+        Err(_) => {
+            tracing::warn!(
+                "Requests weren't answered within the drain timeout of {}s, stopping anyway",
+                drain_timeout.as_secs()
+            );
+            server.abort();
+            Ok(())
+        }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::api::test_utils::TestServer;
+
+    /// Start the server and a login request to it, sending all of it but the last byte of its body
+    async fn start_slow_request() -> (TestServer, TcpStream) {
+        let server = TestServer::start().await;
+        let body = r#"{"username":"nobody-draining","password":"password"}"#;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+        let request = format!(
+            "POST /api/v1/auth/login HTTP/1.1\r\nHost: localhost\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            body.len(),
+            &body[..body.len() - 1]
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        // The server reads the headers before it is stopped
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        (server, stream)
+    }
+
+    #[test]
+    fn test_command() {
+        let args = Args::parse_from(["finance-fusion-server", "--rest-port", "8080"]);
+        assert_eq!(args.command(), Command::Serve);
+        assert_eq!(args.rest_port, Some(8080));
+
+        let args = Args::parse_from([
+            "finance-fusion-server",
+            "-c",
+            "/etc/ff",
+            "create-admin",
+            "--username",
+            "root",
+        ]);
+        assert_eq!(
+            args.command(),
+            Command::CreateAdmin {
+                username: "root".to_owned(),
+                password: None
+            }
+        );
+        assert_eq!(args.config_dir, "/etc/ff");
 
-    Ok(())
+        let args = Args::parse_from([
+            "finance-fusion-server",
+            "generate-openapi",
+            "--out",
+            "a.json",
+        ]);
+        assert_eq!(
+            args.command(),
+            Command::GenerateOpenapi {
+                out: PathBuf::from("a.json")
+            }
+        );
+        assert!(Args::try_parse_from(["finance-fusion-server", "generate-openapi"]).is_err());
+
+        let args = Args::parse_from(["finance-fusion-server", "seed", "--force"]);
+        assert_eq!(args.command(), Command::Seed { force: true });
+    }
+
+    #[tokio::test]
+    async fn test_requests_in_progress_are_answered() {
+        let (
+            TestServer {
+                addr,
+                stop,
+                mut task,
+            },
+            mut stream,
+        ) = start_slow_request().await;
+        let drain = tokio::spawn(async move {
+            stop_rest_server(stop, &mut task, Duration::from_secs(10)).await
+        });
+
+        // The server stops listening, but waits for the request in progress
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!drain.is_finished());
+        assert!(TcpStream::connect(addr).await.is_err());
+
+        stream.write_all(b"}").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 "), "{response}");
+        assert!(response.ends_with('}'), "{response}");
+
+        drain.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout() {
+        let (TestServer { stop, mut task, .. }, _stream) = start_slow_request().await;
+
+        // The request is never completed, the server is aborted
+        let started = Instant::now();
+        stop_rest_server(stop, &mut task, Duration::from_millis(200))
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(task.await.unwrap_err().is_cancelled());
+    }
 }
@@ -0,0 +1,271 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use tracing::Subscriber;
+use tracing_subscriber::{
+    fmt::{time::ChronoUtc, MakeWriter},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    reload, EnvFilter, Layer, Registry,
+};
+
+use crate::errors::AppError;
+
+/// Logged when neither `--log-level` nor `RUST_LOG` is set
+///
+/// axum logs rejections from built-in extractors with the `axum::rejection` target, at `TRACE`
+/// level. `axum::rejection=trace` enables showing those events.
+const DEFAULT_FILTER: &str = "finance_fusion=info,tower_http=info,axum::rejection=trace";
+
+/// How log lines are formatted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable, with the fields of the spans of events
+    Pretty,
+    /// Human readable and shorter, without the names of the fields of spans
+    Compact,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+/// Get the filter of the logged events
+///
+/// # Arguments
+///
+/// * `level` - Directives of `--log-level`, e.g. `debug` or `finance_fusion=debug,tower_http=warn`,
+///   which take precedence over `RUST_LOG`
+pub fn filter(level: Option<&str>) -> Result<EnvFilter, AppError> {
+    match level {
+        Some(level) => EnvFilter::try_new(level)
+            .map_err(|e| AppError::InvalidInput(format!("Invalid log level `{level}`: {e}"))),
+        None => Ok(EnvFilter::try_from_default_env().unwrap_or_else(|_| DEFAULT_FILTER.into())),
+    }
+}
+
+/// Build the subscriber logging the events to a writer
+///
+/// JSON lines have the fields of events as top-level keys next to `timestamp`, in RFC 3339, `level`
+/// and `target`, with the current span in `span` and the spans it is in in `spans`.
+///
+/// # Arguments
+///
+/// * `format` - How log lines are formatted
+/// * `filter` - Which events are logged, see `filter`
+/// * `writer` - Where log lines are written, e.g. `std::io::stdout`
+pub fn subscriber<W>(
+    format: LogFormat,
+    filter: EnvFilter,
+    writer: W,
+) -> impl Subscriber + Send + Sync + 'static
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer(format, writer))
+}
+
+/// Changes which events a subscriber logs while it runs, see `reloadable_subscriber`
+#[derive(Clone)]
+pub struct LogLevel(reload::Handle<EnvFilter, Registry>);
+
+impl LogLevel {
+    /// Log the events of a level from now on
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Directives of the level, `RUST_LOG` or the default when `None`, see `filter`
+    pub fn set(&self, level: Option<&str>) -> Result<(), AppError> {
+        let filter = filter(level)?;
+        self.0
+            .reload(filter)
+            .map_err(|e| AppError::Signal(std::io::Error::other(e)))
+    }
+}
+
+impl fmt::Debug for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LogLevel")
+    }
+}
+
+/// Build the subscriber logging the events to a writer, whose filter can change while it runs
+///
+/// # Arguments
+///
+/// See `subscriber`
+///
+/// # Returns
+///
+/// The subscriber, and the `LogLevel` changing its filter
+pub fn reloadable_subscriber<W>(
+    format: LogFormat,
+    filter: EnvFilter,
+    writer: W,
+) -> (impl Subscriber + Send + Sync + 'static, LogLevel)
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let (filter, handle) = reload::Layer::new(filter);
+    let subscriber = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer(format, writer));
+    (subscriber, LogLevel(handle))
+}
+
+/// Build the layer formatting events
+fn fmt_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        LogFormat::Pretty => layer.boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer
+            .json()
+            .with_timer(ChronoUtc::rfc_3339())
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+/// Log lines written by a subscriber, shared with the test reading them
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl CapturedLogs {
+    /// Get the lines written so far
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+
+    /// Get the lines written so far by a subscriber of the JSON format
+    pub fn json_lines(&self) -> Vec<serde_json::Value> {
+        self.text()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl<'writer> MakeWriter<'writer> for CapturedLogs {
+    type Writer = CapturedLogs;
+
+    fn make_writer(&'writer self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    /// Log a request like the access log does, with a subscriber of a format
+    fn log_request(format: LogFormat) -> String {
+        let lines = CapturedLogs::default();
+        let subscriber = subscriber(format, filter(Some("info")).unwrap(), lines.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", request_id = "abc-123");
+            let _entered = span.enter();
+            tracing::info!(
+                method = "GET",
+                route = "/api/v1/accounts/:id",
+                status = 200,
+                latency_ms = 12,
+                user_id = 7,
+                "Finished request"
+            );
+            tracing::debug!("Filtered out");
+        });
+        lines.text()
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let text = log_request(LogFormat::Json);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1, "{text}");
+        let line: Value = serde_json::from_str(lines[0]).unwrap();
+
+        // Fields are top-level keys, not part of the message
+        assert_eq!(line["message"], "Finished request");
+        assert_eq!(line["method"], "GET");
+        assert_eq!(line["route"], "/api/v1/accounts/:id");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["latency_ms"], 12);
+        assert_eq!(line["user_id"], 7);
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], module_path!());
+        assert_eq!(line["span"]["name"], "request");
+        assert_eq!(line["span"]["request_id"], "abc-123");
+        assert_eq!(line["spans"][0]["request_id"], "abc-123");
+        let timestamp = line["timestamp"].as_str().unwrap();
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(timestamp).is_ok(),
+            "{timestamp}"
+        );
+    }
+
+    #[test]
+    fn test_text_lines() {
+        for format in [LogFormat::Pretty, LogFormat::Compact] {
+            let text = log_request(format);
+            assert!(text.contains("Finished request"), "{text}");
+            assert!(text.contains("abc-123"), "{text}");
+            assert!(!text.contains("Filtered out"), "{text}");
+            assert!(serde_json::from_str::<Value>(text.lines().next().unwrap()).is_err());
+        }
+        assert!(log_request(LogFormat::Pretty).contains("request_id"));
+    }
+
+    #[test]
+    fn test_invalid_level() {
+        assert!(filter(Some("debug")).is_ok());
+        assert!(filter(Some("finance_fusion=debug,tower_http=warn")).is_ok());
+        let error = filter(Some("finance_fusion=loud")).unwrap_err().to_string();
+        assert!(error.contains("Invalid log level"), "{error}");
+    }
+
+    #[test]
+    fn test_level_changes() {
+        let lines = CapturedLogs::default();
+        let (subscriber, level) = reloadable_subscriber(
+            LogFormat::Compact,
+            filter(Some("info")).unwrap(),
+            lines.clone(),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("Before");
+            level.set(Some("debug")).unwrap();
+            tracing::debug!("After");
+
+            // An invalid level keeps the current one
+            assert!(level.set(Some("finance_fusion=loud")).is_err());
+            tracing::debug!("Still");
+        });
+        let text = lines.text();
+        assert!(!text.contains("Before"), "{text}");
+        assert!(text.contains("After"), "{text}");
+        assert!(text.contains("Still"), "{text}");
+    }
+}
@@ -1 +1,8 @@
+pub mod build_info;
+pub mod commands;
+#[allow(clippy::module_inception)]
 pub mod config;
+pub mod logging;
+pub mod reload;
+pub mod settings;
+pub mod startup;
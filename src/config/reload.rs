@@ -0,0 +1,234 @@
+//! Reloading the configuration while the server runs
+//!
+//! On SIGHUP the configuration is loaded again, from the file, the environment and the flags the
+//! server started with. The log level, CORS origins and rate limits are applied to the requests
+//! that follow, the other keys are reported as taking effect once the server restarts. A
+//! configuration that is invalid is rejected whole, and the one in effect is kept.
+
+use std::sync::Arc;
+
+use tokio::signal::unix::Signal;
+
+use crate::api::api::LiveConfig;
+use crate::api::tls::TlsCertificates;
+use crate::config::{config::Args, logging::LogLevel, settings::Config};
+use crate::errors::AppError;
+
+/// Reload the configuration, applying the keys that take effect while the server runs
+///
+/// # Arguments
+///
+/// * `args` - The command line flags, which still override the file and the environment
+/// * `var` - Gets the value of an environment variable, if it is set
+/// * `started` - The configuration the server started with, which the keys requiring a restart
+///   are compared with
+/// * `live` - The settings the middlewares read on every request
+/// * `log_level` - Changes which events are logged, if the subscriber can
+///
+/// # Returns
+///
+/// The reloaded configuration, otherwise the error it is invalid with, the settings in effect
+/// being kept. Either is logged.
+pub fn reload(
+    args: &Args,
+    var: impl Fn(&str) -> Option<String>,
+    started: &Config,
+    live: &LiveConfig,
+    log_level: Option<&LogLevel>,
+) -> Result<Config, AppError> {
+    let config = match Config::load(args, var) {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Failed to reload the configuration, keeping the current one ({e})");
+            return Err(e);
+        }
+    };
+
+    if let Some(log_level) = log_level {
+        log_level.set(config.log_level.as_deref())?;
+    }
+    live.set_rate_limits(config.rate_limits());
+    live.set_cors(config.cors());
+    for key in started.restart_required(&config) {
+        tracing::warn!(
+            key,
+            "The configuration `{key}` changed, it takes effect once the server restarts"
+        );
+    }
+    tracing::info!("Reloaded configuration: {config:?}");
+
+    Ok(config)
+}
+
+/// Reload the configuration, and the TLS certificate if the server serves HTTPS, each time the
+/// server receives a SIGHUP
+///
+/// # Arguments
+///
+/// * `args` - The command line flags the server started with
+/// * `started` - The configuration the server started with
+/// * `live` - The settings the middlewares read on every request
+/// * `log_level` - Changes which events are logged
+/// * `tls` - The certificate of the server, if any
+/// * `hangup` - The SIGHUP listener, created before the server starts so the signal doesn't
+///   terminate it
+pub async fn reload_on_hangup(
+    args: Args,
+    started: Config,
+    live: Arc<LiveConfig>,
+    log_level: Option<LogLevel>,
+    tls: Option<Arc<TlsCertificates>>,
+    mut hangup: Signal,
+) {
+    while hangup.recv().await.is_some() {
+        // Errors are logged by `reload`
+        let _ = reload(
+            &args,
+            |name| std::env::var(name).ok(),
+            &started,
+            &live,
+            log_level.as_ref(),
+        );
+
+        if let Some(tls) = &tls {
+            match tls.reload() {
+                Ok(()) => tracing::info!(
+                    "Reloaded the TLS certificate from {}",
+                    tls.paths().cert.display()
+                ),
+                Err(e) => tracing::error!(
+                    "Failed to reload the TLS certificate, keeping the current one ({e})"
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+    };
+    use clap::Parser;
+
+    use super::*;
+    use crate::api::{api::HttpConfig, test_utils::TestApp};
+    use crate::config::logging::{self, CapturedLogs, LogFormat};
+    use crate::config::settings::CONFIG_FILE;
+    use crate::middleware::forwarded::TrustedProxies;
+
+    /// Write the configuration file of a directory
+    fn write_config(dir: &Path, contents: &str) {
+        std::fs::write(dir.join(CONFIG_FILE), contents).unwrap();
+    }
+
+    /// Reload the configuration, capturing what is logged
+    fn reload_logged(
+        args: &Args,
+        started: &Config,
+        live: &LiveConfig,
+    ) -> (Result<Config, AppError>, Vec<serde_json::Value>) {
+        let logs = CapturedLogs::default();
+        let subscriber = logging::subscriber(
+            LogFormat::Json,
+            logging::filter(Some("info")).unwrap(),
+            logs.clone(),
+        );
+        let result = tracing::subscriber::with_default(subscriber, || {
+            reload(args, |_| None, started, live, None)
+        });
+        (result, logs.json_lines())
+    }
+
+    fn accounts(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::GET)
+            .uri("/api/v1/accounts")
+            .header("x-forwarded-for", "10.0.0.9")
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let dir =
+            std::env::temp_dir().join(format!("finance-fusion-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_config(&dir, "rest_port = 5000\n[rate_limit]\nper_minute = 300");
+        let args = Args::parse_from(["finance-fusion-server", "-c", dir.to_str().unwrap()]);
+        let started = Config::load(&args, |_| None).unwrap();
+        let app = TestApp::with_config(HttpConfig {
+            rate: started.rate_limits(),
+            cors: started.cors(),
+            // Requests of the tests come from no address, as they would from a Unix socket
+            trusted_proxies: TrustedProxies::parse(&["unix"]).unwrap(),
+            ..HttpConfig::default()
+        });
+
+        write_config(
+            &dir,
+            r#"
+            rest_port = 6000
+
+            [rate_limit]
+            per_minute = 2
+
+            [cors]
+            allowed_origins = ["https://finance.example.com"]
+            "#,
+        );
+        let (result, lines) = reload_logged(&args, &started, app.live_config());
+        assert_eq!(result.unwrap().rest_port, 6000);
+
+        // The port is only changed once the server restarts
+        let warnings: Vec<_> = lines
+            .iter()
+            .filter(|line| line["level"] == "WARN")
+            .collect();
+        assert_eq!(warnings.len(), 1, "{lines:?}");
+        assert_eq!(warnings[0]["key"], "rest_port");
+
+        // The requests that follow have the new limits and origins
+        for _ in 0..2 {
+            let (status, headers, _) = app.send(accounts("https://finance.example.com")).await;
+            assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(
+                headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "https://finance.example.com"
+            );
+        }
+        let (status, _, _) = app.send(accounts("http://localhost:3000")).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let (_, headers, _) = app
+            .send(
+                Request::get("/version")
+                    .header(header::ORIGIN, "http://localhost:3000")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        // An invalid configuration is logged, and the current one kept
+        write_config(&dir, "[rate_limit]\nper_minute = 0");
+        let (result, lines) = reload_logged(&args, &started, app.live_config());
+        assert!(matches!(result, Err(AppError::InvalidInput(_))));
+        assert_eq!(lines.len(), 1, "{lines:?}");
+        assert_eq!(lines[0]["level"], "ERROR");
+        assert!(lines[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("`rate_limit.per_minute`"));
+        assert_eq!(app.live_config().rate_limiter().config().global.requests, 2);
+        assert_eq!(
+            app.live_config().allowed_origins(),
+            ["https://finance.example.com"]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
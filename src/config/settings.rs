@@ -0,0 +1,954 @@
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use axum::http::HeaderValue;
+use serde::Deserialize;
+use toml::{Table, Value};
+use tracing_subscriber::EnvFilter;
+
+use crate::api::api::{ApiDocs, CorsConfig};
+use crate::config::config::Args;
+use crate::database::connection::PoolConfig;
+use crate::errors::AppError;
+use crate::middleware::access_log::AccessLogConfig;
+use crate::middleware::forwarded::TrustedProxies;
+use crate::middleware::security_headers::{
+    SecurityHeadersConfig, DEFAULT_CONTENT_SECURITY_POLICY,
+    DEFAULT_SWAGGER_UI_CONTENT_SECURITY_POLICY,
+};
+use crate::quotas::Quotas;
+use crate::rate_limit::{RateLimit, RateLimitConfig};
+use crate::routes::auth::{SameSite, SessionConfig};
+
+/// Name of the configuration file in the configuration directory
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Prefix of the environment variables overriding keys of the configuration, e.g.
+/// `FINANCE_FUSION_RATE_LIMIT_PER_MINUTE` for `rate_limit.per_minute`
+pub const ENV_PREFIX: &str = "FINANCE_FUSION_";
+
+/// How the value of an environment variable is read
+#[derive(Debug, Clone, Copy)]
+enum EnvKind {
+    /// A TOML value, e.g. `300` or `true`
+    Value,
+    /// A string as is
+    Text,
+    /// Strings separated by commas
+    List,
+}
+
+/// The keys of the configuration that environment variables can override
+const ENV_KEYS: [(&str, EnvKind); 28] = [
+    ("rest_port", EnvKind::Value),
+    ("log_level", EnvKind::Text),
+    ("metrics_token", EnvKind::Text),
+    ("trusted_proxies", EnvKind::List),
+    ("api_docs", EnvKind::Text),
+    ("session.ttl_hours", EnvKind::Value),
+    ("session.jwt_secret", EnvKind::Text),
+    ("cookie.secure", EnvKind::Value),
+    ("cookie.same_site", EnvKind::Text),
+    ("database.max_connections", EnvKind::Value),
+    ("database.min_idle", EnvKind::Value),
+    ("database.connection_timeout_secs", EnvKind::Value),
+    ("database.statement_timeout_secs", EnvKind::Value),
+    ("database.startup_max_wait_secs", EnvKind::Value),
+    ("cors.allowed_origins", EnvKind::List),
+    ("rate_limit.enabled", EnvKind::Value),
+    ("rate_limit.per_minute", EnvKind::Value),
+    ("rate_limit.login_per_minute", EnvKind::Value),
+    ("rate_limit.user_creation_per_minute", EnvKind::Value),
+    ("access_log.sampled_routes", EnvKind::List),
+    ("access_log.sample_every", EnvKind::Value),
+    ("security_headers.content_security_policy", EnvKind::Text),
+    (
+        "security_headers.swagger_ui_content_security_policy",
+        EnvKind::Text,
+    ),
+    ("quotas.enabled", EnvKind::Value),
+    ("quotas.transactions", EnvKind::Value),
+    ("quotas.attachment_bytes", EnvKind::Value),
+    ("quotas.webhooks", EnvKind::Value),
+    ("quotas.accounts", EnvKind::Value),
+];
+
+/// A value of the configuration that is never logged
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Get the value of the secret
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"<redacted>\"")
+    }
+}
+
+/// The configuration of the server
+///
+/// It is read from `config.toml` in the configuration directory, whose keys are overridden by
+/// environment variables, which are overridden by command line flags. Keys missing from all of
+/// them keep their defaults, so the server starts without a configuration file.
+///
+/// The log level, CORS origins and rate limits are applied again when the configuration is
+/// reloaded, see `config::reload`, the other keys take effect once the server restarts.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The port the REST server listens on
+    pub rest_port: u16,
+    /// Which events are logged, e.g. `finance_fusion=debug`, `RUST_LOG` is used when it isn't set
+    pub log_level: Option<String>,
+    /// Bearer token required to read the metrics, which are open when it isn't set
+    pub metrics_token: Option<Secret>,
+    /// Reverse proxies whose forwarded headers are trusted, CIDR blocks, addresses or `unix`
+    pub trusted_proxies: Vec<String>,
+    /// Who the Swagger UI and the OpenAPI document are served to, `open`, `admin` or `disabled`
+    pub api_docs: ApiDocs,
+    pub session: SessionSettings,
+    pub cookie: CookieSettings,
+    pub database: DatabaseSettings,
+    pub cors: CorsSettings,
+    pub rate_limit: RateLimitSettings,
+    pub access_log: AccessLogSettings,
+    pub security_headers: SecurityHeadersSettings,
+    pub quotas: QuotaSettings,
+}
+
+/// How long sessions last and how their tokens are signed
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionSettings {
+    /// Hours a session lasts after logging in
+    pub ttl_hours: u32,
+    /// Secret signing the tokens of sessions, `JWT_SECRET` is used when it isn't set
+    pub jwt_secret: Option<Secret>,
+}
+
+/// How the cookie of sessions is set
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CookieSettings {
+    /// Whether the cookie is only sent over HTTPS, always the case when the server serves HTTPS
+    pub secure: bool,
+    /// Which cross-site requests the cookie is sent with, `Strict`, `Lax` or `None`
+    pub same_site: SameSite,
+}
+
+/// Sizing and timeouts of the database pool, see `PoolConfig`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    pub max_connections: u32,
+    pub min_idle: Option<u32>,
+    pub connection_timeout_secs: u64,
+    pub statement_timeout_secs: Option<u64>,
+    pub startup_max_wait_secs: u64,
+}
+
+/// Which origins browsers can call the API from
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsSettings {
+    pub allowed_origins: Vec<String>,
+}
+
+/// Requests a client can make per minute
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    pub per_minute: u32,
+    pub login_per_minute: u32,
+    pub user_creation_per_minute: u32,
+}
+
+/// Which requests are logged, see `AccessLogConfig`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessLogSettings {
+    pub sampled_routes: Vec<String>,
+    pub sample_every: u64,
+}
+
+/// The content security policies of responses, see `SecurityHeadersConfig`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityHeadersSettings {
+    pub content_security_policy: String,
+    pub swagger_ui_content_security_policy: String,
+}
+
+/// How much data each user can store, see `Quotas`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaSettings {
+    pub enabled: bool,
+    pub transactions: u64,
+    pub attachment_bytes: u64,
+    pub webhooks: u64,
+    pub accounts: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rest_port: 5000,
+            log_level: None,
+            metrics_token: None,
+            trusted_proxies: vec![],
+            api_docs: ApiDocs::default(),
+            session: SessionSettings::default(),
+            cookie: CookieSettings::default(),
+            database: DatabaseSettings::default(),
+            cors: CorsSettings::default(),
+            rate_limit: RateLimitSettings::default(),
+            access_log: AccessLogSettings::default(),
+            security_headers: SecurityHeadersSettings::default(),
+            quotas: QuotaSettings::default(),
+        }
+    }
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            ttl_hours: 24,
+            jwt_secret: None,
+        }
+    }
+}
+
+impl Default for CookieSettings {
+    fn default() -> Self {
+        Self {
+            secure: true,
+            same_site: SameSite::Strict,
+        }
+    }
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        let pool = PoolConfig::default();
+        Self {
+            max_connections: pool.max_size,
+            min_idle: pool.min_idle,
+            connection_timeout_secs: pool.connection_timeout.as_secs(),
+            statement_timeout_secs: None,
+            startup_max_wait_secs: pool.startup_max_wait.as_secs(),
+        }
+    }
+}
+
+impl Default for CorsSettings {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["http://localhost:3000".to_string()],
+        }
+    }
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        let limits = RateLimitConfig::default();
+        Self {
+            enabled: limits.enabled,
+            per_minute: limits.global.requests,
+            login_per_minute: limits.login.requests,
+            user_creation_per_minute: limits.user_creation.requests,
+        }
+    }
+}
+
+impl Default for AccessLogSettings {
+    fn default() -> Self {
+        let config = AccessLogConfig::default();
+        Self {
+            sampled_routes: config.sampled_routes,
+            sample_every: config.sample_every,
+        }
+    }
+}
+
+impl Default for SecurityHeadersSettings {
+    fn default() -> Self {
+        Self {
+            content_security_policy: DEFAULT_CONTENT_SECURITY_POLICY.to_string(),
+            swagger_ui_content_security_policy: DEFAULT_SWAGGER_UI_CONTENT_SECURITY_POLICY
+                .to_string(),
+        }
+    }
+}
+
+impl Default for QuotaSettings {
+    fn default() -> Self {
+        let quotas = Quotas::default();
+        Self {
+            enabled: quotas.enabled,
+            transactions: quotas.transactions,
+            attachment_bytes: quotas.attachment_bytes,
+            webhooks: quotas.webhooks,
+            accounts: quotas.accounts,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration from the file in the configuration directory, the environment and
+    /// the command line flags, in increasing precedence
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command line flags, including the configuration directory
+    /// * `var` - Gets the value of an environment variable, if it is set
+    ///
+    /// # Returns
+    ///
+    /// The validated configuration, otherwise `AppError::InvalidInput` naming the offending key
+    pub fn load(args: &Args, var: impl Fn(&str) -> Option<String>) -> Result<Self, AppError> {
+        let path = Path::new(&args.config_dir).join(CONFIG_FILE);
+        let mut table = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents.parse::<Table>().map_err(|e| {
+                AppError::InvalidInput(format!(
+                    "Invalid configuration file {}: {e}",
+                    path.display()
+                ))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Table::new(),
+            Err(e) => {
+                return Err(AppError::InvalidInput(format!(
+                    "Failed to read configuration file {}: {e}",
+                    path.display()
+                )))
+            }
+        };
+
+        for (key, kind) in ENV_KEYS {
+            let name = format!("{ENV_PREFIX}{}", key.replace('.', "_").to_uppercase());
+            if let Some(raw) = var(&name) {
+                set(&mut table, key, env_value(&raw, kind));
+            }
+        }
+
+        let mut config = Self::from_table(table)?;
+        config.apply_args(args);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Deserialize the configuration, naming the key that has an invalid value
+    fn from_table(table: Table) -> Result<Self, AppError> {
+        Value::Table(table.clone())
+            .try_into()
+            .map_err(|e: toml::de::Error| {
+                // Keys are valid on their own but for the invalid one, which is found by trying each
+                let invalid = leaves(&table).into_iter().find_map(|(key, value)| {
+                    let mut single = Table::new();
+                    set(&mut single, &key, value);
+                    Value::Table(single)
+                        .try_into::<Config>()
+                        .err()
+                        .map(|e| (key, e))
+                });
+                match invalid {
+                    Some((key, e)) => AppError::InvalidInput(format!(
+                        "Invalid configuration `{key}`: {}",
+                        e.message()
+                    )),
+                    None => {
+                        AppError::InvalidInput(format!("Invalid configuration: {}", e.message()))
+                    }
+                }
+            })
+    }
+
+    /// Override the configuration with the command line flags that are set
+    fn apply_args(&mut self, args: &Args) {
+        if let Some(port) = args.rest_port {
+            self.rest_port = port;
+        }
+        if let Some(level) = &args.log_level {
+            self.log_level = Some(level.clone());
+        }
+        if let Some(token) = &args.metrics_token {
+            self.metrics_token = Some(Secret(token.clone()));
+        }
+        if let Some(proxies) = &args.trusted_proxies {
+            self.trusted_proxies.clone_from(proxies);
+        }
+        if let Some(api_docs) = args.api_docs {
+            self.api_docs = api_docs;
+        }
+        if let Some(requests) = args.rate_limit {
+            self.rate_limit.per_minute = requests;
+        }
+        if let Some(requests) = args.login_rate_limit {
+            self.rate_limit.login_per_minute = requests;
+        }
+        if let Some(requests) = args.user_creation_rate_limit {
+            self.rate_limit.user_creation_per_minute = requests;
+        }
+        if args.disable_rate_limit {
+            self.rate_limit.enabled = false;
+        }
+        if let Some(max) = args.db_max_connections {
+            self.database.max_connections = max;
+        }
+        if let Some(min_idle) = args.db_min_idle {
+            self.database.min_idle = Some(min_idle);
+        }
+        if let Some(secs) = args.db_connection_timeout_secs {
+            self.database.connection_timeout_secs = secs;
+        }
+        if let Some(secs) = args.db_statement_timeout_secs {
+            self.database.statement_timeout_secs = Some(secs);
+        }
+        if let Some(secs) = args.db_startup_max_wait_secs {
+            self.database.startup_max_wait_secs = secs;
+        }
+    }
+
+    /// Check that the values of the configuration can be used
+    fn validate(&self) -> Result<(), AppError> {
+        let invalid = |key: &str, reason: &str| {
+            AppError::InvalidInput(format!("Invalid configuration `{key}`: {reason}"))
+        };
+        let at_least_one = [
+            ("session.ttl_hours", self.session.ttl_hours as u64),
+            (
+                "database.max_connections",
+                self.database.max_connections as u64,
+            ),
+            (
+                "database.connection_timeout_secs",
+                self.database.connection_timeout_secs,
+            ),
+            ("rate_limit.per_minute", self.rate_limit.per_minute as u64),
+            (
+                "rate_limit.login_per_minute",
+                self.rate_limit.login_per_minute as u64,
+            ),
+            (
+                "rate_limit.user_creation_per_minute",
+                self.rate_limit.user_creation_per_minute as u64,
+            ),
+            ("access_log.sample_every", self.access_log.sample_every),
+            ("quotas.transactions", self.quotas.transactions),
+            ("quotas.attachment_bytes", self.quotas.attachment_bytes),
+        ];
+        for (key, value) in at_least_one {
+            if value == 0 {
+                return Err(invalid(key, "must be at least 1"));
+            }
+        }
+        if self.database.statement_timeout_secs == Some(0) {
+            return Err(invalid(
+                "database.statement_timeout_secs",
+                "must be at least 1",
+            ));
+        }
+
+        if self.session.ttl_hours > 24 * 365 {
+            return Err(invalid("session.ttl_hours", "must be at most a year"));
+        }
+        if self
+            .database
+            .min_idle
+            .is_some_and(|min_idle| min_idle > self.database.max_connections)
+        {
+            return Err(invalid(
+                "database.min_idle",
+                "must be at most `database.max_connections`",
+            ));
+        }
+        if self.cookie.same_site == SameSite::None && !self.cookie.secure {
+            return Err(invalid(
+                "cookie.same_site",
+                "`None` requires `cookie.secure`, browsers reject it otherwise",
+            ));
+        }
+        for origin in &self.cors.allowed_origins {
+            let valid = url::Url::parse(origin)
+                .is_ok_and(|url| url.origin().ascii_serialization() == *origin);
+            if !valid {
+                return Err(invalid(
+                    "cors.allowed_origins",
+                    &format!("`{origin}` isn't an origin, e.g. `https://finance.example.com`"),
+                ));
+            }
+        }
+        if let Some(level) = &self.log_level {
+            if let Err(e) = EnvFilter::try_new(level) {
+                return Err(invalid("log_level", &e.to_string()));
+            }
+        }
+        if let Err(reason) = TrustedProxies::parse(&self.trusted_proxies) {
+            return Err(invalid("trusted_proxies", &reason));
+        }
+        let policies = [
+            (
+                "security_headers.content_security_policy",
+                &self.security_headers.content_security_policy,
+            ),
+            (
+                "security_headers.swagger_ui_content_security_policy",
+                &self.security_headers.swagger_ui_content_security_policy,
+            ),
+        ];
+        for (key, policy) in policies {
+            if policy.trim().is_empty() || HeaderValue::from_str(policy).is_err() {
+                return Err(invalid(key, "must be a policy on a single line"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// List the keys that differ from another configuration and only take effect once the
+    /// server restarts
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The configuration to compare with, e.g. one just reloaded
+    pub fn restart_required(&self, other: &Config) -> Vec<&'static str> {
+        // Keys are listed exhaustively, so new ones are either reloaded or added here
+        let Config {
+            rest_port,
+            log_level: _,
+            metrics_token,
+            trusted_proxies,
+            api_docs,
+            session,
+            cookie,
+            database,
+            cors: _,
+            rate_limit: _,
+            access_log,
+            security_headers,
+            quotas,
+        } = self;
+        let changed = [
+            ("rest_port", *rest_port != other.rest_port),
+            ("metrics_token", *metrics_token != other.metrics_token),
+            ("trusted_proxies", *trusted_proxies != other.trusted_proxies),
+            ("api_docs", *api_docs != other.api_docs),
+            ("session", *session != other.session),
+            ("cookie", *cookie != other.cookie),
+            ("database", *database != other.database),
+            ("access_log", *access_log != other.access_log),
+            (
+                "security_headers",
+                *security_headers != other.security_headers,
+            ),
+            ("quotas", *quotas != other.quotas),
+        ];
+        changed
+            .into_iter()
+            .filter_map(|(key, changed)| changed.then_some(key))
+            .collect()
+    }
+
+    /// Get the sizing and timeouts of the database pool
+    pub fn pool_config(&self) -> PoolConfig {
+        PoolConfig {
+            max_size: self.database.max_connections,
+            min_idle: self.database.min_idle,
+            connection_timeout: Duration::from_secs(self.database.connection_timeout_secs),
+            statement_timeout: self
+                .database
+                .statement_timeout_secs
+                .map(Duration::from_secs),
+            startup_max_wait: Duration::from_secs(self.database.startup_max_wait_secs),
+        }
+    }
+
+    /// Get the limits of the requests of each client
+    pub fn rate_limits(&self) -> RateLimitConfig {
+        RateLimitConfig {
+            enabled: self.rate_limit.enabled,
+            global: RateLimit::per_minute(self.rate_limit.per_minute),
+            login: RateLimit::per_minute(self.rate_limit.login_per_minute),
+            user_creation: RateLimit::per_minute(self.rate_limit.user_creation_per_minute),
+        }
+    }
+
+    /// Get how long sessions last and how their cookies are set
+    pub fn sessions(&self) -> SessionConfig {
+        SessionConfig {
+            ttl: chrono::Duration::hours(self.session.ttl_hours.into()),
+            secure_cookie: self.cookie.secure,
+            same_site: self.cookie.same_site,
+        }
+    }
+
+    /// Get the origins browsers can call the API from
+    pub fn cors(&self) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: self
+                .cors
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok())
+                .collect(),
+        }
+    }
+
+    /// Get which requests are logged
+    pub fn access_log(&self) -> AccessLogConfig {
+        AccessLogConfig {
+            sampled_routes: self.access_log.sampled_routes.clone(),
+            sample_every: self.access_log.sample_every,
+        }
+    }
+
+    /// Get the reverse proxies whose forwarded headers are trusted
+    pub fn trusted_proxies(&self) -> TrustedProxies {
+        TrustedProxies::parse(&self.trusted_proxies).expect("Trusted proxies are validated")
+    }
+
+    /// Get the security headers of responses, `Strict-Transport-Security` is set by the server
+    /// when clients reach it over HTTPS
+    pub fn security_headers(&self) -> SecurityHeadersConfig {
+        let policy = |policy: &str| HeaderValue::from_str(policy).expect("Policies are validated");
+        SecurityHeadersConfig {
+            content_security_policy: policy(&self.security_headers.content_security_policy),
+            swagger_ui_content_security_policy: policy(
+                &self.security_headers.swagger_ui_content_security_policy,
+            ),
+            strict_transport_security: false,
+        }
+    }
+
+    /// Get how much data each user can store
+    pub fn quotas(&self) -> Quotas {
+        Quotas {
+            enabled: self.quotas.enabled,
+            transactions: self.quotas.transactions,
+            attachment_bytes: self.quotas.attachment_bytes,
+            webhooks: self.quotas.webhooks,
+            accounts: self.quotas.accounts,
+        }
+    }
+}
+
+/// Read the value of an environment variable overriding a key
+fn env_value(raw: &str, kind: EnvKind) -> Value {
+    match kind {
+        EnvKind::Text => Value::String(raw.to_string()),
+        EnvKind::List => Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        ),
+        // Values that aren't valid TOML are kept as strings, and rejected if the key isn't one
+        EnvKind::Value => format!("value = {raw}")
+            .parse::<Table>()
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| Value::String(raw.to_string())),
+    }
+}
+
+/// Set a dotted key of a table, e.g. `rate_limit.per_minute`, creating its section if needed
+fn set(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        None => {
+            table.insert(key.to_string(), value);
+        }
+        Some((section, key)) => {
+            let section = table
+                .entry(section)
+                .or_insert_with(|| Value::Table(Table::new()));
+            // A section set to something other than a table is reported when it is deserialized
+            if let Value::Table(section) = section {
+                section.insert(key.to_string(), value);
+            }
+        }
+    }
+}
+
+/// List the keys of a table with their values, down to the keys of its sections
+fn leaves(table: &Table) -> Vec<(String, Value)> {
+    table
+        .iter()
+        .flat_map(|(key, value)| match value {
+            Value::Table(section) => section
+                .iter()
+                .map(|(inner, value)| (format!("{key}.{inner}"), value.clone()))
+                .collect(),
+            value => vec![(key.clone(), value.clone())],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
+    use super::*;
+
+    /// A configuration directory holding a `config.toml`, removed when dropped
+    struct ConfigDir(PathBuf);
+
+    impl ConfigDir {
+        fn new(name: &str, contents: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "finance-fusion-config-{name}-{}",
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(CONFIG_FILE), contents).unwrap();
+            Self(dir)
+        }
+
+        fn args(&self, flags: &[&str]) -> Args {
+            let dir = self.0.to_str().unwrap();
+            Args::parse_from(
+                ["finance-fusion-server", "--config-dir", dir]
+                    .iter()
+                    .chain(flags),
+            )
+        }
+    }
+
+    impl Drop for ConfigDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_precedence() {
+        let dir = ConfigDir::new(
+            "precedence",
+            r#"
+            rest_port = 6000
+            trusted_proxies = ["10.0.0.0/8"]
+            api_docs = "admin"
+
+            [rate_limit]
+            per_minute = 100
+            login_per_minute = 20
+
+            [cors]
+            allowed_origins = ["https://finance.example.com"]
+
+            [access_log]
+            sample_every = 10
+
+            [security_headers]
+            content_security_policy = "default-src 'none'; report-uri /csp"
+
+            [quotas]
+            transactions = 5000
+            webhooks = 5
+            "#,
+        );
+
+        // Without a file, or anything else, the defaults are used
+        let config = Config::load(
+            &Args::parse_from(["finance-fusion-server", "-c", "/nowhere"]),
+            env(&[]),
+        )
+        .unwrap();
+        assert_eq!(config.rest_port, 5000);
+        assert_eq!(config.rate_limit.per_minute, 300);
+        assert_eq!(config.api_docs, ApiDocs::Open);
+
+        // The file overrides the defaults, but for the keys it doesn't set
+        let config = Config::load(&dir.args(&[]), env(&[])).unwrap();
+        assert_eq!(config.rest_port, 6000);
+        assert_eq!(config.rate_limit.per_minute, 100);
+        assert_eq!(config.api_docs, ApiDocs::Admin);
+        assert_eq!(config.rate_limit.login_per_minute, 20);
+        assert_eq!(config.rate_limit.user_creation_per_minute, 3);
+        assert_eq!(config.session.ttl_hours, 24);
+        assert_eq!(config.access_log().sample_every, 10);
+        assert_eq!(
+            config.access_log().sampled_routes,
+            AccessLogConfig::default().sampled_routes
+        );
+        assert_eq!(
+            config.trusted_proxies(),
+            TrustedProxies::parse(&["10.0.0.0/8"]).unwrap()
+        );
+        let headers = config.security_headers();
+        assert_eq!(
+            headers.content_security_policy,
+            "default-src 'none'; report-uri /csp"
+        );
+        assert_eq!(
+            headers.swagger_ui_content_security_policy,
+            DEFAULT_SWAGGER_UI_CONTENT_SECURITY_POLICY
+        );
+        let quotas = config.quotas();
+        assert_eq!(quotas.transactions, 5000);
+        assert_eq!(quotas.webhooks, 5);
+        assert_eq!(quotas.attachment_bytes, Quotas::default().attachment_bytes);
+
+        // The environment overrides the file, and the flags override both
+        let vars = env(&[
+            ("FINANCE_FUSION_REST_PORT", "7000"),
+            ("FINANCE_FUSION_RATE_LIMIT_PER_MINUTE", "50"),
+            ("FINANCE_FUSION_SESSION_TTL_HOURS", "2"),
+            ("FINANCE_FUSION_COOKIE_SAME_SITE", "Lax"),
+            ("FINANCE_FUSION_QUOTAS_ENABLED", "false"),
+            (
+                "FINANCE_FUSION_CORS_ALLOWED_ORIGINS",
+                "https://a.example.com, https://b.example.com",
+            ),
+        ]);
+        let config = Config::load(
+            &dir.args(&[
+                "--rate-limit",
+                "25",
+                "--disable-rate-limit",
+                "--trusted-proxies",
+                "fd00::/8,unix",
+                "--api-docs",
+                "disabled",
+            ]),
+            vars,
+        )
+        .unwrap();
+        assert_eq!(config.api_docs, ApiDocs::Disabled);
+        assert_eq!(
+            config.trusted_proxies(),
+            TrustedProxies::parse(&["fd00::/8", "unix"]).unwrap()
+        );
+        assert_eq!(config.rest_port, 7000);
+        assert_eq!(config.rate_limit.per_minute, 25);
+        assert!(!config.rate_limit.enabled);
+        assert_eq!(config.rate_limit.login_per_minute, 20);
+        assert_eq!(config.sessions().ttl, chrono::Duration::hours(2));
+        assert_eq!(config.cookie.same_site, SameSite::Lax);
+        assert!(!config.quotas().enabled);
+        assert_eq!(config.quotas().webhooks, 5);
+        assert_eq!(
+            config.cors().allowed_origins,
+            ["https://a.example.com", "https://b.example.com"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_configuration() {
+        let dir = ConfigDir::new("malformed", "rest_port = \n[rate_limit");
+        let error = Config::load(&dir.args(&[]), env(&[])).unwrap_err();
+        assert!(matches!(error, AppError::InvalidInput(_)));
+        assert!(error.to_string().contains("config.toml"), "{error}");
+
+        // Errors name the offending key
+        let dir = ConfigDir::new(
+            "invalid",
+            "[rate_limit]\nenabled = true\nper_minute = \"many\"",
+        );
+        let error = Config::load(&dir.args(&[]), env(&[])).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid configuration `rate_limit.per_minute`"),
+            "{error}"
+        );
+        let dir = ConfigDir::new("unknown", "[database]\nmax_conections = 5");
+        let error = Config::load(&dir.args(&[]), env(&[])).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid configuration `database.max_conections`"),
+            "{error}"
+        );
+        let dir = ConfigDir::new("zero", "");
+        let error = Config::load(
+            &dir.args(&[]),
+            env(&[("FINANCE_FUSION_SESSION_TTL_HOURS", "0")]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid configuration `session.ttl_hours`: must be at least 1"
+        );
+        let error = Config::load(
+            &dir.args(&[]),
+            env(&[(
+                "FINANCE_FUSION_CORS_ALLOWED_ORIGINS",
+                "https://example.com/app",
+            )]),
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid configuration `cors.allowed_origins`"),
+            "{error}"
+        );
+        let error = Config::load(
+            &dir.args(&[]),
+            env(&[(
+                "FINANCE_FUSION_TRUSTED_PROXIES",
+                "10.0.0.0/8, proxy.internal",
+            )]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid configuration `trusted_proxies`: `proxy.internal` isn't an IP address or a CIDR block, e.g. `10.0.0.0/8`"
+        );
+        let error = Config::load(
+            &dir.args(&[]),
+            env(&[(
+                "FINANCE_FUSION_SECURITY_HEADERS_CONTENT_SECURITY_POLICY",
+                "default-src 'none';\nscript-src *",
+            )]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid configuration `security_headers.content_security_policy`: must be a policy on a single line"
+        );
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let dir = ConfigDir::new(
+            "secrets",
+            "metrics_token = \"scrape-token\"\n[session]\njwt_secret = \"signing-secret\"",
+        );
+
+        let config = Config::load(&dir.args(&[]), env(&[])).unwrap();
+        assert_eq!(
+            config.session.jwt_secret.as_ref().unwrap().expose(),
+            "signing-secret"
+        );
+        let logged = format!("{config:?}");
+        assert!(!logged.contains("signing-secret"), "{logged}");
+        assert!(!logged.contains("scrape-token"), "{logged}");
+        assert!(
+            logged.contains("jwt_secret: Some(\"<redacted>\")"),
+            "{logged}"
+        );
+        assert!(logged.contains("rest_port: 5000"), "{logged}");
+    }
+}
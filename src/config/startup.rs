@@ -0,0 +1,269 @@
+//! The phases the server goes through before it accepts traffic
+//!
+//! The server connects to the database, migrates it if asked to, checks that it matches the
+//! models and builds the application before it binds its listener, so that clients and load
+//! balancers can't reach it until it can answer them.
+
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::api::listener::{BindAddress, RestListener};
+use crate::database::connection::DbPool;
+use crate::errors::AppError;
+
+/// A phase of the startup of the server, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupPhase {
+    /// Connecting to the database and its replica, waiting for them to come up
+    Connect,
+    /// Applying the pending migrations, with `--migrate`
+    Migrate,
+    /// Checking that the database matches the models
+    VerifySchema,
+    /// Building the state shared by the routes, and the routes
+    BuildState,
+    /// Binding the listener, after which requests are accepted
+    Bind,
+}
+
+impl StartupPhase {
+    /// Get the name of the phase, e.g. `verify_schema`
+    pub fn name(self) -> &'static str {
+        match self {
+            StartupPhase::Connect => "connect",
+            StartupPhase::Migrate => "migrate",
+            StartupPhase::VerifySchema => "verify_schema",
+            StartupPhase::BuildState => "build_state",
+            StartupPhase::Bind => "bind",
+        }
+    }
+}
+
+impl fmt::Display for StartupPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An error that stopped the server from starting, with the phase it happened in
+#[derive(thiserror::Error, Debug)]
+#[error("Startup failed in the {phase} phase: {source}")]
+pub struct StartupError {
+    /// The phase that failed
+    pub phase: StartupPhase,
+    /// Why it failed
+    #[source]
+    pub source: AppError,
+}
+
+impl From<StartupError> for AppError {
+    fn from(error: StartupError) -> Self {
+        error.source
+    }
+}
+
+/// The phases of startup completed so far, shared with the readiness endpoint
+#[derive(Debug, Default)]
+pub struct Startup {
+    completed: Mutex<Vec<StartupPhase>>,
+}
+
+impl Startup {
+    /// A startup whose phases were all completed, for servers started without going through them
+    pub fn finished() -> Self {
+        Self {
+            completed: Mutex::new(vec![
+                StartupPhase::Connect,
+                StartupPhase::Migrate,
+                StartupPhase::VerifySchema,
+                StartupPhase::BuildState,
+                StartupPhase::Bind,
+            ]),
+        }
+    }
+
+    /// Get the phases completed so far, in the order they completed
+    pub fn completed(&self) -> Vec<StartupPhase> {
+        self.completed.lock().expect("Poisoned startup").clone()
+    }
+
+    /// Whether the server went through every phase and accepts requests
+    pub fn is_finished(&self) -> bool {
+        self.completed().contains(&StartupPhase::Bind)
+    }
+
+    /// Run a phase of the startup, logging how long it took
+    ///
+    /// # Arguments
+    ///
+    /// * `phase` - The phase
+    /// * `future` - What the phase does
+    ///
+    /// # Returns
+    ///
+    /// The output of the phase, or a `StartupError` naming the phase if it failed
+    pub async fn run<T>(
+        &self,
+        phase: StartupPhase,
+        future: impl Future<Output = Result<T, AppError>>,
+    ) -> Result<T, StartupError> {
+        let started = Instant::now();
+        let result = future.await;
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(output) => {
+                tracing::info!(phase = phase.name(), duration_ms, "Startup phase completed");
+                self.completed.lock().expect("Poisoned startup").push(phase);
+                Ok(output)
+            }
+            Err(source) => {
+                let error = StartupError { phase, source };
+                tracing::error!(phase = phase.name(), duration_ms, "{error}");
+                Err(error)
+            }
+        }
+    }
+}
+
+/// Go through the phases of startup that follow the connection to the database
+///
+/// The listener is only bound once the database is migrated, if asked to, and checked, and the
+/// application is built, so a server that can't answer requests never accepts them.
+///
+/// # Arguments
+///
+/// * `startup` - The phases completed so far, which the phases are added to
+/// * `pool` - The pool of connections to the database, connected in the `connect` phase
+/// * `migrate` - Whether to apply the pending migrations, the `migrate` phase is skipped otherwise
+/// * `build` - Builds the application and what it needs, in the `build_state` phase
+/// * `address` - The address to bind the listener to
+///
+/// # Returns
+///
+/// What `build` built and the bound listener, or the error of the first phase that failed
+pub async fn start<T>(
+    startup: &Startup,
+    pool: &Arc<DbPool>,
+    migrate: bool,
+    build: impl FnOnce() -> Result<T, AppError>,
+    address: &BindAddress,
+) -> Result<(T, RestListener), StartupError> {
+    if migrate {
+        startup.run(StartupPhase::Migrate, pool.migrate()).await?;
+    }
+    // Fail before serving if the database doesn't match the models, rather than at a request
+    startup
+        .run(StartupPhase::VerifySchema, pool.check_schema())
+        .await?;
+    let built = startup
+        .run(StartupPhase::BuildState, async { build() })
+        .await?;
+    let listener = startup
+        .run(StartupPhase::Bind, RestListener::bind(address))
+        .await?;
+    Ok((built, listener))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use diesel::connection::SimpleConnection;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::api::api::{HttpConfig, RestApp, RestOptions};
+    use crate::database::models::sessions::keys::JwtKeys;
+    use crate::jobs::queue::Services;
+    use crate::jobs::webhooks::WebhookConfig;
+    use crate::quotas::Quotas;
+    use crate::routes::vitals::Shutdown;
+    use crate::storage::attachments::AttachmentStore;
+
+    #[tokio::test]
+    async fn test_startup_refuses_to_bind_before_migrations() {
+        let pool = Arc::new(DbPool::new_test_shared());
+        // The connection of the pool only sees an empty schema, as if the database was new
+        pool.get()
+            .unwrap()
+            .batch_execute("CREATE SCHEMA startup_test; SET LOCAL search_path TO startup_test")
+            .unwrap();
+
+        let data_dir =
+            std::env::temp_dir().join(format!("finance-fusion-startup-{}", std::process::id()));
+        let socket = data_dir.join("server.sock");
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let address = BindAddress::Unix(socket.clone());
+        let startup = Arc::new(Startup::default());
+        let build = || {
+            RestApp::build(
+                data_dir.to_str().unwrap(),
+                pool.clone(),
+                RestOptions {
+                    services: Services::new(
+                        WebhookConfig {
+                            allow_insecure: false,
+                        },
+                        Quotas::disabled(),
+                        AttachmentStore::new(&data_dir),
+                    ),
+                    metrics_token: None,
+                    jwt_keys: JwtKeys::from_secret(b"test-secret"),
+                    http: HttpConfig::default(),
+                    tls: None,
+                },
+                Arc::new(Shutdown::default()),
+                startup.clone(),
+            )
+        };
+
+        // Without the migrations, the schema check fails and nothing is listening
+        let Err(error) = start(&startup, &pool, false, build, &address).await else {
+            panic!("The server started without the migrations");
+        };
+        assert_eq!(error.phase, StartupPhase::VerifySchema);
+        assert!(matches!(error.source, AppError::SchemaDrift(_)));
+        assert!(error
+            .to_string()
+            .starts_with("Startup failed in the verify_schema phase: "));
+        assert!(!socket.exists());
+        assert!(startup.completed().is_empty());
+
+        // Once migrated, the server starts and reports the phases it went through
+        let (app, listener) = start(&startup, &pool, true, build, &address).await.unwrap();
+        assert!(socket.exists());
+        assert_eq!(
+            startup.completed(),
+            vec![
+                StartupPhase::Migrate,
+                StartupPhase::VerifySchema,
+                StartupPhase::BuildState,
+                StartupPhase::Bind,
+            ]
+        );
+        let response = app
+            .router()
+            .oneshot(Request::get("/readyz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let readiness: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(readiness["status"], "ready");
+        assert_eq!(readiness["startup"][3], "bind");
+
+        drop(listener);
+        std::fs::remove_dir_all(&data_dir).ok();
+    }
+}
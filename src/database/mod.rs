@@ -1,3 +1,4 @@
 pub mod connection;
 pub mod models;
 mod schema;
+pub mod schema_check;
@@ -0,0 +1,713 @@
+use bigdecimal::BigDecimal;
+use chrono::Months;
+use chrono::NaiveDate;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    dsl::sum,
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::{Date, Integer, Numeric, Text},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    schema::{accounts, transactions},
+};
+use crate::errors::AppError;
+
+/// Whether the balance of an account is owned or owed
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountKind {
+    /// Chequing, savings and other accounts holding money of the user
+    #[default]
+    Asset,
+    /// Credit cards and other accounts whose balance is owed, negative when money is owed
+    Credit,
+}
+
+impl AccountKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AccountKind::Asset => "asset",
+            AccountKind::Credit => "credit",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for AccountKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for AccountKind {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "asset" => Ok(AccountKind::Asset),
+            "credit" => Ok(AccountKind::Credit),
+            other => Err(format!("Unknown account kind \"{other}\"").into()),
+        }
+    }
+}
+
+/// Account struct
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = accounts)]
+pub struct Account {
+    /// Account ID
+    id: i32,
+    /// Account name
+    name: String,
+    /// Balance of the account before any of its transactions
+    #[schema(value_type = String)]
+    opening_balance: BigDecimal,
+    /// ISO 4217 currency code of the account
+    currency: String,
+    /// The type of savings account, if any
+    savings_type: Option<String>,
+    /// The timestamp when the account was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+    /// ID of the user that owns the account
+    user_id: i32,
+    /// Whether the balance of the account is owned or owed
+    kind: AccountKind,
+    /// The timestamp when the account was archived, if it is closed
+    #[serde(default, with = "crate::utils::serialization::option")]
+    #[schema(value_type = Option<String>)]
+    archived_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = accounts)]
+struct NewAccount<'a> {
+    user_id: i32,
+    name: &'a str,
+    opening_balance: &'a BigDecimal,
+    currency: &'a str,
+    kind: AccountKind,
+}
+
+/// The granularity of a balance history series
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    /// One point per day with transactions
+    #[default]
+    Day,
+    /// One point per calendar month with transactions
+    Month,
+}
+
+impl Granularity {
+    /// The `date_trunc` field name for the granularity
+    fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Month => "month",
+        }
+    }
+
+    /// Get the last day of the period starting on a day
+    pub fn last_day(&self, period: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => period,
+            Granularity::Month => period
+                .checked_add_months(Months::new(1))
+                .and_then(|next| next.pred_opt())
+                .unwrap_or(NaiveDate::MAX),
+        }
+    }
+}
+
+/// The balance of an account at the end of a period
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, QueryableByName, ToSchema)]
+pub struct BalancePoint {
+    /// First day of the period
+    #[diesel(sql_type = Date)]
+    #[schema(value_type = String)]
+    pub period: NaiveDate,
+    /// Balance at the end of the period
+    #[diesel(sql_type = Numeric)]
+    #[schema(value_type = String)]
+    pub balance: BigDecimal,
+}
+
+/// The balance of one of the accounts of a user at the end of a period
+#[derive(Debug, Clone, PartialEq, QueryableByName)]
+pub struct AccountBalancePoint {
+    /// Account ID
+    #[diesel(sql_type = Integer)]
+    pub account_id: i32,
+    /// First day of the period
+    #[diesel(sql_type = Date)]
+    pub period: NaiveDate,
+    /// Balance at the end of the period
+    #[diesel(sql_type = Numeric)]
+    pub balance: BigDecimal,
+}
+
+impl Account {
+    /// Create a new account for a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `name` - Name of the account
+    /// * `opening_balance` - Balance of the account before any transactions
+    /// * `currency` - ISO 4217 currency code of the account
+    /// * `kind` - Whether the balance of the account is owned or owed
+    ///
+    /// # Returns
+    ///
+    /// The newly created account
+    pub fn new(
+        conn: &mut DbConn,
+        user_id: i32,
+        name: &str,
+        opening_balance: &BigDecimal,
+        currency: &str,
+        kind: AccountKind,
+    ) -> Result<Self, AppError> {
+        let new_account = NewAccount {
+            user_id,
+            name,
+            opening_balance,
+            currency,
+            kind,
+        };
+
+        diesel::insert_into(accounts::table)
+            .values(&new_account)
+            .get_result::<Account>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed creating account \"{name}\" for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get an account by ID, scoped to the user that owns it
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Account ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The account, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        accounts::table
+            .filter(accounts::id.eq(id))
+            .filter(accounts::user_id.eq(user_id))
+            .first::<Account>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting account {id} for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get all accounts of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `include_archived` - Whether to include archived accounts
+    ///
+    /// # Returns
+    ///
+    /// A vector of accounts owned by the user
+    pub fn get_all(
+        conn: &mut DbConn,
+        user_id: i32,
+        include_archived: bool,
+    ) -> Result<Vec<Self>, AppError> {
+        let mut query = accounts::table
+            .filter(accounts::user_id.eq(user_id))
+            .order(accounts::id)
+            .into_boxed();
+        if !include_archived {
+            query = query.filter(accounts::archived_at.is_null());
+        }
+        query.load::<Account>(conn).map_err(|e| {
+            tracing::error!("Failed getting accounts for user {user_id} ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Count the accounts of a user, archived ones included, against their quota
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    pub fn count_of_user(conn: &mut DbConn, user_id: i32) -> Result<u64, AppError> {
+        accounts::table
+            .filter(accounts::user_id.eq(user_id))
+            .count()
+            .get_result::<i64>(conn)
+            .map(|count| count as u64)
+            .map_err(|e| {
+                tracing::error!("Failed counting accounts of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Delete all accounts of a user, with everything recorded on them
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The number of accounts deleted
+    pub fn delete_all_of_user(conn: &mut DbConn, user_id: i32) -> Result<u64, AppError> {
+        diesel::delete(accounts::table.filter(accounts::user_id.eq(user_id)))
+            .execute(conn)
+            .map(|rows| rows as u64)
+            .map_err(|e| {
+                tracing::error!("Failed deleting accounts of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Archive or unarchive the account
+    ///
+    /// Archiving an account that is already archived keeps the time it was archived at.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `archived` - Whether the account is archived
+    ///
+    /// # Returns
+    ///
+    /// The updated account
+    pub fn set_archived(&self, conn: &mut DbConn, archived: bool) -> Result<Self, AppError> {
+        if archived == self.archived_at.is_some() {
+            return Ok(self.clone());
+        }
+
+        let query = diesel::update(accounts::table.filter(accounts::id.eq(self.id)));
+        let result = if archived {
+            query
+                .set(accounts::archived_at.eq(diesel::dsl::now.nullable()))
+                .get_result::<Account>(conn)
+        } else {
+            query
+                .set(accounts::archived_at.eq(None::<chrono::NaiveDateTime>))
+                .get_result::<Account>(conn)
+        };
+        result.map_err(|e| {
+            tracing::error!("Failed archiving account {} ({e})", self.id);
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Check that transactions can be created on the account
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the account is open, otherwise `AppError::Conflict`
+    pub fn ensure_open(&self) -> Result<(), AppError> {
+        match self.archived_at {
+            Some(_) => Err(AppError::Conflict(format!(
+                "Account \"{}\" is archived, unarchive it to add transactions",
+                self.name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Compute the current balance of the account
+    ///
+    /// The sum of the account's transactions is computed by the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// The opening balance plus the sum of all transactions
+    pub fn balance(&self, conn: &mut DbConn) -> Result<BigDecimal, AppError> {
+        let total = transactions::table
+            .filter(transactions::account_id.eq(self.id))
+            .select(sum(transactions::amount))
+            .first::<Option<BigDecimal>>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed computing balance of account {} ({e})", self.id);
+                AppError::Diesel(e)
+            })?;
+
+        Ok(&self.opening_balance + total.unwrap_or_default())
+    }
+
+    /// Compute the balance of the account at the start of a day
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `day` - The day, whose own transactions are left out
+    ///
+    /// # Returns
+    ///
+    /// The opening balance plus the sum of the transactions before the day
+    pub fn balance_before(
+        &self,
+        conn: &mut DbConn,
+        day: NaiveDate,
+    ) -> Result<BigDecimal, AppError> {
+        let total = transactions::table
+            .filter(transactions::account_id.eq(self.id))
+            .filter(transactions::occurred_at.lt(day))
+            .select(sum(transactions::amount))
+            .first::<Option<BigDecimal>>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed computing balance of account {} before {day} ({e})",
+                    self.id
+                );
+                AppError::Diesel(e)
+            })?;
+
+        Ok(&self.opening_balance + total.unwrap_or_default())
+    }
+
+    /// Compute the end-of-period balances of the account
+    ///
+    /// Transactions are grouped per period and accumulated with a window function, so only one
+    /// row per period is returned by the database. Periods without transactions are omitted.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `granularity` - The length of each period
+    ///
+    /// # Returns
+    ///
+    /// A vector of balances ordered by period
+    pub fn balance_history(
+        &self,
+        conn: &mut DbConn,
+        granularity: Granularity,
+    ) -> Result<Vec<BalancePoint>, AppError> {
+        diesel::sql_query(
+            "SELECT p.period, a.opening_balance + SUM(p.net) OVER (ORDER BY p.period) AS balance \
+             FROM ( \
+                 SELECT date_trunc($1, occurred_at)::date AS period, SUM(amount) AS net \
+                 FROM transactions WHERE account_id = $2 GROUP BY 1 \
+             ) p \
+             JOIN accounts a ON a.id = $2 \
+             ORDER BY p.period",
+        )
+        .bind::<Text, _>(granularity.as_str())
+        .bind::<Integer, _>(self.id)
+        .load::<BalancePoint>(conn)
+        .map_err(|e| {
+            tracing::error!(
+                "Failed computing balance history of account {} ({e})",
+                self.id
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Compute the end-of-period balances of all accounts of a user
+    ///
+    /// Like `Account::balance_history`, with the window partitioned by account. Periods without
+    /// transactions in an account are omitted for that account.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `granularity` - The length of each period
+    ///
+    /// # Returns
+    ///
+    /// A vector of balances ordered by period, then account
+    pub fn balance_histories(
+        conn: &mut DbConn,
+        user_id: i32,
+        granularity: Granularity,
+    ) -> Result<Vec<AccountBalancePoint>, AppError> {
+        diesel::sql_query(
+            "SELECT p.account_id, p.period, \
+                 a.opening_balance + SUM(p.net) OVER (PARTITION BY p.account_id ORDER BY p.period) \
+                 AS balance \
+             FROM ( \
+                 SELECT t.account_id, date_trunc($1, t.occurred_at)::date AS period, \
+                     SUM(t.amount) AS net \
+                 FROM transactions t JOIN accounts a ON a.id = t.account_id \
+                 WHERE a.user_id = $2 GROUP BY 1, 2 \
+             ) p \
+             JOIN accounts a ON a.id = p.account_id \
+             ORDER BY p.period, p.account_id",
+        )
+        .bind::<Text, _>(granularity.as_str())
+        .bind::<Integer, _>(user_id)
+        .load::<AccountBalancePoint>(conn)
+        .map_err(|e| {
+            tracing::error!("Failed computing balance histories of user {user_id} ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the ID of the account
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the ID of the user that owns the account
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// Get the name of the account
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the currency of the account
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// Get whether the balance of the account is owned or owed
+    pub fn kind(&self) -> AccountKind {
+        self.kind
+    }
+
+    /// Get the balance of the account before any of its transactions
+    pub fn opening_balance(&self) -> &BigDecimal {
+        &self.opening_balance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            transactions::{Transaction, TransactionInput},
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn dec(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_balance() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &dec("100.00"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        assert_eq!(account.balance(conn).unwrap(), dec("100.00"));
+
+        Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(dec("-20.10"), "Groceries", date(2024, 1, 3)),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(dec("0.20"), "Interest", date(2024, 1, 31)),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        assert_eq!(account.balance(conn).unwrap(), dec("80.10"));
+
+        // Another account's transactions don't count
+        let other = Account::new(
+            conn,
+            user.id(),
+            "Savings",
+            &dec("0"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        Transaction::new(
+            conn,
+            &other,
+            &TransactionInput::new(dec("500"), "Deposit", date(2024, 1, 3)),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        assert_eq!(account.balance(conn).unwrap(), dec("80.10"));
+    }
+
+    #[test]
+    fn test_balance_history() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &dec("1000.00"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+
+        Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(dec("2500.00"), "Salary", date(2024, 1, 1)),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(dec("-1200.00"), "Rent", date(2024, 1, 1)),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(dec("-45.67"), "Groceries", date(2024, 1, 15)),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(dec("-0.01"), "Fee", date(2024, 2, 29)),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(dec("2500.00"), "Salary", date(2024, 3, 1)),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+
+        let daily = account.balance_history(conn, Granularity::Day).unwrap();
+        assert_eq!(
+            daily,
+            vec![
+                BalancePoint {
+                    period: date(2024, 1, 1),
+                    balance: dec("2300.00")
+                },
+                BalancePoint {
+                    period: date(2024, 1, 15),
+                    balance: dec("2254.33")
+                },
+                BalancePoint {
+                    period: date(2024, 2, 29),
+                    balance: dec("2254.32")
+                },
+                BalancePoint {
+                    period: date(2024, 3, 1),
+                    balance: dec("4754.32")
+                },
+            ]
+        );
+
+        let monthly = account.balance_history(conn, Granularity::Month).unwrap();
+        assert_eq!(
+            monthly,
+            vec![
+                BalancePoint {
+                    period: date(2024, 1, 1),
+                    balance: dec("2254.33")
+                },
+                BalancePoint {
+                    period: date(2024, 2, 1),
+                    balance: dec("2254.32")
+                },
+                BalancePoint {
+                    period: date(2024, 3, 1),
+                    balance: dec("4754.32")
+                },
+            ]
+        );
+
+        // The last point of the history is the current balance
+        assert_eq!(account.balance(conn).unwrap(), dec("4754.32"));
+    }
+
+    #[test]
+    fn test_from_id_is_scoped_to_user() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let other = User::new(conn, "other_user", "other_password").unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &dec("0"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+
+        assert!(Account::from_id(conn, account.id, user.id()).is_ok());
+        assert!(matches!(
+            Account::from_id(conn, account.id, other.id()),
+            Err(AppError::NotFound(_))
+        ));
+    }
+}
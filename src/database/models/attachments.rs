@@ -0,0 +1,463 @@
+use bigdecimal::{BigDecimal, ToPrimitive};
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    dsl::sum,
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::{BigInt, Text},
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::transactions::Transaction,
+    schema::{accounts, attachments, transactions, user_storage},
+};
+use crate::errors::AppError;
+use crate::quotas::{Quotas, Resource};
+
+diesel::define_sql_function! {
+    /// The larger of two numbers
+    fn greatest(x: BigInt, y: BigInt) -> BigInt;
+}
+
+/// Content types that can be attached to transactions
+pub const ALLOWED_CONTENT_TYPES: [&str; 6] = [
+    "application/pdf",
+    "image/gif",
+    "image/heic",
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+];
+
+/// Whether the file of an attachment is where it was stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, AsExpression, FromSqlRow, ToSchema)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentStatus {
+    /// The file can be downloaded
+    Stored,
+    /// The cleanup of attachments found no file at the storage path
+    Missing,
+}
+
+impl AttachmentStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AttachmentStatus::Stored => "stored",
+            AttachmentStatus::Missing => "missing",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for AttachmentStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for AttachmentStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "stored" => Ok(AttachmentStatus::Stored),
+            "missing" => Ok(AttachmentStatus::Missing),
+            other => Err(format!("Unknown attachment status \"{other}\"").into()),
+        }
+    }
+}
+
+/// A file attached to a transaction, such as a receipt
+#[derive(Debug, Serialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = attachments)]
+pub struct Attachment {
+    /// Attachment ID
+    id: i32,
+    /// ID of the transaction the file is attached to
+    transaction_id: i32,
+    /// Name of the file when it was uploaded
+    filename: String,
+    /// MIME type of the file
+    content_type: String,
+    /// Size of the file in bytes
+    size_bytes: i64,
+    /// Path of the file relative to the attachments directory
+    #[serde(skip)]
+    storage_path: String,
+    /// The timestamp when the file was uploaded
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    uploaded_at: chrono::NaiveDateTime,
+    /// Whether the file is where it was stored, `missing` if the cleanup of attachments found it
+    /// gone
+    status: AttachmentStatus,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = attachments)]
+struct NewAttachment<'a> {
+    transaction_id: i32,
+    filename: &'a str,
+    content_type: &'a str,
+    size_bytes: i64,
+    storage_path: &'a str,
+}
+
+/// Check that a content type can be attached to transactions
+///
+/// # Arguments
+///
+/// * `content_type` - The MIME type of the file
+///
+/// # Returns
+///
+/// An empty result if the type is allowed, otherwise `AppError::InvalidInput`
+pub fn validate_content_type(content_type: &str) -> Result<(), AppError> {
+    if ALLOWED_CONTENT_TYPES.contains(&content_type) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidInput(format!(
+            "Attachments must be one of {}, got \"{content_type}\"",
+            ALLOWED_CONTENT_TYPES.join(", ")
+        )))
+    }
+}
+
+impl Attachment {
+    /// Record a file attached to a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction` - The transaction the file is attached to
+    /// * `filename` - Name of the file when it was uploaded
+    /// * `content_type` - MIME type of the file
+    /// * `size_bytes` - Size of the file in bytes
+    /// * `storage_path` - Path of the stored file relative to the attachments directory
+    /// * `quotas` - The quotas of the server
+    ///
+    /// # Returns
+    ///
+    /// The newly created attachment, counted in the storage of the user, `AppError::InvalidInput`
+    /// if the content type isn't allowed, or `AppError::QuotaExceeded` if the file doesn't fit
+    /// the quota of the user
+    pub fn new(
+        conn: &mut DbConn,
+        transaction: &Transaction,
+        filename: &str,
+        content_type: &str,
+        size_bytes: i64,
+        storage_path: &str,
+        quotas: &Quotas,
+    ) -> Result<Self, AppError> {
+        validate_content_type(content_type)?;
+        let user_id = accounts::table
+            .find(transaction.account_id())
+            .select(accounts::user_id)
+            .first::<i32>(conn)?;
+        quotas.ensure(
+            conn,
+            user_id,
+            Resource::AttachmentBytes,
+            size_bytes.max(0) as u64,
+        )?;
+
+        conn.transaction(|conn| {
+            let attachment = diesel::insert_into(attachments::table)
+                .values(&NewAttachment {
+                    transaction_id: transaction.id(),
+                    filename,
+                    content_type,
+                    size_bytes,
+                    storage_path,
+                })
+                .get_result::<Attachment>(conn)?;
+            count_bytes(conn, user_id, size_bytes)?;
+            Ok(attachment)
+        })
+        .map_err(|e| {
+            tracing::error!(
+                "Failed attaching file to transaction {} ({e})",
+                transaction.id()
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get all files attached to a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction` - The transaction the files are attached to
+    ///
+    /// # Returns
+    ///
+    /// A vector of attachments, oldest first
+    pub fn get_all(conn: &mut DbConn, transaction: &Transaction) -> Result<Vec<Self>, AppError> {
+        attachments::table
+            .filter(attachments::transaction_id.eq(transaction.id()))
+            .order(attachments::id)
+            .load::<Attachment>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting attachments of transaction {} ({e})",
+                    transaction.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get all files attached to some transactions
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction_ids` - IDs of the transactions
+    ///
+    /// # Returns
+    ///
+    /// A vector of attachments, oldest first
+    pub fn of_transactions(
+        conn: &mut DbConn,
+        transaction_ids: &[i32],
+    ) -> Result<Vec<Self>, AppError> {
+        attachments::table
+            .filter(attachments::transaction_id.eq_any(transaction_ids))
+            .order(attachments::id)
+            .load::<Attachment>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting attachments of {} transactions ({e})",
+                    transaction_ids.len()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get every attachment, for the cleanup of the attachments directory
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// A vector of attachments, oldest first
+    pub fn all(conn: &mut DbConn) -> Result<Vec<Self>, AppError> {
+        attachments::table
+            .order(attachments::id)
+            .load::<Attachment>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting all attachments ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Set whether the files of some attachments are where they were stored
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `ids` - IDs of the attachments
+    /// * `status` - The new status of the attachments
+    ///
+    /// # Returns
+    ///
+    /// The number of attachments updated
+    pub fn set_status(
+        conn: &mut DbConn,
+        ids: &[i32],
+        status: AttachmentStatus,
+    ) -> Result<usize, AppError> {
+        diesel::update(attachments::table.filter(attachments::id.eq_any(ids)))
+            .set(attachments::status.eq(status))
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed setting {} attachments {} ({e})",
+                    ids.len(),
+                    status.as_str()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the total size of the files attached to the transactions of a user
+    ///
+    /// The size is counted as attachments are added and deleted rather than summed.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes
+    pub fn total_bytes_of_user(conn: &mut DbConn, user_id: i32) -> Result<u64, AppError> {
+        user_storage::table
+            .find(user_id)
+            .select(user_storage::attachment_bytes_used)
+            .first::<i64>(conn)
+            .optional()
+            .map(|bytes| bytes.unwrap_or(0).max(0) as u64)
+            .map_err(|e| {
+                tracing::error!("Failed getting the attachment bytes of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Take the files attached to some transactions off the storage of their users, in the
+    /// transaction deleting them
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction_ids` - IDs of the transactions being deleted
+    pub fn release(conn: &mut DbConn, transaction_ids: &[i32]) -> QueryResult<()> {
+        let released = attachments::table
+            .inner_join(transactions::table.inner_join(accounts::table))
+            .filter(attachments::transaction_id.eq_any(transaction_ids))
+            .group_by(accounts::user_id)
+            .select((accounts::user_id, sum(attachments::size_bytes)))
+            .load::<(i32, Option<BigDecimal>)>(conn)?;
+        for (user_id, bytes) in released {
+            let bytes = bytes.and_then(|bytes| bytes.to_i64()).unwrap_or(0);
+            count_bytes(conn, user_id, -bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Count the storage of every user again from their attachments, correcting the counts that
+    /// drifted
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// The number of users whose count was corrected
+    pub fn recount_bytes(conn: &mut DbConn) -> Result<usize, AppError> {
+        diesel::sql_query(
+            "INSERT INTO user_storage (user_id, attachment_bytes_used)
+            SELECT counted.user_id, counted.bytes
+            FROM (
+                SELECT users.id AS user_id, COALESCE(SUM(attachments.size_bytes), 0) AS bytes
+                FROM users
+                LEFT JOIN accounts ON accounts.user_id = users.id
+                LEFT JOIN transactions ON transactions.account_id = accounts.id
+                LEFT JOIN attachments ON attachments.transaction_id = transactions.id
+                GROUP BY users.id
+            ) AS counted
+            LEFT JOIN user_storage ON user_storage.user_id = counted.user_id
+            WHERE COALESCE(user_storage.attachment_bytes_used, 0) <> counted.bytes
+            ON CONFLICT (user_id) DO UPDATE
+            SET attachment_bytes_used = excluded.attachment_bytes_used",
+        )
+        .execute(conn)
+        .map_err(|e| {
+            tracing::error!("Failed counting the attachment bytes of users ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get an attachment by ID, scoped to the user owning the account of its transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Attachment ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The attachment, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        attachments::table
+            .inner_join(transactions::table.inner_join(accounts::table))
+            .filter(attachments::id.eq(id))
+            .filter(accounts::user_id.eq(user_id))
+            .select(attachments::all_columns)
+            .first::<Attachment>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting attachment {id} for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Delete the attachment, the stored file is left for the caller to remove
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        conn.transaction(|conn| {
+            let user_id = transactions::table
+                .inner_join(accounts::table)
+                .filter(transactions::id.eq(self.transaction_id))
+                .select(accounts::user_id)
+                .first::<i32>(conn)?;
+            diesel::delete(attachments::table.filter(attachments::id.eq(self.id))).execute(conn)?;
+            count_bytes(conn, user_id, -self.size_bytes)
+        })
+        .map_err(|e| {
+            tracing::error!("Failed deleting attachment {} ({e})", self.id);
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the attachment ID
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the name of the file when it was uploaded
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Get the MIME type of the file
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// Get the path of the file relative to the attachments directory
+    pub fn storage_path(&self) -> &str {
+        &self.storage_path
+    }
+
+    /// Get whether the file is where it was stored
+    pub fn status(&self) -> AttachmentStatus {
+        self.status
+    }
+}
+
+/// Add to the size of the files a user stores, or take off it when `bytes` is negative
+///
+/// The count never goes below zero, so deleting attachments stored before it was kept can't fail.
+fn count_bytes(conn: &mut DbConn, user_id: i32, bytes: i64) -> QueryResult<()> {
+    diesel::insert_into(user_storage::table)
+        .values((
+            user_storage::user_id.eq(user_id),
+            user_storage::attachment_bytes_used.eq(bytes.max(0)),
+        ))
+        .on_conflict(user_storage::user_id)
+        .do_update()
+        .set(
+            user_storage::attachment_bytes_used
+                .eq(greatest(user_storage::attachment_bytes_used + bytes, 0)),
+        )
+        .execute(conn)
+        .map(|_| ())
+}
@@ -0,0 +1,240 @@
+use chrono::NaiveDate;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{connection::DbConn, schema::audit_events};
+use crate::errors::AppError;
+
+/// Kind of a security-relevant event
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    /// A user logged in
+    LoginSuccess,
+    /// A login was refused
+    LoginFailure,
+    /// A user was locked out after too many failed logins
+    Lockout,
+    /// The password of a user was changed
+    PasswordChange,
+    /// A session was ended before it expired
+    SessionRevoked,
+    /// A user was deleted
+    UserDeleted,
+    /// A plan was deleted
+    PlanDeleted,
+    /// The transactions of an account before a day were deleted
+    TransactionsWiped,
+    /// The accounts, transactions and plans of a user were deleted
+    DataWiped,
+    /// An administrator started a session as a user
+    ImpersonationStarted,
+    /// A request was made by an administrator acting as a user
+    ImpersonatedRequest,
+}
+
+impl AuditEventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventKind::LoginSuccess => "login_success",
+            AuditEventKind::LoginFailure => "login_failure",
+            AuditEventKind::Lockout => "lockout",
+            AuditEventKind::PasswordChange => "password_change",
+            AuditEventKind::SessionRevoked => "session_revoked",
+            AuditEventKind::UserDeleted => "user_deleted",
+            AuditEventKind::PlanDeleted => "plan_deleted",
+            AuditEventKind::TransactionsWiped => "transactions_wiped",
+            AuditEventKind::DataWiped => "data_wiped",
+            AuditEventKind::ImpersonationStarted => "impersonation_started",
+            AuditEventKind::ImpersonatedRequest => "impersonated_request",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for AuditEventKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for AuditEventKind {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "login_success" => Ok(AuditEventKind::LoginSuccess),
+            "login_failure" => Ok(AuditEventKind::LoginFailure),
+            "lockout" => Ok(AuditEventKind::Lockout),
+            "password_change" => Ok(AuditEventKind::PasswordChange),
+            "session_revoked" => Ok(AuditEventKind::SessionRevoked),
+            "user_deleted" => Ok(AuditEventKind::UserDeleted),
+            "plan_deleted" => Ok(AuditEventKind::PlanDeleted),
+            "transactions_wiped" => Ok(AuditEventKind::TransactionsWiped),
+            "data_wiped" => Ok(AuditEventKind::DataWiped),
+            "impersonation_started" => Ok(AuditEventKind::ImpersonationStarted),
+            "impersonated_request" => Ok(AuditEventKind::ImpersonatedRequest),
+            other => Err(format!("Unknown audit event \"{other}\"").into()),
+        }
+    }
+}
+
+/// A security-relevant event, such as a login or a deleted user
+#[derive(Debug, Serialize, Queryable, ToSchema)]
+#[diesel(table_name = audit_events)]
+pub struct AuditEvent {
+    /// Event ID
+    id: i32,
+    /// ID of the user the event is about, if known
+    user_id: Option<i32>,
+    /// What happened
+    event: AuditEventKind,
+    /// Details of the event, which depend on its kind
+    #[schema(value_type = Object)]
+    metadata: serde_json::Value,
+    /// IP address of the client that caused the event, if known
+    ip: Option<String>,
+    /// The timestamp when the event happened
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+}
+
+/// An event to record
+#[derive(Debug, Insertable)]
+#[diesel(table_name = audit_events)]
+pub struct NewAuditEvent {
+    user_id: Option<i32>,
+    event: AuditEventKind,
+    metadata: serde_json::Value,
+    ip: Option<String>,
+}
+
+impl NewAuditEvent {
+    /// Describe an event
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - What happened
+    /// * `user_id` - ID of the user the event is about, if known
+    /// * `metadata` - Details of the event
+    ///
+    /// # Returns
+    ///
+    /// The event, without the IP address of the client
+    pub fn new(event: AuditEventKind, user_id: Option<i32>, metadata: serde_json::Value) -> Self {
+        Self {
+            user_id,
+            event,
+            metadata,
+            ip: None,
+        }
+    }
+
+    /// Set the IP address of the client that caused the event
+    pub fn with_ip(mut self, ip: Option<String>) -> Self {
+        self.ip = ip;
+        self
+    }
+
+    /// Get what happened
+    pub fn event(&self) -> AuditEventKind {
+        self.event
+    }
+}
+
+/// Which events to list, newest first
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    /// Only events about this user
+    pub user_id: Option<i32>,
+    /// Only events of this kind
+    pub event: Option<AuditEventKind>,
+    /// Only events on or after this day
+    pub from: Option<NaiveDate>,
+    /// Only events on or before this day
+    pub to: Option<NaiveDate>,
+    /// Only events older than the event with this ID, to resume after the previous page
+    pub before: Option<i32>,
+}
+
+impl AuditEvent {
+    /// Record an event
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `event` - The event to record
+    ///
+    /// # Returns
+    ///
+    /// The recorded event
+    pub fn new(conn: &mut DbConn, event: &NewAuditEvent) -> Result<Self, AppError> {
+        diesel::insert_into(audit_events::table)
+            .values(event)
+            .get_result::<AuditEvent>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed recording audit event {:?} ({e})", event.event);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a page of events, newest first
+    ///
+    /// Pages are keyed on the event ID rather than an offset, so events recorded while paging
+    /// don't shift the following pages.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `filter` - Which events to list
+    /// * `limit` - Maximum number of events in the page
+    ///
+    /// # Returns
+    ///
+    /// A vector of events
+    pub fn get_page(
+        conn: &mut DbConn,
+        filter: &AuditFilter,
+        limit: i64,
+    ) -> Result<Vec<Self>, AppError> {
+        let mut query = audit_events::table.into_boxed();
+        if let Some(user_id) = filter.user_id {
+            query = query.filter(audit_events::user_id.eq(user_id));
+        }
+        if let Some(event) = filter.event {
+            query = query.filter(audit_events::event.eq(event.as_str()));
+        }
+        if let Some(from) = filter.from {
+            query = query.filter(audit_events::created_at.ge(from.and_time(Default::default())));
+        }
+        if let Some(to) = filter.to.and_then(|to| to.succ_opt()) {
+            query = query.filter(audit_events::created_at.lt(to.and_time(Default::default())));
+        }
+        if let Some(before) = filter.before {
+            query = query.filter(audit_events::id.lt(before));
+        }
+
+        query
+            .order(audit_events::id.desc())
+            .limit(limit)
+            .load::<AuditEvent>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting audit events ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the event ID
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+}
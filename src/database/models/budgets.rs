@@ -0,0 +1,742 @@
+use std::collections::HashMap;
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::{Array, Date, Integer, Numeric, Text},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{categories::Category, plans::Plan},
+    schema::{budget_amounts, budgets},
+};
+use crate::errors::AppError;
+
+/// How often the amount of a budget is available to spend
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetInterval {
+    /// The amount is available every month
+    Monthly,
+    /// The amount is available every year
+    Yearly,
+}
+
+impl BudgetInterval {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BudgetInterval::Monthly => "monthly",
+            BudgetInterval::Yearly => "yearly",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for BudgetInterval {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for BudgetInterval {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "monthly" => Ok(BudgetInterval::Monthly),
+            "yearly" => Ok(BudgetInterval::Yearly),
+            other => Err(format!("Unknown budget interval \"{other}\"").into()),
+        }
+    }
+}
+
+/// An amount of a plan to spend on a category
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = budgets)]
+pub struct Budget {
+    /// Budget ID
+    id: i32,
+    /// Name of the plan the budget belongs to
+    plan_name: String,
+    /// Name of the budget
+    name: String,
+    /// The amount available to spend each interval, the one in effect last
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// How often the amount is available
+    interval: BudgetInterval,
+    /// ISO 4217 currency code of the amount
+    currency: String,
+    /// The first day the budget applies to
+    #[schema(value_type = String)]
+    start_date: NaiveDate,
+    /// The last day the budget applies to, if it ends
+    #[schema(value_type = Option<String>)]
+    end_date: Option<NaiveDate>,
+    /// The timestamp when the budget was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+    /// ID of the category the budget is for
+    category_id: i32,
+    /// Version of the budget, incremented by every update and sent as its ETag
+    version: i32,
+    /// Whether what's left of the amount of a month carries into the next months, and what's
+    /// overspent comes out of them
+    rollover: bool,
+}
+
+/// An amount of a budget, in effect from a day until the day of the next one
+#[derive(Debug, Serialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = budget_amounts)]
+pub struct EffectiveAmount {
+    /// Amount ID
+    id: i32,
+    /// ID of the budget
+    budget_id: i32,
+    /// The amount available to spend each interval
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// The first day the amount is in effect
+    #[schema(value_type = String)]
+    effective_from: NaiveDate,
+    /// The timestamp when the amount was set
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+}
+
+/// The amounts of a budget over time, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct AmountHistory(Vec<EffectiveAmount>);
+
+impl AmountHistory {
+    /// Get the amount in effect on a day
+    ///
+    /// Days before the first amount, such as those of a budget whose start was moved earlier, get
+    /// the first amount.
+    pub fn on(&self, day: NaiveDate) -> Option<&BigDecimal> {
+        self.0
+            .iter()
+            .rev()
+            .find(|amount| amount.effective_from <= day)
+            .or(self.0.first())
+            .map(|amount| &amount.amount)
+    }
+
+    /// Get the amounts, oldest first
+    pub fn into_amounts(self) -> Vec<EffectiveAmount> {
+        self.0
+    }
+}
+
+/// Fields of a budget to be created or updated
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = budgets, treat_none_as_null = true)]
+pub struct BudgetInput {
+    /// ID of the category the budget is for, must belong to the owner of the plan
+    pub category_id: i32,
+    /// Name of the budget
+    pub name: String,
+    /// The amount available to spend each interval, must be positive
+    pub amount: BigDecimal,
+    /// How often the amount is available
+    pub interval: BudgetInterval,
+    /// ISO 4217 currency code of the amount
+    pub currency: String,
+    /// The first day the budget applies to
+    pub start_date: NaiveDate,
+    /// The last day the budget applies to, must not be before the first day
+    pub end_date: Option<NaiveDate>,
+    /// Whether what's left of the amount of a month carries into the next months
+    pub rollover: bool,
+}
+
+impl BudgetInput {
+    /// Validate the input
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the input is valid, otherwise `AppError::InvalidInput`
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.amount <= BigDecimal::zero() {
+            return Err(AppError::InvalidInput(
+                "The amount of a budget must be positive".to_string(),
+            ));
+        }
+        if self
+            .end_date
+            .is_some_and(|end_date| end_date < self.start_date)
+        {
+            return Err(AppError::InvalidInput(
+                "The end date of a budget must not be before its start date".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The net amount of the transactions counted against a budget over some months, in a currency
+#[derive(Debug, QueryableByName)]
+pub struct BudgetAmount {
+    /// ID of the budget
+    #[diesel(sql_type = Integer)]
+    pub budget_id: i32,
+    /// ISO 4217 code of the currency of the transactions
+    #[diesel(sql_type = Text)]
+    pub currency: String,
+    /// Sum of the amounts, negative when more was spent than refunded
+    #[diesel(sql_type = Numeric)]
+    pub amount: BigDecimal,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = budgets)]
+struct NewBudget<'a> {
+    plan_name: &'a str,
+    category_id: i32,
+    name: &'a str,
+    amount: &'a BigDecimal,
+    interval: BudgetInterval,
+    currency: &'a str,
+    start_date: NaiveDate,
+    end_date: Option<NaiveDate>,
+    rollover: bool,
+}
+
+impl Budget {
+    /// Create a new budget in a plan, marking the plan as modified
+    ///
+    /// The amount of the budget is in effect from its start date.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `plan` - The plan the budget belongs to
+    /// * `input` - The fields of the budget, validated by the caller
+    ///
+    /// # Returns
+    ///
+    /// The newly created budget
+    pub fn new(conn: &mut DbConn, plan: &Plan, input: &BudgetInput) -> Result<Self, AppError> {
+        conn.transaction(|conn| {
+            let budget = diesel::insert_into(budgets::table)
+                .values(&NewBudget {
+                    plan_name: plan.name(),
+                    category_id: input.category_id,
+                    name: &input.name,
+                    amount: &input.amount,
+                    interval: input.interval,
+                    currency: &input.currency,
+                    start_date: input.start_date,
+                    end_date: input.end_date,
+                    rollover: input.rollover,
+                })
+                .get_result::<Budget>(conn)
+                .map_err(|e| {
+                    tracing::error!("Failed creating budget in plan \"{}\" ({e})", plan.name());
+                    AppError::Diesel(e)
+                })?;
+            let budget = budget.set_amount(conn, &input.amount, input.start_date)?;
+            plan.touch(conn)?;
+            Ok(budget)
+        })
+    }
+
+    /// Get a budget by ID, scoped to the plan it belongs to
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Budget ID
+    /// * `plan` - The plan the budget belongs to
+    ///
+    /// # Returns
+    ///
+    /// The budget, or `AppError::NotFound` if it doesn't exist or belongs to another plan
+    pub fn from_id(conn: &mut DbConn, id: i32, plan: &Plan) -> Result<Self, AppError> {
+        budgets::table
+            .filter(budgets::id.eq(id))
+            .filter(budgets::plan_name.eq(plan.name()))
+            .first::<Budget>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting budget {id} of plan \"{}\" ({e})",
+                    plan.name()
+                );
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get all budgets of a plan
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `plan` - The plan to get the budgets of
+    ///
+    /// # Returns
+    ///
+    /// A vector of budgets ordered by name
+    pub fn get_all(conn: &mut DbConn, plan: &Plan) -> Result<Vec<Self>, AppError> {
+        budgets::table
+            .filter(budgets::plan_name.eq(plan.name()))
+            .order((budgets::name, budgets::id))
+            .load::<Budget>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting budgets of plan \"{}\" ({e})", plan.name());
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the budgets of a plan that apply to any day within a date range
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `plan` - The plan to get the budgets of
+    /// * `from` - First day of the range (inclusive)
+    /// * `to` - Last day of the range (inclusive)
+    ///
+    /// # Returns
+    ///
+    /// A vector of budgets ordered by name
+    pub fn active_between(
+        conn: &mut DbConn,
+        plan: &Plan,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Self>, AppError> {
+        budgets::table
+            .filter(budgets::plan_name.eq(plan.name()))
+            .filter(budgets::start_date.le(to))
+            .filter(budgets::end_date.is_null().or(budgets::end_date.ge(from)))
+            .order((budgets::name, budgets::id))
+            .load::<Budget>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting budgets of plan \"{}\" between {from} and {to} ({e})",
+                    plan.name()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Update the budget if it is still at a version, marking its plan as modified
+    ///
+    /// The version is checked and incremented by the same statement, so of two updates made
+    /// against the same version only the first one is applied.
+    ///
+    /// The amount isn't changed in place: it's recorded in effect from a day, replacing the amount
+    /// set for that day if there's one, so the months before keep their amount. The amount of the
+    /// budget is then the one in effect last.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `plan` - The plan the budget belongs to
+    /// * `input` - The new fields of the budget, validated by the caller
+    /// * `version` - The version of the budget the update was made against
+    /// * `effective_from` - The first day the amount of the input is in effect, `None` to keep the
+    ///   amounts of the budget
+    ///
+    /// # Returns
+    ///
+    /// The updated budget, or `AppError::PreconditionFailed` with the current version if the
+    /// budget was updated since
+    pub fn update(
+        &self,
+        conn: &mut DbConn,
+        plan: &Plan,
+        input: &BudgetInput,
+        version: i32,
+        effective_from: Option<NaiveDate>,
+    ) -> Result<Self, AppError> {
+        conn.transaction(|conn| {
+            let updated = diesel::update(
+                budgets::table
+                    .filter(budgets::id.eq(self.id))
+                    .filter(budgets::version.eq(version)),
+            )
+            .set((input, budgets::version.eq(budgets::version + 1)))
+            .get_result::<Budget>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed updating budget {} ({e})", self.id);
+                AppError::Diesel(e)
+            })?;
+
+            match updated {
+                Some(budget) => {
+                    let budget = match effective_from {
+                        Some(day) => budget.set_amount(conn, &input.amount, day)?,
+                        None => budget.set_amount_in_effect_last(conn)?,
+                    };
+                    plan.touch(conn)?;
+                    Ok(budget)
+                }
+                None => Err(AppError::PreconditionFailed(
+                    Budget::from_id(conn, self.id, plan)?.version,
+                )),
+            }
+        })
+    }
+
+    /// Delete the budget, marking its plan as modified
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `plan` - The plan the budget belongs to
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn, plan: &Plan) -> Result<(), AppError> {
+        conn.transaction(|conn| {
+            diesel::delete(budgets::table.filter(budgets::id.eq(self.id)))
+                .execute(conn)
+                .map_err(|e| {
+                    tracing::error!("Failed deleting budget {} ({e})", self.id);
+                    AppError::Diesel(e)
+                })?;
+            plan.touch(conn)
+        })
+    }
+
+    /// Record an amount of the budget in effect from a day, replacing the one set for the day
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `amount` - The amount available to spend each interval
+    /// * `effective_from` - The first day the amount is in effect
+    ///
+    /// # Returns
+    ///
+    /// The budget with the amount in effect last
+    fn set_amount(
+        self,
+        conn: &mut DbConn,
+        amount: &BigDecimal,
+        effective_from: NaiveDate,
+    ) -> Result<Self, AppError> {
+        diesel::insert_into(budget_amounts::table)
+            .values((
+                budget_amounts::budget_id.eq(self.id),
+                budget_amounts::amount.eq(amount),
+                budget_amounts::effective_from.eq(effective_from),
+            ))
+            .on_conflict((budget_amounts::budget_id, budget_amounts::effective_from))
+            .do_update()
+            .set((
+                budget_amounts::amount.eq(amount),
+                budget_amounts::created_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed setting the amount of budget {} from {effective_from} ({e})",
+                    self.id
+                );
+                AppError::Diesel(e)
+            })?;
+        self.set_amount_in_effect_last(conn)
+    }
+
+    /// Set the amount of the budget to the one in effect last
+    fn set_amount_in_effect_last(self, conn: &mut DbConn) -> Result<Self, AppError> {
+        let latest = budget_amounts::table
+            .filter(budget_amounts::budget_id.eq(self.id))
+            .order(budget_amounts::effective_from.desc())
+            .select(budget_amounts::amount)
+            .first::<BigDecimal>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting the amounts of budget {} ({e})", self.id);
+                AppError::Diesel(e)
+            })?;
+        match latest {
+            Some(amount) if amount != self.amount => diesel::update(budgets::table.find(self.id))
+                .set(budgets::amount.eq(amount))
+                .get_result::<Budget>(conn)
+                .map_err(|e| {
+                    tracing::error!("Failed updating the amount of budget {} ({e})", self.id);
+                    AppError::Diesel(e)
+                }),
+            _ => Ok(self),
+        }
+    }
+
+    /// Get the amounts of the budget over time
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// The amounts, oldest first
+    pub fn history(&self, conn: &mut DbConn) -> Result<AmountHistory, AppError> {
+        Ok(Budget::histories(conn, std::slice::from_ref(self))?
+            .remove(&self.id)
+            .unwrap_or_default())
+    }
+
+    /// Get the amounts of budgets over time, with one query
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `budgets` - The budgets
+    ///
+    /// # Returns
+    ///
+    /// The amounts of each budget by ID, oldest first
+    pub fn histories(
+        conn: &mut DbConn,
+        budgets: &[Budget],
+    ) -> Result<HashMap<i32, AmountHistory>, AppError> {
+        let ids: Vec<i32> = budgets.iter().map(|budget| budget.id).collect();
+        let amounts = budget_amounts::table
+            .filter(budget_amounts::budget_id.eq_any(&ids))
+            .order((budget_amounts::budget_id, budget_amounts::effective_from))
+            .load::<EffectiveAmount>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting the amounts of {} budgets ({e})", ids.len());
+                AppError::Diesel(e)
+            })?;
+
+        let mut histories: HashMap<i32, AmountHistory> = HashMap::new();
+        for amount in amounts {
+            histories
+                .entry(amount.budget_id)
+                .or_default()
+                .0
+                .push(amount);
+        }
+        Ok(histories)
+    }
+
+    /// Move the budgets of a category to another category, marking their plans as modified
+    ///
+    /// A budget of a plan that also budgets the other category in the same interval and currency
+    /// is added to that budget instead of being moved, so the plan keeps a single budget for the
+    /// category. Its current amount is added to every amount the other budget had over time.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `from` - The category of the budgets
+    /// * `into` - The category to move the budgets to
+    ///
+    /// # Returns
+    ///
+    /// The number of budgets moved or added to another budget
+    pub fn move_category(
+        conn: &mut DbConn,
+        from: &Category,
+        into: &Category,
+    ) -> Result<u64, AppError> {
+        let moved = budgets::table
+            .filter(budgets::category_id.eq(from.id()))
+            .order(budgets::id)
+            .load::<Budget>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting budgets of category {} ({e})", from.id());
+                AppError::Diesel(e)
+            })?;
+
+        let mut plan_names: Vec<&str> = vec![];
+        for budget in &moved {
+            let move_budget = |conn: &mut DbConn| -> QueryResult<()> {
+                let target = budgets::table
+                    .filter(budgets::plan_name.eq(&budget.plan_name))
+                    .filter(budgets::category_id.eq(into.id()))
+                    .filter(budgets::interval.eq(budget.interval))
+                    .filter(budgets::currency.eq(&budget.currency))
+                    .order(budgets::id)
+                    .select(budgets::id)
+                    .first::<i32>(conn)
+                    .optional()?;
+                match target {
+                    Some(target) => {
+                        diesel::update(
+                            budget_amounts::table.filter(budget_amounts::budget_id.eq(target)),
+                        )
+                        .set(budget_amounts::amount.eq(budget_amounts::amount + &budget.amount))
+                        .execute(conn)?;
+                        diesel::update(budgets::table.find(target))
+                            .set((
+                                budgets::amount.eq(budgets::amount + &budget.amount),
+                                budgets::version.eq(budgets::version + 1),
+                            ))
+                            .execute(conn)?;
+                        diesel::delete(budgets::table.find(budget.id)).execute(conn)?;
+                    }
+                    None => {
+                        diesel::update(budgets::table.find(budget.id))
+                            .set((
+                                budgets::category_id.eq(into.id()),
+                                budgets::version.eq(budgets::version + 1),
+                            ))
+                            .execute(conn)?;
+                    }
+                }
+                Ok(())
+            };
+            move_budget(conn).map_err(|e| {
+                tracing::error!(
+                    "Failed moving budget {} to category {} ({e})",
+                    budget.id,
+                    into.id()
+                );
+                AppError::Diesel(e)
+            })?;
+            if !plan_names.contains(&budget.plan_name.as_str()) {
+                plan_names.push(&budget.plan_name);
+            }
+        }
+
+        for name in plan_names {
+            Plan::from_name(conn, name, from.user_id())?.touch(conn)?;
+        }
+        Ok(moved.len() as u64)
+    }
+
+    /// Sum the transactions counted against budgets from a day of each, up to a day
+    ///
+    /// Transactions count against a budget like in `Transaction::category_amounts`: splits count
+    /// towards their own category, and transfers are left out. Every budget is summed by the same
+    /// grouped query, however many months they go back.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - ID of the owner of the budgets
+    /// * `since` - The budgets, with the first day of the transactions to sum for each
+    /// * `before` - The day after the last day of the transactions to sum
+    ///
+    /// # Returns
+    ///
+    /// The sums of the budgets that have transactions, by currency
+    pub fn amounts_since(
+        conn: &mut DbConn,
+        user_id: i32,
+        since: &[(&Budget, NaiveDate)],
+        before: NaiveDate,
+    ) -> Result<Vec<BudgetAmount>, AppError> {
+        let ids: Vec<i32> = since.iter().map(|(budget, _)| budget.id).collect();
+        let category_ids: Vec<i32> = since.iter().map(|(budget, _)| budget.category_id).collect();
+        let days: Vec<NaiveDate> = since.iter().map(|(_, day)| *day).collect();
+
+        diesel::sql_query(
+            "SELECT since.budget_id, transactions.currency,
+                SUM(COALESCE(transaction_splits.amount, transactions.amount)) AS amount
+            FROM UNNEST($2, $3, $4) AS since (budget_id, category_id, day)
+            JOIN transactions
+                ON transactions.occurred_at >= since.day AND transactions.occurred_at < $5
+            JOIN accounts ON accounts.id = transactions.account_id
+            LEFT JOIN transaction_splits
+                ON transaction_splits.transaction_id = transactions.id
+            WHERE accounts.user_id = $1
+                AND transactions.transfer_id IS NULL
+                AND COALESCE(transaction_splits.category_id, transactions.category_id)
+                    = since.category_id
+            GROUP BY since.budget_id, transactions.currency
+            ORDER BY since.budget_id, transactions.currency",
+        )
+        .bind::<Integer, _>(user_id)
+        .bind::<Array<Integer>, _>(ids)
+        .bind::<Array<Integer>, _>(category_ids)
+        .bind::<Array<Date>, _>(days)
+        .bind::<Date, _>(before)
+        .load::<BudgetAmount>(conn)
+        .map_err(|e| {
+            tracing::error!(
+                "Failed summing the transactions of {} budgets of user {user_id} ({e})",
+                since.len()
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the amount available to spend in a month
+    ///
+    /// Yearly amounts are spread evenly over the months of the year.
+    pub fn monthly_amount(&self) -> BigDecimal {
+        self.monthly(&self.amount)
+    }
+
+    /// Get the amount available to spend in a month starting on a day
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - The amounts of the budget over time
+    /// * `day` - The first day of the month, the amount in effect then is the amount of the month
+    pub fn monthly_amount_on(&self, history: &AmountHistory, day: NaiveDate) -> BigDecimal {
+        self.monthly(history.on(day).unwrap_or(&self.amount))
+    }
+
+    fn monthly(&self, amount: &BigDecimal) -> BigDecimal {
+        match self.interval {
+            BudgetInterval::Monthly => amount.clone(),
+            BudgetInterval::Yearly => (amount / BigDecimal::from(12)).round(2),
+        }
+    }
+
+    /// Get the amount of the budget, the one in effect last
+    pub fn amount(&self) -> &BigDecimal {
+        &self.amount
+    }
+
+    /// Get the ID of the budget
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the version of the budget
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Get the ID of the category the budget is for
+    pub fn category_id(&self) -> i32 {
+        self.category_id
+    }
+
+    /// Get the name of the budget
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the first day the budget applies to
+    pub fn start_date(&self) -> NaiveDate {
+        self.start_date
+    }
+
+    /// Get the last day the budget applies to, if it ends
+    pub fn end_date(&self) -> Option<NaiveDate> {
+        self.end_date
+    }
+
+    /// Get the ISO 4217 currency code of the amount of the budget
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// Get whether what's left of the amount of a month carries into the next months
+    pub fn rollover(&self) -> bool {
+        self.rollover
+    }
+}
@@ -0,0 +1,179 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{connection::DbConn, schema::categories};
+use crate::errors::AppError;
+
+/// Category struct
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = categories)]
+pub struct Category {
+    /// Category ID
+    id: i32,
+    /// ID of the user that owns the category
+    user_id: i32,
+    /// Category name, unique per user
+    name: String,
+    /// The timestamp when the category was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = categories)]
+struct NewCategory<'a> {
+    user_id: i32,
+    name: &'a str,
+}
+
+impl Category {
+    /// Create a new category for a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `name` - Name of the category, must be unique per user
+    ///
+    /// # Returns
+    ///
+    /// The newly created category
+    pub fn new(conn: &mut DbConn, user_id: i32, name: &str) -> Result<Self, AppError> {
+        diesel::insert_into(categories::table)
+            .values(&NewCategory { user_id, name })
+            .get_result::<Category>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed creating category \"{name}\" for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a category by ID, scoped to the user that owns it
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Category ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The category, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        categories::table
+            .filter(categories::id.eq(id))
+            .filter(categories::user_id.eq(user_id))
+            .first::<Category>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting category {id} for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get all categories of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// A vector of categories ordered by name
+    pub fn get_all(conn: &mut DbConn, user_id: i32) -> Result<Vec<Self>, AppError> {
+        categories::table
+            .filter(categories::user_id.eq(user_id))
+            .order(categories::name)
+            .load::<Category>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting categories for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Delete the category
+    ///
+    /// Its budgets and alert are deleted with it, and its transactions, splits, recurring
+    /// transactions and payee rules are left without a category.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::delete(categories::table.filter(categories::id.eq(self.id)))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::error!("Failed deleting category {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the ID of the category
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the name of the category
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the ID of the user that owns the category
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// Get the timestamp when the category was created
+    pub fn created_at(&self) -> chrono::NaiveDateTime {
+        self.created_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{connection::DbPool, models::users::User};
+
+    #[test]
+    fn test_new_category() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let other = User::new(conn, "other_user", "other_password").unwrap();
+
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        Category::new(conn, user.id(), "Dining").unwrap();
+        // Names are only unique per user
+        Category::new(conn, other.id(), "Groceries").unwrap();
+
+        let categories = Category::get_all(conn, user.id()).unwrap();
+        let names: Vec<&str> = categories.iter().map(|c| c.name()).collect();
+        assert_eq!(names, vec!["Dining", "Groceries"]);
+
+        assert!(Category::from_id(conn, groceries.id, user.id()).is_ok());
+        assert!(Category::from_id(conn, groceries.id, other.id()).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_category() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        Category::new(conn, user.id(), "Groceries").unwrap();
+
+        assert!(Category::new(conn, user.id(), "Groceries").is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Datelike, NaiveDate};
+use diesel::{pg::upsert::excluded, prelude::*};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        categories::Category,
+        notifications::{NewNotification, Notification},
+        transactions::Transaction,
+    },
+    schema::category_alerts,
+};
+use crate::errors::AppError;
+use crate::reports::periods::month_range;
+
+/// Type of the notifications recorded when an alert fires
+pub const NOTIFICATION_TYPE: &str = "category_alert";
+
+/// A monthly spending limit of a category, which fires once a month when spending reaches a
+/// share of it
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = category_alerts)]
+pub struct CategoryAlert {
+    /// Alert ID
+    id: i32,
+    /// ID of the user that owns the alert
+    user_id: i32,
+    /// ID of the category whose spending is watched
+    category_id: i32,
+    /// The most that should be spent on the category in a month, as a decimal string
+    #[schema(value_type = String)]
+    monthly_limit: BigDecimal,
+    /// Percentage of the limit at which the alert fires, from 1 to 100
+    notify_at_percent: i32,
+    /// First day of the last month the alert fired in, if any
+    #[schema(value_type = Option<String>)]
+    last_alerted_month: Option<NaiveDate>,
+    /// The timestamp when the alert was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+}
+
+/// Fields of a category alert
+#[derive(Debug, Clone)]
+pub struct CategoryAlertInput {
+    /// The most that should be spent on the category in a month
+    pub monthly_limit: BigDecimal,
+    /// Percentage of the limit at which the alert fires
+    pub notify_at_percent: i32,
+}
+
+impl CategoryAlertInput {
+    /// Validate the input
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the input is valid, otherwise `AppError::InvalidInput`
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.monthly_limit <= BigDecimal::zero() {
+            return Err(AppError::InvalidInput(
+                "The monthly limit of an alert must be positive".to_string(),
+            ));
+        }
+        if !(1..=100).contains(&self.notify_at_percent) {
+            return Err(AppError::InvalidInput(
+                "An alert must fire at between 1 and 100 percent of its limit".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl CategoryAlert {
+    /// Set the alert of a category, replacing the one it has
+    ///
+    /// Replacing an alert rearms it, so it can fire again in a month it already fired in.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `category` - The category to watch the spending of
+    /// * `input` - The fields of the alert
+    ///
+    /// # Returns
+    ///
+    /// The alert, or `AppError::InvalidInput` if the input is invalid
+    pub fn set(
+        conn: &mut DbConn,
+        category: &Category,
+        input: &CategoryAlertInput,
+    ) -> Result<Self, AppError> {
+        input.validate()?;
+
+        diesel::insert_into(category_alerts::table)
+            .values((
+                category_alerts::user_id.eq(category.user_id()),
+                category_alerts::category_id.eq(category.id()),
+                category_alerts::monthly_limit.eq(&input.monthly_limit),
+                category_alerts::notify_at_percent.eq(input.notify_at_percent),
+            ))
+            .on_conflict(category_alerts::category_id)
+            .do_update()
+            .set((
+                category_alerts::monthly_limit.eq(excluded(category_alerts::monthly_limit)),
+                category_alerts::notify_at_percent.eq(excluded(category_alerts::notify_at_percent)),
+                category_alerts::last_alerted_month.eq(None::<NaiveDate>),
+            ))
+            .get_result::<CategoryAlert>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed setting alert of category {} ({e})", category.id());
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get all alerts of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// A vector of alerts, oldest first
+    pub fn get_all(conn: &mut DbConn, user_id: i32) -> Result<Vec<Self>, AppError> {
+        category_alerts::table
+            .filter(category_alerts::user_id.eq(user_id))
+            .order(category_alerts::id)
+            .load::<CategoryAlert>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting category alerts of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the alerts of some categories of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `category_ids` - IDs of the categories
+    ///
+    /// # Returns
+    ///
+    /// A vector of the alerts of the categories that have one
+    pub fn for_categories(
+        conn: &mut DbConn,
+        user_id: i32,
+        category_ids: &[i32],
+    ) -> Result<Vec<Self>, AppError> {
+        category_alerts::table
+            .filter(category_alerts::user_id.eq(user_id))
+            .filter(category_alerts::category_id.eq_any(category_ids))
+            .load::<CategoryAlert>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting alerts of categories {category_ids:?} of user {user_id} ({e})"
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the alert of a category
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `category` - The category
+    ///
+    /// # Returns
+    ///
+    /// The alert, or `AppError::NotFound` if the category has none
+    pub fn from_category(conn: &mut DbConn, category: &Category) -> Result<Self, AppError> {
+        category_alerts::table
+            .filter(category_alerts::category_id.eq(category.id()))
+            .first::<CategoryAlert>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting alert of category {} ({e})", category.id());
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Move the alert of a category to another category
+    ///
+    /// An alert can't watch two categories, so when the other category has an alert too, the
+    /// limit of the moved alert is added to it instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `from` - The category whose alert is moved
+    /// * `into` - The category to move the alert to
+    ///
+    /// # Returns
+    ///
+    /// The number of alerts moved or added to the alert of the other category, 0 or 1
+    pub fn move_category(
+        conn: &mut DbConn,
+        from: &Category,
+        into: &Category,
+    ) -> Result<u64, AppError> {
+        let alert = match CategoryAlert::from_category(conn, from) {
+            Ok(alert) => alert,
+            Err(AppError::NotFound(_)) => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let moved = match CategoryAlert::from_category(conn, into) {
+            Ok(target) => diesel::update(category_alerts::table.find(target.id))
+                .set(
+                    category_alerts::monthly_limit
+                        .eq(category_alerts::monthly_limit + &alert.monthly_limit),
+                )
+                .execute(conn)
+                .and_then(|_| diesel::delete(category_alerts::table.find(alert.id)).execute(conn)),
+            Err(AppError::NotFound(_)) => diesel::update(category_alerts::table.find(alert.id))
+                .set(category_alerts::category_id.eq(into.id()))
+                .execute(conn),
+            Err(e) => return Err(e),
+        };
+        moved.map(|count| count as u64).map_err(|e| {
+            tracing::error!(
+                "Failed moving alert of category {} to {} ({e})",
+                from.id(),
+                into.id()
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Delete the alert
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::delete(category_alerts::table.filter(category_alerts::id.eq(self.id)))
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!("Failed deleting category alert {} ({e})", self.id);
+                AppError::Diesel(e)
+            })?;
+        Ok(())
+    }
+
+    /// Fire the alert if spending on its category this month reached its threshold
+    ///
+    /// Spending is added up with a single query over the transactions of the month. The alert is
+    /// marked as fired for the month in the same statement that checks it hasn't fired yet, so
+    /// it fires once a month even when transactions are created concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `today` - The current date, whose month is watched
+    ///
+    /// # Returns
+    ///
+    /// The notification recorded if the alert fired, otherwise `None`
+    pub fn evaluate(
+        &self,
+        conn: &mut DbConn,
+        today: NaiveDate,
+    ) -> Result<Option<Notification>, AppError> {
+        let (from, to) = month_range(today.year(), today.month())?;
+        if self.last_alerted_month.is_some_and(|month| month >= from) {
+            return Ok(None);
+        }
+
+        let spent = Transaction::category_spending(conn, self.user_id, self.category_id, from, to)?;
+        let threshold = &self.monthly_limit * BigDecimal::from(self.notify_at_percent);
+        if &spent * BigDecimal::from(100) < threshold {
+            return Ok(None);
+        }
+
+        conn.transaction(|conn| {
+            let fired = diesel::update(
+                category_alerts::table
+                    .filter(category_alerts::id.eq(self.id))
+                    .filter(
+                        category_alerts::last_alerted_month
+                            .is_null()
+                            .or(category_alerts::last_alerted_month.lt(from)),
+                    ),
+            )
+            .set(category_alerts::last_alerted_month.eq(from))
+            .execute(conn)?;
+            if fired == 0 {
+                return Ok(None);
+            }
+
+            let category = Category::from_id(conn, self.category_id, self.user_id)?;
+            let notification = NewNotification::new(
+                self.user_id,
+                NOTIFICATION_TYPE,
+                format!("{} spending alert", category.name()),
+                format!(
+                    "You spent {spent} on {} this month, {}% of your monthly limit of {}",
+                    category.name(),
+                    (&spent * BigDecimal::from(100) / &self.monthly_limit).round(0),
+                    self.monthly_limit
+                ),
+                json!({
+                    "alert_id": self.id,
+                    "category_id": self.category_id,
+                    "month": from,
+                    "spent": spent,
+                    "monthly_limit": self.monthly_limit,
+                    "notify_at_percent": self.notify_at_percent,
+                }),
+            );
+            Notification::new(conn, &notification).map(Some)
+        })
+    }
+
+    /// Get the ID of the alert
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+}
@@ -0,0 +1,121 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use diesel::{pg::upsert::excluded, prelude::*};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{connection::DbConn, schema::exchange_rates};
+use crate::errors::AppError;
+
+/// Check that a currency code is three uppercase letters, like ISO 4217 codes
+///
+/// # Returns
+///
+/// An empty result if the code is valid, otherwise `AppError::InvalidInput`
+pub fn validate_currency_code(code: &str) -> Result<(), AppError> {
+    if code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase()) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidInput(format!(
+            "\"{code}\" is not an ISO 4217 currency code"
+        )))
+    }
+}
+
+/// The amount of a currency that one unit of another currency buys on a day
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, Insertable, ToSchema)]
+#[diesel(table_name = exchange_rates)]
+pub struct ExchangeRate {
+    /// ISO 4217 code of the currency being converted from
+    pub base: String,
+    /// ISO 4217 code of the currency being converted to
+    pub quote: String,
+    /// The day the rate applies from, until the next rate of the pair
+    pub as_of: NaiveDate,
+    /// Units of the quote currency per unit of the base currency, as a decimal string
+    #[schema(value_type = String)]
+    pub rate: BigDecimal,
+}
+
+impl ExchangeRate {
+    /// Validate the rate
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the rate is valid, otherwise `AppError::InvalidInput`
+    pub fn validate(&self) -> Result<(), AppError> {
+        validate_currency_code(&self.base)?;
+        validate_currency_code(&self.quote)?;
+        if self.base == self.quote {
+            return Err(AppError::InvalidInput(format!(
+                "Can't set an exchange rate from {} to itself",
+                self.base
+            )));
+        }
+        if self.rate <= BigDecimal::zero() {
+            return Err(AppError::InvalidInput(format!(
+                "The exchange rate from {} to {} must be positive",
+                self.base, self.quote
+            )));
+        }
+        Ok(())
+    }
+
+    /// Insert rates, replacing the rates of the same pairs and days
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `rates` - The rates to save, validated by the caller
+    ///
+    /// # Returns
+    ///
+    /// The number of rates saved
+    pub fn upsert(conn: &mut DbConn, rates: &[ExchangeRate]) -> Result<usize, AppError> {
+        diesel::insert_into(exchange_rates::table)
+            .values(rates)
+            .on_conflict((
+                exchange_rates::base,
+                exchange_rates::quote,
+                exchange_rates::as_of,
+            ))
+            .do_update()
+            .set(exchange_rates::rate.eq(excluded(exchange_rates::rate)))
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!("Failed saving {} exchange rates ({e})", rates.len());
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the rates to or from a currency, up to a day
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `currency` - ISO 4217 code of the base or quote currency of the rates
+    /// * `to` - Last day of the rates (inclusive)
+    ///
+    /// # Returns
+    ///
+    /// A vector of rates ordered by day
+    pub fn involving(
+        conn: &mut DbConn,
+        currency: &str,
+        to: NaiveDate,
+    ) -> Result<Vec<Self>, AppError> {
+        exchange_rates::table
+            .filter(
+                exchange_rates::base
+                    .eq(currency)
+                    .or(exchange_rates::quote.eq(currency)),
+            )
+            .filter(exchange_rates::as_of.le(to))
+            .order(exchange_rates::as_of)
+            .load::<ExchangeRate>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting exchange rates of {currency} up to {to} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+}
@@ -0,0 +1,435 @@
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
+use chrono::NaiveDate;
+use diesel::{dsl::sum, prelude::*};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::accounts::Account,
+    schema::{goals, transactions},
+};
+use crate::errors::AppError;
+
+/// Savings goal struct
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable)]
+#[diesel(table_name = goals)]
+pub struct Goal {
+    /// Goal ID
+    id: i32,
+    /// ID of the user that owns the goal
+    user_id: i32,
+    /// Name of the goal
+    name: String,
+    /// The amount to save
+    target_amount: BigDecimal,
+    /// The date the amount should be saved by
+    target_date: NaiveDate,
+    /// ID of the account whose balance is the progress of the goal, if any
+    linked_account_id: Option<i32>,
+    /// The timestamp when the goal was created
+    #[serde(with = "crate::utils::serialization")]
+    created_at: chrono::NaiveDateTime,
+}
+
+/// Fields of a goal to be created or updated
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = goals, treat_none_as_null = true)]
+pub struct GoalInput {
+    /// Name of the goal
+    pub name: String,
+    /// The amount to save, must be positive
+    pub target_amount: BigDecimal,
+    /// The date the amount should be saved by, must not be in the past
+    pub target_date: NaiveDate,
+    /// ID of the account whose balance is the progress of the goal, must belong to the user
+    pub linked_account_id: Option<i32>,
+}
+
+impl GoalInput {
+    /// Validate the input
+    ///
+    /// # Arguments
+    ///
+    /// * `today` - The current date
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the input is valid, otherwise `AppError::InvalidInput`
+    pub fn validate(&self, today: NaiveDate) -> Result<(), AppError> {
+        if self.target_amount <= BigDecimal::zero() {
+            return Err(AppError::InvalidInput(
+                "The target amount of a goal must be positive".to_string(),
+            ));
+        }
+        if self.target_date < today {
+            return Err(AppError::InvalidInput(
+                "The target date of a goal must not be in the past".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A goal with how far along it is
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct GoalProgress {
+    /// The goal
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    goal: Goal,
+    /// The amount saved so far, as a decimal string
+    #[schema(value_type = String)]
+    progress: BigDecimal,
+    /// The amount saved so far as a percentage of the target amount
+    percent_complete: f64,
+    /// Whether the amount saved so far keeps up with a steady pace towards the target date
+    on_track: bool,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = goals)]
+struct NewGoal<'a> {
+    user_id: i32,
+    name: &'a str,
+    target_amount: &'a BigDecimal,
+    target_date: NaiveDate,
+    linked_account_id: Option<i32>,
+}
+
+impl Goal {
+    /// Create a new goal for a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `input` - The fields of the goal, validated by the caller
+    ///
+    /// # Returns
+    ///
+    /// The newly created goal
+    pub fn new(conn: &mut DbConn, user_id: i32, input: &GoalInput) -> Result<Self, AppError> {
+        diesel::insert_into(goals::table)
+            .values(&NewGoal {
+                user_id,
+                name: &input.name,
+                target_amount: &input.target_amount,
+                target_date: input.target_date,
+                linked_account_id: input.linked_account_id,
+            })
+            .get_result::<Goal>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed creating goal for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a goal by ID, scoped to the user that owns it
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Goal ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The goal, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        goals::table
+            .filter(goals::id.eq(id))
+            .filter(goals::user_id.eq(user_id))
+            .first::<Goal>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting goal {id} for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get all goals of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// A vector of goals, nearest target date first
+    pub fn get_all(conn: &mut DbConn, user_id: i32) -> Result<Vec<Self>, AppError> {
+        goals::table
+            .filter(goals::user_id.eq(user_id))
+            .order((goals::target_date, goals::id))
+            .load::<Goal>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting goals for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Update the goal
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `input` - The new fields of the goal, validated by the caller
+    ///
+    /// # Returns
+    ///
+    /// The updated goal
+    pub fn update(&self, conn: &mut DbConn, input: &GoalInput) -> Result<Self, AppError> {
+        diesel::update(goals::table.filter(goals::id.eq(self.id)))
+            .set(input)
+            .get_result::<Goal>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed updating goal {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Delete the goal
+    ///
+    /// Transactions that counted towards the goal are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::delete(goals::table.filter(goals::id.eq(self.id)))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::error!("Failed deleting goal {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the amount saved towards the goal
+    ///
+    /// If the goal is linked to an account, this is the balance of the account. Otherwise it is
+    /// the sum of the transactions that count towards the goal.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// The amount saved
+    pub fn progress(&self, conn: &mut DbConn) -> Result<BigDecimal, AppError> {
+        if let Some(account_id) = self.linked_account_id {
+            let account = Account::from_id(conn, account_id, self.user_id)?;
+            return account.balance(conn);
+        }
+
+        transactions::table
+            .filter(transactions::goal_id.eq(self.id))
+            .select(sum(transactions::amount))
+            .first::<Option<BigDecimal>>(conn)
+            .map(Option::unwrap_or_default)
+            .map_err(|e| {
+                tracing::error!("Failed getting progress of goal {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Attach the progress of the goal
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `today` - The current date
+    ///
+    /// # Returns
+    ///
+    /// The goal with its progress
+    pub fn with_progress(
+        self,
+        conn: &mut DbConn,
+        today: NaiveDate,
+    ) -> Result<GoalProgress, AppError> {
+        let progress = self.progress(conn)?;
+        let percent_complete = (&progress * BigDecimal::from(100) / &self.target_amount)
+            .round(2)
+            .to_f64()
+            .unwrap_or_default();
+        let on_track = is_on_track(
+            &progress,
+            &self.target_amount,
+            self.created_at.date(),
+            self.target_date,
+            today,
+        );
+
+        Ok(GoalProgress {
+            goal: self,
+            progress,
+            percent_complete,
+            on_track,
+        })
+    }
+
+    /// Get the ID of the goal
+    #[cfg(test)]
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+/// Whether a goal keeps up with a steady pace from its start to its target date
+///
+/// On any day, a goal is on track if the amount saved is at least the share of the target amount
+/// that the elapsed share of the time span calls for. Once the target date is reached, a goal is
+/// only on track if the target amount is saved.
+fn is_on_track(
+    progress: &BigDecimal,
+    target_amount: &BigDecimal,
+    start: NaiveDate,
+    target_date: NaiveDate,
+    today: NaiveDate,
+) -> bool {
+    let total_days = (target_date - start).num_days();
+    if today >= target_date || total_days <= 0 {
+        return progress >= target_amount;
+    }
+
+    let elapsed_days = (today - start).num_days().max(0);
+    progress * BigDecimal::from(total_days) >= target_amount * BigDecimal::from(elapsed_days)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::AccountKind,
+            transactions::{Transaction, TransactionInput},
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn dec(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_is_on_track() {
+        let (start, target_date) = (date(2024, 1, 1), date(2024, 1, 11));
+        let target = dec("1000");
+
+        // Halfway through the time span, half the amount is needed
+        assert!(is_on_track(
+            &dec("500"),
+            &target,
+            start,
+            target_date,
+            date(2024, 1, 6)
+        ));
+        assert!(!is_on_track(
+            &dec("499.99"),
+            &target,
+            start,
+            target_date,
+            date(2024, 1, 6)
+        ));
+        // Nothing is needed on the first day
+        assert!(is_on_track(&dec("0"), &target, start, target_date, start));
+        // Past the target date, the whole amount is needed
+        assert!(!is_on_track(
+            &dec("999"),
+            &target,
+            start,
+            target_date,
+            date(2024, 2, 1)
+        ));
+        assert!(is_on_track(
+            &dec("1000"),
+            &target,
+            start,
+            target_date,
+            date(2024, 2, 1)
+        ));
+    }
+
+    #[test]
+    fn test_validate() {
+        let today = date(2024, 6, 1);
+        let input = |amount: &str, target_date: NaiveDate| GoalInput {
+            name: "Vacation".to_string(),
+            target_amount: dec(amount),
+            target_date,
+            linked_account_id: None,
+        };
+
+        assert!(input("100", today).validate(today).is_ok());
+        assert!(input("0", today).validate(today).is_err());
+        assert!(input("-5", today).validate(today).is_err());
+        assert!(input("100", date(2024, 5, 31)).validate(today).is_err());
+    }
+
+    #[test]
+    fn test_progress() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Savings",
+            &dec("250"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let today = chrono::Local::now().date_naive();
+        let mut input = GoalInput {
+            name: "Vacation".to_string(),
+            target_amount: dec("1000"),
+            target_date: today + chrono::Duration::days(365),
+            linked_account_id: None,
+        };
+
+        // Unlinked goals sum the transactions that count towards them
+        let goal = Goal::new(conn, user.id(), &input).unwrap();
+        let mut deposit = TransactionInput::new(dec("100.50"), "Deposit", today);
+        deposit.goal_id = Some(goal.id());
+        Transaction::new(conn, &account, &deposit, &Quotas::disabled()).unwrap();
+        Transaction::new(conn, &account, &deposit, &Quotas::disabled()).unwrap();
+        Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(dec("-30"), "Coffee", today),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+
+        let progress = goal.clone().with_progress(conn, today).unwrap();
+        assert_eq!(progress.progress, dec("201.00"));
+        assert_eq!(progress.percent_complete, 20.1);
+        assert!(progress.on_track);
+
+        // Linked goals use the balance of the account
+        input.linked_account_id = Some(account.id());
+        let goal = goal.update(conn, &input).unwrap();
+        let progress = goal.with_progress(conn, today).unwrap();
+        assert_eq!(progress.progress, dec("421.00"));
+        assert_eq!(progress.percent_complete, 42.1);
+    }
+}
@@ -0,0 +1,469 @@
+use chrono::NaiveDateTime;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::{Nullable, Text, Timestamp},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{connection::DbConn, models::accounts::Account, schema::import_jobs};
+use crate::errors::AppError;
+use crate::import::csv::RowError;
+
+diesel::define_sql_function! {
+    /// The first of two timestamps that isn't null, typed as nullable to be set to a nullable
+    /// column
+    fn coalesce(x: Nullable<Timestamp>, y: Timestamp) -> Nullable<Timestamp>;
+}
+
+/// Where an import is at
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    /// The import waits for a worker
+    Queued,
+    /// A worker is inserting the rows in batches
+    Running,
+    /// Every row was inserted, held back or skipped
+    Done,
+    /// The import stopped on an error, see `error`
+    Failed,
+    /// The user cancelled the import, the batches committed before are kept
+    Cancelled,
+}
+
+impl ImportStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ImportStatus::Queued => "queued",
+            ImportStatus::Running => "running",
+            ImportStatus::Done => "done",
+            ImportStatus::Failed => "failed",
+            ImportStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for ImportStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for ImportStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "queued" => Ok(ImportStatus::Queued),
+            "running" => Ok(ImportStatus::Running),
+            "done" => Ok(ImportStatus::Done),
+            "failed" => Ok(ImportStatus::Failed),
+            "cancelled" => Ok(ImportStatus::Cancelled),
+            other => Err(format!("Unknown import status \"{other}\"").into()),
+        }
+    }
+}
+
+/// An import of transactions into an account, and its progress
+#[derive(Debug, Serialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = import_jobs)]
+pub struct ImportJob {
+    /// Import ID
+    id: i32,
+    /// ID of the user that started the import
+    #[serde(skip)]
+    user_id: i32,
+    /// ID of the account the transactions are imported into
+    account_id: i32,
+    /// Where the import is at
+    status: ImportStatus,
+    /// Number of valid rows of the file
+    rows_total: i32,
+    /// Number of rows of the batches committed so far
+    rows_processed: i32,
+    /// Number of transactions inserted so far
+    inserted: i32,
+    /// Number of rows held back for review so far, because they look like duplicates
+    flagged: i32,
+    /// Number of rows skipped because they failed validation
+    skipped: i32,
+    /// Why each skipped row failed validation
+    #[schema(value_type = Vec<RowError>)]
+    errors: serde_json::Value,
+    /// Whether the user cancelled the import, which stops before its next batch
+    cancel_requested: bool,
+    /// Why the import failed, if it did
+    error: Option<String>,
+    /// The timestamp when a worker started the import
+    #[serde(with = "crate::utils::serialization::option")]
+    #[schema(value_type = Option<String>)]
+    started_at: Option<NaiveDateTime>,
+    /// The timestamp when the import was done, failed or cancelled
+    #[serde(with = "crate::utils::serialization::option")]
+    #[schema(value_type = Option<String>)]
+    finished_at: Option<NaiveDateTime>,
+    /// The timestamp when the file was uploaded
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: NaiveDateTime,
+}
+
+impl ImportJob {
+    /// Create a queued import
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the transactions are imported into
+    /// * `rows_total` - Number of valid rows of the file
+    /// * `errors` - Why each skipped row of the file failed validation
+    ///
+    /// # Returns
+    ///
+    /// The import
+    pub fn new(
+        conn: &mut DbConn,
+        account: &Account,
+        rows_total: usize,
+        errors: &[RowError],
+    ) -> Result<Self, AppError> {
+        let errors = serde_json::to_value(errors).expect("Row errors serialize to JSON");
+
+        diesel::insert_into(import_jobs::table)
+            .values((
+                import_jobs::user_id.eq(account.user_id()),
+                import_jobs::account_id.eq(account.id()),
+                import_jobs::rows_total.eq(rows_total as i32),
+                import_jobs::skipped.eq(errors.as_array().map_or(0, Vec::len) as i32),
+                import_jobs::errors.eq(errors),
+            ))
+            .get_result::<ImportJob>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed creating import into account {} ({e})", account.id());
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get an import of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Import ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The import, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        import_jobs::table
+            .filter(import_jobs::id.eq(id))
+            .filter(import_jobs::user_id.eq(user_id))
+            .first::<ImportJob>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting import {id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get the imports into an account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account
+    ///
+    /// # Returns
+    ///
+    /// A vector of imports, newest first
+    pub fn get_all(conn: &mut DbConn, account: &Account) -> Result<Vec<Self>, AppError> {
+        import_jobs::table
+            .filter(import_jobs::account_id.eq(account.id()))
+            .order(import_jobs::id.desc())
+            .load::<ImportJob>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting imports of account {} ({e})", account.id());
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Mark the import as running, for a worker to insert its rows
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Import ID
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The running import, `None` if it was cancelled, finished or deleted
+    pub fn start(conn: &mut DbConn, id: i32, now: NaiveDateTime) -> Result<Option<Self>, AppError> {
+        // An import resumed by another worker keeps the time it first started
+        diesel::update(import_jobs::table.find(id))
+            .filter(import_jobs::status.eq_any([ImportStatus::Queued, ImportStatus::Running]))
+            .set((
+                import_jobs::status.eq(ImportStatus::Running),
+                import_jobs::started_at.eq(coalesce(import_jobs::started_at, now)),
+            ))
+            .get_result::<ImportJob>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed starting import {id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the import as it is now, e.g. to know if it was cancelled
+    pub fn reload(&self, conn: &mut DbConn) -> Result<Self, AppError> {
+        import_jobs::table
+            .find(self.id)
+            .first::<ImportJob>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting import {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Count the rows of a committed batch
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database, in the transaction of the batch
+    /// * `processed` - Number of rows of the batch
+    /// * `inserted` - Number of transactions the batch inserted
+    /// * `flagged` - Number of rows of the batch held back as likely duplicates
+    ///
+    /// # Returns
+    ///
+    /// The import with its progress
+    pub fn record_batch(
+        &self,
+        conn: &mut DbConn,
+        processed: usize,
+        inserted: usize,
+        flagged: usize,
+    ) -> Result<Self, AppError> {
+        diesel::update(import_jobs::table.find(self.id))
+            .set((
+                import_jobs::rows_processed.eq(import_jobs::rows_processed + processed as i32),
+                import_jobs::inserted.eq(import_jobs::inserted + inserted as i32),
+                import_jobs::flagged.eq(import_jobs::flagged + flagged as i32),
+            ))
+            .get_result::<ImportJob>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed recording progress of import {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// End the import
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `status` - How the import ended
+    /// * `error` - Why the import failed, if it did
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The finished import
+    pub fn finish(
+        &self,
+        conn: &mut DbConn,
+        status: ImportStatus,
+        error: Option<&str>,
+        now: NaiveDateTime,
+    ) -> Result<Self, AppError> {
+        diesel::update(import_jobs::table.find(self.id))
+            .set((
+                import_jobs::status.eq(status),
+                import_jobs::error.eq(error),
+                import_jobs::finished_at.eq(now),
+            ))
+            .get_result::<ImportJob>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed finishing import {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Fail an import that didn't finish, once its job ran out of attempts
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Import ID
+    /// * `error` - Why the last attempt failed
+    /// * `now` - The current time
+    pub fn fail(
+        conn: &mut DbConn,
+        id: i32,
+        error: &str,
+        now: NaiveDateTime,
+    ) -> Result<(), AppError> {
+        diesel::update(import_jobs::table.find(id))
+            .filter(import_jobs::status.eq_any([ImportStatus::Queued, ImportStatus::Running]))
+            .set((
+                import_jobs::status.eq(ImportStatus::Failed),
+                import_jobs::error.eq(error),
+                import_jobs::finished_at.eq(now),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::error!("Failed recording the failure of import {id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Cancel the import
+    ///
+    /// A queued import is cancelled right away. A running one is asked to stop, which it does
+    /// before its next batch, keeping the batches it committed.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The import, or `AppError::Conflict` if it already finished
+    pub fn cancel(&self, conn: &mut DbConn, now: NaiveDateTime) -> Result<Self, AppError> {
+        let import = import_jobs::table.find(self.id);
+        conn.transaction(|conn| {
+            let cancelled = diesel::update(import)
+                .filter(import_jobs::status.eq(ImportStatus::Queued))
+                .set((
+                    import_jobs::status.eq(ImportStatus::Cancelled),
+                    import_jobs::cancel_requested.eq(true),
+                    import_jobs::finished_at.eq(now),
+                ))
+                .get_result::<ImportJob>(conn)
+                .optional()?;
+            if let Some(cancelled) = cancelled {
+                return Ok(Some(cancelled));
+            }
+
+            diesel::update(import)
+                .filter(import_jobs::status.eq(ImportStatus::Running))
+                .set(import_jobs::cancel_requested.eq(true))
+                .get_result::<ImportJob>(conn)
+                .optional()
+        })
+        .map_err(|e| {
+            tracing::error!("Failed cancelling import {} ({e})", self.id);
+            AppError::Diesel(e)
+        })?
+        .ok_or_else(|| AppError::Conflict("The import already finished".to_string()))
+    }
+
+    /// Get the import ID
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the ID of the user that started the import
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// Get the ID of the account the transactions are imported into
+    pub fn account_id(&self) -> i32 {
+        self.account_id
+    }
+
+    /// Get where the import is at
+    pub fn status(&self) -> ImportStatus {
+        self.status
+    }
+
+    /// Get the number of rows of the batches committed so far
+    pub fn rows_processed(&self) -> usize {
+        self.rows_processed as usize
+    }
+
+    /// Get the number of transactions inserted so far
+    pub fn inserted(&self) -> usize {
+        self.inserted as usize
+    }
+
+    /// Check if the user cancelled the import
+    pub fn cancel_requested(&self) -> bool {
+        self.cancel_requested
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{accounts::AccountKind, users::User},
+    };
+
+    fn now() -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 7, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cancel() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &"0.00".parse().unwrap(),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+
+        // Queued imports are cancelled right away
+        let queued = ImportJob::new(conn, &account, 10, &[]).unwrap();
+        let cancelled = queued.cancel(conn, now()).unwrap();
+        assert_eq!(cancelled.status(), ImportStatus::Cancelled);
+        assert!(ImportJob::start(conn, queued.id(), now())
+            .unwrap()
+            .is_none());
+
+        // Running imports are asked to stop
+        let running = ImportJob::new(conn, &account, 10, &[]).unwrap();
+        let running = ImportJob::start(conn, running.id(), now())
+            .unwrap()
+            .unwrap();
+        let asked = running.cancel(conn, now()).unwrap();
+        assert_eq!(asked.status(), ImportStatus::Running);
+        assert!(asked.cancel_requested());
+
+        // Finished imports can't be cancelled
+        let done = asked
+            .finish(conn, ImportStatus::Cancelled, None, now())
+            .unwrap();
+        assert!(matches!(
+            done.cancel(conn, now()),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            ImportJob::from_id(conn, done.id(), user.id() + 1),
+            Err(AppError::NotFound(_))
+        ));
+        assert_eq!(ImportJob::get_all(conn, &account).unwrap().len(), 2);
+    }
+}
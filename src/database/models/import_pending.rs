@@ -0,0 +1,278 @@
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        accounts::Account,
+        payee_rules::PayeeRules,
+        transactions::{Transaction, TransactionInput},
+    },
+    schema::import_pending,
+};
+use crate::errors::AppError;
+use crate::quotas::Quotas;
+
+/// An imported row held back for review because it looks like a duplicate
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = import_pending)]
+pub struct PendingImport {
+    /// Pending import ID
+    id: i32,
+    /// ID of the account the row was imported into
+    account_id: i32,
+    /// Signed amount of the row
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// Description of the row
+    description: String,
+    /// The date the row occurred on
+    #[schema(value_type = String)]
+    occurred_at: NaiveDate,
+    /// ID of the existing transaction the row likely duplicates
+    duplicate_of: Option<i32>,
+    /// The timestamp when the row was imported
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = import_pending)]
+struct NewPendingImport<'a> {
+    account_id: i32,
+    amount: &'a BigDecimal,
+    description: &'a str,
+    occurred_at: NaiveDate,
+    duplicate_of: Option<i32>,
+}
+
+impl PendingImport {
+    /// Hold back imported rows for review
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the rows were imported into
+    /// * `rows` - The rows, each with the ID of the transaction it likely duplicates
+    ///
+    /// # Returns
+    ///
+    /// The number of rows held back
+    pub fn insert_all(
+        conn: &mut DbConn,
+        account: &Account,
+        rows: &[(TransactionInput, i32)],
+    ) -> Result<usize, AppError> {
+        let new_rows: Vec<NewPendingImport> = rows
+            .iter()
+            .map(|(input, duplicate_of)| NewPendingImport {
+                account_id: account.id(),
+                amount: &input.amount,
+                description: &input.description,
+                occurred_at: input.occurred_at,
+                duplicate_of: Some(*duplicate_of),
+            })
+            .collect();
+
+        diesel::insert_into(import_pending::table)
+            .values(&new_rows)
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed holding back imported rows on account {} ({e})",
+                    account.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get all rows of an account awaiting review
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the rows were imported into
+    ///
+    /// # Returns
+    ///
+    /// A vector of pending rows, oldest first
+    pub fn get_all(conn: &mut DbConn, account: &Account) -> Result<Vec<Self>, AppError> {
+        import_pending::table
+            .filter(import_pending::account_id.eq(account.id()))
+            .order(import_pending::id)
+            .load::<PendingImport>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting pending imports of account {} ({e})",
+                    account.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a pending row by ID, scoped to an account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Pending import ID
+    /// * `account` - The account the row was imported into
+    ///
+    /// # Returns
+    ///
+    /// The pending row, or `AppError::NotFound` if it doesn't exist on the account
+    pub fn from_id(conn: &mut DbConn, id: i32, account: &Account) -> Result<Self, AppError> {
+        import_pending::table
+            .filter(import_pending::id.eq(id))
+            .filter(import_pending::account_id.eq(account.id()))
+            .first::<PendingImport>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting pending import {id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Confirm that the row is not a duplicate and insert it as a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the row was imported into
+    /// * `rules` - The payee rules of the owner of the account
+    /// * `quotas` - The quotas of the server
+    ///
+    /// # Returns
+    ///
+    /// The newly created transaction, or `AppError::QuotaExceeded` if the user has as many
+    /// transactions as they can
+    pub fn confirm(
+        self,
+        conn: &mut DbConn,
+        account: &Account,
+        rules: &PayeeRules,
+        quotas: &Quotas,
+    ) -> Result<Transaction, AppError> {
+        conn.transaction(|conn| {
+            let mut input =
+                TransactionInput::new(self.amount.clone(), &self.description, self.occurred_at);
+            rules.apply(&mut input);
+            let transaction = Transaction::new(conn, account, &input, quotas)?;
+            self.discard(conn)?;
+            Ok(transaction)
+        })
+    }
+
+    /// Discard the row without inserting it
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn discard(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::delete(import_pending::table.filter(import_pending::id.eq(self.id)))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::error!("Failed discarding pending import {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{accounts::AccountKind, users::User},
+    };
+
+    #[test]
+    fn test_confirm_and_discard() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let amount = BigDecimal::from_str("-12.00").unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 6, 10).unwrap();
+        let input = TransactionInput::new(amount.clone(), "NETFLIX.COM", date);
+        let original = Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+
+        let input = TransactionInput::new(amount, "NETFLIX COM", date);
+        let rows = vec![(input.clone(), original.id()), (input, original.id())];
+        assert_eq!(PendingImport::insert_all(conn, &account, &rows).unwrap(), 2);
+
+        let pending = PendingImport::get_all(conn, &account).unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].duplicate_of, Some(original.id()));
+
+        // Confirming inserts the row as a transaction
+        let rules = PayeeRules::load(conn, user.id()).unwrap();
+        let confirmed = pending[0]
+            .clone()
+            .confirm(conn, &account, &rules, &Quotas::disabled())
+            .unwrap();
+        assert_eq!(confirmed.description(), "NETFLIX COM");
+        assert_eq!(Transaction::get_all(conn, &account).unwrap().len(), 2);
+
+        // Discarding drops the row
+        pending[1].discard(conn).unwrap();
+        assert_eq!(Transaction::get_all(conn, &account).unwrap().len(), 2);
+        assert!(PendingImport::get_all(conn, &account).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_from_id_is_scoped_to_account() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let other =
+            Account::new(conn, user.id(), "Savings", &zero, "CAD", AccountKind::Asset).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 6, 10).unwrap();
+        let input = TransactionInput::new(zero, "Fee", date);
+        let original = Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+
+        PendingImport::insert_all(conn, &account, &[(input, original.id())]).unwrap();
+        let pending = &PendingImport::get_all(conn, &account).unwrap()[0];
+
+        assert!(PendingImport::from_id(conn, pending.id, &account).is_ok());
+        assert!(matches!(
+            PendingImport::from_id(conn, pending.id, &other),
+            Err(AppError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,571 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
+use serde::Serialize;
+
+use crate::database::{connection::DbConn, schema::jobs};
+use crate::errors::AppError;
+
+/// What a job does, which tells how its payload is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Post a signed event to a webhook
+    WebhookDelivery,
+    /// Insert the rows of an uploaded file in batches
+    Import,
+    /// Send the users the digest of the past week, then queue the next week's
+    WeeklyDigest,
+    /// Remove the attachment files no attachment points to and flag the attachments whose file is
+    /// gone, then queue the next day's
+    AttachmentCleanup,
+    /// Push a notification to the channel of its user
+    NotificationPush,
+}
+
+impl JobKind {
+    /// Get the name of the kind, as stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::WebhookDelivery => "webhook_delivery",
+            JobKind::Import => "import",
+            JobKind::WeeklyDigest => "weekly_digest",
+            JobKind::AttachmentCleanup => "attachment_cleanup",
+            JobKind::NotificationPush => "notification_push",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for JobKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for JobKind {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "webhook_delivery" => Ok(JobKind::WebhookDelivery),
+            "import" => Ok(JobKind::Import),
+            "weekly_digest" => Ok(JobKind::WeeklyDigest),
+            "attachment_cleanup" => Ok(JobKind::AttachmentCleanup),
+            "notification_push" => Ok(JobKind::NotificationPush),
+            other => Err(format!("Unknown job kind \"{other}\"").into()),
+        }
+    }
+}
+
+/// Work done in the background, persisted so it survives restarts and is shared by the workers
+/// of every server
+///
+/// A job is pending until a worker claims it, and stays locked by that worker while it runs. A
+/// failed job is retried later with exponential backoff, until it runs out of attempts and is
+/// kept as failed, never to run again.
+#[derive(Debug, Clone, Queryable)]
+#[diesel(table_name = jobs)]
+pub struct Job {
+    id: i32,
+    kind: JobKind,
+    payload: serde_json::Value,
+    run_at: NaiveDateTime,
+    attempts: i32,
+    locked_by: Option<String>,
+    locked_at: Option<NaiveDateTime>,
+    completed_at: Option<NaiveDateTime>,
+    failed_at: Option<NaiveDateTime>,
+    last_error: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+impl Job {
+    /// Queue a job
+    ///
+    /// Queued in the transaction of the change it is about, the job only runs if that change is
+    /// committed.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `kind` - What the job does
+    /// * `payload` - What the job needs to run, read according to its kind
+    /// * `run_at` - The job isn't run before then
+    ///
+    /// # Returns
+    ///
+    /// The queued job
+    pub fn enqueue(
+        conn: &mut DbConn,
+        kind: JobKind,
+        payload: serde_json::Value,
+        run_at: NaiveDateTime,
+    ) -> Result<Self, AppError> {
+        diesel::insert_into(jobs::table)
+            .values((
+                jobs::kind.eq(kind),
+                jobs::payload.eq(payload),
+                jobs::run_at.eq(run_at),
+            ))
+            .get_result::<Job>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed queueing {} job ({e})", kind.as_str());
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Check if a job is queued and hasn't finished yet
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `kind` - What the job does
+    /// * `payload` - What the job needs to run
+    ///
+    /// # Returns
+    ///
+    /// Whether a job of the kind with the payload is pending or running
+    pub fn has_pending(
+        conn: &mut DbConn,
+        kind: JobKind,
+        payload: &serde_json::Value,
+    ) -> Result<bool, AppError> {
+        diesel::select(diesel::dsl::exists(
+            jobs::table
+                .filter(jobs::kind.eq(kind))
+                .filter(jobs::payload.eq(payload))
+                .filter(jobs::completed_at.is_null())
+                .filter(jobs::failed_at.is_null()),
+        ))
+        .get_result::<bool>(conn)
+        .map_err(|e| {
+            tracing::error!("Failed looking for pending {} jobs ({e})", kind.as_str());
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Claim the next job that is due, for a worker to run it
+    ///
+    /// Rows being claimed by other workers are skipped rather than waited for, so concurrent
+    /// workers, of this server or others, never claim the same job. A job locked for longer than
+    /// the lock timeout is assumed to belong to a worker that stopped, and is claimed again.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `worker` - Name of the worker claiming the job
+    /// * `now` - The current time
+    /// * `lock_timeout` - How long a job can stay locked by a worker
+    ///
+    /// # Returns
+    ///
+    /// The claimed job with its attempt counted, if one was due
+    pub fn claim(
+        conn: &mut DbConn,
+        worker: &str,
+        now: NaiveDateTime,
+        lock_timeout: Duration,
+    ) -> Result<Option<Self>, AppError> {
+        let stale = now - chrono::Duration::milliseconds(lock_timeout.as_millis() as i64);
+
+        conn.transaction(|conn| {
+            let Some(id) = jobs::table
+                .select(jobs::id)
+                .filter(jobs::completed_at.is_null())
+                .filter(jobs::failed_at.is_null())
+                .filter(jobs::run_at.le(now))
+                .filter(jobs::locked_at.is_null().or(jobs::locked_at.lt(stale)))
+                .order((jobs::run_at, jobs::id))
+                .limit(1)
+                .for_update()
+                .skip_locked()
+                .get_result::<i32>(conn)
+                .optional()?
+            else {
+                return Ok(None);
+            };
+
+            diesel::update(jobs::table.find(id))
+                .set((
+                    jobs::locked_by.eq(worker),
+                    jobs::locked_at.eq(now),
+                    jobs::attempts.eq(jobs::attempts + 1),
+                ))
+                .get_result::<Job>(conn)
+                .map(Some)
+        })
+        .map_err(|e| {
+            tracing::error!("Worker {worker} failed claiming a job ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Keep the lock of a job that runs for long, so it isn't claimed again while it runs
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// `AppError::Conflict` if another worker claimed the job since, which then runs it
+    pub fn keep_locked(&self, conn: &mut DbConn, now: NaiveDateTime) -> Result<(), AppError> {
+        let updated = diesel::update(jobs::table.find(self.id))
+            .filter(jobs::locked_by.eq(self.locked_by.as_deref()))
+            .set(jobs::locked_at.eq(now))
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!("Failed keeping the lock of job {} ({e})", self.id);
+                AppError::Diesel(e)
+            })?;
+
+        match updated {
+            0 => Err(AppError::Conflict(format!(
+                "Job {} was claimed by another worker",
+                self.id
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Mark the job as done
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The completed job
+    pub fn complete(&self, conn: &mut DbConn, now: NaiveDateTime) -> Result<Self, AppError> {
+        diesel::update(jobs::table.find(self.id))
+            .set((
+                jobs::completed_at.eq(now),
+                jobs::locked_by.eq(None::<String>),
+                jobs::locked_at.eq(None::<NaiveDateTime>),
+            ))
+            .get_result::<Job>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed completing job {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Record that the attempt failed, and schedule the next one or give up on the job
+    ///
+    /// The next attempt is scheduled `retry_delay` later, doubled for each attempt already made.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `error` - Why the attempt failed
+    /// * `now` - The current time
+    /// * `retry_delay` - How long to wait before the first retry
+    /// * `max_attempts` - Number of attempts after which the job is failed
+    ///
+    /// # Returns
+    ///
+    /// The job, failed if it ran out of attempts
+    pub fn fail(
+        &self,
+        conn: &mut DbConn,
+        error: &str,
+        now: NaiveDateTime,
+        retry_delay: Duration,
+        max_attempts: i32,
+    ) -> Result<Self, AppError> {
+        let failed = self.attempts >= max_attempts;
+        let run_at = if failed {
+            self.run_at
+        } else {
+            let backoff = retry_delay * 2u32.saturating_pow((self.attempts - 1).max(0) as u32);
+            now + chrono::Duration::milliseconds(backoff.as_millis() as i64)
+        };
+
+        diesel::update(jobs::table.find(self.id))
+            .set((
+                jobs::run_at.eq(run_at),
+                jobs::failed_at.eq(failed.then_some(now)),
+                jobs::last_error.eq(error),
+                jobs::locked_by.eq(None::<String>),
+                jobs::locked_at.eq(None::<NaiveDateTime>),
+            ))
+            .get_result::<Job>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed recording the failure of job {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the job ID
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get what the job does
+    pub fn kind(&self) -> JobKind {
+        self.kind
+    }
+
+    /// Get what the job needs to run
+    pub fn payload(&self) -> &serde_json::Value {
+        &self.payload
+    }
+
+    /// Get when the job is due
+    pub fn run_at(&self) -> NaiveDateTime {
+        self.run_at
+    }
+
+    /// Get the number of times the job was claimed
+    pub fn attempts(&self) -> i32 {
+        self.attempts
+    }
+
+    /// Get the name of the worker running the job, if one is
+    pub fn locked_by(&self) -> Option<&str> {
+        self.locked_by.as_deref()
+    }
+
+    /// Get the timestamp when the running worker claimed the job, if one is
+    pub fn locked_at(&self) -> Option<NaiveDateTime> {
+        self.locked_at
+    }
+
+    /// Check if the job is done
+    pub fn is_completed(&self) -> bool {
+        self.completed_at.is_some()
+    }
+
+    /// Check if the job ran out of attempts
+    pub fn is_failed(&self) -> bool {
+        self.failed_at.is_some()
+    }
+
+    /// Get why the last attempt failed, if it did
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    /// Get the timestamp when the job was queued
+    pub fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::database::connection::DbPool;
+
+    const LOCK_TIMEOUT: Duration = Duration::from_secs(300);
+
+    fn at(hour: u32, minute: u32, second: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 7, 1)
+            .unwrap()
+            .and_hms_opt(hour, minute, second)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_jobs_are_retried_then_failed() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let job = Job::enqueue(
+            conn,
+            JobKind::WebhookDelivery,
+            serde_json::json!({"webhook_id": 1}),
+            at(12, 0, 0),
+        )
+        .unwrap();
+        assert_eq!(job.attempts(), 0);
+
+        // Jobs aren't claimed before they're due
+        assert!(Job::claim(conn, "a", at(11, 59, 59), LOCK_TIMEOUT)
+            .unwrap()
+            .is_none());
+
+        let claimed = Job::claim(conn, "a", at(12, 0, 0), LOCK_TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(claimed.id(), job.id());
+        assert_eq!((claimed.attempts(), claimed.locked_by()), (1, Some("a")));
+        // A locked job isn't claimed by another worker until its lock times out
+        assert!(Job::claim(conn, "b", at(12, 4, 59), LOCK_TIMEOUT)
+            .unwrap()
+            .is_none());
+
+        // Retries are 10 then 20 seconds later
+        let retry_delay = Duration::from_secs(10);
+        let retried = claimed
+            .fail(conn, "refused", at(12, 0, 1), retry_delay, 3)
+            .unwrap();
+        assert_eq!(retried.run_at(), at(12, 0, 11));
+        assert_eq!(retried.locked_by(), None);
+        assert_eq!(retried.last_error(), Some("refused"));
+        assert!(!retried.is_failed());
+
+        let claimed = Job::claim(conn, "b", at(12, 0, 11), LOCK_TIMEOUT)
+            .unwrap()
+            .unwrap();
+        let retried = claimed
+            .fail(conn, "refused", at(12, 0, 11), retry_delay, 3)
+            .unwrap();
+        assert_eq!(retried.run_at(), at(12, 0, 31));
+
+        // The last attempt fails the job for good
+        let claimed = Job::claim(conn, "a", at(12, 1, 0), LOCK_TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(claimed.attempts(), 3);
+        let failed = claimed
+            .fail(conn, "timed out", at(12, 1, 0), retry_delay, 3)
+            .unwrap();
+        assert!(failed.is_failed());
+        assert_eq!(failed.last_error(), Some("timed out"));
+        assert!(Job::claim(conn, "a", at(23, 0, 0), LOCK_TIMEOUT)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_completed_and_abandoned_jobs() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let first = Job::enqueue(
+            conn,
+            JobKind::WebhookDelivery,
+            serde_json::json!({}),
+            at(9, 0, 0),
+        )
+        .unwrap();
+        let second = Job::enqueue(
+            conn,
+            JobKind::WebhookDelivery,
+            serde_json::json!({}),
+            at(10, 0, 0),
+        )
+        .unwrap();
+
+        // Jobs are claimed in the order they're due
+        let claimed = Job::claim(conn, "a", at(10, 0, 0), LOCK_TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(claimed.id(), first.id());
+        assert!(claimed.complete(conn, at(10, 0, 1)).unwrap().is_completed());
+
+        // The worker running the second job stops, another claims it once the lock times out
+        let abandoned = Job::claim(conn, "a", at(10, 0, 0), LOCK_TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(abandoned.id(), second.id());
+        assert!(Job::claim(conn, "b", at(10, 4, 59), LOCK_TIMEOUT)
+            .unwrap()
+            .is_none());
+        let reclaimed = Job::claim(conn, "b", at(10, 5, 1), LOCK_TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(reclaimed.id(), second.id());
+        assert_eq!(
+            (reclaimed.attempts(), reclaimed.locked_by()),
+            (2, Some("b"))
+        );
+
+        // Completed jobs aren't claimed again
+        reclaimed.complete(conn, at(10, 5, 2)).unwrap();
+        assert!(Job::claim(conn, "a", at(23, 0, 0), LOCK_TIMEOUT)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_concurrent_workers_claim_each_job_once() {
+        // The jobs are committed, so workers on other connections see them. They're due far in
+        // the future, so only the workers of this test claim them.
+        let pool = Arc::new(DbPool::new_test());
+        let due = NaiveDate::from_ymd_opt(2100, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let clean_up = |conn: &mut DbConn| {
+            diesel::delete(jobs::table.filter(jobs::run_at.ge(due)))
+                .execute(conn)
+                .unwrap();
+        };
+        clean_up(&mut pool.get().unwrap());
+        let ids: HashSet<i32> = (0..20)
+            .map(|_| {
+                Job::enqueue(
+                    &mut pool.get().unwrap(),
+                    JobKind::WebhookDelivery,
+                    serde_json::json!({}),
+                    due,
+                )
+                .unwrap()
+                .id()
+            })
+            .collect();
+
+        // A job being claimed is skipped by the other workers rather than waited for
+        {
+            let mut first = pool.get().unwrap();
+            let mut second = pool.get().unwrap();
+            first
+                .transaction(|first| {
+                    let claimed = Job::claim(first, "a", due, LOCK_TIMEOUT)?.unwrap();
+                    let other = Job::claim(&mut second, "b", due, LOCK_TIMEOUT)?.unwrap();
+                    assert_ne!(claimed.id(), other.id());
+                    other.complete(&mut second, due)?;
+                    // The claim of the first job is rolled back, so it is claimed again below
+                    Err::<(), _>(AppError::Conflict("rolled back".to_string()))
+                })
+                .unwrap_err();
+        }
+
+        let workers: Vec<_> = ["a", "b", "c", "d"]
+            .into_iter()
+            .map(|worker| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    let mut conn = pool.get().unwrap();
+                    let mut claimed = vec![];
+                    while let Some(job) = Job::claim(&mut conn, worker, due, LOCK_TIMEOUT).unwrap()
+                    {
+                        job.complete(&mut conn, due).unwrap();
+                        claimed.push(job.id());
+                    }
+                    claimed
+                })
+            })
+            .collect();
+        let claimed: Vec<i32> = workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect();
+        clean_up(&mut pool.get().unwrap());
+
+        // Every job but the one completed above was claimed exactly once
+        assert_eq!(claimed.len(), ids.len() - 1);
+        let claimed: HashSet<i32> = claimed.into_iter().collect();
+        assert_eq!(claimed.len(), ids.len() - 1);
+        assert!(claimed.is_subset(&ids));
+    }
+}
@@ -1,3 +1,25 @@
+pub mod accounts;
+pub mod attachments;
+pub mod audit_events;
+pub mod budgets;
+pub mod categories;
+pub mod category_alerts;
+pub mod exchange_rates;
+pub mod goals;
+pub mod import_jobs;
+pub mod import_pending;
+pub mod jobs;
+pub mod notification_channels;
+pub mod notifications;
+pub mod payee_rules;
+pub mod plan_notes;
 pub mod plans;
+pub mod reconciliations;
+pub mod recurring_transactions;
+pub mod scheduled_reports;
 pub mod sessions;
+pub mod tags;
+pub mod transactions;
+pub mod transfers;
 pub mod users;
+pub mod webhooks;
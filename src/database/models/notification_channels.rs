@@ -0,0 +1,179 @@
+use axum::http::HeaderValue;
+use diesel::prelude::*;
+use serde::{Serialize, Serializer};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn, models::webhooks::validate_url, schema::notification_channels,
+};
+use crate::errors::AppError;
+
+/// Maximum number of characters in the `Authorization` header of pushes
+pub const MAX_AUTH_HEADER_LENGTH: usize = 1024;
+
+/// Where the notifications of a user are pushed besides the app
+///
+/// The notifications are posted as JSON to the URL of the channel, with its `Authorization`
+/// header if it has one. The header is never returned, only whether the channel has one.
+#[derive(Debug, Serialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = notification_channels)]
+pub struct ChannelConfig {
+    /// ID of the user the notifications are for
+    user_id: i32,
+    /// The URL notifications are posted to
+    url: String,
+    /// Whether pushes are sent with an `Authorization` header
+    #[serde(rename = "has_auth_header", serialize_with = "is_some")]
+    #[schema(value_type = bool)]
+    auth_header: Option<String>,
+    /// The timestamp when the channel was set
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+    /// The timestamp when the channel was last changed
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    updated_at: chrono::NaiveDateTime,
+}
+
+fn is_some<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(value.is_some())
+}
+
+/// Fields of a notification channel
+#[derive(Debug, Clone)]
+pub struct ChannelInput {
+    /// The URL notifications are posted to
+    pub url: String,
+    /// The `Authorization` header of pushes, if any
+    pub auth_header: Option<String>,
+}
+
+impl ChannelInput {
+    /// Validate the input
+    ///
+    /// # Arguments
+    ///
+    /// * `allow_insecure` - Whether plain HTTP and private network addresses are allowed, for
+    ///   development
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the input is valid, otherwise `AppError::InvalidInput`
+    pub fn validate(&self, allow_insecure: bool) -> Result<(), AppError> {
+        validate_url(&self.url, allow_insecure)?;
+        if let Some(header) = &self.auth_header {
+            if header.is_empty() || header.chars().count() > MAX_AUTH_HEADER_LENGTH {
+                return Err(AppError::InvalidInput(format!(
+                    "The authorization header of a notification channel must be between 1 and \
+                     {MAX_AUTH_HEADER_LENGTH} characters long"
+                )));
+            }
+            if HeaderValue::from_str(header).is_err() {
+                return Err(AppError::InvalidInput(
+                    "The authorization header of a notification channel can only contain \
+                     visible ASCII characters"
+                        .to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChannelConfig {
+    /// Set the notification channel of a user, replacing the one they had
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `input` - The fields of the channel
+    /// * `allow_insecure` - Whether plain HTTP and private network addresses are allowed
+    ///
+    /// # Returns
+    ///
+    /// The channel, or `AppError::InvalidInput` if the input is invalid
+    pub fn set(
+        conn: &mut DbConn,
+        user_id: i32,
+        input: &ChannelInput,
+        allow_insecure: bool,
+    ) -> Result<Self, AppError> {
+        input.validate(allow_insecure)?;
+
+        diesel::insert_into(notification_channels::table)
+            .values((
+                notification_channels::user_id.eq(user_id),
+                notification_channels::url.eq(&input.url),
+                notification_channels::auth_header.eq(&input.auth_header),
+            ))
+            .on_conflict(notification_channels::user_id)
+            .do_update()
+            .set((
+                notification_channels::url.eq(&input.url),
+                notification_channels::auth_header.eq(&input.auth_header),
+                notification_channels::updated_at.eq(diesel::dsl::now),
+            ))
+            .get_result::<ChannelConfig>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed setting the notification channel of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the notification channel of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The channel, if the user set one
+    pub fn of_user(conn: &mut DbConn, user_id: i32) -> Result<Option<Self>, AppError> {
+        notification_channels::table
+            .filter(notification_channels::user_id.eq(user_id))
+            .first::<ChannelConfig>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting the notification channel of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Delete the channel, notifications are only shown in the app again
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::delete(
+            notification_channels::table.filter(notification_channels::user_id.eq(self.user_id)),
+        )
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|e| {
+            tracing::error!(
+                "Failed deleting the notification channel of user {} ({e})",
+                self.user_id
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the URL notifications are posted to
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Get the `Authorization` header of pushes, if any
+    pub fn auth_header(&self) -> Option<&str> {
+        self.auth_header.as_deref()
+    }
+}
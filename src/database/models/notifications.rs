@@ -0,0 +1,257 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{connection::DbConn, schema::notifications};
+use crate::errors::AppError;
+
+/// Status of a notification that wasn't read yet
+const UNREAD: &str = "unread";
+
+/// Status of a notification that was read
+const READ: &str = "read";
+
+/// Kind of the notifications sent to test a channel
+pub const TEST_NOTIFICATION_TYPE: &str = "test";
+
+/// A notification shown to a user
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = notifications)]
+pub struct Notification {
+    /// Notification ID
+    id: i32,
+    /// What the notification is about, such as `category_alert`
+    #[serde(rename = "type")]
+    type_: String,
+    /// Name of the plan the notification is about, if any
+    plan_name: Option<String>,
+    /// Title of the notification
+    title: String,
+    /// Text of the notification
+    body: String,
+    /// The timestamp when the notification was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+    /// Either `unread` or `read`
+    status: String,
+    /// ID of the user the notification is for
+    user_id: i32,
+    /// Details of what the notification is about
+    #[schema(value_type = Object)]
+    data: serde_json::Value,
+}
+
+/// A notification to be created
+#[derive(Debug, Insertable)]
+#[diesel(table_name = notifications)]
+pub struct NewNotification {
+    user_id: i32,
+    type_: String,
+    title: String,
+    body: String,
+    data: serde_json::Value,
+}
+
+impl NewNotification {
+    /// Create a notification for a user
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user the notification is for
+    /// * `kind` - What the notification is about
+    /// * `title` - Title of the notification
+    /// * `body` - Text of the notification
+    /// * `data` - Details of what the notification is about
+    pub fn new(
+        user_id: i32,
+        kind: &str,
+        title: String,
+        body: String,
+        data: serde_json::Value,
+    ) -> Self {
+        Self {
+            user_id,
+            type_: kind.to_string(),
+            title,
+            body,
+            data,
+        }
+    }
+}
+
+impl Notification {
+    /// Insert a notification, and queue its push to the channel of its user if they set one
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `notification` - The notification
+    ///
+    /// # Returns
+    ///
+    /// The newly created notification
+    pub fn new(conn: &mut DbConn, notification: &NewNotification) -> Result<Self, AppError> {
+        let notification = diesel::insert_into(notifications::table)
+            .values(notification)
+            .get_result::<Notification>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed creating {} notification for user {} ({e})",
+                    notification.type_,
+                    notification.user_id
+                );
+                AppError::Diesel(e)
+            })?;
+        crate::jobs::notifications::enqueue(conn, &notification);
+        Ok(notification)
+    }
+
+    /// Get the notifications of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `unread` - Whether to only get the notifications that weren't read yet
+    /// * `kind` - What the notifications must be about, if anything in particular
+    ///
+    /// # Returns
+    ///
+    /// A vector of notifications, most recent first
+    pub fn get_all(
+        conn: &mut DbConn,
+        user_id: i32,
+        unread: bool,
+        kind: Option<&str>,
+    ) -> Result<Vec<Self>, AppError> {
+        let mut query = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .order((notifications::created_at.desc(), notifications::id.desc()))
+            .into_boxed();
+        if unread {
+            query = query.filter(notifications::status.eq(UNREAD));
+        }
+        if let Some(kind) = kind {
+            query = query.filter(notifications::type_.eq(kind));
+        }
+
+        query.load::<Notification>(conn).map_err(|e| {
+            tracing::error!("Failed getting notifications of user {user_id} ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the most recent notification of a kind for a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `kind` - What the notification is about
+    ///
+    /// # Returns
+    ///
+    /// The notification, if the user has one of the kind
+    pub fn latest_of_kind(
+        conn: &mut DbConn,
+        user_id: i32,
+        kind: &str,
+    ) -> Result<Option<Self>, AppError> {
+        notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::type_.eq(kind))
+            .order((notifications::created_at.desc(), notifications::id.desc()))
+            .first::<Notification>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting the latest {kind} notification of user {user_id} ({e})"
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a notification by ID, scoped to the user it is for
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Notification ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The notification, or `AppError::NotFound` if it doesn't exist or is for another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        notifications::table
+            .filter(notifications::id.eq(id))
+            .filter(notifications::user_id.eq(user_id))
+            .first::<Notification>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting notification {id} for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Mark the notification as read
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// The updated notification
+    pub fn mark_read(&self, conn: &mut DbConn) -> Result<Self, AppError> {
+        diesel::update(notifications::table.filter(notifications::id.eq(self.id)))
+            .set(notifications::status.eq(READ))
+            .get_result::<Notification>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed marking notification {} as read ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Make a notification to test a channel with, which isn't stored
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user the notification is for
+    /// * `now` - The current time, in UTC
+    pub fn test(user_id: i32, now: chrono::NaiveDateTime) -> Self {
+        Self {
+            id: 0,
+            user_id,
+            type_: TEST_NOTIFICATION_TYPE.to_string(),
+            plan_name: None,
+            title: "Test notification".to_string(),
+            body: "Notifications are pushed to this channel".to_string(),
+            data: serde_json::json!({}),
+            created_at: now,
+            status: UNREAD.to_string(),
+        }
+    }
+
+    /// Get the notification ID
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the ID of the user the notification is for
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// Get what the notification is about
+    pub fn kind(&self) -> &str {
+        &self.type_
+    }
+
+    /// Get the details of what the notification is about
+    pub fn data(&self) -> &serde_json::Value {
+        &self.data
+    }
+}
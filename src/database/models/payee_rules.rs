@@ -0,0 +1,641 @@
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{categories::Category, transactions::TransactionInput},
+    schema::{accounts, payee_rules, transactions},
+};
+use crate::errors::AppError;
+
+/// Maximum length of the pattern of a rule, in characters
+pub const MAX_PATTERN_LENGTH: usize = 200;
+
+/// Maximum size of a compiled regex pattern, in bytes
+const REGEX_SIZE_LIMIT: usize = 1 << 16;
+
+/// How the pattern of a rule is matched against the description of a transaction
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchKind {
+    /// The description contains the pattern, regardless of case
+    Contains,
+    /// The description starts with the pattern, regardless of case
+    Prefix,
+    /// The description matches the pattern as a case-insensitive regular expression
+    Regex,
+}
+
+impl MatchKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MatchKind::Contains => "contains",
+            MatchKind::Prefix => "prefix",
+            MatchKind::Regex => "regex",
+        }
+    }
+
+    /// Rank of the kind when rules are equally specific, higher wins
+    fn rank(&self) -> u8 {
+        match self {
+            MatchKind::Prefix => 2,
+            MatchKind::Contains => 1,
+            MatchKind::Regex => 0,
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for MatchKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for MatchKind {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "contains" => Ok(MatchKind::Contains),
+            "prefix" => Ok(MatchKind::Prefix),
+            "regex" => Ok(MatchKind::Regex),
+            other => Err(format!("Unknown match kind \"{other}\"").into()),
+        }
+    }
+}
+
+/// A rule that turns bank descriptions like `AMZN Mktp CA*2J4` into a payee like `Amazon`
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = payee_rules)]
+pub struct PayeeRule {
+    /// Rule ID
+    id: i32,
+    /// ID of the user that owns the rule
+    user_id: i32,
+    /// The pattern matched against descriptions
+    pattern: String,
+    /// How the pattern is matched
+    match_kind: MatchKind,
+    /// The payee of matching transactions
+    normalized_payee: String,
+    /// ID of the category of matching transactions that weren't given one, if any
+    default_category_id: Option<i32>,
+    /// The timestamp when the rule was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+}
+
+/// Fields of a rule to be created or updated
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = payee_rules, treat_none_as_null = true)]
+pub struct PayeeRuleInput {
+    /// The pattern matched against descriptions
+    pub pattern: String,
+    /// How the pattern is matched
+    pub match_kind: MatchKind,
+    /// The payee of matching transactions
+    pub normalized_payee: String,
+    /// ID of the category of matching transactions, must belong to the owner of the rule
+    pub default_category_id: Option<i32>,
+}
+
+impl PayeeRuleInput {
+    /// Validate the input
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the input is valid, otherwise `AppError::InvalidInput`
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.pattern.trim().is_empty() {
+            return Err(AppError::InvalidInput(
+                "The pattern of a rule must not be empty".to_string(),
+            ));
+        }
+        if self.pattern.chars().count() > MAX_PATTERN_LENGTH {
+            return Err(AppError::InvalidInput(format!(
+                "The pattern of a rule must not be longer than {MAX_PATTERN_LENGTH} characters"
+            )));
+        }
+        if self.normalized_payee.trim().is_empty() {
+            return Err(AppError::InvalidInput(
+                "The payee of a rule must not be empty".to_string(),
+            ));
+        }
+        if self.match_kind == MatchKind::Regex {
+            compile(&self.pattern)
+                .map_err(|e| AppError::InvalidInput(format!("Invalid regex pattern: {e}")))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = payee_rules)]
+struct NewPayeeRule<'a> {
+    user_id: i32,
+    pattern: &'a str,
+    match_kind: MatchKind,
+    normalized_payee: &'a str,
+    default_category_id: Option<i32>,
+}
+
+/// Compile a regex pattern, refusing patterns that compile to more than `REGEX_SIZE_LIMIT` bytes
+fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+impl PayeeRule {
+    /// Create a new rule for a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `input` - The fields of the rule, validated by the caller
+    ///
+    /// # Returns
+    ///
+    /// The newly created rule
+    pub fn new(conn: &mut DbConn, user_id: i32, input: &PayeeRuleInput) -> Result<Self, AppError> {
+        diesel::insert_into(payee_rules::table)
+            .values(&NewPayeeRule {
+                user_id,
+                pattern: &input.pattern,
+                match_kind: input.match_kind,
+                normalized_payee: input.normalized_payee.trim(),
+                default_category_id: input.default_category_id,
+            })
+            .get_result::<PayeeRule>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed creating payee rule for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a rule by ID, scoped to the user that owns it
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Rule ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The rule, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        payee_rules::table
+            .filter(payee_rules::id.eq(id))
+            .filter(payee_rules::user_id.eq(user_id))
+            .first::<PayeeRule>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting payee rule {id} for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get all rules of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// A vector of rules, oldest first
+    pub fn get_all(conn: &mut DbConn, user_id: i32) -> Result<Vec<Self>, AppError> {
+        payee_rules::table
+            .filter(payee_rules::user_id.eq(user_id))
+            .order(payee_rules::id)
+            .load::<PayeeRule>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting payee rules for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Update the rule
+    ///
+    /// Transactions the rule was already applied to are not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `input` - The new fields of the rule, validated by the caller
+    ///
+    /// # Returns
+    ///
+    /// The updated rule
+    pub fn update(&self, conn: &mut DbConn, input: &PayeeRuleInput) -> Result<Self, AppError> {
+        diesel::update(payee_rules::table.filter(payee_rules::id.eq(self.id)))
+            .set(input)
+            .get_result::<PayeeRule>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed updating payee rule {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Delete the rule
+    ///
+    /// Transactions the rule was already applied to keep their payee and category.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::delete(payee_rules::table.filter(payee_rules::id.eq(self.id)))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::error!("Failed deleting payee rule {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Give the rules defaulting to a category another category instead
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `from` - The category the rules default to
+    /// * `into` - The category to default to instead
+    ///
+    /// # Returns
+    ///
+    /// The number of rules changed
+    pub fn move_category(
+        conn: &mut DbConn,
+        from: &Category,
+        into: &Category,
+    ) -> Result<u64, AppError> {
+        diesel::update(payee_rules::table.filter(payee_rules::default_category_id.eq(from.id())))
+            .set(payee_rules::default_category_id.eq(into.id()))
+            .execute(conn)
+            .map(|count| count as u64)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed moving payee rules from category {} to {} ({e})",
+                    from.id(),
+                    into.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Apply the rule to the existing uncategorized transactions of its owner
+    ///
+    /// Matching transactions get the payee of the rule and its default category, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `backfill` - Whether to update the matching transactions, or only count them
+    ///
+    /// # Returns
+    ///
+    /// The number of matching transactions
+    pub fn apply(&self, conn: &mut DbConn, backfill: bool) -> Result<usize, AppError> {
+        let matcher = Matcher::new(self.clone()).map_err(|e| {
+            tracing::error!("Failed compiling payee rule {} ({e})", self.id);
+            AppError::InvalidInput(format!("Invalid regex pattern: {e}"))
+        })?;
+
+        let uncategorized = transactions::table
+            .inner_join(accounts::table)
+            .filter(accounts::user_id.eq(self.user_id))
+            .filter(transactions::category_id.is_null())
+            .select((transactions::id, transactions::description))
+            .load::<(i32, String)>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting uncategorized transactions for user {} ({e})",
+                    self.user_id
+                );
+                AppError::Diesel(e)
+            })?;
+
+        let ids: Vec<i32> = uncategorized
+            .into_iter()
+            .filter(|(_, description)| matcher.matches(description))
+            .map(|(id, _)| id)
+            .collect();
+        if !backfill {
+            return Ok(ids.len());
+        }
+
+        diesel::update(transactions::table.filter(transactions::id.eq_any(&ids)))
+            .set((
+                transactions::payee.eq(&self.normalized_payee),
+                transactions::category_id.eq(self.default_category_id),
+            ))
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!("Failed applying payee rule {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get how specific the rule is, from the letters, digits and spaces of its pattern
+    fn specificity(&self) -> usize {
+        self.pattern
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == ' ')
+            .count()
+    }
+}
+
+/// A rule with its pattern ready to be matched
+struct Matcher {
+    rule: PayeeRule,
+    /// The lowercased pattern of `contains` and `prefix` rules
+    lowercase: String,
+    /// The compiled pattern of `regex` rules
+    regex: Option<Regex>,
+}
+
+impl Matcher {
+    fn new(rule: PayeeRule) -> Result<Self, regex::Error> {
+        let regex = match rule.match_kind {
+            MatchKind::Regex => Some(compile(&rule.pattern)?),
+            MatchKind::Contains | MatchKind::Prefix => None,
+        };
+        Ok(Self {
+            lowercase: rule.pattern.to_lowercase(),
+            regex,
+            rule,
+        })
+    }
+
+    fn matches(&self, description: &str) -> bool {
+        match (&self.regex, self.rule.match_kind) {
+            (Some(regex), _) => regex.is_match(description),
+            (None, MatchKind::Prefix) => description.to_lowercase().starts_with(&self.lowercase),
+            (None, _) => description.to_lowercase().contains(&self.lowercase),
+        }
+    }
+}
+
+/// The rules of a user, ready to be applied to new transactions
+pub struct PayeeRules {
+    matchers: Vec<Matcher>,
+}
+
+impl PayeeRules {
+    /// Load the rules of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The rules of the user. Rules whose pattern no longer compiles are skipped.
+    pub fn load(conn: &mut DbConn, user_id: i32) -> Result<Self, AppError> {
+        let matchers = PayeeRule::get_all(conn, user_id)?
+            .into_iter()
+            .filter_map(|rule| {
+                let id = rule.id;
+                Matcher::new(rule)
+                    .map_err(|e| tracing::warn!("Skipping payee rule {id} ({e})"))
+                    .ok()
+            })
+            .collect();
+        Ok(Self { matchers })
+    }
+
+    /// Find the most specific rule that matches a description
+    ///
+    /// The rule whose pattern has the most letters, digits and spaces wins. Between equally
+    /// specific rules, prefix rules win over contains rules, which win over regex rules, and then
+    /// the oldest rule wins.
+    pub fn find(&self, description: &str) -> Option<&PayeeRule> {
+        self.matchers
+            .iter()
+            .filter(|matcher| matcher.matches(description))
+            .map(|matcher| &matcher.rule)
+            .max_by_key(|rule| {
+                (
+                    rule.specificity(),
+                    rule.match_kind.rank(),
+                    std::cmp::Reverse(rule.id),
+                )
+            })
+    }
+
+    /// Set the payee of a transaction from the rule that matches its description, and its
+    /// category if it wasn't given one
+    pub fn apply(&self, input: &mut TransactionInput) {
+        let Some(rule) = self.find(&input.description) else {
+            return;
+        };
+
+        input.payee = Some(rule.normalized_payee.clone());
+        if input.category_id.is_none() && input.splits.is_empty() {
+            input.category_id = rule.default_category_id;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::{Account, AccountKind},
+            categories::Category,
+            transactions::Transaction,
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn rule(pattern: &str, match_kind: MatchKind, payee: &str) -> PayeeRuleInput {
+        PayeeRuleInput {
+            pattern: pattern.to_string(),
+            match_kind,
+            normalized_payee: payee.to_string(),
+            default_category_id: None,
+        }
+    }
+
+    fn input(description: &str) -> TransactionInput {
+        TransactionInput::new(
+            BigDecimal::from(-10),
+            description,
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(rule("AMZN", MatchKind::Contains, "Amazon")
+            .validate()
+            .is_ok());
+        assert!(rule("  ", MatchKind::Contains, "Amazon")
+            .validate()
+            .is_err());
+        assert!(rule("AMZN", MatchKind::Prefix, " ").validate().is_err());
+        assert!(rule(&"a".repeat(201), MatchKind::Prefix, "A")
+            .validate()
+            .is_err());
+        assert!(rule("AMZN (Mktp", MatchKind::Regex, "Amazon")
+            .validate()
+            .is_err());
+        // Within the length limit, but compiles to a program over the size limit
+        assert!(rule(r"\w{50}\w{50}\w{50}", MatchKind::Regex, "Words")
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_match_kinds() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let shopping = Category::new(conn, user.id(), "Shopping").unwrap();
+        let mut amazon = rule("amzn mktp", MatchKind::Contains, "Amazon");
+        amazon.default_category_id = Some(shopping.id());
+        PayeeRule::new(conn, user.id(), &amazon).unwrap();
+        PayeeRule::new(conn, user.id(), &rule("SQ *", MatchKind::Prefix, "Square")).unwrap();
+        PayeeRule::new(
+            conn,
+            user.id(),
+            &rule(r"^uber\s*\*?\s*trip", MatchKind::Regex, "Uber"),
+        )
+        .unwrap();
+
+        let rules = PayeeRules::load(conn, user.id()).unwrap();
+        let payee = |description| rules.find(description).map(|r| r.normalized_payee.as_str());
+        assert_eq!(payee("AMZN Mktp CA*2J4"), Some("Amazon"));
+        assert_eq!(payee("sq *COFFEE SHOP"), Some("Square"));
+        assert_eq!(payee("COFFEE SQ *"), None);
+        assert_eq!(payee("UBER *TRIP HELP.UBER.COM"), Some("Uber"));
+        assert_eq!(payee("Paid UBER TRIP"), None);
+
+        // The default category only applies to transactions without one
+        let mut categorized = input("AMZN Mktp CA*2J4");
+        categorized.category_id = Some(i32::MAX);
+        rules.apply(&mut categorized);
+        assert_eq!(categorized.payee.as_deref(), Some("Amazon"));
+        assert_eq!(categorized.category_id, Some(i32::MAX));
+
+        let mut uncategorized = input("AMZN Mktp CA*2J4");
+        rules.apply(&mut uncategorized);
+        assert_eq!(uncategorized.category_id, Some(shopping.id()));
+    }
+
+    #[test]
+    fn test_most_specific_rule_wins() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        for (pattern, kind, payee) in [
+            ("AMZN", MatchKind::Contains, "Amazon"),
+            ("AMZN Prime", MatchKind::Contains, "Prime Video"),
+            (r"amzn\s+prime", MatchKind::Regex, "Prime Regex"),
+            ("AMZN", MatchKind::Prefix, "Amazon Prefix"),
+        ] {
+            PayeeRule::new(conn, user.id(), &rule(pattern, kind, payee)).unwrap();
+        }
+
+        let rules = PayeeRules::load(conn, user.id()).unwrap();
+        let payee = |description| rules.find(description).map(|r| r.normalized_payee.as_str());
+        assert_eq!(payee("AMZN Prime Video"), Some("Prime Video"));
+        assert_eq!(payee("AMZN Mktp"), Some("Amazon Prefix"));
+        assert_eq!(payee("Refund AMZN"), Some("Amazon"));
+    }
+
+    #[test]
+    fn test_backfill() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let shopping = Category::new(conn, user.id(), "Shopping").unwrap();
+        let other = Category::new(conn, user.id(), "Other").unwrap();
+
+        Transaction::new(
+            conn,
+            &account,
+            &input("AMZN Mktp CA*2J4"),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        Transaction::new(
+            conn,
+            &account,
+            &input("amzn mktp ca*9X1"),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        Transaction::new(conn, &account, &input("Grocery store"), &Quotas::disabled()).unwrap();
+        let mut categorized = input("AMZN Mktp CA*7Q2");
+        categorized.category_id = Some(other.id());
+        Transaction::new(conn, &account, &categorized, &Quotas::disabled()).unwrap();
+
+        let mut amazon = rule("AMZN Mktp", MatchKind::Contains, "Amazon");
+        amazon.default_category_id = Some(shopping.id());
+        let rule = PayeeRule::new(conn, user.id(), &amazon).unwrap();
+
+        // Counting doesn't change anything
+        assert_eq!(rule.apply(conn, false).unwrap(), 2);
+        assert_eq!(rule.apply(conn, false).unwrap(), 2);
+
+        assert_eq!(rule.apply(conn, true).unwrap(), 2);
+        let transactions = Transaction::get_all(conn, &account).unwrap();
+        let amazon: Vec<&Transaction> = transactions
+            .iter()
+            .filter(|t| t.payee() == Some("Amazon"))
+            .collect();
+        assert_eq!(amazon.len(), 2);
+        assert!(amazon
+            .iter()
+            .all(|t| t.category_id() == Some(shopping.id())));
+
+        // Backfilled transactions are categorized, so they aren't matched again
+        assert_eq!(rule.apply(conn, true).unwrap(), 0);
+    }
+}
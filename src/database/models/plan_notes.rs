@@ -0,0 +1,327 @@
+use diesel::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::plans::Plan,
+    schema::{plan_notes, plans},
+};
+use crate::errors::AppError;
+
+/// Maximum number of characters in the body of a note
+pub const MAX_NOTE_LENGTH: usize = 10_000;
+
+/// A note written on a plan, such as why a budget changed
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = plan_notes)]
+pub struct PlanNote {
+    /// Note ID
+    id: i32,
+    /// Name of the plan the note is written on
+    plan_name: String,
+    /// ID of the user that wrote the note
+    author_user_id: i32,
+    /// Plain text of the note
+    body: String,
+    /// The timestamp when the note was written
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+    /// The timestamp when the note was last edited
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = plan_notes)]
+struct NewPlanNote<'a> {
+    plan_name: &'a str,
+    author_user_id: i32,
+    body: &'a str,
+}
+
+/// Check that the body of a note is non-empty plain text of at most `MAX_NOTE_LENGTH` characters
+///
+/// # Arguments
+///
+/// * `body` - The body of the note
+///
+/// # Returns
+///
+/// An empty result if the body is valid, otherwise `AppError::InvalidInput`
+pub fn validate_body(body: &str) -> Result<(), AppError> {
+    if body.trim().is_empty() {
+        return Err(AppError::InvalidInput(
+            "The body of a note can't be empty".to_string(),
+        ));
+    }
+    if body.chars().count() > MAX_NOTE_LENGTH {
+        return Err(AppError::InvalidInput(format!(
+            "The body of a note can't be longer than {MAX_NOTE_LENGTH} characters"
+        )));
+    }
+
+    let tag = Regex::new(r"<\s*/?\s*[A-Za-z!][^<>]*>").expect("the HTML tag pattern is valid");
+    if tag.is_match(body) {
+        return Err(AppError::InvalidInput(
+            "Notes are plain text and can't contain HTML tags".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl PlanNote {
+    /// Write a note on a plan, marking the plan as modified
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `plan` - The plan the note is written on
+    /// * `author_user_id` - ID of the user writing the note
+    /// * `body` - Plain text of the note
+    ///
+    /// # Returns
+    ///
+    /// The newly created note, or `AppError::InvalidInput` if the body is invalid
+    pub fn new(
+        conn: &mut DbConn,
+        plan: &Plan,
+        author_user_id: i32,
+        body: &str,
+    ) -> Result<Self, AppError> {
+        validate_body(body)?;
+
+        conn.transaction(|conn| {
+            let note = diesel::insert_into(plan_notes::table)
+                .values(&NewPlanNote {
+                    plan_name: plan.name(),
+                    author_user_id,
+                    body,
+                })
+                .get_result::<PlanNote>(conn)
+                .map_err(|e| {
+                    tracing::error!(
+                        "Failed writing note on plan \"{}\" for user {author_user_id} ({e})",
+                        plan.name()
+                    );
+                    AppError::Diesel(e)
+                })?;
+            plan.touch(conn)?;
+            Ok(note)
+        })
+    }
+
+    /// Get all notes of a plan
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `plan` - The plan the notes are written on
+    ///
+    /// # Returns
+    ///
+    /// A vector of notes, newest first
+    pub fn get_all(conn: &mut DbConn, plan: &Plan) -> Result<Vec<Self>, AppError> {
+        plan_notes::table
+            .filter(plan_notes::plan_name.eq(plan.name()))
+            .order((plan_notes::created_at.desc(), plan_notes::id.desc()))
+            .load::<PlanNote>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting notes of plan \"{}\" ({e})", plan.name());
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the notes on the plans of a user whose body matches an `ILIKE` pattern
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `pattern` - The `ILIKE` pattern to match
+    /// * `limit` - Maximum number of notes to return
+    ///
+    /// # Returns
+    ///
+    /// The number of matching notes, and up to `limit` of them, newest first
+    pub fn matching(
+        conn: &mut DbConn,
+        user_id: i32,
+        pattern: &str,
+        limit: i64,
+    ) -> Result<(i64, Vec<Self>), AppError> {
+        let query = || {
+            plan_notes::table
+                .inner_join(plans::table)
+                .filter(plans::user_id.eq(user_id))
+                .filter(plan_notes::body.ilike(pattern))
+        };
+
+        let matching = |conn: &mut DbConn| -> QueryResult<(i64, Vec<Self>)> {
+            let total = query().count().get_result::<i64>(conn)?;
+            let matches = query()
+                .select(plan_notes::all_columns)
+                .order((plan_notes::created_at.desc(), plan_notes::id.desc()))
+                .limit(limit)
+                .load::<PlanNote>(conn)?;
+            Ok((total, matches))
+        };
+        matching(conn).map_err(|e| {
+            tracing::error!("Failed matching notes of user {user_id} ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get a note by ID, scoped to a plan
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Note ID
+    /// * `plan` - The plan the note is written on
+    ///
+    /// # Returns
+    ///
+    /// The note, or `AppError::NotFound` if it doesn't exist on the plan
+    pub fn from_id(conn: &mut DbConn, id: i32, plan: &Plan) -> Result<Self, AppError> {
+        plan_notes::table
+            .filter(plan_notes::id.eq(id))
+            .filter(plan_notes::plan_name.eq(plan.name()))
+            .first::<PlanNote>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting note {id} of plan \"{}\" ({e})", plan.name());
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Check that a user can edit the note, which only its author and the plan's owner can
+    fn check_editor(&self, plan: &Plan, user_id: i32) -> Result<(), AppError> {
+        if user_id == self.author_user_id || user_id == plan.user_id() {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden)
+        }
+    }
+
+    /// Replace the body of the note
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `plan` - The plan the note is written on
+    /// * `user_id` - ID of the user editing the note
+    /// * `body` - New plain text of the note
+    ///
+    /// # Returns
+    ///
+    /// The updated note, `AppError::Forbidden` if the user is neither its author nor the plan's
+    /// owner, or `AppError::InvalidInput` if the body is invalid
+    pub fn update(
+        &self,
+        conn: &mut DbConn,
+        plan: &Plan,
+        user_id: i32,
+        body: &str,
+    ) -> Result<Self, AppError> {
+        self.check_editor(plan, user_id)?;
+        validate_body(body)?;
+
+        diesel::update(plan_notes::table.filter(plan_notes::id.eq(self.id)))
+            .set((
+                plan_notes::body.eq(body),
+                plan_notes::updated_at.eq(diesel::dsl::now),
+            ))
+            .get_result::<PlanNote>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed updating note {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Delete the note
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `plan` - The plan the note is written on
+    /// * `user_id` - ID of the user deleting the note
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, `AppError::Forbidden` if the user is neither its author nor
+    /// the plan's owner
+    pub fn delete(&self, conn: &mut DbConn, plan: &Plan, user_id: i32) -> Result<(), AppError> {
+        self.check_editor(plan, user_id)?;
+
+        conn.transaction(|conn| {
+            diesel::delete(plan_notes::table.filter(plan_notes::id.eq(self.id)))
+                .execute(conn)
+                .map_err(|e| {
+                    tracing::error!("Failed deleting note {} ({e})", self.id);
+                    AppError::Diesel(e)
+                })?;
+            plan.touch(conn)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::{connection::DbPool, models::users::User};
+
+    #[test]
+    fn test_note_permissions() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let owner = User::default(conn).unwrap();
+        let other = User::new(conn, "other_user", "other_password").unwrap();
+        let plan = Plan::new(conn, "Household", owner.id()).unwrap();
+        let note = PlanNote::new(conn, &plan, owner.id(), "Raised groceries to 600").unwrap();
+
+        // Notes are only found through a plan of the user
+        assert!(matches!(
+            Plan::from_name(conn, "Household", other.id()),
+            Err(AppError::NotFound(_))
+        ));
+
+        // Only the author and the plan's owner can edit a note
+        assert!(matches!(
+            note.update(conn, &plan, other.id(), "Lowered groceries"),
+            Err(AppError::Forbidden)
+        ));
+        assert!(matches!(
+            note.delete(conn, &plan, other.id()),
+            Err(AppError::Forbidden)
+        ));
+        let note = note
+            .update(conn, &plan, owner.id(), "Raised groceries to 650")
+            .unwrap();
+        assert_eq!(note.body, "Raised groceries to 650");
+
+        note.delete(conn, &plan, owner.id()).unwrap();
+        assert!(PlanNote::get_all(conn, &plan).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_body() {
+        assert!(validate_body("Moved 50 from dining to groceries, since 3 < 4").is_ok());
+        assert!(validate_body(&"a".repeat(MAX_NOTE_LENGTH)).is_ok());
+        // Characters are counted, not bytes
+        assert!(validate_body(&"é".repeat(MAX_NOTE_LENGTH)).is_ok());
+
+        assert!(validate_body(&"a".repeat(MAX_NOTE_LENGTH + 1)).is_err());
+        assert!(validate_body("  \n").is_err());
+        assert!(validate_body("<script>alert(1)</script>").is_err());
+        assert!(validate_body("See <b>this</b>").is_err());
+    }
+}
@@ -1,26 +1,52 @@
+use std::collections::HashMap;
+
 use diesel::{
-    query_builder::AsChangeset, BoolExpressionMethods, ExpressionMethods, Insertable, QueryDsl,
-    Queryable, RunQueryDsl,
+    query_builder::AsChangeset, BoolExpressionMethods, ExpressionMethods, Insertable,
+    OptionalExtension, PgTextExpressionMethods, QueryDsl, QueryResult, Queryable, RunQueryDsl,
 };
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::errors::AppError;
 
-use crate::database::{connection::DbConn, schema::plans};
+use crate::audit;
+use crate::database::{
+    connection::DbConn,
+    models::audit_events::{AuditEventKind, NewAuditEvent},
+    schema::{budgets, plan_notes, plans},
+};
 
 /// Plan struct
-#[derive(Debug, Serialize, Deserialize, Clone, Queryable, AsChangeset)]
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, AsChangeset, ToSchema)]
 #[diesel(table_name = plans)]
 pub struct Plan {
     /// Plan name
     name: String,
+    /// ID of the user owning the plan
     user_id: i32,
     /// Last time the plan was modified
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
     last_modified: chrono::NaiveDateTime,
+    /// Version of the plan, incremented whenever it or one of its budgets or notes changes and
+    /// sent as its ETag
+    version: i32,
+}
+
+/// A plan with the number of budgets and notes it has
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlanSummary {
+    /// The plan
+    #[serde(flatten)]
+    plan: Plan,
+    /// Number of budgets of the plan
+    budgets: i64,
+    /// Number of notes written on the plan
+    notes: i64,
 }
 
 #[derive(Insertable)]
-#[table_name = "plans"]
+#[diesel(table_name = plans)]
 pub struct NewPlan {
     pub name: String,
     user_id: i32,
@@ -73,6 +99,179 @@ impl Plan {
             })
     }
 
+    /// Get a page of the plans of a user, ordered by name
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `limit` - Maximum number of plans in the page
+    /// * `offset` - Number of plans before the page
+    ///
+    /// # Returns
+    ///
+    /// The plans of the page, the number of plans is part of `version`
+    pub fn get_page(
+        conn: &mut DbConn,
+        user_id: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Self>, AppError> {
+        plans::table
+            .filter(plans::user_id.eq(user_id))
+            .order(plans::name)
+            .limit(limit)
+            .offset(offset)
+            .load::<Plan>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting a page of the plans of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a page of the plans of a user ordered by name, with the number of budgets and notes of
+    /// each
+    ///
+    /// The counts of all the plans of the page are loaded at once, so the number of queries doesn't
+    /// grow with the number of plans.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `limit` - Maximum number of plans in the page
+    /// * `offset` - Number of plans before the page
+    ///
+    /// # Returns
+    ///
+    /// The plans of the page with their counts
+    pub fn list_with_stats(
+        conn: &mut DbConn,
+        user_id: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PlanSummary>, AppError> {
+        let plans = Self::get_page(conn, user_id, limit, offset)?;
+        let names: Vec<&str> = plans.iter().map(Plan::name).collect();
+
+        let counts = |conn: &mut DbConn| -> QueryResult<_> {
+            let budgets: HashMap<String, i64> = budgets::table
+                .filter(budgets::plan_name.eq_any(&names))
+                .group_by(budgets::plan_name)
+                .select((budgets::plan_name, diesel::dsl::count(budgets::id)))
+                .load::<(String, i64)>(conn)?
+                .into_iter()
+                .collect();
+            let notes: HashMap<String, i64> = plan_notes::table
+                .filter(plan_notes::plan_name.eq_any(&names))
+                .group_by(plan_notes::plan_name)
+                .select((plan_notes::plan_name, diesel::dsl::count(plan_notes::id)))
+                .load::<(String, i64)>(conn)?
+                .into_iter()
+                .collect();
+            Ok((budgets, notes))
+        };
+        let (budgets, notes) = counts(conn).map_err(|e| {
+            tracing::error!("Failed counting the items of the plans of user {user_id} ({e})");
+            AppError::Diesel(e)
+        })?;
+
+        Ok(plans
+            .into_iter()
+            .map(|plan| PlanSummary {
+                budgets: budgets.get(&plan.name).copied().unwrap_or(0),
+                notes: notes.get(&plan.name).copied().unwrap_or(0),
+                plan,
+            })
+            .collect())
+    }
+
+    /// Get what the plans of a user last looked like, without loading them
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The number of plans, and the last time one of them was modified, which change whenever a
+    /// plan is created, modified or deleted, or a budget or note is added to or removed from one
+    pub fn list_version(
+        conn: &mut DbConn,
+        user_id: i32,
+    ) -> Result<(i64, Option<chrono::NaiveDateTime>), AppError> {
+        plans::table
+            .filter(plans::user_id.eq(user_id))
+            .select((
+                diesel::dsl::count_star(),
+                diesel::dsl::max(plans::last_modified),
+            ))
+            .first(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting the version of the plans of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a plan by name, scoped to the user that owns it
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `name` - Name of the plan
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The plan, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_name(conn: &mut DbConn, name: &str, user_id: i32) -> Result<Self, AppError> {
+        plans::table
+            .filter(plans::name.eq(name).and(plans::user_id.eq(user_id)))
+            .first::<Plan>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting plan \"{name}\" for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get the plans of a user whose name matches an `ILIKE` pattern
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `pattern` - The `ILIKE` pattern to match
+    /// * `limit` - Maximum number of plans to return
+    ///
+    /// # Returns
+    ///
+    /// The number of matching plans, and up to `limit` of them ordered by name
+    pub fn matching(
+        conn: &mut DbConn,
+        user_id: i32,
+        pattern: &str,
+        limit: i64,
+    ) -> Result<(i64, Vec<Self>), AppError> {
+        let query = || {
+            plans::table
+                .filter(plans::user_id.eq(user_id))
+                .filter(plans::name.ilike(pattern))
+        };
+
+        let matching = |conn: &mut DbConn| -> QueryResult<(i64, Vec<Self>)> {
+            let total = query().count().get_result::<i64>(conn)?;
+            let matches = query().order(plans::name).limit(limit).load::<Plan>(conn)?;
+            Ok((total, matches))
+        };
+        matching(conn).map_err(|e| {
+            tracing::error!("Failed matching plans of user {user_id} ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
     /// Delete a plan by name and user ID
     ///
     /// # Arguments
@@ -94,14 +293,97 @@ impl Plan {
             AppError::Diesel(e)
         })?;
 
+        if rows > 0 {
+            audit::record(
+                conn,
+                NewAuditEvent::new(
+                    AuditEventKind::PlanDeleted,
+                    Some(user_id),
+                    serde_json::json!({ "plan": name }),
+                ),
+            );
+        }
         Ok(rows > 0)
     }
+
+    /// Delete all plans of a user, with their budgets and notes
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The number of plans deleted
+    pub fn delete_all_of_user(conn: &mut DbConn, user_id: i32) -> Result<u64, AppError> {
+        diesel::delete(plans::table.filter(plans::user_id.eq(user_id)))
+            .execute(conn)
+            .map(|rows| rows as u64)
+            .map_err(|e| {
+                tracing::error!("Failed deleting plans of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Mark the plan as modified now, incrementing its version
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn touch(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::update(plans::table.filter(plans::name.eq(&self.name)))
+            .set((
+                plans::last_modified.eq(diesel::dsl::now),
+                plans::version.eq(plans::version + 1),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::error!("Failed touching plan \"{}\" ({e})", self.name);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the name of the plan
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the ID of the user that owns the plan
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// Get the version of the plan
+    pub fn version(&self) -> i32 {
+        self.version
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+    use diesel::connection::InstrumentationEvent;
+
     use super::*;
-    use crate::database::{connection::DbPool, models::users::User};
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            budgets::{Budget, BudgetInput, BudgetInterval},
+            categories::Category,
+            plan_notes::PlanNote,
+            users::User,
+        },
+    };
     use diesel::prelude::*;
 
     #[test]
@@ -141,6 +423,70 @@ mod tests {
         assert!(deleted);
     }
 
+    #[test]
+    fn test_list_with_stats() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let category = Category::new(conn, user.id(), "Groceries").unwrap();
+        let add_plan = |conn: &mut DbConn, name: &str, budgets: usize, notes: usize| {
+            let plan = Plan::new(conn, name, user.id()).unwrap();
+            for _ in 0..budgets {
+                let input = BudgetInput {
+                    category_id: category.id(),
+                    name: "Groceries".to_string(),
+                    amount: BigDecimal::from(400),
+                    interval: BudgetInterval::Monthly,
+                    currency: "CAD".to_string(),
+                    start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                    end_date: None,
+                    rollover: false,
+                };
+                Budget::new(conn, &plan, &input).unwrap();
+            }
+            for _ in 0..notes {
+                PlanNote::new(conn, &plan, user.id(), "A note").unwrap();
+            }
+        };
+
+        // Count the queries of listing the plans
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = queries.clone();
+        conn.set_instrumentation(move |event: InstrumentationEvent<'_>| {
+            if matches!(event, InstrumentationEvent::StartQuery { .. }) {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let list = |conn: &mut DbConn| {
+            queries.store(0, Ordering::Relaxed);
+            let plans = Plan::list_with_stats(conn, user.id(), 100, 0).unwrap();
+            (plans, queries.load(Ordering::Relaxed))
+        };
+
+        add_plan(conn, "Household", 2, 1);
+        let (plans, one_plan) = list(conn);
+        assert_eq!(plans.len(), 1);
+        assert_eq!((plans[0].budgets, plans[0].notes), (2, 1));
+
+        for i in 0..10 {
+            add_plan(conn, &format!("Plan {i}"), i % 3, i % 2);
+        }
+        add_plan(conn, "Empty", 0, 0);
+        let (plans, many_plans) = list(conn);
+        assert_eq!(plans.len(), 12);
+        assert_eq!(many_plans, one_plan);
+
+        let counts: Vec<_> = plans
+            .iter()
+            .map(|plan| (plan.plan.name(), plan.budgets, plan.notes))
+            .collect();
+        assert_eq!(counts[0], ("Empty", 0, 0));
+        assert_eq!(counts[1], ("Household", 2, 1));
+        assert_eq!(counts[7], ("Plan 5", 2, 1));
+    }
+
     #[test]
     fn test_duplicate_plan() {
         let pool = DbPool::new_test();
@@ -0,0 +1,397 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use diesel::{dsl::sum, prelude::*};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{accounts::Account, transactions::Transaction},
+    schema::{accounts, cleared_transactions, reconciliations, transactions},
+};
+use crate::errors::AppError;
+
+/// A reconciliation of an account against a bank statement
+///
+/// Transactions up to the statement date are cleared as they are found on the statement. The
+/// reconciliation can be finished once the opening balance plus the cleared transactions equals
+/// the ending balance of the statement, which locks the cleared transactions.
+#[derive(Debug, Serialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = reconciliations)]
+pub struct Reconciliation {
+    /// Reconciliation ID
+    id: i32,
+    /// ID of the account being reconciled
+    account_id: i32,
+    /// The ending date of the statement
+    statement_date: NaiveDate,
+    /// The ending balance of the previous finished reconciliation, or the opening balance of
+    /// the account, as a decimal string
+    #[schema(value_type = String)]
+    opening_balance: BigDecimal,
+    /// The ending balance of the statement, as a decimal string
+    #[schema(value_type = String)]
+    ending_balance: BigDecimal,
+    /// The timestamp when the reconciliation was started
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+    /// The timestamp when the reconciliation was finished, if it was
+    #[serde(default, with = "crate::utils::serialization::option")]
+    #[schema(value_type = Option<String>)]
+    finished_at: Option<chrono::NaiveDateTime>,
+}
+
+/// A transaction that can be cleared in a reconciliation
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReconciliationCandidate {
+    /// The transaction
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    transaction: Transaction,
+    /// Whether the transaction is cleared in the reconciliation
+    cleared: bool,
+}
+
+impl Reconciliation {
+    /// Start reconciling an account against a statement
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account to reconcile
+    /// * `statement_date` - The ending date of the statement
+    /// * `ending_balance` - The ending balance of the statement
+    ///
+    /// # Returns
+    ///
+    /// The newly started reconciliation, `AppError::Conflict` if the account already has one in
+    /// progress, or `AppError::InvalidInput` if the statement ends before the one of the previous
+    /// finished reconciliation
+    pub fn new(
+        conn: &mut DbConn,
+        account: &Account,
+        statement_date: NaiveDate,
+        ending_balance: &BigDecimal,
+    ) -> Result<Self, AppError> {
+        conn.transaction(|conn| {
+            let reconciliations = Self::get_all(conn, account)?;
+            if let Some(open) = reconciliations.iter().find(|r| r.finished_at.is_none()) {
+                return Err(AppError::Conflict(format!(
+                    "Reconciliation {} of account {} is still in progress",
+                    open.id,
+                    account.id()
+                )));
+            }
+
+            let previous = reconciliations.first();
+            if let Some(previous) = previous.filter(|p| statement_date < p.statement_date) {
+                return Err(AppError::InvalidInput(format!(
+                    "The statement must not end before {}, the end of the last reconciled \
+                     statement",
+                    previous.statement_date
+                )));
+            }
+            let opening_balance = previous
+                .map(|previous| &previous.ending_balance)
+                .unwrap_or(account.opening_balance());
+
+            diesel::insert_into(reconciliations::table)
+                .values((
+                    reconciliations::account_id.eq(account.id()),
+                    reconciliations::statement_date.eq(statement_date),
+                    reconciliations::opening_balance.eq(opening_balance),
+                    reconciliations::ending_balance.eq(ending_balance),
+                ))
+                .get_result::<Reconciliation>(conn)
+                .map_err(|e| {
+                    tracing::error!(
+                        "Failed starting reconciliation of account {} ({e})",
+                        account.id()
+                    );
+                    AppError::Diesel(e)
+                })
+        })
+    }
+
+    /// Get all reconciliations of an account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account
+    ///
+    /// # Returns
+    ///
+    /// A vector of reconciliations, latest statement first
+    pub fn get_all(conn: &mut DbConn, account: &Account) -> Result<Vec<Self>, AppError> {
+        reconciliations::table
+            .filter(reconciliations::account_id.eq(account.id()))
+            .order((
+                reconciliations::statement_date.desc(),
+                reconciliations::id.desc(),
+            ))
+            .load::<Reconciliation>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting reconciliations of account {} ({e})",
+                    account.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a reconciliation by ID, scoped to the user that owns its account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Reconciliation ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The reconciliation, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        reconciliations::table
+            .inner_join(accounts::table)
+            .filter(reconciliations::id.eq(id))
+            .filter(accounts::user_id.eq(user_id))
+            .select(reconciliations::all_columns)
+            .first::<Reconciliation>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting reconciliation {id} for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get the transactions that can be cleared in the reconciliation
+    ///
+    /// These are the transactions of the account up to the statement date that weren't cleared
+    /// in another reconciliation.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// A vector of transactions with whether each is cleared, oldest first
+    pub fn candidates(&self, conn: &mut DbConn) -> Result<Vec<ReconciliationCandidate>, AppError> {
+        transactions::table
+            .left_join(cleared_transactions::table)
+            .filter(transactions::account_id.eq(self.account_id))
+            .filter(transactions::occurred_at.le(self.statement_date))
+            .filter(
+                cleared_transactions::reconciliation_id
+                    .nullable()
+                    .is_null()
+                    .or(cleared_transactions::reconciliation_id
+                        .nullable()
+                        .eq(self.id)),
+            )
+            .select((
+                transactions::all_columns,
+                cleared_transactions::reconciliation_id.nullable(),
+            ))
+            .order((transactions::occurred_at, transactions::id))
+            .load::<(Transaction, Option<i32>)>(conn)
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|(transaction, cleared_in)| ReconciliationCandidate {
+                        transaction,
+                        cleared: cleared_in.is_some(),
+                    })
+                    .collect()
+            })
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting candidates of reconciliation {} ({e})",
+                    self.id
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Compute the opening balance plus the transactions cleared in the reconciliation
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// The balance, which equals the ending balance once every transaction is cleared
+    pub fn cleared_balance(&self, conn: &mut DbConn) -> Result<BigDecimal, AppError> {
+        let cleared = cleared_transactions::table
+            .inner_join(transactions::table)
+            .filter(cleared_transactions::reconciliation_id.eq(self.id))
+            .select(sum(transactions::amount))
+            .first::<Option<BigDecimal>>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed computing cleared balance of reconciliation {} ({e})",
+                    self.id
+                );
+                AppError::Diesel(e)
+            })?;
+
+        Ok(&self.opening_balance + cleared.unwrap_or_default())
+    }
+
+    /// Toggle whether transactions are cleared in the reconciliation
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction_ids` - IDs of the transactions to clear, or to unclear if they are cleared
+    ///
+    /// # Returns
+    ///
+    /// An empty result, `AppError::Conflict` if the reconciliation is finished or a transaction
+    /// was cleared in another one, or `AppError::InvalidInput` if a transaction isn't on the
+    /// account or is after the statement date
+    pub fn toggle(&self, conn: &mut DbConn, transaction_ids: &[i32]) -> Result<(), AppError> {
+        self.ensure_open()?;
+
+        conn.transaction(|conn| {
+            for &transaction_id in transaction_ids {
+                let candidate = transactions::table
+                    .filter(transactions::id.eq(transaction_id))
+                    .filter(transactions::account_id.eq(self.account_id))
+                    .filter(transactions::occurred_at.le(self.statement_date))
+                    .select(transactions::id)
+                    .first::<i32>(conn)
+                    .optional()?;
+                if candidate.is_none() {
+                    return Err(AppError::InvalidInput(format!(
+                        "Transaction {transaction_id} isn't on the account or is after the \
+                         statement date"
+                    )));
+                }
+
+                let cleared_in = cleared_transactions::table
+                    .find(transaction_id)
+                    .select(cleared_transactions::reconciliation_id)
+                    .first::<i32>(conn)
+                    .optional()?;
+                match cleared_in {
+                    Some(id) if id == self.id => {
+                        diesel::delete(cleared_transactions::table.find(transaction_id))
+                            .execute(conn)?;
+                    }
+                    Some(id) => {
+                        return Err(AppError::Conflict(format!(
+                            "Transaction {transaction_id} was cleared in reconciliation {id}"
+                        )));
+                    }
+                    None => {
+                        diesel::insert_into(cleared_transactions::table)
+                            .values((
+                                cleared_transactions::transaction_id.eq(transaction_id),
+                                cleared_transactions::reconciliation_id.eq(self.id),
+                            ))
+                            .execute(conn)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+        .map_err(|e| {
+            if let AppError::Diesel(e) = &e {
+                tracing::error!(
+                    "Failed clearing transactions in reconciliation {} ({e})",
+                    self.id
+                );
+            }
+            e
+        })
+    }
+
+    /// Finish the reconciliation, locking its cleared transactions
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// The finished reconciliation, or `AppError::Conflict` if it is already finished or the
+    /// cleared balance doesn't equal the ending balance. The message says how far off it is.
+    pub fn finish(&self, conn: &mut DbConn) -> Result<Self, AppError> {
+        self.ensure_open()?;
+
+        let cleared_balance = self.cleared_balance(conn)?;
+        let discrepancy = &self.ending_balance - &cleared_balance;
+        if !discrepancy.is_zero() {
+            return Err(AppError::Conflict(format!(
+                "The statement's ending balance of {} is {discrepancy} off the opening balance \
+                 plus the cleared transactions, {cleared_balance}",
+                self.ending_balance
+            )));
+        }
+
+        diesel::update(reconciliations::table.filter(reconciliations::id.eq(self.id)))
+            .set(reconciliations::finished_at.eq(diesel::dsl::now))
+            .get_result::<Reconciliation>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed finishing reconciliation {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Check that none of some transactions were cleared in a finished reconciliation
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction_ids` - IDs of the transactions about to be changed
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the transactions can be changed, otherwise `AppError::Conflict`
+    pub fn ensure_unlocked(conn: &mut DbConn, transaction_ids: &[i32]) -> Result<(), AppError> {
+        let locked = cleared_transactions::table
+            .inner_join(reconciliations::table)
+            .filter(cleared_transactions::transaction_id.eq_any(transaction_ids))
+            .filter(reconciliations::finished_at.is_not_null())
+            .select((
+                cleared_transactions::transaction_id,
+                cleared_transactions::reconciliation_id,
+            ))
+            .first::<(i32, i32)>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed checking if transactions {transaction_ids:?} are reconciled ({e})"
+                );
+                AppError::Diesel(e)
+            })?;
+
+        match locked {
+            Some((transaction_id, reconciliation_id)) => Err(AppError::Conflict(format!(
+                "Transaction {transaction_id} was reconciled in reconciliation \
+                 {reconciliation_id} and can't be changed"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that the reconciliation isn't finished
+    fn ensure_open(&self) -> Result<(), AppError> {
+        match self.finished_at {
+            Some(_) => Err(AppError::Conflict(format!(
+                "Reconciliation {} is finished",
+                self.id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the ending balance of the statement
+    pub fn ending_balance(&self) -> &BigDecimal {
+        &self.ending_balance
+    }
+}
@@ -0,0 +1,761 @@
+use bigdecimal::BigDecimal;
+use chrono::{Datelike, Duration, NaiveDate};
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        accounts::Account,
+        categories::Category,
+        transactions::{Transaction, TransactionInput},
+    },
+    schema::{accounts, recurring_transactions},
+};
+use crate::errors::AppError;
+
+/// How often a recurring transaction occurs
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum Cadence {
+    /// Every week on a day of the week
+    Weekly,
+    /// Every month on a day of the month
+    Monthly,
+    /// Every year on a day of the month of the first occurrence
+    Yearly,
+}
+
+impl Cadence {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Cadence::Weekly => "weekly",
+            Cadence::Monthly => "monthly",
+            Cadence::Yearly => "yearly",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for Cadence {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for Cadence {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "weekly" => Ok(Cadence::Weekly),
+            "monthly" => Ok(Cadence::Monthly),
+            "yearly" => Ok(Cadence::Yearly),
+            other => Err(format!("Unknown cadence \"{other}\"").into()),
+        }
+    }
+}
+
+/// When a recurring transaction occurs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    cadence: Cadence,
+    /// Day of the month (1 to 31) for monthly and yearly cadences
+    day_of_month: u32,
+    /// Day of the week (0 is Monday) for weekly cadences
+    weekday: u32,
+}
+
+impl Schedule {
+    /// Create a schedule, validating that the day it occurs on fits the cadence
+    ///
+    /// # Arguments
+    ///
+    /// * `cadence` - How often the transaction occurs
+    /// * `day_of_month` - Day of the month (1 to 31), required for monthly and yearly cadences
+    /// * `weekday` - Day of the week (0 is Monday, 6 is Sunday), required for weekly cadences
+    ///
+    /// # Returns
+    ///
+    /// The schedule, or `AppError::InvalidInput` if the day is missing or out of range
+    pub fn new(
+        cadence: Cadence,
+        day_of_month: Option<i16>,
+        weekday: Option<i16>,
+    ) -> Result<Self, AppError> {
+        match cadence {
+            Cadence::Weekly => match weekday {
+                Some(weekday @ 0..=6) => Ok(Self {
+                    cadence,
+                    day_of_month: 0,
+                    weekday: weekday as u32,
+                }),
+                _ => Err(AppError::InvalidInput(
+                    "A weekly cadence needs a weekday between 0 (Monday) and 6 (Sunday)"
+                        .to_string(),
+                )),
+            },
+            Cadence::Monthly | Cadence::Yearly => match day_of_month {
+                Some(day @ 1..=31) => Ok(Self {
+                    cadence,
+                    day_of_month: day as u32,
+                    weekday: 0,
+                }),
+                _ => Err(AppError::InvalidInput(format!(
+                    "A {} cadence needs a day of the month between 1 and 31",
+                    cadence.as_str()
+                ))),
+            },
+        }
+    }
+
+    /// Get the first occurrence on or after a date
+    pub fn first_on_or_after(&self, date: NaiveDate) -> NaiveDate {
+        match self.cadence {
+            Cadence::Weekly => {
+                let days = (7 + self.weekday - date.weekday().num_days_from_monday()) % 7;
+                date + Duration::days(days as i64)
+            }
+            Cadence::Monthly | Cadence::Yearly => {
+                let candidate = clamped_date(date.year(), date.month(), self.day_of_month);
+                if candidate >= date {
+                    candidate
+                } else {
+                    self.next_after(candidate)
+                }
+            }
+        }
+    }
+
+    /// Get the occurrence following an occurrence
+    ///
+    /// The day of the month is clamped to the last day of short months without drifting, so a
+    /// schedule on the 31st occurs on January 31st, February 29th, then March 31st.
+    pub fn next_after(&self, occurrence: NaiveDate) -> NaiveDate {
+        match self.cadence {
+            Cadence::Weekly => occurrence + Duration::days(7),
+            Cadence::Monthly => {
+                let (year, month) = match occurrence.month() {
+                    12 => (occurrence.year() + 1, 1),
+                    month => (occurrence.year(), month + 1),
+                };
+                clamped_date(year, month, self.day_of_month)
+            }
+            Cadence::Yearly => {
+                clamped_date(occurrence.year() + 1, occurrence.month(), self.day_of_month)
+            }
+        }
+    }
+}
+
+/// Get a date in a month, clamping the day to the last day of the month
+fn clamped_date(year: i32, month: u32, day: u32) -> NaiveDate {
+    (1..=day)
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .expect("Every month has a first day")
+}
+
+/// A transaction that repeats on a schedule, like a subscription or a salary
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = recurring_transactions)]
+pub struct RecurringTransaction {
+    /// Recurring transaction ID
+    id: i32,
+    /// ID of the account the occurrences are created on
+    account_id: i32,
+    /// ID of the category of the occurrences, if categorized
+    category_id: Option<i32>,
+    /// Signed amount of each occurrence
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// Description of each occurrence
+    description: String,
+    /// How often the transaction occurs
+    cadence: Cadence,
+    /// Day of the month for monthly and yearly cadences
+    day_of_month: Option<i16>,
+    /// Day of the week for weekly cadences, 0 is Monday
+    weekday: Option<i16>,
+    /// The date of the next occurrence that hasn't been created yet
+    #[schema(value_type = String)]
+    next_run_on: NaiveDate,
+    /// The last day an occurrence can be on, if any
+    #[schema(value_type = Option<String>)]
+    end_on: Option<NaiveDate>,
+    /// Whether occurrences are still created
+    active: bool,
+    /// The timestamp when the recurring transaction was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = recurring_transactions)]
+struct NewRecurringTransaction<'a> {
+    account_id: i32,
+    category_id: Option<i32>,
+    amount: &'a BigDecimal,
+    description: &'a str,
+    cadence: Cadence,
+    day_of_month: Option<i16>,
+    weekday: Option<i16>,
+    next_run_on: NaiveDate,
+    end_on: Option<NaiveDate>,
+}
+
+/// Fields of a recurring transaction that can be changed after it is created
+///
+/// The schedule can't be changed, delete the recurring transaction and create a new one instead.
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = recurring_transactions, treat_none_as_null = true)]
+pub struct RecurringChanges {
+    /// Signed amount of each occurrence
+    pub amount: BigDecimal,
+    /// Description of each occurrence
+    pub description: String,
+    /// ID of the category of the occurrences, must belong to the account's owner
+    pub category_id: Option<i32>,
+    /// The last day an occurrence can be on, if any
+    pub end_on: Option<NaiveDate>,
+    /// Whether occurrences are still created
+    pub active: bool,
+}
+
+impl RecurringTransaction {
+    /// Create a new recurring transaction on an account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the occurrences are created on
+    /// * `input` - The amount, description and category of each occurrence
+    /// * `schedule` - When the transaction occurs
+    /// * `starts_on` - The first occurrence is the first day on or after this date that fits the
+    ///   schedule
+    /// * `end_on` - The last day an occurrence can be on, if any
+    ///
+    /// # Returns
+    ///
+    /// The newly created recurring transaction, or `AppError::Conflict` if the account is archived
+    pub fn new(
+        conn: &mut DbConn,
+        account: &Account,
+        input: &TransactionInput,
+        schedule: Schedule,
+        starts_on: NaiveDate,
+        end_on: Option<NaiveDate>,
+    ) -> Result<Self, AppError> {
+        account.ensure_open()?;
+        let (day_of_month, weekday) = match schedule.cadence {
+            Cadence::Weekly => (None, Some(schedule.weekday as i16)),
+            Cadence::Monthly | Cadence::Yearly => (Some(schedule.day_of_month as i16), None),
+        };
+
+        diesel::insert_into(recurring_transactions::table)
+            .values(&NewRecurringTransaction {
+                account_id: account.id(),
+                category_id: input.category_id,
+                amount: &input.amount,
+                description: &input.description,
+                cadence: schedule.cadence,
+                day_of_month,
+                weekday,
+                next_run_on: schedule.first_on_or_after(starts_on),
+                end_on,
+            })
+            .get_result::<RecurringTransaction>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed creating recurring transaction on account {} ({e})",
+                    account.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a recurring transaction by ID, scoped to an account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Recurring transaction ID
+    /// * `account` - The account the occurrences are created on
+    ///
+    /// # Returns
+    ///
+    /// The recurring transaction, or `AppError::NotFound` if it doesn't exist on the account
+    pub fn from_id(conn: &mut DbConn, id: i32, account: &Account) -> Result<Self, AppError> {
+        recurring_transactions::table
+            .filter(recurring_transactions::id.eq(id))
+            .filter(recurring_transactions::account_id.eq(account.id()))
+            .first::<RecurringTransaction>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting recurring transaction {id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get all recurring transactions of an account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the occurrences are created on
+    ///
+    /// # Returns
+    ///
+    /// A vector of recurring transactions, ordered by their next occurrence
+    pub fn get_all(conn: &mut DbConn, account: &Account) -> Result<Vec<Self>, AppError> {
+        recurring_transactions::table
+            .filter(recurring_transactions::account_id.eq(account.id()))
+            .order((
+                recurring_transactions::next_run_on,
+                recurring_transactions::id,
+            ))
+            .load::<RecurringTransaction>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting recurring transactions of account {} ({e})",
+                    account.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the active recurring transactions of all open accounts of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// A vector of recurring transactions, ordered by their next occurrence
+    pub fn active_of_user(conn: &mut DbConn, user_id: i32) -> Result<Vec<Self>, AppError> {
+        recurring_transactions::table
+            .inner_join(accounts::table)
+            .filter(accounts::user_id.eq(user_id))
+            .filter(accounts::archived_at.is_null())
+            .filter(recurring_transactions::active.eq(true))
+            .order((
+                recurring_transactions::next_run_on,
+                recurring_transactions::id,
+            ))
+            .select(recurring_transactions::all_columns)
+            .load::<RecurringTransaction>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting active recurring transactions of user {user_id} ({e})"
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Update the recurring transaction
+    ///
+    /// Occurrences that were already created are not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `changes` - The new values of the changeable fields
+    ///
+    /// # Returns
+    ///
+    /// The updated recurring transaction
+    pub fn update(&self, conn: &mut DbConn, changes: &RecurringChanges) -> Result<Self, AppError> {
+        diesel::update(recurring_transactions::table.filter(recurring_transactions::id.eq(self.id)))
+            .set(changes)
+            .get_result::<RecurringTransaction>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed updating recurring transaction {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Give the recurring transactions of a category another category
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `from` - The category of the recurring transactions
+    /// * `into` - The category to give them instead
+    ///
+    /// # Returns
+    ///
+    /// The number of recurring transactions changed
+    pub fn move_category(
+        conn: &mut DbConn,
+        from: &Category,
+        into: &Category,
+    ) -> Result<u64, AppError> {
+        diesel::update(
+            recurring_transactions::table.filter(recurring_transactions::category_id.eq(from.id())),
+        )
+        .set(recurring_transactions::category_id.eq(into.id()))
+        .execute(conn)
+        .map(|count| count as u64)
+        .map_err(|e| {
+            tracing::error!(
+                "Failed moving recurring transactions from category {} to {} ({e})",
+                from.id(),
+                into.id()
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Delete the recurring transaction
+    ///
+    /// Occurrences that were already created are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::delete(recurring_transactions::table.filter(recurring_transactions::id.eq(self.id)))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::error!("Failed deleting recurring transaction {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Create the occurrences of all active recurring transactions that are due
+    ///
+    /// Each recurring transaction is caught up in its own database transaction, creating every
+    /// occurrence up to and including `today` and advancing `next_run_on` past them. Occurrences
+    /// are marked with the recurring transaction they came from, so rerunning after an
+    /// interruption never creates the same occurrence twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `today` - The current date
+    ///
+    /// # Returns
+    ///
+    /// The number of transactions created
+    pub fn materialize_due(conn: &mut DbConn, today: NaiveDate) -> Result<usize, AppError> {
+        // Nothing is created on archived accounts, their occurrences wait until they are unarchived
+        let due = recurring_transactions::table
+            .inner_join(accounts::table)
+            .filter(accounts::archived_at.is_null())
+            .filter(recurring_transactions::active.eq(true))
+            .filter(recurring_transactions::next_run_on.le(today))
+            .order(recurring_transactions::id)
+            .load::<(RecurringTransaction, Account)>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting due recurring transactions ({e})");
+                AppError::Diesel(e)
+            })?;
+
+        let mut created = 0;
+        for (recurring, account) in due {
+            // One broken recurring transaction shouldn't hold back the others
+            match conn.transaction(|conn| recurring.materialize(conn, &account, today)) {
+                Ok(count) => created += count,
+                Err(e) => tracing::error!(
+                    "Failed materializing recurring transaction {} ({e})",
+                    recurring.id
+                ),
+            }
+        }
+
+        Ok(created)
+    }
+
+    /// Get when the recurring transaction occurs
+    pub fn schedule(&self) -> Result<Schedule, AppError> {
+        Schedule::new(self.cadence, self.day_of_month, self.weekday)
+    }
+
+    /// Get the ID of the account the occurrences are created on
+    pub fn account_id(&self) -> i32 {
+        self.account_id
+    }
+
+    /// Get the ID of the category of the occurrences, if categorized
+    pub fn category_id(&self) -> Option<i32> {
+        self.category_id
+    }
+
+    /// Get the signed amount of each occurrence
+    pub fn amount(&self) -> &BigDecimal {
+        &self.amount
+    }
+
+    /// Get the description of each occurrence
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Get the dates of the occurrences within a range that haven't been created yet
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - First day of the range (inclusive)
+    /// * `to` - Last day of the range (inclusive)
+    ///
+    /// # Returns
+    ///
+    /// A vector of dates in ascending order
+    pub fn occurrences_between(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<NaiveDate>, AppError> {
+        let schedule = self.schedule()?;
+        let mut next = if self.next_run_on >= from {
+            self.next_run_on
+        } else {
+            schedule.first_on_or_after(from)
+        };
+
+        let mut occurrences = vec![];
+        while next <= to && self.end_on.map_or(true, |end_on| next <= end_on) {
+            occurrences.push(next);
+            next = schedule.next_after(next);
+        }
+        Ok(occurrences)
+    }
+
+    /// Get the date of the next occurrence that hasn't been created yet
+    pub fn next_run_on(&self) -> NaiveDate {
+        self.next_run_on
+    }
+
+    /// Get the last day an occurrence can be on, if any
+    pub fn end_on(&self) -> Option<NaiveDate> {
+        self.end_on
+    }
+
+    /// Create the due occurrences of this recurring transaction and advance `next_run_on`
+    fn materialize(
+        &self,
+        conn: &mut DbConn,
+        account: &Account,
+        today: NaiveDate,
+    ) -> Result<usize, AppError> {
+        let schedule = self.schedule()?;
+        let in_range = |date: NaiveDate| self.end_on.map_or(true, |end_on| date <= end_on);
+
+        let mut next_run_on = self.next_run_on;
+        let mut created = 0;
+        while next_run_on <= today && in_range(next_run_on) {
+            let input = TransactionInput {
+                amount: self.amount.clone(),
+                currency: None,
+                original_amount: None,
+                original_currency: None,
+                description: self.description.clone(),
+                payee: None,
+                occurred_at: next_run_on,
+                category_id: self.category_id,
+                goal_id: None,
+                splits: vec![],
+            };
+            if Transaction::new_occurrence(conn, account, &input, self.id)? {
+                created += 1;
+            }
+            next_run_on = schedule.next_after(next_run_on);
+        }
+
+        diesel::update(
+            recurring_transactions::table.filter(recurring_transactions::id.eq(self.id)),
+        )
+        .set((
+            recurring_transactions::next_run_on.eq(next_run_on),
+            recurring_transactions::active.eq(in_range(next_run_on)),
+        ))
+        .execute(conn)
+        .map_err(|e| {
+            tracing::error!("Failed advancing recurring transaction {} ({e})", self.id);
+            AppError::Diesel(e)
+        })?;
+
+        Ok(created)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{accounts::AccountKind, users::User},
+    };
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_schedule_clamps_short_months() {
+        let monthly = Schedule::new(Cadence::Monthly, Some(31), None).unwrap();
+        let mut occurrence = monthly.first_on_or_after(date(2024, 1, 15));
+        let mut occurrences = vec![occurrence];
+        for _ in 0..4 {
+            occurrence = monthly.next_after(occurrence);
+            occurrences.push(occurrence);
+        }
+        assert_eq!(
+            occurrences,
+            vec![
+                date(2024, 1, 31),
+                date(2024, 2, 29),
+                date(2024, 3, 31),
+                date(2024, 4, 30),
+                date(2024, 5, 31)
+            ]
+        );
+
+        let yearly = Schedule::new(Cadence::Yearly, Some(29), None).unwrap();
+        assert_eq!(
+            yearly.first_on_or_after(date(2024, 2, 1)),
+            date(2024, 2, 29)
+        );
+        assert_eq!(yearly.next_after(date(2024, 2, 29)), date(2025, 2, 28));
+        assert_eq!(yearly.next_after(date(2027, 2, 28)), date(2028, 2, 29));
+
+        // 2024-06-05 is a Wednesday
+        let weekly = Schedule::new(Cadence::Weekly, None, Some(4)).unwrap();
+        assert_eq!(weekly.first_on_or_after(date(2024, 6, 5)), date(2024, 6, 7));
+        assert_eq!(weekly.first_on_or_after(date(2024, 6, 7)), date(2024, 6, 7));
+        assert_eq!(weekly.next_after(date(2024, 6, 28)), date(2024, 7, 5));
+
+        assert!(Schedule::new(Cadence::Weekly, Some(1), None).is_err());
+        assert!(Schedule::new(Cadence::Monthly, Some(32), None).is_err());
+    }
+
+    #[test]
+    fn test_materialize_is_idempotent() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let amount = BigDecimal::from_str("-15.99").unwrap();
+        let input = TransactionInput::new(amount, "Streaming", date(2024, 1, 1));
+        let schedule = Schedule::new(Cadence::Monthly, Some(31), None).unwrap();
+        let recurring =
+            RecurringTransaction::new(conn, &account, &input, schedule, date(2024, 1, 1), None)
+                .unwrap();
+        assert_eq!(recurring.next_run_on, date(2024, 1, 31));
+
+        // Nothing is due yet
+        assert_eq!(
+            RecurringTransaction::materialize_due(conn, date(2024, 1, 30)).unwrap(),
+            0
+        );
+
+        // Catches up on every missed occurrence
+        assert_eq!(
+            RecurringTransaction::materialize_due(conn, date(2024, 4, 30)).unwrap(),
+            4
+        );
+        let dates: Vec<NaiveDate> = Transaction::get_all(conn, &account)
+            .unwrap()
+            .iter()
+            .map(|t| t.occurred_at())
+            .collect();
+        assert_eq!(
+            dates,
+            vec![
+                date(2024, 4, 30),
+                date(2024, 3, 31),
+                date(2024, 2, 29),
+                date(2024, 1, 31)
+            ]
+        );
+        let recurring = RecurringTransaction::from_id(conn, recurring.id, &account).unwrap();
+        assert_eq!(recurring.next_run_on, date(2024, 5, 31));
+
+        // Running again the same day creates nothing
+        assert_eq!(
+            RecurringTransaction::materialize_due(conn, date(2024, 4, 30)).unwrap(),
+            0
+        );
+
+        // Neither does a rerun after a crash that left `next_run_on` behind
+        diesel::update(recurring_transactions::table)
+            .set(recurring_transactions::next_run_on.eq(date(2024, 1, 31)))
+            .execute(conn)
+            .unwrap();
+        assert_eq!(
+            RecurringTransaction::materialize_due(conn, date(2024, 4, 30)).unwrap(),
+            0
+        );
+        assert_eq!(Transaction::get_all(conn, &account).unwrap().len(), 4);
+        let recurring = RecurringTransaction::from_id(conn, recurring.id, &account).unwrap();
+        assert_eq!(recurring.next_run_on, date(2024, 5, 31));
+    }
+
+    #[test]
+    fn test_materialize_stops_at_end_on() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let input = TransactionInput::new(BigDecimal::from(2500), "Salary", date(2024, 1, 1));
+        // Fridays in June 2024 are the 7th, 14th, 21st and 28th
+        let schedule = Schedule::new(Cadence::Weekly, None, Some(4)).unwrap();
+        let recurring = RecurringTransaction::new(
+            conn,
+            &account,
+            &input,
+            schedule,
+            date(2024, 6, 1),
+            Some(date(2024, 6, 21)),
+        )
+        .unwrap();
+
+        assert_eq!(
+            RecurringTransaction::materialize_due(conn, date(2024, 12, 31)).unwrap(),
+            3
+        );
+        let recurring = RecurringTransaction::from_id(conn, recurring.id, &account).unwrap();
+        assert!(!recurring.active);
+    }
+}
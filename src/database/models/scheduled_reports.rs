@@ -0,0 +1,551 @@
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime};
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{connection::DbConn, models::plans::Plan, schema::scheduled_reports};
+use crate::errors::AppError;
+
+/// A report that can be rendered on a schedule
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportKind {
+    /// The income and expenses of the user in a month, as returned by `/reports/monthly`
+    MonthlySummary,
+    /// The budgets of a plan compared to what was spent in a month, as returned by
+    /// `/plans/{name}/budgets/report`
+    BudgetVsActual,
+}
+
+impl ReportKind {
+    /// Get the name of the kind, as stored and used in file names
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportKind::MonthlySummary => "monthly_summary",
+            ReportKind::BudgetVsActual => "budget_vs_actual",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for ReportKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for ReportKind {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "monthly_summary" => Ok(ReportKind::MonthlySummary),
+            "budget_vs_actual" => Ok(ReportKind::BudgetVsActual),
+            other => Err(format!("Unknown report kind \"{other}\"").into()),
+        }
+    }
+}
+
+/// How often a scheduled report is rendered
+///
+/// Reports run at midnight, server time. Each run covers the month of the day before it, so a
+/// monthly report covers the month that just ended and daily and weekly reports cover the month
+/// so far.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportCadence {
+    /// Every day
+    Daily,
+    /// Every Monday
+    Weekly,
+    /// On the first day of every month
+    Monthly,
+}
+
+impl ReportCadence {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ReportCadence::Daily => "daily",
+            ReportCadence::Weekly => "weekly",
+            ReportCadence::Monthly => "monthly",
+        }
+    }
+
+    /// Get the first run strictly after a time
+    pub fn next_run_after(&self, time: NaiveDateTime) -> NaiveDateTime {
+        let date = time.date();
+        let next = match self {
+            ReportCadence::Daily => date + Duration::days(1),
+            ReportCadence::Weekly => {
+                date + Duration::days(7 - date.weekday().num_days_from_monday() as i64)
+            }
+            ReportCadence::Monthly => {
+                date.with_day(1).expect("Every month has a first day") + Months::new(1)
+            }
+        };
+        next.and_hms_opt(0, 0, 0).expect("Midnight is a valid time")
+    }
+}
+
+impl ToSql<Text, Pg> for ReportCadence {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for ReportCadence {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "daily" => Ok(ReportCadence::Daily),
+            "weekly" => Ok(ReportCadence::Weekly),
+            "monthly" => Ok(ReportCadence::Monthly),
+            other => Err(format!("Unknown report cadence \"{other}\"").into()),
+        }
+    }
+}
+
+/// The format a scheduled report is written in
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// Comma separated values with a header row, one row per category or budget
+    Csv,
+    /// The JSON body of the matching endpoint
+    Json,
+}
+
+impl ReportFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+        }
+    }
+
+    /// Get the file extension of the format
+    pub fn extension(&self) -> &'static str {
+        self.as_str()
+    }
+}
+
+impl ToSql<Text, Pg> for ReportFormat {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for ReportFormat {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            other => Err(format!("Unknown report format \"{other}\"").into()),
+        }
+    }
+}
+
+/// Where a scheduled report is delivered
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportDestination {
+    /// A file in the reports directory of the server
+    #[default]
+    File,
+}
+
+impl ToSql<Text, Pg> for ReportDestination {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match self {
+            ReportDestination::File => <str as ToSql<Text, Pg>>::to_sql("file", out),
+        }
+    }
+}
+
+impl FromSql<Text, Pg> for ReportDestination {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "file" => Ok(ReportDestination::File),
+            other => Err(format!("Unknown report destination \"{other}\"").into()),
+        }
+    }
+}
+
+/// The outcome of the last run of a scheduled report
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    /// The report hasn't run yet
+    Pending,
+    /// The report was written
+    Succeeded,
+    /// The report couldn't be rendered or written, see `last_error`
+    Failed,
+}
+
+impl RunStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Pending => "pending",
+            RunStatus::Succeeded => "succeeded",
+            RunStatus::Failed => "failed",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for RunStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for RunStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "pending" => Ok(RunStatus::Pending),
+            "succeeded" => Ok(RunStatus::Succeeded),
+            "failed" => Ok(RunStatus::Failed),
+            other => Err(format!("Unknown run status \"{other}\"").into()),
+        }
+    }
+}
+
+/// A report of a user that is rendered on a schedule
+#[derive(Debug, Serialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = scheduled_reports)]
+pub struct ScheduledReport {
+    /// Scheduled report ID
+    id: i32,
+    /// ID of the user that owns the report
+    user_id: i32,
+    /// The report that is rendered
+    kind: ReportKind,
+    /// Name of the plan whose budgets are compared, only for `budget_vs_actual` reports
+    plan_name: Option<String>,
+    /// How often the report is rendered
+    cadence: ReportCadence,
+    /// The format the report is written in
+    format: ReportFormat,
+    /// Where the report is delivered
+    destination: ReportDestination,
+    /// The timestamp of the next run
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    next_run_at: NaiveDateTime,
+    /// The timestamp of the last run, if any
+    #[serde(default, with = "crate::utils::serialization::option")]
+    #[schema(value_type = Option<String>)]
+    last_run_at: Option<NaiveDateTime>,
+    /// The outcome of the last run
+    status: RunStatus,
+    /// Why the last run failed, if it did
+    last_error: Option<String>,
+    /// The timestamp when the report was scheduled
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: NaiveDateTime,
+}
+
+/// Fields of a scheduled report
+#[derive(Debug, Clone)]
+pub struct ScheduledReportInput {
+    /// The report that is rendered
+    pub kind: ReportKind,
+    /// Name of the plan whose budgets are compared, required for `budget_vs_actual` reports only
+    pub plan_name: Option<String>,
+    /// How often the report is rendered
+    pub cadence: ReportCadence,
+    /// The format the report is written in
+    pub format: ReportFormat,
+    /// Where the report is delivered
+    pub destination: ReportDestination,
+}
+
+impl ScheduledReportInput {
+    /// Validate the input
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - ID of the user scheduling the report
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the input is valid, `AppError::InvalidInput` if the plan is missing or
+    /// not expected, or `AppError::NotFound` if the plan doesn't belong to the user
+    pub fn validate(&self, conn: &mut DbConn, user_id: i32) -> Result<(), AppError> {
+        match (self.kind, &self.plan_name) {
+            (ReportKind::BudgetVsActual, Some(plan_name)) => {
+                Plan::from_name(conn, plan_name, user_id)?;
+                Ok(())
+            }
+            (ReportKind::BudgetVsActual, None) => Err(AppError::InvalidInput(
+                "A budget_vs_actual report needs the name of a plan".to_string(),
+            )),
+            (ReportKind::MonthlySummary, Some(_)) => Err(AppError::InvalidInput(
+                "Only budget_vs_actual reports are for a plan".to_string(),
+            )),
+            (ReportKind::MonthlySummary, None) => Ok(()),
+        }
+    }
+}
+
+impl ScheduledReport {
+    /// Schedule a report for a user
+    ///
+    /// The first run is the next one of the cadence after `now`.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - ID of the user scheduling the report
+    /// * `input` - The fields of the report
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The newly scheduled report, `AppError::InvalidInput` if the input is invalid, or
+    /// `AppError::NotFound` if the plan doesn't belong to the user
+    pub fn new(
+        conn: &mut DbConn,
+        user_id: i32,
+        input: &ScheduledReportInput,
+        now: NaiveDateTime,
+    ) -> Result<Self, AppError> {
+        input.validate(conn, user_id)?;
+
+        diesel::insert_into(scheduled_reports::table)
+            .values((
+                scheduled_reports::user_id.eq(user_id),
+                scheduled_reports::kind.eq(input.kind),
+                scheduled_reports::plan_name.eq(&input.plan_name),
+                scheduled_reports::cadence.eq(input.cadence),
+                scheduled_reports::format.eq(input.format),
+                scheduled_reports::destination.eq(input.destination),
+                scheduled_reports::next_run_at.eq(input.cadence.next_run_after(now)),
+            ))
+            .get_result::<ScheduledReport>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed scheduling report for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a scheduled report by ID
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Scheduled report ID
+    /// * `user_id` - ID of the user that owns the report
+    ///
+    /// # Returns
+    ///
+    /// The scheduled report, or `AppError::NotFound` if it doesn't exist or belongs to another
+    /// user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        scheduled_reports::table
+            .filter(scheduled_reports::id.eq(id))
+            .filter(scheduled_reports::user_id.eq(user_id))
+            .first::<ScheduledReport>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting scheduled report {id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get all scheduled reports of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// A vector of scheduled reports, oldest first
+    pub fn get_all(conn: &mut DbConn, user_id: i32) -> Result<Vec<Self>, AppError> {
+        scheduled_reports::table
+            .filter(scheduled_reports::user_id.eq(user_id))
+            .order(scheduled_reports::id)
+            .load::<ScheduledReport>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting scheduled reports of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the scheduled reports of all users that are due
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// A vector of the reports whose next run is at or before `now`, earliest first
+    pub fn due(conn: &mut DbConn, now: NaiveDateTime) -> Result<Vec<Self>, AppError> {
+        scheduled_reports::table
+            .filter(scheduled_reports::next_run_at.le(now))
+            .order((scheduled_reports::next_run_at, scheduled_reports::id))
+            .load::<ScheduledReport>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting due scheduled reports ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Update the scheduled report
+    ///
+    /// The next run is moved to the next one of the cadence after `now`, the outcome of the last
+    /// run is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `input` - The new fields of the report
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The updated report, `AppError::InvalidInput` if the input is invalid, or
+    /// `AppError::NotFound` if the plan doesn't belong to the user
+    pub fn update(
+        &self,
+        conn: &mut DbConn,
+        input: &ScheduledReportInput,
+        now: NaiveDateTime,
+    ) -> Result<Self, AppError> {
+        input.validate(conn, self.user_id)?;
+
+        diesel::update(scheduled_reports::table.filter(scheduled_reports::id.eq(self.id)))
+            .set((
+                scheduled_reports::kind.eq(input.kind),
+                scheduled_reports::plan_name.eq(&input.plan_name),
+                scheduled_reports::cadence.eq(input.cadence),
+                scheduled_reports::format.eq(input.format),
+                scheduled_reports::destination.eq(input.destination),
+                scheduled_reports::next_run_at.eq(input.cadence.next_run_after(now)),
+            ))
+            .get_result::<ScheduledReport>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed updating scheduled report {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Delete the scheduled report
+    ///
+    /// Files written by earlier runs are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::delete(scheduled_reports::table.filter(scheduled_reports::id.eq(self.id)))
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!("Failed deleting scheduled report {} ({e})", self.id);
+                AppError::Diesel(e)
+            })?;
+        Ok(())
+    }
+
+    /// Record a run of the report and schedule the next one
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `ran_at` - The time of the run
+    /// * `error` - Why the run failed, if it did
+    ///
+    /// # Returns
+    ///
+    /// The updated report
+    pub fn record_run(
+        &self,
+        conn: &mut DbConn,
+        ran_at: NaiveDateTime,
+        error: Option<String>,
+    ) -> Result<Self, AppError> {
+        let status = match error {
+            Some(_) => RunStatus::Failed,
+            None => RunStatus::Succeeded,
+        };
+
+        diesel::update(scheduled_reports::table.filter(scheduled_reports::id.eq(self.id)))
+            .set((
+                scheduled_reports::last_run_at.eq(ran_at),
+                scheduled_reports::status.eq(status),
+                scheduled_reports::last_error.eq(error),
+                scheduled_reports::next_run_at.eq(self.cadence.next_run_after(ran_at)),
+            ))
+            .get_result::<ScheduledReport>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed recording run of scheduled report {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the year and month a run covers, the month of the day before it
+    pub fn period(ran_at: NaiveDateTime) -> (i32, u32) {
+        let day: NaiveDate = ran_at.date() - Duration::days(1);
+        (day.year(), day.month())
+    }
+
+    /// Get the ID of the scheduled report
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the ID of the user that owns the report
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// Get the report that is rendered
+    pub fn kind(&self) -> ReportKind {
+        self.kind
+    }
+
+    /// Get the name of the plan whose budgets are compared, if any
+    pub fn plan_name(&self) -> Option<&str> {
+        self.plan_name.as_deref()
+    }
+
+    /// Get the format the report is written in
+    pub fn format(&self) -> ReportFormat {
+        self.format
+    }
+}
@@ -6,6 +6,8 @@ use super::manager::Session;
 pub struct Claims {
     /// The user ID
     pub user_id: i32,
+    /// The ID of the session, so the token stops working once the session ends
+    pub session_id: i32,
     /// The expiration timestamp (UNIX timestamp)
     exp: usize,
     /// The issued at timestamp (UNIX timestamp)
@@ -18,6 +20,7 @@ impl Claims {
     pub fn from(session: &Session) -> Self {
         Self {
             user_id: session.user_id(),
+            session_id: session.id(),
             exp: session.expires_at().and_utc().timestamp() as usize,
             iat: chrono::Utc::now().timestamp() as usize,
             nbf: chrono::Utc::now().timestamp() as usize,
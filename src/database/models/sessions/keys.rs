@@ -0,0 +1,71 @@
+use std::env;
+use std::fmt;
+
+use dotenv::dotenv;
+use jsonwebtoken::{DecodingKey, EncodingKey};
+
+/// The default secret for JWT encoding
+const DEFAULT_SECRET: &[u8] = b"default-secret-for-dev"; // Fallback for dev/test
+
+/// The keys signing and verifying the tokens of sessions, derived once from the secret
+#[derive(Clone)]
+pub struct JwtKeys {
+    /// Signs new tokens
+    encoding: EncodingKey,
+    /// Verifies the tokens of requests
+    decoding: DecodingKey,
+}
+
+impl JwtKeys {
+    /// Derives the keys from a secret
+    ///
+    /// # Arguments
+    ///
+    /// * `secret` - The secret shared by the keys
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+        }
+    }
+
+    /// Keys that can't sign the tokens of sessions, to test what happens when signing fails
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn unusable() -> Self {
+        Self {
+            // Ed25519 keys can't sign the HS256 tokens of sessions
+            encoding: EncodingKey::from_ed_der(&[]),
+            decoding: DecodingKey::from_secret(DEFAULT_SECRET),
+        }
+    }
+
+    /// Derives the keys from the `JWT_SECRET` environment variable, or from a default secret for
+    /// development if it isn't set
+    pub fn from_env() -> Self {
+        dotenv().ok(); // Load .env file
+        let secret = env::var("JWT_SECRET")
+            .map(|s| s.into_bytes())
+            .unwrap_or_else(|_| {
+                tracing::warn!("JWT_SECRET not set, using default secret.");
+                DEFAULT_SECRET.to_vec()
+            });
+
+        Self::from_secret(&secret)
+    }
+
+    /// Gets the key signing new tokens
+    pub fn encoding(&self) -> &EncodingKey {
+        &self.encoding
+    }
+
+    /// Gets the key verifying tokens
+    pub fn decoding(&self) -> &DecodingKey {
+        &self.decoding
+    }
+}
+
+impl fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JwtKeys { .. }")
+    }
+}
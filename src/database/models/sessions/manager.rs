@@ -1,11 +1,11 @@
-use std::env;
-
 use diesel::prelude::*;
-use dotenv::dotenv;
-use jsonwebtoken::{encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{encode, Header, Validation};
 
 use super::claims::Claims;
+use super::keys::JwtKeys;
+use crate::audit;
 use crate::database::connection::DbConn;
+use crate::database::models::audit_events::{AuditEventKind, NewAuditEvent};
 use crate::database::schema::sessions;
 use crate::errors::AppError;
 
@@ -21,19 +21,20 @@ pub struct Session {
     expires_at: chrono::NaiveDateTime,
     /// The timestamp when the session was created
     created_at: chrono::NaiveDateTime,
+    /// The ID of the administrator acting as the user, if the session is an impersonation
+    impersonator_user_id: Option<i32>,
 }
 
-/// The default secret for JWT encoding
-const DEFAULT_SECRET: &[u8] = b"default-secret-for-dev"; // Fallback for dev/test
-
 /// username and password hash.
 #[derive(Insertable)]
-#[table_name = "sessions"]
+#[diesel(table_name = sessions)]
 struct NewSession {
     /// The user ID
     user_id: i32,
     /// The session token
     expires_at: chrono::NaiveDateTime,
+    /// The ID of the administrator acting as the user, if any
+    impersonator_user_id: Option<i32>,
 }
 
 impl Session {
@@ -43,27 +44,176 @@ impl Session {
     ///
     /// * `conn` - Connection to the database
     /// * `user_id` - User ID
+    /// * `ttl` - How long the session lasts
+    /// * `now` - The current time, in UTC, the session lasts from
     ///
     /// # Returns
     ///
     /// The newly created session, otherwise an error
-    pub fn new(conn: &mut DbConn, user_id: i32) -> Result<Self, AppError> {
-        let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+    pub fn new(
+        conn: &mut DbConn,
+        user_id: i32,
+        ttl: chrono::Duration,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Self, AppError> {
+        let expires_at = now + ttl;
 
         let new_session = NewSession {
             user_id,
             expires_at,
+            impersonator_user_id: None,
         };
 
         diesel::insert_into(sessions::table)
             .values(&new_session)
-            .execute(conn)
+            .get_result(conn)
             .map_err(|e| {
                 tracing::error!("Failed to create session: {e:?}");
                 AppError::Diesel(e)
-            })?;
+            })
+    }
+
+    /// Creates a new session and signs its token
+    ///
+    /// The session is only kept if its token could be signed, so a failure, e.g. misconfigured
+    /// keys, doesn't leave a session nobody holds the token of.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `keys` - The keys signing the token
+    /// * `user_id` - User ID
+    /// * `ttl` - How long the session lasts
+    /// * `now` - The current time, in UTC, the session lasts from
+    ///
+    /// # Returns
+    ///
+    /// The new session and its token, or `AuthenticateError::TokenCreation` if the token couldn't
+    /// be signed
+    pub fn issue(
+        conn: &mut DbConn,
+        keys: &JwtKeys,
+        user_id: i32,
+        ttl: chrono::Duration,
+        now: chrono::NaiveDateTime,
+    ) -> Result<(Self, String), AppError> {
+        conn.transaction(|conn| {
+            let session = Session::new(conn, user_id, ttl, now)?;
+            let token = session.token(keys)?;
+            Ok((session, token))
+        })
+    }
+
+    /// Creates a session of a user for an administrator acting as them, and signs its token
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `keys` - The keys signing the token
+    /// * `user_id` - ID of the user to act as
+    /// * `impersonator_user_id` - ID of the administrator
+    /// * `ttl` - How long the session lasts
+    /// * `now` - The current time, in UTC, the session lasts from
+    ///
+    /// # Returns
+    ///
+    /// The new session and its token, or `AuthenticateError::TokenCreation` if the token couldn't
+    /// be signed
+    pub fn impersonate(
+        conn: &mut DbConn,
+        keys: &JwtKeys,
+        user_id: i32,
+        impersonator_user_id: i32,
+        ttl: chrono::Duration,
+        now: chrono::NaiveDateTime,
+    ) -> Result<(Self, String), AppError> {
+        conn.transaction(|conn| {
+            let session = diesel::insert_into(sessions::table)
+                .values(&NewSession {
+                    user_id,
+                    expires_at: now + ttl,
+                    impersonator_user_id: Some(impersonator_user_id),
+                })
+                .get_result::<Session>(conn)
+                .map_err(|e| {
+                    tracing::error!("Failed to create impersonation session: {e:?}");
+                    AppError::Diesel(e)
+                })?;
+            let token = session.token(keys)?;
+            Ok((session, token))
+        })
+    }
 
-        Session::from_user_id(conn, user_id)
+    /// Gets the sessions of a user that haven't expired, most recent first
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `now` - The current time, in UTC, the sessions must not have expired at
+    ///
+    /// # Returns
+    ///
+    /// A vector of sessions
+    pub fn get_all_of_user(
+        conn: &mut DbConn,
+        user_id: i32,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Vec<Self>, AppError> {
+        sessions::table
+            .filter(sessions::user_id.eq(user_id))
+            .filter(sessions::expires_at.gt(now))
+            .order((sessions::created_at.desc(), sessions::id.desc()))
+            .load::<Session>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed to get the sessions of user {user_id}: {e:?}");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Replaces a session with a new one of the same user, expiring when it would have
+    ///
+    /// The session is deleted, so its token stops working at once. Sessions are rotated when the
+    /// privileges of their user change, so that a token obtained before, e.g. planted by an
+    /// attacker, can't be used after.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `old_session` - The session to replace
+    ///
+    /// # Returns
+    ///
+    /// The new session, or `AuthenticateError::InvalidToken` if the session already ended
+    pub fn rotate(conn: &mut DbConn, old_session: &Session) -> Result<Self, AppError> {
+        let session = conn.transaction(|conn| {
+            let deleted = diesel::delete(sessions::table.filter(sessions::id.eq(old_session.id)))
+                .execute(conn)?;
+            if deleted == 0 {
+                return Err(AppError::Authenticate(
+                    crate::errors::AuthenticateError::InvalidToken,
+                ));
+            }
+
+            diesel::insert_into(sessions::table)
+                .values(&NewSession {
+                    user_id: old_session.user_id,
+                    expires_at: old_session.expires_at,
+                    impersonator_user_id: old_session.impersonator_user_id,
+                })
+                .get_result::<Session>(conn)
+                .map_err(AppError::Diesel)
+        })?;
+
+        audit::record(
+            conn,
+            NewAuditEvent::new(
+                AuditEventKind::SessionRevoked,
+                Some(old_session.user_id),
+                serde_json::json!({ "session_id": old_session.id, "reason": "rotated" }),
+            ),
+        );
+        Ok(session)
     }
 
     /// Deletes a session
@@ -86,18 +236,57 @@ impl Session {
         Ok(())
     }
 
-    /// Gets a session by user ID
+    /// Ends the session before it expires, such as when the user logs out
     ///
     /// # Arguments
     ///
     /// * `conn` - Connection to the database
+    /// * `reason` - Why the session was ended
+    /// * `ip` - IP address of the client that ended the session, if known
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn revoke(
+        &self,
+        conn: &mut DbConn,
+        reason: &str,
+        ip: Option<String>,
+    ) -> Result<(), AppError> {
+        self.delete(conn)?;
+
+        audit::record(
+            conn,
+            NewAuditEvent::new(
+                AuditEventKind::SessionRevoked,
+                Some(self.user_id),
+                serde_json::json!({ "session_id": self.id, "reason": reason }),
+            )
+            .with_ip(ip),
+        );
+        Ok(())
+    }
+
+    /// Gets a session of a user by ID
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Session ID
     /// * `user_id` - User ID
+    /// * `now` - The current time, in UTC, the session must not have expired at
     ///
     /// # Returns
     ///
-    /// The session if it exists, otherwise an error
-    pub fn from_user_id(conn: &mut DbConn, user_id: i32) -> Result<Self, AppError> {
+    /// The session if it exists and hasn't expired, otherwise an error
+    pub fn from_id(
+        conn: &mut DbConn,
+        id: i32,
+        user_id: i32,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Self, AppError> {
         let session = sessions::table
+            .filter(sessions::id.eq(id))
             .filter(sessions::user_id.eq(user_id))
             .first::<Session>(conn)
             .map_err(|e| {
@@ -106,7 +295,7 @@ impl Session {
             })?;
 
         // Verify that the session hasn't expired yet
-        if chrono::Utc::now().naive_utc() < session.expires_at {
+        if now < session.expires_at {
             return Ok(session);
         }
         session.delete(conn)?;
@@ -121,55 +310,52 @@ impl Session {
     /// # Arguments
     ///
     /// * `conn` - Connection to the database
+    /// * `keys` - The keys verifying the token
     /// * `token` - The token to decode
+    /// * `now` - The current time, in UTC, the session must not have expired at
     ///
     /// # Returns
     ///
     /// The session if the token is valid
-    pub fn from_token(conn: &mut DbConn, token: &str) -> Result<Self, AppError> {
+    pub fn from_token(
+        conn: &mut DbConn,
+        keys: &JwtKeys,
+        token: &str,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Self, AppError> {
         let validation = Validation::default();
 
-        let secret = Session::get_secret();
-        let claims =
-            jsonwebtoken::decode::<Claims>(token, &DecodingKey::from_secret(&secret), &validation)
-                .map(|data| data.claims)
-                .map_err(|e| {
-                    tracing::error!("Failed to decode token: {e:?}");
-                    AppError::Authenticate(crate::errors::AuthenticateError::InvalidToken)
-                })?;
+        let claims = jsonwebtoken::decode::<Claims>(token, keys.decoding(), &validation)
+            .map(|data| data.claims)
+            .map_err(|e| {
+                tracing::error!("Failed to decode token: {e:?}");
+                AppError::Authenticate(crate::errors::AuthenticateError::InvalidToken)
+            })?;
 
         // Verify that the session exists in the database
-        Session::from_user_id(conn, claims.user_id)
+        Session::from_id(conn, claims.session_id, claims.user_id, now)
     }
 
     /// Creates a new token
     ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys signing the token
+    ///
     /// # Returns
     ///
     /// The token as a string if successful, otherwise an error
-    pub fn token(&self) -> Result<String, AppError> {
-        let secret = Session::get_secret();
+    pub fn token(&self, keys: &JwtKeys) -> Result<String, AppError> {
         let claims = Claims::from(self);
-        encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(&secret),
-        )
-        .map_err(|e| {
+        encode(&Header::default(), &claims, keys.encoding()).map_err(|e| {
             tracing::error!("Failed to create token: {e:?}");
             AppError::Authenticate(crate::errors::AuthenticateError::TokenCreation)
         })
     }
 
-    /// Gets the secret key for encoding/decoding JWT tokens
-    fn get_secret() -> Vec<u8> {
-        dotenv().ok(); // Load .env file
-        env::var("JWT_SECRET")
-            .map(|s| s.into_bytes())
-            .unwrap_or_else(|_| {
-                tracing::warn!("JWT_SECRET not set, using default secret.");
-                DEFAULT_SECRET.to_vec()
-            })
+    /// Gets the session ID
+    pub fn id(&self) -> i32 {
+        self.id
     }
 
     /// Gets the user ID
@@ -181,10 +367,21 @@ impl Session {
     pub fn expires_at(&self) -> chrono::NaiveDateTime {
         self.expires_at
     }
+
+    /// Gets the timestamp when the session was created
+    pub fn created_at(&self) -> chrono::NaiveDateTime {
+        self.created_at
+    }
+
+    /// Gets the ID of the administrator acting as the user, if the session is an impersonation
+    pub fn impersonator_user_id(&self) -> Option<i32> {
+        self.impersonator_user_id
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::clock::{Clock, FixedClock};
     use crate::database::{connection::DbPool, models::users::User};
 
     use super::*;
@@ -194,10 +391,11 @@ mod tests {
         let pool = DbPool::new_test();
         let conn = &mut pool.get().unwrap();
         conn.begin_test_transaction().unwrap();
+        let now = FixedClock::at_system_time().now_utc();
 
         let user = User::default(conn).unwrap();
         let user_id = user.id();
-        let session = Session::new(conn, user_id).unwrap();
+        let session = Session::new(conn, user_id, chrono::Duration::days(1), now).unwrap();
 
         assert_eq!(session.user_id, user_id);
 
@@ -208,7 +406,7 @@ mod tests {
             .unwrap();
 
         assert_eq!(found_session.user_id, user_id);
-        assert!(chrono::Utc::now().naive_utc() < found_session.expires_at);
+        assert_eq!(found_session.expires_at, now + chrono::Duration::days(1));
     }
 
     #[test]
@@ -216,15 +414,133 @@ mod tests {
         let pool = DbPool::new_test();
         let conn = &mut pool.get().unwrap();
         conn.begin_test_transaction().unwrap();
+        let now = FixedClock::at_system_time().now_utc();
 
         let user = User::default(conn).unwrap();
         let user_id = user.id();
-        let session = Session::new(conn, user_id).unwrap();
+        let session = Session::new(conn, user_id, chrono::Duration::days(1), now).unwrap();
 
-        let token = session.token().unwrap();
+        let keys = JwtKeys::from_secret(b"test-secret");
+        let token = session.token(&keys).unwrap();
 
-        let decoded_session = Session::from_token(conn, &token).unwrap();
+        let decoded_session = Session::from_token(conn, &keys, &token, now).unwrap();
 
         assert_eq!(decoded_session.user_id, user_id);
+
+        // Tokens of ended sessions are rejected, although their user has other sessions
+        let other = Session::new(conn, user_id, chrono::Duration::days(1), now).unwrap();
+        other.delete(conn).unwrap();
+        assert!(Session::from_token(conn, &keys, &other.token(&keys).unwrap(), now).is_err());
+
+        // Tokens signed with another secret are rejected
+        let other = JwtKeys::from_secret(b"other-secret");
+        assert!(matches!(
+            Session::from_token(conn, &other, &token, now),
+            Err(AppError::Authenticate(
+                crate::errors::AuthenticateError::InvalidToken
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let now = FixedClock::at_system_time().now_utc();
+
+        let user = User::default(conn).unwrap();
+        let session = Session::new(conn, user.id(), chrono::Duration::days(1), now).unwrap();
+        let keys = JwtKeys::from_secret(b"test-secret");
+        let old_token = session.token(&keys).unwrap();
+
+        let rotated = Session::rotate(conn, &session).unwrap();
+        assert_ne!(rotated.id, session.id);
+        assert_eq!(rotated.user_id, session.user_id);
+        assert_eq!(rotated.expires_at, session.expires_at);
+
+        assert!(Session::from_token(conn, &keys, &old_token, now).is_err());
+        let token = rotated.token(&keys).unwrap();
+        assert_eq!(
+            Session::from_token(conn, &keys, &token, now).unwrap().id,
+            rotated.id
+        );
+
+        // A session can only be rotated once
+        assert!(matches!(
+            Session::rotate(conn, &session),
+            Err(AppError::Authenticate(
+                crate::errors::AuthenticateError::InvalidToken
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_session_expires() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let clock = FixedClock::at_system_time();
+
+        let user = User::default(conn).unwrap();
+        let session =
+            Session::new(conn, user.id(), chrono::Duration::hours(1), clock.now_utc()).unwrap();
+        let keys = JwtKeys::from_secret(b"test-secret");
+        let token = session.token(&keys).unwrap();
+
+        clock.advance(chrono::Duration::minutes(59));
+        assert!(Session::from_token(conn, &keys, &token, clock.now_utc()).is_ok());
+
+        // The session is deleted once it expired
+        clock.advance(chrono::Duration::minutes(1));
+        assert!(matches!(
+            Session::from_token(conn, &keys, &token, clock.now_utc()),
+            Err(AppError::Authenticate(
+                crate::errors::AuthenticateError::SessionExpired
+            ))
+        ));
+        assert!(sessions::table
+            .find(session.id)
+            .first::<Session>(conn)
+            .optional()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_issue_without_token_keeps_no_session() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let now = FixedClock::at_system_time().now_utc();
+        let user = User::default(conn).unwrap();
+        let count = |conn: &mut DbConn| {
+            sessions::table
+                .filter(sessions::user_id.eq(user.id()))
+                .count()
+                .get_result::<i64>(conn)
+                .unwrap()
+        };
+
+        assert!(matches!(
+            Session::issue(
+                conn,
+                &JwtKeys::unusable(),
+                user.id(),
+                chrono::Duration::days(1),
+                now
+            ),
+            Err(AppError::Authenticate(
+                crate::errors::AuthenticateError::TokenCreation
+            ))
+        ));
+        assert_eq!(count(conn), 0);
+
+        let keys = JwtKeys::from_secret(b"test-secret");
+        let (session, token) =
+            Session::issue(conn, &keys, user.id(), chrono::Duration::days(1), now).unwrap();
+        assert_eq!(count(conn), 1);
+        let found = Session::from_token(conn, &keys, &token, now).unwrap();
+        assert_eq!(found.id(), session.id());
     }
 }
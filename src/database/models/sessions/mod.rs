@@ -1,2 +1,3 @@
 mod claims;
+pub mod keys;
 pub mod manager;
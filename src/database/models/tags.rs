@@ -0,0 +1,407 @@
+use diesel::{dsl::count, prelude::*, sql_types::Text};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::transactions::Transaction,
+    schema::{tags, transaction_tags},
+};
+use crate::errors::AppError;
+
+/// Maximum length of a tag name, in characters
+pub const MAX_TAG_NAME_LENGTH: usize = 40;
+
+diesel::define_sql_function! {
+    /// Lowercase a string, to compare tag names regardless of case
+    fn lower(x: Text) -> Text;
+}
+
+/// A free-form label on transactions, like `vacation2024`
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = tags)]
+pub struct Tag {
+    /// Tag ID
+    id: i32,
+    /// ID of the user that owns the tag
+    user_id: i32,
+    /// Tag name, unique per user regardless of case
+    name: String,
+    /// Icon of the tag, if any
+    icon: Option<String>,
+    /// The timestamp when the tag was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+}
+
+/// A tag with the number of transactions it is on
+#[derive(Debug, Serialize, Deserialize, Queryable, ToSchema)]
+pub struct TagUsage {
+    /// The tag
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    tag: Tag,
+    /// Number of transactions with the tag
+    usage_count: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = tags)]
+struct NewTag<'a> {
+    user_id: i32,
+    name: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = transaction_tags)]
+struct NewTransactionTag {
+    transaction_id: i32,
+    tag_id: i32,
+}
+
+/// Trim and validate tag names
+///
+/// # Returns
+///
+/// The trimmed names without duplicates (regardless of case), or `AppError::InvalidInput` if a
+/// name is empty or longer than `MAX_TAG_NAME_LENGTH` characters
+pub fn normalize_names(names: &[String]) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = vec![];
+    for name in names {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::InvalidInput(
+                "Tag names must not be empty".to_string(),
+            ));
+        }
+        if name.chars().count() > MAX_TAG_NAME_LENGTH {
+            return Err(AppError::InvalidInput(format!(
+                "Tag \"{name}\" is longer than {MAX_TAG_NAME_LENGTH} characters"
+            )));
+        }
+        if !normalized
+            .iter()
+            .any(|n| n.to_lowercase() == name.to_lowercase())
+        {
+            normalized.push(name.to_string());
+        }
+    }
+    Ok(normalized)
+}
+
+impl Tag {
+    /// Get the tags of a user by name, creating the ones that don't exist yet
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `names` - Tag names, as returned by `normalize_names`
+    ///
+    /// # Returns
+    ///
+    /// The tags, matched regardless of case. Existing tags keep their original case.
+    pub fn find_or_create(
+        conn: &mut DbConn,
+        user_id: i32,
+        names: &[String],
+    ) -> Result<Vec<Self>, AppError> {
+        let new_tags: Vec<NewTag> = names.iter().map(|name| NewTag { user_id, name }).collect();
+
+        // Names that already exist for the user conflict on the case-insensitive unique index
+        diesel::insert_into(tags::table)
+            .values(&new_tags)
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!("Failed creating tags for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?;
+
+        let lowered: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+        tags::table
+            .filter(tags::user_id.eq(user_id))
+            .filter(lower(tags::name).eq_any(lowered))
+            .order(tags::name)
+            .load::<Tag>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting tags for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get all tags of a user with the number of transactions each is on
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// A vector of tags ordered by name
+    pub fn get_all(conn: &mut DbConn, user_id: i32) -> Result<Vec<TagUsage>, AppError> {
+        tags::table
+            .left_join(transaction_tags::table)
+            .filter(tags::user_id.eq(user_id))
+            .group_by(tags::id)
+            .select((
+                tags::all_columns,
+                count(transaction_tags::transaction_id.nullable()),
+            ))
+            .order(tags::name)
+            .load::<TagUsage>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting tags for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the tags of a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction` - The transaction
+    ///
+    /// # Returns
+    ///
+    /// A vector of tags ordered by name
+    pub fn of_transaction(
+        conn: &mut DbConn,
+        transaction: &Transaction,
+    ) -> Result<Vec<Self>, AppError> {
+        tags::table
+            .inner_join(transaction_tags::table)
+            .filter(transaction_tags::transaction_id.eq(transaction.id()))
+            .select(tags::all_columns)
+            .order(tags::name)
+            .load::<Tag>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting tags of transaction {} ({e})",
+                    transaction.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Count the tags on some transactions
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction_ids` - IDs of the transactions
+    ///
+    /// # Returns
+    ///
+    /// The number of tags, a tag counting once per transaction it is on
+    pub fn count_on(conn: &mut DbConn, transaction_ids: &[i32]) -> Result<u64, AppError> {
+        transaction_tags::table
+            .filter(transaction_tags::transaction_id.eq_any(transaction_ids))
+            .count()
+            .get_result::<i64>(conn)
+            .map(|count| count as u64)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed counting tags of {} transactions ({e})",
+                    transaction_ids.len()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Tag a transaction, ignoring tags it already has
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction` - The transaction, must belong to the owner of the tags
+    /// * `tags` - The tags to add
+    ///
+    /// # Returns
+    ///
+    /// The number of tags added
+    pub fn tag(
+        conn: &mut DbConn,
+        transaction: &Transaction,
+        tags: &[Tag],
+    ) -> Result<usize, AppError> {
+        let new_rows: Vec<NewTransactionTag> = tags
+            .iter()
+            .map(|tag| NewTransactionTag {
+                transaction_id: transaction.id(),
+                tag_id: tag.id,
+            })
+            .collect();
+
+        diesel::insert_into(transaction_tags::table)
+            .values(&new_rows)
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!("Failed tagging transaction {} ({e})", transaction.id());
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Remove tags from a transaction by name, ignoring tags it doesn't have
+    ///
+    /// The tags themselves are kept, even if no transaction has them anymore.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transaction` - The transaction
+    /// * `names` - Tag names, as returned by `normalize_names`
+    ///
+    /// # Returns
+    ///
+    /// The number of tags removed
+    pub fn untag(
+        conn: &mut DbConn,
+        transaction: &Transaction,
+        names: &[String],
+    ) -> Result<usize, AppError> {
+        let lowered: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+        let tag_ids = tags::table
+            .filter(lower(tags::name).eq_any(lowered))
+            .select(tags::id);
+
+        diesel::delete(
+            transaction_tags::table
+                .filter(transaction_tags::transaction_id.eq(transaction.id()))
+                .filter(transaction_tags::tag_id.eq_any(tag_ids)),
+        )
+        .execute(conn)
+        .map_err(|e| {
+            tracing::error!("Failed untagging transaction {} ({e})", transaction.id());
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the name of the tag
+    #[cfg(test)]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::{Account, AccountKind},
+            transactions::{TransactionFilter, TransactionInput},
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        normalize_names(&names.iter().map(|n| n.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_names() {
+        assert_eq!(
+            names(&["  Vacation2024 ", "vacation2024", "Work"]),
+            vec!["Vacation2024", "Work"]
+        );
+        assert!(normalize_names(&["  ".to_string()]).is_err());
+        assert!(normalize_names(&["x".repeat(41)]).is_err());
+        assert!(normalize_names(&["é".repeat(40)]).is_ok());
+    }
+
+    #[test]
+    fn test_tagging() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let hotel = Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(zero.clone(), "Hotel", date),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        let rent = Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(zero, "Rent", date),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+
+        let tags =
+            Tag::find_or_create(conn, user.id(), &names(&["Vacation2024", "Travel"])).unwrap();
+        assert_eq!(Tag::tag(conn, &hotel, &tags).unwrap(), 2);
+
+        // Adding the same tags again, in another case, is a no-op
+        let again = Tag::find_or_create(conn, user.id(), &names(&["vacation2024"])).unwrap();
+        assert_eq!(again[0].id, tags[1].id);
+        assert_eq!(again[0].name(), "Vacation2024");
+        assert_eq!(Tag::tag(conn, &hotel, &again).unwrap(), 0);
+
+        let usage = Tag::get_all(conn, user.id()).unwrap();
+        assert_eq!(usage.len(), 2);
+        assert!(usage.iter().all(|u| u.usage_count == 1));
+
+        // Only tagged transactions match the filter
+        let filter = TransactionFilter {
+            tag: Some("VACATION2024".to_string()),
+            ..Default::default()
+        };
+        let tagged = Transaction::search(conn, &account, &filter).unwrap();
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].id(), hotel.id());
+        assert_eq!(
+            Transaction::search(conn, &account, &TransactionFilter::default())
+                .unwrap()
+                .len(),
+            2
+        );
+
+        assert_eq!(Tag::untag(conn, &hotel, &names(&["travel"])).unwrap(), 1);
+        assert_eq!(Tag::untag(conn, &rent, &names(&["travel"])).unwrap(), 0);
+        let remaining = Tag::of_transaction(conn, &hotel).unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].name(), "Vacation2024");
+    }
+
+    #[test]
+    fn test_tags_are_scoped_to_user() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let other = User::new(conn, "other_user", "other_password").unwrap();
+
+        let mine = Tag::find_or_create(conn, user.id(), &names(&["Vacation2024"])).unwrap();
+        let theirs = Tag::find_or_create(conn, other.id(), &names(&["vacation2024"])).unwrap();
+
+        assert_ne!(mine[0].id, theirs[0].id);
+        assert_eq!(theirs[0].name(), "vacation2024");
+        assert_eq!(Tag::get_all(conn, user.id()).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,1758 @@
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Date, Integer, Nullable, Numeric};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        accounts::Account, attachments::Attachment, categories::Category,
+        exchange_rates::validate_currency_code, tags::lower,
+    },
+    schema::{
+        accounts, categories, cleared_transactions, reconciliations, tags, transaction_splits,
+        transaction_tags, transactions,
+    },
+};
+use crate::errors::{AppError, CurrencyMismatch};
+use crate::quotas::{Quotas, Resource};
+use crate::search::ilike::like_pattern;
+
+/// Transaction struct
+///
+/// A positive amount is money coming into the account, a negative amount is money leaving it.
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = transactions)]
+pub struct Transaction {
+    /// Transaction ID
+    id: i32,
+    /// Signed amount of the transaction, in the currency of the account, which balances and
+    /// reports use
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// ISO 4217 currency code of the amount, the currency of the account
+    currency: String,
+    /// The timestamp when the transaction was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+    /// ID of the account the transaction belongs to
+    account_id: i32,
+    /// Description of the transaction, usually as provided by the bank
+    description: String,
+    /// The date the transaction occurred on
+    #[schema(value_type = String)]
+    occurred_at: NaiveDate,
+    /// ID of the category of the transaction, if categorized
+    category_id: Option<i32>,
+    /// ID of the recurring transaction this transaction was generated from, if any
+    recurring_id: Option<i32>,
+    /// ID of the savings goal the transaction counts towards, if any
+    goal_id: Option<i32>,
+    /// The payee of the payee rule that matched the description, if any
+    payee: Option<String>,
+    /// ID of the transfer the transaction is a leg of, if any
+    transfer_id: Option<Uuid>,
+    /// Signed amount in the currency the transaction was made in, if it isn't the account's
+    #[schema(value_type = Option<String>)]
+    original_amount: Option<BigDecimal>,
+    /// ISO 4217 currency code of the original amount, if any
+    original_currency: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = transactions)]
+struct NewTransaction<'a> {
+    account_id: i32,
+    category_id: Option<i32>,
+    amount: &'a BigDecimal,
+    currency: &'a str,
+    original_amount: Option<&'a BigDecimal>,
+    original_currency: Option<&'a str>,
+    description: &'a str,
+    payee: Option<&'a str>,
+    occurred_at: NaiveDate,
+    recurring_id: Option<i32>,
+    goal_id: Option<i32>,
+    transfer_id: Option<Uuid>,
+}
+
+/// Fields of a transaction to be updated
+#[derive(AsChangeset)]
+#[diesel(table_name = transactions, treat_none_as_null = true)]
+struct TransactionChanges<'a> {
+    category_id: Option<i32>,
+    amount: &'a BigDecimal,
+    original_amount: Option<&'a BigDecimal>,
+    original_currency: Option<&'a str>,
+    description: &'a str,
+    occurred_at: NaiveDate,
+    goal_id: Option<i32>,
+}
+
+/// A part of the amount of a transaction attributed to a category
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = transaction_splits)]
+pub struct TransactionSplit {
+    /// Split ID
+    id: i32,
+    /// ID of the transaction the split is a part of
+    transaction_id: i32,
+    /// ID of the category the split is attributed to, if it still exists
+    category_id: Option<i32>,
+    /// Signed amount of the split, as a decimal string
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+}
+
+/// A part of the amount of a transaction to attribute to a category
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct SplitInput {
+    /// ID of the category, must belong to the account's owner
+    pub category_id: i32,
+    /// Signed amount of the split, as a decimal string
+    #[schema(value_type = String)]
+    pub amount: BigDecimal,
+}
+
+/// Check that splits, if any, add up to the amount of their transaction
+///
+/// # Returns
+///
+/// An empty result if the splits are valid, otherwise `AppError::InvalidInput` with how far off
+/// they are
+pub fn validate_splits(amount: &BigDecimal, splits: &[SplitInput]) -> Result<(), AppError> {
+    if splits.is_empty() {
+        return Ok(());
+    }
+
+    let total: BigDecimal = splits.iter().map(|split| &split.amount).sum();
+    if &total != amount {
+        return Err(AppError::InvalidInput(format!(
+            "The splits add up to {total}, which is {} off the amount of {amount}",
+            amount - &total
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = transaction_splits)]
+struct NewSplit<'a> {
+    transaction_id: i32,
+    category_id: i32,
+    amount: &'a BigDecimal,
+}
+
+/// A transaction with the splits of its amount across categories
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SplitTransaction {
+    /// The transaction
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    transaction: Transaction,
+    /// The splits of the amount of the transaction, empty if it isn't split
+    splits: Vec<TransactionSplit>,
+}
+
+impl SplitTransaction {
+    /// Get the transaction
+    pub fn transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+
+    /// Get the IDs of the categories the amount of the transaction is attributed to
+    ///
+    /// # Returns
+    ///
+    /// The categories of the splits if the transaction is split, otherwise its own category if
+    /// it has one
+    pub fn category_ids(&self) -> Vec<i32> {
+        if self.splits.is_empty() {
+            return self.transaction.category_id.into_iter().collect();
+        }
+        self.splits
+            .iter()
+            .filter_map(|split| split.category_id)
+            .collect()
+    }
+}
+
+/// Fields of a transaction to be inserted
+#[derive(Debug, Clone)]
+pub struct TransactionInput {
+    /// Signed amount of the transaction, in the account's currency
+    pub amount: BigDecimal,
+    /// ISO 4217 currency code of the amount, which must be the account's, if the client sent one
+    pub currency: Option<String>,
+    /// Signed amount in the currency the transaction was made in, when it isn't the account's
+    pub original_amount: Option<BigDecimal>,
+    /// ISO 4217 currency code of the original amount, set along with it
+    pub original_currency: Option<String>,
+    /// Description of the transaction
+    pub description: String,
+    /// The payee of the transaction, set by payee rules
+    pub payee: Option<String>,
+    /// The date the transaction occurred on
+    pub occurred_at: NaiveDate,
+    /// ID of the category of the transaction, must belong to the account's owner
+    pub category_id: Option<i32>,
+    /// ID of the savings goal the transaction counts towards, must belong to the account's owner
+    pub goal_id: Option<i32>,
+    /// Splits of the amount across categories, empty if the transaction isn't split
+    pub splits: Vec<SplitInput>,
+}
+
+impl TransactionInput {
+    /// Create the input of an uncategorized transaction
+    pub fn new(amount: BigDecimal, description: &str, occurred_at: NaiveDate) -> Self {
+        Self {
+            amount,
+            currency: None,
+            original_amount: None,
+            original_currency: None,
+            description: description.to_string(),
+            payee: None,
+            occurred_at,
+            category_id: None,
+            goal_id: None,
+            splits: vec![],
+        }
+    }
+
+    /// Validate that the splits, if any, add up to the amount
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the input is valid, otherwise `AppError::InvalidInput` with how far off
+    /// the splits are
+    pub fn validate(&self) -> Result<(), AppError> {
+        validate_splits(&self.amount, &self.splits)
+    }
+
+    /// Check that the amount is in the currency of the account, amounts in other currencies are
+    /// converted by the client and sent with their original amount
+    ///
+    /// # Arguments
+    ///
+    /// * `account_currency` - ISO 4217 currency code of the account of the transaction
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the currencies match, `AppError::CurrencyMismatch` naming both
+    /// currencies if they don't, or `AppError::InvalidInput` if the original amount comes without
+    /// its currency, or the other way around
+    pub fn ensure_currency(&self, account_currency: &str) -> Result<(), AppError> {
+        match (&self.original_amount, &self.original_currency) {
+            (Some(_), Some(currency)) => validate_currency_code(currency)?,
+            (None, None) => {}
+            _ => {
+                return Err(AppError::InvalidInput(
+                    "`original_amount` and `original_currency` are sent together".to_string(),
+                ))
+            }
+        }
+        match &self.currency {
+            Some(currency) if currency != account_currency => {
+                Err(AppError::CurrencyMismatch(CurrencyMismatch {
+                    currency: currency.clone(),
+                    account_currency: account_currency.to_string(),
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Convert the input to an insertable row on an account, once its currency is checked
+    fn to_insertable<'a>(&'a self, account: &'a Account) -> Result<NewTransaction<'a>, AppError> {
+        self.ensure_currency(account.currency())?;
+        Ok(NewTransaction {
+            account_id: account.id(),
+            category_id: self.category_id,
+            amount: &self.amount,
+            currency: account.currency(),
+            original_amount: self.original_amount.as_ref(),
+            original_currency: self.original_currency.as_deref(),
+            description: &self.description,
+            payee: self.payee.as_deref(),
+            occurred_at: self.occurred_at,
+            recurring_id: None,
+            goal_id: self.goal_id,
+            transfer_id: None,
+        })
+    }
+}
+
+/// Criteria to narrow down the transactions of a user
+#[derive(Debug, Clone, Default)]
+pub struct TransactionFilter {
+    /// Only transactions of this account
+    pub account_id: Option<i32>,
+    /// Only transactions with this tag, regardless of case
+    pub tag: Option<String>,
+    /// Only transactions whose description or payee contains this text, regardless of case
+    pub q: Option<String>,
+    /// Only transactions that occurred on or after this day
+    pub from: Option<NaiveDate>,
+    /// Only transactions that occurred on or before this day
+    pub to: Option<NaiveDate>,
+    /// Only transactions without a category or splits
+    pub uncategorized_only: bool,
+}
+
+/// A condition on the rows of the transactions table, shared by selects and updates
+type TransactionPredicate = Box<dyn BoxableExpression<transactions::table, Pg, SqlType = Bool>>;
+
+impl TransactionFilter {
+    /// Build the condition matching the transactions of a user that pass the filter
+    ///
+    /// Listing and bulk updates use the same condition, so they always agree on the transactions
+    /// a filter matches.
+    fn predicate(&self, user_id: i32) -> TransactionPredicate {
+        let mut predicate: TransactionPredicate = Box::new(
+            transactions::account_id.eq_any(
+                accounts::table
+                    .filter(accounts::user_id.eq(user_id))
+                    .select(accounts::id),
+            ),
+        );
+
+        if let Some(account_id) = self.account_id {
+            predicate = Box::new(predicate.and(transactions::account_id.eq(account_id)));
+        }
+        if let Some(tag) = &self.tag {
+            predicate = Box::new(
+                predicate.and(diesel::dsl::exists(
+                    transaction_tags::table
+                        .inner_join(tags::table)
+                        .filter(transaction_tags::transaction_id.eq(transactions::id))
+                        .filter(lower(tags::name).eq(tag.trim().to_lowercase())),
+                )),
+            );
+        }
+        if let Some(q) = self.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+            let pattern = like_pattern(q);
+            predicate = Box::new(
+                predicate.and(
+                    transactions::description
+                        .ilike(pattern.clone())
+                        .or(transactions::payee.ilike(pattern).assume_not_null()),
+                ),
+            );
+        }
+        if let Some(from) = self.from {
+            predicate = Box::new(predicate.and(transactions::occurred_at.ge(from)));
+        }
+        if let Some(to) = self.to {
+            predicate = Box::new(predicate.and(transactions::occurred_at.le(to)));
+        }
+        if self.uncategorized_only {
+            predicate = Box::new(
+                predicate
+                    .and(transactions::category_id.is_null())
+                    .and(diesel::dsl::not(diesel::dsl::exists(
+                        transaction_splits::table
+                            .filter(transaction_splits::transaction_id.eq(transactions::id)),
+                    ))),
+            );
+        }
+        predicate
+    }
+}
+
+/// An amount of a transaction attributed to a category, for reports
+#[derive(Debug, Clone)]
+pub struct CategoryAmount {
+    /// ID of the category, if any
+    pub category_id: Option<i32>,
+    /// Signed amount
+    pub amount: BigDecimal,
+    /// ISO 4217 currency code of the amount
+    pub currency: String,
+    /// The date the transaction occurred on
+    pub occurred_at: NaiveDate,
+}
+
+/// The expenses of a user in a category in a month, for reports
+#[derive(Debug, Clone, PartialEq, QueryableByName)]
+pub struct CategoryMonth {
+    /// First day of the month
+    #[diesel(sql_type = Date)]
+    pub month: NaiveDate,
+    /// ID of the category, if any
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub category_id: Option<i32>,
+    /// Money that went out, as a positive amount
+    #[diesel(sql_type = Numeric)]
+    pub expenses: BigDecimal,
+}
+
+/// The net amount of the transactions of a user in a category in a month, for reports
+#[derive(Debug, Clone, PartialEq, QueryableByName)]
+pub struct CategoryMonthNet {
+    /// First day of the month
+    #[diesel(sql_type = Date)]
+    pub month: NaiveDate,
+    /// ID of the category, if any
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub category_id: Option<i32>,
+    /// Money that came in minus money that went out
+    #[diesel(sql_type = Numeric)]
+    pub net: BigDecimal,
+}
+
+/// What a user spent on a category, net of refunds
+#[derive(Debug, QueryableByName)]
+struct CategorySpending {
+    #[diesel(sql_type = Numeric)]
+    spent: BigDecimal,
+}
+
+/// Maximum number of rows per insert statement, to stay under Postgres' bind parameter limit
+const BULK_INSERT_CHUNK_SIZE: usize = 1000;
+
+/// A transaction as it appears in an export, with its category name resolved
+#[derive(Debug, Serialize, Queryable)]
+pub struct ExportRow {
+    /// Transaction ID, only used to page through the export
+    #[serde(skip)]
+    id: i32,
+    /// The date the transaction occurred on
+    #[serde(rename = "date")]
+    occurred_at: NaiveDate,
+    /// Description of the transaction
+    description: String,
+    /// Name of the category of the transaction, if categorized
+    category: Option<String>,
+    /// Signed amount of the transaction
+    amount: BigDecimal,
+    /// ISO 4217 currency code of the amount
+    currency: String,
+}
+
+impl ExportRow {
+    /// Get the position of the row in the export, to resume the next page after it
+    pub fn cursor(&self) -> (NaiveDate, i32) {
+        (self.occurred_at, self.id)
+    }
+
+    /// Get the date the transaction occurred on
+    pub fn occurred_at(&self) -> NaiveDate {
+        self.occurred_at
+    }
+
+    /// Get the description of the transaction
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Get the name of the category of the transaction, if categorized
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
+    /// Get the signed amount of the transaction
+    pub fn amount(&self) -> &BigDecimal {
+        &self.amount
+    }
+}
+
+impl Transaction {
+    /// Create a new transaction on an account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the transaction belongs to
+    /// * `input` - The fields of the transaction
+    /// * `quotas` - The quotas of the server
+    ///
+    /// # Returns
+    ///
+    /// The newly created transaction, `AppError::InvalidInput` if its splits don't add up to its
+    /// amount, `AppError::CurrencyMismatch` if its amount isn't in the currency of the account,
+    /// `AppError::Conflict` if the account is archived, or `AppError::QuotaExceeded` if the user
+    /// has as many transactions as they can
+    pub fn new(
+        conn: &mut DbConn,
+        account: &Account,
+        input: &TransactionInput,
+        quotas: &Quotas,
+    ) -> Result<Self, AppError> {
+        account.ensure_open()?;
+        input.validate()?;
+        let new_transaction = input.to_insertable(account)?;
+        quotas.ensure(conn, account.user_id(), Resource::Transactions, 1)?;
+
+        // The splits are inserted with the transaction, so neither persists without the other
+        conn.transaction(|conn| {
+            let transaction = diesel::insert_into(transactions::table)
+                .values(&new_transaction)
+                .get_result::<Transaction>(conn)?;
+            transaction.insert_splits(conn, &input.splits)?;
+            Ok(transaction)
+        })
+        .map_err(|e| {
+            tracing::error!(
+                "Failed creating transaction on account {} ({e})",
+                account.id()
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Create a leg of a transfer between accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the leg belongs to
+    /// * `input` - The fields of the leg, without splits
+    /// * `transfer_id` - ID of the transfer, shared with the other leg
+    ///
+    /// # Returns
+    ///
+    /// The newly created leg, or `AppError::Conflict` if the account is archived
+    pub fn new_transfer_leg(
+        conn: &mut DbConn,
+        account: &Account,
+        input: &TransactionInput,
+        transfer_id: Uuid,
+    ) -> Result<Self, AppError> {
+        account.ensure_open()?;
+        let leg = NewTransaction {
+            transfer_id: Some(transfer_id),
+            ..input.to_insertable(account)?
+        };
+
+        diesel::insert_into(transactions::table)
+            .values(&leg)
+            .get_result::<Transaction>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed creating leg of transfer {transfer_id} on account {} ({e})",
+                    account.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the legs of a transfer, scoped to the user owning their accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `transfer_id` - ID of the transfer
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The legs of the transfer, the outgoing one first, or an empty vector if the transfer doesn't
+    /// exist or belongs to another user
+    pub fn transfer_legs(
+        conn: &mut DbConn,
+        transfer_id: Uuid,
+        user_id: i32,
+    ) -> Result<Vec<Self>, AppError> {
+        transactions::table
+            .inner_join(accounts::table)
+            .filter(transactions::transfer_id.eq(transfer_id))
+            .filter(accounts::user_id.eq(user_id))
+            .select(transactions::all_columns)
+            .order((transactions::amount, transactions::id))
+            .load::<Transaction>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting legs of transfer {transfer_id} for user {user_id} ({e})"
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Update the transaction, replacing its splits
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `input` - The new fields of the transaction
+    ///
+    /// # Returns
+    ///
+    /// The updated transaction, `AppError::InvalidInput` if its splits don't add up to its
+    /// amount, or `AppError::CurrencyMismatch` if the amount isn't in the currency of its account
+    pub fn update(&self, conn: &mut DbConn, input: &TransactionInput) -> Result<Self, AppError> {
+        input.validate()?;
+        input.ensure_currency(&self.currency)?;
+
+        conn.transaction(|conn| {
+            let transaction =
+                diesel::update(transactions::table.filter(transactions::id.eq(self.id)))
+                    .set(&TransactionChanges {
+                        category_id: input.category_id,
+                        amount: &input.amount,
+                        original_amount: input.original_amount.as_ref(),
+                        original_currency: input.original_currency.as_deref(),
+                        description: &input.description,
+                        occurred_at: input.occurred_at,
+                        goal_id: input.goal_id,
+                    })
+                    .get_result::<Transaction>(conn)?;
+
+            diesel::delete(
+                transaction_splits::table.filter(transaction_splits::transaction_id.eq(self.id)),
+            )
+            .execute(conn)?;
+            transaction.insert_splits(conn, &input.splits)?;
+            Ok(transaction)
+        })
+        .map_err(|e| {
+            tracing::error!("Failed updating transaction {} ({e})", self.id);
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Delete the transaction with its splits, tags and attachments
+    ///
+    /// The files of its attachments are left for the caller to remove.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        conn.transaction(|conn| {
+            Attachment::release(conn, &[self.id])?;
+            diesel::delete(transactions::table.filter(transactions::id.eq(self.id))).execute(conn)
+        })
+        .map(|_| ())
+        .map_err(|e| {
+            tracing::error!("Failed deleting transaction {} ({e})", self.id);
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Insert splits of the transaction
+    fn insert_splits(&self, conn: &mut DbConn, splits: &[SplitInput]) -> QueryResult<usize> {
+        let new_splits: Vec<NewSplit> = splits
+            .iter()
+            .map(|split| NewSplit {
+                transaction_id: self.id,
+                category_id: split.category_id,
+                amount: &split.amount,
+            })
+            .collect();
+
+        diesel::insert_into(transaction_splits::table)
+            .values(&new_splits)
+            .execute(conn)
+    }
+
+    /// Get the splits of the transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// The transaction with its splits, which are empty if it isn't split
+    pub fn with_splits(self, conn: &mut DbConn) -> Result<SplitTransaction, AppError> {
+        let splits = transaction_splits::table
+            .filter(transaction_splits::transaction_id.eq(self.id))
+            .order(transaction_splits::id)
+            .load::<TransactionSplit>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting splits of transaction {} ({e})", self.id);
+                AppError::Diesel(e)
+            })?;
+
+        Ok(SplitTransaction {
+            transaction: self,
+            splits,
+        })
+    }
+
+    /// Create the occurrence of a recurring transaction on a date, unless it already exists
+    ///
+    /// Each recurring transaction generates at most one transaction per date, so this is safe to
+    /// retry after an interrupted run.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the transaction belongs to
+    /// * `input` - The fields of the transaction
+    /// * `recurring_id` - ID of the recurring transaction the occurrence is generated from
+    ///
+    /// # Returns
+    ///
+    /// Whether the occurrence was created, `false` if it already existed
+    pub fn new_occurrence(
+        conn: &mut DbConn,
+        account: &Account,
+        input: &TransactionInput,
+        recurring_id: i32,
+    ) -> Result<bool, AppError> {
+        let occurrence = NewTransaction {
+            recurring_id: Some(recurring_id),
+            ..input.to_insertable(account)?
+        };
+
+        diesel::insert_into(transactions::table)
+            .values(&occurrence)
+            .on_conflict((transactions::recurring_id, transactions::occurred_at))
+            .do_nothing()
+            .execute(conn)
+            .map(|inserted| inserted > 0)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed creating occurrence of recurring transaction {recurring_id} on {} ({e})",
+                    input.occurred_at
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Insert many transactions on an account at once
+    ///
+    /// All rows are inserted in a single database transaction, so either all of them or none are
+    /// persisted.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account the transactions belong to
+    /// * `inputs` - The transactions to insert
+    /// * `quotas` - The quotas of the server, which all the transactions must fit
+    ///
+    /// # Returns
+    ///
+    /// The number of inserted transactions, `AppError::Conflict` if the account is archived,
+    /// `AppError::CurrencyMismatch` if one of the amounts isn't in the currency of the account, or
+    /// `AppError::QuotaExceeded` if the transactions don't fit the quota of the user
+    pub fn bulk_insert(
+        conn: &mut DbConn,
+        account: &Account,
+        inputs: &[TransactionInput],
+        quotas: &Quotas,
+    ) -> Result<usize, AppError> {
+        account.ensure_open()?;
+        // Every row is checked before any is inserted
+        let new_transactions = inputs
+            .iter()
+            .map(|input| input.to_insertable(account))
+            .collect::<Result<Vec<_>, _>>()?;
+        quotas.ensure(
+            conn,
+            account.user_id(),
+            Resource::Transactions,
+            inputs.len() as u64,
+        )?;
+        conn.transaction(|conn| {
+            let mut inserted = 0;
+            for chunk in new_transactions.chunks(BULK_INSERT_CHUNK_SIZE) {
+                inserted += diesel::insert_into(transactions::table)
+                    .values(chunk)
+                    .execute(conn)?;
+            }
+            Ok(inserted)
+        })
+        .map_err(|e| {
+            tracing::error!(
+                "Failed bulk inserting transactions on account {} ({e})",
+                account.id()
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the IDs of the transactions of an account that occurred before a day
+    ///
+    /// Transfers are deleted whole, so the other legs of the transfers among them are included
+    /// even though they belong to other accounts.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account
+    /// * `before` - The first day whose transactions are left out
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the transactions
+    pub fn ids_before(
+        conn: &mut DbConn,
+        account: &Account,
+        before: NaiveDate,
+    ) -> Result<Vec<i32>, AppError> {
+        let mut query = || -> QueryResult<Vec<i32>> {
+            let own = transactions::table
+                .filter(transactions::account_id.eq(account.id()))
+                .filter(transactions::occurred_at.lt(before))
+                .select((transactions::id, transactions::transfer_id))
+                .load::<(i32, Option<Uuid>)>(conn)?;
+            let transfer_ids: Vec<Uuid> = own.iter().filter_map(|(_, id)| *id).collect();
+            let mut ids: Vec<i32> = own.into_iter().map(|(id, _)| id).collect();
+            if !transfer_ids.is_empty() {
+                ids.extend(
+                    transactions::table
+                        .filter(transactions::transfer_id.eq_any(&transfer_ids))
+                        .filter(transactions::account_id.ne(account.id()))
+                        .select(transactions::id)
+                        .load::<i32>(conn)?,
+                );
+            }
+            Ok(ids)
+        };
+        query().map_err(|e| {
+            tracing::error!(
+                "Failed getting transactions of account {} before {before} ({e})",
+                account.id()
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the IDs of the transactions of a user, across their accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the transactions
+    pub fn ids_of_user(conn: &mut DbConn, user_id: i32) -> Result<Vec<i32>, AppError> {
+        transactions::table
+            .inner_join(accounts::table)
+            .filter(accounts::user_id.eq(user_id))
+            .select(transactions::id)
+            .load::<i32>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting transactions of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Count the splits of some transactions
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `ids` - IDs of the transactions
+    ///
+    /// # Returns
+    ///
+    /// The number of splits
+    pub fn count_splits(conn: &mut DbConn, ids: &[i32]) -> Result<u64, AppError> {
+        transaction_splits::table
+            .filter(transaction_splits::transaction_id.eq_any(ids))
+            .count()
+            .get_result::<i64>(conn)
+            .map(|count| count as u64)
+            .map_err(|e| {
+                tracing::error!("Failed counting splits of {} transactions ({e})", ids.len());
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Delete some transactions with their splits, tags and attachments
+    ///
+    /// The files of their attachments are left for the caller to remove.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `ids` - IDs of the transactions
+    ///
+    /// # Returns
+    ///
+    /// The number of transactions deleted
+    pub fn delete_all(conn: &mut DbConn, ids: &[i32]) -> Result<u64, AppError> {
+        conn.transaction(|conn| {
+            Attachment::release(conn, ids)?;
+            diesel::delete(transactions::table.filter(transactions::id.eq_any(ids))).execute(conn)
+        })
+        .map(|rows| rows as u64)
+        .map_err(|e| {
+            tracing::error!("Failed deleting {} transactions ({e})", ids.len());
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Count the transactions of a user, across their accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    pub fn count_of_user(conn: &mut DbConn, user_id: i32) -> Result<u64, AppError> {
+        transactions::table
+            .inner_join(accounts::table)
+            .filter(accounts::user_id.eq(user_id))
+            .count()
+            .get_result::<i64>(conn)
+            .map(|count| count as u64)
+            .map_err(|e| {
+                tracing::error!("Failed counting transactions of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get all transactions of an account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account to get the transactions of
+    ///
+    /// # Returns
+    ///
+    /// A vector of transactions, most recent first
+    #[cfg(test)]
+    pub fn get_all(conn: &mut DbConn, account: &Account) -> Result<Vec<Self>, AppError> {
+        transactions::table
+            .filter(transactions::account_id.eq(account.id()))
+            .order((transactions::occurred_at.desc(), transactions::id.desc()))
+            .load::<Transaction>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting transactions of account {} ({e})",
+                    account.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the transactions of an account that match a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account to get the transactions of
+    /// * `filter` - The criteria the transactions must match
+    ///
+    /// # Returns
+    ///
+    /// A vector of transactions, most recent first
+    pub fn search(
+        conn: &mut DbConn,
+        account: &Account,
+        filter: &TransactionFilter,
+    ) -> Result<Vec<Self>, AppError> {
+        transactions::table
+            .filter(transactions::account_id.eq(account.id()))
+            .filter(filter.predicate(account.user_id()))
+            .order((transactions::occurred_at.desc(), transactions::id.desc()))
+            .load::<Transaction>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed searching transactions of account {} ({e})",
+                    account.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Build the condition matching the transactions of a user a bulk categorization changes
+    ///
+    /// Those are the transactions that pass the filter and aren't in the category already.
+    /// Legs of transfers, split transactions and transactions cleared in a finished
+    /// reconciliation are left out.
+    fn categorizable_predicate(
+        user_id: i32,
+        filter: &TransactionFilter,
+        category_id: i32,
+    ) -> TransactionPredicate {
+        Box::new(
+            filter
+                .predicate(user_id)
+                .and(transactions::transfer_id.is_null())
+                .and(transactions::category_id.is_distinct_from(category_id))
+                .and(diesel::dsl::not(diesel::dsl::exists(
+                    transaction_splits::table
+                        .filter(transaction_splits::transaction_id.eq(transactions::id)),
+                )))
+                .and(diesel::dsl::not(diesel::dsl::exists(
+                    cleared_transactions::table
+                        .inner_join(reconciliations::table)
+                        .filter(cleared_transactions::transaction_id.eq(transactions::id))
+                        .filter(reconciliations::finished_at.is_not_null()),
+                ))),
+        )
+    }
+
+    /// Get the transactions of a user a bulk categorization would change, without changing them
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `filter` - The criteria the transactions must match
+    /// * `category_id` - ID of the category to give the transactions
+    /// * `limit` - Maximum number of transactions to return
+    ///
+    /// # Returns
+    ///
+    /// The number of transactions that would change, and up to `limit` of them, most recent first
+    pub fn categorizable(
+        conn: &mut DbConn,
+        user_id: i32,
+        filter: &TransactionFilter,
+        category_id: i32,
+        limit: i64,
+    ) -> Result<(i64, Vec<Self>), AppError> {
+        let categorizable = |conn: &mut DbConn| -> QueryResult<(i64, Vec<Self>)> {
+            let total = transactions::table
+                .filter(Self::categorizable_predicate(user_id, filter, category_id))
+                .count()
+                .get_result::<i64>(conn)?;
+            let sample = transactions::table
+                .filter(Self::categorizable_predicate(user_id, filter, category_id))
+                .order((transactions::occurred_at.desc(), transactions::id.desc()))
+                .limit(limit)
+                .load::<Transaction>(conn)?;
+            Ok((total, sample))
+        };
+        categorizable(conn).map_err(|e| {
+            tracing::error!("Failed getting categorizable transactions of user {user_id} ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Give a category to the transactions of a user that match a filter, in a single update
+    ///
+    /// The transactions changed are those `Transaction::categorizable` returns.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `filter` - The criteria the transactions must match
+    /// * `category_id` - ID of the category, which must belong to the user
+    ///
+    /// # Returns
+    ///
+    /// The number of transactions changed
+    pub fn categorize(
+        conn: &mut DbConn,
+        user_id: i32,
+        filter: &TransactionFilter,
+        category_id: i32,
+    ) -> Result<u64, AppError> {
+        diesel::update(transactions::table.filter(Self::categorizable_predicate(
+            user_id,
+            filter,
+            category_id,
+        )))
+        .set(transactions::category_id.eq(category_id))
+        .execute(conn)
+        .map(|count| count as u64)
+        .map_err(|e| {
+            tracing::error!(
+                "Failed categorizing transactions of user {user_id} in category {category_id} ({e})"
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the transactions of a user whose description or payee matches an `ILIKE` pattern
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `pattern` - The `ILIKE` pattern to match
+    /// * `limit` - Maximum number of transactions to return
+    ///
+    /// # Returns
+    ///
+    /// The number of matching transactions, and up to `limit` of them, most recent first
+    pub fn matching(
+        conn: &mut DbConn,
+        user_id: i32,
+        pattern: &str,
+        limit: i64,
+    ) -> Result<(i64, Vec<Self>), AppError> {
+        let query = || {
+            transactions::table
+                .inner_join(accounts::table)
+                .filter(accounts::user_id.eq(user_id))
+                .filter(
+                    transactions::description
+                        .ilike(pattern)
+                        .or(transactions::payee.ilike(pattern).assume_not_null()),
+                )
+        };
+
+        let matching = |conn: &mut DbConn| -> QueryResult<(i64, Vec<Self>)> {
+            let total = query().count().get_result::<i64>(conn)?;
+            let matches = query()
+                .select(transactions::all_columns)
+                .order((transactions::occurred_at.desc(), transactions::id.desc()))
+                .limit(limit)
+                .load::<Transaction>(conn)?;
+            Ok((total, matches))
+        };
+        matching(conn).map_err(|e| {
+            tracing::error!("Failed matching transactions of user {user_id} ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get a transaction by ID, scoped to the user that owns its account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Transaction ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The transaction, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        transactions::table
+            .inner_join(accounts::table)
+            .filter(transactions::id.eq(id))
+            .filter(accounts::user_id.eq(user_id))
+            .select(transactions::all_columns)
+            .first::<Transaction>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting transaction {id} for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Get the transactions of an account that occurred within a date range
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account to get the transactions of
+    /// * `from` - First day of the range (inclusive)
+    /// * `to` - Last day of the range (inclusive)
+    ///
+    /// # Returns
+    ///
+    /// A vector of transactions, oldest first
+    pub fn between(
+        conn: &mut DbConn,
+        account: &Account,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Self>, AppError> {
+        transactions::table
+            .filter(transactions::account_id.eq(account.id()))
+            .filter(transactions::occurred_at.between(from, to))
+            .order((transactions::occurred_at, transactions::id))
+            .load::<Transaction>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting transactions of account {} between {from} and {to} ({e})",
+                    account.id()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the amounts of the transactions of a user within a date range, by category
+    ///
+    /// A split transaction contributes each of its splits to the category of the split, other
+    /// transactions contribute their amount to their own category. Transfers between accounts are
+    /// left out, as they are neither income nor expenses.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `from` - First day of the range (inclusive)
+    /// * `to` - Last day of the range (inclusive)
+    ///
+    /// # Returns
+    ///
+    /// A vector of signed amounts with the category they are attributed to, if any
+    pub fn category_amounts(
+        conn: &mut DbConn,
+        user_id: i32,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<CategoryAmount>, AppError> {
+        let rows = transactions::table
+            .inner_join(accounts::table)
+            .left_join(transaction_splits::table)
+            .filter(accounts::user_id.eq(user_id))
+            .filter(transactions::occurred_at.between(from, to))
+            .filter(transactions::transfer_id.is_null())
+            .select((
+                transactions::category_id,
+                transactions::amount,
+                transactions::currency,
+                transactions::occurred_at,
+                transaction_splits::id.nullable(),
+                transaction_splits::category_id.nullable(),
+                transaction_splits::amount.nullable(),
+            ))
+            .load::<(
+                Option<i32>,
+                BigDecimal,
+                String,
+                NaiveDate,
+                Option<i32>,
+                Option<i32>,
+                Option<BigDecimal>,
+            )>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting amounts by category for user {user_id} between {from} and {to} ({e})"
+                );
+                AppError::Diesel(e)
+            })?;
+
+        Ok(rows
+            .into_iter()
+            .map(
+                |(
+                    category_id,
+                    amount,
+                    currency,
+                    occurred_at,
+                    split_id,
+                    split_category_id,
+                    split_amount,
+                )| {
+                    let (category_id, amount) = match (split_id, split_amount) {
+                        (Some(_), Some(split_amount)) => (split_category_id, split_amount),
+                        _ => (category_id, amount),
+                    };
+                    CategoryAmount {
+                        category_id,
+                        amount,
+                        currency,
+                        occurred_at,
+                    }
+                },
+            )
+            .collect())
+    }
+
+    /// Get the expenses of a user by fiscal month and category within a date range
+    ///
+    /// Like `Transaction::category_amounts`, splits are attributed to their own categories and
+    /// transfers are left out, but the amounts are added up by the database. Only money that went
+    /// out is counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `from` - First day of the range (inclusive)
+    /// * `to` - Last day of the range (inclusive)
+    /// * `start_day` - Day of the month fiscal months start on, see `periods::fiscal_month_of`
+    ///
+    /// # Returns
+    ///
+    /// A vector of expenses ordered by month, then category, each month being the first day of
+    /// the calendar month the fiscal month is named after
+    pub fn monthly_expenses(
+        conn: &mut DbConn,
+        user_id: i32,
+        from: NaiveDate,
+        to: NaiveDate,
+        start_day: u32,
+    ) -> Result<Vec<CategoryMonth>, AppError> {
+        // Going back the days before the start day lands in the month the fiscal month is named
+        // after, as in `periods::fiscal_month_of`
+        diesel::sql_query(
+            "SELECT date_trunc('month', t.occurred_at - $4)::date AS month, \
+                 CASE WHEN s.id IS NULL THEN t.category_id ELSE s.category_id END AS category_id, \
+                 -SUM(CASE WHEN s.id IS NULL THEN t.amount ELSE s.amount END) AS expenses \
+             FROM transactions t \
+             JOIN accounts a ON a.id = t.account_id \
+             LEFT JOIN transaction_splits s ON s.transaction_id = t.id \
+             WHERE a.user_id = $1 AND t.occurred_at BETWEEN $2 AND $3 AND t.transfer_id IS NULL \
+                 AND CASE WHEN s.id IS NULL THEN t.amount ELSE s.amount END < 0 \
+             GROUP BY 1, 2 \
+             ORDER BY 1, 2",
+        )
+        .bind::<Integer, _>(user_id)
+        .bind::<Date, _>(from)
+        .bind::<Date, _>(to)
+        .bind::<Integer, _>(start_day as i32 - 1)
+        .load::<CategoryMonth>(conn)
+        .map_err(|e| {
+            tracing::error!(
+                "Failed getting monthly expenses of user {user_id} between {from} and {to} ({e})"
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the net amounts of the transactions of a user by month and category within a date
+    /// range
+    ///
+    /// Like `Transaction::monthly_expenses`, splits are attributed to their own categories,
+    /// transfers are left out and the amounts are added up by the database regardless of their
+    /// currency. Money that came in and went out of a category cancel out.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `from` - First day of the range (inclusive)
+    /// * `to` - Last day of the range (inclusive)
+    ///
+    /// # Returns
+    ///
+    /// A vector of net amounts ordered by month, then category
+    pub fn monthly_category_net(
+        conn: &mut DbConn,
+        user_id: i32,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<CategoryMonthNet>, AppError> {
+        diesel::sql_query(
+            "SELECT date_trunc('month', t.occurred_at)::date AS month, \
+                 CASE WHEN s.id IS NULL THEN t.category_id ELSE s.category_id END AS category_id, \
+                 SUM(CASE WHEN s.id IS NULL THEN t.amount ELSE s.amount END) AS net \
+             FROM transactions t \
+             JOIN accounts a ON a.id = t.account_id \
+             LEFT JOIN transaction_splits s ON s.transaction_id = t.id \
+             WHERE a.user_id = $1 AND t.occurred_at BETWEEN $2 AND $3 AND t.transfer_id IS NULL \
+             GROUP BY 1, 2 \
+             ORDER BY 1, 2",
+        )
+        .bind::<Integer, _>(user_id)
+        .bind::<Date, _>(from)
+        .bind::<Date, _>(to)
+        .load::<CategoryMonthNet>(conn)
+        .map_err(|e| {
+            tracing::error!(
+                "Failed getting monthly net amounts of user {user_id} between {from} and {to} ({e})"
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get what a user spent on a category within a date range, net of refunds
+    ///
+    /// Like `Transaction::category_amounts`, splits are attributed to their own categories and
+    /// transfers are left out. The amounts are added up by the database regardless of their
+    /// currency, so only the transactions in the range are read.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `category_id` - Category ID
+    /// * `from` - First day of the range (inclusive)
+    /// * `to` - Last day of the range (inclusive)
+    ///
+    /// # Returns
+    ///
+    /// The money that went out minus the money that came back, as a positive amount when more
+    /// went out
+    pub fn category_spending(
+        conn: &mut DbConn,
+        user_id: i32,
+        category_id: i32,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<BigDecimal, AppError> {
+        diesel::sql_query(
+            "SELECT COALESCE(-SUM(CASE WHEN s.id IS NULL THEN t.amount ELSE s.amount END), 0) \
+                 AS spent \
+             FROM transactions t \
+             JOIN accounts a ON a.id = t.account_id \
+             LEFT JOIN transaction_splits s ON s.transaction_id = t.id \
+             WHERE a.user_id = $1 AND t.occurred_at BETWEEN $2 AND $3 AND t.transfer_id IS NULL \
+                 AND CASE WHEN s.id IS NULL THEN t.category_id ELSE s.category_id END = $4",
+        )
+        .bind::<Integer, _>(user_id)
+        .bind::<Date, _>(from)
+        .bind::<Date, _>(to)
+        .bind::<Integer, _>(category_id)
+        .get_result::<CategorySpending>(conn)
+        .map(|row| row.spent)
+        .map_err(|e| {
+            tracing::error!(
+                "Failed getting spending of user {user_id} on category {category_id} between \
+                 {from} and {to} ({e})"
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Give the transactions and splits of a category another category
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `from` - The category of the transactions and splits
+    /// * `into` - The category to give them instead
+    ///
+    /// # Returns
+    ///
+    /// The number of transactions and the number of splits changed
+    pub fn move_category(
+        conn: &mut DbConn,
+        from: &Category,
+        into: &Category,
+    ) -> Result<(u64, u64), AppError> {
+        let move_category = |conn: &mut DbConn| -> QueryResult<(u64, u64)> {
+            let transactions =
+                diesel::update(transactions::table.filter(transactions::category_id.eq(from.id())))
+                    .set(transactions::category_id.eq(into.id()))
+                    .execute(conn)?;
+            let splits = diesel::update(
+                transaction_splits::table.filter(transaction_splits::category_id.eq(from.id())),
+            )
+            .set(transaction_splits::category_id.eq(into.id()))
+            .execute(conn)?;
+            Ok((transactions as u64, splits as u64))
+        };
+        move_category(conn).map_err(|e| {
+            tracing::error!(
+                "Failed moving transactions from category {} to {} ({e})",
+                from.id(),
+                into.id()
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get a page of the transactions of an account within a date range, for export
+    ///
+    /// Pages are keyed on `(occurred_at, id)` rather than offset, so each page is an index range
+    /// scan no matter how deep into the export it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `account` - The account to export the transactions of
+    /// * `from` - First day of the range (inclusive)
+    /// * `to` - Last day of the range (inclusive)
+    /// * `after` - The cursor of the last row of the previous page, if any
+    /// * `limit` - Maximum number of rows in the page
+    ///
+    /// # Returns
+    ///
+    /// A vector of rows, oldest first. The export is done when it holds fewer than `limit` rows.
+    pub fn export_page(
+        conn: &mut DbConn,
+        account: &Account,
+        from: NaiveDate,
+        to: NaiveDate,
+        after: Option<(NaiveDate, i32)>,
+        limit: i64,
+    ) -> Result<Vec<ExportRow>, AppError> {
+        let mut query = transactions::table
+            .left_join(categories::table)
+            .filter(transactions::account_id.eq(account.id()))
+            .filter(transactions::occurred_at.between(from, to))
+            .select((
+                transactions::id,
+                transactions::occurred_at,
+                transactions::description,
+                categories::name.nullable(),
+                transactions::amount,
+                transactions::currency,
+            ))
+            .order((transactions::occurred_at, transactions::id))
+            .limit(limit)
+            .into_boxed();
+
+        if let Some((occurred_at, id)) = after {
+            query = query.filter(
+                transactions::occurred_at
+                    .gt(occurred_at)
+                    .or(transactions::occurred_at
+                        .eq(occurred_at)
+                        .and(transactions::id.gt(id))),
+            );
+        }
+
+        query.load::<ExportRow>(conn).map_err(|e| {
+            tracing::error!(
+                "Failed exporting transactions of account {} between {from} and {to} ({e})",
+                account.id()
+            );
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the ID of the transaction
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the ID of the account the transaction belongs to
+    pub fn account_id(&self) -> i32 {
+        self.account_id
+    }
+
+    /// Get the ID of the category of the transaction
+    pub fn category_id(&self) -> Option<i32> {
+        self.category_id
+    }
+
+    /// Get the payee of the transaction
+    #[cfg(test)]
+    pub fn payee(&self) -> Option<&str> {
+        self.payee.as_deref()
+    }
+
+    /// Get the signed amount of the transaction
+    pub fn amount(&self) -> &BigDecimal {
+        &self.amount
+    }
+
+    /// Get the description of the transaction
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Get the date the transaction occurred on
+    pub fn occurred_at(&self) -> NaiveDate {
+        self.occurred_at
+    }
+
+    /// Get the ID of the transfer the transaction is a leg of, if any
+    pub fn transfer_id(&self) -> Option<Uuid> {
+        self.transfer_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{accounts::AccountKind, categories::Category, users::User},
+    };
+
+    #[test]
+    fn test_bulk_insert() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+
+        let inputs: Vec<TransactionInput> = (0..2500)
+            .map(|i| {
+                TransactionInput::new(
+                    BigDecimal::from_str("-0.01").unwrap(),
+                    &format!("Row {i}"),
+                    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                )
+            })
+            .collect();
+
+        let inserted =
+            Transaction::bulk_insert(conn, &account, &inputs, &Quotas::disabled()).unwrap();
+        assert_eq!(inserted, 2500);
+
+        let transactions = Transaction::get_all(conn, &account).unwrap();
+        assert_eq!(transactions.len(), 2500);
+        assert!(transactions.iter().all(|t| t.currency == "CAD"));
+        assert_eq!(account.balance(conn).unwrap(), BigDecimal::from(-25));
+    }
+
+    fn split(category: &Category, amount: &str) -> SplitInput {
+        SplitInput {
+            category_id: category.id(),
+            amount: BigDecimal::from_str(amount).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_splits_must_add_up() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let household = Category::new(conn, user.id(), "Household").unwrap();
+
+        let mut input = TransactionInput::new(
+            BigDecimal::from_str("-120.00").unwrap(),
+            "Grocery store",
+            NaiveDate::from_ymd_opt(2024, 7, 5).unwrap(),
+        );
+        input.splits = vec![split(&groceries, "-80.00"), split(&household, "-30.00")];
+
+        match Transaction::new(conn, &account, &input, &Quotas::disabled()) {
+            Err(AppError::InvalidInput(message)) => {
+                assert!(message.contains("-110.00"), "{message}");
+                assert!(message.contains("-10.00 off"), "{message}");
+            }
+            other => panic!("Expected invalid input, got {other:?}"),
+        }
+        // Nothing is persisted when the splits don't add up
+        assert!(Transaction::get_all(conn, &account).unwrap().is_empty());
+
+        input.splits[1] = split(&household, "-40.00");
+        let transaction = Transaction::new(conn, &account, &input, &Quotas::disabled())
+            .unwrap()
+            .with_splits(conn)
+            .unwrap();
+        assert_eq!(transaction.splits.len(), 2);
+        assert_eq!(transaction.splits[1].category_id, Some(household.id()));
+    }
+
+    #[test]
+    fn test_edit_splits() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let household = Category::new(conn, user.id(), "Household").unwrap();
+
+        let mut input = TransactionInput::new(
+            BigDecimal::from_str("-120.00").unwrap(),
+            "Grocery store",
+            NaiveDate::from_ymd_opt(2024, 7, 5).unwrap(),
+        );
+        input.splits = vec![split(&groceries, "-80.00"), split(&household, "-40.00")];
+        let transaction = Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+
+        // Changing the amount without the splits is rejected and keeps the old splits
+        input.amount = BigDecimal::from_str("-150.00").unwrap();
+        assert!(transaction.update(conn, &input).is_err());
+        let unchanged = transaction.clone().with_splits(conn).unwrap();
+        assert_eq!(unchanged.transaction.amount, BigDecimal::from(-120));
+        assert_eq!(unchanged.splits.len(), 2);
+
+        input.splits = vec![
+            split(&groceries, "-100.00"),
+            split(&household, "-30.00"),
+            split(&household, "-20.00"),
+        ];
+        let updated = transaction
+            .update(conn, &input)
+            .unwrap()
+            .with_splits(conn)
+            .unwrap();
+        assert_eq!(updated.transaction.amount, BigDecimal::from(-150));
+        assert_eq!(updated.splits.len(), 3);
+
+        // Removing the splits
+        input.splits = vec![];
+        let updated = transaction
+            .update(conn, &input)
+            .unwrap()
+            .with_splits(conn)
+            .unwrap();
+        assert!(updated.splits.is_empty());
+    }
+
+    #[test]
+    fn test_monthly_expenses() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let household = Category::new(conn, user.id(), "Household").unwrap();
+        let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+
+        let mut input = TransactionInput::new(
+            BigDecimal::from_str("-120.00").unwrap(),
+            "Grocery store",
+            date(6, 5),
+        );
+        input.splits = vec![split(&groceries, "-80.00"), split(&household, "-40.00")];
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        let mut input = TransactionInput::new(
+            BigDecimal::from_str("-30.00").unwrap(),
+            "Market",
+            date(7, 2),
+        );
+        input.category_id = Some(groceries.id());
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        // Income isn't spending
+        let mut input =
+            TransactionInput::new(BigDecimal::from_str("15.00").unwrap(), "Refund", date(7, 3));
+        input.category_id = Some(groceries.id());
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+
+        let month = |month, category: &Category, expenses: &str| CategoryMonth {
+            month: date(month, 1),
+            category_id: Some(category.id()),
+            expenses: BigDecimal::from_str(expenses).unwrap(),
+        };
+        assert_eq!(
+            Transaction::monthly_expenses(conn, user.id(), date(6, 1), date(7, 31), 1).unwrap(),
+            vec![
+                month(6, &groceries, "80.00"),
+                month(6, &household, "40.00"),
+                month(7, &groceries, "30.00"),
+            ]
+        );
+        // Early July is still in June when months start on the 3rd
+        assert_eq!(
+            Transaction::monthly_expenses(conn, user.id(), date(6, 1), date(7, 31), 3).unwrap(),
+            vec![
+                month(6, &groceries, "110.00"),
+                month(6, &household, "40.00"),
+            ]
+        );
+    }
+}
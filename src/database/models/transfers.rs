@@ -0,0 +1,331 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use diesel::Connection;
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        accounts::Account,
+        transactions::{Transaction, TransactionInput},
+    },
+};
+use crate::errors::AppError;
+use crate::quotas::{Quotas, Resource};
+
+/// Fields of a transfer between two accounts
+#[derive(Debug, Clone)]
+pub struct TransferInput {
+    /// The amount moved, positive and in the currency of both accounts
+    pub amount: BigDecimal,
+    /// Description of both legs of the transfer
+    pub description: String,
+    /// The date the transfer occurred on
+    pub occurred_at: NaiveDate,
+}
+
+impl TransferInput {
+    /// Validate the input
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the input is valid, otherwise `AppError::InvalidInput`
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.amount <= BigDecimal::zero() {
+            return Err(AppError::InvalidInput(
+                "The amount of a transfer must be positive".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Get the input of the leg of the transfer on one of the accounts
+    fn leg(&self, outgoing: bool) -> TransactionInput {
+        let amount = if outgoing {
+            -&self.amount
+        } else {
+            self.amount.clone()
+        };
+        TransactionInput::new(amount, &self.description, self.occurred_at)
+    }
+}
+
+/// Money moved between two accounts of a user, as a pair of linked transactions
+///
+/// Transfers are neither income nor expenses, so reports leave them out.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Transfer {
+    /// Transfer ID, shared by both legs
+    id: Uuid,
+    /// The leg taking the money out of the source account
+    #[schema(value_type = Object)]
+    from: Transaction,
+    /// The leg putting the money into the destination account
+    #[schema(value_type = Object)]
+    to: Transaction,
+}
+
+impl Transfer {
+    /// Move money between two accounts, creating both legs or neither
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `from` - The account the money leaves
+    /// * `to` - The account the money goes into
+    /// * `input` - The fields of the transfer
+    /// * `quotas` - The quotas of the server, which both legs must fit
+    ///
+    /// # Returns
+    ///
+    /// The newly created transfer, `AppError::InvalidInput` if the amount isn't positive or the
+    /// accounts are the same or in different currencies, `AppError::Conflict` if an account is
+    /// archived, or `AppError::QuotaExceeded` if the legs don't fit the quota of the user
+    pub fn new(
+        conn: &mut DbConn,
+        from: &Account,
+        to: &Account,
+        input: &TransferInput,
+        quotas: &Quotas,
+    ) -> Result<Self, AppError> {
+        input.validate()?;
+        if from.id() == to.id() {
+            return Err(AppError::InvalidInput(
+                "A transfer must be between two different accounts".to_string(),
+            ));
+        }
+        if from.currency() != to.currency() {
+            return Err(AppError::InvalidInput(format!(
+                "Transfers between accounts in different currencies ({} and {}) aren't supported",
+                from.currency(),
+                to.currency()
+            )));
+        }
+
+        quotas.ensure(conn, from.user_id(), Resource::Transactions, 2)?;
+
+        let id = Uuid::new_v4();
+        conn.transaction(|conn| {
+            Ok(Self {
+                id,
+                from: Transaction::new_transfer_leg(conn, from, &input.leg(true), id)?,
+                to: Transaction::new_transfer_leg(conn, to, &input.leg(false), id)?,
+            })
+        })
+    }
+
+    /// Get a transfer by ID, scoped to the user owning its accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Transfer ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The transfer, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: Uuid, user_id: i32) -> Result<Self, AppError> {
+        let mut legs = Transaction::transfer_legs(conn, id, user_id)?.into_iter();
+        match (legs.next(), legs.next(), legs.next()) {
+            (Some(from), Some(to), None) => Ok(Self { id, from, to }),
+            _ => Err(AppError::not_found()),
+        }
+    }
+
+    /// Update both legs of the transfer, the accounts can't be changed
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `input` - The new fields of the transfer
+    ///
+    /// # Returns
+    ///
+    /// The updated transfer, or `AppError::InvalidInput` if the amount isn't positive
+    pub fn update(&self, conn: &mut DbConn, input: &TransferInput) -> Result<Self, AppError> {
+        input.validate()?;
+
+        conn.transaction(|conn| {
+            Ok(Self {
+                id: self.id,
+                from: self.from.update(conn, &input.leg(true))?,
+                to: self.to.update(conn, &input.leg(false))?,
+            })
+        })
+    }
+
+    /// Delete both legs of the transfer
+    ///
+    /// The files of their attachments are left for the caller to remove.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        conn.transaction(|conn| {
+            self.from.delete(conn)?;
+            self.to.delete(conn)
+        })
+    }
+
+    /// Get the fields of the transfer
+    pub fn input(&self) -> TransferInput {
+        TransferInput {
+            amount: self.to.amount().clone(),
+            description: self.to.description().to_string(),
+            occurred_at: self.to.occurred_at(),
+        }
+    }
+
+    /// Get both legs of the transfer, the outgoing one first
+    pub fn legs(&self) -> [&Transaction; 2] {
+        [&self.from, &self.to]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use diesel::prelude::*;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{accounts::AccountKind, users::User},
+        schema::accounts,
+    };
+    use crate::reports::monthly::monthly_summary;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn input(amount: &str) -> TransferInput {
+        TransferInput {
+            amount: decimal(amount),
+            description: "Savings".to_string(),
+            occurred_at: NaiveDate::from_ymd_opt(2024, 7, 5).unwrap(),
+        }
+    }
+
+    fn account(conn: &mut DbConn, user_id: i32, name: &str, currency: &str) -> Account {
+        Account::new(
+            conn,
+            user_id,
+            name,
+            &decimal("0"),
+            currency,
+            AccountKind::Asset,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_transfer_is_left_out_of_reports() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let chequing = account(conn, user.id(), "Chequing", "CAD");
+        let savings = account(conn, user.id(), "Savings", "CAD");
+        Transaction::new(
+            conn,
+            &chequing,
+            &TransactionInput::new(decimal("-25.00"), "Coffee", input("0").occurred_at),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+
+        let transfer = Transfer::new(
+            conn,
+            &chequing,
+            &savings,
+            &input("500.00"),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        let [from, to] = transfer.legs();
+        assert_eq!(from.amount(), &decimal("-500.00"));
+        assert_eq!(to.amount(), &decimal("500.00"));
+        assert_eq!(from.transfer_id(), Some(transfer.id));
+        assert_eq!(to.transfer_id(), Some(transfer.id));
+
+        assert_eq!(chequing.balance(conn).unwrap(), decimal("-525.00"));
+        assert_eq!(savings.balance(conn).unwrap(), decimal("500.00"));
+        let summary = monthly_summary(conn, user.id(), 2024, 7, 1, None).unwrap();
+        assert_eq!(summary.income(), &decimal("0"));
+        assert_eq!(summary.expenses(), &decimal("25.00"));
+
+        let transfer = Transfer::from_id(conn, transfer.id, user.id()).unwrap();
+        let transfer = transfer.update(conn, &input("450.00")).unwrap();
+        assert_eq!(savings.balance(conn).unwrap(), decimal("450.00"));
+        assert_eq!(chequing.balance(conn).unwrap(), decimal("-475.00"));
+
+        transfer.delete(conn).unwrap();
+        assert!(matches!(
+            Transfer::from_id(conn, transfer.id, user.id()),
+            Err(AppError::NotFound(_))
+        ));
+        assert_eq!(savings.balance(conn).unwrap(), decimal("0"));
+    }
+
+    #[test]
+    fn test_transfer_is_atomic() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let chequing = account(conn, user.id(), "Chequing", "CAD");
+        let savings = account(conn, user.id(), "Savings", "CAD");
+        let euros = account(conn, user.id(), "Euros", "EUR");
+
+        assert!(Transfer::new(
+            conn,
+            &chequing,
+            &chequing,
+            &input("10.00"),
+            &Quotas::disabled()
+        )
+        .is_err());
+        assert!(Transfer::new(
+            conn,
+            &chequing,
+            &savings,
+            &input("-10.00"),
+            &Quotas::disabled()
+        )
+        .is_err());
+        assert!(Transfer::new(
+            conn,
+            &chequing,
+            &euros,
+            &input("10.00"),
+            &Quotas::disabled()
+        )
+        .is_err());
+
+        // The second leg fails once the destination account is gone, which undoes the first leg
+        diesel::delete(accounts::table.filter(accounts::id.eq(savings.id())))
+            .execute(conn)
+            .unwrap();
+        assert!(Transfer::new(
+            conn,
+            &chequing,
+            &savings,
+            &input("10.00"),
+            &Quotas::disabled()
+        )
+        .is_err());
+        assert_eq!(chequing.balance(conn).unwrap(), decimal("0"));
+    }
+}
@@ -1,8 +1,25 @@
-use crate::{database::schema::users, errors::AppError};
+use crate::{
+    database::schema::users,
+    errors::{AppError, Lockout},
+};
+use chrono::Weekday;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
-
-use crate::database::{connection::DbConn, models::sessions::manager::Session};
+use utoipa::ToSchema;
+
+use crate::audit;
+use crate::database::{
+    connection::DbConn,
+    models::{
+        audit_events::{AuditEventKind, NewAuditEvent},
+        exchange_rates::validate_currency_code,
+        notifications::{NewNotification, Notification},
+    },
+};
+use crate::reports::periods;
+
+/// Type of the notifications recorded when a user is locked after failed logins
+pub const LOCKOUT_NOTIFICATION_TYPE: &str = "lockout";
 
 /// Struct to represent a user
 ///
@@ -21,6 +38,7 @@ pub struct User {
     /// The two-factor authentication secret of the user
     two_fa_secret: Option<String>,
     /// The timestamp when the user was created
+    #[serde(with = "crate::utils::serialization")]
     created_at: chrono::NaiveDateTime,
     /// If the user is in developer mode
     is_dev_mode: bool,
@@ -36,7 +54,18 @@ pub struct User {
     /// The lockout duration cap in seconds (default 3600 seconds = 60 minutes)
     lock_duration_cap_s: i32,
     /// The timestamp when the user was locked out
+    #[serde(default, with = "crate::utils::serialization::option")]
     locked_until: Option<chrono::NaiveDateTime>,
+    /// The ISO 4217 currency code reports are converted into
+    preferred_currency: String,
+    /// If the user can manage data shared by all users, like exchange rates
+    is_admin: bool,
+    /// If a summary of the week is sent to the user as a notification every Monday
+    digest_enabled: bool,
+    /// The day weekly reports start on, e.g. `monday`
+    week_starts_on: String,
+    /// The day of the month monthly reports start on, from 1 to 28
+    fiscal_month_start_day: i32,
 }
 
 /// Public user struct
@@ -45,21 +74,45 @@ pub struct User {
 /// username and creation timestamp.
 ///
 /// This struct omits the password hash and other sensitive information.
-#[derive(Debug, Serialize, Deserialize, Queryable)]
+#[derive(Debug, Serialize, Deserialize, Queryable, ToSchema)]
 pub struct UserPublic {
     /// The user ID
     id: i32,
     /// The username of the user
     username: String,
     /// The timestamp when the user was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
     created_at: chrono::NaiveDateTime,
     /// If the user is in developer mode
     is_dev_mode: bool,
+    /// The ISO 4217 currency code reports are converted into
+    preferred_currency: String,
+    /// If a summary of the week is sent to the user as a notification every Monday
+    digest_enabled: bool,
+    /// The day weekly reports start on, e.g. `monday`
+    week_starts_on: String,
+    /// The day of the month monthly reports start on, from 1 to 28
+    fiscal_month_start_day: i32,
+}
+
+/// The order users are listed in, by username or by when they were created, descending with `-`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+pub enum UserSort {
+    #[default]
+    #[serde(rename = "username")]
+    Username,
+    #[serde(rename = "-username")]
+    UsernameDesc,
+    #[serde(rename = "created_at")]
+    CreatedAt,
+    #[serde(rename = "-created_at")]
+    CreatedAtDesc,
 }
 
 /// New user struct
 #[derive(Insertable)]
-#[table_name = "users"]
+#[diesel(table_name = users)]
 struct NewUser<'a> {
     /// The username of the new user
     username: &'a str,
@@ -103,6 +156,31 @@ impl User {
         User::new(conn, username, password)
     }
 
+    /// Renames a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - A mutable reference to a `DbConn`.
+    /// * `id` - The ID of the user to rename.
+    /// * `username` - A string slice that holds the new username of the user.
+    ///
+    /// # Returns
+    ///
+    /// `Ok` if the user was renamed, or `AppError::NotFound` if the user doesn't exist.
+    pub fn rename(conn: &mut DbConn, id: i32, username: &str) -> Result<(), AppError> {
+        let renamed = diesel::update(users::table.filter(users::id.eq(id)))
+            .set(users::username.eq(username))
+            .execute(conn)
+            .map_err(|e| {
+                tracing::error!("Error renaming user {id} to {username:?}, error: {e}.");
+                AppError::Diesel(e)
+            })?;
+        if renamed == 0 {
+            return Err(AppError::not_found());
+        }
+        Ok(())
+    }
+
     /// Updates a user's password
     ///
     /// # Arguments
@@ -121,14 +199,24 @@ impl User {
         password: &str,
     ) -> Result<(), AppError> {
         let user = users::table.filter(users::id.eq(id));
-        if user.first::<User>(conn).is_err() {
+        let Ok(current) = user.first::<User>(conn) else {
             return Err(AppError::not_found());
-        }
+        };
         match diesel::update(user)
             .set(users::pw_hash.eq(password))
             .execute(conn)
         {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                audit::record(
+                    conn,
+                    NewAuditEvent::new(
+                        AuditEventKind::PasswordChange,
+                        Some(id),
+                        serde_json::json!({ "username": current.username }),
+                    ),
+                );
+                Ok(())
+            }
             Err(e) => {
                 tracing::error!("Error updating user: {username:?}, error: {e}.");
                 Err(AppError::Diesel(e))
@@ -147,13 +235,25 @@ impl User {
     ///
     /// A boolean indicating if the user was deleted successfully.
     pub fn delete(conn: &mut DbConn, id: i32) -> Result<(), AppError> {
-        diesel::delete(users::table.filter(users::id.eq(id)))
-            .execute(conn)
-            .map(|_| ())
+        let usernames = diesel::delete(users::table.filter(users::id.eq(id)))
+            .returning(users::username)
+            .get_results::<String>(conn)
             .map_err(|e| {
                 tracing::error!("Error deleting user: {id}, error: {e}.");
                 AppError::Diesel(e)
-            })
+            })?;
+
+        for username in usernames {
+            audit::record(
+                conn,
+                NewAuditEvent::new(
+                    AuditEventKind::UserDeleted,
+                    Some(id),
+                    serde_json::json!({ "username": username }),
+                ),
+            );
+        }
+        Ok(())
     }
 
     pub fn is_locked(&self) -> bool {
@@ -168,15 +268,17 @@ impl User {
     ///
     /// # Returns
     ///
-    /// A `User` struct if the user was found.
+    /// A `User` struct if the user was found, otherwise `AppError::NotFound`.
     pub fn from_id(conn: &mut DbConn, id: i32) -> Result<Self, AppError> {
         users::table
             .filter(users::id.eq(id))
             .first::<User>(conn)
+            .optional()
             .map_err(|e| {
                 tracing::error!("Error getting user by ID: {e:?}");
                 AppError::Diesel(e)
-            })
+            })?
+            .ok_or_else(AppError::not_found)
     }
     /// Gets a user by username
     ///
@@ -187,41 +289,58 @@ impl User {
     ///
     /// # Returns
     ///
-    /// A `User` struct if the user was found.
+    /// A `User` struct if the user was found, otherwise `AppError::NotFound`.
     pub fn from_username(conn: &mut DbConn, username: &str) -> Result<Self, AppError> {
         users::table
             .filter(users::username.eq(username))
             .first::<User>(conn)
+            .optional()
             .map_err(|e| {
                 tracing::error!("Error getting user by username \"{username}\": {e:?}");
                 AppError::Diesel(e)
-            })
+            })?
+            .ok_or_else(AppError::not_found)
     }
 
-    pub fn authenticate(&mut self, conn: &mut DbConn, password: &str) -> Result<Session, AppError> {
+    /// Check the password of the user before a login, counting the failures
+    ///
+    /// The session of the login is created by the caller once the user is authenticated, see
+    /// `Session::issue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - A mutable reference to a `DbConn`.
+    /// * `password` - The password to check.
+    /// * `now` - The current time, in UTC.
+    /// * `ip` - The address of the client logging in, if known.
+    ///
+    /// # Returns
+    ///
+    /// `Ok` if the password is correct, `AuthenticateError::Locked` if the user is locked,
+    /// otherwise `AuthenticateError::WrongCredentials`.
+    pub fn authenticate(
+        &mut self,
+        conn: &mut DbConn,
+        password: &str,
+        now: chrono::NaiveDateTime,
+        ip: Option<&str>,
+    ) -> Result<(), AppError> {
         // Check if the password is correct
         // bcrypt::verify(password, &user.pw_hash).unwrap()
 
-        // If account is locked and cannot be unlocked.
-        if self.is_locked() && self.unlock(conn).is_err() {
-            return Err(AppError::Authenticate(
-                crate::errors::AuthenticateError::Locked,
-            ));
-        }
+        // A locked account can't log in until its lock expires
+        self.unlock(conn, now)?;
 
         // If the password is correct, return Ok(())
         if !self.check_password(password) {
             // Increment the invalid login attempts and lock account if necessary
-            self.increment_invalid_login_attempts(conn)?;
+            self.increment_invalid_login_attempts(conn, now, ip)?;
 
             return Err(AppError::Authenticate(
                 crate::errors::AuthenticateError::WrongCredentials,
             ));
         }
-        self.reset_invalid_login_attempts(conn)?;
-
-        let session = Session::new(conn, self.id)?;
-        Ok(session)
+        self.reset_invalid_login_attempts(conn)
     }
 
     /// Will attempt to unlock the user account if it is locked
@@ -229,19 +348,27 @@ impl User {
     /// # Arguments
     ///
     /// * `conn` - A mutable reference to a `DbConn`.
+    /// * `now` - The current time, in UTC.
     ///
     /// # Returns
     ///
-    /// A boolean indicating if the account was unlocked.
-    pub fn unlock(&mut self, conn: &mut DbConn) -> Result<(), AppError> {
+    /// `Ok` if the account isn't locked or its lock expired, otherwise
+    /// `AuthenticateError::Locked` with when it expires.
+    pub fn unlock(
+        &mut self,
+        conn: &mut DbConn,
+        now: chrono::NaiveDateTime,
+    ) -> Result<(), AppError> {
         let locked_until = match self.locked_until {
             None => return Ok(()),
             Some(locked_until) => locked_until,
         };
 
         // Check if the lock duration has expired
-        if locked_until < chrono::Utc::now().naive_utc() {
-            return Ok(());
+        if let Some(lockout) = Lockout::at(locked_until, now) {
+            return Err(AppError::Authenticate(
+                crate::errors::AuthenticateError::Locked(lockout),
+            ));
         }
 
         // Unlock the account
@@ -252,15 +379,98 @@ impl User {
         self.save_changes(conn)
     }
 
-    fn lock(&mut self, conn: &mut DbConn) -> Result<(), AppError> {
+    /// Lock the user after failed logins, and tell them in their notifications
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - A mutable reference to a `DbConn`.
+    /// * `now` - The current time, in UTC, the lock starts at.
+    /// * `ip` - The address of the client whose login failed, if known.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating if the user was locked successfully.
+    fn lock(
+        &mut self,
+        conn: &mut DbConn,
+        now: chrono::NaiveDateTime,
+        ip: Option<&str>,
+    ) -> Result<(), AppError> {
         let lock_duration = self.lock_duration_s * self.lock_duration_factor;
         let lock_duration = lock_duration.min(self.lock_duration_cap_s) as i64;
 
-        self.locked_until =
-            Some(chrono::Utc::now().naive_utc() + chrono::Duration::seconds(lock_duration));
+        let locked_until = now + chrono::Duration::seconds(lock_duration);
+        self.locked_until = Some(locked_until);
 
         // Update database
-        self.save_changes(conn)
+        self.save_changes(conn)?;
+
+        audit::record(
+            conn,
+            NewAuditEvent::new(
+                AuditEventKind::Lockout,
+                Some(self.id),
+                serde_json::json!({
+                    "invalid_login_attempts": self.invalid_login_attempts,
+                    "locked_until": crate::utils::serialization::format(&locked_until),
+                }),
+            ),
+        );
+        self.notify_lockout(conn, now, locked_until, ip)
+    }
+
+    /// Record a notification of a lockout, unless the lock was already notified
+    ///
+    /// Logins failing concurrently can each lock the user, the lock they start while another is
+    /// still running is the same lockout.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - A mutable reference to a `DbConn`.
+    /// * `now` - The current time, in UTC, the lock starts at.
+    /// * `locked_until` - When the lock expires, in UTC.
+    /// * `ip` - The address of the client whose login failed, if known.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating if the notification was recorded or already was.
+    fn notify_lockout(
+        &self,
+        conn: &mut DbConn,
+        now: chrono::NaiveDateTime,
+        locked_until: chrono::NaiveDateTime,
+        ip: Option<&str>,
+    ) -> Result<(), AppError> {
+        let notified = Notification::latest_of_kind(conn, self.id, LOCKOUT_NOTIFICATION_TYPE)?
+            .and_then(|latest| {
+                latest.data()["locked_until"]
+                    .as_str()
+                    .and_then(|until| crate::utils::serialization::parse(until).ok())
+            })
+            .is_some_and(|until| until > now);
+        if notified {
+            return Ok(());
+        }
+
+        let notification = NewNotification::new(
+            self.id,
+            LOCKOUT_NOTIFICATION_TYPE,
+            "Account locked".to_string(),
+            format!(
+                "{} failed login attempts from {} at {} UTC, account locked until {} UTC",
+                self.invalid_login_attempts,
+                ip.unwrap_or("an unknown address"),
+                now.format("%Y-%m-%d %H:%M:%S"),
+                locked_until.format("%Y-%m-%d %H:%M:%S"),
+            ),
+            serde_json::json!({
+                "invalid_login_attempts": self.invalid_login_attempts,
+                "ip": ip,
+                "failed_at": crate::utils::serialization::format(&now),
+                "locked_until": crate::utils::serialization::format(&locked_until),
+            }),
+        );
+        Notification::new(conn, &notification).map(|_| ())
     }
 
     /// Check if the password is correct
@@ -299,18 +509,25 @@ impl User {
     /// # Arguments
     ///
     /// * `conn` - A mutable reference to a `DbConn`.
+    /// * `now` - The current time, in UTC, the lock starts at.
+    /// * `ip` - The address of the client whose login failed, if known.
     ///
     /// # Returns
     ///
     /// A result indicating if the invalid login attempts were incremented successfully.
-    pub fn increment_invalid_login_attempts(&mut self, conn: &mut DbConn) -> Result<(), AppError> {
+    pub fn increment_invalid_login_attempts(
+        &mut self,
+        conn: &mut DbConn,
+        now: chrono::NaiveDateTime,
+        ip: Option<&str>,
+    ) -> Result<(), AppError> {
         self.invalid_login_attempts += 1;
 
-        // Lock the account if necessary
+        // Lock the account if necessary, which saves the attempts along with the lock
         if self.invalid_login_attempts >= 3 {
-            self.lock(conn)?
+            return self.lock(conn, now, ip);
         }
-        Ok(())
+        self.save_changes(conn)
     }
 
     /// Save changes to the user
@@ -344,14 +561,211 @@ impl User {
             username: self.username.clone(),
             created_at: self.created_at,
             is_dev_mode: self.is_dev_mode,
+            preferred_currency: self.preferred_currency.clone(),
+            digest_enabled: self.digest_enabled,
+            week_starts_on: self.week_starts_on.clone(),
+            fiscal_month_start_day: self.fiscal_month_start_day,
         }
     }
 
+    /// Get a page of the users, for administrators
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - A mutable reference to a `DbConn`.
+    /// * `pattern` - `ILIKE` pattern the usernames must match, if any
+    /// * `sort` - The order of the users, users created at the same time are ordered by ID
+    /// * `limit` - Maximum number of users in the page
+    /// * `offset` - Number of users before the page
+    ///
+    /// # Returns
+    ///
+    /// The number of matching users, and the users of the page
+    pub fn get_page(
+        conn: &mut DbConn,
+        pattern: Option<&str>,
+        sort: UserSort,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(i64, Vec<UserPublic>), AppError> {
+        let query = || {
+            let mut query = users::table.into_boxed();
+            if let Some(pattern) = pattern {
+                query = query.filter(users::username.ilike(pattern));
+            }
+            query
+        };
+
+        let page = |conn: &mut DbConn| -> QueryResult<(i64, Vec<UserPublic>)> {
+            let total = query().count().get_result::<i64>(conn)?;
+            let ordered = match sort {
+                UserSort::Username => query().order(users::username.asc()),
+                UserSort::UsernameDesc => query().order(users::username.desc()),
+                UserSort::CreatedAt => query().order(users::created_at.asc()),
+                UserSort::CreatedAtDesc => query().order(users::created_at.desc()),
+            };
+            let users = ordered
+                .then_order_by(users::id.asc())
+                .select((
+                    users::id,
+                    users::username,
+                    users::created_at,
+                    users::is_dev_mode,
+                    users::preferred_currency,
+                    users::digest_enabled,
+                    users::week_starts_on,
+                    users::fiscal_month_start_day,
+                ))
+                .limit(limit)
+                .offset(offset)
+                .load::<UserPublic>(conn)?;
+            Ok((total, users))
+        };
+
+        page(conn).map_err(|e| {
+            tracing::error!("Failed getting a page of users ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Set the currency reports are converted into
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - A mutable reference to a `DbConn`.
+    /// * `currency` - The ISO 4217 currency code, three uppercase letters.
+    ///
+    /// # Returns
+    ///
+    /// The updated user, or `AppError::InvalidInput` if the code isn't three uppercase letters.
+    pub fn set_preferred_currency(
+        &self,
+        conn: &mut DbConn,
+        currency: &str,
+    ) -> Result<Self, AppError> {
+        validate_currency_code(currency)?;
+        diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set(users::preferred_currency.eq(currency))
+            .get_result::<User>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Error setting preferred currency of user {}: {e:?}",
+                    self.id
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the currency reports are converted into
+    pub fn preferred_currency(&self) -> &str {
+        &self.preferred_currency
+    }
+
+    /// Grant or revoke the administrator role of the user
+    pub fn set_admin(&self, conn: &mut DbConn, is_admin: bool) -> Result<Self, AppError> {
+        diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set(users::is_admin.eq(is_admin))
+            .get_result::<User>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Error setting the administrator role of user {}: {e:?}",
+                    self.id
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Check if the user is an administrator
+    pub fn is_admin(&self) -> bool {
+        self.is_admin
+    }
+
+    /// Opt the user in or out of the weekly digest
+    pub fn set_digest_enabled(&self, conn: &mut DbConn, enabled: bool) -> Result<Self, AppError> {
+        diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set(users::digest_enabled.eq(enabled))
+            .get_result::<User>(conn)
+            .map_err(|e| {
+                tracing::error!("Error setting the digest of user {}: {e:?}", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Check if the user is sent the weekly digest
+    pub fn digest_enabled(&self) -> bool {
+        self.digest_enabled
+    }
+
+    /// Set when the weeks and months of the reports of the user start
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `week_starts_on` - The day weeks start on
+    /// * `month_start_day` - The day of the month months start on
+    ///
+    /// # Returns
+    ///
+    /// The updated user, or `AppError::InvalidInput` if the day of the month isn't between 1 and 28
+    pub fn set_periods(
+        &self,
+        conn: &mut DbConn,
+        week_starts_on: Weekday,
+        month_start_day: u32,
+    ) -> Result<Self, AppError> {
+        periods::validate_month_start_day(month_start_day)?;
+        diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set((
+                users::week_starts_on.eq(periods::weekday_name(week_starts_on)),
+                users::fiscal_month_start_day.eq(month_start_day as i32),
+            ))
+            .get_result::<User>(conn)
+            .map_err(|e| {
+                tracing::error!("Error setting the periods of user {}: {e:?}", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the day weekly reports of the user start on
+    pub fn week_starts_on(&self) -> Weekday {
+        periods::parse_weekday(&self.week_starts_on).unwrap_or(Weekday::Mon)
+    }
+
+    /// Get the day of the month monthly reports of the user start on
+    pub fn fiscal_month_start_day(&self) -> u32 {
+        self.fiscal_month_start_day as u32
+    }
+
+    /// Get the IDs of the users who are sent the weekly digest
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// A vector of user IDs, in ascending order
+    pub fn digest_recipients(conn: &mut DbConn) -> Result<Vec<i32>, AppError> {
+        users::table
+            .filter(users::digest_enabled.eq(true))
+            .order(users::id)
+            .select(users::id)
+            .load::<i32>(conn)
+            .map_err(|e| {
+                tracing::error!("Error getting the recipients of the weekly digest: {e:?}");
+                AppError::Diesel(e)
+            })
+    }
+
     /// Get the ID of the user
-    #[cfg(test)]
     pub fn id(&self) -> i32 {
         self.id
     }
+
+    /// Get the username of the user
+    pub fn username(&self) -> &str {
+        &self.username
+    }
 }
 
 // write tests
@@ -0,0 +1,502 @@
+use std::net::IpAddr;
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use url::{Host, Url};
+use utoipa::ToSchema;
+
+use crate::database::{connection::DbConn, schema::webhooks};
+use crate::errors::AppError;
+use crate::quotas::{Quotas, Resource};
+
+/// Number of deliveries in a row that can fail before a webhook is disabled
+pub const DISABLE_AFTER_FAILURES: i32 = 3;
+
+/// Minimum number of characters in the secret deliveries are signed with
+pub const MIN_SECRET_LENGTH: usize = 16;
+
+/// Maximum number of characters in the secret deliveries are signed with
+pub const MAX_SECRET_LENGTH: usize = 128;
+
+/// Something that happened to the data of a user, which webhooks can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A transaction was created
+    TransactionCreated,
+    /// A transaction was deleted
+    TransactionDeleted,
+    /// A plan was created
+    PlanCreated,
+    /// A plan was deleted
+    PlanDeleted,
+    /// Spending in a category reached the threshold of its alert
+    CategoryAlert,
+}
+
+impl WebhookEvent {
+    /// Get the name of the event, as sent in deliveries
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::TransactionCreated => "transaction_created",
+            WebhookEvent::TransactionDeleted => "transaction_deleted",
+            WebhookEvent::PlanCreated => "plan_created",
+            WebhookEvent::PlanDeleted => "plan_deleted",
+            WebhookEvent::CategoryAlert => "category_alert",
+        }
+    }
+
+    fn from_str(name: &str) -> Option<Self> {
+        match name {
+            "transaction_created" => Some(WebhookEvent::TransactionCreated),
+            "transaction_deleted" => Some(WebhookEvent::TransactionDeleted),
+            "plan_created" => Some(WebhookEvent::PlanCreated),
+            "plan_deleted" => Some(WebhookEvent::PlanDeleted),
+            "category_alert" => Some(WebhookEvent::CategoryAlert),
+            _ => None,
+        }
+    }
+}
+
+/// The events a webhook subscribes to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct WebhookEvents(Vec<WebhookEvent>);
+
+impl From<Vec<Option<String>>> for WebhookEvents {
+    fn from(names: Vec<Option<String>>) -> Self {
+        Self(
+            names
+                .iter()
+                .flatten()
+                .filter_map(|name| WebhookEvent::from_str(name))
+                .collect(),
+        )
+    }
+}
+
+/// An endpoint of a user that is sent signed deliveries of the events it subscribes to
+#[derive(Debug, Serialize, Clone, Queryable, ToSchema)]
+#[diesel(table_name = webhooks)]
+pub struct Webhook {
+    /// Webhook ID
+    id: i32,
+    /// ID of the user that owns the webhook
+    user_id: i32,
+    /// The URL deliveries are posted to
+    url: String,
+    /// The secret deliveries are signed with, never returned
+    #[serde(skip)]
+    secret: String,
+    /// The events the webhook subscribes to
+    #[diesel(deserialize_as = Vec<Option<String>>)]
+    #[schema(value_type = Vec<WebhookEvent>)]
+    events: WebhookEvents,
+    /// Whether deliveries are sent, webhooks are disabled after repeated failures
+    active: bool,
+    /// Number of deliveries in a row that failed
+    failed_deliveries: i32,
+    /// The timestamp when the webhook was created
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+}
+
+/// Fields of a webhook
+#[derive(Debug, Clone)]
+pub struct WebhookInput {
+    /// The URL deliveries are posted to
+    pub url: String,
+    /// The secret deliveries are signed with
+    pub secret: String,
+    /// The events the webhook subscribes to
+    pub events: Vec<WebhookEvent>,
+    /// Whether deliveries are sent
+    pub active: bool,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = webhooks)]
+struct WebhookRow<'a> {
+    url: &'a str,
+    secret: &'a str,
+    events: Vec<&'a str>,
+    active: bool,
+}
+
+impl WebhookInput {
+    /// Validate the input
+    ///
+    /// # Arguments
+    ///
+    /// * `allow_insecure` - Whether plain HTTP and private network addresses are allowed, for
+    ///   development
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the input is valid, otherwise `AppError::InvalidInput`
+    pub fn validate(&self, allow_insecure: bool) -> Result<(), AppError> {
+        validate_url(&self.url, allow_insecure)?;
+        let length = self.secret.chars().count();
+        if !(MIN_SECRET_LENGTH..=MAX_SECRET_LENGTH).contains(&length) {
+            return Err(AppError::InvalidInput(format!(
+                "The secret of a webhook must be between {MIN_SECRET_LENGTH} and \
+                 {MAX_SECRET_LENGTH} characters long"
+            )));
+        }
+        if self.events.is_empty() {
+            return Err(AppError::InvalidInput(
+                "A webhook must subscribe to at least one event".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn row(&self) -> WebhookRow<'_> {
+        let mut events: Vec<&str> = self.events.iter().map(WebhookEvent::as_str).collect();
+        events.sort_unstable();
+        events.dedup();
+        WebhookRow {
+            url: &self.url,
+            secret: &self.secret,
+            events,
+            active: self.active,
+        }
+    }
+}
+
+/// Check that deliveries can be posted to a URL
+///
+/// Only HTTPS URLs of public hosts are allowed, so webhooks can't be used to reach services on
+/// the network of the server.
+///
+/// # Arguments
+///
+/// * `url` - The URL deliveries are posted to
+/// * `allow_insecure` - Whether plain HTTP and private network addresses are allowed, for
+///   development
+///
+/// # Returns
+///
+/// An empty result if the URL is allowed, otherwise `AppError::InvalidInput`
+pub fn validate_url(url: &str, allow_insecure: bool) -> Result<(), AppError> {
+    let invalid = |reason: &str| AppError::InvalidInput(format!("Invalid webhook URL: {reason}"));
+    let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+
+    match parsed.scheme() {
+        "https" => {}
+        "http" if allow_insecure => {}
+        _ => return Err(invalid("only HTTPS URLs are allowed")),
+    }
+    let host = parsed
+        .host()
+        .ok_or_else(|| invalid("the URL has no host"))?;
+    if allow_insecure {
+        return Ok(());
+    }
+
+    let private = match host {
+        Host::Domain(domain) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost") || domain.ends_with(".local")
+        }
+        Host::Ipv4(ip) => is_private(IpAddr::V4(ip)),
+        Host::Ipv6(ip) => is_private(IpAddr::V6(ip)),
+    };
+    if private {
+        return Err(invalid("private network addresses aren't allowed"));
+    }
+    Ok(())
+}
+
+/// Check if an address is on a private, loopback or link-local network
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // Shared address space of carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_private(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local addresses
+                || (first & 0xfe00) == 0xfc00
+                // Link-local addresses
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+impl Webhook {
+    /// Create a webhook for a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `input` - The fields of the webhook
+    /// * `allow_insecure` - Whether plain HTTP and private network addresses are allowed
+    /// * `quotas` - The quotas of the server
+    ///
+    /// # Returns
+    ///
+    /// The newly created webhook, `AppError::InvalidInput` if the input is invalid, or
+    /// `AppError::QuotaExceeded` if the user has as many webhooks as they can
+    pub fn new(
+        conn: &mut DbConn,
+        user_id: i32,
+        input: &WebhookInput,
+        allow_insecure: bool,
+        quotas: &Quotas,
+    ) -> Result<Self, AppError> {
+        input.validate(allow_insecure)?;
+        quotas.ensure(conn, user_id, Resource::Webhooks, 1)?;
+
+        diesel::insert_into(webhooks::table)
+            .values((webhooks::user_id.eq(user_id), input.row()))
+            .get_result::<Webhook>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed creating webhook for user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get all webhooks of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// A vector of webhooks, oldest first
+    pub fn get_all(conn: &mut DbConn, user_id: i32) -> Result<Vec<Self>, AppError> {
+        webhooks::table
+            .filter(webhooks::user_id.eq(user_id))
+            .order(webhooks::id)
+            .load::<Webhook>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed getting webhooks of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Count the webhooks of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    pub fn count_of_user(conn: &mut DbConn, user_id: i32) -> Result<u64, AppError> {
+        webhooks::table
+            .filter(webhooks::user_id.eq(user_id))
+            .count()
+            .get_result::<i64>(conn)
+            .map(|count| count as u64)
+            .map_err(|e| {
+                tracing::error!("Failed counting webhooks of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get the active webhooks of a user that subscribe to an event
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `event` - The event
+    ///
+    /// # Returns
+    ///
+    /// A vector of webhooks
+    pub fn subscribed(
+        conn: &mut DbConn,
+        user_id: i32,
+        event: WebhookEvent,
+    ) -> Result<Vec<Self>, AppError> {
+        webhooks::table
+            .filter(webhooks::user_id.eq(user_id))
+            .filter(webhooks::active)
+            .filter(webhooks::events.contains(vec![event.as_str()]))
+            .load::<Webhook>(conn)
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed getting webhooks of user {user_id} subscribed to {} ({e})",
+                    event.as_str()
+                );
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Get a webhook by ID, scoped to the user that owns it
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Webhook ID
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// The webhook, or `AppError::NotFound` if it doesn't exist or belongs to another user
+    pub fn from_id(conn: &mut DbConn, id: i32, user_id: i32) -> Result<Self, AppError> {
+        webhooks::table
+            .filter(webhooks::id.eq(id))
+            .filter(webhooks::user_id.eq(user_id))
+            .first::<Webhook>(conn)
+            .optional()
+            .map_err(|e| {
+                tracing::error!("Failed getting webhook {id} of user {user_id} ({e})");
+                AppError::Diesel(e)
+            })?
+            .ok_or_else(AppError::not_found)
+    }
+
+    /// Replace the fields of the webhook, reactivating it clears its failed deliveries
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `input` - The new fields of the webhook
+    /// * `allow_insecure` - Whether plain HTTP and private network addresses are allowed
+    ///
+    /// # Returns
+    ///
+    /// The updated webhook, or `AppError::InvalidInput` if the input is invalid
+    pub fn update(
+        &self,
+        conn: &mut DbConn,
+        input: &WebhookInput,
+        allow_insecure: bool,
+    ) -> Result<Self, AppError> {
+        input.validate(allow_insecure)?;
+        let failed_deliveries = if input.active && !self.active {
+            0
+        } else {
+            self.failed_deliveries
+        };
+
+        diesel::update(webhooks::table.filter(webhooks::id.eq(self.id)))
+            .set((
+                input.row(),
+                webhooks::failed_deliveries.eq(failed_deliveries),
+            ))
+            .get_result::<Webhook>(conn)
+            .map_err(|e| {
+                tracing::error!("Failed updating webhook {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Delete the webhook
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn delete(&self, conn: &mut DbConn) -> Result<(), AppError> {
+        diesel::delete(webhooks::table.filter(webhooks::id.eq(self.id)))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::error!("Failed deleting webhook {} ({e})", self.id);
+                AppError::Diesel(e)
+            })
+    }
+
+    /// Record the outcome of a delivery, disabling the webhook after too many failures in a row
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `id` - Webhook ID
+    /// * `delivered` - Whether the delivery succeeded
+    ///
+    /// # Returns
+    ///
+    /// An empty result if successful, otherwise an error
+    pub fn record_delivery(conn: &mut DbConn, id: i32, delivered: bool) -> Result<(), AppError> {
+        let webhook = webhooks::table.filter(webhooks::id.eq(id));
+        let result = if delivered {
+            diesel::update(webhook)
+                .set(webhooks::failed_deliveries.eq(0))
+                .execute(conn)
+        } else {
+            diesel::update(webhook)
+                .set((
+                    webhooks::failed_deliveries.eq(webhooks::failed_deliveries + 1),
+                    webhooks::active
+                        .eq((webhooks::failed_deliveries + 1).lt(DISABLE_AFTER_FAILURES)),
+                ))
+                .execute(conn)
+        };
+
+        result.map(|_| ()).map_err(|e| {
+            tracing::error!("Failed recording delivery of webhook {id} ({e})");
+            AppError::Diesel(e)
+        })
+    }
+
+    /// Get the webhook ID
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the URL deliveries are posted to
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Get the secret deliveries are signed with
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_url() {
+        assert!(validate_url("https://hooks.example.com/finance", false).is_ok());
+
+        for url in [
+            "http://hooks.example.com/finance",
+            "ftp://hooks.example.com/finance",
+            "https://localhost:8443/hook",
+            "https://printer.local/hook",
+            "https://127.0.0.1/hook",
+            "https://10.0.0.4/hook",
+            "https://192.168.1.20/hook",
+            "https://172.16.5.1/hook",
+            "https://169.254.169.254/latest/meta-data",
+            "https://100.64.0.1/hook",
+            "https://[::1]/hook",
+            "https://[fd00::1]/hook",
+            "https://[::ffff:10.0.0.1]/hook",
+            "not a url",
+        ] {
+            assert!(validate_url(url, false).is_err(), "{url} was allowed");
+        }
+
+        // Development servers can receive deliveries with the dev flag
+        assert!(validate_url("http://127.0.0.1:8080/hook", true).is_ok());
+        assert!(validate_url("ftp://127.0.0.1/hook", true).is_err());
+    }
+}
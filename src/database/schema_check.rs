@@ -0,0 +1,180 @@
+//! Checks that the database matches the models before the server takes requests
+//!
+//! A model mapping a column the database doesn't have only fails when a request first queries
+//! it, so the server probes every table it maps at startup instead.
+
+use diesel::migration::MigrationSource;
+use diesel::pg::{Pg, PgConnection};
+use diesel::result::Error as DieselError;
+use diesel::{QueryDsl, QueryResult, RunQueryDsl};
+use diesel_migrations::MigrationHarness;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::connection::MIGRATIONS;
+use super::schema::*;
+use crate::errors::AppError;
+
+/// A query selecting every mapped column of a table, without reading any row
+type Probe = fn(&mut PgConnection) -> QueryResult<usize>;
+
+/// Create the probe of each table, named after it
+macro_rules! probes {
+    ($($table:ident),* $(,)?) => {
+        [$((
+            stringify!($table),
+            (|conn| $table::table.select($table::all_columns).limit(0).execute(conn)) as Probe,
+        )),*]
+    };
+}
+
+/// The tables mapped by the models, and their probes
+const TABLES: [(&str, Probe); 32] = probes!(
+    account_tags,
+    accounts,
+    attachments,
+    audit_events,
+    automations,
+    budget_amounts,
+    budgets,
+    categories,
+    category_alerts,
+    cleared_transactions,
+    currencies,
+    exchange_rates,
+    goals,
+    import_jobs,
+    import_pending,
+    jobs,
+    notification_channels,
+    notifications,
+    payee_rules,
+    plan_notes,
+    plans,
+    reconciliations,
+    recurring_transactions,
+    scheduled_reports,
+    sessions,
+    tags,
+    transaction_splits,
+    transaction_tags,
+    transactions,
+    user_storage,
+    users,
+    webhooks,
+);
+
+/// How the database compared to the models when the server started
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SchemaStatus {
+    /// Version of the last migration applied to the database, `null` if none was
+    pub migration: Option<String>,
+    /// Number of tables whose columns were found to match the models
+    pub tables: usize,
+}
+
+/// Check that the database has every migration of this build, and every column of the models
+///
+/// # Arguments
+///
+/// * `conn` - A connection to the database
+///
+/// # Returns
+///
+/// How the database compared, otherwise `AppError::SchemaDrift` naming the pending migrations or
+/// the table and column that don't match
+pub fn check_schema(conn: &mut PgConnection) -> Result<SchemaStatus, AppError> {
+    let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)
+        .map_err(|e| AppError::Migration(e.to_string()))?;
+    let applied = conn
+        .applied_migrations()
+        .map_err(|e| AppError::Migration(e.to_string()))?;
+    if let Some(unknown) = applied
+        .iter()
+        .find(|version| !migrations.iter().any(|m| m.name().version() == **version))
+    {
+        return Err(AppError::SchemaDrift(format!(
+            "migration {unknown} was applied but isn't part of this build, the server is older \
+             than the database"
+        )));
+    }
+    let pending = migrations
+        .iter()
+        .filter(|migration| !applied.contains(&migration.name().version()))
+        .map(|migration| migration.name().to_string())
+        .collect::<Vec<_>>();
+    if !pending.is_empty() {
+        return Err(AppError::SchemaDrift(format!(
+            "{} migration(s) weren't applied ({}), start the server with --migrate or run the \
+             migrate subcommand",
+            pending.len(),
+            pending.join(", ")
+        )));
+    }
+
+    probe_tables(conn, &TABLES)?;
+
+    Ok(SchemaStatus {
+        migration: applied.iter().max().map(ToString::to_string),
+        tables: TABLES.len(),
+    })
+}
+
+/// Run the probes of tables, failing at the first whose columns don't match
+fn probe_tables(conn: &mut PgConnection, tables: &[(&str, Probe)]) -> Result<(), AppError> {
+    for (table, probe) in tables {
+        probe(conn).map_err(|e| {
+            let reason = match &e {
+                DieselError::DatabaseError(_, info) => info.message().to_owned(),
+                e => e.to_string(),
+            };
+            AppError::SchemaDrift(format!(
+                "the table `{table}` doesn't match the models ({reason})"
+            ))
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::sql_query;
+
+    use super::*;
+    use crate::database::connection::DbPool;
+
+    #[test]
+    fn test_check_schema() {
+        let pool = DbPool::new_test_shared();
+        let mut conn = pool.get().unwrap();
+
+        let status = check_schema(&mut conn).unwrap();
+        assert_eq!(status.tables, TABLES.len());
+        let last = MigrationSource::<Pg>::migrations(&MIGRATIONS)
+            .unwrap()
+            .iter()
+            .map(|migration| migration.name().version().to_string())
+            .max();
+        assert_eq!(status.migration, last);
+    }
+
+    #[test]
+    fn test_missing_column_is_named() {
+        let pool = DbPool::new_test_shared();
+        let mut conn = pool.get().unwrap();
+
+        // A temporary view shadows the table for this connection only, and without `user_id`
+        sql_query("CREATE TEMPORARY VIEW plans AS SELECT name, last_modified FROM public.plans")
+            .execute(&mut conn)
+            .unwrap();
+
+        let error = check_schema(&mut conn).unwrap_err();
+        assert!(matches!(error, AppError::SchemaDrift(_)));
+        assert_eq!(
+            error.to_string(),
+            "The database doesn't match the server (the table `plans` doesn't match the models \
+             (column plans.user_id does not exist))"
+        );
+    }
+}
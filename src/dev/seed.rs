@@ -0,0 +1,369 @@
+//! Demo data for local development, created with the constructors of the models
+//!
+//! The data is generated from a fixed seed, so every seeded database has the same transactions
+//! relative to the day it was seeded on.
+
+use bigdecimal::BigDecimal;
+use chrono::{Datelike, Months, NaiveDate};
+use diesel::Connection;
+
+use crate::database::connection::DbConn;
+use crate::database::models::{
+    accounts::{Account, AccountKind},
+    budgets::{Budget, BudgetInput, BudgetInterval},
+    categories::Category,
+    plans::Plan,
+    transactions::{Transaction, TransactionInput},
+    users::User,
+};
+use crate::errors::AppError;
+use crate::quotas::Quotas;
+
+/// Username of the demo user
+pub const DEMO_USERNAME: &str = "demo";
+
+/// Password of the demo user
+pub const DEMO_PASSWORD: &str = "demo_password";
+
+/// Seed of the generator of the transactions
+const SEED: u64 = 0x5EED;
+
+/// Currency of the accounts and budgets of the demo user
+const CURRENCY: &str = "CAD";
+
+/// Number of months of transactions, up to the month of the day of seeding
+const MONTHS: u32 = 12;
+
+/// Plans of the demo user
+const PLANS: [&str; 3] = ["Household", "Vacation", "Emergency fund"];
+
+/// Expenses of the demo user by category: the payees, and the range of an amount in cents
+const EXPENSES: [(&str, &[&str], (u64, u64)); 6] = [
+    (
+        "Groceries",
+        &["FreshCo", "Loblaws", "Farmers Market"],
+        (1500, 18000),
+    ),
+    (
+        "Dining",
+        &["Tim Hortons", "Pizza Pizza", "Thai Express"],
+        (800, 9000),
+    ),
+    (
+        "Transport",
+        &["Petro-Canada", "Transit Pass", "Uber"],
+        (300, 9000),
+    ),
+    (
+        "Utilities",
+        &["Hydro One", "Rogers", "Enbridge Gas"],
+        (4000, 16000),
+    ),
+    (
+        "Entertainment",
+        &["Netflix", "Cineplex", "Steam"],
+        (1000, 6000),
+    ),
+    (
+        "Health",
+        &["Shoppers Drug Mart", "Dental Clinic"],
+        (1500, 25000),
+    ),
+];
+
+/// Expenses of each month, besides the rent and the salary
+const EXPENSES_PER_MONTH: usize = 24;
+
+/// What was seeded
+#[derive(Debug)]
+pub struct SeedSummary {
+    /// The demo user
+    pub user: User,
+    /// Number of plans created
+    pub plans: usize,
+    /// Number of budgets created
+    pub budgets: usize,
+    /// Number of accounts created
+    pub accounts: usize,
+    /// Number of categories created
+    pub categories: usize,
+    /// Number of transactions created
+    pub transactions: usize,
+}
+
+/// Refuse to seed a database unless its name says it is for development or tests
+///
+/// # Arguments
+///
+/// * `url` - The URL of the database
+/// * `force` - Seed the database whatever its name
+///
+/// # Returns
+///
+/// An empty result if the database can be seeded, otherwise `AppError::InvalidInput`
+pub fn ensure_seedable(url: &str, force: bool) -> Result<(), AppError> {
+    let name = url::Url::parse(url)
+        .map_err(|e| AppError::InvalidInput(format!("The database URL is invalid ({e})")))?
+        .path()
+        .trim_start_matches('/')
+        .to_owned();
+    if force || name.contains("dev") || name.contains("test") {
+        return Ok(());
+    }
+
+    Err(AppError::InvalidInput(format!(
+        "Refusing to seed the database `{name}`, whose name contains neither `dev` nor `test`, \
+         without --force"
+    )))
+}
+
+/// Create the demo user with plans, budgets, accounts, categories and a year of transactions
+///
+/// Nothing is created if anything fails.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `today` - The day of seeding, the last month of transactions is its month
+///
+/// # Returns
+///
+/// What was seeded, or `AppError::Conflict` if the demo user exists
+pub fn seed(conn: &mut DbConn, today: NaiveDate) -> Result<SeedSummary, AppError> {
+    conn.transaction(|conn| {
+        match User::from_username(conn, DEMO_USERNAME) {
+            Ok(_) => {
+                return Err(AppError::Conflict(format!(
+                    "The user `{DEMO_USERNAME}` exists, the database is already seeded"
+                )))
+            }
+            Err(AppError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+        let user = User::new(conn, DEMO_USERNAME, DEMO_PASSWORD)?;
+        let mut rng = Rng(SEED);
+
+        let salary = Category::new(conn, user.id(), "Salary")?;
+        let rent = Category::new(conn, user.id(), "Rent")?;
+        let mut expenses = vec![];
+        for (name, payees, range) in EXPENSES {
+            expenses.push((Category::new(conn, user.id(), name)?, payees, range));
+        }
+
+        let first_month = today.with_day(1).unwrap_or(today) - Months::new(MONTHS - 1);
+        let plans = PLANS
+            .iter()
+            .map(|name| Plan::new(conn, name, user.id()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut budgets = 0;
+        for (category, _, (_, max)) in &expenses {
+            Budget::new(
+                conn,
+                &plans[0],
+                &BudgetInput {
+                    category_id: category.id(),
+                    name: category.name().to_owned(),
+                    amount: cents(max * 2),
+                    interval: BudgetInterval::Monthly,
+                    currency: CURRENCY.to_owned(),
+                    start_date: first_month,
+                    end_date: None,
+                    rollover: false,
+                },
+            )?;
+            budgets += 1;
+        }
+
+        let chequing = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &cents(250_000),
+            CURRENCY,
+            AccountKind::Asset,
+        )?;
+        let savings = Account::new(
+            conn,
+            user.id(),
+            "Savings",
+            &cents(1_000_000),
+            CURRENCY,
+            AccountKind::Asset,
+        )?;
+        let credit = Account::new(
+            conn,
+            user.id(),
+            "Credit card",
+            &cents(0),
+            CURRENCY,
+            AccountKind::Credit,
+        )?;
+
+        let mut transactions = 0;
+        let mut add = |conn: &mut DbConn,
+                       account: &Account,
+                       amount: BigDecimal,
+                       payee: &str,
+                       category: &Category,
+                       day: NaiveDate| {
+            let mut input = TransactionInput::new(amount, payee, day);
+            input.payee = Some(payee.to_owned());
+            input.category_id = Some(category.id());
+            Transaction::new(conn, account, &input, &Quotas::disabled())?;
+            transactions += 1;
+            Ok::<_, AppError>(())
+        };
+        for month in 0..MONTHS {
+            let first = first_month + Months::new(month);
+            let days = (first + Months::new(1) - first).num_days() as u64;
+            let last = today.min(first + chrono::Days::new(days - 1));
+
+            add(conn, &chequing, cents(520_000), "Payroll", &salary, first)?;
+            add(conn, &chequing, -cents(185_000), "Landlord", &rent, first)?;
+            for _ in 0..EXPENSES_PER_MONTH {
+                let (category, payees, (min, max)) =
+                    &expenses[rng.below(expenses.len() as u64) as usize];
+                let day = first + chrono::Days::new(rng.below(days));
+                let account = if rng.below(3) == 0 {
+                    &chequing
+                } else {
+                    &credit
+                };
+                let amount = -cents(min + rng.below(max - min));
+                let payee = payees[rng.below(payees.len() as u64) as usize];
+                add(conn, account, amount, payee, category, day.min(last))?;
+            }
+        }
+        // Savings grow by a deposit at the end of each quarter
+        for quarter in 1..=MONTHS / 3 {
+            let day = first_month + Months::new(quarter * 3) - chrono::Days::new(1);
+            if day <= today {
+                add(conn, &savings, cents(60_000), "Deposit", &salary, day)?;
+            }
+        }
+
+        Ok(SeedSummary {
+            user,
+            plans: plans.len(),
+            budgets,
+            accounts: 3,
+            categories: expenses.len() + 2,
+            transactions,
+        })
+    })
+}
+
+/// An amount of cents
+fn cents(cents: u64) -> BigDecimal {
+    BigDecimal::new(cents.into(), 2)
+}
+
+/// A generator of pseudorandom numbers, SplitMix64, the same for the same seed on every platform
+struct Rng(u64);
+
+impl Rng {
+    /// Get the next number
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Get the next number below a bound, which must be positive
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::*;
+    use crate::database::connection::DbPool;
+    use crate::reports::{budgets::budget_vs_actual, net_worth::net_worth};
+    use crate::reports::{categories::category_breakdown, monthly::monthly_summary};
+
+    #[test]
+    fn test_ensure_seedable() {
+        assert!(ensure_seedable("postgres://u:p@localhost:5432/finance_fusion_dev", false).is_ok());
+        assert!(ensure_seedable("postgres://u:p@db/finance_fusion_test", false).is_ok());
+        let refused = ensure_seedable("postgres://u:p@db/finance_fusion", false);
+        assert!(matches!(refused, Err(AppError::InvalidInput(_))));
+        assert!(ensure_seedable("postgres://u:p@db/finance_fusion", true).is_ok());
+    }
+
+    #[test]
+    fn test_rng_is_deterministic() {
+        let numbers = |seed| {
+            let mut rng = Rng(seed);
+            (0..4).map(|_| rng.below(100)).collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(SEED), numbers(SEED));
+        assert_ne!(numbers(SEED), numbers(SEED + 1));
+    }
+
+    #[test]
+    fn test_seed() {
+        let pool = DbPool::new_test_shared();
+        let mut conn = pool.get().unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+
+        let summary = seed(&mut conn, today).unwrap();
+        let user_id = summary.user.id();
+        assert_eq!(summary.plans, 3);
+        assert_eq!(summary.accounts, 3);
+        assert_eq!(summary.categories, 8);
+        assert_eq!(summary.budgets, 6);
+        assert_eq!(summary.transactions, 12 * 26 + 3);
+
+        // The rows are the user's
+        let plans = Plan::get_all(&mut conn, user_id).unwrap();
+        assert_eq!(plans.len(), summary.plans);
+        assert_eq!(
+            Budget::get_all(&mut conn, &plans[0]).unwrap().len()
+                + Budget::get_all(&mut conn, &plans[1]).unwrap().len()
+                + Budget::get_all(&mut conn, &plans[2]).unwrap().len(),
+            summary.budgets
+        );
+        assert_eq!(
+            Category::get_all(&mut conn, user_id).unwrap().len(),
+            summary.categories
+        );
+        let accounts = Account::get_all(&mut conn, user_id, false).unwrap();
+        assert_eq!(accounts.len(), summary.accounts);
+        let transactions: Vec<Transaction> = accounts
+            .iter()
+            .flat_map(|account| Transaction::get_all(&mut conn, account).unwrap())
+            .collect();
+        assert_eq!(transactions.len(), summary.transactions);
+        let first = NaiveDate::from_ymd_opt(2023, 7, 1).unwrap();
+        assert!(transactions
+            .iter()
+            .all(|transaction| (first..=today).contains(&transaction.occurred_at())));
+
+        // Reports have data for every month
+        let summary = json(monthly_summary(&mut conn, user_id, 2023, 7, 1, None).unwrap());
+        assert!(!summary["categories"].as_array().unwrap().is_empty());
+        assert_ne!(summary["expenses"], "0");
+        let breakdown = json(category_breakdown(&mut conn, user_id, 2024, 6).unwrap());
+        assert!(!breakdown["expenses"].as_array().unwrap().is_empty());
+        let worth = json(net_worth(&mut conn, user_id, None).unwrap());
+        assert_eq!(worth.as_array().map(Vec::len), Some(1));
+        let household = Plan::from_name(&mut conn, PLANS[0], user_id).unwrap();
+        let status = json(budget_vs_actual(&mut conn, &household, 2024, 6, 1, None).unwrap());
+        assert!(
+            status.as_array().is_some_and(|budgets| !budgets.is_empty()),
+            "{status}"
+        );
+
+        // The demo user is only seeded once
+        assert!(matches!(seed(&mut conn, today), Err(AppError::Conflict(_))));
+    }
+
+    fn json(value: impl Serialize) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+}
@@ -1,13 +1,23 @@
-use axum::http::StatusCode;
+use axum::extract::multipart::MultipartError;
+use axum::http::{header, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use bcrypt::BcryptError;
-use serde_json::json;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::{json, Value};
 use tokio::task::JoinError;
+use utoipa::openapi::schema::{ObjectBuilder, Schema, SchemaType};
+use utoipa::openapi::RefOr;
+use utoipa::ToSchema;
 
 use diesel::result::ConnectionError as SQLError;
 use diesel::result::Error as DieselError;
 
+use crate::i18n::Locale;
+use crate::middleware::request_id::RequestId;
+use crate::quotas::{QuotaExceeded, Resource};
+
 #[derive(thiserror::Error, Debug)]
 #[error("...")]
 pub enum AppError {
@@ -15,7 +25,7 @@ pub enum AppError {
     Diesel(#[from] DieselError),
 
     #[error("{0}")]
-    SQL(#[from] SQLError),
+    Sql(#[from] SQLError),
 
     #[error("{0}")]
     Signal(#[from] std::io::Error),
@@ -35,43 +45,529 @@ pub enum AppError {
     #[error("{0}")]
     NotFound(#[from] NotFound),
 
+    #[error("{0}")]
+    InvalidInput(String),
+
+    #[error("{0}")]
+    InvalidBody(#[from] InvalidBody),
+
+    #[error("{0}")]
+    InvalidQuery(#[from] InvalidQuery),
+
+    #[error("{0}")]
+    QuotaExceeded(QuotaExceeded),
+
+    #[error("{0}")]
+    CurrencyMismatch(#[from] CurrencyMismatch),
+
+    #[error("The notification channel didn't accept the notification ({0})")]
+    ChannelRefused(String),
+
+    #[error("The resource was modified since it was read, its current version is {0}")]
+    PreconditionFailed(i32),
+
+    #[error("Updates must send the ETag of the resource they modify in `If-Match`")]
+    PreconditionRequired,
+
+    #[error("Forbidden")]
+    Forbidden,
+
+    #[error("Missing exchange rates for {}", .0.join(", "))]
+    MissingExchangeRates(Vec<String>),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("Too many requests, retry in {0} seconds")]
+    RateLimited(u64),
+
+    #[error("Request body is too large")]
+    PayloadTooLarge,
+
+    #[error("Method not allowed")]
+    MethodNotAllowed,
+
     #[error("{0}")]
     RunSyncTask(#[from] JoinError),
 
     #[error("{0}")]
     HashPassword(#[from] BcryptError),
 
-    #[error("Database connection error")]
+    #[error("The database is unavailable, try again later")]
     DbConnectionError,
+
+    #[error("Request timed out")]
+    Timeout,
+
+    #[error("Failed to migrate the database ({0})")]
+    Migration(String),
+
+    #[error("{0}")]
+    Maintenance(String, u64),
+
+    #[error("The database doesn't match the server ({0})")]
+    SchemaDrift(String),
+
+    #[error("Every database connection is in use, retry in {0} seconds")]
+    DbPoolExhausted(u64),
+}
+
+/// The codes of the errors of the API, stable across releases so clients can branch on them
+///
+/// Codes of client errors start with `400`, and codes of server errors with `50`. Each code also
+/// has a stable slug, sent as the `error` field of error bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum ErrorCode {
+    InvalidObjectId = 40001,
+    BadRequest = 40002,
+    NotFound = 40003,
+    WrongCredentials = 40004,
+    InvalidToken = 40005,
+    Locked = 40006,
+    SessionExpired = 40007,
+    InvalidInput = 40008,
+    Forbidden = 40009,
+    MissingExchangeRates = 40010,
+    Conflict = 40011,
+    RateLimited = 40012,
+    PayloadTooLarge = 40013,
+    MethodNotAllowed = 40014,
+    InvalidBody = 40015,
+    QuotaExceeded = 40016,
+    PreconditionFailed = 40017,
+    PreconditionRequired = 40018,
+    InvalidQuery = 40019,
+    CurrencyMismatch = 40020,
+    ChannelRefused = 40021,
+    TokenCreation = 5001,
+    Database = 5002,
+    DatabaseConnection = 5003,
+    Deserialization = 5004,
+    TaskFailed = 5005,
+    PasswordHashing = 5006,
+    Timeout = 5007,
+    DatabaseUnavailable = 5008,
+    Io = 5009,
+    Migration = 5010,
+    Maintenance = 5011,
+    SchemaDrift = 5012,
+    DatabaseBusy = 5013,
+}
+
+impl ErrorCode {
+    /// Every code, in the order they are documented
+    pub const ALL: [ErrorCode; 34] = [
+        ErrorCode::InvalidObjectId,
+        ErrorCode::BadRequest,
+        ErrorCode::NotFound,
+        ErrorCode::WrongCredentials,
+        ErrorCode::InvalidToken,
+        ErrorCode::Locked,
+        ErrorCode::SessionExpired,
+        ErrorCode::InvalidInput,
+        ErrorCode::Forbidden,
+        ErrorCode::MissingExchangeRates,
+        ErrorCode::Conflict,
+        ErrorCode::RateLimited,
+        ErrorCode::PayloadTooLarge,
+        ErrorCode::MethodNotAllowed,
+        ErrorCode::InvalidBody,
+        ErrorCode::QuotaExceeded,
+        ErrorCode::PreconditionFailed,
+        ErrorCode::PreconditionRequired,
+        ErrorCode::InvalidQuery,
+        ErrorCode::CurrencyMismatch,
+        ErrorCode::ChannelRefused,
+        ErrorCode::TokenCreation,
+        ErrorCode::Database,
+        ErrorCode::DatabaseConnection,
+        ErrorCode::Deserialization,
+        ErrorCode::TaskFailed,
+        ErrorCode::PasswordHashing,
+        ErrorCode::Timeout,
+        ErrorCode::DatabaseUnavailable,
+        ErrorCode::Io,
+        ErrorCode::Migration,
+        ErrorCode::Maintenance,
+        ErrorCode::SchemaDrift,
+        ErrorCode::DatabaseBusy,
+    ];
+
+    /// Get the slug of the code, e.g. `wrong_credentials`
+    pub fn slug(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidObjectId => "invalid_object_id",
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::WrongCredentials => "wrong_credentials",
+            ErrorCode::InvalidToken => "invalid_token",
+            ErrorCode::Locked => "locked",
+            ErrorCode::SessionExpired => "session_expired",
+            ErrorCode::InvalidInput => "invalid_input",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::MissingExchangeRates => "missing_exchange_rates",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::MethodNotAllowed => "method_not_allowed",
+            ErrorCode::InvalidBody => "invalid_body",
+            ErrorCode::QuotaExceeded => "quota_exceeded",
+            ErrorCode::PreconditionFailed => "precondition_failed",
+            ErrorCode::PreconditionRequired => "precondition_required",
+            ErrorCode::InvalidQuery => "invalid_query",
+            ErrorCode::CurrencyMismatch => "currency_mismatch",
+            ErrorCode::ChannelRefused => "channel_refused",
+            ErrorCode::TokenCreation => "token_creation",
+            ErrorCode::Database => "database",
+            ErrorCode::DatabaseConnection => "database_connection",
+            ErrorCode::Deserialization => "deserialization",
+            ErrorCode::TaskFailed => "task_failed",
+            ErrorCode::PasswordHashing => "password_hashing",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::DatabaseUnavailable => "database_unavailable",
+            ErrorCode::Io => "io",
+            ErrorCode::Migration => "migration",
+            ErrorCode::Maintenance => "maintenance",
+            ErrorCode::SchemaDrift => "schema_drift",
+            ErrorCode::DatabaseBusy => "database_busy",
+        }
+    }
+
+    /// Get the status of the responses with the code
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidObjectId
+            | ErrorCode::BadRequest
+            | ErrorCode::InvalidInput
+            | ErrorCode::InvalidQuery => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::WrongCredentials | ErrorCode::InvalidToken | ErrorCode::SessionExpired => {
+                StatusCode::UNAUTHORIZED
+            }
+            ErrorCode::Locked => StatusCode::LOCKED,
+            ErrorCode::Forbidden | ErrorCode::QuotaExceeded => StatusCode::FORBIDDEN,
+            ErrorCode::MissingExchangeRates
+            | ErrorCode::InvalidBody
+            | ErrorCode::CurrencyMismatch
+            | ErrorCode::ChannelRefused => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            ErrorCode::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::TokenCreation
+            | ErrorCode::Database
+            | ErrorCode::DatabaseConnection
+            | ErrorCode::Deserialization
+            | ErrorCode::TaskFailed
+            | ErrorCode::PasswordHashing
+            | ErrorCode::Io
+            | ErrorCode::Migration
+            | ErrorCode::SchemaDrift => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::DatabaseUnavailable | ErrorCode::Maintenance | ErrorCode::DatabaseBusy => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }
+    }
+
+    /// Get what the code means, for the documentation
+    fn meaning(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidObjectId => "An object ID couldn't be parsed",
+            ErrorCode::BadRequest => "The request is malformed",
+            ErrorCode::NotFound => "The resource doesn't exist, or isn't the user's",
+            ErrorCode::WrongCredentials => "The username or password is wrong",
+            ErrorCode::InvalidToken => "The session token or metrics token is missing or invalid",
+            ErrorCode::Locked => {
+                "The user is locked out after failed logins, `Retry-After` tells until when"
+            }
+            ErrorCode::SessionExpired => "The session has expired, the user must log in again",
+            ErrorCode::InvalidInput => "A value of the request is invalid, the message tells which",
+            ErrorCode::Forbidden => "The user isn't allowed to do this",
+            ErrorCode::MissingExchangeRates => {
+                "Amounts can't be converted, `details.currencies` lists the missing rates"
+            }
+            ErrorCode::Conflict => "The request conflicts with the current state of the resource",
+            ErrorCode::RateLimited => "Too many requests, `Retry-After` tells when to retry",
+            ErrorCode::PayloadTooLarge => "The request body is over the size limit",
+            ErrorCode::MethodNotAllowed => {
+                "The path doesn't accept the method, `Allow` lists the methods it does"
+            }
+            ErrorCode::InvalidBody => {
+                "The JSON body can't be read, `details` tells the `kind` of error and the `field`, \
+                 or the `line` and `column`, at fault"
+            }
+            ErrorCode::QuotaExceeded => {
+                "The write would take the user over a quota, `details` tells the `resource`, its \
+                 `limit` and how much is `used`"
+            }
+            ErrorCode::PreconditionFailed => {
+                "The `If-Match` of the update isn't the current version of the resource, \
+                 `details.version` tells the current one"
+            }
+            ErrorCode::PreconditionRequired => "The update has no `If-Match` header",
+            ErrorCode::InvalidQuery => {
+                "The query string can't be read or breaks a rule, `details` tells the `kind` of \
+                 error and the `fields` at fault"
+            }
+            ErrorCode::CurrencyMismatch => {
+                "The amount isn't in the currency of the account, `details` tells the `currency` \
+                 of the amount and the `account_currency`"
+            }
+            ErrorCode::ChannelRefused => {
+                "The notification channel couldn't be reached or refused the test notification, \
+                 the message tells why"
+            }
+            ErrorCode::TokenCreation => "A session token couldn't be created",
+            ErrorCode::Database => "A database query failed",
+            ErrorCode::DatabaseConnection => "A connection to the database couldn't be made",
+            ErrorCode::Deserialization => "Stored data couldn't be read",
+            ErrorCode::TaskFailed => "A background task failed",
+            ErrorCode::PasswordHashing => "A password couldn't be hashed",
+            ErrorCode::Timeout => "The request took too long to answer",
+            ErrorCode::DatabaseUnavailable => {
+                "No database connection is available, the request can be retried"
+            }
+            ErrorCode::Io => "An I/O operation of the server failed",
+            ErrorCode::Migration => "The migrations of the database failed",
+            ErrorCode::Maintenance => {
+                "The server is under maintenance, the message is the operator's and `Retry-After` \
+                 tells when to retry"
+            }
+            ErrorCode::SchemaDrift => {
+                "The database is missing migrations or columns of the server, found at startup"
+            }
+            ErrorCode::DatabaseBusy => {
+                "Every database connection stayed in use for the request, `Retry-After` tells when \
+                 to retry"
+            }
+        }
+    }
+}
+
+impl<'s> ToSchema<'s> for ErrorCode {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let mut description = "The code of an error, along with the slug sent as its `error` \
+                               field and the status of its responses\n\n\
+                               | code | error | status | meaning |\n\
+                               |---|---|---|---|\n"
+            .to_string();
+        for code in ErrorCode::ALL {
+            description.push_str(&format!(
+                "| {} | `{}` | {} | {} |\n",
+                code as u16,
+                code.slug(),
+                code.status().as_u16(),
+                code.meaning()
+            ));
+        }
+
+        (
+            "ErrorCode",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::Integer)
+                .enum_values(Some(ErrorCode::ALL.map(|code| code as u16)))
+                .description(Some(description))
+                .into(),
+        )
+    }
+}
+
+/// The body of the responses to errors
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// The code of the error
+    #[schema(value_type = ErrorCode)]
+    pub code: u16,
+    /// The slug of the code of the error, e.g. `wrong_credentials`
+    pub error: &'static str,
+    /// What went wrong, for people rather than clients to read, in the language of
+    /// `Accept-Language` if it has a catalog
+    pub message: String,
+    /// Data about the error, for the codes that document it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Value>,
+    /// The ID of the request, to find it in the logs of the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl AppError {
-    fn get_codes(&self) -> (StatusCode, u16) {
-        match *self {
+    /// Get the code of the error
+    ///
+    /// Every variant is matched by name, so a new variant can't fall back to a `500` unnoticed.
+    pub fn code(&self) -> ErrorCode {
+        match self {
             // 4XX Errors
-            AppError::ParseObjectID(_) => (StatusCode::BAD_REQUEST, 40001),
-            AppError::BadRequest(_) => (StatusCode::BAD_REQUEST, 40002),
-            AppError::NotFound(_) => (StatusCode::NOT_FOUND, 40003),
+            AppError::ParseObjectID(_) => ErrorCode::InvalidObjectId,
+            AppError::BadRequest(_) => ErrorCode::BadRequest,
+            AppError::NotFound(_) => ErrorCode::NotFound,
             AppError::Authenticate(AuthenticateError::WrongCredentials) => {
-                (StatusCode::UNAUTHORIZED, 40004)
+                ErrorCode::WrongCredentials
             }
-            AppError::Authenticate(AuthenticateError::InvalidToken) => {
-                (StatusCode::UNAUTHORIZED, 40005)
-            }
-            AppError::Authenticate(AuthenticateError::Locked) => (StatusCode::LOCKED, 40006),
-            AppError::Authenticate(AuthenticateError::SessionExpired) => (StatusCode::UNAUTHORIZED, 40007),
+            AppError::Authenticate(AuthenticateError::InvalidToken) => ErrorCode::InvalidToken,
+            AppError::Authenticate(AuthenticateError::Locked(_)) => ErrorCode::Locked,
+            AppError::Authenticate(AuthenticateError::SessionExpired) => ErrorCode::SessionExpired,
+            AppError::InvalidInput(_) => ErrorCode::InvalidInput,
+            AppError::Forbidden => ErrorCode::Forbidden,
+            AppError::MissingExchangeRates(_) => ErrorCode::MissingExchangeRates,
+            AppError::Conflict(_) => ErrorCode::Conflict,
+            AppError::RateLimited(_) => ErrorCode::RateLimited,
+            AppError::PayloadTooLarge => ErrorCode::PayloadTooLarge,
+            AppError::MethodNotAllowed => ErrorCode::MethodNotAllowed,
+            AppError::InvalidBody(_) => ErrorCode::InvalidBody,
+            AppError::InvalidQuery(_) => ErrorCode::InvalidQuery,
+            AppError::CurrencyMismatch(_) => ErrorCode::CurrencyMismatch,
+            AppError::ChannelRefused(_) => ErrorCode::ChannelRefused,
+            AppError::QuotaExceeded(_) => ErrorCode::QuotaExceeded,
+            AppError::PreconditionFailed(_) => ErrorCode::PreconditionFailed,
+            AppError::PreconditionRequired => ErrorCode::PreconditionRequired,
 
             // 5XX Errors
-            AppError::Signal(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5003),
-            AppError::Authenticate(AuthenticateError::TokenCreation) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, 5001)
-            }
-            AppError::Diesel(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5002),
-            AppError::SQL(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5003),
-            AppError::SerializeMongoResponse(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5004),
-            AppError::RunSyncTask(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5005),
-            AppError::HashPassword(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5006),
-            AppError::DbConnectionError => (StatusCode::INTERNAL_SERVER_ERROR, 5002),
+            AppError::Authenticate(AuthenticateError::TokenCreation) => ErrorCode::TokenCreation,
+            AppError::Diesel(_) => ErrorCode::Database,
+            AppError::Sql(_) => ErrorCode::DatabaseConnection,
+            AppError::SerializeMongoResponse(_) => ErrorCode::Deserialization,
+            AppError::RunSyncTask(_) => ErrorCode::TaskFailed,
+            AppError::HashPassword(_) => ErrorCode::PasswordHashing,
+            AppError::Timeout => ErrorCode::Timeout,
+            AppError::DbConnectionError => ErrorCode::DatabaseUnavailable,
+            AppError::Signal(_) => ErrorCode::Io,
+            AppError::Migration(_) => ErrorCode::Migration,
+            AppError::SchemaDrift(_) => ErrorCode::SchemaDrift,
+            AppError::Maintenance(..) => ErrorCode::Maintenance,
+            AppError::DbPoolExhausted(_) => ErrorCode::DatabaseBusy,
+        }
+    }
+
+    /// Get the data about the error sent as the `details` of its body, if any
+    fn details(&self) -> Option<Value> {
+        match self {
+            AppError::MissingExchangeRates(currencies) => Some(json!({ "currencies": currencies })),
+            AppError::InvalidBody(invalid) => serde_json::to_value(invalid).ok(),
+            AppError::InvalidQuery(invalid) => serde_json::to_value(invalid).ok(),
+            AppError::QuotaExceeded(exceeded) => serde_json::to_value(exceeded).ok(),
+            AppError::CurrencyMismatch(mismatch) => serde_json::to_value(mismatch).ok(),
+            AppError::PreconditionFailed(version) => Some(json!({ "version": version })),
+            // `retry_after` is kept like for the other errors that can be retried
+            AppError::Authenticate(AuthenticateError::Locked(lockout)) => Some(json!({
+                "retry_after": lockout.retry_after_seconds,
+                "retry_after_seconds": lockout.retry_after_seconds,
+                "locked_until": crate::utils::serialization::format(&lockout.locked_until),
+            })),
+            _ => self
+                .retry_after()
+                .map(|seconds| json!({ "retry_after": seconds })),
+        }
+    }
+
+    /// Get the seconds until the request can be retried, sent as the `Retry-After` header
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            AppError::Authenticate(AuthenticateError::Locked(lockout)) => {
+                Some(lockout.retry_after_seconds)
+            }
+            AppError::RateLimited(seconds)
+            | AppError::Maintenance(_, seconds)
+            | AppError::DbPoolExhausted(seconds) => Some(*seconds),
+            _ => None,
+        }
+    }
+
+    /// Get the message of the error in a locale
+    ///
+    /// English messages are the messages of the errors themselves, other locales take theirs from
+    /// their catalog and fall back to English.
+    pub fn message(&self, locale: Locale) -> String {
+        let slug = self.code().slug();
+        let key = match self {
+            AppError::InvalidBody(InvalidBody {
+                kind: "invalid_field",
+                field: None,
+                ..
+            }) => slug.to_string(),
+            AppError::InvalidBody(invalid) => format!("{slug}.{}", invalid.kind),
+            AppError::InvalidQuery(InvalidQuery {
+                kind: "invalid_field",
+                fields,
+                ..
+            }) if fields.is_empty() => slug.to_string(),
+            AppError::InvalidQuery(invalid) => format!("{slug}.{}", invalid.kind),
+            AppError::QuotaExceeded(exceeded) => format!(
+                "{slug}.{}",
+                match exceeded.resource {
+                    Resource::Transactions => "transactions",
+                    Resource::AttachmentBytes => "attachment_bytes",
+                    Resource::Webhooks => "webhooks",
+                    Resource::Accounts => "accounts",
+                }
+            ),
+            _ => slug.to_string(),
+        };
+
+        locale
+            .message(&key, &self.message_args())
+            .unwrap_or_else(|| self.to_string())
+    }
+
+    /// Get the values of the placeholders of the localized messages of the error
+    fn message_args(&self) -> Vec<(&'static str, String)> {
+        match self {
+            AppError::ParseObjectID(id) => vec![("id", id.clone())],
+            AppError::Authenticate(AuthenticateError::Locked(lockout)) => {
+                vec![("seconds", lockout.retry_after_seconds.to_string())]
+            }
+            AppError::RateLimited(seconds) | AppError::DbPoolExhausted(seconds) => {
+                vec![("seconds", seconds.to_string())]
+            }
+            AppError::MissingExchangeRates(currencies) => {
+                vec![("currencies", currencies.join(", "))]
+            }
+            AppError::InvalidBody(invalid) => [
+                invalid.field.clone().map(|field| ("field", field)),
+                invalid.line.map(|line| ("line", line.to_string())),
+                invalid.column.map(|column| ("column", column.to_string())),
+                invalid.reason.clone().map(|reason| ("reason", reason)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            AppError::InvalidQuery(invalid) => [
+                invalid.fields.first().map(|field| ("field", field.clone())),
+                Some((
+                    "fields",
+                    invalid
+                        .fields
+                        .iter()
+                        .map(|field| format!("`{field}`"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )),
+                invalid.reason.clone().map(|reason| ("reason", reason)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            AppError::QuotaExceeded(exceeded) => vec![
+                ("limit", exceeded.limit.to_string()),
+                ("used", exceeded.used.to_string()),
+            ],
+            AppError::PreconditionFailed(version) => vec![("version", version.to_string())],
+            AppError::CurrencyMismatch(mismatch) => vec![
+                ("currency", mismatch.currency.clone()),
+                ("account_currency", mismatch.account_currency.clone()),
+            ],
+            AppError::InvalidInput(detail)
+            | AppError::Conflict(detail)
+            | AppError::ChannelRefused(detail)
+            | AppError::Migration(detail)
+            | AppError::Maintenance(detail, _)
+            | AppError::SchemaDrift(detail) => vec![("detail", detail.clone())],
+            _ => vec![("detail", self.to_string())],
         }
     }
 
@@ -84,13 +580,34 @@ impl AppError {
     }
 }
 
+impl From<MultipartError> for AppError {
+    fn from(e: MultipartError) -> Self {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            AppError::PayloadTooLarge
+        } else {
+            AppError::InvalidInput(e.body_text())
+        }
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status_code, code) = self.get_codes();
-        let message = self.to_string();
-        let body = Json(json!({ "code": code, "message": message }));
+        let code = self.code();
+        let body = ErrorBody {
+            code: code as u16,
+            error: code.slug(),
+            message: self.message(Locale::current()),
+            details: self.details(),
+            request_id: RequestId::current(),
+        };
 
-        (status_code, body).into_response()
+        let mut response = (code.status(), Json(body)).into_response();
+        if let Some(retry_after) = self.retry_after() {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
     }
 }
 
@@ -103,12 +620,43 @@ pub enum AuthenticateError {
     TokenCreation,
     #[error("Invalid authentication credentials")]
     InvalidToken,
-    #[error("User is locked")]
-    Locked,
+    #[error("User is locked, retry in {} seconds", .0.retry_after_seconds)]
+    Locked(Lockout),
     #[error("Session has expired")]
     SessionExpired,
 }
 
+/// How long a locked user has to wait before logging in, sent as the `details` of the error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Lockout {
+    /// When the lock expires
+    #[serde(with = "crate::utils::serialization")]
+    pub locked_until: NaiveDateTime,
+    /// Seconds until the lock expires, rounded up
+    pub retry_after_seconds: u64,
+}
+
+impl Lockout {
+    /// The lock of a user at a time, `None` if it already expired
+    ///
+    /// # Arguments
+    ///
+    /// * `locked_until` - When the lock expires
+    /// * `now` - The current time, in UTC
+    pub fn at(locked_until: NaiveDateTime, now: NaiveDateTime) -> Option<Self> {
+        let left = locked_until - now;
+        if left <= chrono::Duration::zero() {
+            return None;
+        }
+        // Round up, so that retrying after the advertised wait always succeeds
+        let seconds = left.num_seconds() + i64::from(left.subsec_nanos() > 0);
+        Some(Self {
+            locked_until,
+            retry_after_seconds: seconds as u64,
+        })
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error("Bad Request")]
 pub struct BadRequest {}
@@ -116,3 +664,323 @@ pub struct BadRequest {}
 #[derive(thiserror::Error, Debug)]
 #[error("Not found")]
 pub struct NotFound {}
+
+/// Why a JSON request body can't be read, sent as the `details` of the error
+#[derive(thiserror::Error, Debug, Serialize)]
+#[error("{message}")]
+pub struct InvalidBody {
+    /// What went wrong, the message of the error
+    #[serde(skip)]
+    pub message: String,
+    /// Why the parser couldn't read the body, for the messages of other locales
+    #[serde(skip)]
+    pub reason: Option<String>,
+    /// `content_type`, `syntax`, `missing_field` or `invalid_field`
+    pub kind: &'static str,
+    /// The path of the field at fault, e.g. `splits[0].amount`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// The line of the body at fault, from 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// The column of the line at fault, from 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+/// Why the query string of a request is invalid, sent as the `details` of the error
+#[derive(thiserror::Error, Debug, Serialize)]
+#[error("{message}")]
+pub struct InvalidQuery {
+    /// What went wrong, the message of the error
+    #[serde(skip)]
+    pub message: String,
+    /// Why the parameters can't be read, for the messages of other locales
+    #[serde(skip)]
+    pub reason: Option<String>,
+    /// `invalid_field`, `missing_field`, `unknown_field` or `invalid_range`
+    pub kind: &'static str,
+    /// The parameters at fault, e.g. `["from", "to"]` for a range that ends before it starts
+    pub fields: Vec<String>,
+}
+
+impl InvalidQuery {
+    /// A range of parameters that ends before it starts
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The parameter the range starts at, e.g. `from`
+    /// * `to` - The parameter the range ends at, e.g. `to`
+    /// * `reason` - Why the range is invalid, e.g. "it starts on 2024-06-02, after it ends on
+    ///   2024-06-01"
+    pub fn range(from: &str, to: &str, reason: String) -> Self {
+        Self {
+            message: format!("Invalid range of `{from}` and `{to}`: {reason}"),
+            reason: Some(reason),
+            kind: "invalid_range",
+            fields: vec![from.to_owned(), to.to_owned()],
+        }
+    }
+}
+
+/// The currency of an amount that isn't the currency of its account, sent as the `details` of the
+/// error
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Serialize)]
+#[error(
+    "The amount is in {currency} but the account is in {account_currency}, send it converted to \
+     {account_currency} with `original_amount` and `original_currency`"
+)]
+pub struct CurrencyMismatch {
+    /// ISO 4217 code of the currency of the amount
+    pub currency: String,
+    /// ISO 4217 code of the currency of the account
+    pub account_currency: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use http_body_util::BodyExt;
+
+    use super::*;
+    use crate::quotas::Resource;
+
+    /// Get the status, headers and JSON body of the response to an error
+    async fn respond(error: AppError) -> (StatusCode, axum::http::HeaderMap, Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, headers, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_error_responses() {
+        let task = tokio::spawn(std::future::pending::<()>());
+        task.abort();
+        let cancelled = task.await.unwrap_err();
+
+        let errors = [
+            (
+                AppError::Diesel(DieselError::RollbackTransaction),
+                ErrorCode::Database,
+            ),
+            (
+                AppError::Sql(SQLError::BadConnection("refused".to_string())),
+                ErrorCode::DatabaseConnection,
+            ),
+            (
+                AppError::Signal(std::io::Error::other("interrupted")),
+                ErrorCode::Io,
+            ),
+            (
+                AppError::ParseObjectID("1".to_string()),
+                ErrorCode::InvalidObjectId,
+            ),
+            (
+                AppError::SerializeMongoResponse(serde::de::Error::custom("invalid")),
+                ErrorCode::Deserialization,
+            ),
+            (
+                AppError::Authenticate(AuthenticateError::WrongCredentials),
+                ErrorCode::WrongCredentials,
+            ),
+            (
+                AppError::Authenticate(AuthenticateError::TokenCreation),
+                ErrorCode::TokenCreation,
+            ),
+            (
+                AppError::Authenticate(AuthenticateError::InvalidToken),
+                ErrorCode::InvalidToken,
+            ),
+            (
+                AppError::Authenticate(AuthenticateError::Locked(Lockout {
+                    locked_until: chrono::DateTime::UNIX_EPOCH.naive_utc(),
+                    retry_after_seconds: 60,
+                })),
+                ErrorCode::Locked,
+            ),
+            (
+                AppError::Authenticate(AuthenticateError::SessionExpired),
+                ErrorCode::SessionExpired,
+            ),
+            (AppError::bad_request(), ErrorCode::BadRequest),
+            (AppError::not_found(), ErrorCode::NotFound),
+            (
+                AppError::InvalidInput("Invalid name".to_string()),
+                ErrorCode::InvalidInput,
+            ),
+            (AppError::Forbidden, ErrorCode::Forbidden),
+            (
+                AppError::MissingExchangeRates(vec!["EUR".to_string()]),
+                ErrorCode::MissingExchangeRates,
+            ),
+            (AppError::Conflict("Taken".to_string()), ErrorCode::Conflict),
+            (AppError::RateLimited(3), ErrorCode::RateLimited),
+            (AppError::PayloadTooLarge, ErrorCode::PayloadTooLarge),
+            (AppError::MethodNotAllowed, ErrorCode::MethodNotAllowed),
+            (
+                AppError::InvalidBody(InvalidBody {
+                    message: "Missing field `name`".to_string(),
+                    reason: None,
+                    kind: "missing_field",
+                    field: Some("name".to_string()),
+                    line: None,
+                    column: None,
+                }),
+                ErrorCode::InvalidBody,
+            ),
+            (
+                AppError::InvalidQuery(InvalidQuery::range(
+                    "from",
+                    "to",
+                    "it starts after it ends".to_string(),
+                )),
+                ErrorCode::InvalidQuery,
+            ),
+            (
+                AppError::CurrencyMismatch(CurrencyMismatch {
+                    currency: "USD".to_string(),
+                    account_currency: "CAD".to_string(),
+                }),
+                ErrorCode::CurrencyMismatch,
+            ),
+            (
+                AppError::ChannelRefused("connection refused".to_string()),
+                ErrorCode::ChannelRefused,
+            ),
+            (
+                AppError::QuotaExceeded(QuotaExceeded {
+                    resource: Resource::Webhooks,
+                    limit: 20,
+                    used: 20,
+                }),
+                ErrorCode::QuotaExceeded,
+            ),
+            (
+                AppError::PreconditionFailed(3),
+                ErrorCode::PreconditionFailed,
+            ),
+            (
+                AppError::PreconditionRequired,
+                ErrorCode::PreconditionRequired,
+            ),
+            (AppError::RunSyncTask(cancelled), ErrorCode::TaskFailed),
+            (
+                AppError::HashPassword(BcryptError::CostNotAllowed(1)),
+                ErrorCode::PasswordHashing,
+            ),
+            (AppError::DbConnectionError, ErrorCode::DatabaseUnavailable),
+            (AppError::Timeout, ErrorCode::Timeout),
+            (
+                AppError::Migration("dirty".to_string()),
+                ErrorCode::Migration,
+            ),
+            (
+                AppError::Maintenance("Back soon".to_string(), 60),
+                ErrorCode::Maintenance,
+            ),
+            (
+                AppError::SchemaDrift("missing column".to_string()),
+                ErrorCode::SchemaDrift,
+            ),
+            (AppError::DbPoolExhausted(1), ErrorCode::DatabaseBusy),
+        ];
+        assert_eq!(errors.len(), ErrorCode::ALL.len());
+
+        for (error, code) in errors {
+            let message = error.to_string();
+            let (status, headers, body) = respond(error).await;
+            assert_eq!(status, code.status(), "{message}");
+            assert_eq!(body["code"], code as u16, "{message}");
+            assert_eq!(body["error"], code.slug(), "{message}");
+            assert_eq!(body["message"], message);
+            assert!(body.get("request_id").is_none());
+            assert_eq!(
+                headers.contains_key(header::RETRY_AFTER),
+                matches!(
+                    code,
+                    ErrorCode::Locked
+                        | ErrorCode::RateLimited
+                        | ErrorCode::Maintenance
+                        | ErrorCode::DatabaseBusy
+                ),
+                "{message}"
+            );
+        }
+
+        // Codes and slugs are documented, so they must not change
+        let (status, headers, body) = respond(AppError::RateLimited(3)).await;
+        assert_eq!(status, 429);
+        assert_eq!(headers[header::RETRY_AFTER], "3");
+        assert_eq!(
+            body,
+            json!({
+                "code": 40012,
+                "error": "rate_limited",
+                "message": "Too many requests, retry in 3 seconds",
+                "details": {"retry_after": 3}
+            })
+        );
+        let (status, _, body) =
+            respond(AppError::MissingExchangeRates(vec!["EUR".to_string()])).await;
+        assert_eq!(status, 422);
+        assert_eq!(body["code"], 40010);
+        assert_eq!(body["error"], "missing_exchange_rates");
+        assert_eq!(body["details"], json!({"currencies": ["EUR"]}));
+        let (status, _, body) = respond(AppError::DbConnectionError).await;
+        assert_eq!(status, 503);
+        assert_eq!(body["code"], 5008);
+        assert_eq!(body["error"], "database_unavailable");
+        assert!(body.get("details").is_none());
+
+        // Messages are written in the locale of the request, codes are the same in all of them
+        let (_, _, body) = Locale::Fr
+            .scope(respond(AppError::QuotaExceeded(QuotaExceeded {
+                resource: Resource::Transactions,
+                limit: 100,
+                used: 100,
+            })))
+            .await;
+        assert_eq!(
+            body["message"],
+            "Au-delà du quota de 100 transactions, 100 sont utilisées"
+        );
+        assert_eq!(body["error"], "quota_exceeded");
+        let invalid_field = AppError::InvalidBody(InvalidBody {
+            message: "Invalid field `amount`: invalid type".to_string(),
+            reason: Some("invalid type".to_string()),
+            kind: "invalid_field",
+            field: Some("amount".to_string()),
+            line: Some(1),
+            column: Some(12),
+        });
+        assert_eq!(
+            invalid_field.message(Locale::Fr),
+            "Champ `amount` invalide : invalid type"
+        );
+        assert_eq!(
+            invalid_field.message(Locale::En),
+            "Invalid field `amount`: invalid type"
+        );
+        let invalid_range = AppError::InvalidQuery(InvalidQuery::range(
+            "from",
+            "to",
+            "it starts on 2024-06-02, after it ends on 2024-06-01".to_string(),
+        ));
+        assert_eq!(
+            invalid_range.message(Locale::Fr),
+            "Intervalle de `from`, `to` invalide : it starts on 2024-06-02, after it ends on \
+             2024-06-01"
+        );
+    }
+
+    #[test]
+    fn test_error_codes_are_unique() {
+        let codes: HashSet<u16> = ErrorCode::ALL.iter().map(|code| *code as u16).collect();
+        let slugs: HashSet<&str> = ErrorCode::ALL.iter().map(|code| code.slug()).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+        assert_eq!(slugs.len(), ErrorCode::ALL.len());
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Number of events a stream can fall behind on before it skips them
+const CHANNEL_CAPACITY: usize = 64;
+
+/// A change to the data of a user, pushed to their open event streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserEvent {
+    /// A plan was created
+    PlanCreated,
+    /// A plan was deleted
+    PlanDeleted,
+    /// A transaction was created
+    TransactionCreated,
+    /// A transaction was deleted
+    TransactionDeleted,
+    /// A session of the user was ended before it expired
+    SessionRevoked,
+    /// Spending in a category reached the threshold of its alert
+    CategoryAlert,
+}
+
+impl UserEvent {
+    /// Get the name of the event, as sent in streams
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserEvent::PlanCreated => "plan_created",
+            UserEvent::PlanDeleted => "plan_deleted",
+            UserEvent::TransactionCreated => "transaction_created",
+            UserEvent::TransactionDeleted => "transaction_deleted",
+            UserEvent::SessionRevoked => "session_revoked",
+            UserEvent::CategoryAlert => "category_alert",
+        }
+    }
+}
+
+/// An event published to the streams of a user
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// What happened
+    pub event: UserEvent,
+    /// The record the event is about, as JSON
+    pub data: Arc<str>,
+}
+
+/// Publishes the events of users to their open streams, within this process
+///
+/// Each user with an open stream has a broadcast channel, removed once their last stream closes.
+#[derive(Debug, Default)]
+pub struct EventBus {
+    channels: Mutex<HashMap<i32, broadcast::Sender<Frame>>>,
+    closed: AtomicBool,
+}
+
+impl EventBus {
+    /// Create a bus with no open streams
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to the events of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - User ID
+    ///
+    /// # Returns
+    ///
+    /// A receiver of the events of the user, which ends once the bus is closed
+    pub fn subscribe(&self, user_id: i32) -> broadcast::Receiver<Frame> {
+        let mut channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        if self.closed.load(Ordering::SeqCst) {
+            // The sender is dropped right away, so the stream ends
+            return broadcast::channel(1).1;
+        }
+        channels
+            .entry(user_id)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Publish an event to the open streams of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user the event happened to
+    /// * `event` - What happened
+    /// * `data` - The record the event is about
+    pub fn publish<T: Serialize>(&self, user_id: i32, event: UserEvent, data: &T) {
+        let mut channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(sender) = channels.get(&user_id) else {
+            return;
+        };
+
+        let data = match serde_json::to_string(data) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::error!("Failed serializing {} event ({e})", event.as_str());
+                return;
+            }
+        };
+        let frame = Frame {
+            event,
+            data: data.into(),
+        };
+        if sender.send(frame).is_err() {
+            // Every stream of the user is closed
+            channels.remove(&user_id);
+        }
+    }
+
+    /// End every open stream and refuse new ones, when the server shuts down
+    pub fn close(&self) {
+        let mut channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        self.closed.store(true, Ordering::SeqCst);
+        channels.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_reach_only_their_user() {
+        let bus = EventBus::new();
+        let mut receiver = bus.subscribe(1);
+
+        bus.publish(2, UserEvent::PlanCreated, &"Travel");
+        bus.publish(1, UserEvent::PlanCreated, &"Household");
+        let frame = receiver.try_recv().unwrap();
+        assert_eq!(frame.event, UserEvent::PlanCreated);
+        assert_eq!(&*frame.data, "\"Household\"");
+        assert!(receiver.try_recv().is_err());
+
+        // The channel of a user goes away with their last stream
+        drop(receiver);
+        bus.publish(1, UserEvent::PlanDeleted, &"Household");
+        assert!(bus.channels.lock().unwrap().is_empty());
+
+        let mut receiver = bus.subscribe(1);
+        bus.close();
+        assert!(matches!(
+            receiver.try_recv(),
+            Err(broadcast::error::TryRecvError::Closed)
+        ));
+        assert!(matches!(
+            bus.subscribe(1).try_recv(),
+            Err(broadcast::error::TryRecvError::Closed)
+        ));
+    }
+}
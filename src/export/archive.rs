@@ -0,0 +1,400 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+use axum::body::Body;
+use chrono::NaiveDate;
+use serde::Serialize;
+use zip::{result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use super::transactions::{self, ExportFormat, EXPORT_PAGE_SIZE};
+use crate::database::{
+    connection::{DbConn, DbPool},
+    models::{accounts::Account, budgets::Budget, categories::Category, plans::Plan},
+};
+use crate::errors::AppError;
+
+/// The days every transaction of an account occurred between, within the dates the database
+/// stores
+fn all_time() -> (NaiveDate, NaiveDate) {
+    (
+        NaiveDate::from_ymd_opt(1, 1, 1).expect("The first day of year 1 is a date"),
+        NaiveDate::from_ymd_opt(9999, 12, 31).expect("The last day of year 9999 is a date"),
+    )
+}
+
+/// MIME type of archives
+pub const CONTENT_TYPE: &str = "application/zip";
+
+/// The columns of `categories.csv`
+const CATEGORY_HEADER: [&str; 3] = ["id", "name", "created_at"];
+
+/// A file of the archive, as listed in its manifest
+#[derive(Debug, Serialize)]
+pub struct ManifestFile {
+    /// Path of the file in the archive
+    pub name: String,
+    /// ID of the account the file has the transactions of, if any
+    pub account_id: Option<i32>,
+    /// Number of rows of the file, or of items for JSON files
+    pub rows: usize,
+}
+
+/// What an archive holds, written last as `manifest.json`
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    /// When the export started, in UTC
+    #[serde(with = "crate::utils::serialization")]
+    pub exported_at: chrono::NaiveDateTime,
+    /// Number of accounts, archived ones included
+    pub accounts: usize,
+    /// Number of transactions across the accounts
+    pub transactions: usize,
+    /// Number of plans
+    pub plans: usize,
+    /// Number of budgets across the plans
+    pub budgets: usize,
+    /// Number of categories
+    pub categories: usize,
+    /// The other files of the archive
+    pub files: Vec<ManifestFile>,
+}
+
+/// A plan with its budgets, as written in `plans.json`
+#[derive(Debug, Serialize)]
+struct PlanExport {
+    #[serde(flatten)]
+    plan: Plan,
+    budgets: Vec<Budget>,
+}
+
+/// Writes an archive to a sink an entry at a time
+///
+/// Zip writers seek back to the header of the entry they are writing once they know its size, so
+/// the entry being written is held until the writer flushes it as it starts the next one, see
+/// `ZipWriter::set_flush_on_finish_file`. Entries already flushed are handed to the sink and
+/// can't be sought into.
+///
+/// Once the sink refuses a part, the rest of the archive is discarded so the zip writer can give
+/// up on it without failing.
+struct ArchiveWriter<'a> {
+    sink: &'a mut dyn FnMut(Vec<u8>) -> bool,
+    /// Number of bytes handed to the sink
+    sent: u64,
+    /// The bytes after those handed to the sink
+    pending: Vec<u8>,
+    position: u64,
+    /// Whether the sink refused a part
+    disconnected: bool,
+}
+
+impl<'a> ArchiveWriter<'a> {
+    fn new(sink: &'a mut dyn FnMut(Vec<u8>) -> bool) -> Self {
+        Self {
+            sink,
+            sent: 0,
+            pending: vec![],
+            position: 0,
+            disconnected: false,
+        }
+    }
+}
+
+impl Write for ArchiveWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.disconnected {
+            return Ok(buf.len());
+        }
+        let start = (self.position - self.sent) as usize;
+        let end = start + buf.len();
+        if end > self.pending.len() {
+            self.pending.resize(end, 0);
+        }
+        self.pending[start..end].copy_from_slice(buf);
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.disconnected || self.pending.is_empty() {
+            return Ok(());
+        }
+        let page = std::mem::take(&mut self.pending);
+        self.sent += page.len() as u64;
+        self.position = self.sent;
+        if !(self.sink)(page) {
+            self.disconnected = true;
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "The client disconnected",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Zip writers only read back entries they copy, which archives don't, and only the entry being
+/// written can be read
+impl Read for ArchiveWriter<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.disconnected {
+            return Ok(0);
+        }
+        let start = (self.position - self.sent) as usize;
+        let read = buf.len().min(self.pending.len().saturating_sub(start));
+        buf[..read].copy_from_slice(&self.pending[start..start + read]);
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for ArchiveWriter<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let end = self.sent + self.pending.len() as u64;
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => end.checked_add_signed(offset),
+        };
+        match target {
+            Some(target) if self.disconnected => {
+                self.position = target;
+                Ok(target)
+            }
+            Some(target) if (self.sent..=end).contains(&target) => {
+                self.position = target;
+                Ok(target)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "The archive can't be sought into the entries already sent",
+            )),
+        }
+    }
+}
+
+/// Build the path of the transactions of an account in the archive, e.g.
+/// `accounts/Chequing_12.csv`
+///
+/// The account name is sanitized with `export::sanitize_file_name`, and followed by the account
+/// ID so accounts whose names sanitize alike don't share a file.
+pub fn account_file_name(account: &Account) -> String {
+    format!(
+        "accounts/{}_{}.csv",
+        super::sanitize_file_name(account.name()),
+        account.id()
+    )
+}
+
+/// Build the file name of an archive, e.g. `finance-fusion_2024-06-01.zip`
+pub fn file_name(exported_at: chrono::NaiveDateTime) -> String {
+    format!("finance-fusion_{}.zip", exported_at.date())
+}
+
+/// Write the data of a user as a zip archive, handing it to a sink an entry at a time
+///
+/// The archive has a CSV of the transactions of each account, `plans.json` with the plans and
+/// their budgets, `categories.csv`, and `manifest.json` with the counts of each, written last.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `user_id` - User ID
+/// * `exported_at` - When the export started, in UTC
+/// * `sink` - Receives the archive in parts, returns `false` to stop early
+///
+/// # Returns
+///
+/// The number of files in the archive, the manifest included
+pub fn write_archive(
+    conn: &mut DbConn,
+    user_id: i32,
+    exported_at: chrono::NaiveDateTime,
+    sink: &mut dyn FnMut(Vec<u8>) -> bool,
+) -> Result<usize, AppError> {
+    let mut zip = ZipWriter::new(ArchiveWriter::new(sink));
+    zip.set_flush_on_finish_file(true);
+    match write_entries(conn, user_id, exported_at, &mut zip) {
+        Ok(files) => {
+            zip.finish()
+                .and_then(|mut writer| writer.flush().map_err(ZipError::from))
+                .or_else(ignore_disconnect)?;
+            Ok(files)
+        }
+        Err(AppError::Signal(e)) if e.kind() == io::ErrorKind::BrokenPipe => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Treat a client that disconnected as the end of the archive rather than a failure
+fn ignore_disconnect<T: Default>(e: ZipError) -> Result<T, AppError> {
+    match e {
+        ZipError::Io(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(T::default()),
+        e => Err(AppError::Signal(io::Error::other(e))),
+    }
+}
+
+/// Convert the errors of the zip writer, keeping the IO errors such as a disconnected client
+fn zip_error(e: ZipError) -> AppError {
+    match e {
+        ZipError::Io(e) => AppError::Signal(e),
+        e => AppError::Signal(io::Error::other(e)),
+    }
+}
+
+/// Write the entries of an archive, see `write_archive`
+///
+/// # Returns
+///
+/// The number of files written, the manifest included
+fn write_entries<W: Write + Seek>(
+    conn: &mut DbConn,
+    user_id: i32,
+    exported_at: chrono::NaiveDateTime,
+    zip: &mut ZipWriter<W>,
+) -> Result<usize, AppError> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut files = vec![];
+
+    let accounts = Account::get_all(conn, user_id, true)?;
+    let mut transactions = 0;
+    for account in &accounts {
+        let name = account_file_name(account);
+        zip.start_file(name.as_str(), options).map_err(zip_error)?;
+        let mut written = Ok(());
+        let rows = transactions::write_pages(
+            conn,
+            account,
+            all_time(),
+            ExportFormat::Csv,
+            EXPORT_PAGE_SIZE,
+            |page| {
+                written = zip.write_all(&page);
+                written.is_ok()
+            },
+        )?;
+        written?;
+        transactions += rows;
+        files.push(ManifestFile {
+            name,
+            account_id: Some(account.id()),
+            rows,
+        });
+    }
+
+    let plans = Plan::get_all(conn, user_id)?
+        .into_iter()
+        .map(|plan| {
+            let budgets = Budget::get_all(conn, &plan)?;
+            Ok(PlanExport { plan, budgets })
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+    zip.start_file("plans.json", options).map_err(zip_error)?;
+    serde_json::to_writer_pretty(&mut *zip, &plans).map_err(io::Error::from)?;
+    files.push(ManifestFile {
+        name: "plans.json".to_string(),
+        account_id: None,
+        rows: plans.len(),
+    });
+
+    let categories = Category::get_all(conn, user_id)?;
+    // Flushing the zip writer would hand the entry to the sink before its header is complete, so
+    // the categories are encoded before being written
+    let mut writer = ::csv::Writer::from_writer(vec![]);
+    writer
+        .write_record(CATEGORY_HEADER)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    for category in &categories {
+        writer
+            .write_record([
+                category.id().to_string(),
+                category.name().to_string(),
+                crate::utils::serialization::format(&category.created_at()),
+            ])
+            .map_err(|e| io::Error::other(e.to_string()))?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    zip.start_file("categories.csv", options)
+        .map_err(zip_error)?;
+    zip.write_all(&bytes)?;
+    files.push(ManifestFile {
+        name: "categories.csv".to_string(),
+        account_id: None,
+        rows: categories.len(),
+    });
+
+    let manifest = Manifest {
+        exported_at,
+        accounts: accounts.len(),
+        transactions,
+        plans: plans.len(),
+        budgets: plans.iter().map(|plan| plan.budgets.len()).sum(),
+        categories: categories.len(),
+        files,
+    };
+    zip.start_file("manifest.json", options)
+        .map_err(zip_error)?;
+    serde_json::to_writer_pretty(&mut *zip, &manifest).map_err(io::Error::from)?;
+
+    Ok(manifest.files.len() + 1)
+}
+
+/// Stream the data of a user as a zip archive
+///
+/// Entries are written on a blocking thread as the client reads them, see
+/// `export::stream_pages`. Only the entry being written is held in memory.
+pub fn stream(pool: Arc<DbPool>, user_id: i32, exported_at: chrono::NaiveDateTime) -> Body {
+    let what = format!("Archive of user {user_id}");
+    super::stream_pages(pool, what, move |conn, sink| {
+        write_archive(conn, user_id, exported_at, sink)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_are_sent_once_written() {
+        let mut pages = vec![];
+        let mut sink = |page: Vec<u8>| {
+            pages.push(page);
+            true
+        };
+        let mut zip = ZipWriter::new(ArchiveWriter::new(&mut sink));
+        zip.set_flush_on_finish_file(true);
+        let options = SimpleFileOptions::default();
+        for (name, text) in [("a.csv", "date,amount\n"), ("b.csv", "id,name\n")] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(text.as_bytes()).unwrap();
+        }
+        let mut writer = zip.finish().unwrap();
+        // The writer can't go back into what was sent
+        assert!(writer.seek(SeekFrom::Start(0)).is_err());
+        writer.flush().unwrap();
+        drop(zip);
+
+        // One page per entry, then the central directory
+        assert_eq!(pages.len(), 3);
+        let bytes = pages.concat();
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(bytes)).unwrap();
+        let mut text = String::new();
+        io::Read::read_to_string(&mut archive.by_name("b.csv").unwrap(), &mut text).unwrap();
+        assert_eq!(text, "id,name\n");
+    }
+
+    #[test]
+    fn test_sink_closing_stops_the_archive() {
+        let mut sink = |_: Vec<u8>| false;
+        let mut zip = ZipWriter::new(ArchiveWriter::new(&mut sink));
+        zip.set_flush_on_finish_file(true);
+        let options = SimpleFileOptions::default();
+        zip.start_file("a.csv", options).unwrap();
+        zip.write_all(b"date,amount\n").unwrap();
+        assert!(matches!(
+            zip.start_file("b.csv", options),
+            Err(ZipError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe
+        ));
+    }
+}
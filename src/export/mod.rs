@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::database::connection::{DbConn, DbPool};
+use crate::errors::AppError;
+
+pub mod archive;
+pub mod pdf;
+pub mod reports;
+pub mod statement;
+pub mod transactions;
+
+/// Number of encoded pages buffered ahead of a slow client
+const CHANNEL_CAPACITY: usize = 4;
+
+/// Replace the characters of a name that aren't safe in a `Content-Disposition` header or a file
+/// name with underscores
+pub fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Stream pages written on a blocking thread as a response body
+///
+/// The pages are handed to the body through a bounded channel, so at most a few pages are held in
+/// memory no matter how large the file is. If the client disconnects, the channel closes and the
+/// sink returns `false`, which the writer takes as the signal to stop at the next page.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool, a read connection is taken from it on the blocking thread
+/// * `what` - What is streamed, for the logs
+/// * `write` - Writes the pages to the sink it is given
+pub fn stream_pages<F>(pool: Arc<DbPool>, what: String, write: F) -> Body
+where
+    F: FnOnce(&mut DbConn, &mut dyn FnMut(Vec<u8>) -> bool) -> Result<usize, AppError>
+        + Send
+        + 'static,
+{
+    let (tx, rx) = mpsc::channel::<Result<Vec<u8>, std::io::Error>>(CHANNEL_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        let result = pool
+            .conn_read()
+            .and_then(|mut conn| write(&mut conn, &mut |page| tx.blocking_send(Ok(page)).is_ok()));
+
+        // Headers are already sent, so the best we can do is abort the body
+        if let Err(e) = result {
+            tracing::error!("{what} failed ({e})");
+            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+        }
+    });
+
+    Body::from_stream(ReceiverStream::new(rx))
+}
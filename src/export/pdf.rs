@@ -0,0 +1,254 @@
+//! A minimal writer of plain text PDF documents
+//!
+//! Documents are lines of Courier on US Letter pages, which is all a statement needs and keeps
+//! columns aligned without measuring text. The writer hands out its bytes as pages fill up, so a
+//! document can be streamed while it is written: the page tree, which lists every page, is the
+//! last object of the file and the cross-reference table records where each object landed.
+
+use std::fmt::Write;
+
+/// Width of a page, in points
+const PAGE_WIDTH: usize = 612;
+/// Height of a page, in points
+const PAGE_HEIGHT: usize = 792;
+/// Space left around the text, in points
+const MARGIN: usize = 50;
+/// Size of the font, in points
+const FONT_SIZE: usize = 9;
+/// Distance between the baselines of two lines, in points
+const LEADING: usize = 12;
+
+/// Number of lines that fit on a page
+pub const LINES_PER_PAGE: usize = (PAGE_HEIGHT - 2 * MARGIN) / LEADING;
+/// Number of characters that fit on a line, Courier glyphs being 0.6 em wide
+pub const LINE_WIDTH: usize = (PAGE_WIDTH - 2 * MARGIN) * 10 / (FONT_SIZE * 6);
+
+/// Object number of the document catalog
+const CATALOG: usize = 1;
+/// Object number of the page tree, written last
+const PAGES: usize = 2;
+/// Object number of the font
+const FONT: usize = 3;
+
+/// Writes a PDF document line by line
+#[derive(Debug)]
+pub struct PdfWriter {
+    /// Byte offset of each object in the file, by object number starting at 1
+    offsets: Vec<usize>,
+    /// Number of bytes handed out by `take`
+    taken: usize,
+    /// Bytes written since the last `take`
+    out: Vec<u8>,
+    /// Object numbers of the written pages
+    pages: Vec<usize>,
+    /// Lines of the page being filled
+    lines: Vec<String>,
+}
+
+impl Default for PdfWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PdfWriter {
+    /// Start a document
+    pub fn new() -> Self {
+        let mut writer = Self {
+            offsets: vec![0; FONT],
+            taken: 0,
+            // The comment of high bytes marks the file as binary for transfer tools
+            out: b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec(),
+            pages: vec![],
+            lines: Vec::with_capacity(LINES_PER_PAGE),
+        };
+        writer.object(
+            CATALOG,
+            format!("<< /Type /Catalog /Pages {PAGES} 0 R >>").as_bytes(),
+        );
+        writer.object(
+            FONT,
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>",
+        );
+        writer
+    }
+
+    /// Add a line of text, starting a new page when the current one is full
+    ///
+    /// Lines longer than `LINE_WIDTH` run off the page, so callers lay their text out to fit.
+    pub fn line(&mut self, text: impl Into<String>) {
+        self.lines.push(text.into());
+        if self.lines.len() == LINES_PER_PAGE {
+            self.write_page();
+        }
+    }
+
+    /// Take the bytes written since the last call
+    pub fn take(&mut self) -> Vec<u8> {
+        let out = std::mem::take(&mut self.out);
+        self.taken += out.len();
+        out
+    }
+
+    /// Finish the document
+    ///
+    /// # Returns
+    ///
+    /// The bytes written since the last `take`, ending the file
+    pub fn finish(&mut self) -> Vec<u8> {
+        // A document has at least one page, even if it is blank
+        if !self.lines.is_empty() || self.pages.is_empty() {
+            self.write_page();
+        }
+
+        let kids = self.pages.iter().fold(String::new(), |mut kids, page| {
+            let _ = write!(kids, "{page} 0 R ");
+            kids
+        });
+        self.object(
+            PAGES,
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.trim_end(),
+                self.pages.len()
+            )
+            .as_bytes(),
+        );
+
+        let start = self.position();
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            let _ = writeln!(xref, "{offset:010} 00000 n ");
+        }
+        let _ = write!(
+            xref,
+            "trailer\n<< /Size {} /Root {CATALOG} 0 R >>\nstartxref\n{start}\n%%EOF\n",
+            self.offsets.len() + 1
+        );
+        self.out.extend(xref.as_bytes());
+
+        self.take()
+    }
+
+    /// Get the offset in the file of the next byte written
+    fn position(&self) -> usize {
+        self.taken + self.out.len()
+    }
+
+    /// Reserve the number of a new object
+    fn allocate(&mut self) -> usize {
+        self.offsets.push(0);
+        self.offsets.len()
+    }
+
+    /// Write an object, recording where it starts
+    fn object(&mut self, number: usize, body: &[u8]) {
+        self.offsets[number - 1] = self.position();
+        self.out.extend(format!("{number} 0 obj\n").as_bytes());
+        self.out.extend(body);
+        self.out.extend(b"\nendobj\n");
+    }
+
+    /// Write the lines of the current page as a page object and its content stream
+    fn write_page(&mut self) {
+        let mut content = format!(
+            "BT\n/F1 {FONT_SIZE} Tf\n{LEADING} TL\n{MARGIN} {} Td\n",
+            PAGE_HEIGHT - MARGIN - FONT_SIZE
+        )
+        .into_bytes();
+        for line in self.lines.drain(..) {
+            content.push(b'(');
+            content.extend(encode(&line));
+            content.extend(b") Tj T*\n");
+        }
+        content.extend(b"ET");
+
+        let contents = self.allocate();
+        let page = self.allocate();
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend(b"\nendstream");
+        self.object(contents, &stream);
+        self.object(
+            page,
+            format!(
+                "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                 /Resources << /Font << /F1 {FONT} 0 R >> >> /Contents {contents} 0 R >>"
+            )
+            .as_bytes(),
+        );
+        self.pages.push(page);
+    }
+}
+
+/// Encode text as the contents of a PDF string in the Windows-1252 encoding of the font
+///
+/// Latin-1 characters are written as octal escapes so that the file stays ASCII outside of its
+/// marker comment. Characters the font can't show are replaced with `?` and control characters
+/// with spaces.
+fn encode(text: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '(' | ')' => {
+                encoded.push(b'\\');
+                encoded.push(c as u8);
+            }
+            ' '..='~' => encoded.push(c as u8),
+            '\u{a0}'..='\u{ff}' => encoded.extend(format!("\\{:03o}", c as u32).as_bytes()),
+            c if c.is_control() => encoded.push(b' '),
+            _ => encoded.push(b'?'),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pdf_structure() {
+        let mut writer = PdfWriter::new();
+        let mut file = writer.take();
+        for i in 0..LINES_PER_PAGE + 1 {
+            writer.line(format!("Line {i} (café) \\ 日本"));
+            // Taking bytes midway doesn't change the offsets
+            if i == 10 {
+                file.extend(writer.take());
+            }
+        }
+        file.extend(writer.finish());
+
+        assert!(file.starts_with(b"%PDF-1.4\n"));
+        assert!(file.ends_with(b"%%EOF\n"));
+        let text = String::from_utf8_lossy(&file);
+        assert!(text.contains("/Count 2 >>"));
+        assert!(text.contains(r"(Line 0 \(caf\351\) \\ ??) Tj"));
+
+        // Every object is where the cross-reference table says it is
+        let xref = file.windows(6).rposition(|w| w == b"\nxref\n").unwrap() + 1;
+        let table = std::str::from_utf8(&file[xref..]).unwrap();
+        let start: usize = table.lines().rev().nth(1).unwrap().parse().unwrap();
+        assert_eq!(start, xref);
+        let entries: Vec<usize> = table
+            .lines()
+            .skip(3)
+            .take_while(|line| line.ends_with(" n "))
+            .map(|line| line[..10].parse().unwrap())
+            .collect();
+        // The catalog, the page tree, the font and a content stream and page per page
+        assert_eq!(entries.len(), 7);
+        for (i, offset) in entries.iter().enumerate() {
+            assert!(file[*offset..].starts_with(format!("{} 0 obj\n", i + 1).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_empty_document() {
+        let mut writer = PdfWriter::new();
+        let mut file = writer.take();
+        file.extend(writer.finish());
+        assert!(String::from_utf8_lossy(&file).contains("/Count 1 >>"));
+    }
+}
@@ -0,0 +1,104 @@
+use serde::Serialize;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        plans::Plan,
+        scheduled_reports::{ReportFormat, ReportKind, ScheduledReport},
+        users::User,
+    },
+};
+use crate::errors::AppError;
+use crate::reports::{budgets, monthly};
+
+/// The columns of a monthly summary in CSV, matching the fields of `CategorySummary`
+const MONTHLY_SUMMARY_HEADER: [&str; 5] = ["category_id", "category", "income", "expenses", "net"];
+
+/// The columns of a budget report in CSV, matching the fields of `BudgetStatus`
+const BUDGET_VS_ACTUAL_HEADER: [&str; 9] = [
+    "budget_id",
+    "name",
+    "category_id",
+    "currency",
+    "base_budget",
+    "rollover_adjustment",
+    "effective_budget",
+    "actual",
+    "remaining",
+];
+
+/// Build the file name of a run of a scheduled report, e.g. `12_monthly_summary_2024-01.csv`
+///
+/// Runs that cover the same month have the same file name, so a daily report keeps one file per
+/// month that is brought up to date by each run.
+pub fn file_name(report: &ScheduledReport, year: i32, month: u32) -> String {
+    format!(
+        "{}_{}_{year}-{month:02}.{}",
+        report.id(),
+        report.kind().as_str(),
+        report.format().extension()
+    )
+}
+
+/// Render a scheduled report for a month
+///
+/// Reports are built by the same functions as the matching endpoints, so the JSON format is the
+/// body the endpoint returns for the month without conversion. Months start on the day the user
+/// set for their reports.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `report` - The scheduled report to render
+/// * `year` - Year of the month
+/// * `month` - Month of the year, from 1 to 12
+///
+/// # Returns
+///
+/// The contents of the report file
+pub fn render(
+    conn: &mut DbConn,
+    report: &ScheduledReport,
+    year: i32,
+    month: u32,
+) -> Result<Vec<u8>, AppError> {
+    let start_day = User::from_id(conn, report.user_id())?.fiscal_month_start_day();
+    match report.kind() {
+        ReportKind::MonthlySummary => {
+            let summary =
+                monthly::monthly_summary(conn, report.user_id(), year, month, start_day, None)?;
+            match report.format() {
+                ReportFormat::Csv => encode_csv(&MONTHLY_SUMMARY_HEADER, summary.categories()),
+                ReportFormat::Json => encode_json(&summary),
+            }
+        }
+        ReportKind::BudgetVsActual => {
+            let plan_name = report
+                .plan_name()
+                .ok_or_else(|| AppError::InvalidInput("The report isn't for a plan".to_string()))?;
+            let plan = Plan::from_name(conn, plan_name, report.user_id())?;
+            let statuses = budgets::budget_vs_actual(conn, &plan, year, month, start_day, None)?;
+            match report.format() {
+                ReportFormat::Csv => encode_csv(&BUDGET_VS_ACTUAL_HEADER, &statuses),
+                ReportFormat::Json => encode_json(&statuses),
+            }
+        }
+    }
+}
+
+/// Encode rows as CSV, with a header row even when there are none
+fn encode_csv(header: &[&str], rows: &[impl Serialize]) -> Result<Vec<u8>, AppError> {
+    let mut writer = ::csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer.write_record(header).map_err(std::io::Error::from)?;
+    for row in rows {
+        writer.serialize(row).map_err(std::io::Error::from)?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// Encode a report as JSON
+fn encode_json(report: &impl Serialize) -> Result<Vec<u8>, AppError> {
+    Ok(serde_json::to_vec_pretty(report).map_err(std::io::Error::from)?)
+}
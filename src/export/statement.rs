@@ -0,0 +1,603 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use axum::body::Body;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::{DbConn, DbPool},
+    models::{
+        accounts::Account,
+        transactions::{ExportRow, Transaction},
+    },
+};
+use crate::errors::AppError;
+use crate::export::pdf::{PdfWriter, LINE_WIDTH};
+use crate::export::transactions::EXPORT_PAGE_SIZE;
+use crate::utils::money::{minor_units, Money};
+
+/// Label of the subtotal of the transactions without a category
+const UNCATEGORIZED: &str = "Uncategorized";
+
+/// Widths of the date, description, category and amount columns of a PDF statement
+const PDF_COLUMNS: [usize; 4] = [10, 44, 20, 14];
+
+/// The format of an account statement
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    /// A standalone HTML page
+    #[default]
+    Html,
+    /// A PDF document of plain text
+    Pdf,
+}
+
+impl StatementFormat {
+    /// Get the MIME type of the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StatementFormat::Html => "text/html; charset=utf-8",
+            StatementFormat::Pdf => "application/pdf",
+        }
+    }
+
+    /// Get the file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            StatementFormat::Html => "html",
+            StatementFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// Build the file name of a statement, e.g. `Chequing_statement_2024-06.pdf`
+pub fn file_name(account: &Account, year: i32, month: u32, format: StatementFormat) -> String {
+    format!(
+        "{}_statement_{year}-{month:02}.{}",
+        super::sanitize_file_name(account.name()),
+        format.extension()
+    )
+}
+
+/// What a statement covers, known before its transactions are read
+struct Header<'a> {
+    /// The account of the statement
+    account: &'a Account,
+    /// First day of the month
+    from: NaiveDate,
+    /// Last day of the month
+    to: NaiveDate,
+    /// Balance at the start of the month
+    opening: BigDecimal,
+}
+
+/// Totals of a statement, added up as its transactions are read
+#[derive(Default)]
+struct Totals {
+    /// Number of transactions
+    count: usize,
+    /// Sum of the positive amounts
+    money_in: BigDecimal,
+    /// Sum of the negative amounts
+    money_out: BigDecimal,
+    /// Number and sum of the transactions of each category, named categories first
+    categories: BTreeMap<(bool, String), (usize, BigDecimal)>,
+}
+
+impl Totals {
+    /// Add a page of transactions to the totals
+    fn add(&mut self, rows: &[ExportRow]) {
+        for row in rows {
+            self.count += 1;
+            if row.amount() < &BigDecimal::zero() {
+                self.money_out += row.amount();
+            } else {
+                self.money_in += row.amount();
+            }
+            let key = match row.category() {
+                Some(name) => (false, name.to_string()),
+                None => (true, UNCATEGORIZED.to_string()),
+            };
+            let (count, sum) = self.categories.entry(key).or_default();
+            *count += 1;
+            *sum += row.amount();
+        }
+    }
+
+    /// Get the balance at the end of the month
+    fn closing(&self, opening: &BigDecimal) -> BigDecimal {
+        opening + &self.money_in + &self.money_out
+    }
+
+    /// Iterate over the category subtotals, by name with the uncategorized subtotal last
+    fn categories(&self) -> impl Iterator<Item = (&str, usize, &BigDecimal)> {
+        self.categories
+            .iter()
+            .map(|((_, name), (count, sum))| (name.as_str(), *count, sum))
+    }
+}
+
+/// Renders the parts of a statement as they become available
+trait Render {
+    /// Render what comes before the transactions
+    fn begin(&mut self, header: &Header) -> Vec<u8>;
+    /// Render a page of transactions
+    fn rows(&mut self, header: &Header, rows: &[ExportRow]) -> Vec<u8>;
+    /// Render what comes after the transactions, ending the statement
+    fn end(&mut self, header: &Header, totals: &Totals) -> Vec<u8>;
+}
+
+/// Format an amount with the decimals of the minor units of its currency, e.g. `12.30` for `CAD`
+/// but `1230` for `JPY`
+fn money(amount: &BigDecimal, currency: &str) -> String {
+    match Money::new(amount.clone(), currency) {
+        // Zero is printed without decimals unless they're asked for
+        Ok(money) => format!(
+            "{:.*}",
+            minor_units(currency) as usize,
+            money.round().amount()
+        ),
+        // Currencies of accounts are checked when they're opened
+        Err(_) => amount.to_string(),
+    }
+}
+
+/// Renders a statement as an HTML page
+///
+/// The page has no styles or scripts, so it renders under the strict content security policy of
+/// the API and prints cleanly.
+struct Html;
+
+/// Escape text for the contents or attribute values of an HTML page
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Render for Html {
+    fn begin(&mut self, header: &Header) -> Vec<u8> {
+        let name = escape(header.account.name());
+        format!(
+            "<!DOCTYPE html>\n\
+             <html lang=\"en\">\n\
+             <head>\n\
+             <meta charset=\"utf-8\">\n\
+             <title>Statement of {name}, {from} to {to}</title>\n\
+             </head>\n\
+             <body>\n\
+             <h1>Statement of {name}</h1>\n\
+             <p>{from} to {to}, amounts in {currency}</p>\n\
+             <p>Opening balance: <strong>{opening}</strong></p>\n\
+             <h2>Transactions</h2>\n\
+             <table>\n\
+             <thead>\n\
+             <tr><th>Date</th><th>Description</th><th>Category</th><th>Amount</th></tr>\n\
+             </thead>\n\
+             <tbody>\n",
+            from = header.from,
+            to = header.to,
+            currency = escape(header.account.currency()),
+            opening = money(&header.opening, header.account.currency()),
+        )
+        .into_bytes()
+    }
+
+    fn rows(&mut self, header: &Header, rows: &[ExportRow]) -> Vec<u8> {
+        let currency = header.account.currency();
+        let mut html = String::new();
+        for row in rows {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                row.occurred_at(),
+                escape(row.description()),
+                escape(row.category().unwrap_or_default()),
+                money(row.amount(), currency)
+            );
+        }
+        html.into_bytes()
+    }
+
+    fn end(&mut self, header: &Header, totals: &Totals) -> Vec<u8> {
+        let currency = header.account.currency();
+        let mut html = String::new();
+        if totals.count == 0 {
+            html.push_str("<tr><td colspan=\"4\">No transactions</td></tr>\n");
+        }
+        let _ = write!(
+            html,
+            "</tbody>\n\
+             </table>\n\
+             <h2>Summary</h2>\n\
+             <table>\n\
+             <tr><th>Opening balance</th><td>{}</td></tr>\n\
+             <tr><th>Money in</th><td>{}</td></tr>\n\
+             <tr><th>Money out</th><td>{}</td></tr>\n\
+             <tr><th>Closing balance</th><td>{}</td></tr>\n\
+             </table>\n\
+             <h2>By category</h2>\n\
+             <table>\n\
+             <thead>\n\
+             <tr><th>Category</th><th>Transactions</th><th>Amount</th></tr>\n\
+             </thead>\n\
+             <tbody>\n",
+            money(&header.opening, currency),
+            money(&totals.money_in, currency),
+            money(&totals.money_out, currency),
+            money(&totals.closing(&header.opening), currency),
+        );
+        for (name, count, sum) in totals.categories() {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{count}</td><td>{}</td></tr>",
+                escape(name),
+                money(sum, currency)
+            );
+        }
+        html.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+        html.into_bytes()
+    }
+}
+
+/// Renders a statement as a PDF document, in aligned columns of plain text
+struct Pdf(PdfWriter);
+
+/// Fit text to a column, cutting it short or padding it with spaces
+fn column(text: &str, width: usize) -> String {
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    if text.chars().count() > width {
+        let cut: String = text.chars().take(width - 3).collect();
+        format!("{cut}...")
+    } else {
+        format!("{text:<width$}")
+    }
+}
+
+/// Lay out a row of the transactions table of a PDF statement
+fn pdf_row(date: &str, description: &str, category: &str, amount: &str) -> String {
+    let [date_width, description_width, category_width, amount_width] = PDF_COLUMNS;
+    format!(
+        "{}  {}  {}  {amount:>amount_width$}",
+        column(date, date_width),
+        column(description, description_width),
+        column(category, category_width),
+    )
+}
+
+impl Render for Pdf {
+    fn begin(&mut self, header: &Header) -> Vec<u8> {
+        let writer = &mut self.0;
+        writer.line(column(
+            &format!("Statement of {}", header.account.name()),
+            LINE_WIDTH,
+        ));
+        writer.line(format!(
+            "{} to {}, amounts in {}",
+            header.from,
+            header.to,
+            header.account.currency()
+        ));
+        writer.line("");
+        writer.line(format!(
+            "Opening balance: {}",
+            money(&header.opening, header.account.currency())
+        ));
+        writer.line("");
+        writer.line(pdf_row("Date", "Description", "Category", "Amount"));
+        writer.line("-".repeat(PDF_COLUMNS.iter().sum::<usize>() + 6));
+        writer.take()
+    }
+
+    fn rows(&mut self, header: &Header, rows: &[ExportRow]) -> Vec<u8> {
+        for row in rows {
+            self.0.line(pdf_row(
+                &row.occurred_at().to_string(),
+                row.description(),
+                row.category().unwrap_or_default(),
+                &money(row.amount(), header.account.currency()),
+            ));
+        }
+        self.0.take()
+    }
+
+    fn end(&mut self, header: &Header, totals: &Totals) -> Vec<u8> {
+        let currency = header.account.currency();
+        let writer = &mut self.0;
+        if totals.count == 0 {
+            writer.line("No transactions");
+        }
+        writer.line("");
+        for (label, amount) in [
+            ("Opening balance", header.opening.clone()),
+            ("Money in", totals.money_in.clone()),
+            ("Money out", totals.money_out.clone()),
+            ("Closing balance", totals.closing(&header.opening)),
+        ] {
+            writer.line(format!("{label:<20}{:>14}", money(&amount, currency)));
+        }
+        writer.line("");
+        writer.line("By category");
+        for (name, count, sum) in totals.categories() {
+            writer.line(format!(
+                "{}  {count:>6}  {:>14}",
+                column(name, PDF_COLUMNS[1]),
+                money(sum, currency)
+            ));
+        }
+        writer.finish()
+    }
+}
+
+/// Write the statement of an account for a month, handing each rendered part to a sink
+///
+/// The transactions are read a page at a time, oldest first, and the totals are added up along
+/// the way, so a month with many transactions is never held in memory at once.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `account` - The account of the statement
+/// * `from` - First day of the month
+/// * `to` - Last day of the month
+/// * `format` - The format of the statement
+/// * `page_size` - Number of transactions loaded at a time
+/// * `sink` - Receives each rendered part, returns `false` to stop early
+///
+/// # Returns
+///
+/// The number of transactions in the statement
+pub fn write_statement(
+    conn: &mut DbConn,
+    account: &Account,
+    (from, to): (NaiveDate, NaiveDate),
+    format: StatementFormat,
+    page_size: i64,
+    mut sink: impl FnMut(Vec<u8>) -> bool,
+) -> Result<usize, AppError> {
+    let header = Header {
+        account,
+        from,
+        to,
+        opening: account.balance_before(conn, from)?,
+    };
+    let mut renderer: Box<dyn Render> = match format {
+        StatementFormat::Html => Box::new(Html),
+        StatementFormat::Pdf => Box::new(Pdf(PdfWriter::new())),
+    };
+    let mut totals = Totals::default();
+
+    if !sink(renderer.begin(&header)) {
+        return Ok(0);
+    }
+    let mut after = None;
+    loop {
+        let rows = Transaction::export_page(conn, account, from, to, after, page_size)?;
+        totals.add(&rows);
+        if !rows.is_empty() && !sink(renderer.rows(&header, &rows)) {
+            return Ok(totals.count);
+        }
+        if (rows.len() as i64) < page_size {
+            break;
+        }
+        after = rows.last().map(ExportRow::cursor);
+    }
+    sink(renderer.end(&header, &totals));
+
+    Ok(totals.count)
+}
+
+/// Stream the statement of an account for a month as a response body
+///
+/// Parts are rendered on a blocking thread as the client reads them, see `export::stream_pages`.
+pub fn stream(
+    pool: Arc<DbPool>,
+    account: Account,
+    range: (NaiveDate, NaiveDate),
+    format: StatementFormat,
+) -> Body {
+    let what = format!("Statement of account {} for {}", account.id(), range.0);
+    super::stream_pages(pool, what, move |conn, sink| {
+        write_statement(conn, &account, range, format, EXPORT_PAGE_SIZE, sink)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::models::{
+        accounts::AccountKind, categories::Category, transactions::TransactionInput, users::User,
+    };
+    use crate::quotas::Quotas;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    /// An account with a fixed set of transactions around June 2024
+    fn seed(conn: &mut DbConn) -> Account {
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing <Main>",
+            &BigDecimal::from_str("100.00").unwrap(),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let salary = Category::new(conn, user.id(), "Salary").unwrap();
+
+        for (amount, description, day, category) in [
+            ("-20.00", "Before the month", date(2024, 5, 31), None),
+            ("1500.00", "Pay", date(2024, 6, 1), Some(salary.id())),
+            (
+                "-45.10",
+                "Fish & \"Chips\"",
+                date(2024, 6, 3),
+                Some(groceries.id()),
+            ),
+            ("-12.40", "Bakery", date(2024, 6, 3), Some(groceries.id())),
+            ("-3.50", "Parking", date(2024, 6, 30), None),
+            ("-99.00", "After the month", date(2024, 7, 1), None),
+        ] {
+            let mut input =
+                TransactionInput::new(BigDecimal::from_str(amount).unwrap(), description, day);
+            input.category_id = category;
+            Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        }
+
+        account
+    }
+
+    fn statement(
+        conn: &mut DbConn,
+        account: &Account,
+        format: StatementFormat,
+        page_size: i64,
+    ) -> (usize, Vec<u8>) {
+        let mut output = vec![];
+        let count = write_statement(
+            conn,
+            account,
+            (date(2024, 6, 1), date(2024, 6, 30)),
+            format,
+            page_size,
+            |part| {
+                output.extend(part);
+                true
+            },
+        )
+        .unwrap();
+        (count, output)
+    }
+
+    #[test]
+    fn test_html_statement() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let account = seed(conn);
+
+        // Paging doesn't change the statement
+        let (count, html) = statement(conn, &account, StatementFormat::Html, 3);
+        assert_eq!(count, 4);
+        assert_eq!(
+            String::from_utf8(html.clone()).unwrap(),
+            include_str!("fixtures/statement.html")
+        );
+        let (_, single_page) = statement(conn, &account, StatementFormat::Html, 100);
+        assert_eq!(single_page, html);
+    }
+
+    #[test]
+    fn test_pdf_statement() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let account = seed(conn);
+
+        let (count, pdf) = statement(conn, &account, StatementFormat::Pdf, 3);
+        assert_eq!(count, 4);
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert!(pdf.len() > 1000);
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains(&format!("{:<20}{:>14}", "Closing balance", "1519.00")));
+        assert!(text.contains("Groceries"));
+        assert!(!text.contains("After the month"));
+    }
+
+    #[test]
+    fn test_amounts_have_the_decimals_of_their_currency() {
+        let amount = |amount: &str| BigDecimal::from_str(amount).unwrap();
+        assert_eq!(money(&amount("1519"), "CAD"), "1519.00");
+        assert_eq!(money(&amount("0"), "CAD"), "0.00");
+        assert_eq!(money(&amount("-45.1"), "CAD"), "-45.10");
+        assert_eq!(money(&amount("1500.5"), "JPY"), "1501");
+        assert_eq!(money(&amount("0"), "JPY"), "0");
+        assert_eq!(money(&amount("12.3456"), "KWD"), "12.346");
+        assert_eq!(money(&amount("0"), "KWD"), "0.000");
+    }
+
+    #[test]
+    fn test_statement_in_a_currency_without_minor_units() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Yen",
+            &BigDecimal::from(1000),
+            "JPY",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let input = TransactionInput::new(BigDecimal::from(-250), "Ramen", date(2024, 6, 3));
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+
+        let (count, html) = statement(conn, &account, StatementFormat::Html, 10);
+        assert_eq!(count, 1);
+        let html = String::from_utf8(html).unwrap();
+        assert!(
+            html.contains("<td>Ramen</td><td></td><td>-250</td>"),
+            "{html}"
+        );
+        assert!(html.contains("<tr><th>Opening balance</th><td>1000</td></tr>"));
+        assert!(html.contains("<tr><th>Money in</th><td>0</td></tr>"));
+        assert!(html.contains("<tr><th>Closing balance</th><td>750</td></tr>"));
+    }
+
+    #[test]
+    fn test_empty_statement() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let account = seed(conn);
+
+        let mut output = vec![];
+        let count = write_statement(
+            conn,
+            &account,
+            (date(2024, 8, 1), date(2024, 8, 31)),
+            StatementFormat::Html,
+            10,
+            |part| {
+                output.extend(part);
+                true
+            },
+        )
+        .unwrap();
+        assert_eq!(count, 0);
+        let html = String::from_utf8(output).unwrap();
+        assert!(html.contains("No transactions"));
+        assert!(html.contains("<tr><th>Money in</th><td>0.00</td></tr>"));
+        // The opening and closing balances carry every earlier transaction
+        assert!(html.contains("<tr><th>Opening balance</th><td>1420.00</td></tr>"));
+        assert!(html.contains("<tr><th>Closing balance</th><td>1420.00</td></tr>"));
+    }
+}
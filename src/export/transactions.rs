@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::{DbConn, DbPool},
+    models::{
+        accounts::Account,
+        transactions::{ExportRow, Transaction},
+    },
+};
+use crate::errors::AppError;
+
+/// Number of transactions loaded from the database at a time
+pub const EXPORT_PAGE_SIZE: i64 = 1000;
+
+/// The columns of a CSV export, matching the fields of `ExportRow`
+const CSV_HEADER: [&str; 5] = ["date", "description", "category", "amount", "currency"];
+
+/// The format of a transaction export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Comma separated values with a header row
+    #[default]
+    Csv,
+    /// Newline delimited JSON, one transaction per line
+    Json,
+}
+
+impl ExportFormat {
+    /// Get the MIME type of the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/x-ndjson",
+        }
+    }
+
+    /// Get the file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "ndjson",
+        }
+    }
+}
+
+/// Build the file name of an export, e.g. `Chequing_2024-01-01_2024-12-31.csv`
+///
+/// The account name is sanitized with `export::sanitize_file_name`.
+pub fn file_name(
+    account: &Account,
+    from: NaiveDate,
+    to: NaiveDate,
+    format: ExportFormat,
+) -> String {
+    let name = super::sanitize_file_name(account.name());
+    format!("{name}_{from}_{to}.{}", format.extension())
+}
+
+/// Encode a page of an export
+///
+/// # Arguments
+///
+/// * `rows` - The rows of the page
+/// * `format` - The format of the export
+/// * `with_header` - Whether to start with a header row (CSV only, set for the first page)
+///
+/// # Returns
+///
+/// The encoded page
+pub fn encode_page(
+    rows: &[ExportRow],
+    format: ExportFormat,
+    with_header: bool,
+) -> Result<Vec<u8>, String> {
+    match format {
+        ExportFormat::Csv => {
+            // The header is written by hand so that an empty export still has one
+            let mut writer = ::csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(vec![]);
+            if with_header {
+                writer.write_record(CSV_HEADER).map_err(|e| e.to_string())?;
+            }
+            for row in rows {
+                writer.serialize(row).map_err(|e| e.to_string())?;
+            }
+            writer.into_inner().map_err(|e| e.to_string())
+        }
+        ExportFormat::Json => {
+            let mut buffer = vec![];
+            for row in rows {
+                serde_json::to_writer(&mut buffer, row).map_err(|e| e.to_string())?;
+                buffer.push(b'\n');
+            }
+            Ok(buffer)
+        }
+    }
+}
+
+/// Page through an export, handing each encoded page to a sink
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `account` - The account to export the transactions of
+/// * `from` - First day of the range (inclusive)
+/// * `to` - Last day of the range (inclusive)
+/// * `format` - The format of the export
+/// * `page_size` - Number of transactions loaded at a time
+/// * `sink` - Receives each encoded page, returns `false` to stop early
+///
+/// # Returns
+///
+/// The number of exported transactions
+pub fn write_pages(
+    conn: &mut DbConn,
+    account: &Account,
+    (from, to): (NaiveDate, NaiveDate),
+    format: ExportFormat,
+    page_size: i64,
+    mut sink: impl FnMut(Vec<u8>) -> bool,
+) -> Result<usize, AppError> {
+    let mut after = None;
+    let mut exported = 0;
+
+    loop {
+        let rows = Transaction::export_page(conn, account, from, to, after, page_size)?;
+        // The CSV header is written even when there are no rows
+        if rows.is_empty() && exported > 0 {
+            break;
+        }
+
+        let page = encode_page(&rows, format, after.is_none()).map_err(|e| {
+            tracing::error!("Failed encoding export of account {} ({e})", account.id());
+            AppError::InvalidInput(e)
+        })?;
+        exported += rows.len();
+        if !sink(page) || (rows.len() as i64) < page_size {
+            break;
+        }
+        after = rows.last().map(ExportRow::cursor);
+    }
+
+    Ok(exported)
+}
+
+/// Stream an export as a response body
+///
+/// Pages are loaded and encoded on a blocking thread as the client reads them, see
+/// `export::stream_pages`.
+pub fn stream(
+    pool: Arc<DbPool>,
+    account: Account,
+    range: (NaiveDate, NaiveDate),
+    format: ExportFormat,
+) -> Body {
+    let what = format!("Export of account {}", account.id());
+    super::stream_pages(pool, what, move |conn, sink| {
+        write_pages(conn, &account, range, format, EXPORT_PAGE_SIZE, sink)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::models::{
+        accounts::AccountKind, categories::Category, transactions::TransactionInput, users::User,
+    };
+    use crate::quotas::Quotas;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn export(
+        conn: &mut DbConn,
+        account: &Account,
+        range: (NaiveDate, NaiveDate),
+        format: ExportFormat,
+        page_size: i64,
+    ) -> (usize, Vec<u8>) {
+        let mut output = vec![];
+        let exported = write_pages(conn, account, range, format, page_size, |page| {
+            output.extend(page);
+            true
+        })
+        .unwrap();
+        (exported, output)
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Joint, \"Main\"",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+
+        let nasty = "Café \"Le Coin\", Montréal\nline two\r\n, trailing";
+        let mut input = TransactionInput::new(
+            BigDecimal::from_str("-4.50").unwrap(),
+            nasty,
+            date(2024, 1, 2),
+        );
+        input.category_id = Some(groceries.id());
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+
+        let inputs: Vec<TransactionInput> = (0..24)
+            .map(|i| {
+                TransactionInput::new(BigDecimal::from(i), &format!("Row {i}"), date(2024, 1, 1))
+            })
+            .collect();
+        Transaction::bulk_insert(conn, &account, &inputs, &Quotas::disabled()).unwrap();
+        // Outside the range
+        let outside = TransactionInput::new(zero.clone(), "Outside", date(2024, 2, 1));
+        Transaction::new(conn, &account, &outside, &Quotas::disabled()).unwrap();
+
+        let range = (date(2024, 1, 1), date(2024, 1, 31));
+        // A page size that doesn't divide the row count evenly
+        let (exported, output) = export(conn, &account, range, ExportFormat::Csv, 7);
+        assert_eq!(exported, 25);
+
+        let mut reader = ::csv::Reader::from_reader(output.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["date", "description", "category", "amount", "currency"]
+        );
+        let records: Vec<::csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 25);
+
+        // Oldest first, with ties broken by insertion order
+        assert_eq!(&records[0][1], "Row 0");
+        assert_eq!(&records[23][1], "Row 23");
+        assert_eq!(&records[0][2], "");
+
+        let last = &records[24];
+        assert_eq!(&last[0], "2024-01-02");
+        assert_eq!(&last[1], nasty);
+        assert_eq!(&last[2], "Groceries");
+        assert_eq!(&last[3], "-4.50");
+        assert_eq!(&last[4], "CAD");
+
+        assert_eq!(
+            file_name(&account, range.0, range.1, ExportFormat::Csv),
+            "Joint___Main__2024-01-01_2024-01-31.csv"
+        );
+    }
+
+    #[test]
+    fn test_ndjson_and_empty_exports() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let zero = BigDecimal::from(0);
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &zero,
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let range = (date(2024, 1, 1), date(2024, 1, 31));
+
+        // An empty CSV export still has a header row
+        let (exported, output) = export(conn, &account, range, ExportFormat::Csv, 10);
+        assert_eq!(exported, 0);
+        assert_eq!(output, b"date,description,category,amount,currency\n");
+
+        let input = TransactionInput::new(zero, "Line\nbreak \"quoted\"", date(2024, 1, 5));
+        for _ in 0..10 {
+            Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        }
+
+        // Exactly one full page
+        let (exported, output) = export(conn, &account, range, ExportFormat::Json, 10);
+        assert_eq!(exported, 10);
+
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0]["description"], "Line\nbreak \"quoted\"");
+        assert_eq!(lines[0]["date"], "2024-01-05");
+        assert!(lines[0]["category"].is_null());
+        assert!(lines[0].get("id").is_none());
+    }
+}
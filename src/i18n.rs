@@ -0,0 +1,297 @@
+use std::future::Future;
+
+tokio::task_local! {
+    /// Locale of the request being handled by the current task
+    static CURRENT: Locale;
+}
+
+/// A language the messages of errors are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    /// English, the messages of the errors themselves
+    #[default]
+    En,
+    /// French, from the catalog
+    Fr,
+}
+
+/// French messages of the errors, keyed by the slug of their code
+///
+/// Errors whose message depends on more than their code are keyed by the slug followed by what
+/// tells them apart, e.g. the kind of an `invalid_body`. `{name}` is replaced by the argument of
+/// the error with that name, and `{detail}` by the text the server wrote for the error, which is
+/// in English.
+const FR: &[(&str, &str)] = &[
+    (
+        "invalid_object_id",
+        "Impossible de lire l'identifiant d'objet {id}",
+    ),
+    ("bad_request", "Requête invalide"),
+    ("not_found", "Introuvable"),
+    ("wrong_credentials", "Identifiants de connexion incorrects"),
+    ("invalid_token", "Identifiants de connexion invalides"),
+    (
+        "locked",
+        "L'utilisateur est verrouillé, réessayez dans {seconds} secondes",
+    ),
+    ("session_expired", "La session a expiré"),
+    ("invalid_input", "Valeur invalide : {detail}"),
+    ("forbidden", "Interdit"),
+    (
+        "missing_exchange_rates",
+        "Taux de change manquants pour {currencies}",
+    ),
+    ("conflict", "Conflit : {detail}"),
+    (
+        "rate_limited",
+        "Trop de requêtes, réessayez dans {seconds} secondes",
+    ),
+    (
+        "payload_too_large",
+        "Le corps de la requête est trop volumineux",
+    ),
+    ("method_not_allowed", "Méthode non autorisée"),
+    ("invalid_body", "Corps invalide : {reason}"),
+    (
+        "invalid_body.content_type",
+        "Un corps de requête avec `Content-Type: application/json` est attendu",
+    ),
+    (
+        "invalid_body.syntax",
+        "JSON invalide à la ligne {line} colonne {column} : {reason}",
+    ),
+    ("invalid_body.missing_field", "Champ `{field}` manquant"),
+    (
+        "invalid_body.invalid_field",
+        "Champ `{field}` invalide : {reason}",
+    ),
+    ("invalid_query", "Paramètres invalides : {reason}"),
+    (
+        "invalid_query.invalid_field",
+        "Paramètre `{field}` invalide : {reason}",
+    ),
+    (
+        "invalid_query.missing_field",
+        "Paramètre `{field}` manquant",
+    ),
+    ("invalid_query.unknown_field", "Paramètre `{field}` inconnu"),
+    (
+        "invalid_query.invalid_range",
+        "Intervalle de {fields} invalide : {reason}",
+    ),
+    (
+        "currency_mismatch",
+        "Le montant est en {currency} mais le compte est en {account_currency}, envoyez-le \
+         converti en {account_currency} avec `original_amount` et `original_currency`",
+    ),
+    (
+        "channel_refused",
+        "Le canal de notification n'a pas accepté la notification ({detail})",
+    ),
+    (
+        "quota_exceeded.transactions",
+        "Au-delà du quota de {limit} transactions, {used} sont utilisées",
+    ),
+    (
+        "quota_exceeded.attachment_bytes",
+        "Au-delà du quota de {limit} octets de pièces jointes, {used} sont utilisés",
+    ),
+    (
+        "quota_exceeded.webhooks",
+        "Au-delà du quota de {limit} webhooks, {used} sont utilisés",
+    ),
+    (
+        "quota_exceeded.accounts",
+        "Au-delà du quota de {limit} comptes, {used} sont utilisés",
+    ),
+    (
+        "precondition_failed",
+        "La ressource a été modifiée depuis sa lecture, sa version actuelle est {version}",
+    ),
+    (
+        "precondition_required",
+        "Les modifications doivent envoyer l'ETag de la ressource qu'elles modifient dans \
+         `If-Match`",
+    ),
+    (
+        "token_creation",
+        "Impossible de créer le jeton d'authentification",
+    ),
+    (
+        "database",
+        "Une requête à la base de données a échoué ({detail})",
+    ),
+    (
+        "database_connection",
+        "Impossible de se connecter à la base de données ({detail})",
+    ),
+    (
+        "deserialization",
+        "Impossible de lire les données enregistrées ({detail})",
+    ),
+    ("task_failed", "Une tâche de fond a échoué ({detail})"),
+    (
+        "password_hashing",
+        "Impossible de hacher le mot de passe ({detail})",
+    ),
+    ("timeout", "La requête a pris trop de temps"),
+    (
+        "database_unavailable",
+        "La base de données est indisponible, réessayez plus tard",
+    ),
+    ("io", "Une opération d'entrée-sortie a échoué ({detail})"),
+    (
+        "migration",
+        "La migration de la base de données a échoué ({detail})",
+    ),
+    ("maintenance", "Le serveur est en maintenance : {detail}"),
+    (
+        "schema_drift",
+        "La base de données ne correspond pas au serveur ({detail})",
+    ),
+    (
+        "database_busy",
+        "Toutes les connexions à la base de données sont utilisées, réessayez dans {seconds} \
+         secondes",
+    ),
+];
+
+impl Locale {
+    /// Get the language tag of the locale, e.g. `fr`
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Fr => "fr",
+        }
+    }
+
+    /// Get the locale of a language tag, whatever its region, e.g. `fr-CA`
+    fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.split('-').next().unwrap_or_default();
+        [Locale::En, Locale::Fr]
+            .into_iter()
+            .find(|locale| language.eq_ignore_ascii_case(locale.tag()))
+    }
+
+    /// Pick the locale that best matches an `Accept-Language` header
+    ///
+    /// Languages are ranked by their `q` weight, ties going to the one listed first. Languages
+    /// without a catalog are skipped, and English is used if none is left.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The value of the header, e.g. `fr-CA,fr;q=0.9,en;q=0.8`
+    pub fn negotiate(header: &str) -> Self {
+        let mut best: Option<(Locale, f32)> = None;
+        for range in header.split(',') {
+            let mut params = range.split(';');
+            let Some(locale) = Locale::from_tag(params.next().unwrap_or_default().trim()) else {
+                continue;
+            };
+            let weight = params
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())
+                .unwrap_or(0.0);
+            if weight > 0.0 && best.map_or(true, |(_, most)| weight > most) {
+                best = Some((locale, weight));
+            }
+        }
+        best.map(|(locale, _)| locale).unwrap_or_default()
+    }
+
+    /// Get the locale of the request handled by the current task, English outside of requests
+    ///
+    /// Only set within routes wrapped by the `locale` middleware, and not in tasks they spawn.
+    pub fn current() -> Self {
+        CURRENT.try_with(|locale| *locale).unwrap_or_default()
+    }
+
+    /// Run a future with the locale as the current one
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+
+    /// Write a message from the catalog of the locale
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the message, the slug of an error code possibly followed by a subkey
+    /// * `args` - The values of the placeholders of the message
+    ///
+    /// # Returns
+    ///
+    /// The message, or `None` for English, a key without a message or an argument missing, in
+    /// which case the English message is sent
+    pub fn message(self, key: &str, args: &[(&str, String)]) -> Option<String> {
+        let catalog = match self {
+            Locale::En => return None,
+            Locale::Fr => FR,
+        };
+        let (_, template) = catalog.iter().find(|(name, _)| *name == key)?;
+
+        let mut message = String::with_capacity(template.len());
+        let mut rest = *template;
+        while let Some(start) = rest.find('{') {
+            let end = start + rest[start..].find('}')?;
+            let (_, value) = args
+                .iter()
+                .find(|(name, _)| *name == &rest[start + 1..end])?;
+            message.push_str(&rest[..start]);
+            message.push_str(value);
+            rest = &rest[end + 1..];
+        }
+        message.push_str(rest);
+        Some(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorCode;
+
+    #[test]
+    fn test_negotiate() {
+        for (header, locale) in [
+            ("fr-CA", Locale::Fr),
+            ("FR", Locale::Fr),
+            ("en-US,en;q=0.9", Locale::En),
+            ("fr-CA,fr;q=0.9,en;q=0.8", Locale::Fr),
+            ("en;q=0.5, fr;q=0.8", Locale::Fr),
+            // Ties go to the first language listed
+            ("fr;q=0.7, en;q=0.7", Locale::Fr),
+            // Languages without a catalog are skipped
+            ("de-DE, fr;q=0.1", Locale::Fr),
+            ("de-DE", Locale::En),
+            ("fr;q=0", Locale::En),
+            ("*", Locale::En),
+            ("", Locale::En),
+            ("fr;q=abc, en;q=0.2", Locale::En),
+        ] {
+            assert_eq!(Locale::negotiate(header), locale, "{header}");
+        }
+    }
+
+    #[test]
+    fn test_message() {
+        let args = [("seconds", "3".to_string())];
+        assert_eq!(
+            Locale::Fr.message("rate_limited", &args).as_deref(),
+            Some("Trop de requêtes, réessayez dans 3 secondes")
+        );
+        assert_eq!(Locale::En.message("rate_limited", &args), None);
+        assert_eq!(Locale::Fr.message("rate_limited", &[]), None);
+        assert_eq!(Locale::Fr.message("unknown", &args), None);
+
+        // Every code has a French message
+        for code in ErrorCode::ALL {
+            assert!(
+                FR.iter()
+                    .any(|(key, _)| key == &code.slug()
+                        || key.starts_with(&format!("{}.", code.slug()))),
+                "{}",
+                code.slug()
+            );
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::models::{
+    accounts::AccountKind, exchange_rates::validate_currency_code, transactions::TransactionInput,
+};
+use crate::errors::AppError;
+
+/// Maximum number of accounts in a bootstrap file
+pub const MAX_ACCOUNTS: usize = 100;
+
+/// Maximum number of transactions of an account in a bootstrap file
+pub const MAX_TRANSACTIONS: usize = 10_000;
+
+/// Maximum length of the name of an account, in characters
+const MAX_NAME_LENGTH: usize = 64;
+
+/// What to do with an account named like another account of the user or of the file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateNames {
+    /// The account isn't created and is reported as failed
+    #[default]
+    Reject,
+    /// The account is created with a number after its name, e.g. `Chequing (2)`
+    Suffix,
+}
+
+/// A transaction of an account of a bootstrap file
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BootstrapTransaction {
+    /// The signed amount of the transaction, as a decimal string, in the currency of the account
+    #[schema(value_type = String)]
+    pub amount: BigDecimal,
+    /// The ISO 4217 currency code of the amount, which must be the account's
+    pub currency: Option<String>,
+    /// The signed amount in the currency the transaction was made in, when it isn't the
+    /// account's, as a decimal string
+    #[schema(value_type = Option<String>)]
+    pub original_amount: Option<BigDecimal>,
+    /// The ISO 4217 currency code of the original amount, sent with it
+    pub original_currency: Option<String>,
+    /// The description of the transaction
+    pub description: String,
+    /// The date the transaction occurred on
+    pub occurred_at: NaiveDate,
+}
+
+/// An account of a bootstrap file
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BootstrapAccount {
+    /// The name of the account
+    pub name: String,
+    /// Whether the balance of the account is owned (`asset`, the default) or owed (`credit`)
+    pub kind: Option<AccountKind>,
+    /// The ISO 4217 currency code of the account
+    pub currency: String,
+    /// The balance of the account before any transactions, as a decimal string
+    #[schema(value_type = String)]
+    pub opening_balance: BigDecimal,
+    /// The history of the account, none by default
+    #[serde(default)]
+    pub transactions: Vec<BootstrapTransaction>,
+}
+
+/// Accounts to create at once, e.g. when moving from another tool
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"duplicate_names": "suffix", "accounts": [{"name": "Chequing", "currency": "CAD", "opening_balance": "1520.35", "transactions": [{"amount": "-1200.00", "description": "Rent", "occurred_at": "2024-02-01"}]}, {"name": "Visa", "kind": "credit", "currency": "USD", "opening_balance": "-250.00"}]}))]
+pub struct BootstrapFile {
+    /// The accounts, created in order
+    pub accounts: Vec<BootstrapAccount>,
+    /// What to do with accounts named like an existing account, `reject` by default
+    #[serde(default)]
+    pub duplicate_names: DuplicateNames,
+}
+
+impl BootstrapFile {
+    /// Check that the file has accounts and not more than a file can have
+    ///
+    /// The accounts themselves are checked one by one with `BootstrapAccount::check`, so that the
+    /// valid ones can be created when others aren't.
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the file can be imported, otherwise `AppError::InvalidInput`
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.accounts.is_empty() {
+            return Err(AppError::InvalidInput(
+                "The file has no accounts".to_string(),
+            ));
+        }
+        if self.accounts.len() > MAX_ACCOUNTS {
+            return Err(AppError::InvalidInput(format!(
+                "The file has {} accounts, at most {MAX_ACCOUNTS} can be imported at once",
+                self.accounts.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl BootstrapAccount {
+    /// Check that an account of the file can be created
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the account is valid, otherwise `AppError::InvalidInput` telling why
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.name.trim().is_empty() {
+            return Err(AppError::InvalidInput(
+                "The name of the account is empty".to_string(),
+            ));
+        }
+        if self.name.chars().count() > MAX_NAME_LENGTH {
+            return Err(AppError::InvalidInput(format!(
+                "The name of the account is longer than {MAX_NAME_LENGTH} characters"
+            )));
+        }
+        validate_currency_code(&self.currency)?;
+        if self.transactions.len() > MAX_TRANSACTIONS {
+            return Err(AppError::InvalidInput(format!(
+                "The account has {} transactions, at most {MAX_TRANSACTIONS} can be imported",
+                self.transactions.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Convert the transactions of the account to the fields of transactions
+    pub fn transaction_inputs(&self) -> Vec<TransactionInput> {
+        self.transactions
+            .iter()
+            .map(|t| TransactionInput {
+                currency: t.currency.clone(),
+                original_amount: t.original_amount.clone(),
+                original_currency: t.original_currency.clone(),
+                ..TransactionInput::new(t.amount.clone(), &t.description, t.occurred_at)
+            })
+            .collect()
+    }
+}
+
+/// Get the name to give an account, given the names already taken
+///
+/// # Arguments
+///
+/// * `name` - The name of the account in the file
+/// * `taken` - The names of the accounts of the user, and of those created before from the file
+/// * `duplicates` - What to do if the name is taken
+///
+/// # Returns
+///
+/// The name, with a number after it if it was taken and duplicates get a suffix, or
+/// `AppError::Conflict` if it was taken and duplicates are rejected
+pub fn unique_name(
+    name: &str,
+    taken: &HashSet<String>,
+    duplicates: DuplicateNames,
+) -> Result<String, AppError> {
+    if !taken.contains(name) {
+        return Ok(name.to_string());
+    }
+    if duplicates == DuplicateNames::Reject {
+        return Err(AppError::Conflict(format!(
+            "An account named \"{name}\" already exists"
+        )));
+    }
+
+    (2..)
+        .map(|n| {
+            let suffix = format!(" ({n})");
+            // Shorten the name rather than go over the length of names
+            let kept = MAX_NAME_LENGTH.saturating_sub(suffix.chars().count());
+            let base: String = name.chars().take(kept).collect();
+            format!("{}{suffix}", base.trim_end())
+        })
+        .find(|candidate| !taken.contains(candidate))
+        .ok_or_else(|| AppError::Conflict(format!("No name is left for \"{name}\"")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn fixture(text: &str) -> BootstrapFile {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let file = fixture(include_str!("fixtures/bootstrap.json"));
+        file.check().unwrap();
+        assert_eq!(file.duplicate_names, DuplicateNames::Suffix);
+        assert_eq!(file.accounts.len(), 2);
+
+        let chequing = &file.accounts[0];
+        chequing.check().unwrap();
+        assert_eq!(chequing.kind, None);
+        assert_eq!(
+            chequing.opening_balance,
+            BigDecimal::from_str("1520.35").unwrap()
+        );
+        let inputs = chequing.transaction_inputs();
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(inputs[1].amount, BigDecimal::from_str("-1200.00").unwrap());
+        assert_eq!(inputs[1].description, "Rent");
+        assert_eq!(
+            inputs[1].occurred_at,
+            NaiveDate::from_ymd_opt(2024, 2, 1).unwrap()
+        );
+
+        let card = &file.accounts[1];
+        card.check().unwrap();
+        assert_eq!(card.kind, Some(AccountKind::Credit));
+        assert!(card.transactions.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_accounts() {
+        let file = fixture(include_str!("fixtures/bootstrap_invalid.json"));
+        // The file is read, and its accounts are checked one by one
+        file.check().unwrap();
+        assert_eq!(file.duplicate_names, DuplicateNames::Reject);
+
+        let errors: Vec<_> = file
+            .accounts
+            .iter()
+            .map(|account| account.check().err().map(|e| e.to_string()))
+            .collect();
+        assert_eq!(errors[0], None);
+        assert_eq!(
+            errors[1].as_deref(),
+            Some("\"cad\" is not an ISO 4217 currency code")
+        );
+        assert_eq!(
+            errors[2].as_deref(),
+            Some("The name of the account is empty")
+        );
+    }
+
+    #[test]
+    fn test_file_limits() {
+        let mut file = fixture(include_str!("fixtures/bootstrap.json"));
+        file.accounts.clear();
+        assert!(matches!(file.check(), Err(AppError::InvalidInput(_))));
+
+        let account = fixture(include_str!("fixtures/bootstrap.json")).accounts[1].clone();
+        file.accounts = vec![account; MAX_ACCOUNTS + 1];
+        assert!(matches!(file.check(), Err(AppError::InvalidInput(_))));
+
+        // Files that aren't bootstrap files aren't read
+        assert!(serde_json::from_str::<BootstrapFile>(r#"{"accounts": {}}"#).is_err());
+        assert!(serde_json::from_str::<BootstrapFile>(
+            r#"{"accounts": [{"name": "Chequing", "currency": "CAD"}]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let taken: HashSet<String> = ["Chequing", "Chequing (2)"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        assert_eq!(
+            unique_name("Savings", &taken, DuplicateNames::Reject).unwrap(),
+            "Savings"
+        );
+        assert!(matches!(
+            unique_name("Chequing", &taken, DuplicateNames::Reject),
+            Err(AppError::Conflict(_))
+        ));
+        assert_eq!(
+            unique_name("Chequing", &taken, DuplicateNames::Suffix).unwrap(),
+            "Chequing (3)"
+        );
+
+        // Long names are shortened to fit the suffix
+        let long = "x".repeat(MAX_NAME_LENGTH);
+        let taken = HashSet::from([long.clone()]);
+        let renamed = unique_name(&long, &taken, DuplicateNames::Suffix).unwrap();
+        assert_eq!(renamed.chars().count(), MAX_NAME_LENGTH);
+        assert!(renamed.ends_with("x (2)"));
+    }
+}
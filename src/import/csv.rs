@@ -0,0 +1,351 @@
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A reference to a CSV column, either by header name or by zero-based index
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    /// Zero-based index of the column
+    Index(usize),
+    /// Header name of the column
+    Name(String),
+}
+
+/// The columns holding the amount of a transaction
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(untagged)]
+pub enum AmountColumns {
+    /// A single column with a signed amount (negative for money leaving the account)
+    Signed {
+        /// The signed amount column
+        amount: ColumnRef,
+    },
+    /// Separate columns for money leaving (debit) and entering (credit) the account
+    DebitCredit {
+        /// The debit column, amounts are made negative
+        debit: ColumnRef,
+        /// The credit column, amounts are kept positive
+        credit: ColumnRef,
+    },
+}
+
+/// Describes how the columns of a bank's CSV export map to transaction fields
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ColumnMapping {
+    /// The column holding the date of the transaction
+    pub date: ColumnRef,
+    /// The `chrono` format string of the date column, e.g. `%Y-%m-%d`
+    pub date_format: String,
+    /// The column holding the description of the transaction
+    pub description: ColumnRef,
+    /// The column(s) holding the amount of the transaction
+    #[serde(flatten)]
+    pub amount: AmountColumns,
+    /// Whether the first line of the file is a header row (default `true`)
+    #[serde(default = "default_has_headers")]
+    pub has_headers: bool,
+}
+
+fn default_has_headers() -> bool {
+    true
+}
+
+/// A row that passed validation
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedRow {
+    /// Line number of the row in the file (1-based)
+    pub line: u64,
+    /// The date the transaction occurred on
+    pub occurred_at: NaiveDate,
+    /// Signed amount of the transaction
+    pub amount: BigDecimal,
+    /// Description of the transaction
+    pub description: String,
+}
+
+/// A row that failed validation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RowError {
+    /// Line number of the row in the file (1-based)
+    pub line: u64,
+    /// What was wrong with the row
+    pub message: String,
+}
+
+/// The outcome of parsing a CSV file
+#[derive(Debug, Default)]
+pub struct ParsedCsv {
+    /// Rows that can be inserted
+    pub rows: Vec<ParsedRow>,
+    /// Rows that were skipped, with the reason
+    pub errors: Vec<RowError>,
+}
+
+/// Resolved column indices of a mapping
+struct Columns {
+    date: usize,
+    description: usize,
+    amount: ResolvedAmount,
+}
+
+enum ResolvedAmount {
+    Signed(usize),
+    DebitCredit(usize, usize),
+}
+
+/// Parse a CSV file according to a column mapping
+///
+/// This is CPU bound and should be run on a blocking thread for large files.
+///
+/// # Arguments
+///
+/// * `data` - The content of the CSV file
+/// * `mapping` - How the columns map to transaction fields
+///
+/// # Returns
+///
+/// The valid rows and the errors of the invalid ones, or an error message if the file as a whole
+/// can't be read with the mapping (e.g. a mapped header is missing).
+pub fn parse(data: &str, mapping: &ColumnMapping) -> Result<ParsedCsv, String> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .has_headers(mapping.has_headers)
+        .flexible(true)
+        .trim(::csv::Trim::All)
+        .from_reader(data.as_bytes());
+
+    let headers = if mapping.has_headers {
+        Some(
+            reader
+                .headers()
+                .map_err(|e| format!("Failed to read the header row: {e}"))?
+                .clone(),
+        )
+    } else {
+        None
+    };
+
+    let resolve = |column: &ColumnRef| -> Result<usize, String> {
+        match (column, &headers) {
+            (ColumnRef::Index(index), _) => Ok(*index),
+            (ColumnRef::Name(name), Some(headers)) => headers
+                .iter()
+                .position(|header| header.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("Column \"{name}\" not found in the header row")),
+            (ColumnRef::Name(name), None) => Err(format!(
+                "Column \"{name}\" is referenced by name but the file has no header row"
+            )),
+        }
+    };
+
+    let columns = Columns {
+        date: resolve(&mapping.date)?,
+        description: resolve(&mapping.description)?,
+        amount: match &mapping.amount {
+            AmountColumns::Signed { amount } => ResolvedAmount::Signed(resolve(amount)?),
+            AmountColumns::DebitCredit { debit, credit } => {
+                ResolvedAmount::DebitCredit(resolve(debit)?, resolve(credit)?)
+            }
+        },
+    };
+
+    let mut parsed = ParsedCsv::default();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or_default();
+                parsed.errors.push(RowError {
+                    line,
+                    message: format!("Malformed row: {e}"),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+
+        match parse_record(&record, &columns, &mapping.date_format) {
+            Ok((occurred_at, amount, description)) => parsed.rows.push(ParsedRow {
+                line,
+                occurred_at,
+                amount,
+                description,
+            }),
+            Err(message) => parsed.errors.push(RowError { line, message }),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Validate a single record and extract its fields
+fn parse_record(
+    record: &::csv::StringRecord,
+    columns: &Columns,
+    date_format: &str,
+) -> Result<(NaiveDate, BigDecimal, String), String> {
+    let field = |index: usize| -> Result<&str, String> {
+        record
+            .get(index)
+            .ok_or_else(|| format!("Missing column {index}"))
+    };
+
+    let date = field(columns.date)?;
+    let occurred_at = NaiveDate::parse_from_str(date, date_format)
+        .map_err(|e| format!("Invalid date \"{date}\" for format \"{date_format}\": {e}"))?;
+
+    let description = field(columns.description)?.to_string();
+
+    let amount = match columns.amount {
+        ResolvedAmount::Signed(index) => {
+            parse_amount(field(index)?)?.ok_or_else(|| "Missing amount".to_string())?
+        }
+        ResolvedAmount::DebitCredit(debit, credit) => {
+            match (parse_amount(field(debit)?)?, parse_amount(field(credit)?)?) {
+                (Some(debit), None) => -debit.abs(),
+                (None, Some(credit)) => credit.abs(),
+                (None, None) => return Err("Missing debit and credit amounts".to_string()),
+                (Some(_), Some(_)) => {
+                    return Err("Row has both a debit and a credit amount".to_string())
+                }
+            }
+        }
+    };
+
+    Ok((occurred_at, amount, description))
+}
+
+/// Parse an amount, ignoring thousands separators and currency symbols
+///
+/// Amounts in parentheses are negative, as is common in accounting exports.
+fn parse_amount(value: &str) -> Result<Option<BigDecimal>, String> {
+    let cleaned: String = value
+        .chars()
+        .filter(|c| !matches!(c, ',' | '$' | ' '))
+        .collect();
+    if cleaned.is_empty() {
+        return Ok(None);
+    }
+
+    let (negative, digits) = match cleaned.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(digits) => (true, digits),
+        None => (false, cleaned.as_str()),
+    };
+
+    let amount = BigDecimal::from_str(digits).map_err(|_| format!("Invalid amount \"{value}\""))?;
+    if amount.fractional_digit_count() > 2 {
+        return Err(format!(
+            "Amount \"{value}\" has more than two decimal places"
+        ));
+    }
+
+    Ok(Some(if negative { -amount } else { amount }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_signed_layout() {
+        let mapping: ColumnMapping = serde_json::from_str(
+            r#"{"date": "Date", "date_format": "%Y-%m-%d", "description": "Description", "amount": "Amount"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            mapping.amount,
+            AmountColumns::Signed {
+                amount: ColumnRef::Name("Amount".to_string())
+            }
+        );
+
+        let parsed = parse(include_str!("fixtures/signed.csv"), &mapping).unwrap();
+
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        assert_eq!(parsed.rows.len(), 4);
+        assert_eq!(
+            parsed.rows[0],
+            ParsedRow {
+                line: 2,
+                occurred_at: date(2024, 6, 1),
+                amount: dec("2500.00"),
+                description: "PAYROLL DEPOSIT".to_string(),
+            }
+        );
+        assert_eq!(parsed.rows[1].amount, dec("-1200.00"));
+        assert_eq!(parsed.rows[2].description, "AMZN Mktp CA*2J4, Toronto");
+        assert_eq!(parsed.rows[3].amount, dec("-1234.56"));
+    }
+
+    #[test]
+    fn test_debit_credit_layout() {
+        let mapping: ColumnMapping = serde_json::from_str(
+            r#"{"date": 0, "date_format": "%m/%d/%Y", "description": 1, "debit": 2, "credit": 3, "has_headers": false}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            mapping.amount,
+            AmountColumns::DebitCredit {
+                debit: ColumnRef::Index(2),
+                credit: ColumnRef::Index(3)
+            }
+        );
+
+        let parsed = parse(include_str!("fixtures/debit_credit.csv"), &mapping).unwrap();
+
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        assert_eq!(parsed.rows.len(), 3);
+        assert_eq!(parsed.rows[0].occurred_at, date(2024, 6, 1));
+        assert_eq!(parsed.rows[0].amount, dec("2500.00"));
+        assert_eq!(parsed.rows[1].amount, dec("-64.99"));
+        assert_eq!(parsed.rows[2].line, 3);
+        assert_eq!(parsed.rows[2].amount, dec("-5.00"));
+    }
+
+    #[test]
+    fn test_malformed_rows() {
+        let mapping: ColumnMapping = serde_json::from_str(
+            r#"{"date": "Date", "date_format": "%Y-%m-%d", "description": "Description", "debit": "Debit", "credit": "Credit"}"#,
+        )
+        .unwrap();
+
+        let parsed = parse(include_str!("fixtures/malformed.csv"), &mapping).unwrap();
+
+        assert_eq!(parsed.rows.len(), 1);
+        assert_eq!(parsed.rows[0].line, 2);
+
+        let lines: Vec<u64> = parsed.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6, 7]);
+        assert!(parsed.errors[0].message.contains("Invalid date"));
+        assert!(parsed.errors[1].message.contains("Invalid amount"));
+        assert!(parsed.errors[2]
+            .message
+            .contains("both a debit and a credit"));
+        assert!(parsed.errors[3]
+            .message
+            .contains("Missing debit and credit"));
+        assert!(parsed.errors[4].message.contains("Missing column"));
+    }
+
+    #[test]
+    fn test_missing_header() {
+        let mapping: ColumnMapping = serde_json::from_str(
+            r#"{"date": "Posted", "date_format": "%Y-%m-%d", "description": "Description", "amount": "Amount"}"#,
+        )
+        .unwrap();
+
+        let error = parse(include_str!("fixtures/signed.csv"), &mapping).unwrap_err();
+        assert!(error.contains("\"Posted\""));
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashSet;
+
+use bigdecimal::BigDecimal;
+use chrono::{Duration, NaiveDate};
+
+/// How many days apart two transactions can be and still be considered duplicates
+pub const DATE_WINDOW_DAYS: i64 = 3;
+
+/// Minimum description similarity (0 to 1) for two transactions to be considered duplicates
+pub const SIMILARITY_THRESHOLD: f64 = 0.6;
+
+/// The fields of an existing transaction compared against imported rows
+#[derive(Debug, Clone)]
+pub struct ExistingTransaction {
+    /// ID of the existing transaction
+    pub id: i32,
+    /// Signed amount of the transaction
+    pub amount: BigDecimal,
+    /// Description of the transaction
+    pub description: String,
+    /// The date the transaction occurred on
+    pub occurred_at: NaiveDate,
+}
+
+/// Normalize a description for comparison
+///
+/// Lowercases, drops punctuation and collapses whitespace, so `"AMZN Mktp CA*2J4"` and
+/// `"amzn mktp ca 2j4"` compare equal.
+pub fn normalize(description: &str) -> String {
+    description
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                ' '
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Similarity of two descriptions, from 0 (nothing in common) to 1 (identical once normalized)
+///
+/// This is the Sørensen–Dice coefficient over the character bigrams of the normalized strings.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return 1.0;
+    }
+
+    let bigrams = |s: &str| -> HashSet<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    };
+    let (a, b) = (bigrams(&a), bigrams(&b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+/// Find an existing transaction that an imported row likely duplicates
+///
+/// A row is a duplicate of an existing transaction if the amounts match exactly, the dates are at
+/// most `DATE_WINDOW_DAYS` apart and the descriptions are at least `SIMILARITY_THRESHOLD` similar.
+///
+/// # Arguments
+///
+/// * `amount` - Signed amount of the imported row
+/// * `description` - Description of the imported row
+/// * `occurred_at` - Date of the imported row
+/// * `existing` - Existing transactions of the same account
+///
+/// # Returns
+///
+/// The ID of the most similar matching transaction, if any
+pub fn find_duplicate(
+    amount: &BigDecimal,
+    description: &str,
+    occurred_at: NaiveDate,
+    existing: &[ExistingTransaction],
+) -> Option<i32> {
+    let window = Duration::days(DATE_WINDOW_DAYS);
+
+    existing
+        .iter()
+        .filter(|t| &t.amount == amount)
+        .filter(|t| (t.occurred_at - occurred_at).abs() <= window)
+        .map(|t| (t.id, similarity(&t.description, description)))
+        .filter(|(_, score)| *score >= SIMILARITY_THRESHOLD)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(id, _)| id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn dec(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    fn existing() -> Vec<ExistingTransaction> {
+        vec![
+            ExistingTransaction {
+                id: 1,
+                amount: dec("-45.67"),
+                description: "AMZN Mktp CA*2J4".to_string(),
+                occurred_at: date(2024, 6, 10),
+            },
+            ExistingTransaction {
+                id: 2,
+                amount: dec("-12.00"),
+                description: "NETFLIX.COM".to_string(),
+                occurred_at: date(2024, 6, 10),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("  AMZN Mktp CA*2J4 "), "amzn mktp ca 2j4");
+        assert_eq!(normalize("Tim Hortons #1234"), "tim hortons 1234");
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("NETFLIX.COM", "netflix com"), 1.0);
+        assert!(similarity("AMZN Mktp CA*2J4", "AMZN Mktp CA*9Z1") >= SIMILARITY_THRESHOLD);
+        assert!(similarity("AMZN Mktp CA*2J4", "Shell Gas Station") < SIMILARITY_THRESHOLD);
+        assert_eq!(similarity("", "anything"), 0.0);
+    }
+
+    #[test]
+    fn test_date_window() {
+        let existing = existing();
+        let amount = dec("-45.67");
+
+        // Inside the window on either side
+        for day in [7, 8, 10, 12, 13] {
+            assert_eq!(
+                find_duplicate(&amount, "AMZN MKTP CA*2J4", date(2024, 6, day), &existing),
+                Some(1),
+                "June {day} should be a duplicate"
+            );
+        }
+
+        // Just outside the window on either side
+        for day in [6, 14] {
+            assert_eq!(
+                find_duplicate(&amount, "AMZN MKTP CA*2J4", date(2024, 6, day), &existing),
+                None,
+                "June {day} should not be a duplicate"
+            );
+        }
+    }
+
+    #[test]
+    fn test_amount_and_description_must_match() {
+        let existing = existing();
+
+        // Off by a cent
+        assert_eq!(
+            find_duplicate(
+                &dec("-45.68"),
+                "AMZN Mktp CA*2J4",
+                date(2024, 6, 10),
+                &existing
+            ),
+            None
+        );
+        // Same amount, unrelated description
+        assert_eq!(
+            find_duplicate(&dec("-12.00"), "Corner Store", date(2024, 6, 10), &existing),
+            None
+        );
+        // Same amount with a different scale still matches
+        assert_eq!(
+            find_duplicate(&dec("-12"), "NETFLIX.COM", date(2024, 6, 11), &existing),
+            Some(2)
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub mod bootstrap;
+pub mod csv;
+pub mod duplicates;
@@ -0,0 +1,371 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{
+    connection::{DbConn, DbPool},
+    models::{
+        attachments::{Attachment, AttachmentStatus},
+        jobs::{Job, JobKind},
+    },
+};
+use crate::errors::AppError;
+use crate::storage::attachments::AttachmentStore;
+
+/// How long a file no attachment points to is kept, so the files of uploads still being recorded
+/// aren't removed
+pub const ORPHAN_GRACE: Duration = Duration::hours(24);
+
+/// Hour of the day, in UTC, the cleanup runs at
+const CLEANUP_HOUR: u32 = 3;
+
+/// What a cleanup job needs to run, its payload
+#[derive(Debug, Serialize, Deserialize)]
+struct CleanupPayload {
+    /// The day the cleanup runs on
+    day: NaiveDate,
+}
+
+/// What a cleanup of the attachments directory did
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Cleanup {
+    /// Files no attachment pointed to for longer than the grace period, removed
+    pub removed_files: usize,
+    /// Attachments whose file was found gone, flagged `missing`
+    pub missing: usize,
+    /// Attachments flagged `missing` whose file is back
+    pub found: usize,
+    /// Users whose storage was counted wrong, counted again
+    pub recounted_users: usize,
+}
+
+/// Queue the cleanup of a day, to run at 3 AM UTC
+///
+/// Nothing is queued if the cleanup of the day is already pending, so servers starting together
+/// and retried jobs don't queue a day twice.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `day` - The day the cleanup runs on
+///
+/// # Returns
+///
+/// The queued job, if one was queued
+fn enqueue(conn: &mut DbConn, day: NaiveDate) -> Result<Option<Job>, AppError> {
+    let payload =
+        serde_json::to_value(CleanupPayload { day }).expect("The payload is serializable");
+    if Job::has_pending(conn, JobKind::AttachmentCleanup, &payload)? {
+        return Ok(None);
+    }
+
+    let run_at = day
+        .and_hms_opt(CLEANUP_HOUR, 0, 0)
+        .expect("The hour of the cleanup is a valid time");
+    Job::enqueue(conn, JobKind::AttachmentCleanup, payload, run_at).map(Some)
+}
+
+/// Queue the cleanup of the next day, on startup
+///
+/// Each cleanup job queues the next day's once it runs, so this only starts the chain, or
+/// restarts it if a job ran out of attempts.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `now` - The current time, in UTC
+///
+/// # Returns
+///
+/// The queued job, if one was queued
+pub fn schedule(conn: &mut DbConn, now: NaiveDateTime) -> Result<Option<Job>, AppError> {
+    enqueue(conn, now.date() + Duration::days(1))
+}
+
+/// Run a cleanup job: clean the attachments directory, then queue the next day's cleanup
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool
+/// * `store` - The files attached to transactions
+/// * `job` - The job, whose payload tells the day
+/// * `now` - The current time, in UTC
+///
+/// # Returns
+///
+/// What the cleanup did
+pub async fn run(
+    pool: &Arc<DbPool>,
+    store: &AttachmentStore,
+    job: &Job,
+    now: NaiveDateTime,
+) -> Result<Cleanup, AppError> {
+    let payload = serde_json::from_value::<CleanupPayload>(job.payload().clone())
+        .map_err(|e| AppError::InvalidInput(format!("Invalid cleanup payload ({e})")))?;
+
+    let cleanup = clean(pool, store, now).await?;
+    tracing::info!(
+        removed_files = cleanup.removed_files,
+        missing = cleanup.missing,
+        found = cleanup.found,
+        recounted_users = cleanup.recounted_users,
+        "Cleaned the attachments of {}",
+        payload.day
+    );
+    pool.run(move |conn| enqueue(conn, payload.day + Duration::days(1)))
+        .await?;
+    Ok(cleanup)
+}
+
+/// Reconcile the attachments directory with the attachments
+///
+/// Files no attachment points to are removed once they're older than `ORPHAN_GRACE`, attachments
+/// whose file is gone are flagged `missing`, and the storage of users is counted again from their
+/// attachments.
+///
+/// The attachments are listed before the files: an upload writes its file before recording its
+/// attachment, so every attachment listed has its file listed unless it's really gone. Files of
+/// attachments recorded in between look orphaned, which the grace period keeps them from.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool
+/// * `store` - The files attached to transactions
+/// * `now` - The current time, in UTC
+///
+/// # Returns
+///
+/// What the cleanup did
+pub async fn clean(
+    pool: &Arc<DbPool>,
+    store: &AttachmentStore,
+    now: NaiveDateTime,
+) -> Result<Cleanup, AppError> {
+    let attachments = pool.run(Attachment::all).await?;
+    let files = store.files().await?;
+
+    let recorded: HashSet<&str> = attachments.iter().map(|a| a.storage_path()).collect();
+    let stored: HashSet<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+
+    let mut removed_files = 0;
+    for (path, modified) in &files {
+        let age = now - DateTime::<Utc>::from(*modified).naive_utc();
+        if !recorded.contains(path.as_str()) && age >= ORPHAN_GRACE {
+            tracing::info!("Removing attachment file {path}, which no attachment points to");
+            store.remove(path).await;
+            removed_files += 1;
+        }
+    }
+
+    let mut missing = Vec::new();
+    let mut found = Vec::new();
+    for attachment in &attachments {
+        match (
+            attachment.status(),
+            stored.contains(attachment.storage_path()),
+        ) {
+            (AttachmentStatus::Stored, false) => {
+                tracing::warn!(
+                    attachment_id = attachment.id(),
+                    "The file of attachment {} is missing",
+                    attachment.id()
+                );
+                missing.push(attachment.id());
+            }
+            (AttachmentStatus::Missing, true) => found.push(attachment.id()),
+            _ => {}
+        }
+    }
+
+    pool.run(move |conn| {
+        Ok(Cleanup {
+            removed_files,
+            missing: Attachment::set_status(conn, &missing, AttachmentStatus::Missing)?,
+            found: Attachment::set_status(conn, &found, AttachmentStatus::Stored)?,
+            recounted_users: Attachment::recount_bytes(conn)?,
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use axum::http::Method;
+    use diesel::prelude::*;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::api::test_utils::TestApp;
+    use crate::clock::Clock;
+
+    /// Get the size of the files of the user of the app, as counted
+    fn bytes_used(app: &TestApp) -> u64 {
+        Attachment::total_bytes_of_user(&mut app.pool().get().unwrap(), app.user_id()).unwrap()
+    }
+
+    /// Write a file in the attachments directory, last written some time ago
+    fn write_file(app: &TestApp, name: &str, age: std::time::Duration) {
+        let path = app.data_dir().join("attachments").join(name);
+        std::fs::write(&path, b"left behind").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    async fn statuses(app: &TestApp, uri: &str) -> Vec<Value> {
+        let (status, attachments) = app.request(Method::GET, uri, None).await;
+        assert_eq!(status, 200, "{attachments}");
+        attachments
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|attachment| attachment["status"].clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_cleanup() {
+        let app = TestApp::new();
+        let store = AttachmentStore::new(app.data_dir());
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0", "currency": "CAD"})),
+            )
+            .await;
+        let transaction_uri = format!("/accounts/{}/transactions", account["id"]);
+        let (_, transaction) = app
+            .request(
+                Method::POST,
+                &transaction_uri,
+                Some(json!({"amount": "-42.50", "description": "Hardware store", "occurred_at": "2024-07-05"})),
+            )
+            .await;
+        let uri = format!("/transactions/{}/attachments", transaction["id"]);
+
+        // The storage of the user is counted as attachments are added and deleted
+        app.upload(&uri, "receipt.pdf", "application/pdf", &[1; 3000])
+            .await;
+        let (_, photo) = app.upload(&uri, "photo.jpg", "image/jpeg", &[2; 500]).await;
+        let (_, scan) = app.upload(&uri, "scan.png", "image/png", &[3; 20]).await;
+        assert_eq!(bytes_used(&app), 3520);
+        let (status, _) = app
+            .request(
+                Method::DELETE,
+                &format!("/attachments/{}", scan["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(bytes_used(&app), 3500);
+        assert_eq!(statuses(&app, &uri).await, ["stored", "stored"]);
+
+        // A crash left a file without an attachment, and the file of the photo is gone
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        write_file(&app, "99-crashed", 2 * day);
+        write_file(&app, "99-uploading", day / 2);
+        let photo_path = {
+            let conn = &mut app.pool().get().unwrap();
+            let photo = Attachment::all(conn)
+                .unwrap()
+                .into_iter()
+                .find(|attachment| attachment.id() == photo["id"])
+                .unwrap();
+            // The count drifted, e.g. changed by hand
+            diesel::sql_query(format!(
+                "UPDATE user_storage SET attachment_bytes_used = 7 WHERE user_id = {}",
+                app.user_id()
+            ))
+            .execute(conn)
+            .unwrap();
+            photo.storage_path().to_string()
+        };
+        let photo_file = std::fs::read(store.path(&photo_path)).unwrap();
+        std::fs::remove_file(store.path(&photo_path)).unwrap();
+
+        let cleanup = clean(app.pool(), &store, app.clock().now_utc())
+            .await
+            .unwrap();
+        assert_eq!(
+            cleanup,
+            Cleanup {
+                removed_files: 1,
+                missing: 1,
+                found: 0,
+                recounted_users: 1,
+            }
+        );
+        let files: Vec<_> = store
+            .files()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        // Only the file written too recently to tell it from an upload is kept
+        assert!(files.contains(&"99-uploading".to_string()), "{files:?}");
+        assert!(!files.contains(&"99-crashed".to_string()), "{files:?}");
+        assert_eq!(files.len(), 2, "{files:?}");
+        assert_eq!(bytes_used(&app), 3500);
+        assert_eq!(statuses(&app, &uri).await, ["stored", "missing"]);
+        let (status, _, _) = app.download(&format!("/attachments/{}", photo["id"])).await;
+        assert_eq!(status, 404);
+
+        // A file put back is found again, and nothing else changes
+        std::fs::write(store.path(&photo_path), photo_file).unwrap();
+        let cleanup = clean(app.pool(), &store, app.clock().now_utc())
+            .await
+            .unwrap();
+        assert_eq!(
+            cleanup,
+            Cleanup {
+                found: 1,
+                ..Cleanup::default()
+            }
+        );
+        assert_eq!(statuses(&app, &uri).await, ["stored", "stored"]);
+
+        // Deleting the transaction takes its attachments off the count
+        let (status, _) = app
+            .request(
+                Method::DELETE,
+                &format!("{transaction_uri}/{}", transaction["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(bytes_used(&app), 0);
+    }
+
+    #[test]
+    fn test_schedule() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let now = NaiveDate::from_ymd_opt(2024, 7, 5)
+            .unwrap()
+            .and_hms_opt(18, 30, 0)
+            .unwrap();
+
+        let job = schedule(conn, now).unwrap().unwrap();
+        assert_eq!(job.kind(), JobKind::AttachmentCleanup);
+        assert_eq!(job.payload(), &json!({"day": "2024-07-06"}));
+        assert_eq!(
+            job.run_at(),
+            NaiveDate::from_ymd_opt(2024, 7, 6)
+                .unwrap()
+                .and_hms_opt(3, 0, 0)
+                .unwrap()
+        );
+        // Servers starting together queue the day once
+        assert!(schedule(conn, now).unwrap().is_none());
+    }
+}
@@ -0,0 +1,313 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        jobs::{Job, JobKind},
+        notifications::{NewNotification, Notification},
+        users::User,
+    },
+};
+use crate::errors::AppError;
+use crate::reports::digest::{self, DigestInput, DIGEST_KIND};
+
+/// What a weekly digest job needs to run, its payload
+#[derive(Debug, Serialize, Deserialize)]
+struct DigestPayload {
+    /// Monday of the week the digest is about
+    week_start: NaiveDate,
+}
+
+/// Get the Monday that starts the week after a day
+fn next_monday(day: NaiveDate) -> NaiveDate {
+    day + Duration::days(7 - day.weekday().num_days_from_monday() as i64)
+}
+
+/// Queue the digest of a week, to run at midnight UTC on the Monday after it
+///
+/// Nothing is queued if the digest of the week is already pending, so servers starting together
+/// and retried jobs don't queue a week twice.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `week_start` - Monday of the week
+///
+/// # Returns
+///
+/// The queued job, if one was queued
+fn enqueue(conn: &mut DbConn, week_start: NaiveDate) -> Result<Option<Job>, AppError> {
+    let payload =
+        serde_json::to_value(DigestPayload { week_start }).expect("The payload is serializable");
+    if Job::has_pending(conn, JobKind::WeeklyDigest, &payload)? {
+        return Ok(None);
+    }
+
+    let run_at = (week_start + Duration::days(7))
+        .and_hms_opt(0, 0, 0)
+        .expect("Midnight is a valid time");
+    Job::enqueue(conn, JobKind::WeeklyDigest, payload, run_at).map(Some)
+}
+
+/// Queue the digest of the current week, on startup
+///
+/// Each digest job queues the next week's once it runs, so this only starts the chain, or
+/// restarts it if a job ran out of attempts.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `now` - The current time, in UTC
+///
+/// # Returns
+///
+/// The queued job, if one was queued
+pub fn schedule(conn: &mut DbConn, now: NaiveDateTime) -> Result<Option<Job>, AppError> {
+    enqueue(conn, next_monday(now.date()) - Duration::days(7))
+}
+
+/// Run a weekly digest job: send the digests of its week, then queue the next week's
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `job` - The job, whose payload tells the week
+///
+/// # Returns
+///
+/// The number of digests sent
+pub fn run(conn: &mut DbConn, job: &Job) -> Result<usize, AppError> {
+    let payload = serde_json::from_value::<DigestPayload>(job.payload().clone())
+        .map_err(|e| AppError::InvalidInput(format!("Invalid digest payload ({e})")))?;
+
+    let sent = send(conn, payload.week_start)?;
+    tracing::info!("Sent {sent} digests of the week of {}", payload.week_start);
+    enqueue(conn, payload.week_start + Duration::days(7))?;
+    Ok(sent)
+}
+
+/// Send the digest of a week to every user who has it enabled
+///
+/// Users who were already sent the digest of the week are skipped, so a retried job only sends
+/// the digests it didn't get to. A digest that fails is logged and the other users still get
+/// theirs.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `week_start` - Monday of the week
+///
+/// # Returns
+///
+/// The number of digests sent
+pub fn send(conn: &mut DbConn, week_start: NaiveDate) -> Result<usize, AppError> {
+    let mut sent = 0;
+    for user_id in User::digest_recipients(conn)? {
+        match send_to(conn, user_id, week_start) {
+            Ok(true) => sent += 1,
+            Ok(false) => {}
+            Err(e) => tracing::warn!(
+                "Digest of the week of {week_start} wasn't sent to user {user_id} ({e})"
+            ),
+        }
+    }
+    Ok(sent)
+}
+
+/// Send the digest of a week to a user, unless it was sent already
+///
+/// # Returns
+///
+/// Whether the digest was sent
+fn send_to(conn: &mut DbConn, user_id: i32, week_start: NaiveDate) -> Result<bool, AppError> {
+    let already_sent = Notification::latest_of_kind(conn, user_id, DIGEST_KIND)?
+        .is_some_and(|latest| latest.data()["week_start"] == week_start.to_string());
+    if already_sent {
+        return Ok(false);
+    }
+
+    let digest = digest::assemble(DigestInput::load(conn, user_id, week_start)?);
+    Notification::new(
+        conn,
+        &NewNotification::new(
+            user_id,
+            DIGEST_KIND,
+            digest.title(),
+            digest.body(),
+            serde_json::to_value(&digest).expect("The digest is serializable"),
+        ),
+    )?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+    use diesel::Connection;
+    use serde_json::json;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::{Account, AccountKind},
+            budgets::{Budget, BudgetInput, BudgetInterval},
+            categories::Category,
+            plans::Plan,
+            recurring_transactions::{Cadence, RecurringTransaction, Schedule},
+            transactions::{Transaction, TransactionInput},
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    /// Create a user with an account, a budget and a week of transactions around June 3rd, 2024
+    fn seed(conn: &mut DbConn, name: &str) -> User {
+        let user = User::new(conn, name, "password").unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &decimal("0.00"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let dining = Category::new(conn, user.id(), "Dining").unwrap();
+        let plan = Plan::new(conn, &format!("{name} plan"), user.id()).unwrap();
+        Budget::new(
+            conn,
+            &plan,
+            &BudgetInput {
+                category_id: groceries.id(),
+                name: "Food".to_string(),
+                amount: decimal("200.00"),
+                interval: BudgetInterval::Monthly,
+                currency: "CAD".to_string(),
+                start_date: date(2024, 1, 1),
+                end_date: None,
+                rollover: false,
+            },
+        )
+        .unwrap();
+
+        for (amount, day, category) in [
+            ("-120.00", date(2024, 6, 3), Some(groceries.id())),
+            ("-45.50", date(2024, 6, 5), Some(groceries.id())),
+            ("-30.00", date(2024, 6, 7), Some(dining.id())),
+            ("-8.25", date(2024, 6, 9), None),
+            ("2500.00", date(2024, 6, 7), None),
+            // Outside the week
+            ("-999.00", date(2024, 6, 10), Some(dining.id())),
+        ] {
+            let mut input = TransactionInput::new(decimal(amount), "Purchase", day);
+            input.category_id = category;
+            Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        }
+
+        let rent = TransactionInput::new(decimal("-1200.00"), "Rent", date(2024, 6, 1));
+        RecurringTransaction::new(
+            conn,
+            &account,
+            &rent,
+            Schedule::new(Cadence::Monthly, Some(15), None).unwrap(),
+            date(2024, 6, 1),
+            None,
+        )
+        .unwrap();
+
+        user
+    }
+
+    #[test]
+    fn test_send_digests() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let subscribed = seed(conn, "digest_subscribed");
+        let opted_out = seed(conn, "digest_opted_out")
+            .set_digest_enabled(conn, false)
+            .unwrap();
+        assert!(!opted_out.digest_enabled());
+
+        let week_start = date(2024, 6, 3);
+        assert!(send(conn, week_start).unwrap() >= 1);
+
+        let digests =
+            Notification::get_all(conn, subscribed.id(), false, Some(DIGEST_KIND)).unwrap();
+        assert_eq!(digests.len(), 1);
+        assert_eq!(
+            digests[0].data(),
+            &json!({
+                "week_start": "2024-06-03",
+                "week_end": "2024-06-09",
+                "total_spent": "203.75",
+                "top_categories": [
+                    {"category": "Groceries", "spent": "165.50"},
+                    {"category": "Dining", "spent": "30.00"},
+                    {"category": null, "spent": "8.25"},
+                ],
+                "budgets": [{
+                    "plan": "digest_subscribed plan",
+                    "name": "Food",
+                    "currency": "CAD",
+                    "budgeted": "200.00",
+                    "actual": "165.50",
+                    "percent_used": 82,
+                }],
+                "upcoming": [
+                    {"date": "2024-06-15", "description": "Rent", "amount": "-1200.00"},
+                ],
+            })
+        );
+        assert!(Notification::get_all(conn, opted_out.id(), false, None)
+            .unwrap()
+            .is_empty());
+
+        // The week isn't sent twice
+        send(conn, week_start).unwrap();
+        let digests =
+            Notification::get_all(conn, subscribed.id(), false, Some(DIGEST_KIND)).unwrap();
+        assert_eq!(digests.len(), 1);
+    }
+
+    #[test]
+    fn test_digest_jobs_are_chained() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        // A Wednesday, the current week started on the Monday before
+        let now = date(2024, 6, 5).and_hms_opt(13, 30, 0).unwrap();
+        let job = schedule(conn, now).unwrap().unwrap();
+        assert_eq!(job.payload(), &json!({"week_start": "2024-06-03"}));
+        assert_eq!(
+            job.run_at(),
+            date(2024, 6, 10).and_hms_opt(0, 0, 0).unwrap()
+        );
+        // Scheduling again, e.g. from another server, queues nothing
+        assert!(schedule(conn, now).unwrap().is_none());
+
+        run(conn, &job).unwrap();
+        let next = serde_json::to_value(DigestPayload {
+            week_start: date(2024, 6, 10),
+        })
+        .unwrap();
+        assert!(Job::has_pending(conn, JobKind::WeeklyDigest, &next).unwrap());
+
+        assert_eq!(next_monday(date(2024, 6, 9)), date(2024, 6, 10));
+        assert_eq!(next_monday(date(2024, 6, 10)), date(2024, 6, 17));
+    }
+}
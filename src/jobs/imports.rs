@@ -0,0 +1,398 @@
+use chrono::{Duration, NaiveDateTime};
+use diesel::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::alerts;
+use crate::database::{
+    connection::DbConn,
+    models::{
+        accounts::Account,
+        import_jobs::{ImportJob, ImportStatus},
+        import_pending::PendingImport,
+        jobs::{Job, JobKind},
+        payee_rules::PayeeRules,
+        transactions::{Transaction, TransactionInput},
+    },
+};
+use crate::errors::AppError;
+use crate::import::{
+    csv::{self, ColumnMapping, ParsedCsv},
+    duplicates::{self, ExistingTransaction, DATE_WINDOW_DAYS},
+};
+use crate::jobs::queue::Services;
+use crate::quotas::{Quotas, Resource};
+
+/// Number of rows inserted by each transaction of an import
+pub const BATCH_SIZE: usize = 500;
+
+/// What an import job needs to run, its payload
+#[derive(Debug, Serialize, Deserialize)]
+struct ImportPayload {
+    import_id: i32,
+    data: String,
+    mapping: ColumnMapping,
+}
+
+/// Queue the import of a parsed file into an account
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `account` - The account the transactions are imported into
+/// * `data` - The content of the file, parsed again by the worker
+/// * `mapping` - How the columns of the file map to transaction fields
+/// * `parsed` - The file as parsed when it was uploaded, for its number of rows and errors
+/// * `quotas` - The quotas of the server, which every row of the file must fit
+/// * `now` - The current time, when the import can start
+///
+/// # Returns
+///
+/// The queued import, or `AppError::QuotaExceeded` if the rows don't fit the quota of the user
+pub fn enqueue(
+    conn: &mut DbConn,
+    account: &Account,
+    data: String,
+    mapping: ColumnMapping,
+    parsed: &ParsedCsv,
+    quotas: &Quotas,
+    now: NaiveDateTime,
+) -> Result<ImportJob, AppError> {
+    quotas.ensure(
+        conn,
+        account.user_id(),
+        Resource::Transactions,
+        parsed.rows.len() as u64,
+    )?;
+    let import = ImportJob::new(conn, account, parsed.rows.len(), &parsed.errors)?;
+    let payload = ImportPayload {
+        import_id: import.id(),
+        data,
+        mapping,
+    };
+    let payload = serde_json::to_value(payload).expect("Imports serialize to JSON");
+    Job::enqueue(conn, JobKind::Import, payload, now)?;
+
+    Ok(import)
+}
+
+/// Run an import job, committing its rows in batches until they're all inserted or the user
+/// cancels it
+///
+/// An import resumed after its worker stopped skips the rows of the batches it committed.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `services` - The webhooks, events and reports cache of the server
+/// * `job` - The claimed job
+/// * `batch_size` - Number of rows inserted by each transaction
+pub fn run(
+    conn: &mut DbConn,
+    services: &Services,
+    job: &Job,
+    batch_size: usize,
+) -> Result<(), AppError> {
+    let payload = payload(job)?;
+    let clock = &services.clock;
+    let Some(mut import) = ImportRun::start(conn, &payload, clock.now_utc())? else {
+        return Ok(());
+    };
+
+    while import.next_batch(conn, services, batch_size, clock.now_utc())? {
+        job.keep_locked(conn, clock.now_utc())?;
+    }
+    Ok(())
+}
+
+/// Fail the import of a job that ran out of attempts
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `job` - The failed job
+/// * `now` - The current time
+pub fn fail(conn: &mut DbConn, job: &Job, now: NaiveDateTime) -> Result<(), AppError> {
+    let payload = payload(job)?;
+    ImportJob::fail(
+        conn,
+        payload.import_id,
+        job.last_error().unwrap_or("The import failed"),
+        now,
+    )
+}
+
+/// Read the payload of an import job
+fn payload(job: &Job) -> Result<ImportPayload, AppError> {
+    serde_json::from_value(job.payload().clone())
+        .map_err(|e| AppError::InvalidInput(format!("Invalid import job {} ({e})", job.id())))
+}
+
+/// An import a worker is running
+struct ImportRun {
+    import: ImportJob,
+    account: Account,
+    /// The rows that are left, with the payee rules applied
+    rows: std::vec::IntoIter<TransactionInput>,
+    /// The transactions the rows are compared to, loaded before the first batch so the rows of
+    /// the file aren't compared to each other
+    existing: Vec<ExistingTransaction>,
+}
+
+impl ImportRun {
+    /// Start an import, or resume it after the batches it committed
+    ///
+    /// # Returns
+    ///
+    /// The import, `None` if it was cancelled, finished or deleted
+    fn start(
+        conn: &mut DbConn,
+        payload: &ImportPayload,
+        now: NaiveDateTime,
+    ) -> Result<Option<Self>, AppError> {
+        let Some(import) = ImportJob::start(conn, payload.import_id, now)? else {
+            return Ok(None);
+        };
+        let account = Account::from_id(conn, import.account_id(), import.user_id())?;
+
+        let parsed = csv::parse(&payload.data, &payload.mapping).map_err(AppError::InvalidInput)?;
+        let rules = PayeeRules::load(conn, import.user_id())?;
+        let rows: Vec<TransactionInput> = parsed
+            .rows
+            .into_iter()
+            .map(|row| {
+                let mut input =
+                    TransactionInput::new(row.amount, &row.description, row.occurred_at);
+                rules.apply(&mut input);
+                input
+            })
+            .collect();
+        let existing = existing_transactions(conn, &account, &rows)?;
+
+        let mut rows = rows.into_iter();
+        if import.rows_processed() > 0 {
+            rows.nth(import.rows_processed() - 1);
+        }
+        Ok(Some(Self {
+            import,
+            account,
+            rows,
+            existing,
+        }))
+    }
+
+    /// Insert the next batch of rows, in a transaction with the progress of the import
+    ///
+    /// The import is cancelled instead if the user asked for it, keeping the batches before. It
+    /// fails without retrying if the batch doesn't fit the quota of the user, as another attempt
+    /// wouldn't either.
+    ///
+    /// # Returns
+    ///
+    /// Whether there are batches left
+    fn next_batch(
+        &mut self,
+        conn: &mut DbConn,
+        services: &Services,
+        batch_size: usize,
+        now: NaiveDateTime,
+    ) -> Result<bool, AppError> {
+        if self.import.reload(conn)?.cancel_requested() {
+            self.import = self
+                .import
+                .finish(conn, ImportStatus::Cancelled, None, now)?;
+            return Ok(false);
+        }
+
+        let batch: Vec<TransactionInput> = self.rows.by_ref().take(batch_size).collect();
+        if batch.is_empty() {
+            self.import = self.import.finish(conn, ImportStatus::Done, None, now)?;
+            return Ok(false);
+        }
+
+        let user_id = self.import.user_id();
+        let recorded = conn.transaction(|conn| {
+            let processed = batch.len();
+            let (fresh, duplicates) = flag_duplicates(batch, &self.existing);
+            Transaction::bulk_insert(conn, &self.account, &fresh, &services.quotas)?;
+            let flagged = PendingImport::insert_all(conn, &self.account, &duplicates)?;
+            alerts::check(
+                conn,
+                &services.webhooks,
+                &services.events,
+                user_id,
+                fresh
+                    .iter()
+                    .filter_map(|input| Some((input.category_id?, input.occurred_at))),
+            );
+            self.import
+                .record_batch(conn, processed, fresh.len(), flagged)
+        });
+        self.import = match recorded {
+            Ok(import) => import,
+            Err(AppError::QuotaExceeded(exceeded)) => {
+                self.import = self.import.finish(
+                    conn,
+                    ImportStatus::Failed,
+                    Some(&exceeded.to_string()),
+                    now,
+                )?;
+                return Ok(false);
+            }
+            Err(e) => return Err(e),
+        };
+        services.reports.invalidate(user_id);
+
+        Ok(true)
+    }
+}
+
+/// Load the existing transactions within the date window of the imported rows
+fn existing_transactions(
+    conn: &mut DbConn,
+    account: &Account,
+    rows: &[TransactionInput],
+) -> Result<Vec<ExistingTransaction>, AppError> {
+    let dates = rows.iter().map(|row| row.occurred_at);
+    let (Some(from), Some(to)) = (dates.clone().min(), dates.max()) else {
+        return Ok(vec![]);
+    };
+
+    let window = Duration::days(DATE_WINDOW_DAYS);
+    Ok(
+        Transaction::between(conn, account, from - window, to + window)?
+            .into_iter()
+            .map(|t| ExistingTransaction {
+                id: t.id(),
+                amount: t.amount().clone(),
+                description: t.description().to_string(),
+                occurred_at: t.occurred_at(),
+            })
+            .collect(),
+    )
+}
+
+/// Split imported rows into fresh rows and likely duplicates of existing transactions
+///
+/// # Returns
+///
+/// The fresh rows, and the duplicate rows each with the ID of the transaction it duplicates
+#[allow(clippy::type_complexity)]
+fn flag_duplicates(
+    rows: Vec<TransactionInput>,
+    existing: &[ExistingTransaction],
+) -> (Vec<TransactionInput>, Vec<(TransactionInput, i32)>) {
+    let mut fresh = vec![];
+    let mut flagged = vec![];
+    for row in rows {
+        match duplicates::find_duplicate(&row.amount, &row.description, row.occurred_at, existing) {
+            Some(id) => flagged.push((row, id)),
+            None => fresh.push(row),
+        }
+    }
+
+    (fresh, flagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{accounts::AccountKind, users::User},
+    };
+    use crate::import::csv::AmountColumns;
+    use crate::jobs::webhooks::WebhookConfig;
+    use crate::storage::attachments::AttachmentStore;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 7, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_import_is_committed_in_batches_until_cancelled() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+        let services = Services::new(
+            WebhookConfig::default(),
+            Quotas::disabled(),
+            AttachmentStore::new(std::env::temp_dir()),
+        );
+
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &"0.00".parse().unwrap(),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let mut data = "Date,Description,Amount\n".to_string();
+        for day in 1..=7 {
+            data += &format!("2024-06-0{day},Coffee {day},-3.50\n");
+        }
+        data += "not a date,Broken,-1.00\n";
+        let mapping = ColumnMapping {
+            date: csv::ColumnRef::Name("Date".to_string()),
+            description: csv::ColumnRef::Name("Description".to_string()),
+            amount: AmountColumns::Signed {
+                amount: csv::ColumnRef::Name("Amount".to_string()),
+            },
+            date_format: "%Y-%m-%d".to_string(),
+            has_headers: true,
+        };
+        let parsed = csv::parse(&data, &mapping).unwrap();
+        let queued = enqueue(
+            conn,
+            &account,
+            data,
+            mapping,
+            &parsed,
+            &services.quotas,
+            now(),
+        )
+        .unwrap();
+        let job = Job::claim(conn, "worker", now(), std::time::Duration::from_secs(60))
+            .unwrap()
+            .unwrap();
+        let payload = payload(&job).unwrap();
+        assert_eq!(payload.import_id, queued.id());
+
+        // Each batch commits its rows with the progress of the import
+        let mut run = ImportRun::start(conn, &payload, now()).unwrap().unwrap();
+        for processed in [3, 6] {
+            assert!(run.next_batch(conn, &services, 3, now()).unwrap());
+            let import = ImportJob::from_id(conn, queued.id(), user.id()).unwrap();
+            assert_eq!(import.status(), ImportStatus::Running);
+            assert_eq!(import.rows_processed(), processed);
+            assert_eq!(import.inserted(), processed);
+        }
+
+        // Cancelling stops the import before its next batch, the batches before are kept
+        let import = ImportJob::from_id(conn, queued.id(), user.id()).unwrap();
+        import.cancel(conn, now()).unwrap();
+        assert!(!run.next_batch(conn, &services, 3, now()).unwrap());
+        let import = ImportJob::from_id(conn, queued.id(), user.id()).unwrap();
+        assert_eq!(import.status(), ImportStatus::Cancelled);
+        assert_eq!(import.rows_processed(), 6);
+        let inserted = Transaction::between(
+            conn,
+            &account,
+            now().date() - Duration::days(60),
+            now().date(),
+        )
+        .unwrap();
+        assert_eq!(inserted.len(), 6);
+        let import = serde_json::to_value(&import).unwrap();
+        assert_eq!(import["rows_total"], 7);
+        assert_eq!(import["skipped"], 1);
+        assert_eq!(import["errors"][0]["line"], 9);
+    }
+}
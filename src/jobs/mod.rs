@@ -0,0 +1,8 @@
+pub mod attachments;
+pub mod digest;
+pub mod imports;
+pub mod notifications;
+pub mod queue;
+pub mod recurring;
+pub mod scheduled_reports;
+pub mod webhooks;
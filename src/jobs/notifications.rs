@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use diesel::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::database::{
+    connection::{DbConn, DbPool},
+    models::{
+        jobs::{Job, JobKind},
+        notification_channels::ChannelConfig,
+        notifications::Notification,
+        users::User,
+    },
+};
+use crate::errors::AppError;
+
+/// Number of times a failed push is retried
+pub const MAX_RETRIES: u32 = 5;
+
+/// Somewhere the notifications of a user are pushed besides the app
+#[async_trait]
+pub trait NotificationChannel: Send + Sync {
+    /// Push a notification once
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user the notification is for
+    /// * `notification` - The notification, already stored
+    ///
+    /// # Returns
+    ///
+    /// Why the channel didn't accept the notification, if it didn't
+    async fn send(&self, user: &User, notification: &Notification) -> Result<(), String>;
+}
+
+/// A channel posting notifications as JSON to a URL of the user
+pub struct WebhookChannel {
+    client: reqwest::Client,
+    url: String,
+    auth_header: Option<String>,
+}
+
+/// Body of a push
+#[derive(Serialize)]
+struct Push<'a> {
+    /// The user the notification is for
+    user: PushUser<'a>,
+    /// The notification
+    notification: &'a Notification,
+}
+
+/// The user a push is for
+#[derive(Serialize)]
+struct PushUser<'a> {
+    id: i32,
+    username: &'a str,
+}
+
+impl WebhookChannel {
+    /// Create the channel of a user
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client posting pushes, which doesn't follow redirects
+    /// * `config` - The channel the user set
+    pub fn new(client: reqwest::Client, config: &ChannelConfig) -> Self {
+        Self {
+            client,
+            url: config.url().to_string(),
+            auth_header: config.auth_header().map(str::to_string),
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for WebhookChannel {
+    async fn send(&self, user: &User, notification: &Notification) -> Result<(), String> {
+        let push = Push {
+            user: PushUser {
+                id: user.id(),
+                username: user.username(),
+            },
+            notification,
+        };
+        let body = serde_json::to_string(&push)
+            .map_err(|e| format!("Failed serializing the push ({e})"))?;
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
+        if let Some(header) = &self.auth_header {
+            request = request.header(reqwest::header::AUTHORIZATION, header);
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(format!(
+                "The notification channel refused the push ({})",
+                response.status()
+            )),
+            Err(e) => Err(format!("Push to the notification channel failed ({e})")),
+        }
+    }
+}
+
+/// What a push job needs to run, its payload
+#[derive(Debug, Serialize, Deserialize)]
+struct PushPayload {
+    /// ID of the user the notification is for
+    user_id: i32,
+    /// ID of the notification
+    notification_id: i32,
+}
+
+/// Queue the push of a notification to the channel of its user, if they set one
+///
+/// The push is queued in a savepoint of the transaction the notification was stored in, so it's
+/// only sent if the notification is committed, and failing to queue it never loses the
+/// notification: errors are only logged.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `notification` - The notification, already stored
+pub fn enqueue(conn: &mut DbConn, notification: &Notification) {
+    let payload = PushPayload {
+        user_id: notification.user_id(),
+        notification_id: notification.id(),
+    };
+    let queued = conn.transaction(|conn| {
+        if ChannelConfig::of_user(conn, payload.user_id)?.is_none() {
+            return Ok(());
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let payload = serde_json::to_value(&payload).expect("The payload is serializable");
+        Job::enqueue(conn, JobKind::NotificationPush, payload, now).map(|_| ())
+    });
+    if let Err(e) = queued {
+        tracing::warn!("Dropped push of notification {} ({e})", notification.id());
+    }
+}
+
+/// Run a push job: post the notification to the channel of its user
+///
+/// Nothing is pushed if the user removed their channel or the notification since it was queued.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool
+/// * `client` - The client posting pushes, which doesn't follow redirects
+/// * `job` - The job, whose payload tells the notification
+///
+/// # Returns
+///
+/// Why the channel didn't accept the notification, if it didn't
+pub async fn run(pool: &Arc<DbPool>, client: &reqwest::Client, job: &Job) -> Result<(), String> {
+    let payload = serde_json::from_value::<PushPayload>(job.payload().clone())
+        .map_err(|e| format!("Invalid push payload ({e})"))?;
+
+    let found = pool
+        .run(move |conn| {
+            let Some(config) = ChannelConfig::of_user(conn, payload.user_id)? else {
+                return Ok(None);
+            };
+            let notification =
+                match Notification::from_id(conn, payload.notification_id, payload.user_id) {
+                    Ok(notification) => notification,
+                    Err(AppError::NotFound(_)) => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+            let user = User::from_id(conn, payload.user_id)?;
+            Ok(Some((config, user, notification)))
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some((config, user, notification)) = found else {
+        return Ok(());
+    };
+
+    WebhookChannel::new(client.clone(), &config)
+        .send(&user, &notification)
+        .await
+}
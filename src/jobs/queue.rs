@@ -0,0 +1,381 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use tokio::sync::{watch, Semaphore};
+use uuid::Uuid;
+
+use crate::clock::{Clock, SystemClock};
+use crate::database::{
+    connection::DbPool,
+    models::jobs::{Job, JobKind},
+};
+use crate::errors::AppError;
+use crate::events::EventBus;
+use crate::jobs::webhooks::{self, Delivery, WebhookConfig, WebhookDispatcher};
+use crate::jobs::{attachments, digest, imports, notifications};
+use crate::quotas::Quotas;
+use crate::reports::cache::ReportCache;
+use crate::storage::attachments::AttachmentStore;
+
+/// How long a job can stay locked before it's assumed its worker stopped
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How jobs of the queue are run
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    /// How long to wait before looking for due jobs again, once there are none
+    pub poll_interval: Duration,
+    /// How long to wait before the first retry of a job, doubled before each of the next ones
+    pub retry_delay: Duration,
+    /// Number of jobs a worker runs at once
+    pub concurrency: usize,
+    /// Number of rows an import inserts in each transaction
+    pub import_batch_size: usize,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            retry_delay: Duration::from_secs(2),
+            concurrency: 16,
+            import_batch_size: imports::BATCH_SIZE,
+        }
+    }
+}
+
+/// What the server shares between its routes and the jobs of the queue
+#[derive(Clone)]
+pub struct Services {
+    /// The dispatcher of the events of users to their webhooks
+    pub webhooks: Arc<WebhookDispatcher>,
+    /// The bus publishing the events of users to their open streams
+    pub events: Arc<EventBus>,
+    /// The reports of users computed recently, served again until their data changes
+    pub reports: Arc<ReportCache>,
+    /// How much data each user can store
+    pub quotas: Quotas,
+    /// The files attached to transactions
+    pub attachments: Arc<AttachmentStore>,
+    /// The clock the current time is read from
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Services {
+    /// Create the services of a server, reading the time from the clock of the system
+    ///
+    /// # Arguments
+    ///
+    /// * `webhooks` - How the events of users are delivered to their webhooks
+    /// * `quotas` - How much data each user can store
+    /// * `attachments` - Where the files attached to transactions are stored
+    pub fn new(webhooks: WebhookConfig, quotas: Quotas, attachments: AttachmentStore) -> Self {
+        Self {
+            webhooks: Arc::new(WebhookDispatcher::new(webhooks)),
+            events: Arc::new(EventBus::new()),
+            reports: Arc::new(ReportCache::default()),
+            quotas,
+            attachments: Arc::new(attachments),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Read the current time from another clock than the one of the system, e.g. in tests
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+}
+
+impl std::fmt::Debug for Services {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Services").finish_non_exhaustive()
+    }
+}
+
+/// Number of times a job of a kind is attempted before it's failed
+fn max_attempts(kind: JobKind) -> i32 {
+    match kind {
+        JobKind::WebhookDelivery => webhooks::MAX_RETRIES as i32 + 1,
+        // An import resumes after its committed batches, it's only retried for failures of the
+        // database
+        JobKind::Import => 3,
+        // Users already sent the digest are skipped when it's retried
+        JobKind::WeeklyDigest => 3,
+        // The next cleanup sets right what a failed one left
+        JobKind::AttachmentCleanup => 2,
+        JobKind::NotificationPush => notifications::MAX_RETRIES as i32 + 1,
+    }
+}
+
+/// Claims the due jobs of the queue and runs them
+pub struct Worker {
+    pool: Arc<DbPool>,
+    config: QueueConfig,
+    client: reqwest::Client,
+    services: Services,
+    name: String,
+}
+
+impl Worker {
+    /// Create a worker, named after the process so its locks can be told apart
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The database connection pool
+    /// * `config` - How jobs are run
+    /// * `services` - The webhooks, events and reports cache of the server, which jobs update
+    ///
+    /// # Returns
+    ///
+    /// The worker, or the error creating its HTTP client
+    pub fn new(
+        pool: Arc<DbPool>,
+        config: QueueConfig,
+        services: Services,
+    ) -> reqwest::Result<Self> {
+        Ok(Self {
+            pool,
+            config,
+            client: webhooks::client()?,
+            services,
+            name: format!("worker-{}-{}", std::process::id(), Uuid::new_v4()),
+        })
+    }
+
+    /// Claim the next due job, run it and record its outcome
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time, which the job is claimed and its outcome recorded at
+    ///
+    /// # Returns
+    ///
+    /// The job with its outcome recorded, if one was due
+    pub async fn run_once(&self, now: NaiveDateTime) -> Result<Option<Job>, AppError> {
+        let Some(job) = self.claim(now).await? else {
+            return Ok(None);
+        };
+        let outcome = self.execute(&job).await;
+        self.finish(job, outcome, now).await.map(Some)
+    }
+
+    /// Claim the next due job
+    async fn claim(&self, now: NaiveDateTime) -> Result<Option<Job>, AppError> {
+        let pool = self.pool.clone();
+        let name = self.name.clone();
+        tokio::task::spawn_blocking(move || Job::claim(&mut pool.get()?, &name, now, LOCK_TIMEOUT))
+            .await?
+    }
+
+    /// Run a claimed job
+    ///
+    /// # Returns
+    ///
+    /// Why the job failed, if it did
+    async fn execute(&self, job: &Job) -> Result<(), String> {
+        match job.kind() {
+            JobKind::WebhookDelivery => {
+                let delivery = serde_json::from_value::<Delivery>(job.payload().clone())
+                    .map_err(|e| format!("Invalid delivery ({e})"))?;
+                delivery.send(&self.client).await
+            }
+            JobKind::Import => {
+                let pool = self.pool.clone();
+                let services = self.services.clone();
+                let batch_size = self.config.import_batch_size;
+                let job = job.clone();
+                tokio::task::spawn_blocking(move || {
+                    imports::run(&mut pool.get()?, &services, &job, batch_size)
+                })
+                .await
+                .map_err(|e| e.to_string())?
+                .map_err(|e| e.to_string())
+            }
+            JobKind::WeeklyDigest => {
+                let pool = self.pool.clone();
+                let job = job.clone();
+                tokio::task::spawn_blocking(move || digest::run(&mut pool.get()?, &job))
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            }
+            JobKind::AttachmentCleanup => attachments::run(
+                &self.pool,
+                &self.services.attachments,
+                job,
+                self.services.clock.now_utc(),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+            JobKind::NotificationPush => notifications::run(&self.pool, &self.client, job).await,
+        }
+    }
+
+    /// Record the outcome of a job, scheduling its retry if it failed
+    async fn finish(
+        &self,
+        job: Job,
+        outcome: Result<(), String>,
+        now: NaiveDateTime,
+    ) -> Result<Job, AppError> {
+        let pool = self.pool.clone();
+        let retry_delay = self.config.retry_delay;
+        tokio::task::spawn_blocking(move || {
+            let conn = &mut pool.get()?;
+            let job = match &outcome {
+                Ok(()) => job.complete(conn, now)?,
+                Err(error) => {
+                    tracing::warn!(
+                        "Job {} attempt {} failed ({error})",
+                        job.id(),
+                        job.attempts()
+                    );
+                    job.fail(conn, error, now, retry_delay, max_attempts(job.kind()))?
+                }
+            };
+
+            // Only the final outcome of a delivery counts towards disabling its webhook
+            if job.kind() == JobKind::WebhookDelivery && (job.is_completed() || job.is_failed()) {
+                if let Ok(delivery) = serde_json::from_value::<Delivery>(job.payload().clone()) {
+                    delivery.record(conn, job.is_completed())?;
+                }
+            }
+            if job.kind() == JobKind::Import && job.is_failed() {
+                imports::fail(conn, &job, now)?;
+            }
+            Ok(job)
+        })
+        .await?
+    }
+}
+
+/// Run the due jobs of the queue until the server shuts down
+///
+/// Each job runs on its own task, so a slow one doesn't hold up the others. Once there are no
+/// due jobs, the queue is looked at again after the poll interval.
+///
+/// # Arguments
+///
+/// * `worker` - The worker claiming the jobs
+/// * `stop` - Set to `true` when the server shuts down, the jobs running finish first
+pub async fn run(worker: Arc<Worker>, mut stop: watch::Receiver<bool>) {
+    let concurrency = worker.config.concurrency;
+    let permits = Arc::new(Semaphore::new(concurrency));
+
+    loop {
+        let permit = tokio::select! {
+            permit = permits.clone().acquire_owned() => permit.expect("The semaphore isn't closed"),
+            _ = stop.wait_for(|stop| *stop) => break,
+        };
+
+        match worker.claim(worker.services.clock.now_utc()).await {
+            Ok(Some(job)) => {
+                let worker = worker.clone();
+                tokio::spawn(async move {
+                    let outcome = worker.execute(&job).await;
+                    let id = job.id();
+                    if let Err(e) = worker
+                        .finish(job, outcome, worker.services.clock.now_utc())
+                        .await
+                    {
+                        tracing::error!("Failed recording the outcome of job {id} ({e})");
+                    }
+                    drop(permit);
+                });
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("Failed claiming a job ({e})"),
+        }
+        drop(permit);
+
+        tokio::select! {
+            _ = tokio::time::sleep(worker.config.poll_interval) => {}
+            _ = stop.wait_for(|stop| *stop) => break,
+        }
+    }
+
+    // Wait for the jobs running to finish
+    let _ = permits.acquire_many(concurrency as u32).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Timelike;
+
+    use super::*;
+    use crate::database::models::{
+        users::User,
+        webhooks::{Webhook, WebhookEvent, WebhookInput},
+    };
+
+    #[tokio::test]
+    async fn test_undelivered_webhook_is_retried_then_failed() {
+        let pool = Arc::new(DbPool::new_test_shared());
+        let user_id = {
+            let conn = &mut pool.get().unwrap();
+            let user = User::default(conn).unwrap();
+            // Nothing listens on port 1, connections are refused right away
+            let input = WebhookInput {
+                url: "http://127.0.0.1:1/hook".to_string(),
+                secret: "a-secret-of-the-dashboard".to_string(),
+                events: vec![WebhookEvent::PlanCreated],
+                active: true,
+            };
+            Webhook::new(conn, user.id(), &input, true, &Quotas::disabled()).unwrap();
+            WebhookDispatcher::new(WebhookConfig {
+                allow_insecure: true,
+            })
+            .notify(conn, user.id(), WebhookEvent::PlanCreated, &"Household");
+            user.id()
+        };
+        let worker = Worker::new(
+            pool.clone(),
+            QueueConfig {
+                retry_delay: Duration::from_secs(10),
+                ..QueueConfig::default()
+            },
+            Services::new(
+                WebhookConfig::default(),
+                Quotas::disabled(),
+                AttachmentStore::new(std::env::temp_dir()),
+            ),
+        )
+        .unwrap();
+
+        // Retries are 10, 20, 40, then 80 and 160 seconds after the attempt before
+        // Timestamps are stored to the microsecond, the attempts are made on whole seconds
+        let mut now = chrono::Utc::now().naive_utc().with_nanosecond(0).unwrap()
+            + chrono::Duration::seconds(1);
+        for (attempt, delay) in [10, 20, 40, 80, 160].into_iter().enumerate() {
+            let job = worker.run_once(now).await.unwrap().unwrap();
+            assert_eq!(job.attempts(), attempt as i32 + 1);
+            assert!(!job.is_failed());
+            assert!(job.last_error().unwrap().contains("failed"));
+            assert_eq!(job.run_at(), now + chrono::Duration::seconds(delay));
+
+            // The job isn't run again before its retry is due
+            let early = job.run_at() - chrono::Duration::seconds(1);
+            assert!(worker.run_once(early).await.unwrap().is_none());
+            now = job.run_at();
+        }
+
+        // The last attempt fails the job for good, and counts against the webhook
+        let job = worker.run_once(now).await.unwrap().unwrap();
+        assert_eq!(job.attempts(), 6);
+        assert!(job.is_failed());
+        assert!(worker
+            .run_once(now + chrono::Duration::days(1))
+            .await
+            .unwrap()
+            .is_none());
+
+        let conn = &mut pool.get().unwrap();
+        let webhooks = Webhook::get_all(conn, user_id).unwrap();
+        let webhook = serde_json::to_value(&webhooks[0]).unwrap();
+        assert_eq!(webhook["failed_deliveries"], 1);
+    }
+}
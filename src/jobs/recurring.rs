@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::clock::Clock;
+use crate::database::{connection::DbPool, models::recurring_transactions::RecurringTransaction};
+
+/// How often due recurring transactions are materialized
+const MATERIALIZE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Materialize due recurring transactions on startup, then once a day
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool
+/// * `clock` - The clock of the server, whose current day is materialized up to
+/// * `stop` - Set to `true` when the server shuts down, a run in progress finishes first
+pub async fn run(pool: Arc<DbPool>, clock: Arc<dyn Clock>, mut stop: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(MATERIALIZE_INTERVAL);
+
+    loop {
+        // The first tick completes immediately
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = stop.wait_for(|stop| *stop) => return,
+        }
+
+        let pool = pool.clone();
+        let today = clock.today();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get()?;
+            RecurringTransaction::materialize_due(&mut conn, today)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(created)) => {
+                tracing::info!("Materialized {created} recurring transaction occurrences")
+            }
+            Ok(Err(e)) => tracing::error!("Failed materializing recurring transactions ({e})"),
+            Err(e) => tracing::error!("Recurring transactions task panicked ({e})"),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use tokio::sync::watch;
+
+use crate::clock::Clock;
+use crate::database::{
+    connection::{DbConn, DbPool},
+    models::scheduled_reports::ScheduledReport,
+};
+use crate::errors::AppError;
+use crate::export::reports;
+
+/// How often due scheduled reports are looked for
+const CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Render the scheduled reports that are due on startup, then every 15 minutes
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool
+/// * `clock` - The clock of the server, reports due by its current time are run
+/// * `output_dir` - Directory the reports are written to, in a subdirectory per user
+/// * `stop` - Set to `true` when the server shuts down, a run in progress finishes first
+pub async fn run(
+    pool: Arc<DbPool>,
+    clock: Arc<dyn Clock>,
+    output_dir: PathBuf,
+    mut stop: watch::Receiver<bool>,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        // The first tick completes immediately
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = stop.wait_for(|stop| *stop) => return,
+        }
+
+        let pool = pool.clone();
+        let output_dir = output_dir.clone();
+        let now = clock.now_local();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get()?;
+            run_due(&mut conn, now, &output_dir)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(0)) => {}
+            Ok(Ok(ran)) => tracing::info!("Ran {ran} scheduled reports"),
+            Ok(Err(e)) => tracing::error!("Failed running scheduled reports ({e})"),
+            Err(e) => tracing::error!("Scheduled reports task panicked ({e})"),
+        }
+    }
+}
+
+/// Render and write the scheduled reports that are due
+///
+/// Each report is written to `<output_dir>/<user ID>/<file name>`, see `reports::file_name`.
+/// A report that fails is recorded as failed with the error and is tried again at its next run,
+/// the other reports still run.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `now` - The current time
+/// * `output_dir` - Directory the reports are written to
+///
+/// # Returns
+///
+/// The number of reports that ran, whether they succeeded or failed
+pub fn run_due(
+    conn: &mut DbConn,
+    now: NaiveDateTime,
+    output_dir: &Path,
+) -> Result<usize, AppError> {
+    let due = ScheduledReport::due(conn, now)?;
+
+    for report in &due {
+        let error = write_report(conn, report, now, output_dir)
+            .err()
+            .map(|e| e.to_string());
+        if let Some(error) = &error {
+            tracing::warn!("Scheduled report {} failed ({error})", report.id());
+        }
+        report.record_run(conn, now, error)?;
+    }
+
+    Ok(due.len())
+}
+
+/// Render a report for the month its run covers and write it
+///
+/// The file is written next to its destination first and then renamed, so a file that is being
+/// read is never partially written.
+fn write_report(
+    conn: &mut DbConn,
+    report: &ScheduledReport,
+    now: NaiveDateTime,
+    output_dir: &Path,
+) -> Result<PathBuf, AppError> {
+    let (year, month) = ScheduledReport::period(now);
+    let contents = reports::render(conn, report, year, month)?;
+
+    let dir = output_dir.join(report.user_id().to_string());
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(reports::file_name(report, year, month));
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, &path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&partial);
+    })?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use chrono::NaiveDate;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::api::test_utils::TestApp;
+
+    fn at(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    async fn schedule(app: &TestApp, body: Value) -> Value {
+        let (status, report) = app
+            .request(Method::POST, "/scheduled-reports", Some(body))
+            .await;
+        assert_eq!(status, 201, "{report}");
+        report
+    }
+
+    #[tokio::test]
+    async fn test_run_due_reports() {
+        let app = TestApp::new();
+        let output_dir = app.data_dir().join("reports");
+
+        let (_, category) = app
+            .request(Method::POST, "/categories", Some(json!({"name": "Dining"})))
+            .await;
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        for (amount, occurred_at) in [("-25.50", "2030-01-10"), ("-100.00", "2030-02-03")] {
+            let (status, _) = app
+                .request(
+                    Method::POST,
+                    &format!("/accounts/{}/transactions", account["id"]),
+                    Some(json!({
+                        "amount": amount,
+                        "description": "Restaurant",
+                        "occurred_at": occurred_at,
+                        "category_id": category["id"],
+                    })),
+                )
+                .await;
+            assert_eq!(status, 201);
+        }
+        let (status, _) = app.request(Method::POST, "/plans/Home", None).await;
+        assert_eq!(status, 201);
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/plans/Home/budgets",
+                Some(json!({
+                    "category_id": category["id"],
+                    "name": "Eating out",
+                    "amount": "200.00",
+                    "interval": "monthly",
+                    "currency": "CAD",
+                    "start_date": "2029-01-01",
+                })),
+            )
+            .await;
+        assert_eq!(status, 201);
+
+        let summary = schedule(
+            &app,
+            json!({"kind": "monthly_summary", "cadence": "monthly", "format": "csv"}),
+        )
+        .await;
+        let budget = schedule(
+            &app,
+            json!({
+                "kind": "budget_vs_actual",
+                "plan_name": "Home",
+                "cadence": "monthly",
+                "format": "json",
+            }),
+        )
+        .await;
+        assert_eq!(summary["status"], "pending");
+
+        // The JSON file is blocked by a directory, so only the CSV report can be written
+        let user_dir = output_dir.join(app.user_id().to_string());
+        let budget_file = user_dir.join(format!("{}_budget_vs_actual_2030-01.json", budget["id"]));
+        std::fs::create_dir_all(&budget_file).unwrap();
+
+        let mut conn = app.pool().get().unwrap();
+        assert_eq!(run_due(&mut conn, at(2030, 2, 1), &output_dir).unwrap(), 2);
+        // Nothing is due until the next month
+        assert_eq!(run_due(&mut conn, at(2030, 2, 20), &output_dir).unwrap(), 0);
+        drop(conn);
+
+        let csv = std::fs::read_to_string(
+            user_dir.join(format!("{}_monthly_summary_2030-01.csv", summary["id"])),
+        )
+        .unwrap();
+        assert_eq!(
+            csv,
+            format!(
+                "category_id,category,income,expenses,net\n{},Dining,0,25.50,-25.50\n",
+                category["id"]
+            )
+        );
+
+        let (_, reports) = app.request(Method::GET, "/scheduled-reports", None).await;
+        assert_eq!(reports[0]["status"], "succeeded");
+        assert_eq!(reports[0]["last_run_at"], "2030-02-01T00:00:00Z");
+        assert_eq!(reports[0]["next_run_at"], "2030-03-01T00:00:00Z");
+        assert_eq!(reports[0]["last_error"], Value::Null);
+        assert_eq!(reports[1]["status"], "failed");
+        assert!(reports[1]["last_error"].is_string());
+
+        // The failed report succeeds on its next run once the file can be written
+        std::fs::remove_dir(&budget_file).unwrap();
+        let mut conn = app.pool().get().unwrap();
+        assert_eq!(run_due(&mut conn, at(2030, 3, 1), &output_dir).unwrap(), 2);
+        drop(conn);
+
+        let statuses: Value = serde_json::from_slice(
+            &std::fs::read(
+                user_dir.join(format!("{}_budget_vs_actual_2030-02.json", budget["id"])),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(statuses[0]["name"], "Eating out");
+        assert_eq!(statuses[0]["actual"], "100.00");
+        assert_eq!(statuses[0]["remaining"], "100.00");
+        let (_, report) = app
+            .request(
+                Method::GET,
+                &format!("/scheduled-reports/{}", budget["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(report["status"], "succeeded");
+        assert_eq!(report["last_error"], Value::Null);
+    }
+}
@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        jobs::{Job, JobKind},
+        webhooks::{Webhook, WebhookEvent},
+    },
+};
+use crate::errors::AppError;
+
+/// Header carrying the signature of a delivery
+pub const SIGNATURE_HEADER: &str = "X-FF-Signature";
+
+/// Header carrying the name of the event of a delivery
+pub const EVENT_HEADER: &str = "X-FF-Event";
+
+/// Number of times a failed delivery is retried
+pub const MAX_RETRIES: u32 = 5;
+
+/// How long a delivery can take before it fails
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Create the client posting webhook deliveries and notification pushes
+///
+/// Redirects aren't followed, so the checks of the URLs can't be worked around.
+pub fn client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+}
+
+/// How webhook deliveries are sent
+#[derive(Debug, Clone, Default)]
+pub struct WebhookConfig {
+    /// Whether plain HTTP and private network addresses are allowed, for development
+    pub allow_insecure: bool,
+}
+
+/// A signed payload to post to a webhook, the payload of its delivery job
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Delivery {
+    webhook_id: i32,
+    url: String,
+    event: WebhookEvent,
+    body: String,
+    signature: String,
+}
+
+impl Delivery {
+    /// Post the delivery once
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client posting deliveries, which doesn't follow redirects
+    ///
+    /// # Returns
+    ///
+    /// Why the endpoint didn't accept the delivery, if it didn't
+    pub async fn send(&self, client: &reqwest::Client) -> Result<(), String> {
+        let response = client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &self.signature)
+            .header(EVENT_HEADER, self.event.as_str())
+            .body(self.body.clone())
+            .send()
+            .await;
+
+        match response {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(format!(
+                "Webhook {} refused the delivery ({})",
+                self.webhook_id,
+                response.status()
+            )),
+            Err(e) => Err(format!(
+                "Delivery to webhook {} failed ({e})",
+                self.webhook_id
+            )),
+        }
+    }
+
+    /// Record that the delivery was accepted, or that it ran out of retries
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `delivered` - Whether the endpoint accepted the delivery
+    pub fn record(&self, conn: &mut DbConn, delivered: bool) -> Result<(), AppError> {
+        Webhook::record_delivery(conn, self.webhook_id, delivered)
+    }
+}
+
+/// Body of a delivery
+#[derive(Serialize)]
+struct Payload<'a, T: Serialize> {
+    /// Delivery ID, the same for every retry
+    id: Uuid,
+    /// The name of the event
+    event: &'static str,
+    /// The timestamp when the event happened
+    #[serde(with = "crate::utils::serialization")]
+    occurred_at: chrono::NaiveDateTime,
+    /// The record the event is about
+    data: &'a T,
+}
+
+/// Sign a body with the secret of a webhook
+///
+/// # Arguments
+///
+/// * `secret` - The secret of the webhook
+/// * `body` - The body of the delivery
+///
+/// # Returns
+///
+/// `sha256=` followed by the hex encoded HMAC-SHA256 of the body
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Queues deliveries of the events of users to the webhooks subscribed to them
+///
+/// Deliveries are jobs of the queue, sent by its workers and retried with exponential backoff.
+pub struct WebhookDispatcher {
+    allow_insecure: bool,
+}
+
+impl WebhookDispatcher {
+    /// Create the dispatcher
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - How deliveries are sent
+    ///
+    /// # Returns
+    ///
+    /// The dispatcher
+    pub fn new(config: WebhookConfig) -> Self {
+        Self {
+            allow_insecure: config.allow_insecure,
+        }
+    }
+
+    /// Check if plain HTTP and private network addresses are allowed
+    pub fn allow_insecure(&self) -> bool {
+        self.allow_insecure
+    }
+
+    /// Queue a delivery of an event to each webhook of the user subscribed to it
+    ///
+    /// The deliveries are queued in the transaction of the connection, so they're only sent if
+    /// the change they're about is committed. Notifying never fails the operation the event is
+    /// about, errors are only logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - ID of the user the event happened to
+    /// * `event` - The event
+    /// * `data` - The record the event is about
+    pub fn notify<T: Serialize>(
+        &self,
+        conn: &mut DbConn,
+        user_id: i32,
+        event: WebhookEvent,
+        data: &T,
+    ) {
+        let webhooks = match Webhook::subscribed(conn, user_id, event) {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                tracing::warn!("Webhooks weren't notified of {} ({e})", event.as_str());
+                return;
+            }
+        };
+        if webhooks.is_empty() {
+            return;
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let payload = Payload {
+            id: Uuid::new_v4(),
+            event: event.as_str(),
+            occurred_at: now,
+            data,
+        };
+        let body = match serde_json::to_string(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed serializing {} delivery ({e})", event.as_str());
+                return;
+            }
+        };
+
+        for webhook in webhooks {
+            let delivery = Delivery {
+                webhook_id: webhook.id(),
+                url: webhook.url().to_string(),
+                event,
+                signature: sign(webhook.secret(), body.as_bytes()),
+                body: body.clone(),
+            };
+            let payload = serde_json::to_value(&delivery).expect("Deliveries serialize to JSON");
+            if let Err(e) = Job::enqueue(conn, JobKind::WebhookDelivery, payload, now) {
+                tracing::warn!("Dropped delivery to webhook {} ({e})", webhook.id());
+            }
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! The Finance Fusion server, as a library for the binary and the integration tests in `tests/`
+
+pub mod config;
+pub mod errors;
+pub mod utils;
+
+pub mod alerts;
+pub mod api;
+pub mod audit;
+pub mod clock;
+pub mod database;
+pub mod dev;
+pub mod events;
+pub mod export;
+pub mod i18n;
+pub mod import;
+pub mod jobs;
+pub mod maintenance;
+pub mod merge;
+pub mod metrics;
+pub mod middleware;
+pub mod quotas;
+pub mod rate_limit;
+pub mod reports;
+pub mod routes;
+pub mod search;
+pub mod storage;
+pub mod wipe;
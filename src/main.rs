@@ -1,46 +1,83 @@
 use std::sync::Arc;
 
 use clap::Parser;
-use errors::AppError;
 use tracing::{error, info};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::util::SubscriberInitExt;
 
-mod config;
-mod errors;
-mod utils;
-
-mod api;
-mod database;
-mod middleware;
-mod routes;
-
-use config::config::{run, Args, VERSION};
+use finance_fusion_server::config::commands;
+use finance_fusion_server::config::config::{run, Args, Command, VERSION};
+use finance_fusion_server::config::logging;
+use finance_fusion_server::config::settings::Config;
+use finance_fusion_server::config::startup::{Startup, StartupPhase};
+use finance_fusion_server::database::connection::{database_url, replica_database_url, DbPool};
+use finance_fusion_server::errors::AppError;
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
-    // Set up tracing, which is used for logging.
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-                // axum logs rejections from built-in extractors with the `axum::rejection`
-                // target, at `TRACE` level. `axum::rejection=trace` enables showing those events
-                "finance_fusion=info,tower_http=info,axum::rejection=trace".into()
-            }),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
     // Parse command line arguments
     let args = Args::parse();
 
-    // Connect to database
-    let shared_pool = Arc::new(database::connection::DbPool::new());
+    // Set up tracing, which is used for logging, as the arguments ask until the configuration is
+    // loaded
+    let (subscriber, log_level) = logging::reloadable_subscriber(
+        args.log_format,
+        logging::filter(args.log_level.as_deref())?,
+        std::io::stdout,
+    );
+    subscriber.init();
 
     info!("Starting Finance Fusion Server v{VERSION}");
 
-    match run(args, shared_pool).await {
-        Ok(()) => info!("Exiting Finance Fusion Server"),
-        Err(e) => error!("Server encountered an error: {e}"),
+    // The OpenAPI document is written without a configuration or database
+    let command = args.command();
+    if let Command::GenerateOpenapi { out } = &command {
+        return commands::generate_openapi(out);
+    }
+
+    // Load the configuration, overridden by the environment and the arguments
+    dotenv::dotenv().ok();
+    let config = Config::load(&args, |name| std::env::var(name).ok())?;
+    if config.log_level != args.log_level {
+        log_level.set(config.log_level.as_deref())?;
+    }
+
+    // Connect to database, waiting for it to come up
+    let url = database_url(|name| std::env::var(name).ok())?;
+    let startup = Arc::new(Startup::default());
+    let pool = startup
+        .run(StartupPhase::Connect, async {
+            let pool = DbPool::connect(&url, config.pool_config()).await?;
+            // Reports and listings read from the replica, if one is configured
+            match replica_database_url(|name| std::env::var(name).ok()) {
+                Some(replica_url) => pool.with_replica(&replica_url, config.pool_config()).await,
+                None => Ok(pool),
+            }
+        })
+        .await?;
+    let shared_pool = Arc::new(pool);
+
+    match command {
+        // The server exits with an error if it fails, having logged the phase of startup it failed
+        // in if it didn't start
+        Command::Serve => match run(args, config, shared_pool, startup, log_level).await {
+            Ok(()) => info!("Exiting Finance Fusion Server"),
+            Err(e) => {
+                error!("Server encountered an error: {e}");
+                return Err(e);
+            }
+        },
+        Command::Migrate => commands::migrate(&shared_pool).await?,
+        Command::CreateAdmin { username, password } => {
+            commands::create_admin(&shared_pool, &username, move || match password {
+                Some(password) => Ok(password),
+                None => commands::prompt_password_on_terminal(),
+            })
+            .await?;
+        }
+        Command::Seed { force } => {
+            commands::seed(&shared_pool, &url, force).await?;
+        }
+        Command::GenerateOpenapi { .. } => unreachable!("The OpenAPI document is already written"),
     }
 
     Ok(())
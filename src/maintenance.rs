@@ -0,0 +1,211 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use axum::http::Method;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use utoipa::ToSchema;
+
+use crate::api::api::API_PREFIX;
+use crate::errors::AppError;
+
+/// Seconds clients are told to wait before retrying when the operator doesn't say
+pub const DEFAULT_RETRY_AFTER: u64 = 60;
+
+/// Message of the rejected requests when the operator doesn't give one
+const DEFAULT_MESSAGE: &str = "The server is under maintenance, try again later";
+
+/// Paths answered in every mode, relative to `API_PREFIX`: the vitals and metrics, and logins so
+/// administrators can turn maintenance off
+const OPEN_PATHS: [&str; 6] = [
+    "/vitals",
+    "/livez",
+    "/readyz",
+    "/metrics",
+    "/auth/login",
+    "/auth/logout",
+];
+
+/// Which requests the server answers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceMode {
+    /// Every request is answered
+    #[default]
+    Off,
+    /// Requests that read are answered, requests that write aren't
+    ReadOnly,
+    /// Only the vitals, metrics, logins and administration routes are answered
+    Full,
+}
+
+/// The maintenance the operator put the server in
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceStatus {
+    /// Which requests the server answers
+    pub mode: MaintenanceMode,
+    /// Why requests are rejected, sent as the message of their error
+    pub message: Option<String>,
+    /// Seconds clients are told to wait before retrying, sent as `Retry-After`
+    pub retry_after: u64,
+}
+
+/// The maintenance mode of the server, kept in a file of the data directory so restarts keep it
+#[derive(Debug)]
+pub struct Maintenance {
+    path: PathBuf,
+    status: RwLock<MaintenanceStatus>,
+}
+
+impl Maintenance {
+    /// Load the maintenance the server was in, the server isn't in maintenance without the file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file the maintenance is kept in
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::InvalidInput` naming the file if it can't be read or parsed, so the
+    /// server doesn't start out of maintenance by mistake.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AppError> {
+        let path = path.as_ref().to_path_buf();
+        let invalid = |reason: String| {
+            AppError::InvalidInput(format!(
+                "Invalid maintenance file {}: {reason}",
+                path.display()
+            ))
+        };
+
+        let status = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| invalid(e.to_string()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => MaintenanceStatus::default(),
+            Err(e) => return Err(invalid(e.to_string())),
+        };
+
+        Ok(Self {
+            path,
+            status: RwLock::new(status),
+        })
+    }
+
+    /// Get the current maintenance
+    pub fn status(&self) -> MaintenanceStatus {
+        self.status
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Put the server in maintenance, or take it out of it
+    ///
+    /// The file is replaced before requests are rejected, so the maintenance is kept if it
+    /// can't be written.
+    pub async fn set(&self, status: MaintenanceStatus) -> Result<(), AppError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let json = serde_json::to_vec_pretty(&status)
+            .map_err(|e| AppError::InvalidInput(e.to_string()))?;
+        let temporary = self.path.with_extension("json.tmp");
+        fs::write(&temporary, json).await?;
+        fs::rename(&temporary, &self.path).await?;
+
+        *self.status.write().unwrap_or_else(|e| e.into_inner()) = status;
+        Ok(())
+    }
+
+    /// Check whether a request is answered in the current mode
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The method of the request
+    /// * `path` - The path of the request, under `API_PREFIX` or at its legacy path
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Maintenance` with the message of the operator if the request isn't
+    /// answered.
+    pub fn check(&self, method: &Method, path: &str) -> Result<(), AppError> {
+        let status = self.status.read().unwrap_or_else(|e| e.into_inner());
+        let rejected = match status.mode {
+            MaintenanceMode::Off => false,
+            MaintenanceMode::ReadOnly => !is_read(method) && !is_open(path),
+            MaintenanceMode::Full => !is_open(path),
+        };
+        if !rejected {
+            return Ok(());
+        }
+
+        let message = status.message.as_deref().unwrap_or(DEFAULT_MESSAGE);
+        Err(AppError::Maintenance(
+            message.to_owned(),
+            status.retry_after,
+        ))
+    }
+}
+
+/// Whether a request only reads
+fn is_read(method: &Method) -> bool {
+    [Method::GET, Method::HEAD, Method::OPTIONS].contains(method)
+}
+
+/// Whether a path is answered in every mode
+fn is_open(path: &str) -> bool {
+    let path = path.strip_prefix(API_PREFIX).unwrap_or(path);
+    OPEN_PATHS.contains(&path) || path == "/admin" || path.starts_with("/admin/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("finance-fusion-{name}-{}", std::process::id()))
+            .join("maintenance.json")
+    }
+
+    #[tokio::test]
+    async fn test_restarts_keep_the_mode() {
+        let path = temp_file("maintenance-kept");
+        let maintenance = Maintenance::load(&path).unwrap();
+        assert_eq!(maintenance.status(), MaintenanceStatus::default());
+
+        let status = MaintenanceStatus {
+            mode: MaintenanceMode::ReadOnly,
+            message: Some("Migrating".to_owned()),
+            retry_after: 120,
+        };
+        maintenance.set(status.clone()).await.unwrap();
+        assert_eq!(Maintenance::load(&path).unwrap().status(), status);
+
+        // A file that can't be parsed stops the server from starting
+        std::fs::write(&path, "read_only").unwrap();
+        let error = Maintenance::load(&path).unwrap_err().to_string();
+        assert!(error.contains("Invalid maintenance file"), "{error}");
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_open_paths() {
+        for path in [
+            "/vitals",
+            "/api/v1/auth/login",
+            "/admin/maintenance",
+            "/api/v1/admin/audit",
+        ] {
+            assert!(is_open(path), "{path}");
+        }
+        for path in [
+            "/api/v1/accounts",
+            "/administrators",
+            "/api/v1/vitals/more",
+            "/hello",
+        ] {
+            assert!(!is_open(path), "{path}");
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use diesel::Connection;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        budgets::Budget, categories::Category, category_alerts::CategoryAlert,
+        payee_rules::PayeeRule, recurring_transactions::RecurringTransaction,
+        transactions::Transaction,
+    },
+};
+use crate::errors::AppError;
+
+/// The rows a category merge moved to the category merged into, by table
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct CategoryMerge {
+    /// Transactions
+    pub transactions: u64,
+    /// Splits of transactions
+    pub transaction_splits: u64,
+    /// Budgets, including those added to a budget of the same plan
+    pub budgets: u64,
+    /// The alert, 1 if the merged category had one
+    pub category_alerts: u64,
+    /// Payee rules defaulting to the category
+    pub payee_rules: u64,
+    /// Recurring transactions
+    pub recurring_transactions: u64,
+}
+
+/// Whether a category is an income category, from all of its transactions
+///
+/// Like in the category breakdown, a category where more money came in than went out is an
+/// income category, one where more went out is an expense category, and one without transactions
+/// is neither.
+fn is_income(conn: &mut DbConn, category: &Category) -> Result<Option<bool>, AppError> {
+    let first_day = NaiveDate::from_ymd_opt(1, 1, 1).expect("The first day of year 1 is valid");
+    let spent = Transaction::category_spending(
+        conn,
+        category.user_id(),
+        category.id(),
+        first_day,
+        NaiveDate::MAX,
+    )?;
+    Ok((!spent.is_zero()).then(|| spent < BigDecimal::zero()))
+}
+
+/// Merge a category into another category of the same user, deleting it
+///
+/// Its transactions, splits, budgets, alert, payee rules and recurring transactions are moved to
+/// the other category in a single database transaction. Budgets and alerts the other category
+/// already has in the same plan are added up rather than duplicated.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `source` - The category to merge and delete
+/// * `target` - The category to merge into
+///
+/// # Returns
+///
+/// How many rows were moved by table, or `AppError::InvalidInput` if the categories are the same
+/// or one is an income category and the other an expense category
+pub fn merge_categories(
+    conn: &mut DbConn,
+    source: &Category,
+    target: &Category,
+) -> Result<CategoryMerge, AppError> {
+    if source.id() == target.id() {
+        return Err(AppError::InvalidInput(
+            "A category can't be merged into itself".to_string(),
+        ));
+    }
+
+    conn.transaction(|conn| {
+        if let (Some(source_income), Some(target_income)) =
+            (is_income(conn, source)?, is_income(conn, target)?)
+        {
+            if source_income != target_income {
+                let kind = |income: bool| if income { "an income" } else { "an expense" };
+                return Err(AppError::InvalidInput(format!(
+                    "{} is {} category and {} is {} category, they can't be merged",
+                    source.name(),
+                    kind(source_income),
+                    target.name(),
+                    kind(target_income)
+                )));
+            }
+        }
+
+        let (transactions, transaction_splits) = Transaction::move_category(conn, source, target)?;
+        let merge = CategoryMerge {
+            transactions,
+            transaction_splits,
+            budgets: Budget::move_category(conn, source, target)?,
+            category_alerts: CategoryAlert::move_category(conn, source, target)?,
+            payee_rules: PayeeRule::move_category(conn, source, target)?,
+            recurring_transactions: RecurringTransaction::move_category(conn, source, target)?,
+        };
+        source.delete(conn)?;
+        Ok(merge)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::{Account, AccountKind},
+            budgets::{BudgetInput, BudgetInterval},
+            category_alerts::CategoryAlertInput,
+            plans::Plan,
+            transactions::{SplitInput, TransactionInput},
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn decimal(amount: &str) -> BigDecimal {
+        BigDecimal::from_str(amount).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn budget(category: &Category, amount: &str, currency: &str) -> BudgetInput {
+        BudgetInput {
+            category_id: category.id(),
+            name: category.name().to_string(),
+            amount: decimal(amount),
+            interval: BudgetInterval::Monthly,
+            currency: currency.to_string(),
+            start_date: date(2024, 1, 1),
+            end_date: None,
+            rollover: false,
+        }
+    }
+
+    fn alert(limit: &str) -> CategoryAlertInput {
+        CategoryAlertInput {
+            monthly_limit: decimal(limit),
+            notify_at_percent: 80,
+        }
+    }
+
+    #[test]
+    fn test_merge_categories() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::new(conn, "merge_categories", "password").unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &decimal("0.00"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let food = Category::new(conn, user.id(), "Food").unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let household = Category::new(conn, user.id(), "Household").unwrap();
+
+        let mut input = TransactionInput::new(decimal("-30.00"), "Market", date(2024, 6, 1));
+        input.category_id = Some(food.id());
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        let mut input = TransactionInput::new(decimal("-50.00"), "Store", date(2024, 6, 2));
+        input.splits = vec![
+            SplitInput {
+                category_id: food.id(),
+                amount: decimal("-20.00"),
+            },
+            SplitInput {
+                category_id: household.id(),
+                amount: decimal("-30.00"),
+            },
+        ];
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        let mut input = TransactionInput::new(decimal("-40.00"), "Grocer", date(2024, 6, 3));
+        input.category_id = Some(groceries.id());
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+
+        // Both categories are budgeted in the same plan, and food in another currency
+        let plan = Plan::new(conn, "Merge 2024", user.id()).unwrap();
+        let target_budget = Budget::new(conn, &plan, &budget(&groceries, "300.00", "CAD")).unwrap();
+        Budget::new(conn, &plan, &budget(&food, "100.00", "CAD")).unwrap();
+        Budget::new(conn, &plan, &budget(&food, "50.00", "USD")).unwrap();
+        CategoryAlert::set(conn, &food, &alert("150.00")).unwrap();
+        CategoryAlert::set(conn, &groceries, &alert("250.00")).unwrap();
+
+        let merged = merge_categories(conn, &food, &groceries).unwrap();
+        assert_eq!(
+            merged,
+            CategoryMerge {
+                transactions: 1,
+                transaction_splits: 1,
+                budgets: 2,
+                category_alerts: 1,
+                payee_rules: 0,
+                recurring_transactions: 0,
+            }
+        );
+
+        // The budgets in the same currency were added up, the other one was moved
+        let plan = Plan::from_name(conn, "Merge 2024", user.id()).unwrap();
+        let budgets = Budget::get_all(conn, &plan).unwrap();
+        assert_eq!(budgets.len(), 2);
+        assert!(budgets.iter().all(|b| b.category_id() == groceries.id()));
+        let summed = Budget::from_id(conn, target_budget.id(), &plan).unwrap();
+        assert_eq!(summed.monthly_amount(), decimal("400.00"));
+        assert_eq!(summed.version(), target_budget.version() + 1);
+
+        let alerts = CategoryAlert::get_all(conn, user.id()).unwrap();
+        assert_eq!(alerts.len(), 1);
+        let alert = serde_json::to_value(&alerts[0]).unwrap();
+        assert_eq!(alert["category_id"], groceries.id());
+        assert_eq!(alert["monthly_limit"], "400.00");
+
+        // Nothing refers to the deleted category, and its spending went to the other one
+        assert!(Category::from_id(conn, food.id(), user.id()).is_err());
+        let spent = |conn: &mut DbConn, category: &Category| {
+            Transaction::category_spending(
+                conn,
+                user.id(),
+                category.id(),
+                date(2024, 6, 1),
+                date(2024, 6, 30),
+            )
+            .unwrap()
+        };
+        assert_eq!(spent(conn, &groceries), decimal("90.00"));
+        assert_eq!(spent(conn, &household), decimal("30.00"));
+        assert!(Transaction::get_all(conn, &account)
+            .unwrap()
+            .iter()
+            .all(|t| t.category_id() != Some(food.id())));
+
+        assert!(merge_categories(conn, &groceries, &groceries).is_err());
+    }
+
+    #[test]
+    fn test_income_and_expenses_are_not_merged() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::new(conn, "merge_kinds", "password").unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &decimal("0.00"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let salary = Category::new(conn, user.id(), "Salary").unwrap();
+        let rent = Category::new(conn, user.id(), "Rent").unwrap();
+        let unused = Category::new(conn, user.id(), "Unused").unwrap();
+        for (amount, category) in [("2000.00", &salary), ("-1200.00", &rent)] {
+            let mut input = TransactionInput::new(decimal(amount), "Monthly", date(2024, 6, 1));
+            input.category_id = Some(category.id());
+            Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        }
+
+        assert!(matches!(
+            merge_categories(conn, &salary, &rent),
+            Err(AppError::InvalidInput(_))
+        ));
+        assert!(Category::from_id(conn, salary.id(), user.id()).is_ok());
+
+        // Categories without transactions are neither
+        merge_categories(conn, &unused, &rent).unwrap();
+    }
+}
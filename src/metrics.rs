@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::database::connection::DbPool;
+use crate::reports::cache::ReportCache;
+
+/// Upper bounds of the buckets of the request latency histogram, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Route label of requests that didn't match a route, so unknown paths don't add labels
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// The labels of a series of request metrics
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestLabels {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+/// The requests of a series, with a count per latency bucket
+#[derive(Debug, Default)]
+struct RequestStats {
+    /// Number of requests with a latency up to each bound of `LATENCY_BUCKETS`, not cumulative
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    seconds: f64,
+}
+
+/// Metrics of the server, exposed in the Prometheus text format
+///
+/// Request metrics are recorded by the `track_requests` middleware and login metrics by the login
+/// endpoint, pool metrics are read when the metrics are rendered.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// The token scrapers must send as a bearer token, if any
+    token: Option<String>,
+    requests: Mutex<BTreeMap<RequestLabels, RequestStats>>,
+    logins: AtomicU64,
+    failed_logins: AtomicU64,
+    lockouts: AtomicU64,
+}
+
+impl Metrics {
+    /// Create metrics with nothing recorded
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The bearer token required to read the metrics, `None` to leave them open
+    pub fn new(token: Option<String>) -> Self {
+        Self {
+            token,
+            ..Self::default()
+        }
+    }
+
+    /// Check the bearer token of a scrape
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The bearer token of the request, if any
+    ///
+    /// # Returns
+    ///
+    /// Whether the metrics can be read with the token
+    pub fn authorize(&self, token: Option<&str>) -> bool {
+        match (&self.token, token) {
+            (None, _) => true,
+            (Some(expected), Some(token)) => {
+                constant_time_eq(expected.as_bytes(), token.as_bytes())
+            }
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Record a handled request
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Method of the request
+    /// * `route` - The route the request matched, e.g. `/accounts/:id`, or `UNMATCHED_ROUTE`
+    /// * `status` - Status of the response
+    /// * `latency` - Time taken to respond
+    pub fn record_request(&self, method: &str, route: &str, status: u16, latency: Duration) {
+        let labels = RequestLabels {
+            method: method.to_string(),
+            route: route.to_string(),
+            status,
+        };
+        let seconds = latency.as_secs_f64();
+
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = requests.entry(labels).or_default();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            stats.buckets[bucket] += 1;
+        }
+        stats.count += 1;
+        stats.seconds += seconds;
+    }
+
+    /// Record a successful login
+    pub fn record_login(&self) {
+        self.logins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a refused login
+    pub fn record_failed_login(&self) {
+        self.failed_logins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a user being locked out after too many failed logins
+    pub fn record_lockout(&self) {
+        self.lockouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render the metrics in the Prometheus text format
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The database connection pool, whose connections are reported
+    /// * `reports` - The cache of reports, whose hits and misses are reported
+    ///
+    /// # Returns
+    ///
+    /// The metrics, one sample per line
+    pub fn render(&self, pool: &DbPool, reports: &ReportCache) -> String {
+        let mut out = String::new();
+
+        {
+            let requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+
+            header(
+                &mut out,
+                "http_requests_total",
+                "counter",
+                "Number of HTTP requests handled",
+            );
+            for (labels, stats) in requests.iter() {
+                let _ = writeln!(
+                    out,
+                    "http_requests_total{{{}}} {}",
+                    labels.render(),
+                    stats.count
+                );
+            }
+
+            header(
+                &mut out,
+                "http_request_duration_seconds",
+                "histogram",
+                "Time taken to respond to HTTP requests",
+            );
+            for (labels, stats) in requests.iter() {
+                let labels = labels.render();
+                let mut cumulative = 0;
+                for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+                    cumulative += count;
+                    let _ = writeln!(
+                        out,
+                        "http_request_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                    stats.count
+                );
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_sum{{{labels}}} {}",
+                    stats.seconds
+                );
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_count{{{labels}}} {}",
+                    stats.count
+                );
+            }
+        }
+
+        // The pool of the replica is only reported when one is configured
+        let pools = [
+            ("primary", Some(pool.connections())),
+            ("replica", pool.replica_connections()),
+        ];
+        header(
+            &mut out,
+            "db_pool_connections_in_use",
+            "gauge",
+            "Number of database connections in use",
+        );
+        for (name, connections) in pools {
+            if let Some((connections, idle)) = connections {
+                let _ = writeln!(
+                    out,
+                    "db_pool_connections_in_use{{pool=\"{name}\"}} {}",
+                    connections - idle
+                );
+            }
+        }
+        header(
+            &mut out,
+            "db_pool_connections_idle",
+            "gauge",
+            "Number of open database connections that aren't in use",
+        );
+        for (name, connections) in pools {
+            if let Some((_, idle)) = connections {
+                let _ = writeln!(out, "db_pool_connections_idle{{pool=\"{name}\"}} {idle}");
+            }
+        }
+        header(
+            &mut out,
+            "db_pool_exhausted_total",
+            "counter",
+            "Number of requests answered with 503 as every database connection stayed in use",
+        );
+        for (name, exhausted) in [
+            ("primary", Some(pool.exhausted())),
+            ("replica", pool.replica_exhausted()),
+        ] {
+            if let Some(exhausted) = exhausted {
+                let _ = writeln!(
+                    out,
+                    "db_pool_exhausted_total{{pool=\"{name}\"}} {exhausted}"
+                );
+            }
+        }
+
+        for (name, help, counter) in [
+            (
+                "auth_logins_total",
+                "Number of successful logins",
+                self.logins.load(Ordering::Relaxed),
+            ),
+            (
+                "auth_failed_logins_total",
+                "Number of refused logins",
+                self.failed_logins.load(Ordering::Relaxed),
+            ),
+            (
+                "auth_lockouts_total",
+                "Number of users locked out after failed logins",
+                self.lockouts.load(Ordering::Relaxed),
+            ),
+            (
+                "report_cache_hits_total",
+                "Number of reports served from the cache",
+                reports.hits(),
+            ),
+            (
+                "report_cache_misses_total",
+                "Number of reports computed as they weren't cached",
+                reports.misses(),
+            ),
+        ] {
+            header(&mut out, name, "counter", help);
+            let _ = writeln!(out, "{name} {counter}");
+        }
+
+        out
+    }
+}
+
+impl RequestLabels {
+    /// Render the labels, without the surrounding braces
+    fn render(&self) -> String {
+        format!(
+            "method=\"{}\",route=\"{}\",status=\"{}\"",
+            escape(&self.method),
+            escape(&self.route),
+            self.status
+        )
+    }
+}
+
+/// Write the `HELP` and `TYPE` lines of a metric
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Compare two byte strings in a time that doesn't depend on where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_requests() {
+        let metrics = Metrics::new(None);
+        metrics.record_request("GET", "/accounts", 200, Duration::from_millis(3));
+        metrics.record_request("GET", "/accounts", 200, Duration::from_millis(30));
+        metrics.record_request("GET", "/accounts", 200, Duration::from_secs(20));
+
+        let rendered = metrics.render(&DbPool::new_unreachable(), &ReportCache::default());
+        let labels = "method=\"GET\",route=\"/accounts\",status=\"200\"";
+        for line in [
+            format!("http_requests_total{{{labels}}} 3"),
+            format!("http_request_duration_seconds_bucket{{{labels},le=\"0.005\"}} 1"),
+            format!("http_request_duration_seconds_bucket{{{labels},le=\"0.05\"}} 2"),
+            format!("http_request_duration_seconds_bucket{{{labels},le=\"10\"}} 2"),
+            format!("http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 3"),
+            "db_pool_connections_idle{pool=\"primary\"} 0".to_string(),
+            "db_pool_exhausted_total{pool=\"primary\"} 0".to_string(),
+            "auth_lockouts_total 0".to_string(),
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "{line} in {rendered}");
+        }
+        assert!(!rendered.contains("pool=\"replica\""));
+    }
+
+    #[test]
+    fn test_render_replica_pool() {
+        let pool = DbPool::new_test_shared().with_test_replica();
+        drop(pool.conn_read().unwrap());
+
+        let rendered = Metrics::new(None).render(&pool, &ReportCache::default());
+        for line in [
+            "db_pool_connections_in_use{pool=\"primary\"} 0",
+            "db_pool_connections_in_use{pool=\"replica\"} 0",
+            "db_pool_connections_idle{pool=\"replica\"} 1",
+            "db_pool_exhausted_total{pool=\"replica\"} 0",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "{line} in {rendered}");
+        }
+    }
+
+    #[test]
+    fn test_authorize() {
+        assert!(Metrics::new(None).authorize(None));
+
+        let metrics = Metrics::new(Some("secret".to_string()));
+        assert!(metrics.authorize(Some("secret")));
+        assert!(!metrics.authorize(Some("secrets")));
+        assert!(!metrics.authorize(Some("Secret")));
+        assert!(!metrics.authorize(None));
+    }
+}
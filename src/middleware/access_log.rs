@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    body::HttpBody,
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::metrics::UNMATCHED_ROUTE;
+
+/// Which requests are logged
+#[derive(Debug, Clone)]
+pub struct AccessLogConfig {
+    /// Routes so frequently requested, e.g. by probes, that only some of their requests are logged
+    pub sampled_routes: Vec<String>,
+    /// One of this many successful requests of the sampled routes is logged, failed ones always are
+    pub sample_every: u64,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            sampled_routes: ["/livez", "/readyz", "/metrics"]
+                .map(str::to_owned)
+                .to_vec(),
+            sample_every: 100,
+        }
+    }
+}
+
+/// The user who made a request, set on its response by `jwt_auth` for the access log
+#[derive(Debug, Clone, Copy)]
+pub struct RequestUser {
+    /// ID of the user
+    pub user_id: i32,
+    /// ID of the administrator acting as the user, if any
+    pub impersonator_user_id: Option<i32>,
+}
+
+/// The access log, counting the requests of the sampled routes
+#[derive(Debug)]
+pub struct AccessLog {
+    sample_every: u64,
+    requests: HashMap<String, AtomicU64>,
+}
+
+impl AccessLog {
+    pub fn new(config: AccessLogConfig) -> Self {
+        Self {
+            sample_every: config.sample_every.max(1),
+            requests: config
+                .sampled_routes
+                .into_iter()
+                .map(|route| (route, AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    /// Whether a successful request to a route is logged, the first of each sample is
+    fn is_sampled(&self, route: &str) -> bool {
+        self.requests.get(route).map_or(true, |requests| {
+            requests.fetch_add(1, Ordering::Relaxed) % self.sample_every == 0
+        })
+    }
+}
+
+/// Logs each answered request with its method, route, status, latency, response size and user.
+///
+/// Requests are logged with the route they matched rather than their path, so paths with IDs can
+/// be grouped. Requests answered with a server error are logged as warnings.
+pub async fn access_log(
+    State(log): State<Arc<AccessLog>>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let matched = req.extensions().get::<MatchedPath>().cloned();
+
+    let response = next.run(req).await;
+
+    // Requests to legacy paths are answered by the route they're forwarded to, as kept in the
+    // response
+    let route = matched
+        .as_ref()
+        .or_else(|| response.extensions().get::<MatchedPath>())
+        .map_or(UNMATCHED_ROUTE, |path| path.as_str());
+    let status = response.status();
+    if status.is_success() && !log.is_sampled(route) {
+        return response;
+    }
+
+    let latency_ms = started.elapsed().as_millis() as u64;
+    // Streamed bodies, e.g. exports, don't have a size until they're sent
+    let response_bytes = response.body().size_hint().exact();
+    let user = response.extensions().get::<RequestUser>();
+    let user_id = user.map(|user| user.user_id);
+    let impersonator_user_id = user.and_then(|user| user.impersonator_user_id);
+    if status.is_server_error() {
+        tracing::warn!(
+            method = %method,
+            route,
+            status = status.as_u16(),
+            latency_ms,
+            response_bytes,
+            user_id,
+            impersonator_user_id,
+            "Finished request"
+        );
+    } else {
+        tracing::info!(
+            method = %method,
+            route,
+            status = status.as_u16(),
+            latency_ms,
+            response_bytes,
+            user_id,
+            impersonator_user_id,
+            "Finished request"
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::api::api::HttpConfig;
+    use crate::api::test_utils::TestApp;
+    use crate::config::logging::{self, CapturedLogs, LogFormat};
+    use crate::rate_limit::RateLimitConfig;
+
+    /// Capture the access log of the requests a test makes
+    fn capture() -> (CapturedLogs, tracing::subscriber::DefaultGuard) {
+        let logs = CapturedLogs::default();
+        let guard = tracing::subscriber::set_default(logging::subscriber(
+            LogFormat::Json,
+            logging::filter(Some("finance_fusion_server::middleware::access_log=info")).unwrap(),
+            logs.clone(),
+        ));
+        (logs, guard)
+    }
+
+    #[tokio::test]
+    async fn test_access_log() {
+        let app = TestApp::new();
+        let (logs, _guard) = capture();
+
+        let (status, account) = app
+            .request(
+                Method::POST,
+                "/api/v1/accounts",
+                Some(serde_json::json!({
+                    "name": "Logged",
+                    "opening_balance": "0",
+                    "currency": "USD"
+                })),
+            )
+            .await;
+        assert_eq!(status, 201, "{account}");
+        let uri = format!("/api/v1/accounts/{}/balance", account["id"]);
+        let (status, _) = app.request(Method::GET, &uri, None).await;
+        assert_eq!(status, 200);
+        let (status, _) = app.request(Method::GET, "/accounts/0/balance", None).await;
+        assert_eq!(status, 404);
+
+        // One line per request, with the route rather than the path
+        let lines = logs.json_lines();
+        assert_eq!(lines.len(), 3, "{lines:?}");
+        let created = &lines[0];
+        assert_eq!(created["message"], "Finished request");
+        assert_eq!(created["level"], "INFO");
+        assert_eq!(created["method"], "POST");
+        assert_eq!(created["route"], "/api/v1/accounts");
+        assert_eq!(created["status"], 201);
+        assert!(created["latency_ms"].is_u64());
+        assert!(created["response_bytes"].as_u64().unwrap() > 0);
+        assert_eq!(created["user_id"], app.user_id());
+        for line in &lines[1..] {
+            assert_eq!(line["method"], "GET");
+            assert_eq!(line["route"], "/api/v1/accounts/:id/balance");
+        }
+        assert_eq!(lines[2]["status"], 404);
+
+        // Requests without a session have no user
+        let (status, _, _) = app.download("/api/v1/nowhere").await;
+        assert_eq!(status, 404);
+        let line = logs.json_lines().pop().unwrap();
+        assert_eq!(line["route"], UNMATCHED_ROUTE);
+        assert_eq!(line["user_id"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_sampled_routes() {
+        let app = TestApp::with_config(HttpConfig {
+            rate: RateLimitConfig::disabled(),
+            access_log: AccessLogConfig {
+                sampled_routes: vec!["/livez".to_owned()],
+                sample_every: 3,
+            },
+            ..HttpConfig::default()
+        });
+        let (logs, _guard) = capture();
+
+        for _ in 0..6 {
+            let (status, _, _) = app.download("/livez").await;
+            assert_eq!(status, 200);
+        }
+        let (status, _, _) = app.download("/vitals").await;
+        assert_eq!(status, 200);
+
+        // The first of each 3 requests is logged, other routes are all logged
+        let routes: Vec<Value> = logs
+            .json_lines()
+            .into_iter()
+            .map(|line| line["route"].clone())
+            .collect();
+        assert_eq!(routes, ["/livez", "/livez", "/vitals"]);
+    }
+}
@@ -1,40 +1,92 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Request, State},
+    extract::{OriginalUri, Path, Request, State},
+    http::{HeaderMap, Method},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
+    RequestExt,
 };
+use tracing::Instrument;
 
 use crate::{
-    database::{connection::DbPool, models::sessions::manager::Session},
+    api::state::AppState,
+    audit,
+    database::{
+        connection::DbPool,
+        models::{
+            audit_events::{AuditEventKind, NewAuditEvent},
+            sessions::manager::Session,
+            users::User,
+        },
+    },
     errors::AppError,
+    middleware::{access_log::RequestUser, forwarded::Client},
 };
+
+/// Get the token of the session from the `token` cookie of a request, if it has one
+pub fn token_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("cookie")
+        .and_then(|cookies| cookies.to_str().ok())
+        .and_then(|cookies| cookies.split("; ").find(|c| c.starts_with("token=")))
+        .and_then(|token_cookie| token_cookie.strip_prefix("token="))
+}
+
 /// Authorizes protected routes using JWT tokens.
 pub async fn jwt_auth(
-    State(pool): State<Arc<DbPool>>,
+    State(state): State<AppState>,
     mut req: Request<axum::body::Body>, // Use concrete `axum::body::Body` type
     next: Next,                         // Use `Next` without generics
 ) -> Result<Response, AppError> {
-    let mut conn = pool.get()?;
-
-    // Extract the `token` cookie
-    let token = req
-        .headers()
-        .get("cookie")
-        .and_then(|cookies| cookies.to_str().ok())
-        .and_then(|cookies| cookies.split("; ").find(|c| c.starts_with("token=")))
-        .and_then(|token_cookie| token_cookie.strip_prefix("token="));
+    // No route accepts `OPTIONS`, these requests are answered with the methods of their route
+    if req.method() == Method::OPTIONS {
+        return Ok(next.run(req).await);
+    }
 
-    tracing::info!("token = {token:?}");
+    let token = token_cookie(req.headers());
 
     if let Some(token) = token {
+        // The connection is released before the route runs, so that it isn't held for the
+        // duration of the request
+        let token = token.to_string();
+        let keys = state.jwt_keys.clone();
+        let now = state.clock.now_utc();
+        let ip = req.extensions().get::<Client>().and_then(Client::ip);
+        let method = req.method().to_string();
+        // Routes are nested under the API version, the audit log keeps the path as requested
+        let path = req
+            .extensions()
+            .get::<OriginalUri>()
+            .map_or_else(|| req.uri().path(), |uri| uri.path())
+            .to_string();
+        let session = state
+            .pool
+            .run(move |conn| {
+                let session = Session::from_token(conn, &keys, &token, now);
+                if let Ok(session) = &session {
+                    record_impersonated_request(conn, session, &method, &path, ip);
+                }
+                Ok(session)
+            })
+            .await?;
         // Validate the token (implement your logic here)
-        if let Ok(session) = Session::from_token(&mut conn, token) {
+        if let Ok(session) = session {
             tracing::info!("Token is valid");
             // Add user ID (claims.sub) to request extensions, so that it can be used in the routes later
+            let user = RequestUser {
+                user_id: session.user_id(),
+                impersonator_user_id: session.impersonator_user_id(),
+            };
+            let span = tracing::info_span!(
+                "session",
+                user_id = user.user_id,
+                impersonator_user_id = user.impersonator_user_id
+            );
             req.extensions_mut().insert(session);
-            return Ok(next.run(req).await);
+            let mut response = next.run(req).instrument(span).await;
+            response.extensions_mut().insert(user);
+            return Ok(response);
         }
     }
 
@@ -43,3 +95,136 @@ pub async fn jwt_auth(
         crate::errors::AuthenticateError::InvalidToken,
     ))
 }
+
+/// Record a request made by an administrator acting as a user, if the session is one
+fn record_impersonated_request(
+    conn: &mut crate::database::connection::DbConn,
+    session: &Session,
+    method: &str,
+    path: &str,
+    ip: Option<String>,
+) {
+    let Some(impersonator_user_id) = session.impersonator_user_id() else {
+        return;
+    };
+    audit::record(
+        conn,
+        NewAuditEvent::new(
+            AuditEventKind::ImpersonatedRequest,
+            Some(session.user_id()),
+            serde_json::json!({
+                "impersonator_user_id": impersonator_user_id,
+                "session_id": session.id(),
+                "method": method,
+                "path": path,
+            }),
+        )
+        .with_ip(ip),
+    );
+}
+
+/// Rejects sessions of administrators acting as a user from routes only the user may use, such
+/// as deleting the user or changing their password.
+///
+/// Routes without `jwt_auth` are checked against the session of their cookie, if they have one.
+pub async fn reject_impersonation(
+    State(state): State<AppState>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    if req.method() == Method::OPTIONS {
+        return Ok(next.run(req).await);
+    }
+
+    let impersonator_user_id = match (
+        req.extensions().get::<Session>(),
+        token_cookie(req.headers()),
+    ) {
+        (Some(session), _) => session.impersonator_user_id(),
+        (None, Some(token)) => {
+            let token = token.to_string();
+            let keys = state.jwt_keys.clone();
+            let now = state.clock.now_utc();
+            state
+                .pool
+                .run(move |conn| Ok(Session::from_token(conn, &keys, &token, now).ok()))
+                .await?
+                .and_then(|session| session.impersonator_user_id())
+        }
+        (None, None) => None,
+    };
+    if let Some(impersonator_user_id) = impersonator_user_id {
+        tracing::warn!("Administrator {impersonator_user_id} can't use this route as a user");
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(next.run(req).await)
+}
+
+/// Authorizes routes reserved to administrators.
+///
+/// Must be layered inside `jwt_auth`, which provides the session of the user.
+pub async fn admin_auth(
+    State(pool): State<Arc<DbPool>>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    if req.method() == Method::OPTIONS {
+        return Ok(next.run(req).await);
+    }
+
+    let user_id = req
+        .extensions()
+        .get::<Session>()
+        .map(|session| session.user_id())
+        .ok_or(AppError::Authenticate(
+            crate::errors::AuthenticateError::InvalidToken,
+        ))?;
+
+    let user = pool.run(move |conn| User::from_id(conn, user_id)).await?;
+    if !user.is_admin() {
+        tracing::warn!("User {user_id} is not an administrator");
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(next.run(req).await)
+}
+
+/// Authorizes routes acting on a user by ID, such as `/users/:id`, to that user and to
+/// administrators.
+///
+/// Must be layered inside `jwt_auth`, which provides the session of the user.
+pub async fn user_auth(
+    State(pool): State<Arc<DbPool>>,
+    mut req: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    if req.method() == Method::OPTIONS {
+        return Ok(next.run(req).await);
+    }
+
+    // `OPTIONS` requests are answered without the parameters of the route, so they're only
+    // extracted here
+    let id = match req.extract_parts::<Path<u64>>().await {
+        Ok(Path(id)) => id,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let user_id = req
+        .extensions()
+        .get::<Session>()
+        .map(|session| session.user_id())
+        .ok_or(AppError::Authenticate(
+            crate::errors::AuthenticateError::InvalidToken,
+        ))?;
+
+    if u64::try_from(user_id).ok() != Some(id) {
+        let user = pool.run(move |conn| User::from_id(conn, user_id)).await?;
+        if !user.is_admin() {
+            tracing::warn!("User {user_id} can't act on user {id}");
+            return Err(AppError::Forbidden);
+        }
+    }
+
+    Ok(next.run(req).await)
+}
@@ -0,0 +1,109 @@
+use axum::{
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::errors::AppError;
+
+/// Default limit of request bodies (1 MiB)
+const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+
+/// Default limit of upload request bodies, room for a 10 MiB file and the multipart framing
+const DEFAULT_UPLOAD_BODY_LIMIT: usize = 10 * 1024 * 1024 + 64 * 1024;
+
+/// Limits of the size of request bodies, in bytes
+#[derive(Debug, Clone, Copy)]
+pub struct BodyLimits {
+    /// Limit of the bodies of all routes but uploads
+    pub default: usize,
+    /// Limit of the bodies of transaction imports and attachment uploads
+    pub uploads: usize,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_BODY_LIMIT,
+            uploads: DEFAULT_UPLOAD_BODY_LIMIT,
+        }
+    }
+}
+
+/// Replaces the plain text responses of extractors refusing bodies over the limit with an
+/// `AppError`, so they have the same shape as the other errors of the API.
+pub async fn payload_too_large(req: Request<axum::body::Body>, next: Next) -> Response {
+    let response = next.run(req).await;
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == "application/json");
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return AppError::PayloadTooLarge.into_response();
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+    };
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::api::{api::HttpConfig, test_utils::TestApp};
+    use crate::rate_limit::RateLimitConfig;
+
+    /// A request creating a user, with its JSON body padded to a size
+    fn create_user(size: usize) -> Request<Body> {
+        let body = json!({"name": "padded", "password": "password"}).to_string();
+        Request::builder()
+            .method(Method::POST)
+            .uri("/users")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(format!("{body:<size$}")))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_body_limits() {
+        let app = TestApp::with_config(HttpConfig {
+            rate: RateLimitConfig::disabled(),
+            body: BodyLimits {
+                default: 1024,
+                uploads: 4096,
+            },
+            ..HttpConfig::default()
+        });
+
+        let (status, _, _) = app.send(create_user(1024)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, headers, body) = app.send(create_user(1025)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], 40013);
+        assert_eq!(body["message"], "Request body is too large");
+        assert!(body["request_id"].is_string());
+
+        // Imports take larger bodies
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        let uri = format!("/accounts/{}/transactions/import", account["id"]);
+        let csv = |rows: usize| "2024-01-01,Coffee,-3.50\n".repeat(rows).into_bytes();
+        let (status, summary) = app.upload(&uri, "bank.csv", "text/csv", &csv(150)).await;
+        assert_ne!(status, StatusCode::PAYLOAD_TOO_LARGE, "{summary}");
+        let (status, error) = app.upload(&uri, "bank.csv", "text/csv", &csv(200)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(error["code"], 40013);
+    }
+}
@@ -0,0 +1,195 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+/// Responses with an ETag are specific to the user and must be revalidated before being reused
+pub const CACHE_CONTROL: &str = "private, no-cache";
+
+/// Gives successful responses a strong ETag hashed from their body, and answers requests whose
+/// `If-None-Match` has it with a `304 Not Modified` without a body.
+///
+/// Meant for the `GET` routes of lists that clients poll. Responses that already have an ETag,
+/// e.g. from `Validator`, are left as is.
+pub async fn etag(req: Request<Body>, next: Next) -> Response {
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).cloned();
+    let response = next.run(req).await;
+    if response.status() != StatusCode::OK || response.headers().contains_key(header::ETAG) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Failed reading a response to tag it ({e})");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let hash = Sha256::digest(&bytes);
+    let tag = format!("\"{}\"", hex::encode(&hash[..16]));
+
+    if if_none_match.is_some_and(|values| matches(&values, &tag)) {
+        return not_modified(&tag);
+    }
+    set_headers(&mut parts.headers, &tag);
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// An ETag computed from what a response depends on, without building the response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validator(String);
+
+impl Validator {
+    /// Create a validator, which changes whenever what the response depends on does
+    pub fn new(version: impl std::fmt::Display) -> Self {
+        Self(format!("\"{version}\""))
+    }
+
+    /// Answer with a `304 Not Modified` if the client has the current version
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - The headers of the request
+    pub fn not_modified(&self, headers: &HeaderMap) -> Option<Response> {
+        headers
+            .get(header::IF_NONE_MATCH)
+            .is_some_and(|values| matches(values, &self.0))
+            .then(|| not_modified(&self.0))
+    }
+
+    /// Tag a response built from the current version
+    pub fn tag(&self, response: impl IntoResponse) -> Response {
+        let mut response = response.into_response();
+        set_headers(response.headers_mut(), &self.0);
+        response
+    }
+}
+
+/// Whether an `If-None-Match` header lists an ETag, compared weakly as the header requires
+fn matches(values: &HeaderValue, tag: &str) -> bool {
+    let Ok(values) = values.to_str() else {
+        return false;
+    };
+    let weak = |value: &str| value.trim().trim_start_matches("W/").to_owned();
+    values
+        .split(',')
+        .any(|value| value.trim() == "*" || weak(value) == weak(tag))
+}
+
+/// A `304 Not Modified` response, with the headers the `200` would have had
+fn not_modified(tag: &str) -> Response {
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    set_headers(response.headers_mut(), tag);
+    response
+}
+
+fn set_headers(headers: &mut HeaderMap, tag: &str) {
+    if let Ok(tag) = HeaderValue::from_str(tag) {
+        headers.insert(header::ETAG, tag);
+    }
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, Method, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::test_utils::TestApp;
+
+    /// Get a list as the logged in user, returning its ETag
+    async fn get_tagged(app: &TestApp, uri: &str) -> String {
+        let (status, headers, body) = app.download(uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!body.is_empty());
+        assert_eq!(headers[header::CACHE_CONTROL], CACHE_CONTROL);
+        headers[header::ETAG].to_str().unwrap().to_owned()
+    }
+
+    /// Assert the list wasn't modified since an `If-None-Match` with its ETag, with or without
+    /// compression
+    async fn assert_not_modified(app: &TestApp, uri: &str, if_none_match: &str, tag: &str) {
+        for accept_encoding in ["identity", "gzip"] {
+            let (status, headers, body) = app
+                .download_with(
+                    uri,
+                    &[
+                        (header::IF_NONE_MATCH, if_none_match),
+                        (header::ACCEPT_ENCODING, accept_encoding),
+                    ],
+                )
+                .await;
+            assert_eq!(status, StatusCode::NOT_MODIFIED, "{uri}");
+            assert!(body.is_empty());
+            assert_eq!(headers[header::ETAG], tag);
+            assert_eq!(headers[header::CACHE_CONTROL], CACHE_CONTROL);
+            assert!(headers.get(header::CONTENT_ENCODING).is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accounts_etag() {
+        let app = TestApp::new();
+        let uri = "/api/v1/accounts";
+
+        let tag = get_tagged(&app, uri).await;
+        assert!(tag.starts_with('"') && tag.ends_with('"'), "{tag}");
+        assert_not_modified(&app, uri, &tag, &tag).await;
+        assert_not_modified(&app, uri, &format!("\"other\", W/{tag}"), &tag).await;
+
+        // A mutation changes the ETag
+        let (status, _) = app
+            .request(
+                Method::POST,
+                uri,
+                Some(json!({"name": "Tagged", "opening_balance": "0", "currency": "USD"})),
+            )
+            .await;
+        assert_eq!(status, 201);
+        let (status, headers, _) = app
+            .download_with(uri, &[(header::IF_NONE_MATCH, &tag)])
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(headers[header::ETAG], tag.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_plans_etag() {
+        let app = TestApp::new();
+        let uri = "/api/v1/plans";
+
+        let (status, _) = app
+            .request(Method::POST, "/api/v1/plans/tagged", None)
+            .await;
+        assert_eq!(status, 201);
+        let tag = get_tagged(&app, uri).await;
+        assert_not_modified(&app, uri, &tag, &tag).await;
+
+        // Creating and deleting plans change the ETag
+        let (status, _) = app
+            .request(Method::POST, "/api/v1/plans/tagged-too", None)
+            .await;
+        assert_eq!(status, 201);
+        let created = get_tagged(&app, uri).await;
+        assert_ne!(created, tag);
+        let (status, _) = app
+            .request(Method::DELETE, "/api/v1/plans/tagged-too", None)
+            .await;
+        assert_eq!(status, 200);
+        let deleted = get_tagged(&app, uri).await;
+        assert_ne!(deleted, created);
+        let (status, _, _) = app
+            .download_with(uri, &[(header::IF_NONE_MATCH, &created)])
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
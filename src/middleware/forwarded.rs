@@ -0,0 +1,365 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{uri::Scheme, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+
+/// Entry of the trusted proxies standing for the peers of a Unix domain socket
+const UNIX_PEER: &str = "unix";
+
+/// A block of IP addresses, e.g. `10.0.0.0/8` or `fd00::/8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Whether an address is in the block, IPv4-mapped IPv6 addresses as their IPv4 address
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(addr), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(addr) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(addr), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(addr) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// Parse a block, a single address being a block of its own
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{s}` isn't an IP address or a CIDR block, e.g. `10.0.0.0/8`");
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = IpAddr::from_str(addr)
+            .map_err(|_| invalid())?
+            .to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The reverse proxies whose forwarded headers are trusted
+///
+/// Without any, forwarded headers are ignored, and clients are known by the address they connect
+/// from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    /// The blocks of the addresses of the proxies
+    cidrs: Vec<Cidr>,
+    /// Whether the peers of a Unix domain socket are trusted, which have no address
+    unix: bool,
+}
+
+impl TrustedProxies {
+    /// Parse the trusted proxies, CIDR blocks, single addresses, or `unix` for the peers of a Unix
+    /// domain socket
+    ///
+    /// # Returns
+    ///
+    /// The trusted proxies, or why an entry is invalid
+    pub fn parse<S: AsRef<str>>(entries: &[S]) -> Result<Self, String> {
+        let mut proxies = Self::default();
+        for entry in entries {
+            match entry.as_ref().trim() {
+                UNIX_PEER => proxies.unix = true,
+                entry => proxies.cidrs.push(entry.parse()?),
+            }
+        }
+
+        Ok(proxies)
+    }
+
+    /// Whether a peer is a trusted proxy, `None` being the peer of a Unix domain socket
+    fn trusts(&self, peer: Option<IpAddr>) -> bool {
+        match peer {
+            Some(ip) => self.cidrs.iter().any(|cidr| cidr.contains(ip)),
+            None => self.unix,
+        }
+    }
+}
+
+/// The client that made a request, as told by the trusted proxies it went through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Client {
+    /// The address of the client, unknown for the peers of a Unix domain socket that aren't
+    /// trusted
+    pub ip: Option<IpAddr>,
+    /// Whether the client reached the server over HTTPS
+    pub https: bool,
+}
+
+impl Client {
+    /// Get the client of a request from its peer and its forwarded headers
+    ///
+    /// The forwarded headers are only read when the peer is a trusted proxy. The client is then
+    /// the rightmost hop of `X-Forwarded-For` that isn't a trusted proxy, since the hops left of
+    /// it are added by the client, and can be spoofed. The scheme is the last one in
+    /// `X-Forwarded-Proto`, as set by the peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The address the request was sent from, `None` for a Unix domain socket
+    /// * `https` - Whether the request was sent over the TLS of the server
+    /// * `headers` - The headers of the request
+    /// * `proxies` - The trusted proxies
+    pub fn of_request(
+        peer: Option<IpAddr>,
+        https: bool,
+        headers: &HeaderMap,
+        proxies: &TrustedProxies,
+    ) -> Self {
+        let peer = peer.map(|ip| ip.to_canonical());
+        if !proxies.trusts(peer) {
+            return Self { ip: peer, https };
+        }
+
+        let mut ip = peer;
+        for hop in header_list(headers, "x-forwarded-for").rev() {
+            // Hops that can't be read end the chain, the client is the last trusted hop
+            let Some(hop) = parse_hop(hop) else {
+                break;
+            };
+            ip = Some(hop);
+            if !proxies.trusts(ip) {
+                break;
+            }
+        }
+        let https = match header_list(headers, "x-forwarded-proto").last() {
+            Some(proto) => proto.eq_ignore_ascii_case("https"),
+            None => https,
+        };
+
+        Self { ip, https }
+    }
+
+    /// Get the address of the client, as recorded by audit events
+    pub fn ip(&self) -> Option<String> {
+        self.ip.map(|ip| ip.to_string())
+    }
+}
+
+/// Read the comma separated values of a header, across its lines
+fn header_list<'a>(
+    headers: &'a HeaderMap,
+    name: &'static str,
+) -> impl DoubleEndedIterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Read a hop of `X-Forwarded-For`, an address with or without a port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    IpAddr::from_str(hop)
+        .ok()
+        .or_else(|| SocketAddr::from_str(hop).ok().map(|addr| addr.ip()))
+        .map(|ip| ip.to_canonical())
+}
+
+/// Finds the client of each request, see `Client::of_request`, and adds it to the request
+/// extensions for the rate limits, audit events and session cookies.
+pub async fn forwarded(
+    State(proxies): State<Arc<TrustedProxies>>,
+    mut req: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let https = req.extensions().get::<Scheme>() == Some(&Scheme::HTTPS);
+    let client = Client::of_request(peer, https, req.headers(), &proxies);
+    req.extensions_mut().insert(client);
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn headers(forwarded_for: &[&str], proto: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in forwarded_for {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+        if let Some(proto) = proto {
+            headers.insert("x-forwarded-proto", HeaderValue::from_str(proto).unwrap());
+        }
+        headers
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn test_cidr() {
+        let cidr: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(cidr.contains("10.1.200.3".parse().unwrap()));
+        assert!(!cidr.contains("10.2.0.1".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(!cidr.contains("fd00::1".parse().unwrap()));
+
+        let cidr: Cidr = "fd00:ab::/32".parse().unwrap();
+        assert!(cidr.contains("fd00:ab:ffff::1".parse().unwrap()));
+        assert!(!cidr.contains("fd00:ac::1".parse().unwrap()));
+        assert!(!cidr.contains("10.0.0.1".parse().unwrap()));
+
+        let single: Cidr = "2001:db8::7".parse().unwrap();
+        assert_eq!(single.to_string(), "2001:db8::7/128");
+        assert!(single.contains("2001:db8::7".parse().unwrap()));
+        assert!(!single.contains("2001:db8::8".parse().unwrap()));
+        assert!("0.0.0.0/0"
+            .parse::<Cidr>()
+            .unwrap()
+            .contains("203.0.113.9".parse().unwrap()));
+
+        for invalid in [
+            "10.0.0.0/33",
+            "fd00::/129",
+            "10.0.0/8",
+            "proxy",
+            "10.0.0.0/x",
+        ] {
+            assert!(invalid.parse::<Cidr>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_untrusted_peers_are_not_believed() {
+        let spoofed = headers(&["198.51.100.1"], Some("https"));
+
+        // Without trusted proxies, the headers are ignored
+        let client = Client::of_request(
+            ip("203.0.113.5"),
+            false,
+            &spoofed,
+            &TrustedProxies::default(),
+        );
+        assert_eq!(
+            client,
+            Client {
+                ip: ip("203.0.113.5"),
+                https: false
+            }
+        );
+        let client = Client::of_request(None, false, &spoofed, &TrustedProxies::default());
+        assert_eq!(client.ip, None);
+
+        // Nor are they from peers outside the trusted proxies
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8"]).unwrap();
+        let client = Client::of_request(ip("203.0.113.5"), true, &spoofed, &proxies);
+        assert_eq!(
+            client,
+            Client {
+                ip: ip("203.0.113.5"),
+                https: true
+            }
+        );
+        let client = Client::of_request(None, false, &spoofed, &proxies);
+        assert_eq!(client.ip, None);
+    }
+
+    #[test]
+    fn test_multi_hop_chains() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8", "192.168.1.1"]).unwrap();
+
+        // The client is the rightmost hop that isn't a proxy, hops left of it are its own
+        let chain = headers(
+            &["198.51.100.1, 203.0.113.7", "192.168.1.1"],
+            Some("http, https"),
+        );
+        let client = Client::of_request(ip("10.0.0.2"), false, &chain, &proxies);
+        assert_eq!(
+            client,
+            Client {
+                ip: ip("203.0.113.7"),
+                https: true
+            }
+        );
+
+        // A chain of proxies only is from the leftmost one
+        let chain = headers(&["10.1.1.1, 192.168.1.1"], None);
+        let client = Client::of_request(ip("10.0.0.2"), false, &chain, &proxies);
+        assert_eq!(client.ip, ip("10.1.1.1"));
+
+        // Hops that can't be read end the chain, at the last trusted one
+        let chain = headers(&["203.0.113.7, garbage, 10.3.3.3"], None);
+        let client = Client::of_request(ip("10.0.0.2"), false, &chain, &proxies);
+        assert_eq!(client.ip, ip("10.3.3.3"));
+
+        // Hops with ports are read, and requests without the headers are from the peer
+        let chain = headers(&["203.0.113.7:41000"], Some("http"));
+        let client = Client::of_request(ip("10.0.0.2"), true, &chain, &proxies);
+        assert_eq!(
+            client,
+            Client {
+                ip: ip("203.0.113.7"),
+                https: false
+            }
+        );
+        let client = Client::of_request(ip("10.0.0.2"), false, &HeaderMap::new(), &proxies);
+        assert_eq!(client.ip, ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn test_ipv6_proxies() {
+        let proxies = TrustedProxies::parse(&["fd00::/8", "unix"]).unwrap();
+
+        let chain = headers(&["2001:db8::1, [2001:db8::2]:443, fd12::9"], Some("https"));
+        let client = Client::of_request(ip("fd00::1"), false, &chain, &proxies);
+        assert_eq!(
+            client,
+            Client {
+                ip: ip("2001:db8::2"),
+                https: true
+            }
+        );
+
+        // Peers of a Unix domain socket are trusted as configured
+        let client = Client::of_request(None, false, &chain, &proxies);
+        assert_eq!(client.ip, ip("2001:db8::2"));
+
+        assert!(TrustedProxies::parse(&["fd00::/8", "proxy.internal"]).is_err());
+    }
+}
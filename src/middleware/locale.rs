@@ -0,0 +1,94 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::i18n::Locale;
+
+/// Picks the locale of each request from its `Accept-Language` header, English by default.
+///
+/// The locale is added to the request extensions and is available to error responses through
+/// `Locale::current`, which write their message in it. Responses to errors vary with the header.
+pub async fn locale(mut req: Request<axum::body::Body>, next: Next) -> Response {
+    let locale = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(Locale::negotiate)
+        .unwrap_or_default();
+    req.extensions_mut().insert(locale);
+
+    let mut response = locale.scope(next.run(req)).await;
+    if response.status().is_client_error() || response.status().is_server_error() {
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("accept-language"));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, HeaderMap, Method, Request, StatusCode},
+    };
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+
+    /// Log in, returning the status, headers and JSON body of the response
+    async fn login(
+        app: &TestApp,
+        accept_language: Option<&str>,
+        body: Value,
+    ) -> (StatusCode, HeaderMap, Value) {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/v1/auth/login")
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(value) = accept_language {
+            request = request.header(header::ACCEPT_LANGUAGE, value);
+        }
+        let (status, headers, bytes) = app
+            .send(request.body(Body::from(body.to_string())).unwrap())
+            .await;
+        (status, headers, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_localized() {
+        let app = TestApp::new();
+
+        let wrong_password = json!({"username": "test_user", "password": "wrong"});
+        let (status, _, english) = login(&app, Some("en"), wrong_password.clone()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _, french) = login(&app, Some("fr-CA"), wrong_password).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        assert_eq!(english["message"], "Wrong authentication credentials");
+        assert_eq!(french["message"], "Identifiants de connexion incorrects");
+        assert_eq!(english["code"], french["code"]);
+        assert_eq!(english["error"], "wrong_credentials");
+        assert_eq!(french["error"], "wrong_credentials");
+
+        // Field messages of bodies that can't be read are localized too
+        let missing_password = json!({"username": "test_user"});
+        let (status, headers, body) = login(&app, Some("fr"), missing_password.clone()).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(headers
+            .get_all(header::VARY)
+            .iter()
+            .any(|value| value == "accept-language"));
+        assert_eq!(body["message"], "Champ `password` manquant");
+        assert_eq!(body["details"]["field"], "password");
+
+        // Unknown languages and requests without the header get English
+        for accept_language in [Some("de-DE"), None] {
+            let (_, _, body) = login(&app, accept_language, missing_password.clone()).await;
+            assert_eq!(body["message"], "Missing field `password`");
+        }
+    }
+}
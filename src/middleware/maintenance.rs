@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{errors::AppError, maintenance::Maintenance};
+
+/// Rejects the requests the maintenance mode of the server doesn't answer, with a
+/// `503 Service Unavailable`, the message of the operator and a `Retry-After` header.
+///
+/// Requests are checked by their path, under `API_PREFIX` or at their legacy path, as they
+/// haven't matched a route yet.
+pub async fn maintenance(
+    State(maintenance): State<Arc<Maintenance>>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    maintenance.check(req.method(), req.uri().path())?;
+
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, HeaderMap, Method, Request},
+    };
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+
+    /// Put the application in a mode as an administrator
+    async fn set_mode(app: &TestApp, mode: &str) {
+        let body = json!({
+            "mode": mode,
+            "message": "Migrating the database",
+            "retry_after": 120
+        });
+        let (status, body) = app
+            .request(Method::POST, "/api/v1/admin/maintenance", Some(body))
+            .await;
+        assert_eq!(status, 200, "{body}");
+        assert_eq!(body["mode"], mode);
+    }
+
+    /// Create an account, returning the status of the response
+    async fn create_account(app: &TestApp) -> u16 {
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/api/v1/accounts",
+                Some(json!({"name": "Maintained", "opening_balance": "0", "currency": "USD"})),
+            )
+            .await;
+        status.as_u16()
+    }
+
+    /// Assert a request was rejected with the message of the operator
+    fn assert_rejected(status: u16, headers: &HeaderMap, body: &Value) {
+        assert_eq!(status, 503, "{body}");
+        assert_eq!(body["error"], "maintenance");
+        assert_eq!(body["message"], "Migrating the database");
+        assert_eq!(headers[header::RETRY_AFTER], "120");
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_modes() {
+        let app = TestApp::new();
+        app.make_admin();
+
+        // Read only: reads are answered, writes aren't
+        set_mode(&app, "read_only").await;
+        let (status, _) = app.request(Method::GET, "/api/v1/accounts", None).await;
+        assert_eq!(status, 200);
+        // Requests are rejected before their session is checked
+        let (status, headers, body) = app
+            .send(
+                Request::post("/api/v1/plans/maintained")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_rejected(
+            status.as_u16(),
+            &headers,
+            &serde_json::from_slice(&body).unwrap(),
+        );
+        assert_eq!(create_account(&app).await, 503);
+        let (status, vitals) = app.request(Method::GET, "/vitals", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(vitals["maintenance"], "read_only");
+
+        // Full: only the vitals, metrics, logins and administration routes are answered
+        set_mode(&app, "full").await;
+        let (status, headers, body) = app.download("/api/v1/accounts").await;
+        assert_rejected(
+            status.as_u16(),
+            &headers,
+            &serde_json::from_slice(&body).unwrap(),
+        );
+        let (status, _, _) = app.download("/accounts").await;
+        assert_eq!(status, 503);
+        for uri in [
+            "/vitals",
+            "/livez",
+            "/readyz",
+            "/metrics",
+            "/api/v1/admin/audit",
+        ] {
+            let (status, _, _) = app.download(uri).await;
+            assert_eq!(status, 200, "{uri}");
+        }
+        let (status, vitals) = app.request(Method::GET, "/vitals", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(vitals["maintenance"], "full");
+
+        // Off: everything is answered again
+        set_mode(&app, "off").await;
+        assert_eq!(create_account(&app).await, 201);
+        let (_, vitals) = app.request(Method::GET, "/vitals", None).await;
+        assert_eq!(vitals["maintenance"], "off");
+    }
+
+    #[tokio::test]
+    async fn test_only_administrators_set_the_mode() {
+        let app = TestApp::new();
+
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/api/v1/admin/maintenance",
+                Some(json!({"mode": "full"})),
+            )
+            .await;
+        assert_eq!(status, 403);
+        assert_eq!(create_account(&app).await, 201);
+    }
+}
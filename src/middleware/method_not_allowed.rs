@@ -0,0 +1,181 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Router,
+};
+use tower::ServiceExt;
+
+use crate::{api::legacy::DEPRECATION_HEADER, errors::AppError};
+
+/// Replaces the empty responses of routes to methods they don't accept with an `AppError`, so
+/// they have the same shape as the other errors of the API.
+///
+/// The `Allow` header listing the methods the path accepts is kept. `OPTIONS` requests to a route,
+/// which no route accepts, are answered with a `204 No Content` instead, see `options`.
+pub async fn method_not_allowed(req: Request<axum::body::Body>, next: Next) -> Response {
+    let is_options = req.method() == Method::OPTIONS;
+    let response = next.run(req).await;
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == "application/json");
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED || is_json {
+        return response;
+    }
+    if is_options {
+        let (mut parts, _) = response.into_parts();
+        parts.status = StatusCode::NO_CONTENT;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    // Responses to legacy paths stay marked as deprecated
+    let (parts, _) = response.into_parts();
+    let mut error = AppError::MethodNotAllowed.into_response();
+    for name in [
+        header::ALLOW,
+        header::LINK,
+        HeaderName::from_static(DEPRECATION_HEADER),
+    ] {
+        if let Some(value) = parts.headers.get(&name) {
+            error.headers_mut().insert(name, value.clone());
+        }
+    }
+    error
+}
+
+/// Answers `OPTIONS` requests that aren't CORS preflights with the methods their route accepts, in
+/// the `Allow` header, `OPTIONS` included.
+///
+/// They are sent to the application without the CORS layer, which would answer them as
+/// preflights. The `Allow` header is only complete here, as routes add it once the middleware
+/// they are layered with answered.
+///
+/// # Arguments
+///
+/// * `app` - The application without the CORS layer
+pub async fn options(State(app): State<Router>, req: Request, next: Next) -> Response {
+    let is_preflight = req.headers().contains_key(header::ORIGIN)
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if req.method() != Method::OPTIONS || is_preflight {
+        return next.run(req).await;
+    }
+
+    let mut response = app
+        .oneshot(req)
+        .await
+        .unwrap_or_else(|never| match never {});
+    if response.status() != StatusCode::NO_CONTENT {
+        return response;
+    }
+
+    let mut methods: Vec<&str> = response
+        .headers()
+        .get(header::ALLOW)
+        .and_then(|allow| allow.to_str().ok())
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|method| !method.is_empty())
+        .collect();
+    methods.push(Method::OPTIONS.as_str());
+    if let Ok(allow) = HeaderValue::from_str(&methods.join(", ")) {
+        response.headers_mut().insert(header::ALLOW, allow);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::api::test_utils::TestApp;
+
+    fn request(method: Method, uri: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("x-request-id", "not-allowed-1")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unknown_routes() {
+        let app = TestApp::new();
+
+        // Paths answer methods they don't accept with the methods they do
+        for uri in ["/api/v1/auth/login", "/auth/login"] {
+            let (status, headers, body) = app.send(request(Method::GET, uri)).await;
+            assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED, "{uri}");
+            assert_eq!(headers[header::ALLOW], "POST", "{uri}");
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["code"], 40014);
+            assert_eq!(body["error"], "method_not_allowed");
+            assert_eq!(body["request_id"], "not-allowed-1");
+        }
+        let (_, headers, _) = app.send(request(Method::GET, "/auth/login")).await;
+        assert_eq!(headers[DEPRECATION_HEADER], "true");
+
+        // Unknown paths are not found
+        for uri in ["/nowhere", "/api/v1/nowhere"] {
+            let (status, _, body) = app.send(request(Method::GET, uri)).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["code"], 40003);
+            assert_eq!(body["error"], "not_found");
+            assert_eq!(body["request_id"], "not-allowed-1");
+        }
+
+        // Routes of `GET` answer `HEAD` without a body
+        let (status, _, body) = app.send(request(Method::HEAD, "/vitals")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_options() {
+        let app = TestApp::new();
+
+        // Routes tell which methods they accept, without a session
+        for (uri, allow) in [
+            ("/api/v1/users/1", "PUT, DELETE, OPTIONS"),
+            ("/users/1", "PUT, DELETE, OPTIONS"),
+            ("/api/v1/plans", "GET, HEAD, OPTIONS"),
+            ("/vitals", "GET, HEAD, OPTIONS"),
+        ] {
+            let request = Request::builder()
+                .method(Method::OPTIONS)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let (status, headers, body) = app.send(request).await;
+            assert_eq!(status, StatusCode::NO_CONTENT, "{uri}");
+            assert_eq!(headers[header::ALLOW], allow, "{uri}");
+            assert!(body.is_empty());
+        }
+        let (status, _, _) = app.send(request(Method::OPTIONS, "/api/v1/nowhere")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Preflights of browsers are still answered by the CORS layer
+        let preflight = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api/v1/users/1")
+            .header(header::ORIGIN, "http://localhost:3000")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, _) = app.send(preflight).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers.contains_key(header::ACCESS_CONTROL_ALLOW_METHODS));
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::metrics::{Metrics, UNMATCHED_ROUTE};
+
+/// Records the count and latency of requests, by method, route and status.
+///
+/// Requests are labeled with the route they matched rather than their path, so paths with IDs
+/// don't each get their own series.
+pub async fn track_requests(
+    State(metrics): State<Arc<Metrics>>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let matched = req.extensions().get::<MatchedPath>().cloned();
+
+    let response = next.run(req).await;
+
+    // Requests to legacy paths are answered by the route they're forwarded to, as kept in the
+    // response
+    let route = matched
+        .as_ref()
+        .or_else(|| response.extensions().get::<MatchedPath>())
+        .map_or(UNMATCHED_ROUTE, |path| path.as_str());
+    metrics.record_request(
+        &method,
+        route,
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+    response
+}
@@ -1 +1,13 @@
+pub mod access_log;
 pub mod auth;
+pub mod body_limit;
+pub mod etag;
+pub mod forwarded;
+pub mod locale;
+pub mod maintenance;
+pub mod method_not_allowed;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub mod security_headers;
+pub mod timeout;
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    api::api::API_PREFIX,
+    errors::AppError,
+    middleware::forwarded::Client,
+    rate_limit::{RateLimiter, Scope},
+};
+
+/// Limits the requests of each client, by the IP address of the client.
+///
+/// Every request counts towards the global limit, and requests to routes with stricter limits,
+/// such as logins, also count towards the limit of their route, whether requested under
+/// `API_PREFIX` or at their legacy path. The address is the one of the `Client` of the request,
+/// forwarded by a trusted proxy or else the address of the connection.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    if !limiter.is_enabled() {
+        return Ok(next.run(req).await);
+    }
+
+    let client = req
+        .extensions()
+        .get::<Client>()
+        .and_then(Client::ip)
+        .unwrap_or_default();
+    // Requests to legacy paths match no route until they're forwarded, their path is the route
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(req.uri().path(), |path| path.as_str());
+    let route = Scope::of_route(
+        req.method().as_str(),
+        route.strip_prefix(API_PREFIX).unwrap_or(route),
+    );
+
+    let now = Instant::now();
+    for scope in route.into_iter().chain([Scope::Global]) {
+        if let Err(retry_after) = limiter.check(scope, &client, now) {
+            // Clients are told to wait whole seconds, so they don't retry too early
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            return Err(AppError::RateLimited(seconds));
+        }
+    }
+
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+    };
+    use serde_json::{json, Value};
+
+    use crate::api::{api::HttpConfig, test_utils::TestApp};
+    use crate::middleware::forwarded::TrustedProxies;
+    use crate::rate_limit::{RateLimit, RateLimitConfig};
+
+    fn login(ip: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/auth/login")
+            .header("x-forwarded-for", ip)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({"username": "test", "password": "wrong"}).to_string(),
+            ))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let app = TestApp::with_config(HttpConfig {
+            rate: RateLimitConfig {
+                login: RateLimit {
+                    requests: 2,
+                    per: Duration::from_millis(400),
+                },
+                ..RateLimitConfig::default()
+            },
+            // Requests of the tests come from no address, as they would from a Unix socket
+            trusted_proxies: TrustedProxies::parse(&["unix"]).unwrap(),
+            ..HttpConfig::default()
+        });
+
+        for _ in 0..2 {
+            let (status, _, _) = app.send(login("10.0.0.1")).await;
+            assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+        }
+        let (status, headers, body) = app.send(login("10.0.0.1")).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(headers[header::RETRY_AFTER], "1");
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], 40012);
+        assert_eq!(body["message"], "Too many requests, retry in 1 seconds");
+        assert!(body["request_id"].is_string());
+
+        // Other clients and routes aren't limited
+        let (status, _, _) = app.send(login("10.0.0.2")).await;
+        assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+        let (status, _) = app.request(Method::GET, "/accounts", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // A request is refilled every 200 milliseconds
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let (status, _, _) = app.send(login("10.0.0.1")).await;
+        assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+        let (status, _, _) = app.send(login("10.0.0.1")).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,111 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+/// Header carrying the ID of a request, read from requests and set on responses
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request ID taken from a request, longer ones are replaced by a generated ID
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    /// ID of the request being handled by the current task
+    static CURRENT: RequestId;
+}
+
+/// ID correlating a request with its logs and its response
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Get the ID of the request handled by the current task, if any
+    ///
+    /// Only set within routes wrapped by `request_id`, and not in tasks they spawn.
+    pub fn current() -> Option<String> {
+        CURRENT.try_with(|id| id.0.clone()).ok()
+    }
+}
+
+/// Gives each request an ID, taken from its `X-Request-Id` header or generated.
+///
+/// The ID is added to the request extensions and the tracing span of the request, is available to
+/// error responses through `RequestId::current`, and is echoed in the `X-Request-Id` header of the
+/// response.
+pub async fn request_id(mut req: Request<axum::body::Body>, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid(value))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let span = tracing::info_span!("request", request_id = %id);
+    let mut response = CURRENT
+        .scope(RequestId(id.clone()), next.run(req).instrument(span))
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    response
+}
+
+/// Whether a request ID sent by a client can be used, so it can't flood logs or break headers
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LENGTH && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+    };
+
+    use super::*;
+    use crate::api::test_utils::TestApp;
+
+    fn get(uri: &str, request_id: Option<&str>) -> Request<Body> {
+        let request = Request::builder().method(Method::GET).uri(uri);
+        match request_id {
+            Some(id) => request.header(REQUEST_ID_HEADER, id),
+            None => request,
+        }
+        .body(Body::empty())
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let app = TestApp::new();
+
+        let (status, headers, _) = app.send(get("/livez", Some("abc-123"))).await;
+        assert_eq!(status, 200);
+        assert_eq!(headers[REQUEST_ID_HEADER], "abc-123");
+
+        // A missing or unusable ID is replaced by a generated one
+        for id in [None, Some(""), Some("with space")] {
+            let (_, headers, _) = app.send(get("/livez", id)).await;
+            let generated = headers[REQUEST_ID_HEADER].to_str().unwrap();
+            assert!(uuid::Uuid::parse_str(generated).is_ok(), "{generated}");
+        }
+
+        // Errors carry the ID of their request
+        let (status, headers, body) = app.send(get("/accounts", None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["request_id"],
+            headers[REQUEST_ID_HEADER].to_str().unwrap()
+        );
+        assert_eq!(body["code"], 40005);
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// The Swagger UI, whose page runs inline scripts and styles
+const SWAGGER_UI_PATH: &str = "/swagger-ui";
+
+/// Policy of the responses of the API, which are JSON and never rendered as pages
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str =
+    "default-src 'none'; frame-ancestors 'none'; base-uri 'none'; form-action 'none'";
+
+/// Policy of the Swagger UI, allowing its own inline scripts and styles
+pub const DEFAULT_SWAGGER_UI_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'self' 'unsafe-inline'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; frame-ancestors 'none'; base-uri 'none'; form-action 'none'";
+
+/// `Strict-Transport-Security` of the responses served over HTTPS, for a year
+const STRICT_TRANSPORT_SECURITY: &str = "max-age=31536000; includeSubDomains";
+
+/// Which security headers are set on responses
+#[derive(Debug, Clone)]
+pub struct SecurityHeadersConfig {
+    /// `Content-Security-Policy` of the responses of the API
+    pub content_security_policy: HeaderValue,
+    /// `Content-Security-Policy` of the Swagger UI
+    pub swagger_ui_content_security_policy: HeaderValue,
+    /// Whether `Strict-Transport-Security` is set, when clients reach the server over HTTPS
+    pub strict_transport_security: bool,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            content_security_policy: HeaderValue::from_static(DEFAULT_CONTENT_SECURITY_POLICY),
+            swagger_ui_content_security_policy: HeaderValue::from_static(
+                DEFAULT_SWAGGER_UI_CONTENT_SECURITY_POLICY,
+            ),
+            strict_transport_security: false,
+        }
+    }
+}
+
+/// Sets the security headers on every response, errors included.
+///
+/// Responses aren't sniffed, framed or sent with a referrer, and their content security policy is
+/// the API's, but for the Swagger UI which is relaxed for its inline scripts and styles.
+/// `Strict-Transport-Security` is only set when clients reach the server over HTTPS, as browsers
+/// would otherwise refuse plain HTTP to the host.
+pub async fn security_headers(
+    State(config): State<Arc<SecurityHeadersConfig>>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let policy = if req.uri().path().starts_with(SWAGGER_UI_PATH) {
+        config.swagger_ui_content_security_policy.clone()
+    } else {
+        config.content_security_policy.clone()
+    };
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    headers.insert(
+        header::REFERRER_POLICY,
+        HeaderValue::from_static("no-referrer"),
+    );
+    headers.insert(header::CONTENT_SECURITY_POLICY, policy);
+    if config.strict_transport_security {
+        headers.insert(
+            header::STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_static(STRICT_TRANSPORT_SECURITY),
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{api::HttpConfig, test_utils::TestApp};
+    use crate::rate_limit::RateLimitConfig;
+
+    #[tokio::test]
+    async fn test_security_headers() {
+        let app = TestApp::new();
+
+        // API responses, errors included, get the strict policy
+        for uri in ["/accounts", "/no-such-route"] {
+            let (_, headers, _) = app.download(uri).await;
+            assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+            assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+            assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+            assert_eq!(
+                headers[header::CONTENT_SECURITY_POLICY],
+                DEFAULT_CONTENT_SECURITY_POLICY
+            );
+            assert!(!headers.contains_key(header::STRICT_TRANSPORT_SECURITY));
+        }
+
+        // The Swagger UI can run its inline scripts
+        let (status, headers, _) = app.download("/swagger-ui/").await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            headers[header::CONTENT_SECURITY_POLICY],
+            DEFAULT_SWAGGER_UI_CONTENT_SECURITY_POLICY
+        );
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+    }
+
+    #[tokio::test]
+    async fn test_configured_headers() {
+        let app = TestApp::with_config(HttpConfig {
+            rate: RateLimitConfig::disabled(),
+            security_headers: SecurityHeadersConfig {
+                content_security_policy: HeaderValue::from_static("default-src 'self'"),
+                strict_transport_security: true,
+                ..SecurityHeadersConfig::default()
+            },
+            ..HttpConfig::default()
+        });
+
+        let (_, headers, _) = app.download("/accounts").await;
+        assert_eq!(
+            headers[header::CONTENT_SECURITY_POLICY],
+            "default-src 'self'"
+        );
+        assert_eq!(
+            headers[header::STRICT_TRANSPORT_SECURITY],
+            STRICT_TRANSPORT_SECURITY
+        );
+    }
+}
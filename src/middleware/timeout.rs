@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::errors::AppError;
+
+/// Default time a request has to be answered
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default time a vitals request has to be answered, so health checks fail fast
+const DEFAULT_VITALS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default time a transaction import or attachment upload has to be answered
+const DEFAULT_UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Time requests have to be answered, by route
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    /// Time of all routes but vitals and uploads
+    pub default: Duration,
+    /// Time of the vitals, liveness and readiness routes
+    pub vitals: Duration,
+    /// Time of transaction imports and attachment uploads
+    pub uploads: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_TIMEOUT,
+            vitals: DEFAULT_VITALS_TIMEOUT,
+            uploads: DEFAULT_UPLOAD_TIMEOUT,
+        }
+    }
+}
+
+/// Answers requests that take longer than a duration with `AppError::Timeout`.
+///
+/// Only the time until the response starts is limited, so streamed responses such as exports and
+/// event streams can take longer to send.
+pub async fn timeout(
+    State(duration): State<Duration>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    tokio::time::timeout(duration, next.run(req))
+        .await
+        .map_err(|_| {
+            tracing::warn!("Request timed out after {duration:?}");
+            AppError::Timeout
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::get,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                    "done"
+                }),
+            )
+            .route("/fast", get(|| async { "done" }))
+            .layer(middleware::from_fn_with_state(
+                Duration::from_millis(50),
+                timeout,
+            ));
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(get("/fast")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(get("/slow")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], 5007);
+        assert_eq!(body["message"], "Request timed out");
+    }
+}
@@ -0,0 +1,258 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        accounts::Account, attachments::Attachment, transactions::Transaction, users::User,
+        webhooks::Webhook,
+    },
+};
+use crate::errors::AppError;
+
+/// What a quota limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    /// Transactions on the accounts of the user
+    Transactions,
+    /// Total size of the files attached to the transactions of the user
+    AttachmentBytes,
+    /// Webhooks of the user
+    Webhooks,
+    /// Accounts of the user, archived ones included
+    Accounts,
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Resource::Transactions => "transactions",
+            Resource::AttachmentBytes => "attachment bytes",
+            Resource::Webhooks => "webhooks",
+            Resource::Accounts => "accounts",
+        })
+    }
+}
+
+/// A write that would take a user over a quota, sent as the `details` of the error
+#[derive(Debug, Clone, Serialize, thiserror::Error)]
+#[error("Over the quota of {limit} {resource}, {used} are used")]
+pub struct QuotaExceeded {
+    /// What the quota limits
+    pub resource: Resource,
+    /// The quota
+    pub limit: u64,
+    /// How much the user has used before the write
+    pub used: u64,
+}
+
+/// How much data each user can store, administrators aren't held to it
+#[derive(Debug, Clone, Copy)]
+pub struct Quotas {
+    /// Whether the quotas are enforced at all
+    pub enabled: bool,
+    /// Transactions a user can have across their accounts
+    pub transactions: u64,
+    /// Total size of the files a user can attach, in bytes
+    pub attachment_bytes: u64,
+    /// Webhooks a user can have
+    pub webhooks: u64,
+    /// Accounts a user can have, archived ones included
+    pub accounts: u64,
+}
+
+impl Default for Quotas {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            transactions: 100_000,
+            attachment_bytes: 1024 * 1024 * 1024,
+            webhooks: 20,
+            accounts: 200,
+        }
+    }
+}
+
+impl Quotas {
+    /// Quotas that don't limit anything, for seeding and tests
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    /// Get the limit of a resource, `None` if the quotas aren't enforced
+    pub fn limit(&self, resource: Resource) -> Option<u64> {
+        self.enabled.then_some(match resource {
+            Resource::Transactions => self.transactions,
+            Resource::AttachmentBytes => self.attachment_bytes,
+            Resource::Webhooks => self.webhooks,
+            Resource::Accounts => self.accounts,
+        })
+    }
+
+    /// Ensure a user can add to their use of a resource
+    ///
+    /// The use is counted afresh, so writes are checked against what's stored rather than the
+    /// cached usage report.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `resource` - What the write adds to
+    /// * `adding` - How much the write adds, e.g. the rows it inserts or the size of a file
+    ///
+    /// # Returns
+    ///
+    /// An empty result if the write fits the quota or the user is an administrator, otherwise
+    /// `AppError::QuotaExceeded`
+    pub fn ensure(
+        &self,
+        conn: &mut DbConn,
+        user_id: i32,
+        resource: Resource,
+        adding: u64,
+    ) -> Result<(), AppError> {
+        let Some(limit) = self.limit(resource) else {
+            return Ok(());
+        };
+        let used = used(conn, user_id, resource)?;
+        if used.saturating_add(adding) <= limit || User::from_id(conn, user_id)?.is_admin() {
+            return Ok(());
+        }
+
+        Err(AppError::QuotaExceeded(QuotaExceeded {
+            resource,
+            limit,
+            used,
+        }))
+    }
+}
+
+/// How much of a resource a user uses, against its quota
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ResourceUsage {
+    /// How much is used
+    pub used: u64,
+    /// The quota, `null` if the user isn't held to it
+    pub limit: Option<u64>,
+}
+
+/// How much data a user stores, against their quotas
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Usage {
+    /// Transactions across the accounts of the user
+    pub transactions: ResourceUsage,
+    /// Total size of the files attached to the transactions of the user, in bytes
+    pub attachment_bytes: ResourceUsage,
+    /// Webhooks of the user
+    pub webhooks: ResourceUsage,
+    /// Accounts of the user, archived ones included
+    pub accounts: ResourceUsage,
+}
+
+impl Usage {
+    /// Get how much data a user stores
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user` - The user
+    /// * `quotas` - The quotas of the server, left out for administrators
+    pub fn of_user(conn: &mut DbConn, user: &User, quotas: &Quotas) -> Result<Self, AppError> {
+        let mut usage = |resource| {
+            Ok::<_, AppError>(ResourceUsage {
+                used: used(conn, user.id(), resource)?,
+                limit: quotas.limit(resource).filter(|_| !user.is_admin()),
+            })
+        };
+
+        Ok(Self {
+            transactions: usage(Resource::Transactions)?,
+            attachment_bytes: usage(Resource::AttachmentBytes)?,
+            webhooks: usage(Resource::Webhooks)?,
+            accounts: usage(Resource::Accounts)?,
+        })
+    }
+}
+
+/// Count how much of a resource a user uses
+fn used(conn: &mut DbConn, user_id: i32, resource: Resource) -> Result<u64, AppError> {
+    match resource {
+        Resource::Transactions => Transaction::count_of_user(conn, user_id),
+        Resource::AttachmentBytes => Attachment::total_bytes_of_user(conn, user_id),
+        Resource::Webhooks => Webhook::count_of_user(conn, user_id),
+        Resource::Accounts => Account::count_of_user(conn, user_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{accounts::AccountKind, transactions::TransactionInput},
+    };
+
+    #[test]
+    fn test_quotas() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &"0.00".parse().unwrap(),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let quotas = Quotas {
+            transactions: 2,
+            ..Quotas::default()
+        };
+        let input = TransactionInput::new(
+            "-3.50".parse().unwrap(),
+            "Coffee",
+            chrono::NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+
+        Transaction::new(conn, &account, &input, &quotas).unwrap();
+        // A batch is checked as a whole, so it can't straddle the quota
+        match Transaction::bulk_insert(conn, &account, &[input.clone(), input.clone()], &quotas) {
+            Err(AppError::QuotaExceeded(exceeded)) => {
+                assert_eq!(exceeded.resource, Resource::Transactions);
+                assert_eq!(exceeded.limit, 2);
+                assert_eq!(exceeded.used, 1);
+            }
+            other => panic!("Expected the quota to be exceeded, got {other:?}"),
+        }
+        Transaction::new(conn, &account, &input, &quotas).unwrap();
+        assert!(Transaction::new(conn, &account, &input, &quotas).is_err());
+
+        let usage = Usage::of_user(conn, &user, &quotas).unwrap();
+        assert_eq!(usage.transactions.used, 2);
+        assert_eq!(usage.transactions.limit, Some(2));
+        assert_eq!(usage.attachment_bytes.used, 0);
+        assert_eq!(usage.webhooks.limit, Some(20));
+        assert_eq!(usage.accounts.used, 1);
+        assert_eq!(usage.accounts.limit, Some(200));
+        let usage = Usage::of_user(conn, &user, &Quotas::disabled()).unwrap();
+        assert_eq!(usage.transactions.limit, None);
+
+        // Administrators aren't held to the quotas
+        let admin = user.set_admin(conn, true).unwrap();
+        Transaction::new(conn, &account, &input, &quotas).unwrap();
+        let usage = Usage::of_user(conn, &admin, &quotas).unwrap();
+        assert_eq!(usage.transactions.used, 3);
+        assert_eq!(usage.transactions.limit, None);
+    }
+}
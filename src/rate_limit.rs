@@ -0,0 +1,281 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Number of shards of the buckets, so requests of different clients rarely wait on each other
+const SHARDS: usize = 16;
+
+/// Number of buckets a shard holds before the full ones are dropped
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// How many requests a client can make in a period
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// Requests a client can make at once, refilled evenly over the period
+    pub requests: u32,
+    /// The period over which the requests are refilled
+    pub per: Duration,
+}
+
+impl RateLimit {
+    /// A limit of a number of requests per minute
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            requests,
+            per: Duration::from_secs(60),
+        }
+    }
+
+    /// Time taken to refill one request
+    fn refill_interval(&self) -> Duration {
+        self.per / self.requests.max(1)
+    }
+}
+
+/// The limits of the requests of each client
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    /// Whether requests are limited at all
+    pub enabled: bool,
+    /// Limit of all requests
+    pub global: RateLimit,
+    /// Limit of login attempts
+    pub login: RateLimit,
+    /// Limit of users created
+    pub user_creation: RateLimit,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            global: RateLimit::per_minute(300),
+            login: RateLimit::per_minute(10),
+            user_creation: RateLimit::per_minute(3),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Get the limit of a scope
+    fn limit(&self, scope: Scope) -> RateLimit {
+        match scope {
+            Scope::Global => self.global,
+            Scope::Login => self.login,
+            Scope::UserCreation => self.user_creation,
+        }
+    }
+
+    /// A configuration that doesn't limit requests
+    #[cfg(test)]
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+}
+
+/// What a bucket limits, requests are counted by the global bucket and the bucket of their route
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Global,
+    Login,
+    UserCreation,
+}
+
+impl Scope {
+    /// Get the scope of the route of a request, besides the global one
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Method of the request
+    /// * `route` - The route the request matched, without `API_PREFIX`, e.g. `/auth/login`
+    ///
+    /// # Returns
+    ///
+    /// The scope of the route, if it has stricter limits
+    pub fn of_route(method: &str, route: &str) -> Option<Self> {
+        match (method, route) {
+            ("POST", "/auth/login") => Some(Scope::Login),
+            ("POST", "/users") => Some(Scope::UserCreation),
+            _ => None,
+        }
+    }
+}
+
+/// The requests left to a client in a scope
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Limits the requests of each client with token buckets, within this process
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// The limits, replaced when the configuration is reloaded
+    config: RwLock<RateLimitConfig>,
+    shards: Vec<Mutex<HashMap<(Scope, String), Bucket>>>,
+}
+
+impl RateLimiter {
+    /// Create a limiter with full buckets
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The limits of the requests
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    /// Get the limits of the requests
+    pub fn config(&self) -> RateLimitConfig {
+        *self.config.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replace the limits of the requests
+    ///
+    /// Clients keep the requests they have left, up to the new limits.
+    pub fn set_config(&self, config: RateLimitConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    /// Whether requests are limited at all
+    pub fn is_enabled(&self) -> bool {
+        self.config().enabled
+    }
+
+    /// Take a request from the bucket of a client
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - What the request counts towards
+    /// * `client` - The client making the request, e.g. its IP address
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// `Ok` if the request can be made, otherwise how long until it can
+    pub fn check(&self, scope: Scope, client: &str, now: Instant) -> Result<(), Duration> {
+        let config = self.config();
+        if !config.enabled {
+            return Ok(());
+        }
+        let limit = config.limit(scope);
+        let capacity = f64::from(limit.requests);
+        let refill_interval = limit.refill_interval();
+
+        let key = (scope, client.to_string());
+        let mut shard = self.shards[shard_of(&key)]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if shard.len() >= PRUNE_THRESHOLD && !shard.contains_key(&key) {
+            // Full buckets are the same as missing ones
+            shard.retain(|(scope, _), bucket| {
+                let limit = config.limit(*scope);
+                bucket.tokens + refilled(bucket, limit.refill_interval(), now)
+                    < f64::from(limit.requests)
+            });
+        }
+
+        let bucket = shard.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            refilled_at: now,
+        });
+        bucket.tokens = (bucket.tokens + refilled(bucket, refill_interval, now)).min(capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(refill_interval.mul_f64(1.0 - bucket.tokens))
+        }
+    }
+}
+
+/// Number of tokens refilled in a bucket since it was last refilled
+fn refilled(bucket: &Bucket, refill_interval: Duration, now: Instant) -> f64 {
+    now.saturating_duration_since(bucket.refilled_at)
+        .as_secs_f64()
+        / refill_interval.as_secs_f64()
+}
+
+/// Get the shard of the bucket of a client
+fn shard_of(key: &(Scope, String)) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % SHARDS as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the seconds until a refused request can be made, to the millisecond
+    fn retry_after(result: Result<(), Duration>) -> f64 {
+        (result.unwrap_err().as_secs_f64() * 1000.0).round() / 1000.0
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            login: RateLimit {
+                requests: 2,
+                per: Duration::from_secs(10),
+            },
+            ..RateLimitConfig::default()
+        });
+        let start = Instant::now();
+
+        assert!(limiter.check(Scope::Login, "1.1.1.1", start).is_ok());
+        assert!(limiter.check(Scope::Login, "1.1.1.1", start).is_ok());
+        assert_eq!(
+            retry_after(limiter.check(Scope::Login, "1.1.1.1", start)),
+            5.0
+        );
+        // Other clients and scopes have their own buckets
+        assert!(limiter.check(Scope::Login, "2.2.2.2", start).is_ok());
+        assert!(limiter.check(Scope::Global, "1.1.1.1", start).is_ok());
+
+        // One request is refilled every five seconds
+        let later = start + Duration::from_secs(4);
+        assert_eq!(
+            retry_after(limiter.check(Scope::Login, "1.1.1.1", later)),
+            1.0
+        );
+        let later = start + Duration::from_secs(5);
+        assert!(limiter.check(Scope::Login, "1.1.1.1", later).is_ok());
+        assert!(limiter.check(Scope::Login, "1.1.1.1", later).is_err());
+
+        // Buckets don't refill past their capacity
+        let later = start + Duration::from_secs(60);
+        assert!(limiter.check(Scope::Login, "1.1.1.1", later).is_ok());
+        assert!(limiter.check(Scope::Login, "1.1.1.1", later).is_ok());
+        assert!(limiter.check(Scope::Login, "1.1.1.1", later).is_err());
+
+        // New limits apply to the requests left, which don't exceed them
+        limiter.set_config(RateLimitConfig {
+            login: RateLimit {
+                requests: 1,
+                per: Duration::from_secs(10),
+            },
+            ..RateLimitConfig::default()
+        });
+        assert!(limiter.check(Scope::Login, "2.2.2.2", later).is_ok());
+        assert!(limiter.check(Scope::Login, "2.2.2.2", later).is_err());
+
+        let disabled = RateLimiter::new(RateLimitConfig::disabled());
+        for _ in 0..1000 {
+            assert!(disabled
+                .check(Scope::UserCreation, "1.1.1.1", start)
+                .is_ok());
+        }
+    }
+}
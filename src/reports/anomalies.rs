@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Datelike, Months, NaiveDate};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        categories::Category,
+        transactions::{CategoryMonth, Transaction},
+    },
+};
+use crate::errors::AppError;
+use crate::reports::periods::fiscal_month_range;
+
+/// Number of months before a month its spending is compared to
+pub const HISTORY_MONTHS: u32 = 6;
+
+/// Minimum number of months with spending in a category for it to be compared
+pub const MIN_HISTORY_MONTHS: usize = 3;
+
+/// How far spending must be above the average of a category to be flagged
+#[derive(Debug, Clone)]
+pub struct AnomalyThresholds {
+    /// Number of standard deviations above the average
+    pub std_devs: BigDecimal,
+    /// Ratio to the average, `1.5` flags spending above 150% of the average
+    pub ratio: BigDecimal,
+}
+
+impl Default for AnomalyThresholds {
+    fn default() -> Self {
+        Self {
+            std_devs: BigDecimal::from(2),
+            ratio: BigDecimal::new(15.into(), 1),
+        }
+    }
+}
+
+/// Spending in a category that is unusually high compared to previous months
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Anomaly {
+    /// ID of the category, `null` for uncategorized transactions
+    category_id: Option<i32>,
+    /// Name of the category, `null` for uncategorized transactions
+    category: Option<String>,
+    /// Money that went out in the month, as a decimal string
+    #[schema(value_type = String)]
+    expenses: BigDecimal,
+    /// Average monthly spending in the previous months with spending, as a decimal string
+    #[schema(value_type = String)]
+    average: BigDecimal,
+    /// Standard deviation of the monthly spending in those months, as a decimal string
+    #[schema(value_type = String)]
+    standard_deviation: BigDecimal,
+}
+
+/// Flag the categories whose spending in a month is strongly above their trailing average
+///
+/// Spending is compared to the months with spending in the category among the `HISTORY_MONTHS`
+/// before the month. Categories with fewer than `MIN_HISTORY_MONTHS` such months are skipped.
+///
+/// # Arguments
+///
+/// * `month` - First day of the month to check
+/// * `sums` - Monthly spending by category, for the month and the months before it
+/// * `names` - Names of the categories by ID
+/// * `thresholds` - How far above the average spending is flagged
+///
+/// # Returns
+///
+/// The flagged categories, by name with uncategorized last
+pub fn detect(
+    month: NaiveDate,
+    sums: &[CategoryMonth],
+    names: &HashMap<i32, String>,
+    thresholds: &AnomalyThresholds,
+) -> Vec<Anomaly> {
+    let history_from = month - Months::new(HISTORY_MONTHS);
+    let mut current: BTreeMap<Option<i32>, &BigDecimal> = BTreeMap::new();
+    let mut history: HashMap<Option<i32>, Vec<&BigDecimal>> = HashMap::new();
+    for sum in sums {
+        if sum.month == month {
+            current.insert(sum.category_id, &sum.expenses);
+        } else if sum.month >= history_from && sum.month < month {
+            history
+                .entry(sum.category_id)
+                .or_default()
+                .push(&sum.expenses);
+        }
+    }
+
+    let mut anomalies: Vec<Anomaly> = current
+        .into_iter()
+        .filter_map(|(category_id, expenses)| {
+            let history = history.get(&category_id)?;
+            if history.len() < MIN_HISTORY_MONTHS {
+                return None;
+            }
+
+            let count = BigDecimal::from(history.len() as u64);
+            let average = history.iter().copied().sum::<BigDecimal>() / &count;
+            let variance = history
+                .iter()
+                .map(|expenses| (*expenses - &average).square())
+                .sum::<BigDecimal>()
+                / &count;
+            let standard_deviation = variance.sqrt().unwrap_or_default();
+
+            let above_deviation = standard_deviation > BigDecimal::zero()
+                && *expenses > &average + &thresholds.std_devs * &standard_deviation;
+            let above_ratio = *expenses > &average * &thresholds.ratio;
+            (above_deviation || above_ratio).then(|| Anomaly {
+                category_id,
+                category: category_id.and_then(|id| names.get(&id).cloned()),
+                expenses: expenses.clone(),
+                average: average.round(2),
+                standard_deviation: standard_deviation.round(2),
+            })
+        })
+        .collect();
+    anomalies.sort_by(|a, b| match (&a.category, &b.category) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    anomalies
+}
+
+/// Flag the categories of a user whose spending in a month is strongly above their trailing
+/// average, see `detect`
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `user_id` - User ID
+/// * `year` - Year of the month
+/// * `month` - Month of the year, from 1 to 12
+/// * `start_day` - Day of the month fiscal months start on, see `periods::fiscal_month_range`
+/// * `thresholds` - How far above the average spending is flagged
+///
+/// # Returns
+///
+/// The flagged categories, by name with uncategorized last
+pub fn monthly_anomalies(
+    conn: &mut DbConn,
+    user_id: i32,
+    year: i32,
+    month: u32,
+    start_day: u32,
+    thresholds: &AnomalyThresholds,
+) -> Result<Vec<Anomaly>, AppError> {
+    let (from, to) = fiscal_month_range(year, month, start_day)?;
+    let sums = Transaction::monthly_expenses(
+        conn,
+        user_id,
+        from - Months::new(HISTORY_MONTHS),
+        to,
+        start_day,
+    )?;
+    let names: HashMap<i32, String> = Category::get_all(conn, user_id)?
+        .into_iter()
+        .map(|category| (category.id(), category.name().to_string()))
+        .collect();
+
+    Ok(detect(
+        from.with_day(1).expect("Every month has a first day"),
+        &sums,
+        &names,
+        thresholds,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    const GROCERIES: i32 = 1;
+    const RENT: i32 = 2;
+    const DINING: i32 = 3;
+    const UTILITIES: i32 = 4;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn month(month: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, 1).unwrap()
+    }
+
+    /// Monthly sums of a category, the last one in August and the others in the months before
+    fn history(category_id: Option<i32>, amounts: &[&str]) -> Vec<CategoryMonth> {
+        let first = 9 - amounts.len() as u32;
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(index, amount)| CategoryMonth {
+                month: month(first + index as u32),
+                category_id,
+                expenses: decimal(amount),
+            })
+            .collect()
+    }
+
+    fn flagged(sums: &[CategoryMonth], thresholds: &AnomalyThresholds) -> Vec<Option<i32>> {
+        let names = HashMap::from([
+            (GROCERIES, "Groceries".to_string()),
+            (RENT, "Rent".to_string()),
+            (DINING, "Dining".to_string()),
+            (UTILITIES, "Utilities".to_string()),
+        ]);
+        detect(month(8), sums, &names, thresholds)
+            .iter()
+            .map(|anomaly| anomaly.category_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        let sums = [
+            // Far above a steady history
+            history(
+                Some(GROCERIES),
+                &["400", "420", "380", "410", "390", "400", "600"],
+            ),
+            history(
+                Some(RENT),
+                &["2000", "2000", "2000", "2000", "2000", "2000", "2000"],
+            ),
+            // Not enough history
+            history(Some(DINING), &["100", "150", "500"]),
+            // Within two standard deviations and under 150% of a noisy history
+            history(Some(UTILITIES), &["100", "140", "60", "120", "80", "145"]),
+            // Only the ratio is exceeded, the history is too noisy for the deviation
+            history(None, &["10", "90", "10", "90", "110"]),
+        ]
+        .concat();
+
+        let thresholds = AnomalyThresholds::default();
+        assert_eq!(flagged(&sums, &thresholds), vec![Some(GROCERIES), None]);
+
+        let anomalies = detect(month(8), &sums, &HashMap::new(), &thresholds);
+        let groceries = anomalies
+            .iter()
+            .find(|anomaly| anomaly.category_id == Some(GROCERIES))
+            .unwrap();
+        assert_eq!(groceries.expenses, decimal("600"));
+        assert_eq!(groceries.average, decimal("400"));
+        assert_eq!(groceries.standard_deviation, decimal("12.91"));
+
+        let thresholds = AnomalyThresholds {
+            std_devs: decimal("3"),
+            ratio: decimal("1.4"),
+        };
+        assert_eq!(
+            flagged(&sums, &thresholds),
+            vec![Some(GROCERIES), Some(UTILITIES), None]
+        );
+    }
+
+    #[test]
+    fn test_detect_ignores_older_months() {
+        // January is more than six months before August, so it isn't part of the average
+        let sums = history(
+            Some(GROCERIES),
+            &["1000", "100", "100", "100", "100", "100", "100", "160"],
+        );
+        assert_eq!(
+            flagged(&sums, &AnomalyThresholds::default()),
+            vec![Some(GROCERIES)]
+        );
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+
+use bigdecimal::{BigDecimal, Zero};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{budgets::Budget, plans::Plan, transactions::Transaction},
+};
+use crate::errors::AppError;
+use crate::reports::{
+    currency::CurrencyConverter,
+    periods::{fiscal_month_of, fiscal_month_range},
+};
+use crate::utils::money::Money;
+
+/// How much of a budget was spent in a month
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BudgetStatus {
+    /// ID of the budget
+    budget_id: i32,
+    /// Name of the budget
+    name: String,
+    /// ID of the category the budget is for
+    category_id: i32,
+    /// ISO 4217 code of the currency of the amounts
+    currency: String,
+    /// The amount of the budget for the month, as a decimal string
+    #[schema(value_type = String)]
+    base_budget: BigDecimal,
+    /// What was left of the amounts of the previous months, negative if they were overspent, as a
+    /// decimal string. Always zero for budgets without rollover.
+    #[schema(value_type = String)]
+    rollover_adjustment: BigDecimal,
+    /// The amount available to spend in the month, the base budget with the rollover adjustment,
+    /// as a decimal string
+    #[schema(value_type = String)]
+    effective_budget: BigDecimal,
+    /// The amount spent on the category in the month, net of refunds, as a decimal string
+    #[schema(value_type = String)]
+    actual: BigDecimal,
+    /// The amount left to spend, negative if the budget was exceeded, as a decimal string
+    #[schema(value_type = String)]
+    remaining: BigDecimal,
+}
+
+impl BudgetStatus {
+    /// Get the name of the budget
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the ISO 4217 code of the currency of the amounts
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// Get the amount available to spend in the month
+    pub fn effective_budget(&self) -> &BigDecimal {
+        &self.effective_budget
+    }
+
+    /// Get the amount spent on the category in the month
+    pub fn actual(&self) -> &BigDecimal {
+        &self.actual
+    }
+}
+
+/// Compare the budgets of a plan to what was spent in a fiscal month
+///
+/// Spending is taken from all accounts of the owner of the plan. Split transactions count towards
+/// the categories of their splits instead of their own. Budgets apply to the fiscal month if they
+/// overlap it.
+///
+/// Budgets with rollover add what was left of each month since the one they started in, and take
+/// off what was overspent. What was spent in those months is summed by one query for every
+/// budget.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `plan` - The plan to compare the budgets of
+/// * `year` - Year of the month
+/// * `month` - Month of the year, from 1 to 12
+/// * `start_day` - Day of the month fiscal months start on, see `periods::fiscal_month_range`
+/// * `convert_to` - ISO 4217 code of the currency to convert amounts into, if any, otherwise
+///   spending is added up regardless of its currency. Budgets, and the spending of the months
+///   they roll over from, are converted with the rate of the last day of the month.
+///
+/// # Returns
+///
+/// The status of each budget that applies to the month ordered by name, or
+/// `AppError::MissingExchangeRates` if amounts couldn't be converted
+pub fn budget_vs_actual(
+    conn: &mut DbConn,
+    plan: &Plan,
+    year: i32,
+    month: u32,
+    start_day: u32,
+    convert_to: Option<&str>,
+) -> Result<Vec<BudgetStatus>, AppError> {
+    let (from, to) = fiscal_month_range(year, month, start_day)?;
+    let mut converter = convert_to
+        .map(|currency| CurrencyConverter::load(conn, currency, to))
+        .transpose()?;
+
+    let mut spent: HashMap<i32, BigDecimal> = HashMap::new();
+    for row in Transaction::category_amounts(conn, plan.user_id(), from, to)? {
+        if let Some(category_id) = row.category_id {
+            let amount = match converter.as_mut() {
+                Some(converter) => converter
+                    .convert(&Money::new(row.amount, &row.currency)?, row.occurred_at)
+                    .into_amount(),
+                None => row.amount,
+            };
+            *spent.entry(category_id).or_default() -= amount;
+        }
+    }
+
+    let budgets = Budget::active_between(conn, plan, from, to)?;
+    let histories = Budget::histories(conn, &budgets)?;
+    let history = |budget: &Budget| histories.get(&budget.id()).cloned().unwrap_or_default();
+
+    // Budgets with rollover carry the months from the one they started in to the one before,
+    // each with the amount in effect when it started
+    let month_number = |(year, month): (i32, u32)| year * 12 + month as i32;
+    let mut budgeted_before: HashMap<i32, BigDecimal> = HashMap::new();
+    let mut since = Vec::new();
+    for budget in budgets.iter().filter(|budget| budget.rollover()) {
+        let (first_year, first_month) = fiscal_month_of(budget.start_date(), start_day);
+        let first = month_number((first_year, first_month));
+        if first >= month_number((year, month)) {
+            continue;
+        }
+        let history = history(budget);
+        let mut budgeted = BigDecimal::zero();
+        for number in first..month_number((year, month)) {
+            let (year, month) = ((number - 1).div_euclid(12), (number - 1).rem_euclid(12) + 1);
+            let (month_start, _) = fiscal_month_range(year, month as u32, start_day)?;
+            budgeted += budget.monthly_amount_on(&history, month_start);
+        }
+        budgeted_before.insert(budget.id(), budgeted);
+        since.push((
+            budget,
+            fiscal_month_range(first_year, first_month, start_day)?.0,
+        ));
+    }
+    let mut carried: HashMap<i32, BigDecimal> = HashMap::new();
+    if !since.is_empty() {
+        for row in Budget::amounts_since(conn, plan.user_id(), &since, from)? {
+            let amount = match converter.as_mut() {
+                Some(converter) => converter
+                    .convert(&Money::new(row.amount, &row.currency)?, to)
+                    .into_amount(),
+                None => row.amount,
+            };
+            *carried.entry(row.budget_id).or_default() += amount;
+        }
+    }
+
+    let statuses = budgets
+        .iter()
+        .map(|budget| {
+            let mut convert = |amount: BigDecimal| -> Result<Money, AppError> {
+                let amount = Money::new(amount, budget.currency())?;
+                Ok(match converter.as_mut() {
+                    Some(converter) => converter.convert(&amount, to),
+                    None => amount,
+                })
+            };
+            let base_budget = convert(budget.monthly_amount_on(&history(budget), from))?;
+            // The amounts of the months carried, net of what was spent in them
+            let rollover_adjustment = match budgeted_before.get(&budget.id()) {
+                Some(budgeted) => base_budget.with_amount(
+                    convert(budgeted.clone())?.into_amount()
+                        + carried.get(&budget.id()).cloned().unwrap_or_default(),
+                ),
+                None => base_budget.with_amount(BigDecimal::zero()),
+            };
+            let effective_budget = base_budget.checked_add(&rollover_adjustment)?;
+            let actual = base_budget.with_amount(
+                spent
+                    .get(&budget.category_id())
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+            Ok(BudgetStatus {
+                budget_id: budget.id(),
+                name: budget.name().to_string(),
+                category_id: budget.category_id(),
+                currency: base_budget.currency().to_string(),
+                remaining: effective_budget.checked_sub(&actual)?.into_amount(),
+                base_budget: base_budget.into_amount(),
+                rollover_adjustment: rollover_adjustment.into_amount(),
+                effective_budget: effective_budget.into_amount(),
+                actual: actual.into_amount(),
+            })
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    converter.map(CurrencyConverter::finish).transpose()?;
+    Ok(statuses)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::NaiveDate;
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::{Account, AccountKind},
+            budgets::{BudgetInput, BudgetInterval},
+            categories::Category,
+            transactions::{SplitInput, TransactionInput},
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_budget_vs_actual_uses_splits() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let plan = Plan::new(conn, "Household plan", user.id()).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &decimal("0"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let household = Category::new(conn, user.id(), "Household").unwrap();
+
+        let budget = |category_id, amount, interval| BudgetInput {
+            category_id,
+            name: format!("Budget {category_id}"),
+            amount: decimal(amount),
+            interval,
+            currency: "CAD".to_string(),
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            end_date: None,
+            rollover: false,
+        };
+        Budget::new(
+            conn,
+            &plan,
+            &budget(groceries.id(), "400", BudgetInterval::Monthly),
+        )
+        .unwrap();
+        Budget::new(
+            conn,
+            &plan,
+            &budget(household.id(), "600", BudgetInterval::Yearly),
+        )
+        .unwrap();
+
+        let date = NaiveDate::from_ymd_opt(2024, 7, 5).unwrap();
+        let mut input = TransactionInput::new(decimal("-120.00"), "Grocery store", date);
+        input.category_id = Some(groceries.id());
+        input.splits = vec![
+            SplitInput {
+                category_id: groceries.id(),
+                amount: decimal("-80.00"),
+            },
+            SplitInput {
+                category_id: household.id(),
+                amount: decimal("-40.00"),
+            },
+        ];
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+
+        let statuses = budget_vs_actual(conn, &plan, 2024, 7, 1, None).unwrap();
+        let by_category = |id| {
+            statuses
+                .iter()
+                .find(|status| status.category_id == id)
+                .unwrap()
+        };
+        assert_eq!(by_category(groceries.id()).actual, decimal("80"));
+        assert_eq!(by_category(groceries.id()).remaining, decimal("320"));
+        assert_eq!(by_category(household.id()).effective_budget, decimal("50"));
+        assert_eq!(by_category(household.id()).actual, decimal("40"));
+
+        // Budgets don't apply before they start
+        assert!(budget_vs_actual(conn, &plan, 2023, 12, 1, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_budget_vs_actual_rolls_over() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let plan = Plan::new(conn, "Envelopes", user.id()).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &decimal("0"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let dining = Category::new(conn, user.id(), "Dining").unwrap();
+        let budget = |category_id, rollover| BudgetInput {
+            category_id,
+            name: format!("Budget {category_id}"),
+            amount: decimal("100"),
+            interval: BudgetInterval::Monthly,
+            currency: "CAD".to_string(),
+            // Budgets apply to the whole month they start in
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 10).unwrap(),
+            end_date: None,
+            rollover,
+        };
+        Budget::new(conn, &plan, &budget(groceries.id(), true)).unwrap();
+        Budget::new(conn, &plan, &budget(dining.id(), false)).unwrap();
+
+        let spend = |conn: &mut DbConn, category_id, amount, month, day| {
+            let date = NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+            let mut input = TransactionInput::new(decimal(amount), "Grocery store", date);
+            input.category_id = Some(category_id);
+            Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        };
+        // 40 left in January, 50 overspent in February net of a refund, 70 left in March
+        spend(conn, groceries.id(), "-60.00", 1, 3);
+        spend(conn, groceries.id(), "-160.00", 2, 12);
+        spend(conn, groceries.id(), "10.00", 2, 20);
+        spend(conn, groceries.id(), "-30.00", 3, 8);
+        spend(conn, dining.id(), "-20.00", 1, 20);
+
+        let groceries_in = |conn: &mut DbConn, month| {
+            let statuses = budget_vs_actual(conn, &plan, 2024, month, 1, None).unwrap();
+            let status = statuses
+                .into_iter()
+                .find(|status| status.category_id == groceries.id())
+                .unwrap();
+            (
+                status.base_budget,
+                status.rollover_adjustment,
+                status.effective_budget,
+                status.actual,
+                status.remaining,
+            )
+        };
+        let amounts = |amounts: [&str; 5]| amounts.map(decimal).into();
+
+        // Nothing is carried into the month the budget starts in
+        assert_eq!(
+            groceries_in(conn, 1),
+            amounts(["100", "0", "100", "60", "40"])
+        );
+        assert_eq!(
+            groceries_in(conn, 2),
+            amounts(["100", "40", "140", "150", "-10"])
+        );
+        // February's overspending comes out of March
+        assert_eq!(
+            groceries_in(conn, 3),
+            amounts(["100", "-10", "90", "30", "60"])
+        );
+        assert_eq!(
+            groceries_in(conn, 4),
+            amounts(["100", "60", "160", "0", "160"])
+        );
+
+        // Budgets without rollover only have their base amount
+        let statuses = budget_vs_actual(conn, &plan, 2024, 2, 1, None).unwrap();
+        let dining = statuses
+            .iter()
+            .find(|status| status.category_id == dining.id())
+            .unwrap();
+        assert_eq!(dining.rollover_adjustment, decimal("0"));
+        assert_eq!(dining.effective_budget, decimal("100"));
+    }
+
+    #[test]
+    fn test_budget_vs_actual_uses_the_amount_in_effect() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let plan = Plan::new(conn, "Household plan", user.id()).unwrap();
+        let housing = Category::new(conn, user.id(), "Housing").unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let budget = |category_id, amount, rollover| BudgetInput {
+            category_id,
+            name: format!("Budget {category_id}"),
+            amount: decimal(amount),
+            interval: BudgetInterval::Monthly,
+            currency: "CAD".to_string(),
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            end_date: None,
+            rollover,
+        };
+        let rent = Budget::new(conn, &plan, &budget(housing.id(), "1000", false)).unwrap();
+        let food = Budget::new(conn, &plan, &budget(groceries.id(), "100", true)).unwrap();
+
+        // The rent goes up in July, and groceries in March
+        let rent = rent
+            .update(
+                conn,
+                &plan,
+                &budget(housing.id(), "1200", false),
+                rent.version(),
+                NaiveDate::from_ymd_opt(2024, 7, 1),
+            )
+            .unwrap();
+        assert_eq!(rent.amount(), &decimal("1200"));
+        food.update(
+            conn,
+            &plan,
+            &budget(groceries.id(), "200", true),
+            food.version(),
+            NaiveDate::from_ymd_opt(2024, 3, 1),
+        )
+        .unwrap();
+
+        let status_in = |conn: &mut DbConn, category_id, month, start_day| {
+            budget_vs_actual(conn, &plan, 2024, month, start_day, None)
+                .unwrap()
+                .into_iter()
+                .find(|status| status.category_id == category_id)
+                .map(|status| (status.base_budget, status.rollover_adjustment))
+                .unwrap()
+        };
+
+        // The months before the change keep their amount
+        for (month, amount) in [(1, "1000"), (6, "1000"), (7, "1200"), (12, "1200")] {
+            assert_eq!(
+                status_in(conn, housing.id(), month, 1),
+                (decimal(amount), decimal("0")),
+                "{month}"
+            );
+        }
+        // Fiscal months use the amount in effect on the day they start
+        assert_eq!(status_in(conn, housing.id(), 6, 15).0, decimal("1000"));
+        assert_eq!(status_in(conn, housing.id(), 7, 15).0, decimal("1200"));
+
+        // The months rolled over carry the amount each had
+        assert_eq!(
+            status_in(conn, groceries.id(), 4, 1),
+            (decimal("200"), decimal("400"))
+        );
+        assert_eq!(
+            status_in(conn, groceries.id(), 2, 1),
+            (decimal("100"), decimal("100"))
+        );
+    }
+}
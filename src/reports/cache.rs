@@ -0,0 +1,249 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::errors::AppError;
+
+/// How long a report is served from the cache, by default
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// A computed report, with when it was computed
+struct Entry {
+    computed_at: Instant,
+    report: Arc<dyn Any + Send + Sync>,
+}
+
+/// Caches the reports of users, within this process
+///
+/// Reports are keyed by user and by the report and its parameters, e.g. `monthly` and the query of
+/// the request. A user's reports are invalidated when their transactions or accounts change, and
+/// expire after the TTL regardless, for the changes made outside of requests, e.g. by jobs.
+pub struct ReportCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<(i32, String), Entry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for ReportCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+impl ReportCache {
+    /// Create an empty cache
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long a report is served from the cache after it was computed
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get a report of a user from the cache, or compute and cache it
+    ///
+    /// The report isn't cached if computing it fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - ID of the user the report is of
+    /// * `key` - The report and its parameters
+    /// * `now` - The current time
+    /// * `compute` - Computes the report, when it isn't cached or has expired
+    ///
+    /// # Returns
+    ///
+    /// The report, or the error of `compute`
+    pub fn get_or_compute<T, F>(
+        &self,
+        user_id: i32,
+        key: String,
+        now: Instant,
+        compute: F,
+    ) -> Result<T, AppError>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce() -> Result<T, AppError>,
+    {
+        let key = (user_id, key);
+        if let Some(report) = self.get(&key, now) {
+            if let Some(report) = report.downcast_ref::<T>() {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(report.clone());
+            }
+        }
+
+        // The lock isn't held while computing, a report computed twice at once is cached twice
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let report = compute()?;
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, entry| now.duration_since(entry.computed_at) < self.ttl);
+        entries.insert(
+            key,
+            Entry {
+                computed_at: now,
+                report: Arc::new(report.clone()),
+            },
+        );
+
+        Ok(report)
+    }
+
+    /// Get a report that hasn't expired
+    fn get(&self, key: &(i32, String), now: Instant) -> Option<Arc<dyn Any + Send + Sync>> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(key)
+            .filter(|entry| now.duration_since(entry.computed_at) < self.ttl)
+            .map(|entry| entry.report.clone())
+    }
+
+    /// Forget the reports of a user, when their data changed
+    pub fn invalidate(&self, user_id: i32) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|(entry_user_id, _), _| *entry_user_id != user_id);
+    }
+
+    /// Forget the reports of every user, when data they share changed, e.g. exchange rates
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Get the number of reports served from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of reports that had to be computed
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn test_reports_are_computed_once() {
+        let cache = ReportCache::default();
+        let now = Instant::now();
+        let computed = Cell::new(0);
+        let compute = || {
+            computed.set(computed.get() + 1);
+            Ok(vec![computed.get()])
+        };
+
+        assert_eq!(
+            cache
+                .get_or_compute(1, "monthly".into(), now, compute)
+                .unwrap(),
+            vec![1]
+        );
+        assert_eq!(
+            cache
+                .get_or_compute(1, "monthly".into(), now, compute)
+                .unwrap(),
+            vec![1]
+        );
+        assert_eq!(computed.get(), 1);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // Other parameters and other users have reports of their own
+        assert_eq!(
+            cache
+                .get_or_compute(1, "net-worth".into(), now, compute)
+                .unwrap(),
+            vec![2]
+        );
+        assert_eq!(
+            cache
+                .get_or_compute(2, "monthly".into(), now, compute)
+                .unwrap(),
+            vec![3]
+        );
+
+        // Failures aren't cached
+        let failed = cache
+            .get_or_compute::<Vec<i32>, _>(3, "monthly".into(), now, || Err(AppError::not_found()));
+        assert!(failed.is_err());
+        assert_eq!(
+            cache
+                .get_or_compute(3, "monthly".into(), now, compute)
+                .unwrap(),
+            vec![4]
+        );
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let cache = ReportCache::default();
+        let now = Instant::now();
+        cache
+            .get_or_compute(1, "monthly".into(), now, || Ok(1))
+            .unwrap();
+        cache
+            .get_or_compute(2, "monthly".into(), now, || Ok(2))
+            .unwrap();
+
+        cache.invalidate(1);
+        assert_eq!(
+            cache
+                .get_or_compute(1, "monthly".into(), now, || Ok(3))
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            cache
+                .get_or_compute(2, "monthly".into(), now, || Ok(4))
+                .unwrap(),
+            2
+        );
+
+        cache.clear();
+        assert_eq!(
+            cache
+                .get_or_compute(2, "monthly".into(), now, || Ok(5))
+                .unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_reports_expire() {
+        let cache = ReportCache::new(Duration::from_secs(30));
+        let now = Instant::now();
+        cache
+            .get_or_compute(1, "monthly".into(), now, || Ok(1))
+            .unwrap();
+
+        let later = now + Duration::from_secs(29);
+        assert_eq!(
+            cache
+                .get_or_compute(1, "monthly".into(), later, || Ok(2))
+                .unwrap(),
+            1
+        );
+        let expired = now + Duration::from_secs(30);
+        assert_eq!(
+            cache
+                .get_or_compute(1, "monthly".into(), expired, || Ok(3))
+                .unwrap(),
+            3
+        );
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+}
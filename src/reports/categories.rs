@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Datelike, Months, NaiveDate};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{categories::Category, transactions::Transaction},
+};
+use crate::errors::AppError;
+use crate::reports::periods::month_range;
+
+/// Name of the bucket of transactions without a category
+pub const UNCATEGORIZED: &str = "Uncategorized";
+
+/// The share of a category in the income or expenses of a month
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CategoryShare {
+    /// ID of the category, `null` for uncategorized transactions
+    category_id: Option<i32>,
+    /// Name of the category, `Uncategorized` for transactions without one
+    category: String,
+    /// The amount of the category in the month, net of refunds, as a positive decimal string
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// Percentage of the total of the section, rounded to one decimal, as a decimal string
+    #[schema(value_type = String)]
+    percent: BigDecimal,
+    /// The amount of the category in the previous month, as a decimal string. Zero if the
+    /// category was in the other section or had no transactions.
+    #[schema(value_type = String)]
+    previous_amount: BigDecimal,
+    /// The amount minus the previous amount, as a decimal string
+    #[schema(value_type = String)]
+    delta: BigDecimal,
+}
+
+/// Spending and income of a user in a month, broken down by category
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CategoryBreakdown {
+    /// First day of the month
+    from: NaiveDate,
+    /// Last day of the month
+    to: NaiveDate,
+    /// Total of the expense categories, as a decimal string
+    #[schema(value_type = String)]
+    total_expenses: BigDecimal,
+    /// Total of the income categories, as a decimal string
+    #[schema(value_type = String)]
+    total_income: BigDecimal,
+    /// Categories where more money went out than came in, by amount descending
+    expenses: Vec<CategoryShare>,
+    /// Categories where more money came in than went out, by amount descending
+    income: Vec<CategoryShare>,
+}
+
+/// Break down the spending and income of a user in a month by category
+///
+/// Each category is netted for the month: a category where more money went out than came in is
+/// an expense category and one where more came in is an income category, so refunds reduce the
+/// spending of their category. Splits count towards their own categories, transfers are left
+/// out, and amounts are added up regardless of their currency.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `user_id` - User ID
+/// * `year` - Year of the month
+/// * `month` - Month of the year, from 1 to 12
+///
+/// # Returns
+///
+/// The breakdown of the month, or `AppError::InvalidInput` if the month is invalid
+pub fn category_breakdown(
+    conn: &mut DbConn,
+    user_id: i32,
+    year: i32,
+    month: u32,
+) -> Result<CategoryBreakdown, AppError> {
+    let (from, to) = month_range(year, month)?;
+    let previous = from - Months::new(1);
+    let names: HashMap<i32, String> = Category::get_all(conn, user_id)?
+        .into_iter()
+        .map(|category| (category.id(), category.name().to_string()))
+        .collect();
+
+    let mut current: Vec<(Option<i32>, BigDecimal)> = vec![];
+    let mut previous_net: HashMap<Option<i32>, BigDecimal> = HashMap::new();
+    for row in Transaction::monthly_category_net(conn, user_id, previous, to)? {
+        if row.month == from {
+            current.push((row.category_id, row.net));
+        } else {
+            previous_net.insert(row.category_id, row.net);
+        }
+    }
+
+    let mut expenses = vec![];
+    let mut income = vec![];
+    for (category_id, net) in current {
+        if net.is_zero() {
+            continue;
+        }
+        let is_expense = net < BigDecimal::zero();
+        // The previous amount only counts if the category was in the same section
+        let previous_amount = match previous_net.get(&category_id) {
+            Some(previous) if is_expense && *previous < BigDecimal::zero() => -previous,
+            Some(previous) if !is_expense && *previous > BigDecimal::zero() => previous.clone(),
+            _ => BigDecimal::zero(),
+        };
+        let amount = net.abs();
+        let share = CategoryShare {
+            category_id,
+            category: category_id
+                .and_then(|id| names.get(&id).cloned())
+                .unwrap_or_else(|| UNCATEGORIZED.to_string()),
+            delta: &amount - &previous_amount,
+            percent: BigDecimal::zero(),
+            amount,
+            previous_amount,
+        };
+        if is_expense {
+            expenses.push(share);
+        } else {
+            income.push(share);
+        }
+    }
+
+    let total_expenses = with_percents(&mut expenses);
+    let total_income = with_percents(&mut income);
+    Ok(CategoryBreakdown {
+        from,
+        to,
+        total_expenses,
+        total_income,
+        expenses,
+        income,
+    })
+}
+
+/// Sort the shares of a section by amount descending and fill in their percentages
+///
+/// # Returns
+///
+/// The total of the section
+fn with_percents(shares: &mut [CategoryShare]) -> BigDecimal {
+    shares.sort_by(|a, b| {
+        b.amount
+            .cmp(&a.amount)
+            .then_with(|| a.category.cmp(&b.category))
+    });
+
+    let total: BigDecimal = shares.iter().map(|share| &share.amount).sum();
+    if !total.is_zero() {
+        for share in shares.iter_mut() {
+            share.percent = (&share.amount * BigDecimal::from(100) / &total).round(1);
+        }
+    }
+    total
+}
+
+/// Parse a month in the `YYYY-MM` format
+///
+/// # Returns
+///
+/// The year and month, or `AppError::InvalidInput` if it isn't a valid month
+pub fn parse_month(month: &str) -> Result<(i32, u32), AppError> {
+    NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d")
+        .map(|date| (date.year(), date.month()))
+        .map_err(|_| {
+            AppError::InvalidInput(format!("{month} is not a month in the YYYY-MM format"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::{Account, AccountKind},
+            transactions::TransactionInput,
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_parse_month() {
+        assert_eq!(parse_month("2024-06").unwrap(), (2024, 6));
+        assert!(parse_month("2024-13").is_err());
+        assert!(parse_month("June").is_err());
+    }
+
+    #[test]
+    fn test_category_breakdown() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &decimal("0"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let dining = Category::new(conn, user.id(), "Dining").unwrap();
+        let salary = Category::new(conn, user.id(), "Salary").unwrap();
+
+        let mut add = |amount: &str, category: Option<&Category>, month: u32| {
+            let date = NaiveDate::from_ymd_opt(2024, month, 10).unwrap();
+            let mut input = TransactionInput::new(decimal(amount), "Purchase", date);
+            input.category_id = category.map(Category::id);
+            Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        };
+        add("-50.00", Some(&groceries), 5);
+        add("-150.00", Some(&dining), 5);
+        add("3000.00", Some(&salary), 5);
+
+        // A refund reduces the spending of its category
+        add("-100.00", Some(&groceries), 6);
+        add("-20.00", Some(&groceries), 6);
+        add("20.00", Some(&groceries), 6);
+        add("-100.00", Some(&dining), 6);
+        add("-100.00", None, 6);
+        add("3000.00", Some(&salary), 6);
+
+        let breakdown = category_breakdown(conn, user.id(), 2024, 6).unwrap();
+        assert_eq!(breakdown.total_expenses, decimal("300"));
+        assert_eq!(breakdown.total_income, decimal("3000"));
+
+        // Shares of a third are rounded to one decimal, so they add up to 100 give or take rounding
+        let expenses: Vec<(&str, &BigDecimal, &BigDecimal)> = breakdown
+            .expenses
+            .iter()
+            .map(|share| (share.category.as_str(), &share.amount, &share.percent))
+            .collect();
+        assert_eq!(
+            expenses,
+            vec![
+                ("Dining", &decimal("100"), &decimal("33.3")),
+                ("Groceries", &decimal("100"), &decimal("33.3")),
+                (UNCATEGORIZED, &decimal("100"), &decimal("33.3")),
+            ]
+        );
+        let total: BigDecimal = breakdown.expenses.iter().map(|s| &s.percent).sum();
+        assert!((total - decimal("100")).abs() <= decimal("0.1"));
+
+        // Spending went down on dining and up on groceries
+        assert_eq!(breakdown.expenses[0].previous_amount, decimal("150"));
+        assert!(breakdown.expenses[0].delta < BigDecimal::zero());
+        assert!(breakdown.expenses[1].delta > BigDecimal::zero());
+        assert_eq!(breakdown.expenses[2].previous_amount, decimal("0"));
+        assert_eq!(breakdown.income[0].category, "Salary");
+        assert_eq!(breakdown.income[0].percent, decimal("100"));
+        assert_eq!(breakdown.income[0].delta, decimal("0"));
+
+        let breakdown = category_breakdown(conn, user.id(), 2024, 5).unwrap();
+        let percents: Vec<&BigDecimal> = breakdown.expenses.iter().map(|s| &s.percent).collect();
+        assert_eq!(percents, vec![&decimal("75.0"), &decimal("25.0")]);
+    }
+}
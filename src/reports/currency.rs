@@ -0,0 +1,203 @@
+use std::collections::{BTreeSet, HashMap};
+
+use bigdecimal::{BigDecimal, One};
+use chrono::NaiveDate;
+
+use crate::database::{connection::DbConn, models::exchange_rates::ExchangeRate};
+use crate::errors::AppError;
+use crate::utils::money::Money;
+
+/// Converts amounts into a currency with the stored exchange rates
+///
+/// An amount is converted with the latest rate of its currency on or before the day of the amount.
+/// Rates to the target currency are used as is, rates from it are inverted. Amounts without a rate
+/// convert to zero and their pair is recorded, see `CurrencyConverter::finish`.
+pub struct CurrencyConverter {
+    /// No money in the currency amounts are converted into
+    zero: Money,
+    /// Multipliers into the target currency by currency, ordered by day
+    rates: HashMap<String, Vec<(NaiveDate, BigDecimal)>>,
+    /// Pairs amounts had no rate for, as `BASE/QUOTE`
+    missing: BTreeSet<String>,
+}
+
+impl CurrencyConverter {
+    /// Load the rates needed to convert amounts into a currency
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `target` - ISO 4217 code of the currency to convert into
+    /// * `to` - Last day of the amounts that will be converted
+    ///
+    /// # Returns
+    ///
+    /// The converter
+    pub fn load(conn: &mut DbConn, target: &str, to: NaiveDate) -> Result<Self, AppError> {
+        let mut rates: HashMap<String, Vec<(NaiveDate, BigDecimal)>> = HashMap::new();
+        for rate in ExchangeRate::involving(conn, target, to)? {
+            let (currency, multiplier, inverted) = if rate.quote == target {
+                (rate.base, rate.rate, false)
+            } else {
+                (rate.quote, BigDecimal::one() / rate.rate, true)
+            };
+
+            let days = rates.entry(currency).or_default();
+            match days.last_mut() {
+                // A rate to the target wins over a rate from it on the same day
+                Some((day, _)) if *day == rate.as_of && inverted => {}
+                Some((day, last)) if *day == rate.as_of => *last = multiplier,
+                _ => days.push((rate.as_of, multiplier)),
+            }
+        }
+
+        Ok(Self {
+            zero: Money::zero(target)?,
+            rates,
+            missing: BTreeSet::new(),
+        })
+    }
+
+    /// Get the ISO 4217 code of the currency amounts are converted into
+    pub fn target(&self) -> &str {
+        self.zero.currency()
+    }
+
+    /// Convert an amount, rounded to the minor units of the target currency
+    ///
+    /// # Arguments
+    ///
+    /// * `money` - The amount to convert
+    /// * `on` - The day of the amount
+    ///
+    /// # Returns
+    ///
+    /// The converted amount, or zero if there is no rate for the currency on or before the day
+    pub fn convert(&mut self, money: &Money, on: NaiveDate) -> Money {
+        if money.currency() == self.target() {
+            return money.clone();
+        }
+
+        let multiplier = self.rates.get(money.currency()).and_then(|days| {
+            let index = days.partition_point(|(day, _)| *day <= on);
+            index.checked_sub(1).map(|index| &days[index].1)
+        });
+        match multiplier {
+            Some(multiplier) => self.zero.with_amount(money.amount() * multiplier).round(),
+            None => {
+                self.missing
+                    .insert(format!("{}/{}", money.currency(), self.target()));
+                self.zero.clone()
+            }
+        }
+    }
+
+    /// Check that every amount had a rate
+    ///
+    /// # Returns
+    ///
+    /// The ISO 4217 code of the currency amounts were converted into if every amount had a rate,
+    /// otherwise `AppError::MissingExchangeRates` with the pairs that had no rate
+    pub fn finish(self) -> Result<String, AppError> {
+        if self.missing.is_empty() {
+            Ok(self.zero.currency().to_string())
+        } else {
+            Err(AppError::MissingExchangeRates(
+                self.missing.into_iter().collect(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::connection::DbPool;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn money(amount: &str, currency: &str) -> Money {
+        Money::new(decimal(amount), currency).unwrap()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 7, day).unwrap()
+    }
+
+    #[test]
+    fn test_convert_uses_latest_rate() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let rate = |base: &str, quote: &str, day, rate| ExchangeRate {
+            base: base.to_string(),
+            quote: quote.to_string(),
+            as_of: date(day),
+            rate: decimal(rate),
+        };
+        ExchangeRate::upsert(
+            conn,
+            &[
+                rate("USD", "CAD", 1, "1.3500"),
+                rate("USD", "CAD", 15, "1.3700"),
+                rate("CAD", "EUR", 1, "0.6400"),
+            ],
+        )
+        .unwrap();
+        // Saving a rate of the same pair and day replaces it
+        ExchangeRate::upsert(conn, &[rate("USD", "CAD", 15, "1.3600")]).unwrap();
+
+        let mut converter = CurrencyConverter::load(conn, "CAD", date(31)).unwrap();
+        assert_eq!(
+            converter.convert(&money("-10.00", "CAD"), date(3)),
+            money("-10.00", "CAD")
+        );
+        assert_eq!(
+            converter.convert(&money("-10.00", "USD"), date(14)),
+            money("-13.50", "CAD")
+        );
+        assert_eq!(
+            converter.convert(&money("-10.00", "USD"), date(15)),
+            money("-13.60", "CAD")
+        );
+        // Rates from the target currency are inverted
+        assert_eq!(
+            converter.convert(&money("64.00", "EUR"), date(2)),
+            money("100.00", "CAD")
+        );
+        assert_eq!(converter.finish().unwrap(), "CAD");
+
+        let mut converter = CurrencyConverter::load(conn, "CAD", date(31)).unwrap();
+        converter.convert(&money("5.00", "GBP"), date(2));
+        converter.convert(&money("5.00", "USD"), date(1));
+        converter.convert(&money("5.00", "USD"), date(1));
+        match converter.finish() {
+            Err(AppError::MissingExchangeRates(pairs)) => assert_eq!(pairs, vec!["GBP/CAD"]),
+            other => panic!("Expected missing exchange rates, got {other:?}"),
+        }
+
+        // There is no rate yet on the day before the first one
+        let mut converter = CurrencyConverter::load(conn, "CAD", date(31)).unwrap();
+        converter.convert(
+            &money("5.00", "USD"),
+            NaiveDate::from_ymd_opt(2024, 6, 30).unwrap(),
+        );
+        assert!(converter.finish().is_err());
+
+        // Amounts are rounded to the minor units of the target currency
+        ExchangeRate::upsert(conn, &[rate("USD", "JPY", 1, "157.4350")]).unwrap();
+        let mut converter = CurrencyConverter::load(conn, "JPY", date(31)).unwrap();
+        assert_eq!(
+            converter.convert(&money("10.05", "USD"), date(2)),
+            money("1582", "JPY")
+        );
+        assert_eq!(converter.finish().unwrap(), "JPY");
+    }
+}
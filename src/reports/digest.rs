@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        categories::Category, plans::Plan, recurring_transactions::RecurringTransaction,
+        transactions::Transaction, users::User,
+    },
+};
+use crate::errors::AppError;
+use crate::reports::{budgets::budget_vs_actual, periods::fiscal_month_of};
+
+/// Kind of the notifications holding a weekly digest
+pub const DIGEST_KIND: &str = "digest";
+
+/// Number of categories with the most spending listed in a digest
+const TOP_CATEGORIES: usize = 3;
+
+/// Share of a budget spent from which it is listed in a digest, in percent
+const BUDGET_WARNING_PERCENT: u32 = 80;
+
+/// Number of days after the week whose recurring transactions are listed in a digest
+const UPCOMING_DAYS: i64 = 7;
+
+/// How much was spent on a category
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategorySpending {
+    /// Name of the category, `null` for uncategorized transactions
+    pub category: Option<String>,
+    /// Money that went out, as a positive decimal string
+    pub spent: BigDecimal,
+}
+
+/// How much of a budget was spent in the month
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetUse {
+    /// Name of the plan of the budget
+    pub plan: String,
+    /// Name of the budget
+    pub name: String,
+    /// ISO 4217 code of the currency of the amounts
+    pub currency: String,
+    /// The amount available to spend in the month, as a decimal string
+    pub budgeted: BigDecimal,
+    /// The amount spent in the month so far, as a decimal string
+    pub actual: BigDecimal,
+}
+
+/// A budget listed in a digest, with how much of it was spent
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetWarning {
+    /// The budget
+    #[serde(flatten)]
+    pub budget: BudgetUse,
+    /// Share of the budget spent, in percent rounded down
+    pub percent_used: u32,
+}
+
+/// An occurrence of a recurring transaction due soon
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpcomingTransaction {
+    /// The day the transaction occurs on
+    pub date: NaiveDate,
+    /// Description of the transaction
+    pub description: String,
+    /// Signed amount of the transaction, as a decimal string
+    pub amount: BigDecimal,
+}
+
+/// The report data a digest is assembled from
+#[derive(Debug, Clone)]
+pub struct DigestInput {
+    /// Monday of the week
+    pub week_start: NaiveDate,
+    /// Spending of the week, by category
+    pub spending: Vec<CategorySpending>,
+    /// The budgets of the month of the week
+    pub budgets: Vec<BudgetUse>,
+    /// Occurrences of recurring transactions in the days after the week
+    pub upcoming: Vec<UpcomingTransaction>,
+}
+
+/// Summary of a week of a user, stored as the `data` of a notification
+///
+/// Amounts are added up regardless of their currency.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Digest {
+    /// Monday of the week
+    pub week_start: NaiveDate,
+    /// Sunday of the week
+    pub week_end: NaiveDate,
+    /// Money that went out in the week, as a positive decimal string
+    pub total_spent: BigDecimal,
+    /// The categories with the most spending, most first
+    pub top_categories: Vec<CategorySpending>,
+    /// The budgets of the month with at least 80% spent, most spent first
+    pub budgets: Vec<BudgetWarning>,
+    /// Occurrences of recurring transactions in the 7 days after the week, soonest first
+    pub upcoming: Vec<UpcomingTransaction>,
+}
+
+/// Assemble the digest of a week from its report data
+///
+/// # Arguments
+///
+/// * `input` - The report data of the week
+///
+/// # Returns
+///
+/// The digest
+pub fn assemble(input: DigestInput) -> Digest {
+    let total_spent = input.spending.iter().map(|c| &c.spent).sum();
+
+    let mut top_categories: Vec<CategorySpending> = input
+        .spending
+        .into_iter()
+        .filter(|c| c.spent > BigDecimal::zero())
+        .collect();
+    top_categories.sort_by(|a, b| b.spent.cmp(&a.spent).then(a.category.cmp(&b.category)));
+    top_categories.truncate(TOP_CATEGORIES);
+
+    let mut budgets: Vec<BudgetWarning> = input
+        .budgets
+        .into_iter()
+        .filter(|budget| budget.budgeted > BigDecimal::zero())
+        .filter_map(|budget| {
+            let percent_used = (&budget.actual * BigDecimal::from(100) / &budget.budgeted)
+                .with_scale(0)
+                .to_u32()
+                .unwrap_or(0);
+            (percent_used >= BUDGET_WARNING_PERCENT).then_some(BudgetWarning {
+                budget,
+                percent_used,
+            })
+        })
+        .collect();
+    budgets.sort_by(|a, b| {
+        b.percent_used
+            .cmp(&a.percent_used)
+            .then(a.budget.plan.cmp(&b.budget.plan))
+            .then(a.budget.name.cmp(&b.budget.name))
+    });
+
+    let mut upcoming = input.upcoming;
+    upcoming.sort_by(|a, b| a.date.cmp(&b.date).then(a.description.cmp(&b.description)));
+
+    Digest {
+        week_start: input.week_start,
+        week_end: input.week_start + Duration::days(6),
+        total_spent,
+        top_categories,
+        budgets,
+        upcoming,
+    }
+}
+
+impl Digest {
+    /// Get the title of the notification of the digest
+    pub fn title(&self) -> String {
+        format!("Your week of {}", self.week_start)
+    }
+
+    /// Get the text of the notification of the digest
+    pub fn body(&self) -> String {
+        let mut body = format!(
+            "You spent {:.2} from {} to {}",
+            self.total_spent, self.week_start, self.week_end
+        );
+        if let Some(top) = self.top_categories.first() {
+            body.push_str(&format!(
+                ", most of it on {}",
+                top.category
+                    .as_deref()
+                    .unwrap_or("uncategorized transactions")
+            ));
+        }
+        body.push_str(". ");
+        body.push_str(&match self.budgets.len() {
+            0 => "No budget is over 80%".to_string(),
+            1 => "1 budget is over 80%".to_string(),
+            n => format!("{n} budgets are over 80%"),
+        });
+        body.push_str(&match self.upcoming.len() {
+            0 => " and no recurring transaction is due this week.".to_string(),
+            1 => " and 1 recurring transaction is due this week.".to_string(),
+            n => format!(" and {n} recurring transactions are due this week."),
+        });
+        body
+    }
+}
+
+impl DigestInput {
+    /// Load the report data of a week of a user
+    ///
+    /// Spending is taken from the same rows as the monthly summary, so split transactions count
+    /// towards the categories of their splits and transfers are left out. Budgets are compared to
+    /// the spending of the fiscal month of the user the week ends in.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `week_start` - Monday of the week
+    ///
+    /// # Returns
+    ///
+    /// The report data of the week
+    pub fn load(conn: &mut DbConn, user_id: i32, week_start: NaiveDate) -> Result<Self, AppError> {
+        let week_end = week_start + Duration::days(6);
+
+        let names: HashMap<i32, String> = Category::get_all(conn, user_id)?
+            .into_iter()
+            .map(|category| (category.id(), category.name().to_string()))
+            .collect();
+        let mut spent: HashMap<Option<i32>, BigDecimal> = HashMap::new();
+        for row in Transaction::category_amounts(conn, user_id, week_start, week_end)? {
+            if row.amount < BigDecimal::zero() {
+                *spent.entry(row.category_id).or_default() -= row.amount;
+            }
+        }
+        let spending = spent
+            .into_iter()
+            .map(|(category_id, spent)| CategorySpending {
+                category: category_id.and_then(|id| names.get(&id).cloned()),
+                spent,
+            })
+            .collect();
+
+        let start_day = User::from_id(conn, user_id)?.fiscal_month_start_day();
+        let (year, month) = fiscal_month_of(week_end, start_day);
+        let mut budgets = vec![];
+        for plan in Plan::get_all(conn, user_id)? {
+            for status in budget_vs_actual(conn, &plan, year, month, start_day, None)? {
+                budgets.push(BudgetUse {
+                    plan: plan.name().to_string(),
+                    name: status.name().to_string(),
+                    currency: status.currency().to_string(),
+                    budgeted: status.effective_budget().clone(),
+                    actual: status.actual().clone(),
+                });
+            }
+        }
+
+        let (from, to) = (
+            week_end + Duration::days(1),
+            week_end + Duration::days(UPCOMING_DAYS),
+        );
+        let mut upcoming = vec![];
+        for recurring in RecurringTransaction::active_of_user(conn, user_id)? {
+            for date in recurring.occurrences_between(from, to)? {
+                upcoming.push(UpcomingTransaction {
+                    date,
+                    description: recurring.description().to_string(),
+                    amount: recurring.amount().clone(),
+                });
+            }
+        }
+
+        Ok(Self {
+            week_start,
+            spending,
+            budgets,
+            upcoming,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn spending(category: Option<&str>, spent: &str) -> CategorySpending {
+        CategorySpending {
+            category: category.map(str::to_string),
+            spent: decimal(spent),
+        }
+    }
+
+    fn budget(name: &str, budgeted: &str, actual: &str) -> BudgetUse {
+        BudgetUse {
+            plan: "Household".to_string(),
+            name: name.to_string(),
+            currency: "CAD".to_string(),
+            budgeted: decimal(budgeted),
+            actual: decimal(actual),
+        }
+    }
+
+    #[test]
+    fn test_assemble() {
+        let digest = assemble(DigestInput {
+            week_start: date(2024, 6, 3),
+            spending: vec![
+                spending(Some("Transit"), "12.00"),
+                spending(None, "30.00"),
+                spending(Some("Groceries"), "95.40"),
+                spending(Some("Dining"), "30.00"),
+                spending(Some("Gifts"), "0.00"),
+            ],
+            budgets: vec![
+                budget("Groceries", "400.00", "319.99"),
+                budget("Dining", "100.00", "80.00"),
+                budget("Transit", "50.00", "65.00"),
+                budget("Unfunded", "0.00", "10.00"),
+            ],
+            upcoming: vec![
+                UpcomingTransaction {
+                    date: date(2024, 6, 14),
+                    description: "Phone".to_string(),
+                    amount: decimal("-45.00"),
+                },
+                UpcomingTransaction {
+                    date: date(2024, 6, 10),
+                    description: "Rent".to_string(),
+                    amount: decimal("-1200.00"),
+                },
+            ],
+        });
+
+        assert_eq!(
+            serde_json::to_value(&digest).unwrap(),
+            json!({
+                "week_start": "2024-06-03",
+                "week_end": "2024-06-09",
+                "total_spent": "167.40",
+                "top_categories": [
+                    {"category": "Groceries", "spent": "95.40"},
+                    // Ties are broken by name, uncategorized first
+                    {"category": null, "spent": "30.00"},
+                    {"category": "Dining", "spent": "30.00"},
+                ],
+                "budgets": [
+                    {
+                        "plan": "Household",
+                        "name": "Transit",
+                        "currency": "CAD",
+                        "budgeted": "50.00",
+                        "actual": "65.00",
+                        "percent_used": 130,
+                    },
+                    {
+                        "plan": "Household",
+                        "name": "Dining",
+                        "currency": "CAD",
+                        "budgeted": "100.00",
+                        "actual": "80.00",
+                        "percent_used": 80,
+                    },
+                ],
+                "upcoming": [
+                    {"date": "2024-06-10", "description": "Rent", "amount": "-1200.00"},
+                    {"date": "2024-06-14", "description": "Phone", "amount": "-45.00"},
+                ],
+            })
+        );
+        assert_eq!(digest.title(), "Your week of 2024-06-03");
+        assert_eq!(
+            digest.body(),
+            "You spent 167.40 from 2024-06-03 to 2024-06-09, most of it on Groceries. \
+             2 budgets are over 80% and 2 recurring transactions are due this week."
+        );
+    }
+
+    #[test]
+    fn test_assemble_quiet_week() {
+        let digest = assemble(DigestInput {
+            week_start: date(2024, 12, 30),
+            spending: vec![],
+            budgets: vec![],
+            upcoming: vec![],
+        });
+        assert_eq!(digest.week_end, date(2025, 1, 5));
+        assert_eq!(digest.total_spent, BigDecimal::zero());
+        assert_eq!(
+            digest.body(),
+            "You spent 0.00 from 2024-12-30 to 2025-01-05. No budget is over 80% and no \
+             recurring transaction is due this week."
+        );
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{
+        accounts::{Account, AccountKind},
+        budgets::Budget,
+        plans::Plan,
+        recurring_transactions::{RecurringTransaction, Schedule},
+        transactions::Transaction,
+    },
+};
+use crate::errors::AppError;
+use crate::reports::periods::month_range;
+
+/// Maximum number of months a forecast can cover
+pub const MAX_FORECAST_MONTHS: u32 = 24;
+
+/// The current balance of an account
+#[derive(Debug, Clone)]
+pub struct ForecastAccount {
+    /// Account ID
+    pub account_id: i32,
+    /// Whether the balance of the account is owned or owed
+    pub kind: AccountKind,
+    /// Current balance
+    pub balance: BigDecimal,
+}
+
+/// A transaction expected on a schedule
+#[derive(Debug, Clone)]
+pub struct ScheduledTransaction {
+    /// ID of the account the transaction occurs on
+    pub account_id: i32,
+    /// ID of the category of the transaction, if any
+    pub category_id: Option<i32>,
+    /// Signed amount of each occurrence
+    pub amount: BigDecimal,
+    /// When the transaction occurs
+    pub schedule: Schedule,
+    /// The next occurrence, occurrences before today are expected today
+    pub next_on: NaiveDate,
+    /// The last day an occurrence can be on, if any
+    pub end_on: Option<NaiveDate>,
+}
+
+/// An amount budgeted to be spent on a category each month
+#[derive(Debug, Clone)]
+pub struct BudgetedSpending {
+    /// ID of the category
+    pub category_id: i32,
+    /// The amount available to spend each month
+    pub monthly_amount: BigDecimal,
+    /// The amount already spent on the category in the current month, net of refunds
+    pub spent: BigDecimal,
+    /// The first day the budget applies to
+    pub start_date: NaiveDate,
+    /// The last day the budget applies to, if it ends
+    pub end_date: Option<NaiveDate>,
+}
+
+/// Everything a forecast is projected from
+#[derive(Debug, Clone)]
+pub struct ForecastInput {
+    /// The current date, the forecast covers the rest of its month and the following months
+    pub today: NaiveDate,
+    /// Number of months covered, including the current one
+    pub months: u32,
+    /// The accounts to project the balances of
+    pub accounts: Vec<ForecastAccount>,
+    /// Transactions expected on a schedule
+    pub scheduled: Vec<ScheduledTransaction>,
+    /// Spending expected on budgeted categories
+    pub budgets: Vec<BudgetedSpending>,
+    /// ID of the account budgeted spending is taken from, if any
+    pub spending_account_id: Option<i32>,
+}
+
+/// The projected balance of an account at the end of a month
+#[derive(Debug, Serialize, ToSchema, PartialEq)]
+pub struct AccountProjection {
+    /// Account ID
+    account_id: i32,
+    /// Balance at the end of the month, as a decimal string
+    #[schema(value_type = String)]
+    ending_balance: BigDecimal,
+    /// Whether the balance of this asset account is projected to be negative during the month
+    goes_negative: bool,
+}
+
+/// The projected cash flow of a month
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ForecastMonth {
+    /// First day of the month
+    month: NaiveDate,
+    /// Projected income minus spending across all accounts, as a decimal string
+    #[schema(value_type = String)]
+    net: BigDecimal,
+    /// The projection of each account, ordered by account ID
+    accounts: Vec<AccountProjection>,
+    /// Whether any asset account is projected to be negative during the month
+    goes_negative: bool,
+}
+
+/// Project the balances of accounts over the coming months
+///
+/// Scheduled transactions are applied on the day they occur. Budgeted spending that isn't already
+/// covered by scheduled transactions in the same category, or by what was spent so far in the
+/// current month, is taken from the spending account on the last day of each month.
+///
+/// # Arguments
+///
+/// * `input` - The balances, scheduled transactions and budgets to project from
+///
+/// # Returns
+///
+/// A projection per month, starting with the current month
+pub fn forecast(input: &ForecastInput) -> Vec<ForecastMonth> {
+    let first_month = input
+        .today
+        .with_day(1)
+        .expect("Every month has a first day");
+    let mut balances: BTreeMap<i32, (AccountKind, BigDecimal)> = input
+        .accounts
+        .iter()
+        .map(|account| (account.account_id, (account.kind, account.balance.clone())))
+        .collect();
+
+    (0..input.months)
+        .map(|index| {
+            let from = first_month + Months::new(index);
+            let to = from + Months::new(1) - Duration::days(1);
+
+            // Changes of balances in the month, as (day, account ID, amount)
+            let mut changes: Vec<(NaiveDate, i32, BigDecimal)> = Vec::new();
+            let mut scheduled_spending: HashMap<i32, BigDecimal> = HashMap::new();
+            for scheduled in &input.scheduled {
+                let mut on = scheduled.next_on;
+                while on <= to && scheduled.end_on.map_or(true, |end_on| on <= end_on) {
+                    let day = on.max(input.today);
+                    if day >= from {
+                        changes.push((day, scheduled.account_id, scheduled.amount.clone()));
+                        match scheduled.category_id {
+                            Some(category_id) if scheduled.amount < BigDecimal::zero() => {
+                                *scheduled_spending.entry(category_id).or_default() -=
+                                    &scheduled.amount;
+                            }
+                            _ => {}
+                        }
+                    }
+                    on = scheduled.schedule.next_after(on);
+                }
+            }
+
+            if let Some(account_id) = input.spending_account_id {
+                for budget in &input.budgets {
+                    if budget.start_date > to || budget.end_date.is_some_and(|end| end < from) {
+                        continue;
+                    }
+                    let mut planned = &budget.monthly_amount
+                        - scheduled_spending
+                            .get(&budget.category_id)
+                            .cloned()
+                            .unwrap_or_default();
+                    if index == 0 {
+                        planned -= &budget.spent;
+                    }
+                    if planned > BigDecimal::zero() {
+                        changes.push((to, account_id, -planned));
+                    }
+                }
+            }
+
+            // Sorting is stable, so budgeted spending comes after the last day's transactions
+            changes.sort_by_key(|(day, _, _)| *day);
+            let mut negative: HashSet<i32> = HashSet::new();
+            let mut net = BigDecimal::zero();
+            for (_, account_id, amount) in changes {
+                if let Some((kind, balance)) = balances.get_mut(&account_id) {
+                    *balance += &amount;
+                    if *kind == AccountKind::Asset && *balance < BigDecimal::zero() {
+                        negative.insert(account_id);
+                    }
+                    net += amount;
+                }
+            }
+
+            let accounts: Vec<AccountProjection> = balances
+                .iter()
+                .map(|(account_id, (_, balance))| AccountProjection {
+                    account_id: *account_id,
+                    ending_balance: balance.clone(),
+                    goes_negative: negative.contains(account_id),
+                })
+                .collect();
+            ForecastMonth {
+                month: from,
+                net,
+                goes_negative: accounts.iter().any(|account| account.goes_negative),
+                accounts,
+            }
+        })
+        .collect()
+}
+
+impl ForecastInput {
+    /// Load what the forecast of a user is projected from
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `plan` - The plan whose budgets are expected to be spent, if any
+    /// * `spending_account_id` - ID of the account budgeted spending is taken from, defaults to
+    ///   the first open asset account of the user
+    /// * `today` - The current date
+    /// * `months` - Number of months covered, including the current one
+    ///
+    /// # Returns
+    ///
+    /// The input of the forecast, `AppError::InvalidInput` if the number of months is out of
+    /// range, `AppError::NotFound` if the spending account doesn't belong to the user, or
+    /// `AppError::Conflict` if it is archived
+    pub fn load(
+        conn: &mut DbConn,
+        user_id: i32,
+        plan: Option<&Plan>,
+        spending_account_id: Option<i32>,
+        today: NaiveDate,
+        months: u32,
+    ) -> Result<Self, AppError> {
+        if !(1..=MAX_FORECAST_MONTHS).contains(&months) {
+            return Err(AppError::InvalidInput(format!(
+                "A forecast covers between 1 and {MAX_FORECAST_MONTHS} months"
+            )));
+        }
+
+        let mut accounts = Vec::new();
+        // Archived accounts are closed, so they have no future
+        for account in Account::get_all(conn, user_id, false)? {
+            accounts.push(ForecastAccount {
+                account_id: account.id(),
+                kind: account.kind(),
+                balance: account.balance(conn)?,
+            });
+        }
+        let spending_account_id = match spending_account_id {
+            Some(id) => {
+                let account = Account::from_id(conn, id, user_id)?;
+                account.ensure_open()?;
+                Some(account.id())
+            }
+            None => accounts
+                .iter()
+                .find(|account| account.kind == AccountKind::Asset)
+                .map(|account| account.account_id),
+        };
+
+        let scheduled = RecurringTransaction::active_of_user(conn, user_id)?
+            .into_iter()
+            .map(|recurring| {
+                Ok(ScheduledTransaction {
+                    account_id: recurring.account_id(),
+                    category_id: recurring.category_id(),
+                    amount: recurring.amount().clone(),
+                    schedule: recurring.schedule()?,
+                    next_on: recurring.next_run_on(),
+                    end_on: recurring.end_on(),
+                })
+            })
+            .collect::<Result<Vec<_>, AppError>>()?;
+
+        let budgets = match plan {
+            Some(plan) => {
+                let (from, _) = month_range(today.year(), today.month())?;
+                let to = from + Months::new(months) - Duration::days(1);
+                let mut spent: HashMap<i32, BigDecimal> = HashMap::new();
+                for row in Transaction::category_amounts(conn, user_id, from, today)? {
+                    if let Some(category_id) = row.category_id {
+                        *spent.entry(category_id).or_default() -= row.amount;
+                    }
+                }
+
+                Budget::active_between(conn, plan, from, to)?
+                    .into_iter()
+                    .map(|budget| BudgetedSpending {
+                        category_id: budget.category_id(),
+                        monthly_amount: budget.monthly_amount(),
+                        spent: spent
+                            .get(&budget.category_id())
+                            .cloned()
+                            .unwrap_or_default(),
+                        start_date: budget.start_date(),
+                        end_date: budget.end_date(),
+                    })
+                    .collect()
+            }
+            None => vec![],
+        };
+
+        Ok(Self {
+            today,
+            months,
+            accounts,
+            scheduled,
+            budgets,
+            spending_account_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::database::models::recurring_transactions::Cadence;
+
+    const CHEQUING: i32 = 1;
+    const CARD: i32 = 2;
+    const HOUSING: i32 = 10;
+    const GROCERIES: i32 = 11;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn monthly(
+        category_id: Option<i32>,
+        amount: &str,
+        day: i16,
+        next_on: NaiveDate,
+    ) -> ScheduledTransaction {
+        ScheduledTransaction {
+            account_id: CHEQUING,
+            category_id,
+            amount: decimal(amount),
+            schedule: Schedule::new(Cadence::Monthly, Some(day), None).unwrap(),
+            next_on,
+            end_on: None,
+        }
+    }
+
+    fn input(opening_balance: &str) -> ForecastInput {
+        ForecastInput {
+            today: date(7, 1),
+            months: 3,
+            accounts: vec![
+                ForecastAccount {
+                    account_id: CHEQUING,
+                    kind: AccountKind::Asset,
+                    balance: decimal(opening_balance),
+                },
+                ForecastAccount {
+                    account_id: CARD,
+                    kind: AccountKind::Credit,
+                    balance: decimal("-300.00"),
+                },
+            ],
+            scheduled: vec![
+                monthly(None, "3000.00", 15, date(7, 15)),
+                monthly(Some(HOUSING), "-2000.00", 1, date(7, 1)),
+            ],
+            budgets: vec![],
+            spending_account_id: Some(CHEQUING),
+        }
+    }
+
+    fn chequing(month: &ForecastMonth) -> &AccountProjection {
+        &month.accounts[0]
+    }
+
+    #[test]
+    fn test_salary_and_rent() {
+        let months = forecast(&input("2500.00"));
+
+        let projected: Vec<(NaiveDate, BigDecimal, BigDecimal, bool)> = months
+            .iter()
+            .map(|month| {
+                (
+                    month.month,
+                    month.net.clone(),
+                    chequing(month).ending_balance.clone(),
+                    month.goes_negative,
+                )
+            })
+            .collect();
+        assert_eq!(
+            projected,
+            vec![
+                (date(7, 1), decimal("1000.00"), decimal("3500.00"), false),
+                (date(8, 1), decimal("1000.00"), decimal("4500.00"), false),
+                (date(9, 1), decimal("1000.00"), decimal("5500.00"), false),
+            ]
+        );
+        // Credit accounts are owed, so their negative balance isn't flagged
+        assert_eq!(
+            months[2].accounts[1],
+            AccountProjection {
+                account_id: CARD,
+                ending_balance: decimal("-300.00"),
+                goes_negative: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_budgets_not_covered_by_scheduled_transactions() {
+        let mut input = input("2500.00");
+        let budget = |category_id, amount: &str, spent: &str| BudgetedSpending {
+            category_id,
+            monthly_amount: decimal(amount),
+            spent: decimal(spent),
+            start_date: date(1, 1),
+            end_date: Some(date(8, 31)),
+        };
+        input.budgets = vec![
+            budget(GROCERIES, "400.00", "150.00"),
+            // The rent covers 2000 of the budget
+            budget(HOUSING, "2100.00", "0"),
+        ];
+
+        let months = forecast(&input);
+        let nets: Vec<BigDecimal> = months.iter().map(|month| month.net.clone()).collect();
+        assert_eq!(
+            nets,
+            vec![
+                // 150.00 of groceries were already spent this month
+                decimal("650.00"),
+                decimal("500.00"),
+                // The budgets end in August
+                decimal("1000.00"),
+            ]
+        );
+        assert_eq!(chequing(&months[2]).ending_balance, decimal("4650.00"));
+    }
+
+    #[test]
+    fn test_overdraft_is_flagged() {
+        // The rent is due before the salary comes in
+        let months = forecast(&input("1500.00"));
+
+        let flags: Vec<(bool, bool)> = months
+            .iter()
+            .map(|month| (chequing(month).goes_negative, month.goes_negative))
+            .collect();
+        assert_eq!(flags, vec![(true, true), (false, false), (false, false)]);
+        assert_eq!(chequing(&months[0]).ending_balance, decimal("2500.00"));
+    }
+}
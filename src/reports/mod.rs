@@ -0,0 +1,10 @@
+pub mod anomalies;
+pub mod budgets;
+pub mod cache;
+pub mod categories;
+pub mod currency;
+pub mod digest;
+pub mod forecast;
+pub mod monthly;
+pub mod net_worth;
+pub mod periods;
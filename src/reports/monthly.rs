@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{categories::Category, transactions::Transaction},
+};
+use crate::errors::AppError;
+use crate::reports::{
+    anomalies::Anomaly, currency::CurrencyConverter, periods::fiscal_month_range,
+};
+use crate::utils::money::Money;
+
+/// Income and expenses of a category in a period
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CategorySummary {
+    /// ID of the category, `null` for uncategorized transactions
+    category_id: Option<i32>,
+    /// Name of the category, `null` for uncategorized transactions
+    category: Option<String>,
+    /// Money that came in, as a decimal string
+    #[schema(value_type = String)]
+    income: BigDecimal,
+    /// Money that went out, as a positive decimal string
+    #[schema(value_type = String)]
+    expenses: BigDecimal,
+    /// Income minus expenses, as a decimal string
+    #[schema(value_type = String)]
+    net: BigDecimal,
+}
+
+/// Income and expenses of a user in a month or week, across all of their accounts
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MonthlySummary {
+    /// First day of the period
+    from: NaiveDate,
+    /// Last day of the period
+    to: NaiveDate,
+    /// ISO 4217 code of the currency amounts were converted into, `null` if they weren't
+    currency: Option<String>,
+    /// Money that came in, as a decimal string
+    #[schema(value_type = String)]
+    income: BigDecimal,
+    /// Money that went out, as a positive decimal string
+    #[schema(value_type = String)]
+    expenses: BigDecimal,
+    /// Income minus expenses, as a decimal string
+    #[schema(value_type = String)]
+    net: BigDecimal,
+    /// The summary of each category with transactions, by name with uncategorized last
+    categories: Vec<CategorySummary>,
+    /// Categories with unusually high spending, only when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anomalies: Option<Vec<Anomaly>>,
+}
+
+impl MonthlySummary {
+    /// Add the categories with unusually high spending to the summary
+    pub fn with_anomalies(self, anomalies: Vec<Anomaly>) -> Self {
+        Self {
+            anomalies: Some(anomalies),
+            ..self
+        }
+    }
+
+    /// Get the summary of each category with transactions
+    pub fn categories(&self) -> &[CategorySummary] {
+        &self.categories
+    }
+
+    /// Get the money that came in during the period
+    #[cfg(test)]
+    pub fn income(&self) -> &BigDecimal {
+        &self.income
+    }
+
+    /// Get the money that went out during the period
+    #[cfg(test)]
+    pub fn expenses(&self) -> &BigDecimal {
+        &self.expenses
+    }
+}
+
+/// Summarize the income and expenses of a user in a fiscal month
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `user_id` - User ID
+/// * `year` - Year of the month
+/// * `month` - Month of the year, from 1 to 12
+/// * `start_day` - Day of the month fiscal months start on, see `periods::fiscal_month_range`
+/// * `convert_to` - ISO 4217 code of the currency to convert amounts into, if any
+///
+/// # Returns
+///
+/// The summary of the month, see `period_summary`
+pub fn monthly_summary(
+    conn: &mut DbConn,
+    user_id: i32,
+    year: i32,
+    month: u32,
+    start_day: u32,
+    convert_to: Option<&str>,
+) -> Result<MonthlySummary, AppError> {
+    let (from, to) = fiscal_month_range(year, month, start_day)?;
+    period_summary(conn, user_id, from, to, convert_to)
+}
+
+/// Summarize the income and expenses of a user between two days
+///
+/// Split transactions count towards the categories of their splits instead of their own.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `user_id` - User ID
+/// * `from` - First day of the period (inclusive)
+/// * `to` - Last day of the period (inclusive)
+/// * `convert_to` - ISO 4217 code of the currency to convert amounts into, if any, otherwise
+///   amounts are added up regardless of their currency
+///
+/// # Returns
+///
+/// The summary of the period, or `AppError::MissingExchangeRates` if amounts couldn't be
+/// converted
+pub fn period_summary(
+    conn: &mut DbConn,
+    user_id: i32,
+    from: NaiveDate,
+    to: NaiveDate,
+    convert_to: Option<&str>,
+) -> Result<MonthlySummary, AppError> {
+    let names: HashMap<i32, String> = Category::get_all(conn, user_id)?
+        .into_iter()
+        .map(|category| (category.id(), category.name().to_string()))
+        .collect();
+
+    let mut converter = convert_to
+        .map(|currency| CurrencyConverter::load(conn, currency, to))
+        .transpose()?;
+
+    let mut totals: BTreeMap<Option<i32>, (BigDecimal, BigDecimal)> = BTreeMap::new();
+    for row in Transaction::category_amounts(conn, user_id, from, to)? {
+        let amount = match converter.as_mut() {
+            Some(converter) => converter
+                .convert(&Money::new(row.amount, &row.currency)?, row.occurred_at)
+                .into_amount(),
+            None => row.amount,
+        };
+        let (income, expenses) = totals.entry(row.category_id).or_default();
+        if amount < BigDecimal::zero() {
+            *expenses -= amount;
+        } else {
+            *income += amount;
+        }
+    }
+
+    let mut categories: Vec<CategorySummary> = totals
+        .into_iter()
+        .map(|(category_id, (income, expenses))| CategorySummary {
+            category_id,
+            category: category_id.and_then(|id| names.get(&id).cloned()),
+            net: &income - &expenses,
+            income,
+            expenses,
+        })
+        .collect();
+    let currency = converter.map(CurrencyConverter::finish).transpose()?;
+    categories.sort_by(|a, b| match (&a.category, &b.category) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+
+    let income: BigDecimal = categories.iter().map(|c| &c.income).sum();
+    let expenses: BigDecimal = categories.iter().map(|c| &c.expenses).sum();
+    Ok(MonthlySummary {
+        from,
+        to,
+        currency,
+        net: &income - &expenses,
+        income,
+        expenses,
+        categories,
+        anomalies: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::{Account, AccountKind},
+            transactions::{SplitInput, TransactionInput},
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_splits_are_attributed_to_their_categories() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let account = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &decimal("0"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let household = Category::new(conn, user.id(), "Household").unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 7, 5).unwrap();
+
+        // A grocery store charge split between groceries and household items
+        let mut input = TransactionInput::new(decimal("-120.00"), "Grocery store", date);
+        input.category_id = Some(groceries.id());
+        input.splits = vec![
+            SplitInput {
+                category_id: groceries.id(),
+                amount: decimal("-80.00"),
+            },
+            SplitInput {
+                category_id: household.id(),
+                amount: decimal("-40.00"),
+            },
+        ];
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+
+        // Unsplit transactions fall back to their own category
+        let mut input = TransactionInput::new(decimal("-15.00"), "Soap", date);
+        input.category_id = Some(household.id());
+        Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+        Transaction::new(
+            conn,
+            &account,
+            &TransactionInput::new(decimal("2000.00"), "Salary", date),
+            &Quotas::disabled(),
+        )
+        .unwrap();
+
+        let summary = monthly_summary(conn, user.id(), 2024, 7, 1, None).unwrap();
+        assert_eq!(summary.income, decimal("2000"));
+        assert_eq!(summary.expenses, decimal("135"));
+        assert_eq!(summary.net, decimal("1865"));
+
+        let categories: Vec<(Option<&str>, &BigDecimal)> = summary
+            .categories
+            .iter()
+            .map(|c| (c.category.as_deref(), &c.expenses))
+            .collect();
+        assert_eq!(
+            categories,
+            vec![
+                (Some("Groceries"), &decimal("80")),
+                (Some("Household"), &decimal("55")),
+                (None, &decimal("0")),
+            ]
+        );
+
+        let empty = monthly_summary(conn, user.id(), 2024, 8, 1, None).unwrap();
+        assert!(empty.categories.is_empty());
+        assert_eq!(empty.net, BigDecimal::zero());
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::accounts::{Account, AccountKind, Granularity},
+};
+use crate::errors::AppError;
+use crate::reports::currency::CurrencyConverter;
+use crate::utils::money::Money;
+
+/// Assets and liabilities of a user in a currency
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct NetWorth {
+    /// ISO 4217 code of the currency of the amounts
+    currency: String,
+    /// Sum of the balances of asset accounts, as a decimal string
+    #[schema(value_type = String)]
+    assets: BigDecimal,
+    /// Sum of the amounts owed on credit accounts, as a decimal string
+    #[schema(value_type = String)]
+    liabilities: BigDecimal,
+    /// Assets minus liabilities, as a decimal string
+    #[schema(value_type = String)]
+    net: BigDecimal,
+}
+
+/// Net worth of a user at the end of a period
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct NetWorthPoint {
+    /// First day of the period
+    period: NaiveDate,
+    /// Net worth by currency, ordered by currency
+    totals: Vec<NetWorth>,
+}
+
+/// Add up the balances of accounts by currency
+///
+/// Balances of credit accounts are negative when money is owed, and count as liabilities.
+fn summarize<'a>(
+    balances: impl Iterator<Item = (&'a Account, &'a BigDecimal)>,
+    mut converter: Option<&mut CurrencyConverter>,
+    on: NaiveDate,
+) -> Result<Vec<NetWorth>, AppError> {
+    let mut totals: BTreeMap<String, (Money, Money)> = BTreeMap::new();
+    for (account, balance) in balances {
+        let balance = Money::new(balance.clone(), account.currency())?;
+        let balance = match converter.as_deref_mut() {
+            Some(converter) => converter.convert(&balance, on),
+            None => balance,
+        };
+
+        let zero = balance.with_amount(BigDecimal::zero());
+        let (assets, liabilities) = totals
+            .entry(balance.currency().to_string())
+            .or_insert_with(|| (zero.clone(), zero));
+        match account.kind() {
+            AccountKind::Asset => *assets = assets.checked_add(&balance)?,
+            AccountKind::Credit => *liabilities = liabilities.checked_sub(&balance)?,
+        }
+    }
+
+    totals
+        .into_iter()
+        .map(|(currency, (assets, liabilities))| {
+            Ok(NetWorth {
+                currency,
+                net: assets.checked_sub(&liabilities)?.into_amount(),
+                assets: assets.into_amount(),
+                liabilities: liabilities.into_amount(),
+            })
+        })
+        .collect()
+}
+
+/// Compute the current net worth of a user, across all of their open accounts
+///
+/// Archived accounts are closed, so their balances are left out.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `user_id` - User ID
+/// * `convert_to` - ISO 4217 code of the currency to convert balances into with today's rates,
+///   if any, otherwise balances are grouped by currency
+///
+/// # Returns
+///
+/// The net worth by currency ordered by currency, or `AppError::MissingExchangeRates` if balances
+/// couldn't be converted
+pub fn net_worth(
+    conn: &mut DbConn,
+    user_id: i32,
+    convert_to: Option<&str>,
+) -> Result<Vec<NetWorth>, AppError> {
+    let today = chrono::Local::now().date_naive();
+    let accounts = Account::get_all(conn, user_id, false)?;
+
+    // The last point of the history of an account is its current balance
+    let mut balances: HashMap<i32, BigDecimal> = accounts
+        .iter()
+        .map(|account| (account.id(), account.opening_balance().clone()))
+        .collect();
+    for point in Account::balance_histories(conn, user_id, Granularity::Month)? {
+        balances.insert(point.account_id, point.balance);
+    }
+
+    let mut converter = convert_to
+        .map(|currency| CurrencyConverter::load(conn, currency, today))
+        .transpose()?;
+    let totals = summarize(
+        accounts
+            .iter()
+            .map(|account| (account, &balances[&account.id()])),
+        converter.as_mut(),
+        today,
+    )?;
+
+    converter.map(CurrencyConverter::finish).transpose()?;
+    Ok(totals)
+}
+
+/// Compute the net worth of a user at the end of each period with transactions
+///
+/// Accounts without transactions in a period keep their balance from the previous period, or their
+/// opening balance. Archived accounts are included, as they were open in the past.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `user_id` - User ID
+/// * `granularity` - The length of each period
+/// * `convert_to` - ISO 4217 code of the currency to convert balances into with the rates of the
+///   last day of each period, if any, otherwise balances are grouped by currency
+///
+/// # Returns
+///
+/// A vector of net worths ordered by period, or `AppError::MissingExchangeRates` if balances
+/// couldn't be converted
+pub fn net_worth_history(
+    conn: &mut DbConn,
+    user_id: i32,
+    granularity: Granularity,
+    convert_to: Option<&str>,
+) -> Result<Vec<NetWorthPoint>, AppError> {
+    let accounts = Account::get_all(conn, user_id, true)?;
+    let points = Account::balance_histories(conn, user_id, granularity)?;
+
+    let mut converter = match (convert_to, points.last()) {
+        (Some(currency), Some(last)) => Some(CurrencyConverter::load(
+            conn,
+            currency,
+            granularity.last_day(last.period),
+        )?),
+        _ => None,
+    };
+
+    let mut balances: HashMap<i32, BigDecimal> = accounts
+        .iter()
+        .map(|account| (account.id(), account.opening_balance().clone()))
+        .collect();
+    let mut history = Vec::new();
+    let mut points = points.into_iter().peekable();
+    while let Some(period) = points.peek().map(|point| point.period) {
+        while let Some(point) = points.next_if(|point| point.period == period) {
+            balances.insert(point.account_id, point.balance);
+        }
+
+        history.push(NetWorthPoint {
+            period,
+            totals: summarize(
+                accounts
+                    .iter()
+                    .map(|account| (account, &balances[&account.id()])),
+                converter.as_mut(),
+                granularity.last_day(period),
+            )?,
+        });
+    }
+
+    converter.map(CurrencyConverter::finish).transpose()?;
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            transactions::{Transaction, TransactionInput},
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn totals(currency: &str, assets: &str, liabilities: &str, net: &str) -> NetWorth {
+        NetWorth {
+            currency: currency.to_string(),
+            assets: decimal(assets),
+            liabilities: decimal(liabilities),
+            net: decimal(net),
+        }
+    }
+
+    #[test]
+    fn test_net_worth_counts_credit_as_liabilities() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let chequing = Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &decimal("1000.00"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let savings = Account::new(
+            conn,
+            user.id(),
+            "Savings",
+            &decimal("5000.00"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        let card = Account::new(
+            conn,
+            user.id(),
+            "Credit card",
+            &decimal("-200.00"),
+            "CAD",
+            AccountKind::Credit,
+        )
+        .unwrap();
+
+        let add = |conn: &mut DbConn, account: &Account, amount, description, on| {
+            Transaction::new(
+                conn,
+                account,
+                &TransactionInput::new(decimal(amount), description, on),
+                &Quotas::disabled(),
+            )
+            .unwrap();
+        };
+        add(conn, &chequing, "2500.00", "Salary", date(1, 1));
+        add(conn, &card, "-350.25", "Groceries", date(1, 12));
+        add(conn, &chequing, "-550.25", "Card payment", date(2, 5));
+        add(conn, &card, "550.25", "Card payment", date(2, 5));
+        add(conn, &savings, "12.50", "Interest", date(3, 31));
+
+        assert_eq!(
+            net_worth(conn, user.id(), None).unwrap(),
+            vec![totals("CAD", "7962.25", "0.00", "7962.25")]
+        );
+
+        let history = net_worth_history(conn, user.id(), Granularity::Month, None).unwrap();
+        let series: Vec<(NaiveDate, &NetWorth)> = history
+            .iter()
+            .map(|point| (point.period, &point.totals[0]))
+            .collect();
+        assert_eq!(
+            series,
+            vec![
+                (date(1, 1), &totals("CAD", "8500.00", "550.25", "7949.75")),
+                (date(2, 1), &totals("CAD", "7949.75", "0.00", "7949.75")),
+                (date(3, 1), &totals("CAD", "7962.25", "0.00", "7962.25")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_net_worth_is_grouped_by_currency() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        Account::new(
+            conn,
+            user.id(),
+            "Chequing",
+            &decimal("100.00"),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap();
+        Account::new(
+            conn,
+            user.id(),
+            "US card",
+            &decimal("-40.00"),
+            "USD",
+            AccountKind::Credit,
+        )
+        .unwrap();
+
+        assert_eq!(
+            net_worth(conn, user.id(), None).unwrap(),
+            vec![
+                totals("CAD", "100.00", "0", "100.00"),
+                totals("USD", "0", "40.00", "-40.00"),
+            ]
+        );
+        assert!(matches!(
+            net_worth(conn, user.id(), Some("CAD")),
+            Err(AppError::MissingExchangeRates(_))
+        ));
+    }
+}
@@ -0,0 +1,276 @@
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+
+use crate::errors::AppError;
+
+/// Latest day of the month fiscal months can start on, so that every month has it
+pub const MAX_MONTH_START_DAY: u32 = 28;
+
+/// The days of the week, as named by the API
+const WEEKDAYS: [(Weekday, &str); 7] = [
+    (Weekday::Mon, "monday"),
+    (Weekday::Tue, "tuesday"),
+    (Weekday::Wed, "wednesday"),
+    (Weekday::Thu, "thursday"),
+    (Weekday::Fri, "friday"),
+    (Weekday::Sat, "saturday"),
+    (Weekday::Sun, "sunday"),
+];
+
+/// Get the name of a day of the week, e.g. `monday`
+pub fn weekday_name(weekday: Weekday) -> &'static str {
+    WEEKDAYS
+        .iter()
+        .find(|(day, _)| *day == weekday)
+        .map(|(_, name)| *name)
+        .expect("Every day of the week is named")
+}
+
+/// Parse the name of a day of the week, e.g. `monday`
+///
+/// # Returns
+///
+/// The day, or `AppError::InvalidInput` if the name isn't one of the days in lowercase
+pub fn parse_weekday(name: &str) -> Result<Weekday, AppError> {
+    WEEKDAYS
+        .iter()
+        .find(|(_, day)| *day == name)
+        .map(|(weekday, _)| *weekday)
+        .ok_or_else(|| {
+            AppError::InvalidInput(format!(
+                "{name} is not a day of the week, expected one of monday, tuesday, wednesday, \
+                 thursday, friday, saturday or sunday"
+            ))
+        })
+}
+
+/// Check that fiscal months can start on a day of the month
+///
+/// # Returns
+///
+/// An empty result if the day is between 1 and 28, otherwise `AppError::InvalidInput`
+pub fn validate_month_start_day(day: u32) -> Result<(), AppError> {
+    if !(1..=MAX_MONTH_START_DAY).contains(&day) {
+        return Err(AppError::InvalidInput(format!(
+            "Months must start on a day between 1 and {MAX_MONTH_START_DAY}, not {day}"
+        )));
+    }
+    Ok(())
+}
+
+/// Get the first and last day of a calendar month
+///
+/// # Returns
+///
+/// The range, or `AppError::InvalidInput` if the month isn't between 1 and 12
+pub fn month_range(year: i32, month: u32) -> Result<(NaiveDate, NaiveDate), AppError> {
+    fiscal_month_range(year, month, 1)
+}
+
+/// Get the first and last day of a fiscal month
+///
+/// A fiscal month is named after the calendar month it starts in: with months starting on the
+/// 15th, June runs from June 15th to July 14th. Months starting on the 1st are calendar months.
+///
+/// # Arguments
+///
+/// * `year` - Year of the month
+/// * `month` - Month of the year, from 1 to 12
+/// * `start_day` - Day of the month fiscal months start on, from 1 to 28
+///
+/// # Returns
+///
+/// The range, or `AppError::InvalidInput` if the month or start day is out of range
+pub fn fiscal_month_range(
+    year: i32,
+    month: u32,
+    start_day: u32,
+) -> Result<(NaiveDate, NaiveDate), AppError> {
+    validate_month_start_day(start_day)?;
+    NaiveDate::from_ymd_opt(year, month, start_day)
+        .and_then(|from| Some((from, from.checked_add_months(Months::new(1))?.pred_opt()?)))
+        .ok_or_else(|| AppError::InvalidInput(format!("{year}-{month:02} is not a valid month")))
+}
+
+/// Get the fiscal month a day falls in
+///
+/// Days before the start day belong to the fiscal month started in the previous calendar month,
+/// e.g. March 14th is in February with months starting on the 15th.
+///
+/// # Arguments
+///
+/// * `date` - The day
+/// * `start_day` - Day of the month fiscal months start on, from 1 to 28
+///
+/// # Returns
+///
+/// The year and month of the fiscal month
+pub fn fiscal_month_of(date: NaiveDate, start_day: u32) -> (i32, u32) {
+    // Every month has at least as many days as months can be shifted by, so going back the days
+    // before the start day lands in the calendar month named like the fiscal month
+    let shifted = date - Days::new(u64::from(start_day.clamp(1, MAX_MONTH_START_DAY) - 1));
+    (shifted.year(), shifted.month())
+}
+
+/// Get the first and last day of the week a day falls in
+///
+/// # Arguments
+///
+/// * `date` - The day
+/// * `week_starts_on` - The day weeks start on
+///
+/// # Returns
+///
+/// The range, 7 days including the day
+pub fn week_range(date: NaiveDate, week_starts_on: Weekday) -> (NaiveDate, NaiveDate) {
+    let into_week =
+        (date.weekday().num_days_from_monday() + 7 - week_starts_on.num_days_from_monday()) % 7;
+    let from = date - Days::new(u64::from(into_week));
+    (from, from + Days::new(6))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_month_range() {
+        assert_eq!(
+            month_range(2024, 2).unwrap(),
+            (date(2024, 2, 1), date(2024, 2, 29))
+        );
+        assert_eq!(
+            month_range(2024, 12).unwrap(),
+            (date(2024, 12, 1), date(2024, 12, 31))
+        );
+        assert!(month_range(2024, 13).is_err());
+        assert!(month_range(2024, 0).is_err());
+    }
+
+    #[test]
+    fn test_fiscal_month_range() {
+        assert_eq!(
+            fiscal_month_range(2024, 6, 15).unwrap(),
+            (date(2024, 6, 15), date(2024, 7, 14))
+        );
+        // Fiscal months cross into the next year
+        assert_eq!(
+            fiscal_month_range(2024, 12, 15).unwrap(),
+            (date(2024, 12, 15), date(2025, 1, 14))
+        );
+        // Starting on the 28th, February and the months around it are still whole
+        assert_eq!(
+            fiscal_month_range(2023, 1, 28).unwrap(),
+            (date(2023, 1, 28), date(2023, 2, 27))
+        );
+        assert_eq!(
+            fiscal_month_range(2023, 2, 28).unwrap(),
+            (date(2023, 2, 28), date(2023, 3, 27))
+        );
+        assert_eq!(
+            fiscal_month_range(2024, 2, 28).unwrap(),
+            (date(2024, 2, 28), date(2024, 3, 27))
+        );
+        assert_eq!(
+            fiscal_month_range(2024, 1, 28).unwrap(),
+            (date(2024, 1, 28), date(2024, 2, 27))
+        );
+        assert_eq!(
+            fiscal_month_range(2024, 6, 1).unwrap(),
+            month_range(2024, 6).unwrap()
+        );
+
+        assert!(fiscal_month_range(2024, 6, 0).is_err());
+        assert!(fiscal_month_range(2024, 6, 29).is_err());
+        assert!(fiscal_month_range(2024, 13, 15).is_err());
+    }
+
+    #[test]
+    fn test_fiscal_months_cover_every_day_once() {
+        for start_day in 1..=MAX_MONTH_START_DAY {
+            let mut expected = fiscal_month_range(2023, 1, start_day).unwrap().0;
+            for (year, month) in (0..36).map(|i| (2023 + i / 12, i as u32 % 12 + 1)) {
+                let (from, to) = fiscal_month_range(year, month, start_day).unwrap();
+                assert_eq!(from, expected, "{year}-{month} from the {start_day}");
+                assert!(to > from);
+                for day in from.iter_days().take_while(|day| *day <= to) {
+                    assert_eq!(
+                        fiscal_month_of(day, start_day),
+                        (year, month),
+                        "{day} from the {start_day}"
+                    );
+                }
+                expected = to.succ_opt().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_fiscal_month_of() {
+        // The 14th and the 15th are in different months when months start on the 15th
+        assert_eq!(fiscal_month_of(date(2024, 6, 14), 15), (2024, 5));
+        assert_eq!(fiscal_month_of(date(2024, 6, 15), 15), (2024, 6));
+        assert_eq!(fiscal_month_of(date(2024, 7, 14), 15), (2024, 6));
+        // Early January belongs to December of the year before
+        assert_eq!(fiscal_month_of(date(2025, 1, 3), 15), (2024, 12));
+        // The end of February, in common and leap years
+        assert_eq!(fiscal_month_of(date(2023, 2, 27), 28), (2023, 1));
+        assert_eq!(fiscal_month_of(date(2023, 2, 28), 28), (2023, 2));
+        assert_eq!(fiscal_month_of(date(2024, 2, 29), 28), (2024, 2));
+        assert_eq!(fiscal_month_of(date(2024, 3, 1), 28), (2024, 2));
+        assert_eq!(fiscal_month_of(date(2024, 3, 27), 28), (2024, 2));
+        assert_eq!(fiscal_month_of(date(2024, 3, 28), 28), (2024, 3));
+        // Calendar months
+        assert_eq!(fiscal_month_of(date(2024, 3, 1), 1), (2024, 3));
+        assert_eq!(fiscal_month_of(date(2024, 3, 31), 1), (2024, 3));
+    }
+
+    #[test]
+    fn test_week_range() {
+        // Wednesday June 5th, 2024
+        let wednesday = date(2024, 6, 5);
+        assert_eq!(
+            week_range(wednesday, Weekday::Mon),
+            (date(2024, 6, 3), date(2024, 6, 9))
+        );
+        assert_eq!(
+            week_range(wednesday, Weekday::Sun),
+            (date(2024, 6, 2), date(2024, 6, 8))
+        );
+        assert_eq!(
+            week_range(wednesday, Weekday::Wed),
+            (date(2024, 6, 5), date(2024, 6, 11))
+        );
+        assert_eq!(
+            week_range(wednesday, Weekday::Thu),
+            (date(2024, 5, 30), date(2024, 6, 5))
+        );
+        // Weeks cross years
+        assert_eq!(
+            week_range(date(2025, 1, 1), Weekday::Mon),
+            (date(2024, 12, 30), date(2025, 1, 5))
+        );
+
+        // Every day of a week is in it, whatever day weeks start on
+        for (start, _) in WEEKDAYS {
+            let (from, to) = week_range(wednesday, start);
+            assert_eq!(from.weekday(), start);
+            for day in from.iter_days().take(7) {
+                assert_eq!(week_range(day, start), (from, to));
+            }
+        }
+    }
+
+    #[test]
+    fn test_weekday_names() {
+        for (weekday, name) in WEEKDAYS {
+            assert_eq!(weekday_name(weekday), name);
+            assert_eq!(parse_weekday(name).unwrap(), weekday);
+        }
+        assert!(parse_weekday("Monday").is_err());
+        assert!(parse_weekday("mon").is_err());
+    }
+}
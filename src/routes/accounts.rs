@@ -0,0 +1,1379 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use diesel::Connection;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::{
+    alerts,
+    api::{
+        extract::{Validate, ValidatedJson, ValidatedQuery},
+        responses::MessageResponse,
+        state::AppState,
+    },
+    database::{
+        connection::{DbConn, DbPool},
+        models::{
+            accounts::{Account, AccountKind, BalancePoint, Granularity},
+            attachments::Attachment,
+            categories::Category,
+            goals::Goal,
+            payee_rules::PayeeRules,
+            reconciliations::Reconciliation,
+            sessions::manager::Session,
+            transactions::{
+                validate_splits, SplitInput, SplitTransaction, Transaction, TransactionFilter,
+                TransactionInput,
+            },
+            transfers::Transfer,
+            webhooks::WebhookEvent,
+        },
+    },
+    errors::{AppError, InvalidQuery},
+    events::{EventBus, UserEvent},
+    import::bootstrap::{unique_name, BootstrapFile},
+    jobs::webhooks::WebhookDispatcher,
+    quotas::{Quotas, Resource},
+    reports::cache::ReportCache,
+    storage::attachments::AttachmentStore,
+    wipe::{self, TransactionWipe},
+};
+
+/// Create account request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_account))]
+#[schema(example = json!({"name": "Chequing", "opening_balance": "1520.35", "currency": "CAD", "kind": "asset"}))]
+pub struct CreateAccount {
+    /// The name of the account
+    name: String,
+    /// The balance of the account before any transactions, as a decimal string
+    #[schema(value_type = String)]
+    opening_balance: BigDecimal,
+    /// The ISO 4217 currency code of the account
+    currency: String,
+    /// Whether the balance of the account is owned (`asset`, the default) or owed (`credit`)
+    kind: Option<AccountKind>,
+}
+
+impl Validate for CreateAccount {}
+
+/// Create or update transaction request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_transaction, update_transaction))]
+#[schema(example = json!({"amount": "-84.12", "description": "Groceries", "occurred_at": "2024-06-12", "category_id": 3}))]
+pub struct SaveTransaction {
+    /// The signed amount of the transaction, as a decimal string, in the currency of the account
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// The ISO 4217 currency code of the amount, which must be the account's
+    currency: Option<String>,
+    /// The signed amount in the currency the transaction was made in, when it isn't the
+    /// account's, as a decimal string. `amount` is then the amount converted to the currency of
+    /// the account.
+    #[schema(value_type = Option<String>)]
+    original_amount: Option<BigDecimal>,
+    /// The ISO 4217 currency code of the original amount, sent with it
+    original_currency: Option<String>,
+    /// The description of the transaction
+    description: String,
+    /// The date the transaction occurred on
+    occurred_at: NaiveDate,
+    /// The ID of the category of the transaction
+    category_id: Option<i32>,
+    /// The ID of the savings goal the transaction counts towards
+    goal_id: Option<i32>,
+    /// Splits of the amount across categories, which must add up to the amount. Reports use the
+    /// splits instead of the category of the transaction.
+    splits: Option<Vec<SplitInput>>,
+}
+
+impl Validate for SaveTransaction {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_splits(&self.amount, self.splits.as_deref().unwrap_or_default())
+    }
+}
+
+impl SaveTransaction {
+    /// Check that the referenced category, goal and split categories belong to the user, and
+    /// convert the request to the fields of a transaction
+    fn into_input(self, conn: &mut DbConn, user_id: i32) -> Result<TransactionInput, AppError> {
+        if let Some(category_id) = self.category_id {
+            Category::from_id(conn, category_id, user_id)?;
+        }
+        if let Some(goal_id) = self.goal_id {
+            Goal::from_id(conn, goal_id, user_id)?;
+        }
+        let splits = self.splits.unwrap_or_default();
+        for split in &splits {
+            Category::from_id(conn, split.category_id, user_id)?;
+        }
+
+        Ok(TransactionInput {
+            amount: self.amount,
+            currency: self.currency,
+            original_amount: self.original_amount,
+            original_currency: self.original_currency,
+            description: self.description,
+            payee: None,
+            occurred_at: self.occurred_at,
+            category_id: self.category_id,
+            goal_id: self.goal_id,
+            splits,
+        })
+    }
+}
+
+impl Validate for BootstrapFile {
+    fn validate(&self) -> Result<(), AppError> {
+        self.check()
+    }
+}
+
+/// An account of a bootstrap file that was created
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ImportedAccount {
+    /// Position of the account in the file, from 0
+    index: usize,
+    /// The ID of the created account
+    account_id: i32,
+    /// The name of the created account, with a number after it if the name was taken
+    name: String,
+    /// Number of transactions created on the account
+    transactions: usize,
+}
+
+/// An account of a bootstrap file that wasn't created
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FailedAccount {
+    /// Position of the account in the file, from 0
+    index: usize,
+    /// The slug of the error, e.g. `invalid_input` or `quota_exceeded`
+    error: String,
+    /// Why the account wasn't created
+    message: String,
+}
+
+/// Outcome of an account import
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct AccountImport {
+    /// The accounts that were created, in the order of the file
+    created: Vec<ImportedAccount>,
+    /// The accounts that weren't, in the order of the file
+    failed: Vec<FailedAccount>,
+}
+
+/// Current balance of an account
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AccountBalance {
+    /// The account ID
+    account_id: i32,
+    /// The current balance, as a decimal string
+    #[schema(value_type = String)]
+    balance: BigDecimal,
+    /// The ISO 4217 currency code of the balance
+    currency: String,
+}
+
+/// Transaction list query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct TransactionParams {
+    /// Only transactions with this tag, regardless of case
+    tag: Option<String>,
+    /// Only transactions whose description or payee contains this text, regardless of case
+    q: Option<String>,
+    /// Only transactions that occurred on or after this day
+    from: Option<NaiveDate>,
+    /// Only transactions that occurred on or before this day
+    to: Option<NaiveDate>,
+    /// Whether to only list transactions without a category or splits, false by default
+    #[serde(default)]
+    uncategorized_only: bool,
+}
+
+impl Validate for TransactionParams {
+    fn validate(&self) -> Result<(), AppError> {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from > to => {
+                Err(AppError::InvalidQuery(InvalidQuery::range(
+                    "from",
+                    "to",
+                    format!("it starts on {from}, after it ends on {to}"),
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Account list query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct AccountParams {
+    /// Whether to include archived accounts, false by default
+    #[serde(default)]
+    include_archived: bool,
+}
+
+impl Validate for AccountParams {}
+
+/// Balance history query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryParams {
+    /// The length of each period (`day` or `month`)
+    granularity: Option<Granularity>,
+}
+
+impl Validate for HistoryParams {}
+
+/// Transaction wipe query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct WipeParams {
+    /// Delete the transactions that occurred before this day
+    before: NaiveDate,
+    /// Whether to only count what would be deleted, false by default
+    #[serde(default)]
+    dry_run: bool,
+}
+
+impl Validate for WipeParams {}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/accounts",
+            get(all_accounts)
+                .layer(middleware::from_fn(crate::middleware::etag::etag))
+                .post(create_account),
+        )
+        .route("/accounts/import", post(import_accounts))
+        .route("/accounts/:id/archive", post(archive_account))
+        .route("/accounts/:id/unarchive", post(unarchive_account))
+        .route("/accounts/:id/balance", get(get_balance))
+        .route("/accounts/:id/balance/history", get(get_balance_history))
+        .route(
+            "/accounts/:id/transactions",
+            get(all_transactions)
+                .post(create_transaction)
+                .delete(delete_transactions),
+        )
+        .route(
+            "/accounts/:id/transactions/:transaction_id",
+            put(update_transaction).delete(delete_transaction),
+        )
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all accounts of the authenticated user
+///
+/// Archived accounts are left out unless `include_archived=true`.
+///
+/// ## Responses
+/// `200` : A successful response. Returns a vector of accounts.
+/// `400` : A parameter can't be read or is unknown.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts",
+    security(("cookieAuth" = [])),
+    params(AccountParams),
+    responses(
+        (status = 200, description = "Accounts of the user", body = Vec<Account>),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 401, description = "User is not authenticated", body = ErrorBody)
+    )
+)]
+async fn all_accounts(
+    Extension(session): Extension<Session>,
+    State(pool): State<Arc<DbPool>>,
+    ValidatedQuery(params): ValidatedQuery<AccountParams>,
+) -> Result<Json<Vec<Account>>, AppError> {
+    pool.run_read(move |conn| {
+        let accounts = Account::get_all(conn, session.user_id(), params.include_archived)?;
+        Ok(Json(accounts))
+    })
+    .await
+}
+
+/// This endpoint creates a new account
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created account.
+/// `403` : The user has as many accounts as their quota allows.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts",
+    security(("cookieAuth" = [])),
+    request_body = CreateAccount,
+    responses(
+        (status = 201, description = "Account created", body = Account),
+        (status = 403, description = "Quota of accounts exceeded")
+    )
+)]
+async fn create_account(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    State(quotas): State<Quotas>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<CreateAccount>,
+) -> Result<(StatusCode, Json<Account>), AppError> {
+    pool.run(move |conn| {
+        quotas.ensure(conn, session.user_id(), Resource::Accounts, 1)?;
+        let account = Account::new(
+            conn,
+            session.user_id(),
+            &payload.name,
+            &payload.opening_balance,
+            &payload.currency,
+            payload.kind.unwrap_or_default(),
+        )?;
+        reports.invalidate(session.user_id());
+
+        Ok((StatusCode::CREATED, Json(account)))
+    })
+    .await
+}
+
+/// This endpoint creates accounts and their history from a bootstrap file, e.g. when moving from
+/// another tool
+///
+/// The body is a `BootstrapFile`: accounts with their kind, currency, opening balance and
+/// optionally their transactions. Each account is created with its transactions in a database
+/// transaction of its own, so an account that can't be created, e.g. with an unsupported currency
+/// code or over the quota of accounts, is reported without stopping the others. Accounts named
+/// like an existing account or an account before them in the file are rejected, or get a number
+/// after their name with `"duplicate_names": "suffix"`.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the IDs of the created accounts by position in the
+/// file, and why the others weren't created.
+/// `400` : The file has no accounts or more than 100.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts/import",
+    security(("cookieAuth" = [])),
+    request_body = BootstrapFile,
+    responses(
+        (status = 200, description = "Accounts imported", body = AccountImport),
+        (status = 400, description = "Invalid file")
+    )
+)]
+async fn import_accounts(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    State(quotas): State<Quotas>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<BootstrapFile>,
+) -> Result<Json<AccountImport>, AppError> {
+    pool.run(move |conn| {
+        let user_id = session.user_id();
+        let mut taken: HashSet<String> = Account::get_all(conn, user_id, true)?
+            .into_iter()
+            .map(|account| account.name().to_string())
+            .collect();
+
+        let mut outcome = AccountImport::default();
+        for (index, input) in payload.accounts.iter().enumerate() {
+            let created = input
+                .check()
+                .and_then(|()| unique_name(&input.name, &taken, payload.duplicate_names))
+                .and_then(|name| {
+                    conn.transaction(|conn| {
+                        quotas.ensure(conn, user_id, Resource::Accounts, 1)?;
+                        let account = Account::new(
+                            conn,
+                            user_id,
+                            &name,
+                            &input.opening_balance,
+                            &input.currency,
+                            input.kind.unwrap_or_default(),
+                        )?;
+                        let transactions = input.transaction_inputs();
+                        if !transactions.is_empty() {
+                            Transaction::bulk_insert(conn, &account, &transactions, &quotas)?;
+                        }
+                        Ok(ImportedAccount {
+                            index,
+                            account_id: account.id(),
+                            name,
+                            transactions: transactions.len(),
+                        })
+                    })
+                });
+
+            match created {
+                Ok(imported) => {
+                    taken.insert(imported.name.clone());
+                    outcome.created.push(imported);
+                }
+                Err(e) => outcome.failed.push(FailedAccount {
+                    index,
+                    error: e.code().slug().to_string(),
+                    message: e.to_string(),
+                }),
+            }
+        }
+        if !outcome.created.is_empty() {
+            reports.invalidate(user_id);
+        }
+
+        Ok(Json(outcome))
+    })
+    .await
+}
+
+/// This endpoint archives an account of the authenticated user
+///
+/// Archived accounts are closed: they are left out of the account list, the current net worth and
+/// the forecast, and no transactions can be created on them. Their transactions stay in
+/// historical reports. Archiving an archived account changes nothing.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the archived account.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/archive",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    responses(
+        (status = 200, description = "Account archived", body = Account),
+        (status = 404, description = "Account not found")
+    )
+)]
+async fn archive_account(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<Account>, AppError> {
+    pool.run(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?.set_archived(conn, true)?;
+        reports.invalidate(session.user_id());
+
+        Ok(Json(account))
+    })
+    .await
+}
+
+/// This endpoint unarchives an account of the authenticated user
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the unarchived account.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/unarchive",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    responses(
+        (status = 200, description = "Account unarchived", body = Account),
+        (status = 404, description = "Account not found")
+    )
+)]
+async fn unarchive_account(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<Account>, AppError> {
+    pool.run(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?.set_archived(conn, false)?;
+        reports.invalidate(session.user_id());
+
+        Ok(Json(account))
+    })
+    .await
+}
+
+/// This endpoint returns the current balance of an account
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the balance of the account.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/balance",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    responses(
+        (status = 200, description = "Account balance", body = AccountBalance),
+        (status = 404, description = "Account not found")
+    )
+)]
+async fn get_balance(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<AccountBalance>, AppError> {
+    pool.run_read(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let balance = account.balance(conn)?;
+
+        Ok(Json(AccountBalance {
+            account_id: account.id(),
+            balance,
+            currency: account.currency().to_string(),
+        }))
+    })
+    .await
+}
+
+/// This endpoint returns the end-of-period balances of an account
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of balances ordered by period.
+/// `400` : A parameter can't be read or is unknown.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/balance/history",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account"), HistoryParams),
+    responses(
+        (status = 200, description = "Account balance history", body = Vec<BalancePoint>),
+        (status = 400, description = "Invalid parameters"),
+        (status = 404, description = "Account not found")
+    )
+)]
+async fn get_balance_history(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedQuery(params): ValidatedQuery<HistoryParams>,
+) -> Result<Json<Vec<BalancePoint>>, AppError> {
+    pool.run_read(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let history = account.balance_history(conn, params.granularity.unwrap_or_default())?;
+
+        Ok(Json(history))
+    })
+    .await
+}
+
+/// This endpoint returns the transactions of an account that match the filters of the query
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of transactions, most recent first.
+/// `400` : A parameter can't be read or is unknown, or `from` is after `to`.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/transactions",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account"), TransactionParams),
+    responses(
+        (status = 200, description = "Transactions of the account", body = Vec<Transaction>),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 404, description = "Account not found", body = ErrorBody)
+    )
+)]
+async fn all_transactions(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedQuery(params): ValidatedQuery<TransactionParams>,
+) -> Result<Json<Vec<Transaction>>, AppError> {
+    pool.run_read(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let filter = TransactionFilter {
+            account_id: Some(account.id()),
+            tag: params.tag,
+            q: params.q,
+            from: params.from,
+            to: params.to,
+            uncategorized_only: params.uncategorized_only,
+        };
+        let transactions = Transaction::search(conn, &account, &filter)?;
+
+        Ok(Json(transactions))
+    })
+    .await
+}
+
+/// This endpoint creates a new transaction on an account
+///
+/// The payee of the transaction is set from the most specific payee rule that matches its
+/// description. The rule also sets the category, if none was given and the transaction isn't split.
+/// The spending alerts of its categories are evaluated once it is created.
+///
+/// The amount is in the currency of the account. A transaction made in another currency is sent
+/// with its `original_amount` and `original_currency`, which are kept for display, and with the
+/// `amount` converted to the currency of the account, which balances and reports use.
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created transaction with its splits.
+/// `400` : The splits don't add up to the amount. The message says how far off they are.
+/// `403` : The user has as many transactions as their quota allows.
+/// `404` : The account, category or goal doesn't exist or belongs to another user.
+/// `409` : The account is archived.
+/// `422` : The `currency` of the amount isn't the account's. `details` names both currencies.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/transactions",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    request_body = SaveTransaction,
+    responses(
+        (status = 201, description = "Transaction created", body = SplitTransaction),
+        (status = 400, description = "Splits don't add up to the amount"),
+        (status = 403, description = "Over the quota of transactions"),
+        (status = 404, description = "Account not found"),
+        (status = 409, description = "Account archived"),
+        (status = 422, description = "Amount not in the currency of the account", body = ErrorBody)
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn create_transaction(
+    State(pool): State<Arc<DbPool>>,
+    State(quotas): State<Quotas>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Extension(webhooks): Extension<Arc<WebhookDispatcher>>,
+    State(events): State<Arc<EventBus>>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<SaveTransaction>,
+) -> Result<(StatusCode, Json<SplitTransaction>), AppError> {
+    pool.transaction(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let mut input = payload.into_input(conn, session.user_id())?;
+        PayeeRules::load(conn, session.user_id())?.apply(&mut input);
+        let transaction = Transaction::new(conn, &account, &input, &quotas)?.with_splits(conn)?;
+        webhooks.notify(
+            conn,
+            session.user_id(),
+            WebhookEvent::TransactionCreated,
+            &transaction,
+        );
+        events.publish(
+            session.user_id(),
+            UserEvent::TransactionCreated,
+            &transaction,
+        );
+        reports.invalidate(session.user_id());
+        let occurred_at = transaction.transaction().occurred_at();
+        alerts::check(
+            conn,
+            &webhooks,
+            &events,
+            session.user_id(),
+            transaction
+                .category_ids()
+                .into_iter()
+                .map(|category_id| (category_id, occurred_at)),
+        );
+
+        Ok((StatusCode::CREATED, Json(transaction)))
+    })
+    .await
+}
+
+/// This endpoint updates a transaction
+///
+/// The transaction is replaced as a whole, so leaving out the splits removes them. Legs of a
+/// transfer are updated together through `PATCH /transfers/{id}` instead.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated transaction with its splits.
+/// `400` : The splits don't add up to the amount. The message says how far off they are.
+/// `404` : The account, transaction, category or goal doesn't exist or belongs to another user.
+/// `409` : The transaction is a leg of a transfer, or was cleared in a finished reconciliation.
+/// The message says which.
+/// `422` : The `currency` of the amount isn't the account's. `details` names both currencies.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    put,
+    path = "/accounts/{id}/transactions/{transaction_id}",
+    security(("cookieAuth" = [])),
+    params(
+        ("id" = i32, Path, description = "ID of the account"),
+        ("transaction_id" = i32, Path, description = "ID of the transaction")
+    ),
+    request_body = SaveTransaction,
+    responses(
+        (status = 200, description = "Transaction updated", body = SplitTransaction),
+        (status = 400, description = "Splits don't add up to the amount"),
+        (status = 404, description = "Transaction not found"),
+        (status = 409, description = "Transaction is a leg of a transfer or reconciled"),
+        (status = 422, description = "Amount not in the currency of the account", body = ErrorBody)
+    )
+)]
+async fn update_transaction(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Path((id, transaction_id)): Path<(i32, i32)>,
+    ValidatedJson(payload): ValidatedJson<SaveTransaction>,
+) -> Result<Json<SplitTransaction>, AppError> {
+    pool.run(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let transaction = Transaction::from_id(conn, transaction_id, session.user_id())?;
+        if transaction.account_id() != account.id() {
+            return Err(AppError::not_found());
+        }
+
+        if let Some(transfer_id) = transaction.transfer_id() {
+            return Err(AppError::Conflict(format!(
+                "Transaction {transaction_id} is a leg of transfer {transfer_id}, update both legs with \
+                 PATCH /transfers/{transfer_id}"
+            )));
+        }
+
+        Reconciliation::ensure_unlocked(conn, &[transaction.id()])?;
+
+        let input = payload.into_input(conn, session.user_id())?;
+        let transaction = transaction.update(conn, &input)?;
+        reports.invalidate(session.user_id());
+
+        Ok(Json(transaction.with_splits(conn)?))
+    })
+    .await
+}
+
+/// This endpoint deletes a transaction
+///
+/// Its splits, tags and attachments are deleted with it, along with the attached files. Deleting
+/// a leg of a transfer deletes the other leg too.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the transaction was deleted.
+/// `404` : The account or transaction doesn't exist or belongs to another user.
+/// `409` : The transaction, or the other leg of its transfer, was cleared in a finished
+/// reconciliation.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/accounts/{id}/transactions/{transaction_id}",
+    security(("cookieAuth" = [])),
+    params(
+        ("id" = i32, Path, description = "ID of the account"),
+        ("transaction_id" = i32, Path, description = "ID of the transaction")
+    ),
+    responses(
+        (status = 200, description = "Transaction deleted", body = MessageResponse),
+        (status = 404, description = "Transaction not found"),
+        (status = 409, description = "Transaction is reconciled")
+    )
+)]
+async fn delete_transaction(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Extension(store): Extension<Arc<AttachmentStore>>,
+    Extension(webhooks): Extension<Arc<WebhookDispatcher>>,
+    State(events): State<Arc<EventBus>>,
+    Path((id, transaction_id)): Path<(i32, i32)>,
+) -> Result<Json<MessageResponse>, AppError> {
+    let attachments = pool
+        .run(move |conn| {
+            let account = Account::from_id(conn, id, session.user_id())?;
+            let transaction = Transaction::from_id(conn, transaction_id, session.user_id())?;
+            if transaction.account_id() != account.id() {
+                return Err(AppError::not_found());
+            }
+
+            // Deleting a leg of a transfer deletes the other leg with it
+            let transfer = transaction
+                .transfer_id()
+                .map(|transfer_id| Transfer::from_id(conn, transfer_id, session.user_id()))
+                .transpose()?;
+            let legs = match &transfer {
+                Some(transfer) => transfer.legs().to_vec(),
+                None => vec![&transaction],
+            };
+            let leg_ids: Vec<i32> = legs.iter().map(|leg| leg.id()).collect();
+            Reconciliation::ensure_unlocked(conn, &leg_ids)?;
+
+            // The attachment rows go with the transactions, their files are removed once they're
+            // gone
+            let mut attachments = Vec::new();
+            for leg in &legs {
+                attachments.extend(Attachment::get_all(conn, leg)?);
+            }
+            match &transfer {
+                Some(transfer) => transfer.delete(conn)?,
+                None => transaction.delete(conn)?,
+            }
+            for leg in legs {
+                webhooks.notify(
+                    conn,
+                    session.user_id(),
+                    WebhookEvent::TransactionDeleted,
+                    leg,
+                );
+                events.publish(session.user_id(), UserEvent::TransactionDeleted, leg);
+            }
+            reports.invalidate(session.user_id());
+            Ok(attachments)
+        })
+        .await?;
+    for attachment in &attachments {
+        store.remove(attachment.storage_path()).await;
+    }
+
+    Ok(Json(MessageResponse::new("Transaction deleted")))
+}
+
+/// This endpoint deletes the transactions of an account that occurred before a day
+///
+/// Their splits, tags and attachments are deleted with them, along with the attached files.
+/// Transfers are deleted whole, so the other legs of the transfers among them are deleted too.
+/// With `dry_run=true` nothing is deleted and the response tells what would be. A wipe is recorded
+/// in the audit log with its counts.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns how many transactions, splits, tags and attachments
+/// were deleted, or would be on a dry run.
+/// `400` : A parameter can't be read or is unknown.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `409` : One of the transactions was cleared in a finished reconciliation, nothing was
+/// deleted.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/accounts/{id}/transactions",
+    security(("cookieAuth" = [])),
+    params(
+        ("id" = i32, Path, description = "ID of the account"),
+        WipeParams
+    ),
+    responses(
+        (status = 200, description = "Transactions deleted", body = TransactionWipe),
+        (status = 400, description = "Invalid parameters"),
+        (status = 404, description = "Account not found"),
+        (status = 409, description = "A transaction is reconciled")
+    )
+)]
+async fn delete_transactions(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Extension(store): Extension<Arc<AttachmentStore>>,
+    Path(id): Path<i32>,
+    ValidatedQuery(params): ValidatedQuery<WipeParams>,
+) -> Result<Json<TransactionWipe>, AppError> {
+    let (counts, attachments) = pool
+        .run(move |conn| {
+            let account = Account::from_id(conn, id, session.user_id())?;
+            let wiped = wipe::wipe_transactions(conn, &account, params.before, params.dry_run)?;
+            if !params.dry_run {
+                reports.invalidate(session.user_id());
+            }
+            Ok(wiped)
+        })
+        .await?;
+    for attachment in &attachments {
+        store.remove(attachment.storage_path()).await;
+    }
+
+    Ok(Json(counts))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+    use crate::quotas::Quotas;
+
+    #[tokio::test]
+    async fn test_archived_accounts() {
+        let app = TestApp::new();
+        let mut accounts = Vec::new();
+        for name in ["Chequing", "Old bank"] {
+            let (_, account) = app
+                .request(
+                    Method::POST,
+                    "/accounts",
+                    Some(json!({"name": name, "opening_balance": "100.00", "currency": "CAD"})),
+                )
+                .await;
+            accounts.push(account["id"].clone());
+        }
+        let transaction = json!({
+            "amount": "-40.00",
+            "description": "Groceries",
+            "occurred_at": "2024-06-12",
+        });
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/transactions", accounts[1]),
+                Some(transaction.clone()),
+            )
+            .await;
+        assert_eq!(status, 201);
+
+        let (status, archived) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/archive", accounts[1]),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200, "{archived}");
+        assert!(archived["archived_at"].is_string());
+
+        let ids = |accounts: &Value| -> Vec<Value> {
+            accounts
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|account| account["id"].clone())
+                .collect()
+        };
+        let (_, listed) = app.request(Method::GET, "/accounts", None).await;
+        assert_eq!(ids(&listed), vec![accounts[0].clone()]);
+        let (_, listed) = app
+            .request(Method::GET, "/accounts?include_archived=true", None)
+            .await;
+        assert_eq!(ids(&listed), accounts);
+
+        // History keeps the spending of the archived account
+        let (_, summary) = app
+            .request(Method::GET, "/reports/monthly?year=2024&month=6", None)
+            .await;
+        assert_eq!(summary["expenses"], "40.00");
+
+        // Current figures leave it out
+        let (_, net_worth) = app.request(Method::GET, "/reports/net-worth", None).await;
+        assert_eq!(net_worth[0]["net"], "100.00");
+        let (status, forecast) = app
+            .request(Method::GET, "/reports/forecast?months=1", None)
+            .await;
+        assert_eq!(status, 200, "{forecast}");
+        assert_eq!(ids_of_projections(&forecast[0]), vec![accounts[0].clone()]);
+
+        // Nothing can be added to it until it is unarchived
+        let uri = format!("/accounts/{}/transactions", accounts[1]);
+        let (status, error) = app
+            .request(Method::POST, &uri, Some(transaction.clone()))
+            .await;
+        assert_eq!(status, 409, "{error}");
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/transfers",
+                Some(json!({
+                    "from_account_id": accounts[0],
+                    "to_account_id": accounts[1],
+                    "amount": "10.00",
+                    "occurred_at": "2024-06-15",
+                })),
+            )
+            .await;
+        assert_eq!(status, 409);
+
+        let (status, unarchived) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/unarchive", accounts[1]),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(unarchived["archived_at"], Value::Null);
+        let (status, _) = app.request(Method::POST, &uri, Some(transaction)).await;
+        assert_eq!(status, 201);
+    }
+
+    #[tokio::test]
+    async fn test_transaction_currency() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        let uri = format!("/accounts/{}/transactions", account["id"]);
+
+        // An amount in another currency is refused, naming both currencies
+        let (status, error) = app
+            .request(
+                Method::POST,
+                &uri,
+                Some(json!({
+                    "amount": "-25.00",
+                    "currency": "USD",
+                    "description": "Hotel",
+                    "occurred_at": "2024-06-12",
+                })),
+            )
+            .await;
+        assert_eq!(status, 422, "{error}");
+        assert_eq!(error["error"], "currency_mismatch");
+        assert_eq!(
+            error["details"],
+            json!({"currency": "USD", "account_currency": "CAD"})
+        );
+
+        // Converted by the client, the original amount is kept alongside
+        let (status, hotel) = app
+            .request(
+                Method::POST,
+                &uri,
+                Some(json!({
+                    "amount": "-34.10",
+                    "currency": "CAD",
+                    "original_amount": "-25.00",
+                    "original_currency": "USD",
+                    "description": "Hotel",
+                    "occurred_at": "2024-06-12",
+                })),
+            )
+            .await;
+        assert_eq!(status, 201, "{hotel}");
+        let (status, error) = app
+            .request(
+                Method::POST,
+                &uri,
+                Some(json!({
+                    "amount": "-34.10",
+                    "original_amount": "-25.00",
+                    "description": "Hotel",
+                    "occurred_at": "2024-06-12",
+                })),
+            )
+            .await;
+        assert_eq!(status, 400, "{error}");
+
+        let (status, listed) = app.request(Method::GET, &uri, None).await;
+        assert_eq!(status, 200, "{listed}");
+        assert_eq!(listed[0]["amount"], "-34.10");
+        assert_eq!(listed[0]["currency"], "CAD");
+        assert_eq!(listed[0]["original_amount"], "-25.00");
+        assert_eq!(listed[0]["original_currency"], "USD");
+
+        // Balances and reports use the amount in the currency of the account
+        let (_, balance) = app
+            .request(
+                Method::GET,
+                &format!("/accounts/{}/balance", account["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(balance["balance"], "-34.10");
+        let (_, summary) = app
+            .request(Method::GET, "/reports/monthly?year=2024&month=6", None)
+            .await;
+        assert_eq!(summary["expenses"], "34.10");
+
+        // Updates are held to the same currency
+        let (status, error) = app
+            .request(
+                Method::PUT,
+                &format!("{uri}/{}", hotel["id"]),
+                Some(json!({
+                    "amount": "-25.00",
+                    "currency": "USD",
+                    "description": "Hotel",
+                    "occurred_at": "2024-06-12",
+                })),
+            )
+            .await;
+        assert_eq!(status, 422, "{error}");
+
+        // So are imported transactions, the account is left out along with them
+        let (_, outcome) = app
+            .request(
+                Method::POST,
+                "/accounts/import",
+                Some(json!({"accounts": [{
+                    "name": "Travel",
+                    "currency": "CAD",
+                    "opening_balance": "0.00",
+                    "transactions": [
+                        {"amount": "-12.00", "currency": "EUR", "description": "Museum", "occurred_at": "2024-06-13"},
+                    ],
+                }]})),
+            )
+            .await;
+        assert_eq!(outcome["failed"][0]["error"], "currency_mismatch");
+        assert_eq!(outcome["created"], json!([]));
+    }
+
+    fn ids_of_projections(month: &Value) -> Vec<Value> {
+        month["accounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|account| account["account_id"].clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_wipe_transactions() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        let mut ids = Vec::new();
+        for (amount, occurred_at) in [
+            ("-20.00", "2024-05-01"),
+            ("-30.00", "2024-05-20"),
+            ("-40.00", "2024-06-01"),
+        ] {
+            let (status, transaction) = app
+                .request(
+                    Method::POST,
+                    &format!("/accounts/{}/transactions", account["id"]),
+                    Some(json!({"amount": amount, "description": "Card", "occurred_at": occurred_at})),
+                )
+                .await;
+            assert_eq!(status, 201, "{transaction}");
+            ids.push(transaction["id"].clone());
+        }
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/transactions/{}/tags", ids[1]),
+                Some(json!(["card"])),
+            )
+            .await;
+        assert_eq!(status, 200);
+
+        // A dry run deletes nothing and counts what the wipe deletes
+        let uri = format!("/accounts/{}/transactions?before=2024-06-01", account["id"]);
+        let (status, dry_run) = app
+            .request(Method::DELETE, &format!("{uri}&dry_run=true"), None)
+            .await;
+        assert_eq!(status, 200, "{dry_run}");
+        assert_eq!(
+            dry_run,
+            json!({"transactions": 2, "splits": 0, "tags": 1, "attachments": 0})
+        );
+        let (_, listed) = app
+            .request(
+                Method::GET,
+                &format!("/accounts/{}/transactions", account["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(listed.as_array().unwrap().len(), 3);
+
+        let (status, wiped) = app.request(Method::DELETE, &uri, None).await;
+        assert_eq!(status, 200, "{wiped}");
+        assert_eq!(wiped, dry_run);
+        let (_, listed) = app
+            .request(
+                Method::GET,
+                &format!("/accounts/{}/transactions", account["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["id"], ids[2]);
+
+        // Transactions cleared in a finished reconciliation lock the wipe, dry runs included
+        let (_, reconciliation) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/reconciliations", account["id"]),
+                Some(json!({"statement_date": "2024-06-30", "ending_balance": "-40.00"})),
+            )
+            .await;
+        let reconciliation = &reconciliation["id"];
+        app.request(
+            Method::POST,
+            &format!("/reconciliations/{reconciliation}/clear"),
+            Some(json!({"transaction_ids": [ids[2]]})),
+        )
+        .await;
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/reconciliations/{reconciliation}/finish"),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200);
+        let uri = format!("/accounts/{}/transactions?before=2024-07-01", account["id"]);
+        for uri in [format!("{uri}&dry_run=true"), uri] {
+            let (status, error) = app.request(Method::DELETE, &uri, None).await;
+            assert_eq!(status, 409, "{error}");
+        }
+        let (_, listed) = app
+            .request(
+                Method::GET,
+                &format!("/accounts/{}/transactions", account["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_accounts() {
+        let app = TestApp::with_quotas(Quotas {
+            accounts: 4,
+            ..Quotas::default()
+        });
+        app.request(
+            Method::POST,
+            "/accounts",
+            Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+        )
+        .await;
+
+        let file = |duplicate_names: &str| {
+            json!({
+                "duplicate_names": duplicate_names,
+                "accounts": [
+                    {
+                        "name": "Chequing",
+                        "currency": "CAD",
+                        "opening_balance": "1000.00",
+                        "transactions": [
+                            {"amount": "-200.00", "description": "Rent", "occurred_at": "2024-01-01"},
+                            {"amount": "50.00", "description": "Refund", "occurred_at": "2024-01-15"},
+                        ],
+                    },
+                    {"name": "Travel", "currency": "cad", "opening_balance": "0.00"},
+                    {"name": "Visa", "kind": "credit", "currency": "USD", "opening_balance": "-80.00"},
+                    {"name": "Savings", "currency": "CAD", "opening_balance": "10.00"},
+                ],
+            })
+        };
+
+        // Taken names are rejected, and the other accounts are still created
+        let (status, outcome) = app
+            .request(Method::POST, "/accounts/import", Some(file("reject")))
+            .await;
+        assert_eq!(status, 200, "{outcome}");
+        let created = outcome["created"].as_array().unwrap();
+        assert_eq!(created.len(), 2);
+        assert_eq!(created[0]["index"], 2);
+        assert_eq!(created[0]["name"], "Visa");
+        assert_eq!(created[1]["index"], 3);
+        let failed: Vec<_> = outcome["failed"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|failure| (failure["index"].clone(), failure["error"].clone()))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                (json!(0), json!("conflict")),
+                (json!(1), json!("invalid_input")),
+            ]
+        );
+
+        let (_, visa) = app
+            .request(
+                Method::GET,
+                &format!("/accounts/{}/balance", created[0]["account_id"]),
+                None,
+            )
+            .await;
+        assert_eq!(visa["balance"], "-80.00");
+        assert_eq!(visa["currency"], "USD");
+
+        // With suffixes, the account is created with its history until the quota is reached
+        let (status, outcome) = app
+            .request(Method::POST, "/accounts/import", Some(file("suffix")))
+            .await;
+        assert_eq!(status, 200, "{outcome}");
+        let created = &outcome["created"][0];
+        assert_eq!(created["index"], 0);
+        assert_eq!(created["name"], "Chequing (2)");
+        assert_eq!(created["transactions"], 2);
+        assert_eq!(outcome["failed"][0]["error"], "invalid_input");
+        assert_eq!(outcome["failed"][1]["index"], 2);
+        assert_eq!(outcome["failed"][1]["error"], "quota_exceeded");
+        assert_eq!(outcome["failed"][2]["error"], "quota_exceeded");
+
+        let (_, balance) = app
+            .request(
+                Method::GET,
+                &format!("/accounts/{}/balance", created["account_id"]),
+                None,
+            )
+            .await;
+        assert_eq!(balance["balance"], "850.00");
+
+        let (_, accounts) = app.request(Method::GET, "/accounts", None).await;
+        assert_eq!(accounts.as_array().unwrap().len(), 4);
+
+        // Empty files are refused as a whole
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/accounts/import",
+                Some(json!({"accounts": []})),
+            )
+            .await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_query_parameters_are_refused() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        let id = &account["id"];
+
+        for (method, uri) in [
+            (Method::GET, "/accounts?include_archive=true".to_string()),
+            (
+                Method::GET,
+                format!("/accounts/{id}/balance/history?granularity=day&from=2024-06-01"),
+            ),
+            (
+                Method::DELETE,
+                format!("/accounts/{id}/transactions?before=2024-06-01&dryrun=true"),
+            ),
+        ] {
+            let (status, body) = app.request(method, &uri, None).await;
+            assert_eq!(status, 400, "{uri}");
+            assert_eq!(body["error"], "invalid_query");
+            assert_eq!(body["details"]["kind"], "unknown_field", "{uri}");
+        }
+    }
+}
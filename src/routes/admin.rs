@@ -0,0 +1,779 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::header::SET_COOKIE,
+    middleware,
+    response::IntoResponse,
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson, ValidatedQuery},
+        pagination::{Page, PageParams},
+        state::AppState,
+    },
+    audit,
+    clock::Clock,
+    database::{
+        connection::DbPool,
+        models::{
+            audit_events::{AuditEvent, AuditEventKind, AuditFilter, NewAuditEvent},
+            exchange_rates::ExchangeRate,
+            sessions::{keys::JwtKeys, manager::Session},
+            users::{User, UserPublic, UserSort},
+        },
+    },
+    errors::AppError,
+    maintenance::{Maintenance, MaintenanceMode, MaintenanceStatus, DEFAULT_RETRY_AFTER},
+    middleware::forwarded::Client,
+    reports::cache::ReportCache,
+    routes::auth::SessionConfig,
+    search::ilike::like_pattern,
+};
+
+/// Number of audit events in a page when no limit is given
+const DEFAULT_AUDIT_LIMIT: i64 = 50;
+
+/// Maximum number of audit events in a page
+const MAX_AUDIT_LIMIT: i64 = 500;
+
+/// How long administrators can act as a user before starting again
+const IMPERSONATION_TTL: chrono::Duration = chrono::Duration::minutes(15);
+
+/// Save exchange rates request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(save_exchange_rates))]
+#[schema(example = json!({"rates": [{"base": "USD", "quote": "CAD", "as_of": "2024-06-01", "rate": "1.3712"}]}))]
+pub struct SaveExchangeRates {
+    /// The rates to save, replacing the rates of the same pairs and days
+    rates: Vec<ExchangeRate>,
+}
+
+impl Validate for SaveExchangeRates {
+    fn validate(&self) -> Result<(), AppError> {
+        self.rates.iter().try_for_each(ExchangeRate::validate)
+    }
+}
+
+/// Outcome of saving exchange rates
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SavedExchangeRates {
+    /// Number of rates saved
+    saved: usize,
+}
+
+/// Set maintenance request body
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"mode": "read_only", "message": "Back at 10:00", "retry_after": 600}))]
+pub struct SetMaintenance {
+    /// `off`, `read_only` to reject writes, or `full` to reject everything but the vitals,
+    /// metrics, logins and administration routes
+    mode: MaintenanceMode,
+    /// Why requests are rejected, sent to clients as the message of the error
+    message: Option<String>,
+    /// Seconds clients are told to wait before retrying (60 by default)
+    retry_after: Option<u64>,
+}
+
+impl Validate for SetMaintenance {}
+
+/// Audit log query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct AuditParams {
+    /// Only events about this user
+    user_id: Option<i32>,
+    /// Only events of this kind, such as `login_failure`
+    event: Option<AuditEventKind>,
+    /// Only events on or after this day
+    from: Option<NaiveDate>,
+    /// Only events on or before this day
+    to: Option<NaiveDate>,
+    /// Only events older than this one, set to the `next_before` of the previous page
+    before: Option<i32>,
+    /// Maximum number of events in the page (50 by default, at most 500)
+    limit: Option<i64>,
+}
+
+impl Validate for AuditParams {}
+
+/// User listing query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct UserParams {
+    /// Only users whose username contains this text, ignoring case
+    q: Option<String>,
+    /// `username` (the default), `created_at`, or either with `-` to sort in descending order
+    sort: Option<UserSort>,
+    /// The page to list, from 1 (1 by default)
+    ///
+    /// Pages are read by `PageParams`, they're only accepted here so that they aren't unknown.
+    #[serde(rename = "page")]
+    _page: Option<u64>,
+    /// Number of items in a page (20 by default, at most 100)
+    #[serde(rename = "per_page")]
+    _per_page: Option<u64>,
+}
+
+impl Validate for UserParams {}
+
+/// A page of the audit log
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditPage {
+    /// The events, newest first
+    events: Vec<AuditEvent>,
+    /// The `before` parameter of the next page, `null` on the last page
+    next_before: Option<i32>,
+}
+
+/// A session started by an administrator to act as a user
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Impersonation {
+    /// The user the administrator acts as
+    user: UserPublic,
+    /// ID of the session, which the response sets the cookie of
+    session_id: i32,
+    /// When the session expires, 15 minutes after it started
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    expires_at: chrono::NaiveDateTime,
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    // Layers run from the last one added, so the session is set before the user is checked
+    Router::new()
+        .route("/admin/exchange-rates", put(save_exchange_rates))
+        .route("/admin/audit", get(get_audit_events))
+        .route("/admin/maintenance", post(set_maintenance))
+        .route("/admin/users", get(all_users))
+        .route(
+            "/admin/users/:id/impersonate",
+            // Sessions acting as an administrator can't start another one
+            post(impersonate_user).layer(middleware::from_fn_with_state(
+                state.clone(),
+                crate::middleware::auth::reject_impersonation,
+            )),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            crate::middleware::auth::admin_auth,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint saves exchange rates, for administrators only
+///
+/// Reports use the latest rate of a pair on or before the day of each transaction. Either all
+/// rates are saved or none are.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the number of rates saved.
+/// `400` : A currency code isn't three uppercase letters, a pair has the same currency twice, or
+/// a rate isn't positive.
+/// `403` : The user isn't an administrator.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    put,
+    path = "/admin/exchange-rates",
+    security(("cookieAuth" = [])),
+    request_body = SaveExchangeRates,
+    responses(
+        (status = 200, description = "Rates saved", body = SavedExchangeRates),
+        (status = 400, description = "Invalid rate"),
+        (status = 403, description = "User is not an administrator")
+    )
+)]
+async fn save_exchange_rates(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    ValidatedJson(payload): ValidatedJson<SaveExchangeRates>,
+) -> Result<Json<SavedExchangeRates>, AppError> {
+    pool.run(move |conn| {
+        let saved = ExchangeRate::upsert(conn, &payload.rates)?;
+        // Rates are shared, every converted report may have changed
+        reports.clear();
+
+        Ok(Json(SavedExchangeRates { saved }))
+    })
+    .await
+}
+
+/// This endpoint puts the server in maintenance or takes it out of it, for administrators only
+///
+/// Requests the mode rejects are answered with `503` and the message, whether they're
+/// authenticated or not. The mode is kept in the data directory, so restarts keep it.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the maintenance the server is in.
+/// `403` : The user isn't an administrator.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/admin/maintenance",
+    security(("cookieAuth" = [])),
+    request_body = SetMaintenance,
+    responses(
+        (status = 200, description = "Maintenance set", body = MaintenanceStatus),
+        (status = 403, description = "User is not an administrator")
+    )
+)]
+async fn set_maintenance(
+    Extension(maintenance): Extension<Arc<Maintenance>>,
+    ValidatedJson(payload): ValidatedJson<SetMaintenance>,
+) -> Result<Json<MaintenanceStatus>, AppError> {
+    let status = MaintenanceStatus {
+        mode: payload.mode,
+        message: payload.message,
+        retry_after: payload.retry_after.unwrap_or(DEFAULT_RETRY_AFTER),
+    };
+    maintenance.set(status.clone()).await?;
+    tracing::warn!("Maintenance mode set to {:?}", status.mode);
+
+    Ok(Json(status))
+}
+
+/// This endpoint lists the users, for administrators only
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a page of users, with the other pages in `Link`.
+/// `400` : A parameter can't be read or is unknown, or the page is invalid.
+/// `403` : The user isn't an administrator.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    security(("cookieAuth" = [])),
+    params(UserParams),
+    responses(
+        (status = 200, description = "Page of users", body = UserPage),
+        (status = 400, description = "Invalid parameters or page"),
+        (status = 403, description = "User is not an administrator")
+    )
+)]
+async fn all_users(
+    State(pool): State<Arc<DbPool>>,
+    ValidatedQuery(params): ValidatedQuery<UserParams>,
+    page: PageParams,
+) -> Result<Page<UserPublic>, AppError> {
+    pool.run_read(move |conn| {
+        let pattern = params.q.as_deref().map(like_pattern);
+        let (total, users) = User::get_page(
+            conn,
+            pattern.as_deref(),
+            params.sort.unwrap_or_default(),
+            page.limit(),
+            page.offset(),
+        )?;
+
+        Ok(page.into_page(users, total))
+    })
+    .await
+}
+
+/// This endpoint starts a session as a user, for administrators only
+///
+/// Support staff see what the user sees with the cookie the response sets, for 15 minutes. Every
+/// request of the session is recorded in the audit log with the administrator, and the session
+/// can't change the credentials of the user nor delete the user or their data.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Sets the cookie of the session and returns the user and when
+/// the session expires.
+/// `400` : The user is the administrator.
+/// `403` : The user isn't an administrator, is already acting as a user, or the user to act as is
+/// an administrator.
+/// `404` : The user doesn't exist.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/admin/users/{id}/impersonate",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the user to act as")),
+    responses(
+        (status = 200, description = "Session started as the user", body = Impersonation),
+        (status = 400, description = "The user is the administrator", body = ErrorBody),
+        (status = 403, description = "User is not an administrator, or the user to act as is", body = ErrorBody),
+        (status = 404, description = "User not found", body = ErrorBody)
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn impersonate_user(
+    State(pool): State<Arc<DbPool>>,
+    State(keys): State<Arc<JwtKeys>>,
+    State(sessions): State<SessionConfig>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    Extension(client): Extension<Client>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let now = clock.now_utc();
+    pool.run(move |conn| {
+        if id == session.user_id() {
+            return Err(AppError::InvalidInput(
+                "Administrators can't act as themselves".to_string(),
+            ));
+        }
+        let user = User::from_id(conn, id)?;
+        // Acting as another administrator would grant their access beyond the audit of the session
+        if user.is_admin() {
+            tracing::warn!(
+                "Administrator {} can't act as administrator {id}",
+                session.user_id()
+            );
+            return Err(AppError::Forbidden);
+        }
+        let (impersonation, token) =
+            Session::impersonate(conn, &keys, id, session.user_id(), IMPERSONATION_TTL, now)?;
+        audit::record(
+            conn,
+            NewAuditEvent::new(
+                AuditEventKind::ImpersonationStarted,
+                Some(id),
+                serde_json::json!({
+                    "impersonator_user_id": session.user_id(),
+                    "session_id": impersonation.id(),
+                    "expires_at": crate::utils::serialization::format(&impersonation.expires_at()),
+                }),
+            )
+            .with_ip(client.ip()),
+        );
+        tracing::warn!("Administrator {} is acting as user {id}", session.user_id());
+
+        let cookie = sessions.cookie(&token, &client);
+        Ok((
+            [(SET_COOKIE, cookie)],
+            Json(Impersonation {
+                user: user.to_public(),
+                session_id: impersonation.id(),
+                expires_at: impersonation.expires_at(),
+            }),
+        ))
+    })
+    .await
+}
+
+/// This endpoint lists security-relevant events, for administrators only
+///
+/// Logins, lockouts, password changes, revoked sessions, deleted users and plans, and the
+/// requests of administrators acting as users are recorded. Events are listed newest first, a page at a time.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a page of events.
+/// `400` : A parameter can't be read or is unknown, or the limit isn't between 1 and 500.
+/// `403` : The user isn't an administrator.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    security(("cookieAuth" = [])),
+    params(AuditParams),
+    responses(
+        (status = 200, description = "Page of audit events", body = AuditPage),
+        (status = 400, description = "Invalid parameters or limit"),
+        (status = 403, description = "User is not an administrator")
+    )
+)]
+async fn get_audit_events(
+    State(pool): State<Arc<DbPool>>,
+    ValidatedQuery(params): ValidatedQuery<AuditParams>,
+) -> Result<Json<AuditPage>, AppError> {
+    let limit = params.limit.unwrap_or(DEFAULT_AUDIT_LIMIT);
+    if !(1..=MAX_AUDIT_LIMIT).contains(&limit) {
+        return Err(AppError::InvalidInput(format!(
+            "The limit must be between 1 and {MAX_AUDIT_LIMIT}"
+        )));
+    }
+    pool.run_read(move |conn| {
+        let filter = AuditFilter {
+            user_id: params.user_id,
+            event: params.event,
+            from: params.from,
+            to: params.to,
+            before: params.before,
+        };
+        let events = AuditEvent::get_page(conn, &filter, limit)?;
+        let next_before = match events.last() {
+            Some(last) if events.len() as i64 == limit => Some(last.id()),
+            _ => None,
+        };
+
+        Ok(Json(AuditPage {
+            events,
+            next_before,
+        }))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, Method};
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::{session_cookie, TestApp};
+    use crate::clock::Clock;
+    use crate::config::logging::{self, CapturedLogs, LogFormat};
+    use crate::database::models::users::User;
+
+    #[tokio::test]
+    async fn test_converted_reports() {
+        let app = TestApp::new();
+        let rates = json!({"rates": [
+            {"base": "USD", "quote": "CAD", "as_of": "2024-07-01", "rate": "1.3650"},
+            {"base": "USD", "quote": "CAD", "as_of": "2024-07-10", "rate": "1.3725"}
+        ]});
+
+        let (status, _) = app
+            .request(Method::PUT, "/admin/exchange-rates", Some(rates.clone()))
+            .await;
+        assert_eq!(status, 403);
+        app.make_admin();
+
+        let (status, error) = app
+            .request(
+                Method::PUT,
+                "/admin/exchange-rates",
+                Some(json!({"rates": [{"base": "USD", "quote": "USD", "as_of": "2024-07-01", "rate": "1"}]})),
+            )
+            .await;
+        assert_eq!(status, 400, "{error}");
+        let (status, saved) = app
+            .request(Method::PUT, "/admin/exchange-rates", Some(rates))
+            .await;
+        assert_eq!(status, 200, "{saved}");
+        assert_eq!(saved["saved"], 2);
+
+        for (name, currency) in [("Chequing", "CAD"), ("US chequing", "USD"), ("Euro", "EUR")] {
+            let (_, account) = app
+                .request(
+                    Method::POST,
+                    "/accounts",
+                    Some(json!({"name": name, "opening_balance": "0", "currency": currency})),
+                )
+                .await;
+            let uri = format!("/accounts/{}/transactions", account["id"]);
+            for (amount, day) in [("-10.00", "05"), ("-20.00", "15")] {
+                let (status, _) = app
+                    .request(
+                        Method::POST,
+                        &uri,
+                        Some(json!({"amount": amount, "description": name, "occurred_at": format!("2024-07-{day}")})),
+                    )
+                    .await;
+                assert_eq!(status, 201);
+            }
+        }
+
+        let (status, error) = app
+            .request(
+                Method::GET,
+                "/reports/monthly?year=2024&month=7&convert=true",
+                None,
+            )
+            .await;
+        assert_eq!(status, 422);
+        assert_eq!(error["code"], 40010);
+        assert_eq!(error["message"], "Missing exchange rates for EUR/CAD");
+
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                "/admin/exchange-rates",
+                Some(json!({"rates": [{"base": "CAD", "quote": "EUR", "as_of": "2024-06-28", "rate": "0.6250"}]})),
+            )
+            .await;
+        assert_eq!(status, 200);
+
+        // 30.00 CAD, 10.00 USD at 1.3650 and 20.00 USD at 1.3725, 30.00 EUR at 1 / 0.6250
+        let (status, summary) = app
+            .request(
+                Method::GET,
+                "/reports/monthly?year=2024&month=7&convert=true",
+                None,
+            )
+            .await;
+        assert_eq!(status, 200, "{summary}");
+        assert_eq!(summary["currency"], "CAD");
+        assert_eq!(summary["expenses"], "119.10");
+
+        let (status, summary) = app
+            .request(Method::GET, "/reports/monthly?year=2024&month=7", None)
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(summary["currency"], serde_json::Value::Null);
+        assert_eq!(summary["expenses"], "90.00");
+    }
+
+    #[tokio::test]
+    async fn test_user_pages() {
+        let app = TestApp::new();
+        {
+            let mut conn = app.pool().get().unwrap();
+            for name in ["alice", "alina", "bob", "malik"] {
+                User::new(&mut conn, name, "password").unwrap();
+            }
+        }
+        let (status, _, _) = app.download("/api/v1/admin/users").await;
+        assert_eq!(status, 403);
+        app.make_admin();
+
+        // Other query parameters are kept in the links to other pages
+        let uri = "/api/v1/admin/users?q=AL&sort=-username&per_page=2&page=1";
+        let (status, headers, body) = app.download(uri).await;
+        assert_eq!(status, 200);
+        let page: Value = serde_json::from_slice(&body).unwrap();
+        let names: Vec<&str> = page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|user| user["username"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["malik", "alina"]);
+        assert_eq!(page["page"], 1);
+        assert_eq!(page["per_page"], 2);
+        assert_eq!(page["total"], 3);
+        assert_eq!(page["total_pages"], 2);
+        assert!(page["items"][0].get("pw_hash").is_none());
+        assert_eq!(
+            headers[header::LINK],
+            "</api/v1/admin/users?q=AL&sort=-username&page=1&per_page=2>; rel=\"first\", \
+             </api/v1/admin/users?q=AL&sort=-username&page=2&per_page=2>; rel=\"next\", \
+             </api/v1/admin/users?q=AL&sort=-username&page=2&per_page=2>; rel=\"last\""
+        );
+
+        let (status, _, body) = app
+            .download("/api/v1/admin/users?q=AL&sort=-username&page=2&per_page=2")
+            .await;
+        assert_eq!(status, 200);
+        let page: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page["items"][0]["username"], "alice");
+
+        // Pages are bound
+        for query in ["per_page=0", "per_page=101", "page=0"] {
+            let (status, body) = app
+                .request(Method::GET, &format!("/api/v1/admin/users?{query}"), None)
+                .await;
+            assert_eq!(status, 400, "{query}");
+            assert_eq!(body["error"], "invalid_input");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_administrators_cant_be_impersonated() {
+        let app = TestApp::new();
+        app.make_admin();
+        let admin_id = {
+            let mut conn = app.pool().get().unwrap();
+            let admin = User::new(&mut conn, "root", "correct-horse").unwrap();
+            admin.set_admin(&mut conn, true).unwrap();
+            admin.id()
+        };
+
+        let (status, body) = app
+            .request(
+                Method::POST,
+                &format!("/admin/users/{admin_id}/impersonate"),
+                None,
+            )
+            .await;
+        assert_eq!(status, 403);
+        assert_eq!(body["error"], "forbidden");
+
+        // No session is started nor recorded
+        let (status, page) = app
+            .request(
+                Method::GET,
+                &format!("/admin/audit?user_id={admin_id}&event=impersonation_started"),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200);
+        assert!(page["events"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_impersonation() {
+        let app = TestApp::new();
+        let user_id = {
+            let mut conn = app.pool().get().unwrap();
+            User::new(&mut conn, "jane", "correct-horse").unwrap().id()
+        };
+        let uri = format!("/admin/users/{user_id}/impersonate");
+        let (status, _) = app.request(Method::POST, &uri, None).await;
+        assert_eq!(status, 403);
+        app.make_admin();
+
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/admin/users/{}/impersonate", app.user_id()),
+                None,
+            )
+            .await;
+        assert_eq!(status, 400);
+        let (status, _) = app
+            .request(Method::POST, "/admin/users/0/impersonate", None)
+            .await;
+        assert_eq!(status, 404);
+
+        let (status, headers, started) = app
+            .request_with_cookie(app.cookie(), Method::POST, &uri, None)
+            .await;
+        assert_eq!(status, 200, "{started}");
+        assert_eq!(started["user"]["username"], "jane");
+        assert_eq!(
+            started["expires_at"],
+            crate::utils::serialization::format(
+                &(app.clock().now_utc() + chrono::Duration::minutes(15))
+            )
+        );
+        let cookie = session_cookie(&headers);
+
+        // The administrator sees what the user sees, and the access log tells who acted
+        let logs = CapturedLogs::default();
+        let guard = tracing::subscriber::set_default(logging::subscriber(
+            LogFormat::Json,
+            logging::filter(Some("finance_fusion_server::middleware::access_log=info")).unwrap(),
+            logs.clone(),
+        ));
+        let (status, _, account) = app
+            .request_with_cookie(
+                &cookie,
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0", "currency": "CAD"})),
+            )
+            .await;
+        assert_eq!(status, 201, "{account}");
+        assert_eq!(account["user_id"], user_id);
+        drop(guard);
+        let line = logs.json_lines().pop().unwrap();
+        assert_eq!(line["user_id"], user_id);
+        assert_eq!(line["impersonator_user_id"], app.user_id());
+
+        let (status, _, sessions) = app
+            .request_with_cookie(&cookie, Method::GET, "/auth/sessions", None)
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(sessions[0]["id"], started["session_id"]);
+        assert_eq!(sessions[0]["current"], true);
+        assert_eq!(sessions[0]["impersonated"], true);
+        assert_eq!(sessions[0]["impersonator_user_id"], app.user_id());
+        let (_, _, own) = app
+            .request_with_cookie(app.cookie(), Method::GET, "/auth/sessions", None)
+            .await;
+        assert_eq!(own[0]["impersonated"], false);
+        assert_eq!(own[0]["impersonator_user_id"], Value::Null);
+
+        // Only the user can change their credentials, or delete themselves or their data
+        for (method, uri, body) in [
+            (
+                Method::PUT,
+                "/users/me/password".to_string(),
+                Some(
+                    json!({"current_password": "correct-horse", "new_password": "staple-tuna-orbit"}),
+                ),
+            ),
+            (
+                Method::PUT,
+                format!("/users/{user_id}"),
+                Some(json!({"name": "janet"})),
+            ),
+            (Method::DELETE, format!("/users/{user_id}"), None),
+            (
+                Method::DELETE,
+                "/users/me/data?confirm=jane".to_string(),
+                None,
+            ),
+            (
+                Method::POST,
+                format!("/admin/users/{user_id}/impersonate"),
+                None,
+            ),
+        ] {
+            let (status, _, error) = app
+                .request_with_cookie(&cookie, method.clone(), &uri, body)
+                .await;
+            assert_eq!(status, 403, "{method} {uri}");
+            assert_eq!(error["error"], "forbidden");
+        }
+        let mut conn = app.pool().get().unwrap();
+        let user = User::from_id(&mut conn, user_id).unwrap();
+        assert!(user.check_password("correct-horse"));
+        drop(conn);
+
+        // Every request of the session is recorded with the administrator
+        let (status, page) = app
+            .request(
+                Method::GET,
+                &format!("/admin/audit?user_id={user_id}&event=impersonated_request"),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200);
+        let requests = page["events"].as_array().unwrap();
+        assert_eq!(requests.len(), 7);
+        assert!(requests.iter().all(|event| {
+            event["metadata"]["impersonator_user_id"] == app.user_id()
+                && event["metadata"]["session_id"] == started["session_id"]
+        }));
+        assert_eq!(requests[6]["metadata"]["method"], "POST");
+        assert_eq!(requests[6]["metadata"]["path"], "/accounts");
+        let (_, page) = app
+            .request(
+                Method::GET,
+                &format!("/admin/audit?user_id={user_id}&event=impersonation_started"),
+                None,
+            )
+            .await;
+        assert_eq!(page["events"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            page["events"][0]["metadata"]["impersonator_user_id"],
+            app.user_id()
+        );
+
+        // The session ends after 15 minutes, the administrator's own session doesn't
+        app.clock().advance(chrono::Duration::minutes(15));
+        let (status, _, _) = app
+            .request_with_cookie(&cookie, Method::GET, "/accounts", None)
+            .await;
+        assert_eq!(status, 401);
+        let (status, _) = app.request(Method::GET, "/accounts", None).await;
+        assert_eq!(status, 200);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_query_parameters_are_refused() {
+        let app = TestApp::new();
+        app.make_admin();
+
+        for uri in [
+            "/admin/users?q=al&order=username",
+            "/admin/audit?kind=login_failure",
+        ] {
+            let (status, body) = app.request(Method::GET, uri, None).await;
+            assert_eq!(status, 400, "{uri}");
+            assert_eq!(body["error"], "invalid_query");
+            assert_eq!(body["details"]["kind"], "unknown_field", "{uri}");
+        }
+        // Pages are still read along with the other parameters
+        let (status, _) = app
+            .request(Method::GET, "/admin/users?q=al&page=1&per_page=5", None)
+            .await;
+        assert_eq!(status, 200);
+    }
+}
@@ -0,0 +1,398 @@
+use std::path::Path as FilePath;
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, Multipart, Path, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware,
+    response::Response,
+    routing::get,
+    Extension, Json, Router,
+};
+use tower_http::services::ServeFile;
+use utoipa::ToSchema;
+
+use crate::{
+    api::{responses::MessageResponse, state::AppState},
+    database::{
+        connection::DbPool,
+        models::{
+            attachments::{validate_content_type, Attachment},
+            sessions::manager::Session,
+            transactions::Transaction,
+        },
+    },
+    errors::AppError,
+    quotas::Quotas,
+    storage::attachments::AttachmentStore,
+};
+
+/// Maximum size of an attached file (10 MiB)
+const MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+
+/// Maximum length of the name of an attached file
+const MAX_FILENAME_LENGTH: usize = 255;
+
+/// Attachment upload request body, sent as `multipart/form-data`
+#[derive(ToSchema)]
+pub struct AttachmentForm {
+    /// The file, a PDF or an image
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+pub fn create_route(state: AppState, body_limit: usize) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/transactions/:id/attachments",
+            get(all_attachments).post(upload_attachment),
+        )
+        .route(
+            "/attachments/:id",
+            get(get_attachment).delete(delete_attachment),
+        )
+        .layer(DefaultBodyLimit::max(body_limit))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// Keep the last component of an uploaded file name, so it can't name another directory
+fn clean_filename(filename: Option<&str>) -> String {
+    let filename = filename
+        .and_then(|name| FilePath::new(name).file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or("attachment");
+    filename.chars().take(MAX_FILENAME_LENGTH).collect()
+}
+
+/// This endpoint returns the files attached to a transaction
+///
+/// Attachments whose file the daily cleanup found gone have the `missing` status, and can't be
+/// downloaded.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of attachments, oldest first.
+/// `404` : The transaction doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/transactions/{id}/attachments",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the transaction")),
+    responses(
+        (status = 200, description = "Attachments of the transaction", body = Vec<Attachment>),
+        (status = 404, description = "Transaction not found")
+    )
+)]
+async fn all_attachments(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<Attachment>>, AppError> {
+    pool.run_read(move |conn| {
+        let transaction = Transaction::from_id(conn, id, session.user_id())?;
+        let attachments = Attachment::get_all(conn, &transaction)?;
+
+        Ok(Json(attachments))
+    })
+    .await
+}
+
+/// This endpoint attaches a file, such as a receipt, to a transaction
+///
+/// The request is a `multipart/form-data` body with a `file` part holding the file. The part's
+/// content type must be a PDF or an image (JPEG, PNG, GIF, WebP or HEIC), and the file can't be
+/// larger than 10 MiB.
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created attachment.
+/// `400` : The file is missing, too large, or of a type that can't be attached.
+/// `403` : The file doesn't fit the quota of attachment bytes of the user.
+/// `404` : The transaction doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/transactions/{id}/attachments",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the transaction")),
+    request_body(content = AttachmentForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "File attached", body = Attachment),
+        (status = 400, description = "Invalid file"),
+        (status = 403, description = "Over the quota of attachment bytes"),
+        (status = 404, description = "Transaction not found")
+    )
+)]
+async fn upload_attachment(
+    State(pool): State<Arc<DbPool>>,
+    State(quotas): State<Quotas>,
+    Extension(session): Extension<Session>,
+    Extension(store): Extension<Arc<AttachmentStore>>,
+    Path(id): Path<i32>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<Attachment>), AppError> {
+    let user_id = session.user_id();
+    let transaction = pool
+        .run(move |conn| Transaction::from_id(conn, id, user_id))
+        .await?;
+
+    let mut file = None;
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let content_type = field.content_type().unwrap_or_default().to_string();
+        validate_content_type(&content_type)?;
+        let filename = clean_filename(field.file_name());
+        let bytes = field.bytes().await?;
+        file = Some((filename, content_type, bytes));
+    }
+
+    let (filename, content_type, bytes) =
+        file.ok_or_else(|| AppError::InvalidInput("Missing \"file\" part".to_string()))?;
+    if bytes.len() > MAX_ATTACHMENT_BYTES {
+        return Err(AppError::InvalidInput(format!(
+            "Attachments can't be larger than {} MiB",
+            MAX_ATTACHMENT_BYTES / 1024 / 1024
+        )));
+    }
+
+    let storage_path = store.write(transaction.id(), &bytes).await?;
+    let size = bytes.len() as i64;
+    let path = storage_path.clone();
+    let attachment = pool
+        .run(move |conn| {
+            Attachment::new(
+                conn,
+                &transaction,
+                &filename,
+                &content_type,
+                size,
+                &path,
+                &quotas,
+            )
+        })
+        .await;
+    match attachment {
+        Ok(attachment) => Ok((StatusCode::CREATED, Json(attachment))),
+        Err(e) => {
+            // Don't leave a file behind without an attachment pointing to it
+            store.remove(&storage_path).await;
+            Err(e)
+        }
+    }
+}
+
+/// This endpoint downloads an attached file
+///
+/// The file is streamed with the content type it was uploaded with. Range requests are supported.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the contents of the file.
+/// `404` : The attachment doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/attachments/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the attachment")),
+    responses(
+        (status = 200, description = "Contents of the file", body = String, content_type = "application/octet-stream"),
+        (status = 404, description = "Attachment not found")
+    )
+)]
+async fn get_attachment(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Extension(store): Extension<Arc<AttachmentStore>>,
+    Path(id): Path<i32>,
+    request: Request,
+) -> Result<Response, AppError> {
+    let attachment = pool
+        .run(move |conn| Attachment::from_id(conn, id, session.user_id()))
+        .await?;
+
+    let response = ServeFile::new(store.path(attachment.storage_path()))
+        .try_call(request)
+        .await?;
+    let mut response = response.map(Body::new);
+    if response.status().is_success() {
+        // Quotes and control characters can't be part of the quoted file name
+        let filename: String = attachment
+            .filename()
+            .chars()
+            .filter(|c| (c.is_ascii_graphic() || *c == ' ') && *c != '"' && *c != '\\')
+            .collect();
+        let headers = response.headers_mut();
+        if let Ok(content_type) = HeaderValue::from_str(attachment.content_type()) {
+            headers.insert(header::CONTENT_TYPE, content_type);
+        }
+        if let Ok(disposition) = HeaderValue::from_str(&format!("inline; filename=\"{filename}\""))
+        {
+            headers.insert(header::CONTENT_DISPOSITION, disposition);
+        }
+    }
+
+    Ok(response)
+}
+
+/// This endpoint deletes an attachment and its file
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the attachment was deleted.
+/// `404` : The attachment doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/attachments/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the attachment")),
+    responses(
+        (status = 200, description = "Attachment deleted", body = MessageResponse),
+        (status = 404, description = "Attachment not found")
+    )
+)]
+async fn delete_attachment(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Extension(store): Extension<Arc<AttachmentStore>>,
+    Path(id): Path<i32>,
+) -> Result<Json<MessageResponse>, AppError> {
+    let attachment = pool
+        .run(move |conn| {
+            let attachment = Attachment::from_id(conn, id, session.user_id())?;
+            attachment.delete(conn)?;
+            Ok(attachment)
+        })
+        .await?;
+    store.remove(attachment.storage_path()).await;
+
+    Ok(Json(MessageResponse::new("Attachment deleted")))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, Method};
+    use serde_json::{json, Value};
+
+    use super::MAX_ATTACHMENT_BYTES;
+    use crate::api::test_utils::TestApp;
+
+    /// Create an account with a transaction, returning the URI of the transaction
+    async fn transaction(app: &TestApp) -> (String, Value) {
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0", "currency": "CAD"})),
+            )
+            .await;
+        let uri = format!("/accounts/{}/transactions", account["id"]);
+        let (status, transaction) = app
+            .request(
+                Method::POST,
+                &uri,
+                Some(json!({"amount": "-42.50", "description": "Hardware store", "occurred_at": "2024-07-05"})),
+            )
+            .await;
+        assert_eq!(status, 201, "{transaction}");
+
+        (format!("{uri}/{}", transaction["id"]), transaction)
+    }
+
+    fn stored_files(app: &TestApp) -> usize {
+        std::fs::read_dir(app.data_dir().join("attachments"))
+            .map(|entries| entries.count())
+            .unwrap_or(0)
+    }
+
+    #[tokio::test]
+    async fn test_attachments() {
+        let app = TestApp::new();
+        let (_, transaction) = transaction(&app).await;
+        let uri = format!("/transactions/{}/attachments", transaction["id"]);
+        let receipt: Vec<u8> = (0..=255u8).cycle().take(3000).collect();
+
+        let (status, attachment) = app
+            .upload(&uri, "../receipts/receipt.png", "image/png", &receipt)
+            .await;
+        assert_eq!(status, 201, "{attachment}");
+        assert_eq!(attachment["filename"], "receipt.png");
+        assert_eq!(attachment["content_type"], "image/png");
+        assert_eq!(attachment["size_bytes"], 3000);
+        assert!(attachment.get("storage_path").is_none());
+
+        let (status, error) = app
+            .upload(&uri, "notes.txt", "text/plain", b"not a receipt")
+            .await;
+        assert_eq!(status, 400, "{error}");
+        let (status, error) = app
+            .upload(
+                &uri,
+                "huge.pdf",
+                "application/pdf",
+                &vec![0; MAX_ATTACHMENT_BYTES + 1],
+            )
+            .await;
+        assert_eq!(status, 400, "{error}");
+        let (status, _) = app
+            .upload(
+                "/transactions/0/attachments",
+                "receipt.png",
+                "image/png",
+                &receipt,
+            )
+            .await;
+        assert_eq!(status, 404);
+        assert_eq!(stored_files(&app), 1);
+
+        let (status, attachments) = app.request(Method::GET, &uri, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(attachments, json!([attachment]));
+
+        let attachment_uri = format!("/attachments/{}", attachment["id"]);
+        let (status, headers, bytes) = app.download(&attachment_uri).await;
+        assert_eq!(status, 200);
+        assert_eq!(headers[header::CONTENT_TYPE], "image/png");
+        assert_eq!(bytes.as_ref(), receipt.as_slice());
+
+        let (status, _) = app.request(Method::DELETE, &attachment_uri, None).await;
+        assert_eq!(status, 200);
+        let (status, _, _) = app.download(&attachment_uri).await;
+        assert_eq!(status, 404);
+        assert_eq!(stored_files(&app), 0);
+    }
+
+    #[tokio::test]
+    async fn test_deleting_transaction_removes_attachments() {
+        let app = TestApp::new();
+        let (transaction_uri, transaction) = transaction(&app).await;
+        let uri = format!("/transactions/{}/attachments", transaction["id"]);
+
+        let (_, receipt) = app
+            .upload(&uri, "receipt.pdf", "application/pdf", b"%PDF-1.7")
+            .await;
+        app.upload(&uri, "photo.jpg", "image/jpeg", b"\xff\xd8\xff")
+            .await;
+        assert_eq!(stored_files(&app), 2);
+
+        let (status, _) = app.request(Method::DELETE, &transaction_uri, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(stored_files(&app), 0);
+
+        let (status, _, _) = app
+            .download(&format!("/attachments/{}", receipt["id"]))
+            .await;
+        assert_eq!(status, 404);
+        let (status, _) = app.request(Method::GET, &uri, None).await;
+        assert_eq!(status, 404);
+    }
+}
@@ -2,113 +2,685 @@ use std::sync::Arc;
 
 use axum::{
     extract::State,
-    http::{header::SET_COOKIE, StatusCode},
+    http::{header::SET_COOKIE, HeaderMap, StatusCode},
     middleware,
     response::IntoResponse,
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
 use serde::{Deserialize, Serialize};
 use utoipa::{OpenApi, ToSchema};
 
 use crate::{
-    database::{connection::DbPool, models::users::User},
-    errors::AppError,
+    api::{
+        extract::{Validate, ValidatedJson},
+        responses::MessageResponse,
+        state::AppState,
+    },
+    audit,
+    clock::Clock,
+    database::{
+        connection::{DbConn, DbPool},
+        models::{
+            audit_events::{AuditEventKind, NewAuditEvent},
+            sessions::{keys::JwtKeys, manager::Session},
+            users::User,
+        },
+    },
+    errors::{AppError, AuthenticateError},
+    events::{EventBus, UserEvent},
+    metrics::Metrics,
+    middleware::{auth::token_cookie, forwarded::Client},
+    utils::sensitive::Sensitive,
 };
 
 /// This struct represents the user login request body
 #[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
 #[openapi(paths(login))]
+#[schema(example = json!({"username": "jane", "password": "correct-horse-battery"}))]
 pub struct LoginInfo {
     /// The username of the user
     username: String,
     /// The password of the user
-    password: String,
+    #[schema(value_type = String, format = Password)]
+    password: Sensitive<String>,
 }
 
-pub fn create_route(pool: Arc<DbPool>) -> Router<Arc<DbPool>> {
-    Router::new().route("/auth/login", post(login)).route(
-        "/auth/logout",
-        get(logout).layer(middleware::from_fn_with_state(
-            pool.clone(),
-            crate::middleware::auth::jwt_auth,
-        )),
-    )
+impl Validate for LoginInfo {
+    fn validate(&self) -> Result<(), AppError> {
+        if self.username.trim().is_empty() {
+            return Err(AppError::InvalidInput(
+                "The username can't be empty".to_string(),
+            ));
+        }
+        if self.password.expose().is_empty() {
+            return Err(AppError::InvalidInput(
+                "The password can't be empty".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A session of the authenticated user
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionInfo {
+    /// ID of the session
+    id: i32,
+    /// When the session started
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    created_at: chrono::NaiveDateTime,
+    /// When the session expires
+    #[serde(with = "crate::utils::serialization")]
+    #[schema(value_type = String)]
+    expires_at: chrono::NaiveDateTime,
+    /// Whether the request was made with this session
+    current: bool,
+    /// Whether an administrator is acting as the user with this session
+    impersonated: bool,
+    /// ID of the administrator acting as the user, if any
+    impersonator_user_id: Option<i32>,
+}
+
+impl SessionInfo {
+    fn new(session: &Session, current: &Session) -> Self {
+        Self {
+            id: session.id(),
+            created_at: session.created_at(),
+            expires_at: session.expires_at(),
+            current: session.id() == current.id(),
+            impersonated: session.impersonator_user_id().is_some(),
+            impersonator_user_id: session.impersonator_user_id(),
+        }
+    }
+}
+
+/// Which cross-site requests the session cookie is sent with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    /// Get the value of the `SameSite` attribute of the cookie
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// How long sessions last and how their cookies are set
+#[derive(Debug, Clone, Copy)]
+pub struct SessionConfig {
+    /// How long a session lasts after logging in
+    pub ttl: chrono::Duration,
+    /// Whether the cookie is only sent over HTTPS
+    pub secure_cookie: bool,
+    /// Which cross-site requests the cookie is sent with
+    pub same_site: SameSite,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            ttl: chrono::Duration::days(1),
+            secure_cookie: true,
+            same_site: SameSite::Strict,
+        }
+    }
+}
+
+impl SessionConfig {
+    /// Build the cookie holding the token of a session
+    ///
+    /// The cookie is only sent over HTTPS when configured so, or when the client reached the
+    /// server over HTTPS.
+    pub fn cookie(&self, token: &str, client: &Client) -> String {
+        let secure = if self.secure_cookie || client.https {
+            " Secure;"
+        } else {
+            ""
+        };
+        format!(
+            "token={token}; HttpOnly;{secure} SameSite={}; Path=/",
+            self.same_site.as_str()
+        )
+    }
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/auth/login", post(login))
+        .route(
+            "/auth/logout",
+            get(logout).layer(middleware::from_fn_with_state(
+                state.clone(),
+                crate::middleware::auth::jwt_auth,
+            )),
+        )
+        .route(
+            "/auth/sessions",
+            get(all_sessions).layer(middleware::from_fn_with_state(
+                state,
+                crate::middleware::auth::jwt_auth,
+            )),
+        )
 }
 
 /// This endpoint logs a user in
 ///
 /// ## Responses
-/// `200` : A successful response. Returns a a "Login successful" message.
+/// `200` : A successful response. Sets the session cookie and returns a message.
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
     post,
     path = "/auth/login",
-    responses((status = 200, description = "Login successful"))
+    request_body = LoginInfo,
+    responses(
+        (status = 200, description = "Login successful", body = MessageResponse),
+        (status = 401, description = "Wrong credentials", body = ErrorBody)
+    )
 )]
+#[allow(clippy::too_many_arguments)]
 async fn login(
     State(pool): State<Arc<DbPool>>,
-    Json(info): Json<LoginInfo>,
+    State(keys): State<Arc<JwtKeys>>,
+    State(sessions): State<SessionConfig>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(metrics): Extension<Arc<Metrics>>,
+    Extension(client): Extension<Client>,
+    headers: HeaderMap,
+    ValidatedJson(info): ValidatedJson<LoginInfo>,
 ) -> Result<impl IntoResponse, AppError> {
-    let mut conn = pool.get()?;
+    let previous_token = token_cookie(&headers).map(str::to_owned);
+    let now = clock.now_utc();
+    // Not in a transaction, the failures recorded below must persist although the login fails
+    pool.run(move |conn| {
+        let ip = client.ip();
 
-    let mut user = User::from_username(&mut conn, &info.username)?;
+        let mut user = match User::from_username(conn, &info.username) {
+            Ok(user) => user,
+            Err(e) => {
+                metrics.record_failed_login();
+                record_login_failure(conn, None, &info.username, "unknown_user", ip);
+                // Unknown users are refused as wrong passwords are, not to tell who has an account
+                return Err(match e {
+                    AppError::NotFound(_) => {
+                        AppError::Authenticate(AuthenticateError::WrongCredentials)
+                    }
+                    e => e,
+                });
+            }
+        };
 
-    let session = user.authenticate(&mut conn, &info.password)?;
+        if let Err(e) = user.authenticate(conn, info.password.expose(), now, ip.as_deref()) {
+            let reason = match e {
+                AppError::Authenticate(AuthenticateError::Locked(_)) => "locked",
+                AppError::Authenticate(AuthenticateError::WrongCredentials) => "wrong_credentials",
+                _ => "error",
+            };
+            metrics.record_failed_login();
+            if reason == "wrong_credentials" && user.is_locked() {
+                metrics.record_lockout();
+            }
+            record_login_failure(conn, Some(user.id()), &info.username, reason, ip);
+            return Err(e);
+        }
+        // A new session every time, even for clients that already have one, which is rolled back
+        // if its token can't be signed
+        let (session, token) = Session::issue(conn, &keys, user.id(), sessions.ttl, now)?;
+        metrics.record_login();
+        // The session the client had before logging in, if any, is replaced by the new one, so
+        // that a token planted before the login can't be used after it
+        if let Some(previous) =
+            previous_token.and_then(|token| Session::from_token(conn, &keys, &token, now).ok())
+        {
+            previous.revoke(conn, "rotated", ip.clone())?;
+        }
+        audit::record(
+            conn,
+            NewAuditEvent::new(
+                AuditEventKind::LoginSuccess,
+                Some(user.id()),
+                serde_json::json!({ "session_id": session.id() }),
+            )
+            .with_ip(ip),
+        );
 
-    let token = session.token()?;
+        let cookie = sessions.cookie(&token, &client);
+        let response = (
+            StatusCode::OK,
+            [(SET_COOKIE, cookie)],
+            Json(MessageResponse::new("Login successful")),
+        );
+        Ok(response)
+    })
+    .await
+}
 
-    let cookie = format!("token={token}; HttpOnly; Secure; SameSite=Strict; Path=/");
-    let response = (
-        StatusCode::OK,
-        [(SET_COOKIE, cookie)],
-        "Login successful".to_string(),
+/// Record a refused login
+fn record_login_failure(
+    conn: &mut DbConn,
+    user_id: Option<i32>,
+    username: &str,
+    reason: &str,
+    ip: Option<String>,
+) {
+    audit::record(
+        conn,
+        NewAuditEvent::new(
+            AuditEventKind::LoginFailure,
+            user_id,
+            serde_json::json!({ "username": username, "reason": reason }),
+        )
+        .with_ip(ip),
     );
-    Ok(response)
 }
 
-/// This endpoint logs a user out
+/// This endpoint logs a user out, revoking their session
 ///
 /// ## Responses
-/// `200` : A successful response. Returns a string indicating the user was logged out.
+/// `200` : A successful response. Returns a message telling the user was logged out.
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
     get,
     path = "/auth/logout",
-    responses((status = 200, description = "User logged out"))
+    security(("cookieAuth" = [])),
+    responses(
+        (status = 200, description = "User logged out", body = MessageResponse),
+        (status = 401, description = "User is not authenticated", body = ErrorBody)
+    )
 )]
-async fn logout() -> Result<String, AppError> {
-    // Here you would normally handle the logout process
-    // For simplicity, we'll just return a success message
-    Ok("Logged out".to_string())
+async fn logout(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    State(events): State<Arc<EventBus>>,
+    Extension(client): Extension<Client>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        session.revoke(conn, "logout", client.ip())?;
+        events.publish(
+            session.user_id(),
+            UserEvent::SessionRevoked,
+            &serde_json::json!({"session_id": session.id(), "reason": "logout"}),
+        );
+
+        Ok(Json(MessageResponse::new("Logged out")))
+    })
+    .await
 }
 
-/// This endpoint refreshes a user's session (TODO: Implement)
+/// This endpoint lists the sessions of the authenticated user that haven't expired
+///
+/// Sessions started by an administrator acting as the user are labeled as impersonated.
 ///
 /// ## Responses
-/// `200` : A successful response. Returns a a "Token refresh successful" message.
+/// `200` : A successful response. Returns the sessions, most recent first.
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
-    post,
-    path = "/auth/refresh",
-    responses((status = 200, description = "Token refresh successful"))
+    get,
+    path = "/auth/sessions",
+    security(("cookieAuth" = [])),
+    responses(
+        (status = 200, description = "Sessions of the user", body = Vec<SessionInfo>),
+        (status = 401, description = "User is not authenticated", body = ErrorBody)
+    )
 )]
-async fn refresh(State(pool): State<Arc<DbPool>>) -> Result<impl IntoResponse, AppError> {
-    // let mut conn = pool.get()?;
+async fn all_sessions(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+) -> Result<Json<Vec<SessionInfo>>, AppError> {
+    let now = clock.now_utc();
+    pool.run_read(move |conn| {
+        let sessions = Session::get_all_of_user(conn, session.user_id(), now)?;
+        Ok(Json(
+            sessions
+                .iter()
+                .map(|other| SessionInfo::new(other, &session))
+                .collect(),
+        ))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Method, Request},
+    };
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::api::test_utils::{session_cookie, TestApp};
+    use crate::config::logging::{self, CapturedLogs, LogFormat};
+    use crate::routes::users::{ChangePassword, CreateUser};
+
+    /// Get the recorded events of a kind, newest first
+    async fn events(app: &TestApp, event: &str) -> Vec<Value> {
+        let (status, page) = app
+            .request(Method::GET, &format!("/admin/audit?event={event}"), None)
+            .await;
+        assert_eq!(status, 200, "{page}");
+        page["events"].as_array().unwrap().clone()
+    }
+
+    fn keys(event: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = event["metadata"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    #[tokio::test]
+    async fn test_security_events_are_audited() {
+        let app = TestApp::new();
+        app.make_admin();
+        let wrong = json!({"username": "test_user", "password": "wrong_password"});
+
+        let (status, _) = app
+            .request(Method::POST, "/auth/login", Some(wrong.clone()))
+            .await;
+        assert_eq!(status, 401);
+        let failures = events(&app, "login_failure").await;
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0]["user_id"], app.user_id());
+        assert_eq!(keys(&failures[0]), ["reason", "username"]);
+        assert_eq!(failures[0]["metadata"]["reason"], "wrong_credentials");
+        assert!(events(&app, "lockout").await.is_empty());
+
+        // The third failed login in a row locks the user out
+        for _ in 0..2 {
+            app.request(Method::POST, "/auth/login", Some(wrong.clone()))
+                .await;
+        }
+        let lockouts = events(&app, "lockout").await;
+        assert_eq!(lockouts.len(), 1);
+        assert_eq!(
+            keys(&lockouts[0]),
+            ["invalid_login_attempts", "locked_until"]
+        );
+        assert_eq!(lockouts[0]["metadata"]["invalid_login_attempts"], 3);
+
+        // Changing the password rotates the session, the one of another user is changed
+        User::new(
+            &mut app.pool().get().unwrap(),
+            "other_user",
+            "other_password",
+        )
+        .unwrap();
+        let (status, headers, _) = app
+            .request_with_cookie(
+                "",
+                Method::POST,
+                "/auth/login",
+                Some(json!({"username": "other_user", "password": "other_password"})),
+            )
+            .await;
+        assert_eq!(status, 200);
+        let cookie = session_cookie(&headers);
+        let (status, _, _) = app
+            .request_with_cookie(
+                &cookie,
+                Method::PUT,
+                "/users/me/password",
+                Some(json!({"current_password": "other_password", "new_password": "new_password"})),
+            )
+            .await;
+        assert_eq!(status, 200);
+        let changes = events(&app, "password_change").await;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(keys(&changes[0]), ["username"]);
+        assert_eq!(changes[0]["metadata"]["username"], "other_user");
+    }
+
+    #[tokio::test]
+    async fn test_login_rotates_the_session() {
+        let app = TestApp::new();
+        let credentials = json!({"username": "test_user", "password": "test_password"});
+
+        // Logging in from another client adds a session, the first one still works
+        let (status, headers, _) = app
+            .request_with_cookie("", Method::POST, "/auth/login", Some(credentials.clone()))
+            .await;
+        assert_eq!(status, 200);
+        let other = session_cookie(&headers);
+        assert_ne!(other, app.cookie());
+        let (status, _, _) = app
+            .request_with_cookie(app.cookie(), Method::GET, "/users/me/usage", None)
+            .await;
+        assert_eq!(status, 200);
+
+        // The session the client already had ends with the login
+        let (status, headers, _) = app
+            .request_with_cookie(app.cookie(), Method::POST, "/auth/login", Some(credentials))
+            .await;
+        assert_eq!(status, 200);
+        let cookie = session_cookie(&headers);
+        assert_ne!(cookie, app.cookie());
+        assert_ne!(cookie, other);
+
+        let (status, _, _) = app
+            .request_with_cookie(app.cookie(), Method::GET, "/users/me/usage", None)
+            .await;
+        assert_eq!(status, 401);
+        for cookie in [&cookie, &other] {
+            let (status, _, _) = app
+                .request_with_cookie(cookie, Method::GET, "/users/me/usage", None)
+                .await;
+            assert_eq!(status, 200);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lockout() {
+        let app = TestApp::new();
+        app.make_admin();
+        let login = |password: &str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/api/v1/auth/login")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    json!({"username": "test_user", "password": password}).to_string(),
+                ))
+                .unwrap()
+        };
+
+        for _ in 0..3 {
+            let (status, _, _) = app.send(login("wrong_password")).await;
+            assert_eq!(status, 401);
+        }
+
+        // Locked users can't log in even with the right password, until the lock expires
+        let (status, headers, body) = app.send(login("test_password")).await;
+        assert_eq!(status, 423);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], 40006);
+        assert_eq!(body["error"], "locked");
+        let retry_after: u64 = headers[header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=120).contains(&retry_after), "{retry_after}");
+        assert_eq!(body["details"]["retry_after"], retry_after);
+        assert_eq!(body["details"]["retry_after_seconds"], retry_after);
+        let locked_until =
+            crate::utils::serialization::parse(body["details"]["locked_until"].as_str().unwrap())
+                .unwrap();
+        let wait = locked_until - app.clock().now_utc();
+        assert!(wait <= chrono::Duration::seconds(retry_after as i64));
+        assert!(wait > chrono::Duration::seconds(retry_after as i64 - 1));
+        let failures = events(&app, "login_failure").await;
+        assert_eq!(failures[0]["metadata"]["reason"], "locked");
+
+        // Still locked a second before the lock expires, unlocked once it has
+        app.clock()
+            .advance(chrono::Duration::seconds(retry_after as i64 - 1));
+        let (status, _, _) = app.send(login("test_password")).await;
+        assert_eq!(status, 423);
+        app.clock().advance(chrono::Duration::seconds(1));
+        let (status, _, body) = app.send(login("test_password")).await;
+        assert_eq!(status, 200, "{body:?}");
+    }
+
+    #[tokio::test]
+    async fn test_lockout_notification() {
+        let app = TestApp::new();
+        let login = |password: &str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/api/v1/auth/login")
+                .header(header::CONTENT_TYPE, "application/json")
+                .extension(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                    [203, 0, 113, 7],
+                    50000,
+                ))))
+                .body(Body::from(
+                    json!({"username": "test_user", "password": password}).to_string(),
+                ))
+                .unwrap()
+        };
+        let failed_at = app.clock().now_utc();
+        for _ in 0..3 {
+            let (status, _, _) = app.send(login("wrong_password")).await;
+            assert_eq!(status, 401);
+        }
+        // Logins refused while locked don't notify again
+        let (status, _, _) = app.send(login("wrong_password")).await;
+        assert_eq!(status, 423);
+
+        app.clock().advance(chrono::Duration::hours(1));
+        let (status, headers, _) = app.send(login("test_password")).await;
+        assert_eq!(status, 200);
+        let cookie = session_cookie(&headers);
+        let (status, _, unread) = app
+            .request_with_cookie(&cookie, Method::GET, "/notifications?unread=true", None)
+            .await;
+        assert_eq!(status, 200);
+        let unread = unread.as_array().unwrap();
+        assert_eq!(unread.len(), 1);
+        let notification = &unread[0];
+        assert_eq!(notification["type"], "lockout");
+        assert_eq!(notification["status"], "unread");
+        assert_eq!(notification["data"]["invalid_login_attempts"], 3);
+        assert_eq!(notification["data"]["ip"], "203.0.113.7");
+        assert_eq!(
+            notification["data"]["failed_at"],
+            crate::utils::serialization::format(&failed_at)
+        );
+        let locked_until = crate::utils::serialization::parse(
+            notification["data"]["locked_until"].as_str().unwrap(),
+        )
+        .unwrap();
+        assert!(locked_until > failed_at);
+        assert!(notification["body"]
+            .as_str()
+            .unwrap()
+            .starts_with("3 failed login attempts from 203.0.113.7 at "));
+
+        let (status, _, read) = app
+            .request_with_cookie(
+                &cookie,
+                Method::POST,
+                &format!("/notifications/{}/read", notification["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(read["status"], "read");
+        let (_, _, unread) = app
+            .request_with_cookie(&cookie, Method::GET, "/notifications?unread=true", None)
+            .await;
+        assert_eq!(unread, json!([]));
+        let (_, _, all) = app
+            .request_with_cookie(&cookie, Method::GET, "/notifications", None)
+            .await;
+        assert_eq!(all.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_passwords_are_redacted() {
+        let credentials = json!({
+            "username": "test_user",
+            "name": "test_user",
+            "password": "hunter2-secret",
+            "current_password": "hunter2-secret",
+            "new_password": "hunter2-secret",
+        });
+        let printed = [
+            format!(
+                "{:?}",
+                serde_json::from_value::<LoginInfo>(credentials.clone()).unwrap()
+            ),
+            format!(
+                "{:?}",
+                serde_json::from_value::<CreateUser>(credentials.clone()).unwrap()
+            ),
+            format!(
+                "{:?}",
+                serde_json::from_value::<ChangePassword>(credentials).unwrap()
+            ),
+        ];
+        for printed in printed {
+            assert!(!printed.contains("hunter2-secret"), "{printed}");
+            assert!(printed.contains("password: \"***\""), "{printed}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_never_logs_passwords() {
+        let app = TestApp::new();
+        let logs = CapturedLogs::default();
+        let _guard = tracing::subscriber::set_default(logging::subscriber(
+            LogFormat::Json,
+            logging::filter(Some("trace")).unwrap(),
+            logs.clone(),
+        ));
 
-    // let mut user = User::from_username(&mut conn, &info.username)?;
+        for password in ["wrong_password", "test_password"] {
+            app.request(
+                Method::POST,
+                "/auth/login",
+                Some(json!({"username": "test_user", "password": password})),
+            )
+            .await;
+        }
 
-    // let session = user.authenticate(&mut conn, &info.password)?;
+        let text = logs.text();
+        assert!(text.contains("/api/v1/auth/login"), "{text}");
+        assert!(!text.contains("wrong_password"), "{text}");
+        assert!(!text.contains("test_password"), "{text}");
+    }
 
-    // let token = session.token()?;
+    #[tokio::test]
+    async fn test_authenticated_requests_never_log_tokens() {
+        let app = TestApp::new();
+        let logs = CapturedLogs::default();
+        let _guard = tracing::subscriber::set_default(logging::subscriber(
+            LogFormat::Json,
+            logging::filter(Some("trace")).unwrap(),
+            logs.clone(),
+        ));
 
-    // let cookie = format!("token={}; HttpOnly; Secure; SameSite=Strict", token);
-    // let response = (
-    //     StatusCode::OK,
-    //     [(SET_COOKIE, cookie)],
-    //     "Login successful".to_string(),
-    // );
-    // Ok(response)
+        let (status, _) = app.request(Method::GET, "/auth/sessions", None).await;
+        assert_eq!(status, 200);
 
-    Ok("TODO: Token refresh successful".to_string())
+        let token = app.cookie().strip_prefix("token=").unwrap();
+        let text = logs.text();
+        assert!(text.contains("/api/v1/auth/sessions"), "{text}");
+        assert!(!text.contains(token), "{text}");
+    }
 }
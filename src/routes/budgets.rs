@@ -0,0 +1,630 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::Response,
+    routing::get,
+    Extension, Json, Router,
+};
+use bigdecimal::BigDecimal;
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{IfMatch, Validate, ValidatedJson, ValidatedQuery},
+        owned::OwnedPlan,
+        responses::MessageResponse,
+        state::AppState,
+    },
+    clock::Clock,
+    database::{
+        connection::{DbConn, DbPool},
+        models::{
+            budgets::{Budget, BudgetInput, BudgetInterval, EffectiveAmount},
+            categories::Category,
+            sessions::manager::Session,
+        },
+    },
+    errors::AppError,
+    middleware::etag::Validator,
+    reports::budgets::{self, BudgetStatus},
+    routes::reports::MonthParams,
+};
+
+/// Create or update budget request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_budget, update_budget))]
+#[schema(example = json!({"category_id": 3, "name": "Groceries", "amount": "400.00", "interval": "monthly", "currency": "CAD", "start_date": "2024-01-01", "end_date": null, "rollover": false}))]
+pub struct SaveBudget {
+    /// The ID of the category the budget is for
+    category_id: i32,
+    /// The name of the budget
+    name: String,
+    /// The amount available to spend each interval, as a positive decimal string
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// How often the amount is available (`monthly` or `yearly`)
+    interval: BudgetInterval,
+    /// The ISO 4217 currency code of the amount
+    currency: String,
+    /// The first day the budget applies to
+    start_date: NaiveDate,
+    /// The last day the budget applies to
+    end_date: Option<NaiveDate>,
+    /// Whether what's left of the amount of a month carries into the next months, and what's
+    /// overspent comes out of them, `false` by default
+    #[serde(default)]
+    rollover: bool,
+    /// The first day the amount is in effect when updating, the first day of the current month
+    /// by default. The amount of a new budget is in effect from its start date.
+    #[schema(value_type = Option<String>)]
+    effective_from: Option<NaiveDate>,
+}
+
+impl Validate for SaveBudget {}
+
+impl SaveBudget {
+    /// Validate the request and convert it to the fields of a budget
+    fn into_input(self, conn: &mut DbConn, user_id: i32) -> Result<BudgetInput, AppError> {
+        let input = BudgetInput {
+            category_id: self.category_id,
+            name: self.name,
+            amount: self.amount,
+            interval: self.interval,
+            currency: self.currency,
+            start_date: self.start_date,
+            end_date: self.end_date,
+            rollover: self.rollover,
+        };
+        input.validate()?;
+        Category::from_id(conn, input.category_id, user_id)?;
+
+        Ok(input)
+    }
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/plans/:name/budgets", get(all_budgets).post(create_budget))
+        .route("/plans/:name/budgets/report", get(get_budget_report))
+        .route(
+            "/plans/:name/budgets/:id",
+            get(get_budget).put(update_budget).delete(delete_budget),
+        )
+        .route("/plans/:name/budgets/:id/history", get(budget_history))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all budgets of a plan
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of budgets ordered by name.
+/// `404` : The plan doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/plans/{name}/budgets",
+    security(("cookieAuth" = [])),
+    params(("name" = String, Path, description = "Name of the plan")),
+    responses(
+        (status = 200, description = "Budgets of the plan", body = Vec<Budget>),
+        (status = 404, description = "Plan not found", body = ErrorBody)
+    )
+)]
+async fn all_budgets(
+    State(pool): State<Arc<DbPool>>,
+    OwnedPlan(plan): OwnedPlan,
+) -> Result<Json<Vec<Budget>>, AppError> {
+    pool.run_read(move |conn| {
+        let budgets = Budget::get_all(conn, &plan)?;
+
+        Ok(Json(budgets))
+    })
+    .await
+}
+
+/// This endpoint creates a new budget in a plan
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created budget.
+/// `400` : The amount isn't positive or the budget ends before it starts.
+/// `404` : The plan or category doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/plans/{name}/budgets",
+    security(("cookieAuth" = [])),
+    params(("name" = String, Path, description = "Name of the plan")),
+    request_body = SaveBudget,
+    responses(
+        (status = 201, description = "Budget created", body = Budget),
+        (status = 400, description = "Invalid budget"),
+        (status = 404, description = "Plan or category not found")
+    )
+)]
+async fn create_budget(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    OwnedPlan(plan): OwnedPlan,
+    ValidatedJson(payload): ValidatedJson<SaveBudget>,
+) -> Result<(StatusCode, Json<Budget>), AppError> {
+    pool.run(move |conn| {
+        let input = payload.into_input(conn, session.user_id())?;
+        let budget = Budget::new(conn, &plan, &input)?;
+
+        Ok((StatusCode::CREATED, Json(budget)))
+    })
+    .await
+}
+
+/// This endpoint returns a budget of a plan
+///
+/// The budget is tagged with its version, which updates must send back in `If-Match`.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the budget, with its version in `ETag`.
+/// `304` : The budget wasn't modified since the ETag of `If-None-Match`.
+/// `404` : The plan or budget doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/plans/{name}/budgets/{id}",
+    security(("cookieAuth" = [])),
+    params(
+        ("name" = String, Path, description = "Name of the plan"),
+        ("id" = i32, Path, description = "ID of the budget")
+    ),
+    responses(
+        (status = 200, description = "Budget", body = Budget),
+        (status = 304, description = "Budget wasn't modified"),
+        (status = 404, description = "Budget not found", body = ErrorBody)
+    )
+)]
+async fn get_budget(
+    State(pool): State<Arc<DbPool>>,
+    OwnedPlan(plan): OwnedPlan,
+    Path((_name, id)): Path<(String, i32)>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    pool.run(move |conn| {
+        let budget = Budget::from_id(conn, id, &plan)?;
+        let validator = Validator::new(budget.version());
+        if let Some(response) = validator.not_modified(&headers) {
+            return Ok(response);
+        }
+
+        Ok(validator.tag(Json(budget)))
+    })
+    .await
+}
+
+/// This endpoint updates a budget
+///
+/// `If-Match` must hold the ETag the budget was read with. If the budget was updated since, the
+/// update is refused so the other change isn't lost, and the budget has to be read again.
+///
+/// A new amount doesn't rewrite the past: it's in effect from `effective_from`, the first day of
+/// the current month by default, and the months before keep the amount they had. Setting an
+/// amount from a day that already has one replaces it. The `amount` of the budget is the one in
+/// effect last, `/history` lists them all.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated budget, with its new version in `ETag`.
+/// `400` : The amount isn't positive, the budget ends before it starts, the amount is in effect
+/// before the budget starts or `If-Match` isn't an ETag.
+/// `404` : The plan, budget or category doesn't exist or belongs to another user.
+/// `412` : The budget was updated since the ETag of `If-Match`. Returns its current version.
+/// `428` : `If-Match` is missing.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    put,
+    path = "/plans/{name}/budgets/{id}",
+    security(("cookieAuth" = [])),
+    params(
+        ("name" = String, Path, description = "Name of the plan"),
+        ("id" = i32, Path, description = "ID of the budget")
+    ),
+    request_body = SaveBudget,
+    responses(
+        (status = 200, description = "Budget updated", body = Budget),
+        (status = 400, description = "Invalid budget"),
+        (status = 404, description = "Budget not found"),
+        (status = 412, description = "Budget was modified", body = ErrorBody),
+        (status = 428, description = "Missing If-Match", body = ErrorBody)
+    )
+)]
+async fn update_budget(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    OwnedPlan(plan): OwnedPlan,
+    Path((_name, id)): Path<(String, i32)>,
+    IfMatch(version): IfMatch,
+    ValidatedJson(payload): ValidatedJson<SaveBudget>,
+) -> Result<Response, AppError> {
+    let month_start = clock
+        .today()
+        .with_day(1)
+        .expect("Every month has a first day");
+    pool.run(move |conn| {
+        let budget = Budget::from_id(conn, id, &plan)?;
+        let effective_from = payload.effective_from;
+        let input = payload.into_input(conn, session.user_id())?;
+        let effective_from = match effective_from {
+            Some(day) if day < input.start_date => {
+                return Err(AppError::InvalidInput(
+                    "The amount of a budget can't be in effect before the budget starts"
+                        .to_string(),
+                ))
+            }
+            Some(day) => Some(day),
+            None if input.amount != *budget.amount() => Some(month_start.max(input.start_date)),
+            None => None,
+        };
+        let budget = budget.update(conn, &plan, &input, version, effective_from)?;
+
+        Ok(Validator::new(budget.version()).tag(Json(budget)))
+    })
+    .await
+}
+
+/// This endpoint returns the amounts of a budget over time
+///
+/// Each amount is in effect from its `effective_from` until the next one. Reports of a month use
+/// the amount in effect on its first day.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the amounts, oldest first.
+/// `404` : The plan or budget doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/plans/{name}/budgets/{id}/history",
+    security(("cookieAuth" = [])),
+    params(
+        ("name" = String, Path, description = "Name of the plan"),
+        ("id" = i32, Path, description = "ID of the budget")
+    ),
+    responses(
+        (status = 200, description = "Amounts of the budget", body = Vec<EffectiveAmount>),
+        (status = 404, description = "Budget not found", body = ErrorBody)
+    )
+)]
+async fn budget_history(
+    State(pool): State<Arc<DbPool>>,
+    OwnedPlan(plan): OwnedPlan,
+    Path((_name, id)): Path<(String, i32)>,
+) -> Result<Json<Vec<EffectiveAmount>>, AppError> {
+    pool.run_read(move |conn| {
+        let history = Budget::from_id(conn, id, &plan)?.history(conn)?;
+
+        Ok(Json(history.into_amounts()))
+    })
+    .await
+}
+
+/// This endpoint deletes a budget
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the budget was deleted.
+/// `404` : The plan or budget doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/plans/{name}/budgets/{id}",
+    security(("cookieAuth" = [])),
+    params(
+        ("name" = String, Path, description = "Name of the plan"),
+        ("id" = i32, Path, description = "ID of the budget")
+    ),
+    responses(
+        (status = 200, description = "Budget deleted", body = MessageResponse),
+        (status = 404, description = "Budget not found")
+    )
+)]
+async fn delete_budget(
+    State(pool): State<Arc<DbPool>>,
+    OwnedPlan(plan): OwnedPlan,
+    Path((_name, id)): Path<(String, i32)>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        Budget::from_id(conn, id, &plan)?.delete(conn, &plan)?;
+
+        Ok(Json(MessageResponse::new("Budget deleted")))
+    })
+    .await
+}
+
+/// This endpoint compares the budgets of a plan to what was spent in a month
+///
+/// Spending is taken from all accounts of the user. Split transactions count towards the
+/// categories of their splits instead of their own. Yearly budgets are spread evenly over the
+/// months of the year. With `convert=true`, spending and budgets are converted into the preferred
+/// currency of the user, budgets with the exchange rate of the last day of the month. Months start
+/// on the day of the month set by the user, or `period_anchor`. Budgets with `rollover` add what
+/// was left of the previous months since they started to their base budget, and take off what was
+/// overspent.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the status of each budget that applies to the month.
+/// `400` : The month isn't between 1 and 12, or `period_anchor` isn't between 1 and 28.
+/// `404` : The plan doesn't exist or belongs to another user.
+/// `422` : Amounts couldn't be converted. Lists the currency pairs without an exchange rate.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/plans/{name}/budgets/report",
+    security(("cookieAuth" = [])),
+    params(("name" = String, Path, description = "Name of the plan"), MonthParams),
+    responses(
+        (status = 200, description = "Budget vs. actual", body = Vec<BudgetStatus>),
+        (status = 400, description = "Invalid month"),
+        (status = 404, description = "Plan not found"),
+        (status = 422, description = "Missing exchange rates")
+    )
+)]
+async fn get_budget_report(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    OwnedPlan(plan): OwnedPlan,
+    ValidatedQuery(params): ValidatedQuery<MonthParams>,
+) -> Result<Json<Vec<BudgetStatus>>, AppError> {
+    pool.run_read(move |conn| {
+        let convert_to = params.convert_to(conn, session.user_id())?;
+        let start_day = params.start_day(conn, session.user_id())?;
+        let statuses = budgets::budget_vs_actual(
+            conn,
+            &plan,
+            params.year,
+            params.month,
+            start_day,
+            convert_to.as_deref(),
+        )?;
+
+        Ok(Json(statuses))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+    };
+    use chrono::Datelike;
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+    use crate::clock::Clock;
+    use crate::database::models::categories::Category;
+
+    /// Update a budget as the logged in user, returning the status, ETag and body of the response
+    async fn put(
+        app: &TestApp,
+        uri: &str,
+        if_match: Option<&str>,
+        body: &Value,
+    ) -> (StatusCode, Option<String>, Value) {
+        let mut request = Request::builder()
+            .method(Method::PUT)
+            .uri(uri)
+            .header(header::COOKIE, app.cookie())
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(tag) = if_match {
+            request = request.header(header::IF_MATCH, tag);
+        }
+        let (status, headers, bytes) = app
+            .send(request.body(Body::from(body.to_string())).unwrap())
+            .await;
+        let etag = headers
+            .get(header::ETAG)
+            .map(|tag| tag.to_str().unwrap().to_owned());
+        (status, etag, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_lost_updates_are_refused() {
+        let app = TestApp::new();
+        let category_id = {
+            let mut conn = app.pool().get().unwrap();
+            Category::new(&mut conn, app.user_id(), "Groceries")
+                .unwrap()
+                .id()
+        };
+        let budget = |name: &str, amount: &str| {
+            json!({
+                "category_id": category_id,
+                "name": name,
+                "amount": amount,
+                "interval": "monthly",
+                "currency": "CAD",
+                "start_date": "2024-01-01",
+                "end_date": null,
+            })
+        };
+
+        app.request(Method::POST, "/api/v1/plans/home", None).await;
+        let (status, created) = app
+            .request(
+                Method::POST,
+                "/api/v1/plans/home/budgets",
+                Some(budget("Food", "400.00")),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let uri = format!("/api/v1/plans/home/budgets/{}", created["id"]);
+        let (_, plan, _) = app.download("/api/v1/plans/home").await;
+
+        // Both tabs read the budget
+        let (status, headers, _) = app.download(&uri).await;
+        assert_eq!(status, StatusCode::OK);
+        let tag = headers[header::ETAG].to_str().unwrap().to_owned();
+        assert_eq!(tag, "\"1\"");
+        let (status, _, _) = app
+            .download_with(&uri, &[(header::IF_NONE_MATCH, &tag)])
+            .await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+        // The first tab saves
+        let (status, etag, body) = put(&app, &uri, Some(&tag), &budget("Food", "450.00")).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(etag.as_deref(), Some("\"2\""));
+        assert_eq!(body["version"], 2);
+
+        // The second tab saves against what it read and is refused
+        let (status, etag, body) =
+            put(&app, &uri, Some(&tag), &budget("Groceries", "300.00")).await;
+        assert_eq!(status, StatusCode::PRECONDITION_FAILED, "{body}");
+        assert_eq!(etag, None);
+        assert_eq!(body["error"], "precondition_failed");
+        assert_eq!(body["details"], json!({"version": 2}));
+
+        // The first tab's change wasn't lost
+        let (_, saved) = app.request(Method::GET, &uri, None).await;
+        assert_eq!(saved["name"], "Food");
+        assert_eq!(saved["amount"], "450.00");
+        assert_eq!(saved["version"], 2);
+
+        // The plan changed along with its budget
+        let (_, headers, _) = app.download("/api/v1/plans/home").await;
+        assert_ne!(headers[header::ETAG], plan[header::ETAG]);
+
+        // Updates must say which version they were made against
+        let (status, _, body) = put(&app, &uri, None, &budget("Food", "500.00")).await;
+        assert_eq!(status, StatusCode::PRECONDITION_REQUIRED, "{body}");
+        assert_eq!(body["error"], "precondition_required");
+        let (status, _, _) = put(&app, &uri, Some("*"), &budget("Food", "500.00")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Once read again, the second tab can save
+        let (status, etag, _) =
+            put(&app, &uri, Some("\"2\""), &budget("Groceries", "300.00")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(etag.as_deref(), Some("\"3\""));
+    }
+
+    #[tokio::test]
+    async fn test_amount_changes_are_date_effective() {
+        let app = TestApp::new();
+        let category_id = {
+            let mut conn = app.pool().get().unwrap();
+            Category::new(&mut conn, app.user_id(), "Housing")
+                .unwrap()
+                .id()
+        };
+        let budget = |name: &str, amount: &str, effective_from: Option<&str>| {
+            json!({
+                "category_id": category_id,
+                "name": name,
+                "amount": amount,
+                "interval": "monthly",
+                "currency": "CAD",
+                "start_date": "2024-01-01",
+                "end_date": null,
+                "effective_from": effective_from,
+            })
+        };
+        let base_budget = |month: u32| {
+            let app = &app;
+            async move {
+                let (status, report) = app
+                    .request(
+                        Method::GET,
+                        &format!("/plans/home/budgets/report?year=2024&month={month}"),
+                        None,
+                    )
+                    .await;
+                assert_eq!(status, StatusCode::OK, "{report}");
+                report[0]["base_budget"].clone()
+            }
+        };
+
+        app.request(Method::POST, "/plans/home", None).await;
+        let (_, created) = app
+            .request(
+                Method::POST,
+                "/plans/home/budgets",
+                Some(budget("Rent", "1000.00", None)),
+            )
+            .await;
+        let uri = format!("/plans/home/budgets/{}", created["id"]);
+
+        // The rent goes up in July
+        let (status, _, updated) = put(
+            &app,
+            &uri,
+            Some("\"1\""),
+            &budget("Rent", "1200.00", Some("2024-07-01")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{updated}");
+        assert_eq!(updated["amount"], "1200.00");
+        let (status, history) = app
+            .request(Method::GET, &format!("{uri}/history"), None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        let amounts: Vec<_> = history
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|amount| (amount["amount"].clone(), amount["effective_from"].clone()))
+            .collect();
+        assert_eq!(
+            amounts,
+            [
+                (json!("1000.00"), json!("2024-01-01")),
+                (json!("1200.00"), json!("2024-07-01"))
+            ]
+        );
+
+        // The reports of the months before keep the amount they had
+        assert_eq!(base_budget(6).await, "1000.00");
+        assert_eq!(base_budget(7).await, "1200.00");
+        assert_eq!(base_budget(11).await, "1200.00");
+
+        // Updates that keep the amount don't add to the history
+        let (status, _, _) = put(&app, &uri, Some("\"2\""), &budget("Home", "1200.00", None)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, history) = app
+            .request(Method::GET, &format!("{uri}/history"), None)
+            .await;
+        assert_eq!(history.as_array().unwrap().len(), 2);
+
+        // Amounts can't be in effect before the budget starts
+        let (status, _, body) = put(
+            &app,
+            &uri,
+            Some("\"3\""),
+            &budget("Home", "900.00", Some("2023-12-01")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+
+        // Without `effective_from`, a new amount is in effect from the start of the current month
+        let (status, _, updated) =
+            put(&app, &uri, Some("\"3\""), &budget("Home", "1300.00", None)).await;
+        assert_eq!(status, StatusCode::OK, "{updated}");
+        let (_, history) = app
+            .request(Method::GET, &format!("{uri}/history"), None)
+            .await;
+        let month_start = app.clock().today().with_day(1).unwrap();
+        assert_eq!(history[2]["amount"], "1300.00");
+        assert_eq!(history[2]["effective_from"], month_start.to_string());
+        assert_eq!(base_budget(7).await, "1200.00");
+    }
+}
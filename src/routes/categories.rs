@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson},
+        responses::MessageResponse,
+        state::AppState,
+    },
+    database::{
+        connection::DbPool,
+        models::{
+            categories::Category,
+            category_alerts::{CategoryAlert, CategoryAlertInput},
+            sessions::manager::Session,
+        },
+    },
+    errors::AppError,
+    merge::{self, CategoryMerge},
+    reports::cache::ReportCache,
+};
+
+/// Create category request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_category))]
+#[schema(example = json!({"name": "Groceries"}))]
+pub struct CreateCategory {
+    /// The name of the category, unique per user
+    name: String,
+}
+
+impl Validate for CreateCategory {}
+
+/// Set category alert request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(set_alert))]
+#[schema(example = json!({"monthly_limit": "400.00", "notify_at_percent": 80}))]
+pub struct SaveAlert {
+    /// The most that should be spent on the category in a month, as a decimal string
+    #[schema(value_type = String)]
+    monthly_limit: BigDecimal,
+    /// Percentage of the limit at which the alert fires, from 1 to 100, 100 by default
+    #[serde(default = "default_notify_at_percent")]
+    notify_at_percent: i32,
+}
+
+impl Validate for SaveAlert {}
+
+fn default_notify_at_percent() -> i32 {
+    100
+}
+
+/// Merge category request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(merge_category))]
+#[schema(example = json!({"into_category_id": 3}))]
+pub struct MergeCategory {
+    /// The ID of the category to merge into
+    into_category_id: i32,
+}
+
+impl Validate for MergeCategory {}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/categories", get(all_categories).post(create_category))
+        .route("/categories/alerts", get(all_alerts))
+        .route("/categories/:id/alert", put(set_alert).delete(delete_alert))
+        .route("/categories/:id/merge", post(merge_category))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all categories of the authenticated user
+///
+/// ## Responses
+/// `200` : A successful response. Returns a vector of categories ordered by name.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/categories",
+    security(("cookieAuth" = [])),
+    responses(
+        (status = 200, description = "Categories of the user", body = Vec<Category>),
+        (status = 401, description = "User is not authenticated", body = ErrorBody)
+    )
+)]
+async fn all_categories(
+    Extension(session): Extension<Session>,
+    State(pool): State<Arc<DbPool>>,
+) -> Result<Json<Vec<Category>>, AppError> {
+    pool.run_read(move |conn| {
+        let categories = Category::get_all(conn, session.user_id())?;
+        Ok(Json(categories))
+    })
+    .await
+}
+
+/// This endpoint creates a new category
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created category.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/categories",
+    security(("cookieAuth" = [])),
+    request_body = CreateCategory,
+    responses((status = 201, description = "Category created", body = Category))
+)]
+async fn create_category(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<CreateCategory>,
+) -> Result<(StatusCode, Json<Category>), AppError> {
+    pool.run(move |conn| {
+        let category = Category::new(conn, session.user_id(), &payload.name)?;
+
+        Ok((StatusCode::CREATED, Json(category)))
+    })
+    .await
+}
+
+/// This endpoint returns the spending alerts of the categories of the authenticated user
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of alerts.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/categories/alerts",
+    security(("cookieAuth" = [])),
+    responses((status = 200, description = "Alerts of the user", body = Vec<CategoryAlert>))
+)]
+async fn all_alerts(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+) -> Result<Json<Vec<CategoryAlert>>, AppError> {
+    pool.run_read(move |conn| Ok(Json(CategoryAlert::get_all(conn, session.user_id())?)))
+        .await
+}
+
+/// This endpoint sets the spending alert of a category, replacing the one it has
+///
+/// Whenever a transaction dated in the current month is created or imported in the category, the
+/// alert fires if spending this month, net of refunds, reached `notify_at_percent` of the limit.
+/// It fires at most once a month: a `category_alert` notification is recorded, pushed to the
+/// event stream and sent to the webhooks subscribed to `category_alert`. Setting the alert again
+/// rearms it for the current month.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the alert.
+/// `400` : The limit isn't positive or the percentage isn't between 1 and 100.
+/// `404` : The category doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    put,
+    path = "/categories/{id}/alert",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the category")),
+    request_body = SaveAlert,
+    responses(
+        (status = 200, description = "Alert set", body = CategoryAlert),
+        (status = 400, description = "Invalid alert"),
+        (status = 404, description = "Category not found")
+    )
+)]
+async fn set_alert(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<SaveAlert>,
+) -> Result<Json<CategoryAlert>, AppError> {
+    pool.run(move |conn| {
+        let category = Category::from_id(conn, id, session.user_id())?;
+        let input = CategoryAlertInput {
+            monthly_limit: payload.monthly_limit,
+            notify_at_percent: payload.notify_at_percent,
+        };
+
+        Ok(Json(CategoryAlert::set(conn, &category, &input)?))
+    })
+    .await
+}
+
+/// This endpoint removes the spending alert of a category
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the alert was removed.
+/// `404` : The category doesn't exist, belongs to another user or has no alert.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/categories/{id}/alert",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the category")),
+    responses(
+        (status = 200, description = "Alert removed", body = MessageResponse),
+        (status = 404, description = "Alert not found")
+    )
+)]
+async fn delete_alert(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        let category = Category::from_id(conn, id, session.user_id())?;
+        CategoryAlert::from_category(conn, &category)?.delete(conn)?;
+
+        Ok(Json(MessageResponse::new("Alert removed")))
+    })
+    .await
+}
+
+/// This endpoint merges a category into another category of the authenticated user
+///
+/// The transactions, splits, budgets, alert, payee rules and recurring transactions of the
+/// category are moved to the other category in a single database transaction, then the category
+/// is deleted. A budget of a plan that already budgets the other category in the same interval
+/// and currency is added to that budget, and an alert is added to the alert of the other category
+/// if it has one. A category where more money came in than went out can't be merged with one
+/// where more went out.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns how many rows were moved, by table.
+/// `400` : The categories are the same, or one is an income category and the other an expense
+/// category.
+/// `404` : One of the categories doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/categories/{id}/merge",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the category to merge and delete")),
+    request_body = MergeCategory,
+    responses(
+        (status = 200, description = "Category merged", body = CategoryMerge),
+        (status = 400, description = "Categories can't be merged"),
+        (status = 404, description = "Category not found")
+    )
+)]
+async fn merge_category(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<MergeCategory>,
+) -> Result<Json<CategoryMerge>, AppError> {
+    pool.run(move |conn| {
+        let source = Category::from_id(conn, id, session.user_id())?;
+        let target = Category::from_id(conn, payload.into_category_id, session.user_id())?;
+        let merged = merge::merge_categories(conn, &source, &target)?;
+        reports.invalidate(session.user_id());
+
+        Ok(Json(merged))
+    })
+    .await
+}
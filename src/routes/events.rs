@@ -0,0 +1,145 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    middleware,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Extension, Router,
+};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+
+use crate::{api::state::AppState, database::models::sessions::manager::Session, events::EventBus};
+
+/// How often a comment is sent on an idle stream, so proxies don't close it
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/events/stream", get(stream_events))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint streams changes to the data of the authenticated user as server-sent events
+///
+/// Each event is named after what happened (`plan_created`, `plan_deleted`,
+/// `transaction_created`, `transaction_deleted`, `session_revoked` or `category_alert`) and
+/// carries the record it is about as JSON. A keep-alive comment is sent every 15 seconds. Events published while a
+/// client falls too far behind are skipped.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a `text/event-stream` that stays open until the server
+/// shuts down.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/events/stream",
+    security(("cookieAuth" = [])),
+    responses(
+        (status = 200, description = "Stream of events", body = String, content_type = "text/event-stream"),
+        (status = 401, description = "User is not authenticated")
+    )
+)]
+async fn stream_events(
+    Extension(session): Extension<Session>,
+    State(events): State<Arc<EventBus>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let user_id = session.user_id();
+    let stream =
+        BroadcastStream::new(events.subscribe(user_id)).filter_map(move |frame| match frame {
+            Ok(frame) => Some(Ok(Event::default()
+                .event(frame.event.as_str())
+                .data(&*frame.data))),
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                tracing::warn!("Event stream of user {user_id} skipped {skipped} events");
+                None
+            }
+        });
+
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(KEEP_ALIVE_INTERVAL))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{
+        body::Body,
+        http::{header, Method},
+    };
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+
+    /// Read the next event of a stream, skipping keep-alive comments
+    async fn next_event(body: &mut Body) -> Option<(String, Value)> {
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(5), body.frame())
+                .await
+                .expect("No event arrived in time")?
+                .unwrap();
+            let Ok(data) = frame.into_data() else {
+                continue;
+            };
+            let text = String::from_utf8(data.to_vec()).unwrap();
+            let field = |name: &str| {
+                text.lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(str::to_string)
+            };
+            if let (Some(event), Some(data)) = (field("event: "), field("data: ")) {
+                return Some((event, serde_json::from_str(&data).unwrap()));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_events() {
+        let app = TestApp::new();
+        let (status, headers, mut body) = app.open("/events/stream").await;
+        assert_eq!(status, 200);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/event-stream");
+
+        app.request(Method::POST, "/plans/Household", None).await;
+        let (event, plan) = next_event(&mut body).await.unwrap();
+        assert_eq!(event, "plan_created");
+        assert_eq!(plan["name"], "Household");
+
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        app.request(
+            Method::POST,
+            &format!("/accounts/{}/transactions", account["id"]),
+            Some(json!({"amount": "-60.00", "description": "Groceries", "occurred_at": "2024-07-05"})),
+        )
+        .await;
+        let (event, transaction) = next_event(&mut body).await.unwrap();
+        assert_eq!(event, "transaction_created");
+        assert_eq!(transaction["description"], "Groceries");
+        assert_eq!(transaction["amount"], "-60.00");
+
+        app.request(Method::GET, "/auth/logout", None).await;
+        let (event, revoked) = next_event(&mut body).await.unwrap();
+        assert_eq!(event, "session_revoked");
+        assert_eq!(revoked["reason"], "logout");
+
+        // Shutting down ends the stream
+        app.events().close();
+        assert!(next_event(&mut body).await.is_none());
+    }
+}
@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::header,
+    middleware,
+    response::IntoResponse,
+    routing::get,
+    Extension, Router,
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedQuery},
+        state::AppState,
+    },
+    database::{
+        connection::DbPool,
+        models::{accounts::Account, sessions::manager::Session},
+    },
+    errors::AppError,
+    export::{
+        statement::{self, StatementFormat},
+        transactions::{self as export, ExportFormat},
+    },
+    reports::{categories::parse_month, periods::month_range},
+};
+
+/// Export query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct ExportParams {
+    /// First day of the export (inclusive)
+    from: NaiveDate,
+    /// Last day of the export (inclusive)
+    to: NaiveDate,
+    /// The format of the export (`csv` or `json`, default `csv`)
+    format: Option<ExportFormat>,
+}
+
+impl Validate for ExportParams {}
+
+/// Statement query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct StatementParams {
+    /// The month of the statement, in the `YYYY-MM` format
+    month: String,
+    /// The format of the statement (`html` or `pdf`, default `html`)
+    format: Option<StatementFormat>,
+}
+
+impl Validate for StatementParams {}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/accounts/:id/transactions/export",
+            get(export_transactions),
+        )
+        .route("/accounts/:id/statement", get(get_statement))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint exports the transactions of an account within a date range
+///
+/// The export is streamed as it is read from the database, oldest transaction first, with the
+/// columns `date`, `description`, `category`, `amount` and `currency`. `format=csv` returns a CSV
+/// file with a header row, `format=json` returns newline delimited JSON.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the export as an attachment.
+/// `400` : A parameter can't be read or is unknown, or the date range is invalid.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/transactions/export",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account"), ExportParams),
+    responses(
+        (status = 200, description = "Transaction export", body = String, content_type = "text/csv"),
+        (status = 400, description = "Invalid parameters or date range"),
+        (status = 404, description = "Account not found")
+    )
+)]
+async fn export_transactions(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedQuery(params): ValidatedQuery<ExportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    if params.from > params.to {
+        return Err(AppError::InvalidInput(
+            "\"from\" must not be after \"to\"".to_string(),
+        ));
+    }
+
+    let account = pool
+        .run(move |conn| Account::from_id(conn, id, session.user_id()))
+        .await?;
+
+    let format = params.format.unwrap_or_default();
+    let file_name = export::file_name(&account, params.from, params.to, format);
+    let body = export::stream(pool, account, (params.from, params.to), format);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        body,
+    ))
+}
+
+/// This endpoint renders the statement of an account for a month
+///
+/// The statement has the opening balance of the month, its transactions oldest first, the money in
+/// and out, the closing balance and the subtotal of each category. `format=html` returns a page
+/// without styles or scripts, `format=pdf` a PDF document. The statement is streamed as its
+/// transactions are read. Archived accounts have statements too, archiving only stops new
+/// transactions.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the statement, shown inline.
+/// `400` : A parameter can't be read or is unknown, or the month isn't in the `YYYY-MM` format.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/statement",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account"), StatementParams),
+    responses(
+        (status = 200, description = "Account statement", body = String, content_type = "text/html"),
+        (status = 400, description = "Invalid parameters or month"),
+        (status = 404, description = "Account not found")
+    )
+)]
+async fn get_statement(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedQuery(params): ValidatedQuery<StatementParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let (year, month) = parse_month(&params.month)?;
+    let range = month_range(year, month)?;
+
+    let account = pool
+        .run(move |conn| Account::from_id(conn, id, session.user_id()))
+        .await?;
+
+    let format = params.format.unwrap_or_default();
+    let file_name = statement::file_name(&account, year, month, format);
+    let body = statement::stream(pool, account, range, format);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{file_name}\""),
+            ),
+        ],
+        body,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use axum::{
+        body::Body,
+        http::{header, Method, Request},
+    };
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+    use serde_json::json;
+
+    use crate::api::test_utils::TestApp;
+    use crate::database::models::{
+        accounts::{Account, AccountKind},
+        users::User,
+    };
+
+    #[tokio::test]
+    async fn test_statement() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "10.00", "currency": "CAD"})),
+            )
+            .await;
+        for (amount, day) in [("-2.50", "2024-05-31"), ("-4.25", "2024-06-15")] {
+            let (status, _) = app
+                .request(
+                    Method::POST,
+                    &format!("/accounts/{}/transactions", account["id"]),
+                    Some(json!({"amount": amount, "description": "Coffee", "occurred_at": day})),
+                )
+                .await;
+            assert_eq!(status, 201);
+        }
+        let uri = format!("/accounts/{}/statement", account["id"]);
+
+        let (status, headers, html) = app.download(&format!("{uri}?month=2024-06")).await;
+        assert_eq!(status, 200);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert_eq!(
+            headers[header::CONTENT_DISPOSITION],
+            "inline; filename=\"Chequing_statement_2024-06.html\""
+        );
+        let html = String::from_utf8(html.to_vec()).unwrap();
+        assert!(html.contains("<p>Opening balance: <strong>7.50</strong></p>"));
+        assert!(html.contains("<tr><th>Closing balance</th><td>3.25</td></tr>"));
+
+        // Archived accounts keep their statements
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/archive", account["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200);
+        let (status, headers, pdf) = app
+            .download(&format!("{uri}?month=2024-06&format=pdf"))
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/pdf");
+        assert!(pdf.starts_with(b"%PDF-"));
+
+        for query in ["month=2024-13", "month=June", "month=2024-06&format=docx"] {
+            let (status, _, _) = app.download(&format!("{uri}?{query}")).await;
+            assert_eq!(status, 400, "{query}");
+        }
+
+        // Other users' accounts aren't found
+        let other = {
+            let mut conn = app.pool().get().unwrap();
+            let user = User::new(&mut conn, "statement_other", "password").unwrap();
+            Account::new(
+                &mut conn,
+                user.id(),
+                "Savings",
+                &"0.00".parse().unwrap(),
+                "CAD",
+                AccountKind::Asset,
+            )
+            .unwrap()
+        };
+        let (status, _, _) = app
+            .download(&format!("/accounts/{}/statement?month=2024-06", other.id()))
+            .await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_export() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        for day in 1..=28 {
+            let (status, _) = app
+                .request(
+                    Method::POST,
+                    &format!("/accounts/{}/transactions", account["id"]),
+                    Some(json!({
+                        "amount": "-12.50",
+                        "description": "Coffee with a description long enough to compress",
+                        "occurred_at": format!("2024-02-{day:02}"),
+                    })),
+                )
+                .await;
+            assert_eq!(status, 201);
+        }
+
+        for format in ["csv", "json"] {
+            let uri = format!(
+                "/accounts/{}/transactions/export?from=2024-02-01&to=2024-02-29&format={format}",
+                account["id"]
+            );
+            let (status, headers, plain) = app.download(&uri).await;
+            assert_eq!(status, 200);
+            assert!(headers.get(header::CONTENT_ENCODING).is_none());
+
+            let (status, headers, compressed) = app
+                .download_with(&uri, &[(header::ACCEPT_ENCODING, "gzip")])
+                .await;
+            assert_eq!(status, 200);
+            assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+            assert!(compressed.len() < plain.len());
+            let mut decompressed = vec![];
+            GzDecoder::new(&compressed[..])
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, plain);
+        }
+
+        // Metrics are never compressed
+        let (_, headers, _) = app
+            .download_with("/metrics", &[(header::ACCEPT_ENCODING, "gzip, br")])
+            .await;
+        assert!(headers.get(header::CONTENT_ENCODING).is_none());
+
+        // Request bodies can be compressed
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder
+            .write_all(
+                json!({"name": "gzipped", "password": "password"})
+                    .to_string()
+                    .as_bytes(),
+            )
+            .unwrap();
+        let (status, _, body) = app
+            .send(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/users")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::CONTENT_ENCODING, "gzip")
+                    .body(Body::from(encoder.finish().unwrap()))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(status, 201, "{body:?}");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_query_parameters_are_refused() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        let id = &account["id"];
+
+        for uri in [
+            format!("/accounts/{id}/transactions/export?from=2024-06-01&to=2024-06-30&fromat=json"),
+            format!("/accounts/{id}/statement?month=2024-06&year=2024"),
+        ] {
+            let (status, body) = app.request(Method::GET, &uri, None).await;
+            assert_eq!(status, 400, "{uri}");
+            assert_eq!(body["error"], "invalid_query");
+            assert_eq!(body["details"]["kind"], "unknown_field", "{uri}");
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::get,
+    Extension, Json, Router,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson},
+        responses::MessageResponse,
+        state::AppState,
+    },
+    clock::Clock,
+    database::{
+        connection::{DbConn, DbPool},
+        models::{
+            accounts::Account,
+            goals::{Goal, GoalInput, GoalProgress},
+            sessions::manager::Session,
+        },
+    },
+    errors::AppError,
+};
+
+/// Create or update goal request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_goal, update_goal))]
+#[schema(example = json!({"name": "Emergency fund", "target_amount": "5000.00", "target_date": "2025-12-31", "linked_account_id": 2}))]
+pub struct SaveGoal {
+    /// The name of the goal
+    name: String,
+    /// The amount to save, as a positive decimal string
+    #[schema(value_type = String)]
+    target_amount: BigDecimal,
+    /// The date the amount should be saved by, not in the past
+    target_date: NaiveDate,
+    /// The ID of an account whose balance is the progress of the goal. Without one, the progress
+    /// is the sum of the transactions that count towards the goal.
+    linked_account_id: Option<i32>,
+}
+
+impl Validate for SaveGoal {}
+
+impl SaveGoal {
+    /// Validate the request and convert it to the fields of a goal
+    fn into_input(
+        self,
+        conn: &mut DbConn,
+        user_id: i32,
+        today: NaiveDate,
+    ) -> Result<GoalInput, AppError> {
+        let input = GoalInput {
+            name: self.name,
+            target_amount: self.target_amount,
+            target_date: self.target_date,
+            linked_account_id: self.linked_account_id,
+        };
+        input.validate(today)?;
+
+        if let Some(account_id) = input.linked_account_id {
+            Account::from_id(conn, account_id, user_id)?;
+        }
+
+        Ok(input)
+    }
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/goals", get(all_goals).post(create_goal))
+        .route(
+            "/goals/:id",
+            get(get_goal).put(update_goal).delete(delete_goal),
+        )
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all goals of the authenticated user with their progress
+///
+/// ## Responses
+/// `200` : A successful response. Returns a vector of goals, nearest target date first.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/goals",
+    security(("cookieAuth" = [])),
+    responses(
+        (status = 200, description = "Goals with their progress", body = Vec<GoalProgress>),
+        (status = 401, description = "User is not authenticated")
+    )
+)]
+async fn all_goals(
+    Extension(session): Extension<Session>,
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+) -> Result<Json<Vec<GoalProgress>>, AppError> {
+    pool.run_read(move |conn| {
+        let today = clock.today();
+
+        let goals = Goal::get_all(conn, session.user_id())?
+            .into_iter()
+            .map(|goal| goal.with_progress(conn, today))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Json(goals))
+    })
+    .await
+}
+
+/// This endpoint returns a goal with its progress
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the goal.
+/// `404` : The goal doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/goals/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the goal")),
+    responses(
+        (status = 200, description = "Goal with its progress", body = GoalProgress),
+        (status = 404, description = "Goal not found")
+    )
+)]
+async fn get_goal(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<GoalProgress>, AppError> {
+    pool.run(move |conn| {
+        let goal = Goal::from_id(conn, id, session.user_id())?;
+
+        Ok(Json(goal.with_progress(conn, clock.today())?))
+    })
+    .await
+}
+
+/// This endpoint creates a new goal
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created goal with its progress.
+/// `400` : The target amount isn't positive or the target date is in the past.
+/// `404` : The linked account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/goals",
+    security(("cookieAuth" = [])),
+    request_body = SaveGoal,
+    responses(
+        (status = 201, description = "Goal created", body = GoalProgress),
+        (status = 400, description = "Invalid target"),
+        (status = 404, description = "Linked account not found")
+    )
+)]
+async fn create_goal(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<SaveGoal>,
+) -> Result<(StatusCode, Json<GoalProgress>), AppError> {
+    pool.run(move |conn| {
+        let input = payload.into_input(conn, session.user_id(), clock.today())?;
+        let goal = Goal::new(conn, session.user_id(), &input)?;
+        let goal = goal.with_progress(conn, clock.today())?;
+
+        Ok((StatusCode::CREATED, Json(goal)))
+    })
+    .await
+}
+
+/// This endpoint updates a goal
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated goal with its progress.
+/// `400` : The target amount isn't positive or the target date is in the past.
+/// `404` : The goal or linked account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    put,
+    path = "/goals/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the goal")),
+    request_body = SaveGoal,
+    responses(
+        (status = 200, description = "Goal updated", body = GoalProgress),
+        (status = 400, description = "Invalid target"),
+        (status = 404, description = "Goal not found")
+    )
+)]
+async fn update_goal(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<SaveGoal>,
+) -> Result<Json<GoalProgress>, AppError> {
+    pool.run(move |conn| {
+        let goal = Goal::from_id(conn, id, session.user_id())?;
+        let input = payload.into_input(conn, session.user_id(), clock.today())?;
+        let goal = goal.update(conn, &input)?;
+
+        Ok(Json(goal.with_progress(conn, clock.today())?))
+    })
+    .await
+}
+
+/// This endpoint deletes a goal
+///
+/// Transactions that counted towards the goal are kept.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the goal was deleted.
+/// `404` : The goal doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/goals/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the goal")),
+    responses(
+        (status = 200, description = "Goal deleted", body = MessageResponse),
+        (status = 404, description = "Goal not found")
+    )
+)]
+async fn delete_goal(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        Goal::from_id(conn, id, session.user_id())?.delete(conn)?;
+
+        Ok(Json(MessageResponse::new("Goal deleted")))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_goal_routes() {
+        let app = TestApp::new();
+        let target_date = chrono::Local::now().date_naive() + chrono::Duration::days(100);
+
+        let (status, goal) = app
+            .request(
+                Method::POST,
+                "/goals",
+                Some(json!({"name": "Vacation", "target_amount": "1000", "target_date": target_date})),
+            )
+            .await;
+        assert_eq!(status, 201, "{goal}");
+        assert_eq!(goal["name"], "Vacation");
+        assert_eq!(goal["progress"], "0");
+        assert_eq!(goal["percent_complete"], 0.0);
+        assert_eq!(goal["on_track"], true);
+
+        // Tag a transaction with the goal
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0", "currency": "CAD"})),
+            )
+            .await;
+        let uri = format!("/accounts/{}/transactions", account["id"]);
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &uri,
+                Some(json!({
+                    "amount": "250.00",
+                    "description": "Transfer",
+                    "occurred_at": "2024-01-01",
+                    "goal_id": goal["id"]
+                })),
+            )
+            .await;
+        assert_eq!(status, 201);
+
+        let (status, goals) = app.request(Method::GET, "/goals", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(goals[0]["progress"], "250.00");
+        assert_eq!(goals[0]["percent_complete"], 25.0);
+
+        let uri = format!("/goals/{}", goal["id"]);
+        let (status, _) = app.request(Method::DELETE, &uri, None).await;
+        assert_eq!(status, 200);
+        let (status, _) = app.request(Method::GET, &uri, None).await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_goals() {
+        let app = TestApp::new();
+        let target_date = chrono::Local::now().date_naive() + chrono::Duration::days(100);
+
+        let (status, error) = app
+            .request(
+                Method::POST,
+                "/goals",
+                Some(json!({"name": "Nothing", "target_amount": "0", "target_date": target_date})),
+            )
+            .await;
+        assert_eq!(status, 400);
+        assert_eq!(error["code"], 40008);
+
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/goals",
+                Some(
+                    json!({"name": "Too late", "target_amount": "10", "target_date": "2000-01-01"}),
+                ),
+            )
+            .await;
+        assert_eq!(status, 400);
+
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/goals",
+                Some(json!({
+                    "name": "Someone else's",
+                    "target_amount": "10",
+                    "target_date": target_date,
+                    "linked_account_id": i32::MAX
+                })),
+            )
+            .await;
+        assert_eq!(status, 404);
+    }
+}
@@ -0,0 +1,511 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use utoipa::ToSchema;
+
+use crate::{
+    alerts,
+    api::{responses::MessageResponse, state::AppState},
+    clock::Clock,
+    database::{
+        connection::DbPool,
+        models::{
+            accounts::Account, import_jobs::ImportJob, import_pending::PendingImport,
+            payee_rules::PayeeRules, sessions::manager::Session, transactions::Transaction,
+            webhooks::WebhookEvent,
+        },
+    },
+    errors::AppError,
+    events::{EventBus, UserEvent},
+    import::csv::{self, ColumnMapping},
+    jobs::{self, webhooks::WebhookDispatcher},
+    quotas::Quotas,
+    reports::cache::ReportCache,
+};
+
+/// Transaction import request body, sent as `multipart/form-data`
+#[derive(ToSchema)]
+pub struct ImportForm {
+    /// The CSV file
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+    /// How the columns of the file map to the fields of transactions, as JSON
+    pub mapping: ColumnMapping,
+}
+
+pub fn create_route(state: AppState, body_limit: usize) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/accounts/:id/transactions/import",
+            post(import_transactions),
+        )
+        .route("/accounts/:id/imports", get(all_imports))
+        .route("/imports/:id", get(get_import))
+        .route("/imports/:id/cancel", post(cancel_import))
+        .route("/accounts/:id/imports/pending", get(all_pending))
+        .route(
+            "/accounts/:id/imports/pending/:pending_id/confirm",
+            post(confirm_pending),
+        )
+        .route(
+            "/accounts/:id/imports/pending/:pending_id/discard",
+            post(discard_pending),
+        )
+        .layer(DefaultBodyLimit::max(body_limit))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint queues the import of transactions from a bank's CSV export
+///
+/// The request is a `multipart/form-data` body with a `file` part holding the CSV file and a
+/// `mapping` part holding a JSON `ColumnMapping`. The file is validated right away, then
+/// imported by the job queue in batches of 500 rows, each committed with the progress of the
+/// import. Rows that look like duplicates of existing transactions are held back for review
+/// instead of being inserted. Payee rules are applied to the imported rows, and the spending
+/// alerts of their categories are evaluated once they are inserted.
+///
+/// ## Responses
+///
+/// `202` : The import is queued. Returns the import, to poll at `/imports/{id}`.
+/// `400` : The file isn't UTF-8, the mapping is invalid, or a part is missing.
+/// `403` : The rows of the file don't fit the quota of transactions of the user.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `409` : The account is archived.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/transactions/import",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    request_body(content = ImportForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 202, description = "Import queued", body = ImportJob),
+        (status = 400, description = "Invalid file or mapping"),
+        (status = 403, description = "Over the quota of transactions"),
+        (status = 404, description = "Account not found"),
+        (status = 409, description = "Account archived")
+    )
+)]
+async fn import_transactions(
+    State(pool): State<Arc<DbPool>>,
+    State(quotas): State<Quotas>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<ImportJob>), AppError> {
+    let mut file = None;
+    let mut mapping = None;
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("file") => {
+                let bytes = field.bytes().await?;
+                file = Some(bytes);
+            }
+            Some("mapping") => {
+                let text = field.text().await?;
+                mapping = Some(text);
+            }
+            _ => {}
+        }
+    }
+
+    let file = file.ok_or_else(|| AppError::InvalidInput("Missing \"file\" part".to_string()))?;
+    let mapping =
+        mapping.ok_or_else(|| AppError::InvalidInput("Missing \"mapping\" part".to_string()))?;
+
+    let user_id = session.user_id();
+    let account = pool
+        .run(move |conn| {
+            let account = Account::from_id(conn, id, user_id)?;
+            account.ensure_open()?;
+            Ok(account)
+        })
+        .await?;
+
+    let mapping: ColumnMapping = serde_json::from_str(&mapping)
+        .map_err(|e| AppError::InvalidInput(format!("Invalid column mapping: {e}")))?;
+    let data = String::from_utf8(file.to_vec())
+        .map_err(|_| AppError::InvalidInput("The CSV file is not valid UTF-8".to_string()))?;
+
+    let (data, mapping, parsed) = tokio::task::spawn_blocking(move || {
+        let parsed = csv::parse(&data, &mapping)?;
+        Ok::<_, String>((data, mapping, parsed))
+    })
+    .await?
+    .map_err(AppError::InvalidInput)?;
+
+    // The import and its job are created together, or not at all
+    let import = pool
+        .transaction(move |conn| {
+            let now = clock.now_utc();
+            jobs::imports::enqueue(conn, &account, data, mapping, &parsed, &quotas, now)
+        })
+        .await?;
+
+    Ok((StatusCode::ACCEPTED, Json(import)))
+}
+
+/// This endpoint returns the imports of an account, newest first
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of imports.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/imports",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    responses(
+        (status = 200, description = "Imports of the account", body = Vec<ImportJob>),
+        (status = 404, description = "Account not found", body = ErrorBody)
+    )
+)]
+async fn all_imports(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<ImportJob>>, AppError> {
+    pool.run_read(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let imports = ImportJob::get_all(conn, &account)?;
+
+        Ok(Json(imports))
+    })
+    .await
+}
+
+/// This endpoint returns an import, with its progress
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the import.
+/// `404` : The import doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/imports/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the import")),
+    responses(
+        (status = 200, description = "The import", body = ImportJob),
+        (status = 404, description = "Import not found", body = ErrorBody)
+    )
+)]
+async fn get_import(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<ImportJob>, AppError> {
+    pool.run(move |conn| Ok(Json(ImportJob::from_id(conn, id, session.user_id())?)))
+        .await
+}
+
+/// This endpoint cancels an import
+///
+/// A queued import is cancelled right away. A running import stops before its next batch, the
+/// batches it committed are kept.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the import, `cancelled` or about to be.
+/// `404` : The import doesn't exist or belongs to another user.
+/// `409` : The import already finished.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/imports/{id}/cancel",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the import")),
+    responses(
+        (status = 200, description = "Import cancelled", body = ImportJob),
+        (status = 404, description = "Import not found", body = ErrorBody),
+        (status = 409, description = "Import already finished", body = ErrorBody)
+    )
+)]
+async fn cancel_import(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<ImportJob>, AppError> {
+    pool.transaction(move |conn| {
+        let import = ImportJob::from_id(conn, id, session.user_id())?;
+        let import = import.cancel(conn, clock.now_utc())?;
+
+        Ok(Json(import))
+    })
+    .await
+}
+
+/// This endpoint returns the imported rows of an account held back as likely duplicates
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of pending rows.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/imports/pending",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    responses(
+        (status = 200, description = "Pending rows of the account", body = Vec<PendingImport>),
+        (status = 404, description = "Account not found", body = ErrorBody)
+    )
+)]
+async fn all_pending(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<PendingImport>>, AppError> {
+    pool.run_read(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let pending = PendingImport::get_all(conn, &account)?;
+
+        Ok(Json(pending))
+    })
+    .await
+}
+
+/// This endpoint confirms that a pending row is not a duplicate and inserts it
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the created transaction.
+/// `403` : The user has as many transactions as their quota allows.
+/// `404` : The account or pending row doesn't exist.
+/// `409` : The account is archived.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/imports/pending/{pending_id}/confirm",
+    security(("cookieAuth" = [])),
+    params(
+        ("id" = i32, Path, description = "ID of the account"),
+        ("pending_id" = i32, Path, description = "ID of the pending row")
+    ),
+    responses(
+        (status = 200, description = "Transaction created from the row", body = Transaction),
+        (status = 403, description = "Over the quota of transactions"),
+        (status = 404, description = "Pending row not found"),
+        (status = 409, description = "Account archived")
+    )
+)]
+async fn confirm_pending(
+    State(pool): State<Arc<DbPool>>,
+    State(quotas): State<Quotas>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Extension(webhooks): Extension<Arc<WebhookDispatcher>>,
+    State(events): State<Arc<EventBus>>,
+    Path((id, pending_id)): Path<(i32, i32)>,
+) -> Result<Json<Transaction>, AppError> {
+    pool.transaction(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let pending = PendingImport::from_id(conn, pending_id, &account)?;
+        let rules = PayeeRules::load(conn, session.user_id())?;
+        let transaction = pending.confirm(conn, &account, &rules, &quotas)?;
+        webhooks.notify(
+            conn,
+            session.user_id(),
+            WebhookEvent::TransactionCreated,
+            &transaction,
+        );
+        events.publish(
+            session.user_id(),
+            UserEvent::TransactionCreated,
+            &transaction,
+        );
+        reports.invalidate(session.user_id());
+        alerts::check(
+            conn,
+            &webhooks,
+            &events,
+            session.user_id(),
+            transaction
+                .category_id()
+                .map(|category_id| (category_id, transaction.occurred_at())),
+        );
+
+        Ok(Json(transaction))
+    })
+    .await
+}
+
+/// This endpoint discards a pending row without inserting it
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the row was discarded.
+/// `404` : The account or pending row doesn't exist.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/imports/pending/{pending_id}/discard",
+    security(("cookieAuth" = [])),
+    params(
+        ("id" = i32, Path, description = "ID of the account"),
+        ("pending_id" = i32, Path, description = "ID of the pending row")
+    ),
+    responses(
+        (status = 200, description = "Pending row discarded", body = MessageResponse),
+        (status = 404, description = "Pending row not found")
+    )
+)]
+async fn discard_pending(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path((id, pending_id)): Path<(i32, i32)>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        PendingImport::from_id(conn, pending_id, &account)?.discard(conn)?;
+
+        Ok(Json(MessageResponse::new("Pending row discarded")))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+    };
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+    use crate::database::models::{accounts::Account, import_jobs::ImportJob};
+
+    async fn account(app: &TestApp) -> Value {
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        account
+    }
+
+    /// Upload a CSV file with its column mapping
+    async fn import(app: &TestApp, uri: &str, csv: &str) -> (StatusCode, Value) {
+        let mapping = json!({
+            "date": "Date",
+            "date_format": "%Y-%m-%d",
+            "description": "Description",
+            "amount": "Amount",
+        });
+        let boundary = "finance-fusion-test-boundary";
+        let body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"bank.csv\"\r\nContent-Type: text/csv\r\n\r\n{csv}\r\n--{boundary}\r\nContent-Disposition: form-data; name=\"mapping\"\r\n\r\n{mapping}\r\n--{boundary}--\r\n"
+        );
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(header::COOKIE, app.cookie())
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let (status, _, bytes) = app.send(request).await;
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_import_runs_on_the_queue() {
+        let app = TestApp::new();
+        let account = account(&app).await;
+        let uri = format!("/accounts/{}/transactions/import", account["id"]);
+
+        let csv = "Date,Description,Amount\n2024-06-01,Coffee,-3.50\n2024-06-02,Groceries,-84.10\nsoon,Broken,-1.00\n";
+        let (status, import) = import(&app, &uri, csv).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{import}");
+        assert_eq!(import["status"], "queued");
+        assert_eq!(import["rows_total"], 2);
+        assert_eq!(import["skipped"], 1);
+
+        // The worker of the queue imports the rows in the background
+        let uri = format!("/imports/{}", import["id"]);
+        let mut import = import;
+        for _ in 0..200 {
+            (_, import) = app.request(Method::GET, &uri, None).await;
+            if import["status"] == "done" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(import["status"], "done", "{import}");
+        assert_eq!(import["rows_processed"], 2);
+        assert_eq!(import["inserted"], 2);
+        assert!(import["finished_at"].is_string());
+
+        let (_, transactions) = app
+            .request(
+                Method::GET,
+                &format!("/accounts/{}/transactions", account["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(transactions.as_array().unwrap().len(), 2, "{transactions}");
+
+        let (status, imports) = app
+            .request(
+                Method::GET,
+                &format!("/accounts/{}/imports", account["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(imports.as_array().unwrap().len(), 1);
+        assert_eq!(imports[0]["id"], import["id"]);
+
+        // A finished import can't be cancelled
+        let (status, error) = app
+            .request(Method::POST, &format!("{uri}/cancel"), None)
+            .await;
+        assert_eq!(status, StatusCode::CONFLICT, "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_queued_import() {
+        let app = TestApp::new();
+        let account = account(&app).await;
+        let import = {
+            let conn = &mut app.pool().get().unwrap();
+            let account =
+                Account::from_id(conn, account["id"].as_i64().unwrap() as i32, app.user_id())
+                    .unwrap();
+            ImportJob::new(conn, &account, 10, &[]).unwrap()
+        };
+
+        let uri = format!("/imports/{}/cancel", import.id());
+        let (status, import) = app.request(Method::POST, &uri, None).await;
+        assert_eq!(status, StatusCode::OK, "{import}");
+        assert_eq!(import["status"], "cancelled");
+        assert_eq!(import["rows_processed"], 0);
+
+        let (status, _) = app.request(Method::POST, &uri, None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = app.request(Method::GET, "/imports/0", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+use axum::{routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    api::{api::API_PREFIX, state::AppState},
+    config::config::VERSION,
+};
+
+/// Resources of the API listed by the index, with their paths relative to `API_PREFIX`
+const RESOURCES: [(&str, &str); 13] = [
+    ("accounts", "/accounts"),
+    ("categories", "/categories"),
+    ("events", "/events/stream"),
+    ("goals", "/goals"),
+    ("login", "/auth/login"),
+    ("notifications", "/notifications"),
+    ("plans", "/plans"),
+    ("rules", "/rules"),
+    ("scheduled_reports", "/scheduled-reports"),
+    ("search", "/search"),
+    ("tags", "/tags"),
+    ("transfers", "/transfers"),
+    ("webhooks", "/webhooks"),
+];
+
+/// The entry point of the API
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiIndex {
+    /// Version of the server
+    pub version: String,
+    /// URLs of the top level resources by their name
+    pub resources: BTreeMap<String, String>,
+}
+
+pub fn create_route() -> Router<AppState> {
+    Router::new().route("/", get(get_index))
+}
+
+/// This endpoint responds with the URLs of the top level resources of the API and the version of
+/// the server.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the index of the API.
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "index",
+    responses((status = 200, description = "The index of the API", body = ApiIndex))
+)]
+pub async fn get_index() -> Json<ApiIndex> {
+    let mut resources: BTreeMap<String, String> = RESOURCES
+        .iter()
+        .map(|(name, path)| (name.to_string(), format!("{API_PREFIX}{path}")))
+        .collect();
+    // Served at the root rather than under the prefix
+    resources.insert("openapi".to_owned(), "/api-docs/openapi.json".to_owned());
+    resources.insert("version".to_owned(), "/version".to_owned());
+    resources.insert("vitals".to_owned(), "/vitals".to_owned());
+
+    Json(ApiIndex {
+        version: VERSION.to_owned(),
+        resources,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+    };
+
+    use super::*;
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_index() {
+        let app = TestApp::new();
+
+        let (status, index) = app.request(Method::GET, API_PREFIX, None).await;
+        assert_eq!(status, 200, "{index}");
+        assert_eq!(index["version"], VERSION);
+        assert_eq!(index["resources"]["plans"], "/api/v1/plans");
+        assert_eq!(index["resources"]["accounts"], "/api/v1/accounts");
+        assert_eq!(index["resources"]["vitals"], "/vitals");
+
+        // Every resource listed is a route, asked for its methods as the event stream doesn't end
+        for (name, url) in index["resources"].as_object().unwrap() {
+            let request = Request::builder()
+                .method(Method::OPTIONS)
+                .uri(url.as_str().unwrap())
+                .body(Body::empty())
+                .unwrap();
+            let (status, headers, _) = app.send(request).await;
+            assert_eq!(status, StatusCode::NO_CONTENT, "{name}");
+            assert!(headers.contains_key(header::ALLOW), "{name}");
+        }
+    }
+}
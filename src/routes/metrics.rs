@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    routing::get,
+    Extension, Router,
+};
+
+use crate::{
+    api::state::AppState,
+    database::connection::DbPool,
+    errors::{AppError, AuthenticateError},
+    metrics::Metrics,
+    reports::cache::ReportCache,
+};
+
+/// Content type of the Prometheus text format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+pub fn create_route() -> Router<AppState> {
+    Router::new().route("/metrics", get(get_metrics))
+}
+
+/// This endpoint responds with the metrics of the server, in the Prometheus text format
+///
+/// The metrics are HTTP request counts and latencies by method, route and status, the connections
+/// of the database pool, counts of logins, failed logins and lockouts, and the hits and misses of
+/// the cache of reports since the server started. When the server is configured with a metrics token, it must be sent as a bearer token.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the metrics.
+/// `401` : The metrics token is missing or wrong.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    security((), ("metricsToken" = [])),
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or wrong metrics token")
+    )
+)]
+pub async fn get_metrics(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(metrics): Extension<Arc<Metrics>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !metrics.authorize(token) {
+        return Err(AppError::Authenticate(AuthenticateError::InvalidToken));
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, CONTENT_TYPE)],
+        metrics.render(&pool, &reports),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use crate::api::test_utils::TestApp;
+
+    /// Get the value of a sample in scraped metrics
+    fn sample(metrics: &str, series: &str) -> u64 {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .unwrap_or("0")
+            .parse()
+            .unwrap()
+    }
+
+    async fn scrape(app: &TestApp) -> String {
+        let (status, metrics) = app.request(Method::GET, "/metrics", None).await;
+        assert_eq!(status, 200);
+        metrics.as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let app = TestApp::new();
+        let accounts =
+            "http_requests_total{method=\"GET\",route=\"/api/v1/accounts\",status=\"200\"}";
+        let before = scrape(&app).await;
+
+        // Unknown paths aren't labeled with their path
+        let (status, _) = app.request(Method::GET, "/nowhere/1", None).await;
+        assert_eq!(status, 404);
+        // Requests to legacy paths are labeled with the route they're forwarded to
+        for uri in ["/api/v1/accounts", "/accounts"] {
+            let (status, _) = app.request(Method::GET, uri, None).await;
+            assert_eq!(status, 200);
+        }
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/auth/login",
+                Some(json!({"username": "nobody", "password": "wrong"})),
+            )
+            .await;
+        assert_ne!(status, 200);
+
+        let after = scrape(&app).await;
+        assert_eq!(
+            sample(&after, accounts),
+            sample(&before, accounts) + 2,
+            "{after}"
+        );
+        assert_eq!(
+            sample(&after, "auth_failed_logins_total"),
+            sample(&before, "auth_failed_logins_total") + 1
+        );
+        assert!(after.contains(
+            "http_request_duration_seconds_count{method=\"GET\",route=\"/api/v1/accounts\",status=\"200\"} 2"
+        ));
+        assert!(!after.contains("/nowhere"));
+        assert!(after.contains("db_pool_connections_in_use{pool=\"primary\"} "));
+        assert!(after.contains("# TYPE report_cache_hits_total counter"));
+        assert!(after.contains("report_cache_misses_total 0"));
+    }
+}
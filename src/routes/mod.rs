@@ -1,4 +1,28 @@
+pub mod accounts;
+pub mod admin;
+pub mod attachments;
 pub mod auth;
+pub mod budgets;
+pub mod categories;
+pub mod events;
+pub mod exports;
+pub mod goals;
+pub mod imports;
+pub mod index;
+pub mod metrics;
+pub mod notes;
+pub mod notifications;
 pub mod plans;
+pub mod reconciliations;
+pub mod recurring;
+pub mod reports;
+pub mod rules;
+pub mod scheduled_reports;
+pub mod search;
+pub mod tags;
+pub mod transactions;
+pub mod transfers;
 pub mod users;
+pub mod version;
 pub mod vitals;
+pub mod webhooks;
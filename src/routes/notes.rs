@@ -0,0 +1,279 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{get, patch},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson},
+        owned::OwnedPlan,
+        responses::MessageResponse,
+        state::AppState,
+    },
+    database::{
+        connection::DbPool,
+        models::{
+            plan_notes::{self, PlanNote},
+            sessions::manager::Session,
+        },
+    },
+    errors::AppError,
+};
+
+/// Create or update note request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_note, update_note))]
+#[schema(example = json!({"body": "Cut dining out until the trip is paid off"}))]
+pub struct SaveNote {
+    /// Plain text of the note, at most 10,000 characters and without HTML tags
+    body: String,
+}
+
+impl Validate for SaveNote {
+    fn validate(&self) -> Result<(), AppError> {
+        plan_notes::validate_body(&self.body)
+    }
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/plans/:name/notes", get(all_notes).post(create_note))
+        .route(
+            "/plans/:name/notes/:id",
+            patch(update_note).delete(delete_note),
+        )
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all notes of a plan
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of notes, newest first.
+/// `404` : The plan doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/plans/{name}/notes",
+    security(("cookieAuth" = [])),
+    params(("name" = String, Path, description = "Name of the plan")),
+    responses(
+        (status = 200, description = "Notes of the plan", body = Vec<PlanNote>),
+        (status = 404, description = "Plan not found")
+    )
+)]
+async fn all_notes(
+    State(pool): State<Arc<DbPool>>,
+    OwnedPlan(plan): OwnedPlan,
+) -> Result<Json<Vec<PlanNote>>, AppError> {
+    pool.run_read(move |conn| {
+        let notes = PlanNote::get_all(conn, &plan)?;
+
+        Ok(Json(notes))
+    })
+    .await
+}
+
+/// This endpoint writes a note on a plan, such as why a budget changed
+///
+/// The plan is marked as modified.
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created note.
+/// `400` : The body is empty, longer than 10,000 characters, or contains HTML tags.
+/// `404` : The plan doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/plans/{name}/notes",
+    security(("cookieAuth" = [])),
+    params(("name" = String, Path, description = "Name of the plan")),
+    request_body = SaveNote,
+    responses(
+        (status = 201, description = "Note created", body = PlanNote),
+        (status = 400, description = "Invalid body"),
+        (status = 404, description = "Plan not found")
+    )
+)]
+async fn create_note(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    OwnedPlan(plan): OwnedPlan,
+    ValidatedJson(payload): ValidatedJson<SaveNote>,
+) -> Result<(StatusCode, Json<PlanNote>), AppError> {
+    pool.run(move |conn| {
+        let note = PlanNote::new(conn, &plan, session.user_id(), &payload.body)?;
+
+        Ok((StatusCode::CREATED, Json(note)))
+    })
+    .await
+}
+
+/// This endpoint edits the body of a note
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated note.
+/// `400` : The body is empty, longer than 10,000 characters, or contains HTML tags.
+/// `403` : The user is neither the author of the note nor the owner of the plan.
+/// `404` : The plan or note doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    patch,
+    path = "/plans/{name}/notes/{id}",
+    security(("cookieAuth" = [])),
+    params(
+        ("name" = String, Path, description = "Name of the plan"),
+        ("id" = i32, Path, description = "ID of the note")
+    ),
+    request_body = SaveNote,
+    responses(
+        (status = 200, description = "Note updated", body = PlanNote),
+        (status = 400, description = "Invalid body"),
+        (status = 403, description = "User can't edit the note"),
+        (status = 404, description = "Note not found")
+    )
+)]
+async fn update_note(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    OwnedPlan(plan): OwnedPlan,
+    Path((_name, id)): Path<(String, i32)>,
+    ValidatedJson(payload): ValidatedJson<SaveNote>,
+) -> Result<Json<PlanNote>, AppError> {
+    pool.run(move |conn| {
+        let note = PlanNote::from_id(conn, id, &plan)?.update(
+            conn,
+            &plan,
+            session.user_id(),
+            &payload.body,
+        )?;
+
+        Ok(Json(note))
+    })
+    .await
+}
+
+/// This endpoint deletes a note
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the note was deleted.
+/// `403` : The user is neither the author of the note nor the owner of the plan.
+/// `404` : The plan or note doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/plans/{name}/notes/{id}",
+    security(("cookieAuth" = [])),
+    params(
+        ("name" = String, Path, description = "Name of the plan"),
+        ("id" = i32, Path, description = "ID of the note")
+    ),
+    responses(
+        (status = 200, description = "Note deleted", body = MessageResponse),
+        (status = 403, description = "User can't delete the note"),
+        (status = 404, description = "Note not found")
+    )
+)]
+async fn delete_note(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    OwnedPlan(plan): OwnedPlan,
+    Path((_name, id)): Path<(String, i32)>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        PlanNote::from_id(conn, id, &plan)?.delete(conn, &plan, session.user_id())?;
+
+        Ok(Json(MessageResponse::new("Note deleted")))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_notes() {
+        let app = TestApp::new();
+        let (status, _) = app.request(Method::POST, "/plans/Household", None).await;
+        assert_eq!(status, 201);
+
+        for body in ["Set up the budgets", "Raised groceries to 600"] {
+            let (status, note) = app
+                .request(
+                    Method::POST,
+                    "/plans/Household/notes",
+                    Some(json!({ "body": body })),
+                )
+                .await;
+            assert_eq!(status, 201, "{note}");
+        }
+
+        let (status, error) = app
+            .request(
+                Method::POST,
+                "/plans/Household/notes",
+                Some(json!({ "body": "a".repeat(10_001) })),
+            )
+            .await;
+        assert_eq!(status, 400, "{error}");
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/plans/Missing/notes",
+                Some(json!({ "body": "Lost" })),
+            )
+            .await;
+        assert_eq!(status, 404);
+
+        // Newest first
+        let (status, notes) = app
+            .request(Method::GET, "/plans/Household/notes", None)
+            .await;
+        assert_eq!(status, 200);
+        let bodies: Vec<&str> = notes
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|note| note["body"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            bodies,
+            vec!["Raised groceries to 600", "Set up the budgets"]
+        );
+
+        let uri = format!("/plans/Household/notes/{}", notes[0]["id"]);
+        let (status, note) = app
+            .request(
+                Method::PATCH,
+                &uri,
+                Some(json!({ "body": "Raised groceries to 650" })),
+            )
+            .await;
+        assert_eq!(status, 200, "{note}");
+        assert_eq!(note["body"], "Raised groceries to 650");
+
+        let (status, _) = app.request(Method::DELETE, &uri, None).await;
+        assert_eq!(status, 200);
+        let (_, notes) = app
+            .request(Method::GET, "/plans/Household/notes", None)
+            .await;
+        assert_eq!(notes.as_array().unwrap().len(), 1);
+    }
+}
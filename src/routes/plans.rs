@@ -2,92 +2,267 @@ use std::sync::Arc;
 
 use axum::{
     extract::{Path, State},
+    http::{HeaderMap, StatusCode},
     middleware,
-    routing::{delete, get, post},
+    response::Response,
+    routing::{delete, get},
     Extension, Json, Router,
 };
 
 use crate::{
+    api::{
+        owned::OwnedPlan,
+        pagination::{PageParams, PageQuery},
+        responses::MessageResponse,
+        state::AppState,
+    },
     database::{
         connection::DbPool,
-        models::{plans::Plan, sessions::manager::Session},
+        models::{plans::Plan, sessions::manager::Session, webhooks::WebhookEvent},
     },
     errors::AppError,
+    events::{EventBus, UserEvent},
+    jobs::webhooks::WebhookDispatcher,
+    middleware::etag::Validator,
 };
 
-pub fn create_route(pool: Arc<DbPool>) -> Router<Arc<DbPool>> {
+pub fn create_route(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/plans", get(all_plans))
-        .route("/plans/:name", post(create_plan))
+        .route("/plans/:name", get(get_plan).post(create_plan))
         .route("/plans/:name", delete(delete_plan))
         .layer(middleware::from_fn_with_state(
-            pool.clone(),
+            state,
             crate::middleware::auth::jwt_auth,
         ))
 }
 
-/// This endpoint returns all plans for the authenticated user
+/// This endpoint returns the plans of the authenticated user, a page at a time
+///
+/// Plans are ordered by name, each with the number of its budgets and notes. Pages are tagged with the version of the plans, `If-None-Match`
+/// with it skips loading them.
 ///
 /// ## Responses
-/// `200` : A successful response. Returns  a vector of plans.
+/// `200` : A successful response. Returns a page of plans, with the other pages in `Link`.
+/// `304` : The plans weren't modified since the ETag of `If-None-Match`.
+/// `400` : The page is 0, or the number of plans per page isn't between 1 and 100.
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
         get,
         path = "/plans",
-        responses((status = 200), (status = 401, description = "User is not authenticated"))
+        security(("cookieAuth" = [])),
+        params(PageQuery),
+        responses(
+            (status = 200, description = "Page of plans", body = PlanPage),
+            (status = 400, description = "Invalid page", body = ErrorBody),
+            (status = 304, description = "Plans weren't modified"),
+            (status = 401, description = "User is not authenticated", body = ErrorBody)
+        )
     )]
 async fn all_plans(
     Extension(session): Extension<Session>,
     State(pool): State<Arc<DbPool>>,
-) -> Result<Json<Vec<Plan>>, AppError> {
-    let mut conn = pool.get()?;
+    params: PageParams,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    pool.run_read(move |conn| {
+        let (count, last_modified) = Plan::list_version(conn, session.user_id())?;
+        let validator = Validator::new(format_args!(
+            "plans-{count}-{}-{}-{}",
+            last_modified.map_or(0, |time| time.and_utc().timestamp_micros()),
+            params.page(),
+            params.per_page()
+        ));
+        if let Some(response) = validator.not_modified(&headers) {
+            return Ok(response);
+        }
+
+        let plans =
+            Plan::list_with_stats(conn, session.user_id(), params.limit(), params.offset())?;
+        Ok(validator.tag(params.into_page(plans, count)))
+    })
+    .await
+}
 
-    let plans = Plan::get_all(&mut conn, session.user_id())?;
-    Ok(Json(plans)) // Wrap the result in Json
+/// This endpoint returns a plan
+///
+/// The plan is tagged with its version, which changes whenever the plan or one of its budgets or
+/// notes does.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the plan, with its version in `ETag`.
+/// `304` : The plan wasn't modified since the ETag of `If-None-Match`.
+/// `404` : The plan doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/plans/{name}",
+    security(("cookieAuth" = [])),
+    params(("name" = String, Path, description = "Name of the plan")),
+    responses(
+        (status = 200, description = "Plan", body = Plan),
+        (status = 304, description = "Plan wasn't modified"),
+        (status = 401, description = "User is not authenticated", body = ErrorBody),
+        (status = 404, description = "Plan not found", body = ErrorBody)
+    )
+)]
+async fn get_plan(OwnedPlan(plan): OwnedPlan, headers: HeaderMap) -> Response {
+    let validator = Validator::new(plan.version());
+    validator
+        .not_modified(&headers)
+        .unwrap_or_else(|| validator.tag(Json(plan)))
 }
 
 /// This endpoint creates a new plan
 ///
 /// ## Responses
 ///
-/// `201` : A successful response. Returns a string indicating the plan was created.
+/// `201` : A successful response. Returns the plan.
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
     post,
     path = "/plans/{name}",
-    responses((status = 201, description = "Plan created"))
+    security(("cookieAuth" = [])),
+    responses(
+        (status = 201, description = "Plan created", body = Plan),
+        (status = 401, description = "User is not authenticated", body = ErrorBody)
+    )
 )]
 async fn create_plan(
     State(pool): State<Arc<DbPool>>,
     Extension(session): Extension<Session>,
+    Extension(webhooks): Extension<Arc<WebhookDispatcher>>,
+    State(events): State<Arc<EventBus>>,
     Path(name): Path<String>,
-) -> Result<String, AppError> {
-    let mut conn = pool.get()?;
+) -> Result<(StatusCode, Json<Plan>), AppError> {
+    pool.run(move |conn| {
+        let plan = Plan::new(conn, &name, session.user_id())?;
+        webhooks.notify(conn, session.user_id(), WebhookEvent::PlanCreated, &plan);
+        events.publish(session.user_id(), UserEvent::PlanCreated, &plan);
 
-    Plan::new(&mut conn, &name, session.user_id())?;
-
-    Ok("Plan created".to_string())
+        Ok((StatusCode::CREATED, Json(plan)))
+    })
+    .await
 }
 
 /// This endpoint deletes a plan
 ///
 /// ## Responses
 ///
-/// `200` : A successful response. Returns a string indicating the plan was deleted.
+/// `200` : A successful response. Returns a message telling the plan was deleted.
+/// `404` : The plan doesn't exist or belongs to another user.
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
     delete,
     path = "/plans/{name}",
-    responses((status = 200, description = "Plan deleted"))
+    security(("cookieAuth" = [])),
+    params(("name" = String, Path, description = "Name of the plan")),
+    responses(
+        (status = 200, description = "Plan deleted", body = MessageResponse),
+        (status = 401, description = "User is not authenticated", body = ErrorBody),
+        (status = 404, description = "Plan not found", body = ErrorBody)
+    )
 )]
 async fn delete_plan(
     State(pool): State<Arc<DbPool>>,
-    Extension(session): Extension<Session>,
-    Path(name): Path<String>,
-) -> Result<String, AppError> {
-    let mut conn = pool.get()?;
+    Extension(webhooks): Extension<Arc<WebhookDispatcher>>,
+    State(events): State<Arc<EventBus>>,
+    OwnedPlan(plan): OwnedPlan,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        Plan::delete(conn, plan.name(), plan.user_id())?;
+        webhooks.notify(conn, plan.user_id(), WebhookEvent::PlanDeleted, &plan);
+        events.publish(plan.user_id(), UserEvent::PlanDeleted, &plan);
+
+        Ok(Json(MessageResponse::new("Plan deleted")))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, Method, StatusCode};
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+    use crate::database::models::{plans::Plan, users::User};
+
+    #[tokio::test]
+    async fn test_plan_pages() {
+        let app = TestApp::new();
+        for name in ["c", "a", "b"] {
+            let (status, _) = app
+                .request(Method::POST, &format!("/api/v1/plans/{name}"), None)
+                .await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let (status, headers, body) = app.download("/api/v1/plans?per_page=2&page=2").await;
+        assert_eq!(status, StatusCode::OK);
+        let page: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page["items"].as_array().unwrap().len(), 1);
+        assert_eq!(page["items"][0]["name"], "c");
+        assert_eq!(page["items"][0]["budgets"], 0);
+        assert_eq!(page["items"][0]["notes"], 0);
+        assert_eq!(page["total"], 3);
+        let links = headers[header::LINK].to_str().unwrap();
+        assert!(links.contains("</api/v1/plans?page=1&per_page=2>; rel=\"prev\""));
+        assert!(!links.contains("rel=\"next\""));
+
+        // Pages have ETags of their own
+        let (_, first, _) = app.download("/api/v1/plans?per_page=2").await;
+        assert_ne!(first[header::ETAG], headers[header::ETAG]);
+
+        // Notes are counted as they're written
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/api/v1/plans/c/notes",
+                Some(json!({"body": "Keep it lean"})),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, _, body) = app.download("/api/v1/plans?per_page=2&page=2").await;
+        let page: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page["items"][0]["notes"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_foreign_plans_are_not_found() {
+        let app = TestApp::new();
+        let other = {
+            let mut conn = app.pool().get().unwrap();
+            let other = User::new(&mut conn, "plans_other", "password").unwrap();
+            Plan::new(&mut conn, "foreign", other.id()).unwrap();
+            other
+        };
 
-    Plan::delete(&mut conn, &name, session.user_id())?;
+        // Plans of other users answer exactly like plans that don't exist
+        for (method, uri) in [
+            (Method::GET, "/api/v1/plans/{}/notes"),
+            (Method::GET, "/api/v1/plans/{}/budgets"),
+            (
+                Method::GET,
+                "/api/v1/plans/{}/budgets/report?year=2024&month=6",
+            ),
+            (Method::DELETE, "/api/v1/plans/{}/budgets/1"),
+            (Method::DELETE, "/api/v1/plans/{}"),
+        ] {
+            let mut bodies = Vec::new();
+            for name in ["foreign", "missing"] {
+                let (status, mut body) = app
+                    .request(method.clone(), &uri.replace("{}", name), None)
+                    .await;
+                assert_eq!(status, StatusCode::NOT_FOUND, "{method} {uri}: {body}");
+                body.as_object_mut().unwrap().remove("request_id");
+                bodies.push(body);
+            }
+            assert_eq!(bodies[0], bodies[1], "{method} {uri}");
+        }
 
-    Ok("Plan deleted".to_string())
+        // The plan of the other user wasn't deleted
+        let mut conn = app.pool().get().unwrap();
+        assert_eq!(Plan::get_all(&mut conn, other.id()).unwrap().len(), 1);
+    }
 }
@@ -0,0 +1,415 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson},
+        state::AppState,
+    },
+    database::{
+        connection::{DbConn, DbPool},
+        models::{
+            accounts::Account,
+            reconciliations::{Reconciliation, ReconciliationCandidate},
+            sessions::manager::Session,
+        },
+    },
+    errors::AppError,
+};
+
+/// Start reconciliation request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(start_reconciliation))]
+#[schema(example = json!({"statement_date": "2024-06-30", "ending_balance": "1234.56"}))]
+pub struct StartReconciliation {
+    /// The ending date of the statement
+    statement_date: NaiveDate,
+    /// The ending balance of the statement, as a decimal string
+    #[schema(value_type = String)]
+    ending_balance: BigDecimal,
+}
+
+impl Validate for StartReconciliation {}
+
+/// Clear transactions request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(clear_transactions))]
+#[schema(example = json!({"transaction_ids": [12, 15, 16]}))]
+pub struct ClearTransactions {
+    /// IDs of the transactions to clear, cleared transactions are uncleared instead
+    transaction_ids: Vec<i32>,
+}
+
+impl Validate for ClearTransactions {}
+
+/// A reconciliation with the transactions that can be cleared in it
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReconciliationDetails {
+    /// The reconciliation
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    reconciliation: Reconciliation,
+    /// The opening balance plus the cleared transactions, as a decimal string
+    #[schema(value_type = String)]
+    cleared_balance: BigDecimal,
+    /// The ending balance minus the cleared balance, zero once the statement is matched
+    #[schema(value_type = String)]
+    difference: BigDecimal,
+    /// The transactions up to the statement date, oldest first
+    transactions: Vec<ReconciliationCandidate>,
+}
+
+impl ReconciliationDetails {
+    fn load(conn: &mut DbConn, reconciliation: Reconciliation) -> Result<Self, AppError> {
+        let cleared_balance = reconciliation.cleared_balance(conn)?;
+        Ok(Self {
+            difference: reconciliation.ending_balance() - &cleared_balance,
+            transactions: reconciliation.candidates(conn)?,
+            cleared_balance,
+            reconciliation,
+        })
+    }
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/accounts/:id/reconciliations",
+            get(all_reconciliations).post(start_reconciliation),
+        )
+        .route("/reconciliations/:id", get(get_reconciliation))
+        .route("/reconciliations/:id/clear", post(clear_transactions))
+        .route("/reconciliations/:id/finish", post(finish_reconciliation))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns the reconciliations of an account
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of reconciliations, latest statement first.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/reconciliations",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    responses(
+        (status = 200, description = "Reconciliations of the account", body = Vec<Reconciliation>),
+        (status = 404, description = "Account not found")
+    )
+)]
+async fn all_reconciliations(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<Reconciliation>>, AppError> {
+    pool.run_read(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+
+        Ok(Json(Reconciliation::get_all(conn, &account)?))
+    })
+    .await
+}
+
+/// This endpoint starts reconciling an account against a bank statement
+///
+/// The opening balance is the ending balance of the previous finished reconciliation, or the
+/// opening balance of the account for the first one.
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the reconciliation with its candidate transactions.
+/// `400` : The statement ends before the last reconciled statement.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `409` : The account has a reconciliation in progress.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/reconciliations",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    request_body = StartReconciliation,
+    responses(
+        (status = 201, description = "Reconciliation started", body = ReconciliationDetails),
+        (status = 400, description = "Statement ends before the last reconciled statement"),
+        (status = 404, description = "Account not found"),
+        (status = 409, description = "Reconciliation in progress")
+    )
+)]
+async fn start_reconciliation(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<StartReconciliation>,
+) -> Result<(StatusCode, Json<ReconciliationDetails>), AppError> {
+    pool.run(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let reconciliation = Reconciliation::new(
+            conn,
+            &account,
+            payload.statement_date,
+            &payload.ending_balance,
+        )?;
+
+        Ok((
+            StatusCode::CREATED,
+            Json(ReconciliationDetails::load(conn, reconciliation)?),
+        ))
+    })
+    .await
+}
+
+/// This endpoint returns a reconciliation with the transactions that can be cleared in it
+///
+/// These are the transactions of the account up to the statement date that weren't cleared in
+/// another reconciliation, each with whether it is cleared in this one.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the reconciliation with its candidate transactions.
+/// `404` : The reconciliation doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/reconciliations/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the reconciliation")),
+    responses(
+        (status = 200, description = "Reconciliation", body = ReconciliationDetails),
+        (status = 404, description = "Reconciliation not found")
+    )
+)]
+async fn get_reconciliation(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<ReconciliationDetails>, AppError> {
+    pool.run(move |conn| {
+        let reconciliation = Reconciliation::from_id(conn, id, session.user_id())?;
+
+        Ok(Json(ReconciliationDetails::load(conn, reconciliation)?))
+    })
+    .await
+}
+
+/// This endpoint toggles whether transactions are cleared in a reconciliation
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the reconciliation with its candidate transactions.
+/// `400` : A transaction isn't on the account or is after the statement date.
+/// `404` : The reconciliation doesn't exist or belongs to another user.
+/// `409` : The reconciliation is finished, or a transaction was cleared in another one.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/reconciliations/{id}/clear",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the reconciliation")),
+    request_body = ClearTransactions,
+    responses(
+        (status = 200, description = "Transactions toggled", body = ReconciliationDetails),
+        (status = 400, description = "Transaction can't be cleared"),
+        (status = 404, description = "Reconciliation not found"),
+        (status = 409, description = "Reconciliation finished or transaction cleared elsewhere")
+    )
+)]
+async fn clear_transactions(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<ClearTransactions>,
+) -> Result<Json<ReconciliationDetails>, AppError> {
+    pool.run(move |conn| {
+        let reconciliation = Reconciliation::from_id(conn, id, session.user_id())?;
+        reconciliation.toggle(conn, &payload.transaction_ids)?;
+
+        Ok(Json(ReconciliationDetails::load(conn, reconciliation)?))
+    })
+    .await
+}
+
+/// This endpoint finishes a reconciliation
+///
+/// The opening balance plus the cleared transactions must equal the ending balance of the
+/// statement exactly. Once finished, the cleared transactions can't be updated or deleted.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the finished reconciliation.
+/// `404` : The reconciliation doesn't exist or belongs to another user.
+/// `409` : The reconciliation is already finished, or the cleared balance doesn't match the
+/// ending balance. The message says how far off it is.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/reconciliations/{id}/finish",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the reconciliation")),
+    responses(
+        (status = 200, description = "Reconciliation finished", body = Reconciliation),
+        (status = 404, description = "Reconciliation not found"),
+        (status = 409, description = "Reconciliation finished or off balance")
+    )
+)]
+async fn finish_reconciliation(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<Reconciliation>, AppError> {
+    pool.run(move |conn| {
+        let reconciliation = Reconciliation::from_id(conn, id, session.user_id())?;
+
+        Ok(Json(reconciliation.finish(conn)?))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+
+    async fn transaction(app: &TestApp, account: &Value, amount: &str, occurred_at: &str) -> Value {
+        let (status, transaction) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/transactions", account["id"]),
+                Some(json!({"amount": amount, "description": "Statement line", "occurred_at": occurred_at})),
+            )
+            .await;
+        assert_eq!(status, 201, "{transaction}");
+        transaction
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_account() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "100.00", "currency": "CAD"})),
+            )
+            .await;
+        let pay = transaction(&app, &account, "500.00", "2024-06-01").await;
+        let rent = transaction(&app, &account, "-300.00", "2024-06-03").await;
+        let groceries = transaction(&app, &account, "-45.50", "2024-06-20").await;
+        transaction(&app, &account, "-20.00", "2024-07-02").await;
+
+        let (status, started) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/reconciliations", account["id"]),
+                Some(json!({"statement_date": "2024-06-30", "ending_balance": "300.00"})),
+            )
+            .await;
+        assert_eq!(status, 201, "{started}");
+        assert_eq!(started["opening_balance"], "100.00");
+        // The July transaction is after the statement date
+        assert_eq!(started["transactions"].as_array().unwrap().len(), 3);
+        let id = started["id"].clone();
+
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/reconciliations", account["id"]),
+                Some(json!({"statement_date": "2024-07-31", "ending_balance": "0.00"})),
+            )
+            .await;
+        assert_eq!(status, 409);
+
+        // Groceries only cleared the bank in July, so clearing it puts the balance off
+        let (status, cleared) = app
+            .request(
+                Method::POST,
+                &format!("/reconciliations/{id}/clear"),
+                Some(json!({"transaction_ids": [pay["id"], rent["id"], groceries["id"]]})),
+            )
+            .await;
+        assert_eq!(status, 200, "{cleared}");
+        assert_eq!(cleared["difference"], "45.50");
+        let (status, error) = app
+            .request(Method::POST, &format!("/reconciliations/{id}/finish"), None)
+            .await;
+        assert_eq!(status, 409);
+        assert!(
+            error["message"].as_str().unwrap().contains("45.50"),
+            "{error}"
+        );
+
+        let (_, cleared) = app
+            .request(
+                Method::POST,
+                &format!("/reconciliations/{id}/clear"),
+                Some(json!({"transaction_ids": [groceries["id"]]})),
+            )
+            .await;
+        assert_eq!(cleared["cleared_balance"], "300.00");
+        assert_eq!(cleared["transactions"][2]["cleared"], false);
+        let (status, finished) = app
+            .request(Method::POST, &format!("/reconciliations/{id}/finish"), None)
+            .await;
+        assert_eq!(status, 200, "{finished}");
+        assert!(finished["finished_at"].is_string());
+
+        // Cleared transactions are locked, the others can still change
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                &format!("/accounts/{}/transactions/{}", account["id"], rent["id"]),
+                Some(json!({"amount": "-310.00", "description": "Rent", "occurred_at": "2024-06-03"})),
+            )
+            .await;
+        assert_eq!(status, 409);
+        let (status, _) = app
+            .request(
+                Method::DELETE,
+                &format!("/accounts/{}/transactions/{}", account["id"], pay["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(status, 409);
+        let (status, _) = app
+            .request(
+                Method::DELETE,
+                &format!(
+                    "/accounts/{}/transactions/{}",
+                    account["id"], groceries["id"]
+                ),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200);
+
+        // The next statement opens with the ending balance of this one
+        let (status, next) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/reconciliations", account["id"]),
+                Some(json!({"statement_date": "2024-07-31", "ending_balance": "280.00"})),
+            )
+            .await;
+        assert_eq!(status, 201, "{next}");
+        assert_eq!(next["opening_balance"], "300.00");
+        assert_eq!(next["transactions"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,278 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{get, put},
+    Extension, Json, Router,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson},
+        responses::MessageResponse,
+        state::AppState,
+    },
+    database::{
+        connection::DbPool,
+        models::{
+            accounts::Account,
+            categories::Category,
+            recurring_transactions::{Cadence, RecurringChanges, RecurringTransaction, Schedule},
+            sessions::manager::Session,
+            transactions::TransactionInput,
+        },
+    },
+    errors::AppError,
+};
+
+/// Create recurring transaction request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_recurring))]
+#[schema(example = json!({"amount": "-1200.00", "description": "Rent", "category_id": 3, "cadence": "monthly", "day_of_month": 1, "starts_on": "2024-07-01", "end_on": null}))]
+pub struct CreateRecurring {
+    /// The signed amount of each occurrence, as a decimal string
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// The description of each occurrence
+    description: String,
+    /// The ID of the category of each occurrence
+    category_id: Option<i32>,
+    /// How often the transaction occurs (`weekly`, `monthly` or `yearly`)
+    cadence: Cadence,
+    /// The day of the month (1 to 31) of monthly and yearly cadences, clamped in short months
+    day_of_month: Option<i16>,
+    /// The day of the week (0 is Monday, 6 is Sunday) of weekly cadences
+    weekday: Option<i16>,
+    /// The first occurrence is the first day on or after this date that fits the schedule
+    starts_on: NaiveDate,
+    /// The last day an occurrence can be on
+    end_on: Option<NaiveDate>,
+}
+
+impl Validate for CreateRecurring {}
+
+/// Update recurring transaction request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(update_recurring))]
+#[schema(example = json!({"amount": "-1250.00", "description": "Rent", "category_id": 3, "end_on": "2025-06-30", "active": true}))]
+pub struct UpdateRecurring {
+    /// The signed amount of each occurrence, as a decimal string
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// The description of each occurrence
+    description: String,
+    /// The ID of the category of each occurrence
+    category_id: Option<i32>,
+    /// The last day an occurrence can be on
+    end_on: Option<NaiveDate>,
+    /// Whether occurrences are still created
+    active: bool,
+}
+
+impl Validate for UpdateRecurring {}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/accounts/:id/recurring",
+            get(all_recurring).post(create_recurring),
+        )
+        .route(
+            "/accounts/:id/recurring/:recurring_id",
+            put(update_recurring).delete(delete_recurring),
+        )
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all recurring transactions of an account
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of recurring transactions, next due first.
+/// `404` : The account doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/recurring",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    responses(
+        (status = 200, description = "Recurring transactions of the account", body = Vec<RecurringTransaction>),
+        (status = 404, description = "Account not found", body = ErrorBody)
+    )
+)]
+async fn all_recurring(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<RecurringTransaction>>, AppError> {
+    pool.run_read(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let recurring = RecurringTransaction::get_all(conn, &account)?;
+
+        Ok(Json(recurring))
+    })
+    .await
+}
+
+/// This endpoint creates a new recurring transaction on an account
+///
+/// Occurrences are created as transactions once they are due, by a background task that runs on
+/// startup and then once a day.
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created recurring transaction.
+/// `400` : The day of the schedule is missing or doesn't fit the cadence.
+/// `404` : The account or category doesn't exist or belongs to another user.
+/// `409` : The account is archived.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/recurring",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the account")),
+    request_body = CreateRecurring,
+    responses(
+        (status = 201, description = "Recurring transaction created", body = RecurringTransaction),
+        (status = 400, description = "Invalid schedule"),
+        (status = 404, description = "Account not found"),
+        (status = 409, description = "Account archived")
+    )
+)]
+async fn create_recurring(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<CreateRecurring>,
+) -> Result<(StatusCode, Json<RecurringTransaction>), AppError> {
+    pool.run(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        if let Some(category_id) = payload.category_id {
+            Category::from_id(conn, category_id, session.user_id())?;
+        }
+
+        let schedule = Schedule::new(payload.cadence, payload.day_of_month, payload.weekday)?;
+        let input = TransactionInput {
+            amount: payload.amount,
+            currency: None,
+            original_amount: None,
+            original_currency: None,
+            description: payload.description,
+            payee: None,
+            occurred_at: payload.starts_on,
+            category_id: payload.category_id,
+            goal_id: None,
+            splits: vec![],
+        };
+        let recurring = RecurringTransaction::new(
+            conn,
+            &account,
+            &input,
+            schedule,
+            payload.starts_on,
+            payload.end_on,
+        )?;
+
+        Ok((StatusCode::CREATED, Json(recurring)))
+    })
+    .await
+}
+
+/// This endpoint updates a recurring transaction
+///
+/// Occurrences that were already created are not changed. The schedule can't be changed, delete
+/// the recurring transaction and create a new one instead.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated recurring transaction.
+/// `404` : The account, recurring transaction or category doesn't exist.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    put,
+    path = "/accounts/{id}/recurring/{recurring_id}",
+    security(("cookieAuth" = [])),
+    params(
+        ("id" = i32, Path, description = "ID of the account"),
+        ("recurring_id" = i32, Path, description = "ID of the recurring transaction")
+    ),
+    request_body = UpdateRecurring,
+    responses(
+        (status = 200, description = "Recurring transaction updated", body = RecurringTransaction),
+        (status = 404, description = "Recurring transaction not found")
+    )
+)]
+async fn update_recurring(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path((id, recurring_id)): Path<(i32, i32)>,
+    ValidatedJson(payload): ValidatedJson<UpdateRecurring>,
+) -> Result<Json<RecurringTransaction>, AppError> {
+    pool.run(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        let recurring = RecurringTransaction::from_id(conn, recurring_id, &account)?;
+        if let Some(category_id) = payload.category_id {
+            Category::from_id(conn, category_id, session.user_id())?;
+        }
+
+        let recurring = recurring.update(
+            conn,
+            &RecurringChanges {
+                amount: payload.amount,
+                description: payload.description,
+                category_id: payload.category_id,
+                end_on: payload.end_on,
+                active: payload.active,
+            },
+        )?;
+
+        Ok(Json(recurring))
+    })
+    .await
+}
+
+/// This endpoint deletes a recurring transaction
+///
+/// Occurrences that were already created are kept.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the recurring transaction was deleted.
+/// `404` : The account or recurring transaction doesn't exist.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/accounts/{id}/recurring/{recurring_id}",
+    security(("cookieAuth" = [])),
+    params(
+        ("id" = i32, Path, description = "ID of the account"),
+        ("recurring_id" = i32, Path, description = "ID of the recurring transaction")
+    ),
+    responses(
+        (status = 200, description = "Recurring transaction deleted", body = MessageResponse),
+        (status = 404, description = "Recurring transaction not found")
+    )
+)]
+async fn delete_recurring(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path((id, recurring_id)): Path<(i32, i32)>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        let account = Account::from_id(conn, id, session.user_id())?;
+        RecurringTransaction::from_id(conn, recurring_id, &account)?.delete(conn)?;
+
+        Ok(Json(MessageResponse::new("Recurring transaction deleted")))
+    })
+    .await
+}
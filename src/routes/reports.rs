@@ -0,0 +1,649 @@
+use std::sync::Arc;
+
+use axum::{extract::State, middleware, routing::get, Extension, Json, Router};
+use std::time::Instant;
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedQuery},
+        state::AppState,
+    },
+    clock::Clock,
+    database::{
+        connection::{DbConn, DbPool},
+        models::{accounts::Granularity, plans::Plan, sessions::manager::Session, users::User},
+    },
+    errors::AppError,
+    reports::{
+        anomalies::{self, AnomalyThresholds},
+        cache::ReportCache,
+        categories::{self, CategoryBreakdown},
+        forecast::{self, ForecastInput, ForecastMonth},
+        monthly::{self, MonthlySummary},
+        net_worth::{self, NetWorth, NetWorthPoint},
+        periods,
+    },
+};
+
+/// Query parameters of reports covering a month
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct MonthParams {
+    /// Year of the month
+    pub year: i32,
+    /// Month of the year, from 1 to 12
+    pub month: u32,
+    /// Whether to convert amounts into the preferred currency of the user
+    #[serde(default)]
+    pub convert: bool,
+    /// Day of the month the month starts on, from 1 to 28, the day set by the user by default
+    pub period_anchor: Option<u32>,
+}
+
+impl Validate for MonthParams {}
+
+impl MonthParams {
+    /// Get the currency to convert the amounts of the report into, if conversion was requested
+    pub fn convert_to(&self, conn: &mut DbConn, user_id: i32) -> Result<Option<String>, AppError> {
+        convert_to(conn, user_id, self.convert)
+    }
+
+    /// Get the day of the month the month of the report starts on
+    pub fn start_day(&self, conn: &mut DbConn, user_id: i32) -> Result<u32, AppError> {
+        month_start_day(conn, user_id, self.period_anchor)
+    }
+}
+
+/// Monthly summary query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct MonthlySummaryParams {
+    /// Year of the month
+    year: i32,
+    /// Month of the year, from 1 to 12
+    month: u32,
+    /// Whether to convert amounts into the preferred currency of the user
+    #[serde(default)]
+    convert: bool,
+    /// Whether to flag categories with unusually high spending
+    #[serde(default)]
+    flag_anomalies: bool,
+    /// Number of standard deviations above the average that is flagged (2 by default)
+    #[param(value_type = Option<String>)]
+    anomaly_std_devs: Option<BigDecimal>,
+    /// Ratio to the average that is flagged (1.5 by default)
+    #[param(value_type = Option<String>)]
+    anomaly_ratio: Option<BigDecimal>,
+    /// Day of the month the month starts on, from 1 to 28, the day set by the user by default
+    period_anchor: Option<u32>,
+}
+
+impl Validate for MonthlySummaryParams {}
+
+/// Weekly summary query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct WeeklySummaryParams {
+    /// A day of the week, in the `YYYY-MM-DD` format
+    #[param(value_type = String)]
+    date: NaiveDate,
+    /// Whether to convert amounts into the preferred currency of the user
+    #[serde(default)]
+    convert: bool,
+    /// Day the week starts on, e.g. `sunday`, the day set by the user by default
+    period_anchor: Option<String>,
+}
+
+impl Validate for WeeklySummaryParams {}
+
+/// Category breakdown query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryBreakdownParams {
+    /// The month, in the `YYYY-MM` format
+    month: String,
+}
+
+impl Validate for CategoryBreakdownParams {}
+
+/// Net worth query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct NetWorthParams {
+    /// Whether to convert balances into the preferred currency of the user
+    #[serde(default)]
+    convert: bool,
+}
+
+impl Validate for NetWorthParams {}
+
+/// Net worth history query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct NetWorthHistoryParams {
+    /// The length of each period (`day` or `month`)
+    granularity: Option<Granularity>,
+    /// Whether to convert balances into the preferred currency of the user
+    #[serde(default)]
+    convert: bool,
+}
+
+impl Validate for NetWorthHistoryParams {}
+
+/// Forecast query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct ForecastParams {
+    /// Number of months to project, including the current one (3 by default, at most 24)
+    months: Option<u32>,
+    /// Name of the plan whose budgets are expected to be spent, none by default
+    plan: Option<String>,
+    /// ID of the account budgeted spending is taken from, the first open asset account by default
+    spending_account_id: Option<i32>,
+}
+
+impl Validate for ForecastParams {}
+
+/// Get the preferred currency of a user if conversion was requested
+fn convert_to(conn: &mut DbConn, user_id: i32, convert: bool) -> Result<Option<String>, AppError> {
+    if !convert {
+        return Ok(None);
+    }
+    Ok(Some(
+        User::from_id(conn, user_id)?
+            .preferred_currency()
+            .to_string(),
+    ))
+}
+
+/// Get the day of the month months start on for a user, unless a report asked for another one
+fn month_start_day(
+    conn: &mut DbConn,
+    user_id: i32,
+    period_anchor: Option<u32>,
+) -> Result<u32, AppError> {
+    match period_anchor {
+        Some(day) => {
+            periods::validate_month_start_day(day)?;
+            Ok(day)
+        }
+        None => Ok(User::from_id(conn, user_id)?.fiscal_month_start_day()),
+    }
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/reports/monthly", get(get_monthly_summary))
+        .route("/reports/weekly", get(get_weekly_summary))
+        .route("/reports/categories", get(get_category_breakdown))
+        .route("/reports/net-worth", get(get_net_worth))
+        .route("/reports/net-worth/history", get(get_net_worth_history))
+        .route("/reports/forecast", get(get_forecast))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns the income and expenses of the authenticated user in a month
+///
+/// Transactions of all accounts are included. Split transactions count towards the categories of
+/// their splits instead of their own. With `convert=true`, amounts are converted into the preferred
+/// currency of the user with the latest exchange rate on or before the day of each transaction.
+///
+/// With `flag_anomalies=true`, the summary lists the categories whose spending is more than
+/// `anomaly_std_devs` standard deviations, or `anomaly_ratio` times, above their average in the
+/// previous six months. Categories with spending in fewer than three of those months are skipped.
+/// Anomalies are computed from unconverted amounts.
+///
+/// Months start on the day of the month set by the user, or `period_anchor`. With months starting
+/// on the 15th, June runs from June 15th to July 14th.
+///
+/// Summaries are cached for a minute, until the transactions or accounts of the user change.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the summary of the month by category.
+/// `400` : The month isn't between 1 and 12, or `period_anchor` isn't between 1 and 28.
+/// `422` : Amounts couldn't be converted. Lists the currency pairs without an exchange rate.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/reports/monthly",
+    security(("cookieAuth" = [])),
+    params(MonthlySummaryParams),
+    responses(
+        (status = 200, description = "Summary of the month", body = MonthlySummary),
+        (status = 400, description = "Invalid month"),
+        (status = 422, description = "Missing exchange rates")
+    )
+)]
+async fn get_monthly_summary(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    ValidatedQuery(params): ValidatedQuery<MonthlySummaryParams>,
+) -> Result<Json<MonthlySummary>, AppError> {
+    pool.run_read(move |conn| {
+        let key = format!("monthly {params:?}");
+        let summary = reports.get_or_compute(session.user_id(), key, Instant::now(), || {
+            let convert_to = convert_to(conn, session.user_id(), params.convert)?;
+            let start_day = month_start_day(conn, session.user_id(), params.period_anchor)?;
+            let mut summary = monthly::monthly_summary(
+                conn,
+                session.user_id(),
+                params.year,
+                params.month,
+                start_day,
+                convert_to.as_deref(),
+            )?;
+
+            if params.flag_anomalies {
+                let defaults = AnomalyThresholds::default();
+                let thresholds = AnomalyThresholds {
+                    std_devs: params.anomaly_std_devs.unwrap_or(defaults.std_devs),
+                    ratio: params.anomaly_ratio.unwrap_or(defaults.ratio),
+                };
+                let anomalies = anomalies::monthly_anomalies(
+                    conn,
+                    session.user_id(),
+                    params.year,
+                    params.month,
+                    start_day,
+                    &thresholds,
+                )?;
+                summary = summary.with_anomalies(anomalies);
+            }
+
+            Ok(summary)
+        })?;
+
+        Ok(Json(summary))
+    })
+    .await
+}
+
+/// This endpoint returns the income and expenses of the authenticated user in a week
+///
+/// The week is the one the day falls in, starting on the day set by the user, or `period_anchor`.
+/// Amounts are summarized and converted like in the monthly summary.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the summary of the week by category.
+/// `400` : `period_anchor` isn't a day of the week.
+/// `422` : Amounts couldn't be converted. Lists the currency pairs without an exchange rate.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/reports/weekly",
+    security(("cookieAuth" = [])),
+    params(WeeklySummaryParams),
+    responses(
+        (status = 200, description = "Summary of the week", body = MonthlySummary),
+        (status = 400, description = "Invalid day of the week"),
+        (status = 422, description = "Missing exchange rates")
+    )
+)]
+async fn get_weekly_summary(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    ValidatedQuery(params): ValidatedQuery<WeeklySummaryParams>,
+) -> Result<Json<MonthlySummary>, AppError> {
+    pool.run_read(move |conn| {
+        let week_starts_on = match &params.period_anchor {
+            Some(day) => periods::parse_weekday(day)?,
+            None => User::from_id(conn, session.user_id())?.week_starts_on(),
+        };
+        let (from, to) = periods::week_range(params.date, week_starts_on);
+        let convert_to = convert_to(conn, session.user_id(), params.convert)?;
+
+        Ok(Json(monthly::period_summary(
+            conn,
+            session.user_id(),
+            from,
+            to,
+            convert_to.as_deref(),
+        )?))
+    })
+    .await
+}
+
+/// This endpoint breaks down the spending and income of the authenticated user in a month by
+/// category
+///
+/// Each category is netted for the month, so refunds reduce its spending. Categories where more
+/// money went out than came in are listed under `expenses`, the others under `income`, each
+/// with its percentage of the total of its section and its change from the previous month.
+/// Transactions without a category are grouped under `Uncategorized`. Splits count towards their
+/// own categories, transfers are left out, and amounts are added up regardless of their currency.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the breakdown, with categories by amount descending.
+/// `400` : The month isn't in the `YYYY-MM` format.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/reports/categories",
+    security(("cookieAuth" = [])),
+    params(CategoryBreakdownParams),
+    responses(
+        (status = 200, description = "Breakdown of the month by category", body = CategoryBreakdown),
+        (status = 400, description = "Invalid month")
+    )
+)]
+async fn get_category_breakdown(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    ValidatedQuery(params): ValidatedQuery<CategoryBreakdownParams>,
+) -> Result<Json<CategoryBreakdown>, AppError> {
+    pool.run_read(move |conn| {
+        let (year, month) = categories::parse_month(&params.month)?;
+
+        Ok(Json(categories::category_breakdown(
+            conn,
+            session.user_id(),
+            year,
+            month,
+        )?))
+    })
+    .await
+}
+
+/// This endpoint returns the current net worth of the authenticated user
+///
+/// Archived accounts are left out. Balances of credit accounts are negative when money is owed, and count as liabilities. Without
+/// `convert=true`, balances are grouped by currency. With it, they are converted into the preferred
+/// currency of the user with the latest exchange rates.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the net worth by currency.
+/// `422` : Balances couldn't be converted. Lists the currency pairs without an exchange rate.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/reports/net-worth",
+    security(("cookieAuth" = [])),
+    params(NetWorthParams),
+    responses(
+        (status = 200, description = "Net worth by currency", body = Vec<NetWorth>),
+        (status = 422, description = "Missing exchange rates")
+    )
+)]
+async fn get_net_worth(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    ValidatedQuery(params): ValidatedQuery<NetWorthParams>,
+) -> Result<Json<Vec<NetWorth>>, AppError> {
+    pool.run_read(move |conn| {
+        let convert_to = convert_to(conn, session.user_id(), params.convert)?;
+        let totals = net_worth::net_worth(conn, session.user_id(), convert_to.as_deref())?;
+
+        Ok(Json(totals))
+    })
+    .await
+}
+
+/// This endpoint returns the net worth of the authenticated user at the end of each period
+///
+/// Only periods with transactions are included. With `convert=true`, balances are converted with
+/// the exchange rates of the last day of each period. Histories are cached for a minute, until the
+/// transactions or accounts of the user change.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of net worths ordered by period.
+/// `422` : Balances couldn't be converted. Lists the currency pairs without an exchange rate.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/reports/net-worth/history",
+    security(("cookieAuth" = [])),
+    params(NetWorthHistoryParams),
+    responses(
+        (status = 200, description = "Net worth history", body = Vec<NetWorthPoint>),
+        (status = 422, description = "Missing exchange rates")
+    )
+)]
+async fn get_net_worth_history(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    ValidatedQuery(params): ValidatedQuery<NetWorthHistoryParams>,
+) -> Result<Json<Vec<NetWorthPoint>>, AppError> {
+    pool.run_read(move |conn| {
+        let key = format!("net-worth-history {params:?}");
+        let history = reports.get_or_compute(session.user_id(), key, Instant::now(), || {
+            let convert_to = convert_to(conn, session.user_id(), params.convert)?;
+            net_worth::net_worth_history(
+                conn,
+                session.user_id(),
+                params.granularity.unwrap_or_default(),
+                convert_to.as_deref(),
+            )
+        })?;
+
+        Ok(Json(history))
+    })
+    .await
+}
+
+/// This endpoint projects the balances of the accounts of the authenticated user
+///
+/// The projection starts from the current balances of the open accounts and covers the rest of
+/// the current month and the following months. Archived accounts and their recurring transactions
+/// are left out. Active recurring transactions are applied on the days they occur. When a
+/// plan is given, the part of its budgets not covered by recurring transactions or by what was
+/// already spent this month is taken from the spending account at the end of each month. Amounts
+/// aren't converted between currencies.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the projection of each month.
+/// `400` : The number of months isn't between 1 and 24.
+/// `404` : The plan or spending account doesn't exist or belongs to another user.
+/// `409` : The spending account is archived.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/reports/forecast",
+    security(("cookieAuth" = [])),
+    params(ForecastParams),
+    responses(
+        (status = 200, description = "Cash-flow forecast", body = Vec<ForecastMonth>),
+        (status = 400, description = "Invalid number of months"),
+        (status = 404, description = "Plan or account not found"),
+        (status = 409, description = "Spending account archived")
+    )
+)]
+async fn get_forecast(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    ValidatedQuery(params): ValidatedQuery<ForecastParams>,
+) -> Result<Json<Vec<ForecastMonth>>, AppError> {
+    pool.run_read(move |conn| {
+        let plan = params
+            .plan
+            .map(|name| Plan::from_name(conn, &name, session.user_id()))
+            .transpose()?;
+        let input = ForecastInput::load(
+            conn,
+            session.user_id(),
+            plan.as_ref(),
+            params.spending_account_id,
+            clock.today(),
+            params.months.unwrap_or(3),
+        )?;
+
+        Ok(Json(forecast::forecast(&input)))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_reports_are_cached() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "100.00", "currency": "CAD"})),
+            )
+            .await;
+        let uri = format!("/accounts/{}/transactions", account["id"]);
+        let spend = |amount: &str| json!({"amount": amount, "description": "Groceries", "occurred_at": "2024-06-12"});
+        let (status, _) = app.request(Method::POST, &uri, Some(spend("-40.00"))).await;
+        assert_eq!(status, 201);
+
+        // The second identical request is served without querying the database
+        let monthly = "/reports/monthly?year=2024&month=6";
+        for _ in 0..2 {
+            let (status, summary) = app.request(Method::GET, monthly, None).await;
+            assert_eq!(status, 200, "{summary}");
+            assert_eq!(summary["expenses"], "40.00");
+        }
+        assert_eq!((app.reports().hits(), app.reports().misses()), (1, 1));
+
+        // Other parameters are computed on their own
+        let (status, _) = app
+            .request(Method::GET, "/reports/monthly?year=2024&month=5", None)
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(app.reports().misses(), 2);
+
+        // A new transaction invalidates the reports of the user
+        let (status, _) = app.request(Method::POST, &uri, Some(spend("-2.50"))).await;
+        assert_eq!(status, 201);
+        let (_, summary) = app.request(Method::GET, monthly, None).await;
+        assert_eq!(summary["expenses"], "42.50");
+        assert_eq!((app.reports().hits(), app.reports().misses()), (1, 3));
+
+        let history = "/reports/net-worth/history?granularity=month";
+        let (_, before) = app.request(Method::GET, history, None).await;
+        let (status, archived) = app
+            .request(
+                Method::POST,
+                &format!("/accounts/{}/archive", account["id"]),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200, "{archived}");
+        let (_, after) = app.request(Method::GET, history, None).await;
+        assert_eq!(before, after);
+        assert_eq!((app.reports().hits(), app.reports().misses()), (1, 5));
+    }
+
+    /// Get the first day and the expenses of a monthly summary
+    async fn expenses(app: &TestApp, year: i32, month: u32, anchor: Option<u32>) -> (Value, Value) {
+        let query = anchor.map_or(String::new(), |day| format!("&period_anchor={day}"));
+        let uri = format!("/reports/monthly?year={year}&month={month}{query}");
+        let (status, summary) = app.request(Method::GET, &uri, None).await;
+        assert_eq!(status, 200, "{summary}");
+        (summary["from"].clone(), summary["expenses"].clone())
+    }
+
+    #[tokio::test]
+    async fn test_fiscal_months() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Credit card", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        let uri = format!("/accounts/{}/transactions", account["id"]);
+        for (amount, day) in [("-14.00", "2024-06-14"), ("-15.00", "2024-06-15")] {
+            let spend = json!({"amount": amount, "description": "Fuel", "occurred_at": day});
+            let (status, _) = app.request(Method::POST, &uri, Some(spend)).await;
+            assert_eq!(status, 201);
+        }
+        assert_eq!(
+            expenses(&app, 2024, 6, None).await,
+            (json!("2024-06-01"), json!("29.00"))
+        );
+
+        // With the statement month starting on the 15th, the 14th is still in May
+        let (status, user) = app
+            .request(
+                Method::PUT,
+                "/users/me/periods",
+                Some(json!({"week_starts_on": "sunday", "fiscal_month_start_day": 15})),
+            )
+            .await;
+        assert_eq!(status, 200, "{user}");
+        assert_eq!(user["week_starts_on"], "sunday");
+        assert_eq!(user["fiscal_month_start_day"], 15);
+        assert_eq!(
+            expenses(&app, 2024, 5, None).await,
+            (json!("2024-05-15"), json!("14.00"))
+        );
+        assert_eq!(
+            expenses(&app, 2024, 6, None).await,
+            (json!("2024-06-15"), json!("15.00"))
+        );
+
+        // Reports can ask for other months
+        assert_eq!(
+            expenses(&app, 2024, 6, Some(1)).await,
+            (json!("2024-06-01"), json!("29.00"))
+        );
+        assert_eq!(
+            expenses(&app, 2024, 6, Some(14)).await,
+            (json!("2024-06-14"), json!("29.00"))
+        );
+        let (status, _) = app
+            .request(
+                Method::GET,
+                "/reports/monthly?year=2024&month=6&period_anchor=29",
+                None,
+            )
+            .await;
+        assert_eq!(status, 400);
+
+        // Friday June 14th and Saturday June 15th are in the same week starting on Sunday, but not
+        // in weeks starting on Saturday
+        let (status, week) = app
+            .request(Method::GET, "/reports/weekly?date=2024-06-12", None)
+            .await;
+        assert_eq!(status, 200, "{week}");
+        assert_eq!(
+            (&week["from"], &week["to"]),
+            (&json!("2024-06-09"), &json!("2024-06-15"))
+        );
+        assert_eq!(week["expenses"], "29.00");
+        let (_, week) = app
+            .request(
+                Method::GET,
+                "/reports/weekly?date=2024-06-15&period_anchor=saturday",
+                None,
+            )
+            .await;
+        assert_eq!(week["from"], "2024-06-15");
+        assert_eq!(week["expenses"], "15.00");
+
+        let (status, body) = app
+            .request(
+                Method::PUT,
+                "/users/me/periods",
+                Some(json!({"week_starts_on": "monday", "fiscal_month_start_day": 31})),
+            )
+            .await;
+        assert_eq!(status, 400, "{body}");
+    }
+}
@@ -0,0 +1,350 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson, ValidatedQuery},
+        responses::MessageResponse,
+        state::AppState,
+    },
+    database::{
+        connection::{DbConn, DbPool},
+        models::{
+            categories::Category,
+            payee_rules::{MatchKind, PayeeRule, PayeeRuleInput},
+            sessions::manager::Session,
+        },
+    },
+    errors::AppError,
+    reports::cache::ReportCache,
+};
+
+/// Create or update payee rule request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_rule, update_rule))]
+#[schema(example = json!({"pattern": "AMZN", "match_kind": "contains", "normalized_payee": "Amazon", "default_category_id": 5}))]
+pub struct SaveRule {
+    /// The pattern matched against the descriptions of transactions, at most 200 characters
+    pattern: String,
+    /// How the pattern is matched (`contains`, `prefix` or `regex`), regardless of case
+    match_kind: MatchKind,
+    /// The payee of matching transactions
+    normalized_payee: String,
+    /// The ID of the category of matching transactions that weren't given one
+    default_category_id: Option<i32>,
+}
+
+impl Validate for SaveRule {}
+
+impl SaveRule {
+    /// Validate the request and convert it to the fields of a rule
+    fn into_input(self, conn: &mut DbConn, user_id: i32) -> Result<PayeeRuleInput, AppError> {
+        let input = PayeeRuleInput {
+            pattern: self.pattern,
+            match_kind: self.match_kind,
+            normalized_payee: self.normalized_payee,
+            default_category_id: self.default_category_id,
+        };
+        input.validate()?;
+
+        if let Some(category_id) = input.default_category_id {
+            Category::from_id(conn, category_id, user_id)?;
+        }
+
+        Ok(input)
+    }
+}
+
+/// Apply rule query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct ApplyParams {
+    /// Whether to update the matching transactions, otherwise they are only counted
+    #[serde(default)]
+    backfill: bool,
+}
+
+impl Validate for ApplyParams {}
+
+/// Outcome of applying a payee rule to existing transactions
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RuleApplication {
+    /// Number of uncategorized transactions the rule matches
+    affected: usize,
+    /// Whether the matching transactions were updated
+    backfilled: bool,
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/rules", get(all_rules).post(create_rule))
+        .route("/rules/:id", put(update_rule).delete(delete_rule))
+        .route("/rules/:id/apply", post(apply_rule))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all payee rules of the authenticated user
+///
+/// ## Responses
+/// `200` : A successful response. Returns a vector of rules, oldest first.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/rules",
+    security(("cookieAuth" = [])),
+    responses(
+        (status = 200, description = "Payee rules of the user", body = Vec<PayeeRule>),
+        (status = 401, description = "User is not authenticated", body = ErrorBody)
+    )
+)]
+async fn all_rules(
+    Extension(session): Extension<Session>,
+    State(pool): State<Arc<DbPool>>,
+) -> Result<Json<Vec<PayeeRule>>, AppError> {
+    pool.run_read(move |conn| {
+        let rules = PayeeRule::get_all(conn, session.user_id())?;
+        Ok(Json(rules))
+    })
+    .await
+}
+
+/// This endpoint creates a new payee rule
+///
+/// Rules are applied to transactions as they are created or imported. When several rules match a
+/// description, the one whose pattern has the most letters, digits and spaces wins.
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created rule.
+/// `400` : The pattern is empty, too long, or an invalid or too large regex.
+/// `404` : The default category doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/rules",
+    security(("cookieAuth" = [])),
+    request_body = SaveRule,
+    responses(
+        (status = 201, description = "Rule created", body = PayeeRule),
+        (status = 400, description = "Invalid pattern"),
+        (status = 404, description = "Category not found")
+    )
+)]
+async fn create_rule(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<SaveRule>,
+) -> Result<(StatusCode, Json<PayeeRule>), AppError> {
+    pool.run(move |conn| {
+        let input = payload.into_input(conn, session.user_id())?;
+        let rule = PayeeRule::new(conn, session.user_id(), &input)?;
+
+        Ok((StatusCode::CREATED, Json(rule)))
+    })
+    .await
+}
+
+/// This endpoint updates a payee rule
+///
+/// Transactions the rule was already applied to are not changed.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated rule.
+/// `400` : The pattern is empty, too long, or an invalid or too large regex.
+/// `404` : The rule or default category doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    put,
+    path = "/rules/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the rule")),
+    request_body = SaveRule,
+    responses(
+        (status = 200, description = "Rule updated", body = PayeeRule),
+        (status = 400, description = "Invalid pattern"),
+        (status = 404, description = "Rule not found")
+    )
+)]
+async fn update_rule(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<SaveRule>,
+) -> Result<Json<PayeeRule>, AppError> {
+    pool.run(move |conn| {
+        let rule = PayeeRule::from_id(conn, id, session.user_id())?;
+        let input = payload.into_input(conn, session.user_id())?;
+
+        Ok(Json(rule.update(conn, &input)?))
+    })
+    .await
+}
+
+/// This endpoint deletes a payee rule
+///
+/// Transactions the rule was already applied to keep their payee and category.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the rule was deleted.
+/// `404` : The rule doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/rules/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the rule")),
+    responses(
+        (status = 200, description = "Rule deleted", body = MessageResponse),
+        (status = 404, description = "Rule not found")
+    )
+)]
+async fn delete_rule(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        PayeeRule::from_id(conn, id, session.user_id())?.delete(conn)?;
+
+        Ok(Json(MessageResponse::new("Rule deleted")))
+    })
+    .await
+}
+
+/// This endpoint applies a payee rule to the existing uncategorized transactions of the user
+///
+/// Without `backfill=true`, the matching transactions are only counted.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the number of matching transactions.
+/// `400` : A parameter can't be read or is unknown.
+/// `404` : The rule doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/rules/{id}/apply",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the rule"), ApplyParams),
+    responses(
+        (status = 200, description = "Rule applied", body = RuleApplication),
+        (status = 400, description = "Invalid parameters"),
+        (status = 404, description = "Rule not found")
+    )
+)]
+async fn apply_rule(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedQuery(params): ValidatedQuery<ApplyParams>,
+) -> Result<Json<RuleApplication>, AppError> {
+    pool.run(move |conn| {
+        let rule = PayeeRule::from_id(conn, id, session.user_id())?;
+        let affected = rule.apply(conn, params.backfill)?;
+        if params.backfill {
+            reports.invalidate(session.user_id());
+        }
+
+        Ok(Json(RuleApplication {
+            affected,
+            backfilled: params.backfill,
+        }))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_rule_routes() {
+        let app = TestApp::new();
+
+        let (status, error) = app
+            .request(
+                Method::POST,
+                "/rules",
+                Some(json!({"pattern": "AMZN (", "match_kind": "regex", "normalized_payee": "Amazon"})),
+            )
+            .await;
+        assert_eq!(status, 400);
+        assert_eq!(error["code"], 40008);
+
+        let (status, rule) = app
+            .request(
+                Method::POST,
+                "/rules",
+                Some(json!({"pattern": "AMZN Mktp", "match_kind": "contains", "normalized_payee": "Amazon"})),
+            )
+            .await;
+        assert_eq!(status, 201, "{rule}");
+
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0", "currency": "CAD"})),
+            )
+            .await;
+        let uri = format!("/accounts/{}/transactions", account["id"]);
+        let (status, transaction) = app
+            .request(
+                Method::POST,
+                &uri,
+                Some(json!({"amount": "-25.99", "description": "AMZN Mktp CA*2J4", "occurred_at": "2024-07-01"})),
+            )
+            .await;
+        assert_eq!(status, 201);
+        assert_eq!(transaction["payee"], "Amazon");
+
+        let uri = format!("/rules/{}/apply", rule["id"]);
+        let (status, applied) = app.request(Method::POST, &uri, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(applied, json!({"affected": 1, "backfilled": false}));
+
+        let (status, _) = app
+            .request(Method::POST, "/rules/2147483647/apply", None)
+            .await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_query_parameters_are_refused() {
+        let app = TestApp::new();
+        let (_, rule) = app
+            .request(
+                Method::POST,
+                "/rules",
+                Some(json!({"pattern": "AMZN Mktp", "match_kind": "contains", "normalized_payee": "Amazon"})),
+            )
+            .await;
+
+        let uri = format!("/rules/{}/apply?back_fill=true", rule["id"]);
+        let (status, body) = app.request(Method::POST, &uri, None).await;
+        assert_eq!(status, 400, "{uri}");
+        assert_eq!(body["error"], "invalid_query");
+        assert_eq!(
+            body["details"],
+            json!({"kind": "unknown_field", "fields": ["back_fill"]})
+        );
+    }
+}
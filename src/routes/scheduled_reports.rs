@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::get,
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson},
+        responses::MessageResponse,
+        state::AppState,
+    },
+    clock::Clock,
+    database::{
+        connection::DbPool,
+        models::{
+            scheduled_reports::{
+                ReportCadence, ReportDestination, ReportFormat, ReportKind, ScheduledReport,
+                ScheduledReportInput,
+            },
+            sessions::manager::Session,
+        },
+    },
+    errors::AppError,
+};
+
+/// Create or update scheduled report request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_scheduled_report, update_scheduled_report))]
+#[schema(example = json!({"kind": "monthly_summary", "plan_name": null, "cadence": "monthly", "format": "csv", "destination": "file"}))]
+pub struct SaveScheduledReport {
+    /// The report that is rendered
+    kind: ReportKind,
+    /// Name of the plan whose budgets are compared, required for `budget_vs_actual` reports only
+    plan_name: Option<String>,
+    /// How often the report is rendered
+    cadence: ReportCadence,
+    /// The format the report is written in
+    format: ReportFormat,
+    /// Where the report is delivered, `file` by default
+    #[serde(default)]
+    destination: ReportDestination,
+}
+
+impl Validate for SaveScheduledReport {}
+
+impl SaveScheduledReport {
+    fn into_input(self) -> ScheduledReportInput {
+        ScheduledReportInput {
+            kind: self.kind,
+            plan_name: self.plan_name,
+            cadence: self.cadence,
+            format: self.format,
+            destination: self.destination,
+        }
+    }
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/scheduled-reports",
+            get(all_scheduled_reports).post(create_scheduled_report),
+        )
+        .route(
+            "/scheduled-reports/:id",
+            get(get_scheduled_report)
+                .put(update_scheduled_report)
+                .delete(delete_scheduled_report),
+        )
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all scheduled reports of the authenticated user
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of scheduled reports, oldest first.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/scheduled-reports",
+    security(("cookieAuth" = [])),
+    responses((status = 200, description = "Scheduled reports of the user", body = Vec<ScheduledReport>))
+)]
+async fn all_scheduled_reports(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+) -> Result<Json<Vec<ScheduledReport>>, AppError> {
+    pool.run_read(move |conn| Ok(Json(ScheduledReport::get_all(conn, session.user_id())?)))
+        .await
+}
+
+/// This endpoint returns a scheduled report of the authenticated user
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the scheduled report with the outcome of its last run.
+/// `404` : The scheduled report doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/scheduled-reports/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the scheduled report")),
+    responses(
+        (status = 200, description = "Scheduled report", body = ScheduledReport),
+        (status = 404, description = "Scheduled report not found")
+    )
+)]
+async fn get_scheduled_report(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<ScheduledReport>, AppError> {
+    pool.run(move |conn| Ok(Json(ScheduledReport::from_id(conn, id, session.user_id())?)))
+        .await
+}
+
+/// This endpoint schedules a report for the authenticated user
+///
+/// Reports run at midnight, server time, and cover the month of the day before the run: monthly
+/// reports run on the first day of the month and cover the month that ended, weekly reports run
+/// on Mondays and daily reports every day, covering the month so far. Each run writes a file named
+/// `<id>_<kind>_<YYYY-MM>.<format>` to the directory of the user in the reports directory of the
+/// server, replacing the file of an earlier run that covered the same month.
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the scheduled report.
+/// `400` : A `budget_vs_actual` report has no plan, or another kind of report has one.
+/// `404` : The plan doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/scheduled-reports",
+    security(("cookieAuth" = [])),
+    request_body = SaveScheduledReport,
+    responses(
+        (status = 201, description = "Report scheduled", body = ScheduledReport),
+        (status = 400, description = "Invalid scheduled report"),
+        (status = 404, description = "Plan not found")
+    )
+)]
+async fn create_scheduled_report(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<SaveScheduledReport>,
+) -> Result<(StatusCode, Json<ScheduledReport>), AppError> {
+    pool.run(move |conn| {
+        let report = ScheduledReport::new(
+            conn,
+            session.user_id(),
+            &payload.into_input(),
+            clock.now_local(),
+        )?;
+
+        Ok((StatusCode::CREATED, Json(report)))
+    })
+    .await
+}
+
+/// This endpoint updates a scheduled report of the authenticated user
+///
+/// The next run is rescheduled for the new cadence.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated scheduled report.
+/// `400` : A `budget_vs_actual` report has no plan, or another kind of report has one.
+/// `404` : The scheduled report or the plan doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    put,
+    path = "/scheduled-reports/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the scheduled report")),
+    request_body = SaveScheduledReport,
+    responses(
+        (status = 200, description = "Scheduled report updated", body = ScheduledReport),
+        (status = 400, description = "Invalid scheduled report"),
+        (status = 404, description = "Scheduled report or plan not found")
+    )
+)]
+async fn update_scheduled_report(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<SaveScheduledReport>,
+) -> Result<Json<ScheduledReport>, AppError> {
+    pool.run(move |conn| {
+        let report = ScheduledReport::from_id(conn, id, session.user_id())?;
+        let report = report.update(conn, &payload.into_input(), clock.now_local())?;
+
+        Ok(Json(report))
+    })
+    .await
+}
+
+/// This endpoint deletes a scheduled report of the authenticated user
+///
+/// Files written by earlier runs are kept.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the scheduled report was deleted.
+/// `404` : The scheduled report doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/scheduled-reports/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the scheduled report")),
+    responses(
+        (status = 200, description = "Scheduled report deleted", body = MessageResponse),
+        (status = 404, description = "Scheduled report not found")
+    )
+)]
+async fn delete_scheduled_report(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        ScheduledReport::from_id(conn, id, session.user_id())?.delete(conn)?;
+
+        Ok(Json(MessageResponse::new("Scheduled report deleted")))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_scheduled_reports() {
+        let app = TestApp::new();
+        let (status, _) = app.request(Method::POST, "/plans/Home", None).await;
+        assert_eq!(status, 201);
+
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/scheduled-reports",
+                Some(json!({"kind": "budget_vs_actual", "cadence": "weekly", "format": "csv"})),
+            )
+            .await;
+        assert_eq!(status, 400);
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/scheduled-reports",
+                Some(json!({
+                    "kind": "budget_vs_actual",
+                    "plan_name": "Elsewhere",
+                    "cadence": "weekly",
+                    "format": "csv",
+                })),
+            )
+            .await;
+        assert_eq!(status, 404);
+
+        let (status, report) = app
+            .request(
+                Method::POST,
+                "/scheduled-reports",
+                Some(json!({
+                    "kind": "budget_vs_actual",
+                    "plan_name": "Home",
+                    "cadence": "weekly",
+                    "format": "csv",
+                })),
+            )
+            .await;
+        assert_eq!(status, 201, "{report}");
+        assert_eq!(report["destination"], "file");
+        assert_eq!(report["status"], "pending");
+        assert_eq!(report["last_run_at"], serde_json::Value::Null);
+
+        let uri = format!("/scheduled-reports/{}", report["id"]);
+        let (status, updated) = app
+            .request(
+                Method::PUT,
+                &uri,
+                Some(json!({"kind": "monthly_summary", "cadence": "monthly", "format": "json"})),
+            )
+            .await;
+        assert_eq!(status, 200, "{updated}");
+        assert_eq!(updated["plan_name"], serde_json::Value::Null);
+        assert!(updated["next_run_at"]
+            .as_str()
+            .unwrap()
+            .ends_with("-01T00:00:00Z"));
+
+        let (status, _) = app.request(Method::DELETE, &uri, None).await;
+        assert_eq!(status, 200);
+        let (status, _) = app.request(Method::GET, &uri, None).await;
+        assert_eq!(status, 404);
+    }
+}
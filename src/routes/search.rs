@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use axum::{extract::State, middleware, routing::get, Extension, Json, Router};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedQuery},
+        state::AppState,
+    },
+    database::{connection::DbPool, models::sessions::manager::Session},
+    errors::AppError,
+    search::{ilike::IlikeSearch, SearchProvider, SearchResults},
+};
+
+/// Search query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct SearchParams {
+    /// The text to search for, at least two characters long
+    q: String,
+}
+
+impl Validate for SearchParams {}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/search", get(search))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint searches the transactions, plans and notes of the authenticated user
+///
+/// Transactions are matched on their description and payee, plans on their name and notes on
+/// their body, ignoring case. Each group holds up to 10 results along with the total number of
+/// matches.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the results grouped by domain.
+/// `400` : A parameter can't be read or is unknown, or the query is shorter than two characters.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/search",
+    security(("cookieAuth" = [])),
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Search results", body = SearchResults),
+        (status = 400, description = "Invalid parameters or query too short")
+    )
+)]
+async fn search(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    ValidatedQuery(params): ValidatedQuery<SearchParams>,
+) -> Result<Json<SearchResults>, AppError> {
+    pool.run_read(move |conn| {
+        let results = IlikeSearch.search(conn, session.user_id(), &params.q)?;
+
+        Ok(Json(results))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_search() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        app.request(
+            Method::POST,
+            &format!("/accounts/{}/transactions", account["id"]),
+            Some(json!({"amount": "-60.00", "description": "Groceries", "occurred_at": "2024-07-05"})),
+        )
+        .await;
+        app.request(Method::POST, "/plans/Groceries%202024", None)
+            .await;
+
+        let (status, results) = app.request(Method::GET, "/search?q=grocer", None).await;
+        assert_eq!(status, 200, "{results}");
+        assert_eq!(results["transactions"]["total"], 1);
+        assert_eq!(results["plans"]["items"][0]["name"], "Groceries 2024");
+        assert_eq!(results["notes"]["total"], 0);
+
+        let (status, _) = app.request(Method::GET, "/search?q=g", None).await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_query_parameters_are_refused() {
+        let app = TestApp::new();
+
+        let uri = "/search?query=coffee";
+        let (status, body) = app.request(Method::GET, uri, None).await;
+        assert_eq!(status, 400, "{uri}");
+        assert_eq!(body["error"], "invalid_query");
+        assert_eq!(
+            body["details"],
+            json!({"kind": "unknown_field", "fields": ["query"]})
+        );
+    }
+}
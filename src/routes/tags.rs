@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    middleware,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+
+use crate::{
+    api::{extract::ValidatedJson, state::AppState},
+    database::{
+        connection::DbPool,
+        models::{
+            sessions::manager::Session,
+            tags::{self, Tag, TagUsage},
+            transactions::Transaction,
+        },
+    },
+    errors::AppError,
+};
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/tags", get(all_tags))
+        .route("/transactions/:id/tags", post(add_tags).delete(remove_tags))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all tags of the authenticated user with how many transactions have them
+///
+/// ## Responses
+/// `200` : A successful response. Returns a vector of tags ordered by name.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/tags",
+    security(("cookieAuth" = [])),
+    responses(
+        (status = 200, description = "Tags with their usage counts", body = Vec<TagUsage>),
+        (status = 401, description = "User is not authenticated")
+    )
+)]
+async fn all_tags(
+    Extension(session): Extension<Session>,
+    State(pool): State<Arc<DbPool>>,
+) -> Result<Json<Vec<TagUsage>>, AppError> {
+    pool.run_read(move |conn| {
+        let tags = Tag::get_all(conn, session.user_id())?;
+        Ok(Json(tags))
+    })
+    .await
+}
+
+/// This endpoint tags a transaction
+///
+/// The body is an array of tag names. Names are trimmed and matched regardless of case, and tags
+/// the user doesn't have yet are created. Tags the transaction already has are ignored.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the tags of the transaction.
+/// `400` : A tag name is empty or longer than 40 characters.
+/// `404` : The transaction doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/transactions/{id}/tags",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the transaction")),
+    request_body(content = Vec<String>, example = json!(["groceries", "shared"])),
+    responses(
+        (status = 200, description = "Tags of the transaction", body = Vec<Tag>),
+        (status = 400, description = "Invalid tag name"),
+        (status = 404, description = "Transaction not found")
+    )
+)]
+async fn add_tags(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(names): ValidatedJson<Vec<String>>,
+) -> Result<Json<Vec<Tag>>, AppError> {
+    pool.run(move |conn| {
+        let transaction = Transaction::from_id(conn, id, session.user_id())?;
+        let names = tags::normalize_names(&names)?;
+        let tags = Tag::find_or_create(conn, session.user_id(), &names)?;
+        Tag::tag(conn, &transaction, &tags)?;
+
+        Ok(Json(Tag::of_transaction(conn, &transaction)?))
+    })
+    .await
+}
+
+/// This endpoint removes tags from a transaction
+///
+/// The body is an array of tag names, matched regardless of case. Tags the transaction doesn't
+/// have are ignored.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the remaining tags of the transaction.
+/// `400` : A tag name is empty or longer than 40 characters.
+/// `404` : The transaction doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/transactions/{id}/tags",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the transaction")),
+    request_body(content = Vec<String>, example = json!(["groceries", "shared"])),
+    responses(
+        (status = 200, description = "Remaining tags of the transaction", body = Vec<Tag>),
+        (status = 400, description = "Invalid tag name"),
+        (status = 404, description = "Transaction not found")
+    )
+)]
+async fn remove_tags(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+    ValidatedJson(names): ValidatedJson<Vec<String>>,
+) -> Result<Json<Vec<Tag>>, AppError> {
+    pool.run(move |conn| {
+        let transaction = Transaction::from_id(conn, id, session.user_id())?;
+        let names = tags::normalize_names(&names)?;
+        Tag::untag(conn, &transaction, &names)?;
+
+        Ok(Json(Tag::of_transaction(conn, &transaction)?))
+    })
+    .await
+}
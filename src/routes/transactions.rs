@@ -0,0 +1,318 @@
+use std::sync::Arc;
+
+use axum::{extract::State, middleware, routing::post, Extension, Json, Router};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson},
+        state::AppState,
+    },
+    database::{
+        connection::DbPool,
+        models::{
+            accounts::Account,
+            categories::Category,
+            sessions::manager::Session,
+            transactions::{Transaction, TransactionFilter},
+        },
+    },
+    errors::AppError,
+    reports::cache::ReportCache,
+};
+
+/// Maximum number of transactions in the sample of a dry run
+const SAMPLE_SIZE: i64 = 20;
+
+/// The transactions of a bulk operation, all of those of the user when empty
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct BulkFilter {
+    /// Only transactions of this account
+    account_id: Option<i32>,
+    /// Only transactions whose description or payee contains this text, regardless of case
+    q: Option<String>,
+    /// Only transactions that occurred on or after this day
+    from: Option<NaiveDate>,
+    /// Only transactions that occurred on or before this day
+    to: Option<NaiveDate>,
+    /// Whether to only match transactions without a category or splits, false by default
+    #[serde(default)]
+    uncategorized_only: bool,
+}
+
+impl From<BulkFilter> for TransactionFilter {
+    fn from(filter: BulkFilter) -> Self {
+        TransactionFilter {
+            account_id: filter.account_id,
+            tag: None,
+            q: filter.q,
+            from: filter.from,
+            to: filter.to,
+            uncategorized_only: filter.uncategorized_only,
+        }
+    }
+}
+
+/// Bulk categorize request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(bulk_categorize))]
+#[schema(example = json!({"filter": {"q": "market", "from": "2024-01-01", "to": "2024-06-30", "uncategorized_only": true}, "category_id": 3, "dry_run": true}))]
+pub struct BulkCategorize {
+    /// The transactions to categorize
+    #[serde(default)]
+    filter: BulkFilter,
+    /// The ID of the category to give the transactions
+    category_id: i32,
+    /// Whether to only count the transactions and return a sample of them, false by default
+    #[serde(default)]
+    dry_run: bool,
+}
+
+impl Validate for BulkCategorize {
+    fn validate(&self) -> Result<(), AppError> {
+        match (self.filter.from, self.filter.to) {
+            (Some(from), Some(to)) if from > to => Err(AppError::InvalidInput(format!(
+                "The filter starts on {from}, after it ends on {to}"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Outcome of a bulk categorization
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BulkCategorization {
+    /// Number of transactions categorized, or that would be on a dry run
+    affected: u64,
+    /// Whether the transactions were only counted
+    dry_run: bool,
+    /// Up to 20 of the transactions that would be categorized, most recent first, on dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sample: Option<Vec<Transaction>>,
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/transactions/bulk-categorize", post(bulk_categorize))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint gives a category to the transactions of the authenticated user that match a
+/// filter
+///
+/// The filter matches transactions like the filters of `GET /accounts/{id}/transactions`.
+/// Transactions already in the category, legs of transfers, split transactions and transactions
+/// cleared in a finished reconciliation are left out. With `dry_run` nothing is changed and the
+/// response has a sample of the transactions that would be. Otherwise the transactions are
+/// categorized in a single update.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns how many transactions were categorized, or would be.
+/// `400` : The filter starts after it ends.
+/// `404` : The category or the account of the filter doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/transactions/bulk-categorize",
+    security(("cookieAuth" = [])),
+    request_body = BulkCategorize,
+    responses(
+        (status = 200, description = "Transactions categorized", body = BulkCategorization),
+        (status = 400, description = "Invalid filter"),
+        (status = 404, description = "Category or account not found")
+    )
+)]
+async fn bulk_categorize(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<BulkCategorize>,
+) -> Result<Json<BulkCategorization>, AppError> {
+    pool.run(move |conn| {
+        let user_id = session.user_id();
+        Category::from_id(conn, payload.category_id, user_id)?;
+        if let Some(account_id) = payload.filter.account_id {
+            Account::from_id(conn, account_id, user_id)?;
+        }
+        let filter = TransactionFilter::from(payload.filter);
+
+        if payload.dry_run {
+            let (total, sample) = Transaction::categorizable(
+                conn,
+                user_id,
+                &filter,
+                payload.category_id,
+                SAMPLE_SIZE,
+            )?;
+            return Ok(Json(BulkCategorization {
+                affected: total as u64,
+                dry_run: true,
+                sample: Some(sample),
+            }));
+        }
+
+        let affected = Transaction::categorize(conn, user_id, &filter, payload.category_id)?;
+        if affected > 0 {
+            reports.invalidate(user_id);
+        }
+        Ok(Json(BulkCategorization {
+            affected,
+            dry_run: false,
+            sample: None,
+        }))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use bigdecimal::BigDecimal;
+    use serde_json::json;
+
+    use crate::api::test_utils::TestApp;
+    use crate::database::models::{
+        accounts::{Account, AccountKind},
+        transactions::{Transaction, TransactionInput},
+        users::User,
+    };
+    use crate::quotas::Quotas;
+
+    #[tokio::test]
+    async fn test_bulk_categorize() {
+        let app = TestApp::new();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        let (_, groceries) = app
+            .request(
+                Method::POST,
+                "/categories",
+                Some(json!({"name": "Groceries"})),
+            )
+            .await;
+        for (description, occurred_at) in [
+            ("Corner Market", "2024-01-05"),
+            ("MARKET street", "2024-02-10"),
+            ("Market 100%", "2024-03-15"),
+            ("Rent", "2024-02-01"),
+        ] {
+            app.request(
+                Method::POST,
+                &format!("/accounts/{}/transactions", account["id"]),
+                Some(json!({"amount": "-20.00", "description": description, "occurred_at": occurred_at})),
+            )
+            .await;
+        }
+
+        // Another user's transaction matching the filter
+        let (other_id, foreign) = {
+            let conn = &mut app.pool().get().unwrap();
+            let other = User::new(conn, "bulk_categorize_other", "password").unwrap();
+            let account = Account::new(
+                conn,
+                other.id(),
+                "Chequing",
+                &BigDecimal::from(0),
+                "CAD",
+                AccountKind::Asset,
+            )
+            .unwrap();
+            let input = TransactionInput::new(
+                BigDecimal::from(-20),
+                "Market",
+                chrono::NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
+            );
+            let transaction =
+                Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+            (other.id(), transaction)
+        };
+
+        let body = |dry_run: bool| {
+            json!({
+                "filter": {"q": "market", "to": "2024-02-29", "uncategorized_only": true},
+                "category_id": groceries["id"],
+                "dry_run": dry_run,
+            })
+        };
+        let (status, preview) = app
+            .request(
+                Method::POST,
+                "/transactions/bulk-categorize",
+                Some(body(true)),
+            )
+            .await;
+        assert_eq!(status, 200, "{preview}");
+        assert_eq!(preview["affected"], 2);
+        assert_eq!(preview["sample"][0]["description"], "MARKET street");
+        assert_eq!(preview["sample"][1]["description"], "Corner Market");
+
+        let (status, applied) = app
+            .request(
+                Method::POST,
+                "/transactions/bulk-categorize",
+                Some(body(false)),
+            )
+            .await;
+        assert_eq!(status, 200, "{applied}");
+        assert_eq!(applied["affected"], preview["affected"]);
+        assert!(applied.get("sample").is_none());
+
+        let (_, transactions) = app
+            .request(
+                Method::GET,
+                &format!("/accounts/{}/transactions?q=market", account["id"]),
+                None,
+            )
+            .await;
+        let categories: Vec<_> = transactions
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| (t["description"].as_str().unwrap(), t["category_id"].clone()))
+            .collect();
+        assert_eq!(
+            categories,
+            vec![
+                ("Market 100%", json!(null)),
+                ("MARKET street", groceries["id"].clone()),
+                ("Corner Market", groceries["id"].clone()),
+            ]
+        );
+        {
+            let conn = &mut app.pool().get().unwrap();
+            let foreign = Transaction::from_id(conn, foreign.id(), other_id).unwrap();
+            assert_eq!(foreign.category_id(), None);
+        }
+
+        // Nothing is left to categorize
+        let (_, again) = app
+            .request(
+                Method::POST,
+                "/transactions/bulk-categorize",
+                Some(body(true)),
+            )
+            .await;
+        assert_eq!(again["affected"], 0);
+
+        // Wildcards in the text are matched literally
+        let (_, literal) = app
+            .request(
+                Method::POST,
+                "/transactions/bulk-categorize",
+                Some(json!({"filter": {"q": "100%"}, "category_id": groceries["id"], "dry_run": true})),
+            )
+            .await;
+        assert_eq!(literal["affected"], 1);
+    }
+}
@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{patch, post},
+    Extension, Json, Router,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson},
+        state::AppState,
+    },
+    database::{
+        connection::DbPool,
+        models::{
+            accounts::Account,
+            reconciliations::Reconciliation,
+            sessions::manager::Session,
+            transfers::{Transfer, TransferInput},
+            webhooks::WebhookEvent,
+        },
+    },
+    errors::AppError,
+    events::{EventBus, UserEvent},
+    jobs::webhooks::WebhookDispatcher,
+    quotas::Quotas,
+    reports::cache::ReportCache,
+};
+
+/// Create transfer request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_transfer))]
+#[schema(example = json!({"from_account_id": 1, "to_account_id": 2, "amount": "250.00", "occurred_at": "2024-06-15", "description": "Savings"}))]
+pub struct CreateTransfer {
+    /// The ID of the account the money leaves
+    from_account_id: i32,
+    /// The ID of the account the money goes into, in the same currency
+    to_account_id: i32,
+    /// The amount moved, as a positive decimal string
+    #[schema(value_type = String)]
+    amount: BigDecimal,
+    /// The date the transfer occurred on
+    occurred_at: NaiveDate,
+    /// Description of both legs of the transfer
+    #[serde(default)]
+    description: String,
+}
+
+impl Validate for CreateTransfer {}
+
+/// Update transfer request body, fields that are left out are kept
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(update_transfer))]
+#[schema(example = json!({"amount": "300.00", "occurred_at": "2024-06-16"}))]
+pub struct UpdateTransfer {
+    /// The amount moved, as a positive decimal string
+    #[schema(value_type = Option<String>)]
+    amount: Option<BigDecimal>,
+    /// The date the transfer occurred on
+    occurred_at: Option<NaiveDate>,
+    /// Description of both legs of the transfer
+    description: Option<String>,
+}
+
+impl Validate for UpdateTransfer {}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/transfers", post(create_transfer))
+        .route("/transfers/:id", patch(update_transfer))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint moves money between two accounts of the authenticated user
+///
+/// A transaction taking the amount out of the source account and one putting it into the
+/// destination account are created together, linked by the ID of the transfer. Reports leave
+/// transfers out of income and expenses.
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created transfer with both legs.
+/// `400` : The amount isn't positive, or the accounts are the same or in different currencies.
+/// `403` : The legs don't fit the quota of transactions of the user.
+/// `404` : An account doesn't exist or belongs to another user.
+/// `409` : An account is archived.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/transfers",
+    security(("cookieAuth" = [])),
+    request_body = CreateTransfer,
+    responses(
+        (status = 201, description = "Transfer created", body = Transfer),
+        (status = 400, description = "Invalid transfer"),
+        (status = 403, description = "Over the quota of transactions"),
+        (status = 404, description = "Account not found"),
+        (status = 409, description = "Account archived")
+    )
+)]
+async fn create_transfer(
+    State(pool): State<Arc<DbPool>>,
+    State(quotas): State<Quotas>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Extension(webhooks): Extension<Arc<WebhookDispatcher>>,
+    State(events): State<Arc<EventBus>>,
+    ValidatedJson(payload): ValidatedJson<CreateTransfer>,
+) -> Result<(StatusCode, Json<Transfer>), AppError> {
+    pool.transaction(move |conn| {
+        let from = Account::from_id(conn, payload.from_account_id, session.user_id())?;
+        let to = Account::from_id(conn, payload.to_account_id, session.user_id())?;
+        let input = TransferInput {
+            amount: payload.amount,
+            description: payload.description,
+            occurred_at: payload.occurred_at,
+        };
+        let transfer = Transfer::new(conn, &from, &to, &input, &quotas)?;
+        for leg in transfer.legs() {
+            webhooks.notify(
+                conn,
+                session.user_id(),
+                WebhookEvent::TransactionCreated,
+                leg,
+            );
+            events.publish(session.user_id(), UserEvent::TransactionCreated, leg);
+        }
+        reports.invalidate(session.user_id());
+
+        Ok((StatusCode::CREATED, Json(transfer)))
+    })
+    .await
+}
+
+/// This endpoint updates both legs of a transfer
+///
+/// The accounts of a transfer can't be changed, delete it and create a new one instead.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated transfer with both legs.
+/// `400` : The amount isn't positive.
+/// `404` : The transfer doesn't exist or belongs to another user.
+/// `409` : A leg was cleared in a finished reconciliation.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    patch,
+    path = "/transfers/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = Uuid, Path, description = "ID of the transfer")),
+    request_body = UpdateTransfer,
+    responses(
+        (status = 200, description = "Transfer updated", body = Transfer),
+        (status = 400, description = "Invalid transfer"),
+        (status = 404, description = "Transfer not found"),
+        (status = 409, description = "Transfer is reconciled")
+    )
+)]
+async fn update_transfer(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<Uuid>,
+    ValidatedJson(payload): ValidatedJson<UpdateTransfer>,
+) -> Result<Json<Transfer>, AppError> {
+    pool.run(move |conn| {
+        let transfer = Transfer::from_id(conn, id, session.user_id())?;
+        let leg_ids: Vec<i32> = transfer.legs().iter().map(|leg| leg.id()).collect();
+        Reconciliation::ensure_unlocked(conn, &leg_ids)?;
+        let current = transfer.input();
+        let input = TransferInput {
+            amount: payload.amount.unwrap_or(current.amount),
+            description: payload.description.unwrap_or(current.description),
+            occurred_at: payload.occurred_at.unwrap_or(current.occurred_at),
+        };
+
+        let transfer = transfer.update(conn, &input)?;
+        reports.invalidate(session.user_id());
+
+        Ok(Json(transfer))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::{json, Value};
+
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_transfer_legs_stay_in_sync() {
+        let app = TestApp::new();
+        let mut accounts = Vec::new();
+        for name in ["Chequing", "Savings"] {
+            let (_, account) = app
+                .request(
+                    Method::POST,
+                    "/accounts",
+                    Some(json!({"name": name, "opening_balance": "1000.00", "currency": "CAD"})),
+                )
+                .await;
+            accounts.push(account["id"].clone());
+        }
+        let balance = |account: &Value| format!("/accounts/{account}/balance");
+
+        let (status, transfer) = app
+            .request(
+                Method::POST,
+                "/transfers",
+                Some(json!({
+                    "from_account_id": accounts[0],
+                    "to_account_id": accounts[1],
+                    "amount": "250.00",
+                    "occurred_at": "2024-07-05",
+                    "description": "Monthly savings"
+                })),
+            )
+            .await;
+        assert_eq!(status, 201, "{transfer}");
+        assert_eq!(transfer["from"]["amount"], "-250.00");
+        assert_eq!(transfer["to"]["transfer_id"], transfer["id"]);
+
+        // Legs can't be edited on their own
+        let leg_uri = format!(
+            "/accounts/{}/transactions/{}",
+            accounts[1], transfer["to"]["id"]
+        );
+        let (status, error) = app
+            .request(
+                Method::PUT,
+                &leg_uri,
+                Some(json!({"amount": "300.00", "description": "Savings", "occurred_at": "2024-07-05"})),
+            )
+            .await;
+        assert_eq!(status, 409, "{error}");
+        assert!(error["message"].as_str().unwrap().contains(&format!(
+            "PATCH /transfers/{}",
+            transfer["id"].as_str().unwrap()
+        )));
+
+        let uri = format!("/transfers/{}", transfer["id"].as_str().unwrap());
+        let (status, updated) = app
+            .request(Method::PATCH, &uri, Some(json!({"amount": "300.00"})))
+            .await;
+        assert_eq!(status, 200, "{updated}");
+        assert_eq!(updated["to"]["description"], "Monthly savings");
+        let (_, chequing) = app.request(Method::GET, &balance(&accounts[0]), None).await;
+        let (_, savings) = app.request(Method::GET, &balance(&accounts[1]), None).await;
+        assert_eq!(chequing["balance"], "700.00");
+        assert_eq!(savings["balance"], "1300.00");
+
+        // Deleting one leg deletes the other
+        let (status, _) = app.request(Method::DELETE, &leg_uri, None).await;
+        assert_eq!(status, 200);
+        let (_, chequing) = app.request(Method::GET, &balance(&accounts[0]), None).await;
+        assert_eq!(chequing["balance"], "1000.00");
+        let (status, _) = app
+            .request(Method::PATCH, &uri, Some(json!({"amount": "300.00"})))
+            .await;
+        assert_eq!(status, 404);
+    }
+}
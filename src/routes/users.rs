@@ -1,75 +1,316 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use axum::{
     extract::{Path, State},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE, SET_COOKIE},
+        StatusCode,
+    },
+    middleware,
+    response::IntoResponse,
     routing::{delete, get, post, put},
-    Json, Router,
+    Extension, Json, Router,
 };
+use diesel::Connection;
 use serde::{Deserialize, Serialize};
-use utoipa::{OpenApi, ToSchema};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
 use crate::{
+    api::{
+        extract::{Validate, ValidatedJson, ValidatedQuery},
+        responses::MessageResponse,
+        state::AppState,
+    },
+    clock::Clock,
     database::{
         connection::DbPool,
-        models::users::{User, UserPublic},
+        models::{
+            sessions::{keys::JwtKeys, manager::Session},
+            users::{User, UserPublic},
+        },
     },
-    errors::AppError,
+    errors::{AppError, AuthenticateError},
+    export::archive,
+    middleware::forwarded::Client,
+    quotas::{Quotas, Usage},
+    reports::{cache::ReportCache, periods},
+    routes::auth::SessionConfig,
+    storage::attachments::AttachmentStore,
+    utils::sensitive::Sensitive,
+    wipe::{self, DataWipe},
 };
 
 /// Create a new user request body
 #[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
 #[openapi(paths(create_user))]
+#[schema(example = json!({"name": "jane", "password": "correct-horse-battery"}))]
 pub struct CreateUser {
     /// The username of the user
     name: String,
     /// The password of the user
-    password: String,
+    #[schema(value_type = String, format = Password)]
+    password: Sensitive<String>,
+}
+
+impl Validate for CreateUser {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_credentials(&self.name, self.password.expose())
+    }
 }
 
 /// Update user request body
 #[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
 #[openapi(paths(update_user))]
+#[serde(deny_unknown_fields)]
+#[schema(example = json!({"name": "jane"}))]
 pub struct UpdateUser {
     /// The username of the user
     name: String,
-    /// The password of the user
-    password: String,
 }
 
-pub fn create_route() -> Router<Arc<DbPool>> {
+impl Validate for UpdateUser {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_username(&self.name)
+    }
+}
+
+/// Change password request body
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"current_password": "correct-horse-battery", "new_password": "staple-tuna-orbit"}))]
+pub struct ChangePassword {
+    /// The current password of the user
+    #[schema(value_type = String, format = Password)]
+    current_password: Sensitive<String>,
+    /// The new password of the user
+    #[schema(value_type = String, format = Password)]
+    new_password: Sensitive<String>,
+}
+
+impl Validate for ChangePassword {
+    fn validate(&self) -> Result<(), AppError> {
+        if self.new_password.expose().chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(AppError::InvalidInput(format!(
+                "The password must be at least {MIN_PASSWORD_LENGTH} characters long"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Set preferred currency request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(set_preferred_currency))]
+#[schema(example = json!({"currency": "CAD"}))]
+pub struct SetPreferredCurrency {
+    /// The ISO 4217 code of the currency reports are converted into
+    currency: String,
+}
+
+impl Validate for SetPreferredCurrency {}
+
+/// Set weekly digest request body
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"enabled": true}))]
+pub struct SetDigest {
+    /// Whether a summary of the week is sent as a notification every Monday
+    enabled: bool,
+}
+
+impl Validate for SetDigest {}
+
+/// Set report periods request body
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"week_starts_on": "monday", "fiscal_month_start_day": 15}))]
+pub struct SetPeriods {
+    /// The day weekly reports start on, e.g. `monday`
+    week_starts_on: String,
+    /// The day of the month monthly reports start on, from 1 to 28
+    fiscal_month_start_day: u32,
+}
+
+impl Validate for SetPeriods {
+    fn validate(&self) -> Result<(), AppError> {
+        periods::parse_weekday(&self.week_starts_on)?;
+        periods::validate_month_start_day(self.fiscal_month_start_day)
+    }
+}
+
+/// Created user response body
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UserCreatedResponse {
+    /// The ID of the user
+    id: i32,
+    /// The username of the user
+    username: String,
+}
+
+/// Shortest password a user can set
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Longest username a user can have
+const MAX_USERNAME_LENGTH: usize = 64;
+
+/// Data wipe query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct WipeDataParams {
+    /// The username of the authenticated user, exactly
+    confirm: String,
+}
+
+impl Validate for WipeDataParams {}
+
+/// Check that a username isn't blank or too long
+pub fn validate_username(name: &str) -> Result<(), AppError> {
+    if name.trim().is_empty() {
+        return Err(AppError::InvalidInput(
+            "The username can't be empty".to_string(),
+        ));
+    }
+    if name.chars().count() > MAX_USERNAME_LENGTH {
+        return Err(AppError::InvalidInput(format!(
+            "The username can't be longer than {MAX_USERNAME_LENGTH} characters"
+        )));
+    }
+    Ok(())
+}
+
+/// Check that a username isn't blank or too long, and that a password is long enough
+pub fn validate_credentials(name: &str, password: &str) -> Result<(), AppError> {
+    validate_username(name)?;
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::InvalidInput(format!(
+            "The password must be at least {MIN_PASSWORD_LENGTH} characters long"
+        )));
+    }
+    Ok(())
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    // Administrators acting as a user can't change their credentials nor delete them or their data
+    let not_impersonated = || {
+        middleware::from_fn_with_state(state.clone(), crate::middleware::auth::reject_impersonation)
+    };
+    // Only the user and administrators can act on a user by their ID
+    let own_user =
+        || middleware::from_fn_with_state(state.clone(), crate::middleware::auth::user_auth);
     Router::new()
         .route("/users", post(create_user))
         .route("/users/username/:username", get(get_user))
-        .route("/users/:id", put(update_user))
-        .route("/users/:id", delete(delete_user))
+        .route(
+            "/users/:id",
+            put(update_user)
+                .layer(not_impersonated())
+                .layer(own_user())
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    crate::middleware::auth::jwt_auth,
+                )),
+        )
+        .route(
+            "/users/:id",
+            delete(delete_user)
+                .layer(not_impersonated())
+                .layer(own_user())
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    crate::middleware::auth::jwt_auth,
+                )),
+        )
+        .route(
+            "/users/me/password",
+            put(change_password)
+                .layer(not_impersonated())
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    crate::middleware::auth::jwt_auth,
+                )),
+        )
+        .route(
+            "/users/me/preferred-currency",
+            put(set_preferred_currency).layer(middleware::from_fn_with_state(
+                state.clone(),
+                crate::middleware::auth::jwt_auth,
+            )),
+        )
+        .route(
+            "/users/me/digest",
+            put(set_digest).layer(middleware::from_fn_with_state(
+                state.clone(),
+                crate::middleware::auth::jwt_auth,
+            )),
+        )
+        .route(
+            "/users/me/periods",
+            put(set_periods).layer(middleware::from_fn_with_state(
+                state.clone(),
+                crate::middleware::auth::jwt_auth,
+            )),
+        )
+        .route(
+            "/users/me/data",
+            delete(wipe_data)
+                .layer(not_impersonated())
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    crate::middleware::auth::jwt_auth,
+                )),
+        )
+        .route(
+            "/users/me/export.zip",
+            get(export_archive).layer(middleware::from_fn_with_state(
+                state.clone(),
+                crate::middleware::auth::jwt_auth,
+            )),
+        )
+        .route(
+            "/users/me/usage",
+            get(get_usage).layer(middleware::from_fn_with_state(
+                state,
+                crate::middleware::auth::jwt_auth,
+            )),
+        )
 }
 
 /// This endpoint creates a user
 ///
 /// ## Responses
 ///
-/// `201` : A successful response. Returns a string indicating the user was created.
+/// `201` : A successful response. Returns the ID and username of the user.
 ///
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
   post,
   path = "/users",
-  responses((status = 201, description = "User created"))
+  request_body = CreateUser,
+  responses(
+    (status = 201, description = "User created", body = UserCreatedResponse),
+    (status = 400, description = "Invalid username or password", body = ErrorBody)
+  )
 )]
 async fn create_user(
     State(pool): State<Arc<DbPool>>,
-    Json(payload): Json<CreateUser>,
-) -> Result<String, AppError> {
-    let mut conn = pool.get()?;
-    User::new(&mut conn, &payload.name, &payload.password)?;
-    Ok(format!("Creating user: {}", payload.name))
+    ValidatedJson(payload): ValidatedJson<CreateUser>,
+) -> Result<(StatusCode, Json<UserCreatedResponse>), AppError> {
+    pool.run(move |conn| {
+        let user = User::new(conn, &payload.name, payload.password.expose())?;
+        Ok((
+            StatusCode::CREATED,
+            Json(UserCreatedResponse {
+                id: user.id(),
+                username: payload.name,
+            }),
+        ))
+    })
+    .await
 }
 
 /// Retreives a specific user.
 ///
 /// ## Responses
 ///
-/// `200` : A successful response. Returns a string indicating the user details.
+/// `200` : A successful response. Returns the user.
 ///
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
@@ -78,68 +319,795 @@ async fn create_user(
   params(
     ("username" = String, Path, description = "Username of the user to retrieve")
   ),
-  responses((status = 200, description = "User retrieved"))
+  responses(
+    (status = 200, description = "User retrieved", body = UserPublic),
+    (status = 404, description = "User not found", body = ErrorBody)
+  )
 )]
 async fn get_user(
     State(pool): State<Arc<DbPool>>,
     Path(username): Path<String>,
 ) -> Result<Json<UserPublic>, AppError> {
-    let mut conn = pool.get()?;
-    let user = User::from_username(&mut conn, &username)?.to_public();
-    Ok(Json(user))
+    pool.run(move |conn| {
+        let user = User::from_username(conn, &username)?.to_public();
+        Ok(Json(user))
+    })
+    .await
 }
 
 /// Updates a specific user.
 ///
+/// Only the username is updated, passwords are changed with `PUT /users/me/password`.
+///
 /// ## Responses
 ///
-/// `201` : A successful response. Returns a string indicateing the user was updated.
+/// `200` : A successful response. Returns a message telling the user was updated.
+/// `400` : The username is blank or too long.
+/// `401` : The user is not authenticated.
+/// `403` : The user is another user and not an administrator, or an administrator is acting as
+/// the user.
 ///
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
   put,
   path = "/users/{id}",
+  security(("cookieAuth" = [])),
   params(
     ("id" = String, Path, description = "ID of the user to update")
   ),
+  request_body = UpdateUser,
   responses(
-    (status = 200, description = "Updated user {id} successfully"),
-    (status = 404, description = "User {id} not found")
-)
+    (status = 200, description = "Updated user {id} successfully", body = MessageResponse),
+    (status = 400, description = "Invalid username", body = ErrorBody),
+    (status = 401, description = "User is not authenticated", body = ErrorBody),
+    (status = 403, description = "Not the user nor an administrator, or impersonated", body = ErrorBody),
+    (status = 404, description = "User {id} not found", body = ErrorBody)
+  )
 )]
 async fn update_user(
     State(pool): State<Arc<DbPool>>,
     Path(id): Path<u64>,
-    Json(payload): Json<UpdateUser>,
-) -> Result<String, AppError> {
+    ValidatedJson(payload): ValidatedJson<UpdateUser>,
+) -> Result<Json<MessageResponse>, AppError> {
     // return if can't get pool connection
-    let mut conn = pool.get()?;
+    pool.run(move |conn| {
+        User::rename(conn, id as i32, &payload.name).map(|_| {
+            Json(MessageResponse::new(format!(
+                "Updated user {id} successfully"
+            )))
+        })
+    })
+    .await
+}
+
+/// This endpoint changes the password of the authenticated user
+///
+/// The session is rotated: the token it was made with stops working and the response sets the
+/// cookie of a new session, expiring when the old one would have.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Sets the cookie of the new session and returns a message.
+/// `400` : The new password is too short.
+/// `401` : The current password is wrong, or the user is not authenticated.
+/// `403` : An administrator is acting as the user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+  put,
+  path = "/users/me/password",
+  security(("cookieAuth" = [])),
+  request_body = ChangePassword,
+  responses(
+    (status = 200, description = "Password changed", body = MessageResponse),
+    (status = 400, description = "Invalid password", body = ErrorBody),
+    (status = 401, description = "Wrong password or user is not authenticated", body = ErrorBody),
+    (status = 403, description = "An administrator is acting as the user", body = ErrorBody)
+  )
+)]
+async fn change_password(
+    State(pool): State<Arc<DbPool>>,
+    State(keys): State<Arc<JwtKeys>>,
+    State(sessions): State<SessionConfig>,
+    Extension(session): Extension<Session>,
+    Extension(client): Extension<Client>,
+    ValidatedJson(payload): ValidatedJson<ChangePassword>,
+) -> Result<impl IntoResponse, AppError> {
+    pool.run(move |conn| {
+        let user = User::from_id(conn, session.user_id())?;
+        if !user.check_password(payload.current_password.expose()) {
+            return Err(AppError::Authenticate(AuthenticateError::WrongCredentials));
+        }
 
-    User::update(&mut conn, id as i32, &payload.name, &payload.password)
-        .map(|_| format!("Updated user {id} successfully"))
+        let session = conn.transaction(|conn| {
+            User::update(
+                conn,
+                user.id(),
+                user.username(),
+                payload.new_password.expose(),
+            )?;
+            Session::rotate(conn, &session)
+        })?;
+        let cookie = sessions.cookie(&session.token(&keys)?, &client);
+        Ok((
+            [(SET_COOKIE, cookie)],
+            Json(MessageResponse::new("Password changed")),
+        ))
+    })
+    .await
 }
 
 /// Deletes a specific user.
 ///
 /// ## Responses
 ///
-/// `200` : A successful response. Returns a string indicating the user details.
+/// `200` : A successful response. Returns a message telling the user was deleted.
+/// `401` : The user is not authenticated.
+/// `403` : The user is another user and not an administrator, or an administrator is acting as
+/// the user.
 ///
 /// `default` : An unexpected error occurred. Returns an `AppError`.
 #[utoipa::path(
   delete,
   path = "/users/{id}",
+  security(("cookieAuth" = [])),
   params(
     ("id" = String, Path, description = "ID of the user to update")
   ),
-  responses((status = 200, description = "Deleted user {id} successfully"))
+  responses(
+    (status = 200, description = "Deleted user {id} successfully", body = MessageResponse),
+    (status = 401, description = "User is not authenticated", body = ErrorBody),
+    (status = 403, description = "Not the user nor an administrator, or impersonated", body = ErrorBody),
+    (status = 404, description = "User {id} not found", body = ErrorBody)
+  )
 )]
 async fn delete_user(
     State(pool): State<Arc<DbPool>>,
     Path(id): Path<u64>,
-) -> Result<String, AppError> {
+) -> Result<Json<MessageResponse>, AppError> {
     // return if can't get pool connection
-    let mut conn = pool.get()?;
+    pool.run(move |conn| {
+        User::delete(conn, id as i32).map(|_| {
+            Json(MessageResponse::new(format!(
+                "Deleted user {id} successfully"
+            )))
+        })
+    })
+    .await
+}
+
+/// This endpoint sets the currency the reports of the authenticated user are converted into
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated user.
+/// `400` : The currency isn't three uppercase letters.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+  put,
+  path = "/users/me/preferred-currency",
+  security(("cookieAuth" = [])),
+  request_body = SetPreferredCurrency,
+  responses(
+    (status = 200, description = "Preferred currency set", body = UserPublic),
+    (status = 400, description = "Invalid currency", body = ErrorBody),
+    (status = 401, description = "User is not authenticated", body = ErrorBody)
+  )
+)]
+async fn set_preferred_currency(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<SetPreferredCurrency>,
+) -> Result<Json<UserPublic>, AppError> {
+    pool.run(move |conn| {
+        let user = User::from_id(conn, session.user_id())?
+            .set_preferred_currency(conn, &payload.currency)?;
+        // Converted reports are in the preferred currency
+        reports.invalidate(session.user_id());
+        Ok(Json(user.to_public()))
+    })
+    .await
+}
+
+/// This endpoint opts the authenticated user in or out of the weekly digest
+///
+/// The digest summarizes the spending of the past week, the budgets over 80% and the recurring
+/// transactions due in the coming week. It is sent as a `digest` notification every Monday, and
+/// users are opted in when they're created.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+  put,
+  path = "/users/me/digest",
+  security(("cookieAuth" = [])),
+  request_body = SetDigest,
+  responses(
+    (status = 200, description = "Weekly digest set", body = UserPublic),
+    (status = 401, description = "User is not authenticated", body = ErrorBody)
+  )
+)]
+async fn set_digest(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<SetDigest>,
+) -> Result<Json<UserPublic>, AppError> {
+    pool.run(move |conn| {
+        let user =
+            User::from_id(conn, session.user_id())?.set_digest_enabled(conn, payload.enabled)?;
+        Ok(Json(user.to_public()))
+    })
+    .await
+}
+
+/// This endpoint sets when the weeks and months of the reports of the authenticated user start
+///
+/// Monthly reports cover fiscal months named after the month they start in: with months starting
+/// on the 15th, June runs from June 15th to July 14th. Days up to the 28th can be set so that
+/// every month has the day. Users start with weeks starting on Monday and calendar months.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated user.
+/// `400` : The day of the week isn't one, or the day of the month isn't between 1 and 28.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+  put,
+  path = "/users/me/periods",
+  security(("cookieAuth" = [])),
+  request_body = SetPeriods,
+  responses(
+    (status = 200, description = "Report periods set", body = UserPublic),
+    (status = 400, description = "Invalid day", body = ErrorBody),
+    (status = 401, description = "User is not authenticated", body = ErrorBody)
+  )
+)]
+async fn set_periods(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    ValidatedJson(payload): ValidatedJson<SetPeriods>,
+) -> Result<Json<UserPublic>, AppError> {
+    pool.run(move |conn| {
+        let user = User::from_id(conn, session.user_id())?.set_periods(
+            conn,
+            periods::parse_weekday(&payload.week_starts_on)?,
+            payload.fiscal_month_start_day,
+        )?;
+        // Cached reports cover the months of the previous setting
+        reports.invalidate(session.user_id());
+        Ok(Json(user.to_public()))
+    })
+    .await
+}
+
+/// This endpoint deletes the accounts, transactions and plans of the authenticated user
+///
+/// The user is kept, along with their categories, tags, webhooks and settings. As the data can't
+/// be recovered, `confirm` must be the username of the user. The wipe is recorded in the audit log
+/// with its counts.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns how many accounts, plans, transactions, splits, tags and
+/// attachments were deleted.
+/// `400` : A parameter can't be read or is unknown, or `confirm` isn't the username of the user.
+/// `403` : An administrator is acting as the user.
+/// `409` : A transaction was cleared in a finished reconciliation, nothing was deleted.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+  delete,
+  path = "/users/me/data",
+  security(("cookieAuth" = [])),
+  params(WipeDataParams),
+  responses(
+    (status = 200, description = "Data deleted", body = DataWipe),
+    (status = 400, description = "The username wasn't confirmed", body = ErrorBody),
+    (status = 401, description = "User is not authenticated", body = ErrorBody),
+    (status = 403, description = "An administrator is acting as the user", body = ErrorBody),
+    (status = 409, description = "A transaction is reconciled", body = ErrorBody)
+  )
+)]
+async fn wipe_data(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    Extension(session): Extension<Session>,
+    Extension(store): Extension<Arc<AttachmentStore>>,
+    ValidatedQuery(params): ValidatedQuery<WipeDataParams>,
+) -> Result<Json<DataWipe>, AppError> {
+    let (counts, attachments) = pool
+        .run(move |conn| {
+            let user = User::from_id(conn, session.user_id())?;
+            if params.confirm != user.username() {
+                return Err(AppError::InvalidInput(
+                    "Set confirm to your username to delete your data".to_string(),
+                ));
+            }
+
+            let wiped = wipe::wipe_data(conn, user.id())?;
+            reports.invalidate(user.id());
+            Ok(wiped)
+        })
+        .await?;
+    for attachment in &attachments {
+        store.remove(attachment.storage_path()).await;
+    }
+
+    Ok(Json(counts))
+}
+
+/// This endpoint exports the data of the authenticated user as a zip archive
+///
+/// The archive has a CSV of the transactions of each account, named after the account, e.g.
+/// `accounts/Chequing_12.csv`, `plans.json` with the plans and their budgets, `categories.csv`,
+/// and `manifest.json` with when the export started and how many of each it has. The archive is
+/// streamed as it is written, an entry at a time.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the archive as an attachment.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+  get,
+  path = "/users/me/export.zip",
+  security(("cookieAuth" = [])),
+  responses(
+    (status = 200, description = "Archive of the data of the user", body = String, content_type = "application/zip"),
+    (status = 401, description = "User is not authenticated", body = ErrorBody)
+  )
+)]
+async fn export_archive(
+    State(pool): State<Arc<DbPool>>,
+    State(clock): State<Arc<dyn Clock>>,
+    Extension(session): Extension<Session>,
+) -> impl IntoResponse {
+    let exported_at = clock.now_utc();
+    let body = archive::stream(pool, session.user_id(), exported_at);
+
+    (
+        [
+            (CONTENT_TYPE, archive::CONTENT_TYPE.to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"{}\"",
+                    archive::file_name(exported_at)
+                ),
+            ),
+        ],
+        body,
+    )
+}
+
+/// This endpoint returns how much data the authenticated user stores, against their quotas
+///
+/// Writes over a quota are refused with `quota_exceeded`. The usage is cached briefly, so it can
+/// lag behind the latest writes for up to a minute. Administrators aren't held to the quotas, so
+/// their limits are `null`.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the usage of each resource with its limit.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+  get,
+  path = "/users/me/usage",
+  security(("cookieAuth" = [])),
+  responses(
+    (status = 200, description = "Usage of the user", body = Usage),
+    (status = 401, description = "User is not authenticated", body = ErrorBody)
+  )
+)]
+async fn get_usage(
+    State(pool): State<Arc<DbPool>>,
+    State(reports): State<Arc<ReportCache>>,
+    State(quotas): State<Quotas>,
+    Extension(session): Extension<Session>,
+) -> Result<Json<Usage>, AppError> {
+    pool.run_read(move |conn| {
+        let usage = reports.get_or_compute(
+            session.user_id(),
+            "usage".to_string(),
+            Instant::now(),
+            || {
+                let user = User::from_id(conn, session.user_id())?;
+                Usage::of_user(conn, &user, &quotas)
+            },
+        )?;
+        Ok(Json(usage))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use crate::api::test_utils::{session_cookie, TestApp};
+    use crate::database::models::users::User;
+    use crate::quotas::Quotas;
+
+    #[tokio::test]
+    async fn test_set_digest() {
+        let app = TestApp::new();
+
+        let (status, user) = app
+            .request(
+                Method::PUT,
+                "/users/me/digest",
+                Some(json!({"enabled": false})),
+            )
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(user["digest_enabled"], false);
+
+        let (status, user) = app
+            .request(
+                Method::PUT,
+                "/users/me/digest",
+                Some(json!({"enabled": true})),
+            )
+            .await;
+        assert_eq!(status, 200);
+        assert_eq!(user["digest_enabled"], true);
+    }
+
+    #[tokio::test]
+    async fn test_change_password_rotates_the_session() {
+        let app = TestApp::new();
+        let change =
+            |current: &str| json!({"current_password": current, "new_password": "new_password"});
+
+        let (status, _, _) = app
+            .request_with_cookie(
+                app.cookie(),
+                Method::PUT,
+                "/users/me/password",
+                Some(change("wrong_password")),
+            )
+            .await;
+        assert_eq!(status, 401);
+
+        let (status, headers, _) = app
+            .request_with_cookie(
+                app.cookie(),
+                Method::PUT,
+                "/users/me/password",
+                Some(change("test_password")),
+            )
+            .await;
+        assert_eq!(status, 200);
+        let cookie = session_cookie(&headers);
+
+        // The token from before the change stops working, the new one works
+        let (status, _, _) = app
+            .request_with_cookie(app.cookie(), Method::GET, "/users/me/usage", None)
+            .await;
+        assert_eq!(status, 401);
+        let (status, _, _) = app
+            .request_with_cookie(&cookie, Method::GET, "/users/me/usage", None)
+            .await;
+        assert_eq!(status, 200);
+
+        let (status, _, _) = app
+            .request_with_cookie(
+                &cookie,
+                Method::POST,
+                "/auth/login",
+                Some(json!({"username": "test_user", "password": "new_password"})),
+            )
+            .await;
+        assert_eq!(status, 200);
+    }
+
+    #[tokio::test]
+    async fn test_acting_on_a_user_requires_the_user_or_an_administrator() {
+        let app = TestApp::new();
+        let other = {
+            let mut conn = app.pool().get().unwrap();
+            User::new(&mut conn, "other_user", "other_password").unwrap()
+        };
+        let update = || Some(json!({"name": "renamed_user"}));
+
+        // Without a session
+        let uri = format!("/users/{}", app.user_id());
+        let (status, _, _) = app
+            .request_with_cookie("", Method::PUT, &uri, update())
+            .await;
+        assert_eq!(status, 401);
+        let (status, _, _) = app
+            .request_with_cookie("", Method::DELETE, &uri, None)
+            .await;
+        assert_eq!(status, 401);
+
+        // As another user
+        let uri = format!("/users/{}", other.id());
+        let (status, body) = app.request(Method::PUT, &uri, update()).await;
+        assert_eq!(status, 403);
+        assert_eq!(body["error"], "forbidden");
+        let (status, body) = app.request(Method::DELETE, &uri, None).await;
+        assert_eq!(status, 403);
+        assert_eq!(body["error"], "forbidden");
+
+        // As an administrator
+        app.make_admin();
+        let (status, _) = app.request(Method::DELETE, &uri, None).await;
+        assert!(status.is_success(), "{status}");
+    }
+
+    #[tokio::test]
+    async fn test_quotas_and_usage() {
+        let app = TestApp::with_quotas(Quotas {
+            enabled: true,
+            transactions: 2,
+            attachment_bytes: 10,
+            webhooks: 1,
+            accounts: 2,
+        });
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        let transactions = format!("/accounts/{}/transactions", account["id"]);
+        let transaction = json!({
+            "amount": "-4.50",
+            "description": "Coffee",
+            "occurred_at": "2024-06-12",
+        });
+
+        // Writes are refused once they'd go over a quota
+        let (status, created) = app
+            .request(Method::POST, &transactions, Some(transaction.clone()))
+            .await;
+        assert_eq!(status, 201);
+        let (status, _) = app
+            .request(Method::POST, &transactions, Some(transaction.clone()))
+            .await;
+        assert_eq!(status, 201);
+        let (status, error) = app
+            .request(Method::POST, &transactions, Some(transaction.clone()))
+            .await;
+        assert_eq!(status, 403);
+        assert_eq!(error["error"], "quota_exceeded");
+        assert_eq!(
+            error["details"],
+            json!({"resource": "transactions", "limit": 2, "used": 2})
+        );
+
+        let attachments = format!("/transactions/{}/attachments", created["id"]);
+        let (status, _) = app
+            .upload(&attachments, "receipt.pdf", "application/pdf", b"%PDF-1.7")
+            .await;
+        assert_eq!(status, 201);
+        let (status, error) = app
+            .upload(&attachments, "receipt.pdf", "application/pdf", b"%PDF-1.7")
+            .await;
+        assert_eq!(status, 403);
+        assert_eq!(error["details"]["resource"], "attachment_bytes");
+
+        let webhook = json!({
+            "url": "http://127.0.0.1:9/hook",
+            "secret": "a-secret-of-the-dashboard",
+            "events": ["transaction_created"],
+        });
+        let (status, _) = app
+            .request(Method::POST, "/webhooks", Some(webhook.clone()))
+            .await;
+        assert_eq!(status, 201);
+        let (status, _) = app
+            .request(Method::POST, "/webhooks", Some(webhook.clone()))
+            .await;
+        assert_eq!(status, 403);
+
+        let (status, usage) = app.request(Method::GET, "/users/me/usage", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            usage,
+            json!({
+                "transactions": {"used": 2, "limit": 2},
+                "attachment_bytes": {"used": 8, "limit": 10},
+                "webhooks": {"used": 1, "limit": 1},
+                "accounts": {"used": 1, "limit": 2},
+            })
+        );
+
+        // Administrators aren't held to the quotas
+        app.make_admin();
+        let (status, _) = app
+            .request(Method::POST, &transactions, Some(transaction))
+            .await;
+        assert_eq!(status, 201);
+        let (status, _) = app.request(Method::POST, "/webhooks", Some(webhook)).await;
+        assert_eq!(status, 201);
+        let (_, usage) = app.request(Method::GET, "/users/me/usage", None).await;
+        assert_eq!(usage["transactions"], json!({"used": 3, "limit": null}));
+        assert_eq!(usage["webhooks"], json!({"used": 2, "limit": null}));
+    }
+
+    #[tokio::test]
+    async fn test_wipe_data() {
+        let app = TestApp::new();
+        let username = User::from_id(&mut app.pool().get().unwrap(), app.user_id())
+            .unwrap()
+            .username()
+            .to_string();
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        for occurred_at in ["2024-05-01", "2024-06-01"] {
+            let (status, _) = app
+                .request(
+                    Method::POST,
+                    &format!("/accounts/{}/transactions", account["id"]),
+                    Some(json!({"amount": "-5.00", "description": "Coffee", "occurred_at": occurred_at})),
+                )
+                .await;
+            assert_eq!(status, 201);
+        }
+        let (status, _) = app.request(Method::POST, "/plans/wiped_plan", None).await;
+        assert_eq!(status, 201);
+
+        // The username must be echoed back exactly
+        for confirm in ["", "yes", &username.to_uppercase()] {
+            let (status, error) = app
+                .request(
+                    Method::DELETE,
+                    &format!("/users/me/data?confirm={confirm}"),
+                    None,
+                )
+                .await;
+            assert_eq!(status, 400, "{error}");
+        }
+        let (_, accounts) = app.request(Method::GET, "/accounts", None).await;
+        assert_eq!(accounts.as_array().unwrap().len(), 1);
+
+        let (status, wiped) = app
+            .request(
+                Method::DELETE,
+                &format!("/users/me/data?confirm={username}"),
+                None,
+            )
+            .await;
+        assert_eq!(status, 200, "{wiped}");
+        assert_eq!(
+            wiped,
+            json!({
+                "accounts": 1,
+                "plans": 1,
+                "transactions": 2,
+                "splits": 0,
+                "tags": 0,
+                "attachments": 0,
+            })
+        );
+
+        // The user is kept, without their data
+        let (_, accounts) = app.request(Method::GET, "/accounts", None).await;
+        assert_eq!(accounts, json!([]));
+        let (status, usage) = app.request(Method::GET, "/users/me/usage", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(usage["transactions"]["used"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_export_archive() {
+        use std::io::{Cursor, Read};
+
+        let app = TestApp::new();
+        let (_, category) = app
+            .request(
+                Method::POST,
+                "/categories",
+                Some(json!({"name": "Groceries"})),
+            )
+            .await;
+        app.request(Method::POST, "/categories", Some(json!({"name": "Rent"})))
+            .await;
+        let mut accounts = vec![];
+        for name in ["Chequing", "Joint / Savings*"] {
+            let (status, account) = app
+                .request(
+                    Method::POST,
+                    "/accounts",
+                    Some(json!({"name": name, "opening_balance": "0.00", "currency": "CAD"})),
+                )
+                .await;
+            assert_eq!(status, 201, "{account}");
+            accounts.push(account);
+        }
+        for (amount, day) in [("-42.10", "03"), ("-7.25", "04"), ("1500.00", "15")] {
+            let (status, _) = app
+                .request(
+                    Method::POST,
+                    &format!("/accounts/{}/transactions", accounts[0]["id"]),
+                    Some(json!({
+                        "amount": amount,
+                        "description": "Market, \"downtown\"",
+                        "occurred_at": format!("2024-05-{day}"),
+                        "category_id": category["id"],
+                    })),
+                )
+                .await;
+            assert_eq!(status, 201);
+        }
+        let (status, _) = app.request(Method::POST, "/plans/2024", None).await;
+        assert_eq!(status, 201);
+        let (status, budget) = app
+            .request(
+                Method::POST,
+                "/plans/2024/budgets",
+                Some(json!({"category_id": category["id"], "name": "Groceries", "amount": "400.00", "interval": "monthly", "currency": "CAD", "start_date": "2024-01-01"})),
+            )
+            .await;
+        assert_eq!(status, 201, "{budget}");
+
+        let (status, headers, body) = app.download("/api/v1/users/me/export.zip").await;
+        assert_eq!(status, 200);
+        assert_eq!(headers["content-type"], "application/zip");
+        assert!(headers["content-disposition"]
+            .to_str()
+            .unwrap()
+            .starts_with("attachment; filename=\"finance-fusion_"));
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(body.to_vec())).unwrap();
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        let manifest: serde_json::Value = serde_json::from_str(&read("manifest.json")).unwrap();
+        assert_eq!(manifest["accounts"], 2);
+        assert_eq!(manifest["transactions"], 3);
+        assert_eq!(manifest["plans"], 1);
+        assert_eq!(manifest["budgets"], 1);
+        assert_eq!(manifest["categories"], 2);
+        assert!(manifest["exported_at"].is_string());
+
+        // Account names are sanitized into file names
+        let savings = format!("accounts/Joint___Savings__{}.csv", accounts[1]["id"]);
+        assert_eq!(manifest["files"][1]["name"], savings);
+        assert_eq!(manifest["files"][1]["rows"], 0);
+        assert_eq!(
+            read(&savings),
+            "date,description,category,amount,currency\n"
+        );
+        let chequing = read(&format!("accounts/Chequing_{}.csv", accounts[0]["id"]));
+        assert_eq!(chequing.lines().count(), 4);
+        assert!(chequing.contains("2024-05-03,\"Market, \"\"downtown\"\"\",Groceries,-42.10,CAD"));
+
+        let plans: serde_json::Value = serde_json::from_str(&read("plans.json")).unwrap();
+        assert_eq!(plans[0]["name"], "2024");
+        assert_eq!(plans[0]["budgets"][0]["amount"], "400.00");
+        let categories = read("categories.csv");
+        assert!(categories.starts_with("id,name,created_at\n"));
+        assert_eq!(categories.lines().count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_query_parameters_are_refused() {
+        let app = TestApp::new();
 
-    User::delete(&mut conn, id as i32).map(|_| format!("Deleted user {id} successfully"))
+        let uri = "/users/me/data?confirm=test_user&force=true";
+        let (status, body) = app.request(Method::DELETE, uri, None).await;
+        assert_eq!(status, 400, "{uri}");
+        assert_eq!(body["error"], "invalid_query");
+        assert_eq!(
+            body["details"],
+            json!({"kind": "unknown_field", "fields": ["force"]})
+        );
+    }
 }
@@ -0,0 +1,64 @@
+use axum::{routing::get, Json, Router};
+
+use crate::{api::state::AppState, config::build_info::BuildInfo};
+
+pub fn create_route() -> Router<AppState> {
+    Router::new().route("/version", get(get_version))
+}
+
+/// This endpoint responds with the metadata of the build of the server, so operators can confirm
+/// exactly what is deployed.
+///
+/// It requires no authentication, but counts towards the rate limit like any other request.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the metadata of the build.
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "vitals",
+    responses((status = 200, description = "The metadata of the build", body = BuildInfo))
+)]
+pub async fn get_version() -> Json<BuildInfo> {
+    Json(BuildInfo::current())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+
+    use crate::api::test_utils::TestApp;
+    use crate::config::config::VERSION;
+
+    #[tokio::test]
+    async fn test_version() {
+        let app = TestApp::new();
+
+        let (status, version) = app.request(Method::GET, "/version", None).await;
+        assert_eq!(status, 200, "{version}");
+        let mut keys: Vec<&str> = version
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "built_at",
+                "features",
+                "git_describe",
+                "rustc_version",
+                "version"
+            ]
+        );
+        assert_eq!(version["version"], VERSION);
+        assert!(version["rustc_version"]
+            .as_str()
+            .unwrap()
+            .starts_with("rustc "));
+        assert!(version["features"].is_array());
+    }
+}
@@ -1,38 +1,260 @@
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
-use axum::{routing::get, Json, Router};
-use bson::doc;
+use axum::{extract::State, http::StatusCode, routing::get, Extension, Json, Router};
+use diesel::RunQueryDsl;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::{database::connection::DbPool, errors::AppError};
+use crate::{
+    api::{
+        api::{ApiDocs, HttpConfig},
+        state::AppState,
+    },
+    config::{
+        config::VERSION,
+        startup::{Startup, StartupPhase},
+    },
+    database::{connection::DbPool, schema_check::SchemaStatus},
+    errors::AppError,
+    maintenance::{Maintenance, MaintenanceMode},
+};
+
+/// How long the database has to answer the vitals check
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// When the server started, set when the routes are created
+static STARTED_AT: OnceLock<Instant> = OnceLock::new();
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct Vitals {
+    /// `ok` if the database and its replica, if any, answered, `degraded` otherwise
+    pub status: String,
+    /// Whether the database answered a query in time
+    pub database: bool,
+    /// Number of open connections of the pool
+    pub connections: u32,
+    /// Number of open connections of the pool that aren't in use
+    pub idle_connections: u32,
+    /// Version of the server
+    pub version: String,
+    /// Seconds since the server started
+    pub uptime_seconds: u64,
+    /// Which requests the server answers, set by administrators
+    pub maintenance: MaintenanceMode,
+    /// The vitals of the read replica, `null` if none is configured
+    pub replica: Option<ReplicaVitals>,
+    /// Who the Swagger UI and the OpenAPI document are served to
+    pub api_docs: ApiDocs,
+}
+
+/// The vitals of the read replica of the database
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReplicaVitals {
+    /// Whether the replica answered a query in time
+    pub database: bool,
+    /// Number of open connections of the pool of the replica
+    pub connections: u32,
+    /// Number of open connections of the pool of the replica that aren't in use
+    pub idle_connections: u32,
+}
+
+/// Whether the server can take requests
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Readiness {
+    /// `ready`, `starting` until every phase of startup completed, `shutting_down` once a
+    /// shutdown signal was received, or `degraded` if the database didn't answer
     pub status: String,
+    /// Whether the database answered a query in time, `null` when starting or shutting down
+    pub database: Option<bool>,
+    /// How the database compared to the models at startup, `null` if it wasn't checked
+    pub schema: Option<SchemaStatus>,
+    /// The phases of startup completed, in the order they completed
+    pub startup: Vec<StartupPhase>,
+}
+
+/// Whether the server received a shutdown signal, shared with the readiness endpoint
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    started: AtomicBool,
+}
+
+impl Shutdown {
+    /// Mark the server as shutting down, it stops being ready
+    pub fn start(&self) {
+        self.started.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the server is shutting down
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
 }
-pub fn create_route() -> Router<Arc<DbPool>> {
+
+pub fn create_route() -> Router<AppState> {
+    STARTED_AT.get_or_init(Instant::now);
+
     Router::new()
         .route("/vitals", get(get_vitals))
+        .route("/livez", get(get_liveness))
+        .route("/readyz", get(get_readiness))
         .route("/hello", get(hello))
 }
 
 /// This endpoint responds with the vitals of the server.
 ///
+/// The database, and its read replica if one is configured, are checked with a query that has two
+/// seconds to complete. The vitals also tell who the API documentation is served to.
+///
 /// ## Responses
 ///
 /// `200` : A successful response. Returns a JSON object containing the server's vitals.
 ///
-/// `default` : An unexpected error occurred. Returns an `AppError`.
+/// `503` : The database or its replica couldn't be reached. Returns the vitals with a `degraded`
+/// status.
 #[utoipa::path(
   get,
   path = "/vitals",
-  responses((status = 200, description = "Successful response"))
+  responses(
+    (status = 200, description = "Successful response", body = Vitals),
+    (status = 503, description = "The database couldn't be reached", body = Vitals)
+  )
+)]
+pub async fn get_vitals(
+    State(pool): State<Arc<DbPool>>,
+    State(http): State<HttpConfig>,
+    Extension(maintenance): Extension<Arc<Maintenance>>,
+) -> (StatusCode, Json<Vitals>) {
+    let database = check_database(&pool, false).await;
+    let replica_database = match pool.replica_connections() {
+        Some(_) => Some(check_database(&pool, true).await),
+        None => None,
+    };
+    let replica = replica_database.zip(pool.replica_connections()).map(
+        |(database, (connections, idle_connections))| ReplicaVitals {
+            database,
+            connections,
+            idle_connections,
+        },
+    );
+    let healthy = database && replica.as_ref().map_or(true, |replica| replica.database);
+
+    let (connections, idle_connections) = pool.connections();
+    let vitals = Vitals {
+        status: if healthy { "ok" } else { "degraded" }.to_owned(),
+        database,
+        connections,
+        idle_connections,
+        version: VERSION.to_owned(),
+        uptime_seconds: STARTED_AT.get_or_init(Instant::now).elapsed().as_secs(),
+        maintenance: maintenance.status().mode,
+        replica,
+        api_docs: http.api_docs,
+    };
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(vitals))
+}
+
+/// This endpoint responds as long as the server is running, without checking the database.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns `ok`.
+#[utoipa::path(
+  get,
+  path = "/livez",
+  responses((status = 200, description = "The server is running", body = String, content_type = "text/plain"))
 )]
-pub async fn get_vitals() -> Result<Json<Vitals>, AppError> {
-    Ok(Json(Vitals {
-        status: "ok".to_owned(),
-    }))
+pub async fn get_liveness() -> &'static str {
+    "ok"
+}
+
+/// This endpoint responds with whether the server can take requests.
+///
+/// The server isn't ready until it went through every phase of startup, once it received a
+/// shutdown signal, while requests are drained before it stops listening, or when the database doesn't answer a query within two seconds. The
+/// readiness also tells how the database compared to the models when the server started.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the readiness with a `ready` status.
+///
+/// `503` : The server is starting, shutting down or the database couldn't be reached. Returns the
+/// readiness with a `starting`, `shutting_down` or `degraded` status.
+#[utoipa::path(
+  get,
+  path = "/readyz",
+  responses(
+    (status = 200, description = "The server is ready", body = Readiness),
+    (status = 503, description = "The server is starting, shutting down or the database couldn't be reached", body = Readiness)
+  )
+)]
+pub async fn get_readiness(
+    State(pool): State<Arc<DbPool>>,
+    Extension(shutdown): Extension<Arc<Shutdown>>,
+    Extension(startup): Extension<Arc<Startup>>,
+) -> (StatusCode, Json<Readiness>) {
+    // The database isn't checked once shutting down, the server won't be ready again
+    let (status, database) = if shutdown.is_started() {
+        ("shutting_down", None)
+    } else if !startup.is_finished() {
+        ("starting", None)
+    } else if check_database(&pool, false).await {
+        ("ready", Some(true))
+    } else {
+        ("degraded", Some(false))
+    };
+    let code = if status == "ready" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        code,
+        Json(Readiness {
+            status: status.to_owned(),
+            database,
+            schema: pool.schema().cloned(),
+            startup: startup.completed(),
+        }),
+    )
+}
+
+/// Check that the database, or its replica, answers a query within `DATABASE_TIMEOUT`
+async fn check_database(pool: &Arc<DbPool>, replica: bool) -> bool {
+    let pool = pool.clone();
+    let check = tokio::task::spawn_blocking(move || {
+        let mut conn = if replica {
+            pool.conn_read()?
+        } else {
+            pool.get()?
+        };
+        diesel::sql_query("SELECT 1").execute(&mut conn)?;
+        Ok::<_, AppError>(())
+    });
+
+    match tokio::time::timeout(DATABASE_TIMEOUT, check).await {
+        Ok(Ok(Ok(()))) => true,
+        Ok(Ok(Err(e))) => {
+            tracing::warn!("Database check failed ({e})");
+            false
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Database check panicked ({e})");
+            false
+        }
+        Err(_) => {
+            tracing::warn!("Database check timed out");
+            false
+        }
+    }
 }
 
 /// This endpoint responds with a simple greeting message.
@@ -45,8 +267,90 @@ pub async fn get_vitals() -> Result<Json<Vitals>, AppError> {
 #[utoipa::path(
   get,
   path = "/hello",
-  responses((status = 200, description = "Successful response"))
+  responses((status = 200, description = "Successful response", body = String, content_type = "text/plain"))
 )]
 async fn hello() -> Result<String, AppError> {
     Ok("Hello, world!".to_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+
+    use super::*;
+    use crate::api::test_utils::TestApp;
+
+    #[tokio::test]
+    async fn test_vitals() {
+        let app = TestApp::new();
+
+        let (status, vitals) = app.request(Method::GET, "/vitals", None).await;
+        assert_eq!(status, 200, "{vitals}");
+        assert_eq!(vitals["status"], "ok");
+        assert_eq!(vitals["database"], true);
+        assert_eq!(vitals["version"], VERSION);
+        assert!(vitals["connections"].as_u64().unwrap() >= 1);
+        assert!(vitals["uptime_seconds"].is_u64());
+        assert_eq!(vitals["maintenance"], "off");
+        assert!(vitals["replica"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_vitals_with_replica() {
+        let pool = Arc::new(DbPool::new_test_shared().with_test_replica());
+        let maintenance = Maintenance::load(std::env::temp_dir().join("missing-maintenance.json"));
+
+        let (status, Json(vitals)) = get_vitals(
+            State(pool),
+            State(HttpConfig::default()),
+            Extension(Arc::new(maintenance.unwrap())),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(vitals.status, "ok");
+        let replica = vitals.replica.unwrap();
+        assert!(replica.database);
+        assert_eq!((replica.connections, replica.idle_connections), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_vitals_without_database() {
+        let pool = Arc::new(DbPool::new_unreachable());
+        let maintenance = Maintenance::load(std::env::temp_dir().join("missing-maintenance.json"));
+
+        let (status, Json(vitals)) = get_vitals(
+            State(pool),
+            State(HttpConfig::default()),
+            Extension(Arc::new(maintenance.unwrap())),
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(vitals.status, "degraded");
+        assert!(!vitals.database);
+        assert_eq!(vitals.connections, 0);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_on_shutdown() {
+        let app = TestApp::new();
+
+        let (status, readiness) = app.request(Method::GET, "/readyz", None).await;
+        assert_eq!(status, 200, "{readiness}");
+        assert_eq!(readiness["status"], "ready");
+        assert_eq!(readiness["database"], true);
+        assert!(readiness["schema"].is_null());
+
+        // Once checked, the readiness tells how the database compared
+        let checked = app.pool().check_schema().await.unwrap();
+        let (_, readiness) = app.request(Method::GET, "/readyz", None).await;
+        assert_eq!(readiness["schema"]["tables"], checked.tables);
+        assert_eq!(readiness["schema"]["migration"], checked.migration.unwrap());
+
+        app.shutdown().start();
+        let (status, readiness) = app.request(Method::GET, "/readyz", None).await;
+        assert_eq!(status, 503);
+        assert_eq!(readiness["status"], "shutting_down");
+        let (status, _) = app.request(Method::GET, "/livez", None).await;
+        assert_eq!(status, 200);
+    }
+}
@@ -0,0 +1,305 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{get, put},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    api::{
+        extract::{Validate, ValidatedJson},
+        responses::MessageResponse,
+        state::AppState,
+    },
+    database::{
+        connection::DbPool,
+        models::{
+            sessions::manager::Session,
+            webhooks::{Webhook, WebhookEvent, WebhookInput},
+        },
+    },
+    errors::AppError,
+    jobs::webhooks::WebhookDispatcher,
+    quotas::Quotas,
+};
+
+/// Create or update webhook request body
+#[derive(Debug, Serialize, Deserialize, OpenApi, ToSchema)]
+#[openapi(paths(create_webhook, update_webhook))]
+#[schema(example = json!({"url": "https://hooks.example.com/finance", "secret": "a-secret-of-the-dashboard", "events": ["transaction_created", "category_alert"], "active": true}))]
+pub struct SaveWebhook {
+    /// The HTTPS URL deliveries are posted to
+    url: String,
+    /// The secret deliveries are signed with, between 16 and 128 characters
+    secret: String,
+    /// The events the webhook subscribes to
+    events: Vec<WebhookEvent>,
+    /// Whether deliveries are sent, reactivating a webhook clears its failed deliveries
+    #[serde(default = "default_active")]
+    active: bool,
+}
+
+impl Validate for SaveWebhook {}
+
+fn default_active() -> bool {
+    true
+}
+
+impl SaveWebhook {
+    fn into_input(self) -> WebhookInput {
+        WebhookInput {
+            url: self.url,
+            secret: self.secret,
+            events: self.events,
+            active: self.active,
+        }
+    }
+}
+
+pub fn create_route(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/webhooks", get(all_webhooks).post(create_webhook))
+        .route("/webhooks/:id", put(update_webhook).delete(delete_webhook))
+        .layer(middleware::from_fn_with_state(
+            state,
+            crate::middleware::auth::jwt_auth,
+        ))
+}
+
+/// This endpoint returns all webhooks of the authenticated user
+///
+/// Secrets are never returned.
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a vector of webhooks.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    security(("cookieAuth" = [])),
+    responses((status = 200, description = "Webhooks of the user", body = Vec<Webhook>))
+)]
+async fn all_webhooks(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+) -> Result<Json<Vec<Webhook>>, AppError> {
+    pool.run_read(move |conn| Ok(Json(Webhook::get_all(conn, session.user_id())?)))
+        .await
+}
+
+/// This endpoint creates a webhook for the authenticated user
+///
+/// Each event the webhook subscribes to is posted to its URL as JSON, with the HMAC-SHA256 of
+/// the body keyed with the secret in the `X-FF-Signature` header as `sha256=<hex>`. Failed
+/// deliveries are retried 5 times with exponential backoff, and the webhook is disabled after 3
+/// deliveries in a row fail.
+///
+/// ## Responses
+///
+/// `201` : A successful response. Returns the created webhook.
+/// `400` : The URL isn't HTTPS or points to a private network, the secret is too short or too
+/// long, or no events are given.
+/// `403` : The user has as many webhooks as their quota allows.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    security(("cookieAuth" = [])),
+    request_body = SaveWebhook,
+    responses(
+        (status = 201, description = "Webhook created", body = Webhook),
+        (status = 400, description = "Invalid webhook"),
+        (status = 403, description = "Over the quota of webhooks")
+    )
+)]
+async fn create_webhook(
+    State(pool): State<Arc<DbPool>>,
+    State(quotas): State<Quotas>,
+    Extension(session): Extension<Session>,
+    Extension(webhooks): Extension<Arc<WebhookDispatcher>>,
+    ValidatedJson(payload): ValidatedJson<SaveWebhook>,
+) -> Result<(StatusCode, Json<Webhook>), AppError> {
+    pool.run(move |conn| {
+        let webhook = Webhook::new(
+            conn,
+            session.user_id(),
+            &payload.into_input(),
+            webhooks.allow_insecure(),
+            &quotas,
+        )?;
+
+        Ok((StatusCode::CREATED, Json(webhook)))
+    })
+    .await
+}
+
+/// This endpoint updates a webhook of the authenticated user
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns the updated webhook.
+/// `400` : The URL isn't HTTPS or points to a private network, the secret is too short or too
+/// long, or no events are given.
+/// `404` : The webhook doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    put,
+    path = "/webhooks/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the webhook")),
+    request_body = SaveWebhook,
+    responses(
+        (status = 200, description = "Webhook updated", body = Webhook),
+        (status = 400, description = "Invalid webhook"),
+        (status = 404, description = "Webhook not found")
+    )
+)]
+async fn update_webhook(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Extension(webhooks): Extension<Arc<WebhookDispatcher>>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<SaveWebhook>,
+) -> Result<Json<Webhook>, AppError> {
+    pool.run(move |conn| {
+        let webhook = Webhook::from_id(conn, id, session.user_id())?;
+        let webhook = webhook.update(conn, &payload.into_input(), webhooks.allow_insecure())?;
+
+        Ok(Json(webhook))
+    })
+    .await
+}
+
+/// This endpoint deletes a webhook of the authenticated user
+///
+/// ## Responses
+///
+/// `200` : A successful response. Returns a message telling the webhook was deleted.
+/// `404` : The webhook doesn't exist or belongs to another user.
+/// `default` : An unexpected error occurred. Returns an `AppError`.
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    security(("cookieAuth" = [])),
+    params(("id" = i32, Path, description = "ID of the webhook")),
+    responses(
+        (status = 200, description = "Webhook deleted", body = MessageResponse),
+        (status = 404, description = "Webhook not found")
+    )
+)]
+async fn delete_webhook(
+    State(pool): State<Arc<DbPool>>,
+    Extension(session): Extension<Session>,
+    Path(id): Path<i32>,
+) -> Result<Json<MessageResponse>, AppError> {
+    pool.run(move |conn| {
+        Webhook::from_id(conn, id, session.user_id())?.delete(conn)?;
+
+        Ok(Json(MessageResponse::new("Webhook deleted")))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::Method;
+    use hmac::{Hmac, Mac};
+    use serde_json::{json, Value};
+    use sha2::Sha256;
+
+    use crate::api::test_utils::{endpoint, eventually, TestApp};
+
+    async fn webhook(app: &TestApp) -> Value {
+        let (_, webhooks) = app.request(Method::GET, "/webhooks", None).await;
+        webhooks[0].clone()
+    }
+
+    #[tokio::test]
+    async fn test_signed_delivery_is_retried() {
+        let app = TestApp::new();
+        let (url, received) = endpoint(vec![500, 503], 200).await;
+        let secret = "a-secret-of-the-dashboard";
+
+        let (status, created) = app
+            .request(
+                Method::POST,
+                "/webhooks",
+                Some(json!({"url": url, "secret": secret, "events": ["transaction_created"]})),
+            )
+            .await;
+        assert_eq!(status, 201, "{created}");
+        assert!(created.get("secret").is_none());
+
+        let (_, account) = app
+            .request(
+                Method::POST,
+                "/accounts",
+                Some(json!({"name": "Chequing", "opening_balance": "0.00", "currency": "CAD"})),
+            )
+            .await;
+        app.request(Method::POST, "/plans/Household", None).await;
+        app.request(
+            Method::POST,
+            &format!("/accounts/{}/transactions", account["id"]),
+            Some(json!({"amount": "-60.00", "description": "Groceries", "occurred_at": "2024-07-05"})),
+        )
+        .await;
+
+        // Two refused attempts, then the delivery is accepted
+        eventually(|| async { received.lock().unwrap().len() >= 3 }).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 3);
+
+        for (headers, body) in &received {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+            mac.update(body);
+            let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+            assert_eq!(headers["x-ff-signature"], expected.as_str());
+            assert_eq!(headers["x-ff-event"], "transaction_created");
+            assert_eq!(body, &received[0].1);
+        }
+        let payload: Value = serde_json::from_slice(&received[0].1).unwrap();
+        assert_eq!(payload["event"], "transaction_created");
+        assert_eq!(payload["data"]["description"], "Groceries");
+
+        let webhook = webhook(&app).await;
+        assert_eq!(webhook["active"], true);
+        assert_eq!(webhook["failed_deliveries"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_failing_webhook_is_disabled() {
+        let app = TestApp::new();
+        let (url, received) = endpoint(vec![], 500).await;
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/webhooks",
+                Some(json!({"url": url, "secret": "a-secret-of-the-dashboard", "events": ["plan_created"]})),
+            )
+            .await;
+        assert_eq!(status, 201);
+
+        for (i, name) in ["Household", "Travel", "Renovation"].iter().enumerate() {
+            app.request(Method::POST, &format!("/plans/{name}"), None)
+                .await;
+            // Every delivery is tried once and retried five times
+            eventually(|| async { received.lock().unwrap().len() >= 6 * (i + 1) }).await;
+            eventually(|| async { webhook(&app).await["failed_deliveries"] == i + 1 }).await;
+        }
+        assert_eq!(webhook(&app).await["active"], false);
+
+        app.request(Method::POST, "/plans/Wedding", None).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(received.lock().unwrap().len(), 18);
+    }
+}
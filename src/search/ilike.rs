@@ -0,0 +1,137 @@
+use crate::database::{
+    connection::DbConn,
+    models::{plan_notes::PlanNote, plans::Plan, transactions::Transaction},
+};
+use crate::errors::AppError;
+use crate::search::{SearchGroup, SearchProvider, GROUP_LIMIT};
+
+/// Case-insensitive substring search with `ILIKE`
+pub struct IlikeSearch;
+
+/// Get the `ILIKE` pattern matching text that contains the query
+///
+/// # Arguments
+///
+/// * `query` - The text to search for, wildcards in it are matched literally
+///
+/// # Returns
+///
+/// The pattern
+pub fn like_pattern(query: &str) -> String {
+    let mut pattern = String::with_capacity(query.len() + 2);
+    pattern.push('%');
+    for c in query.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+impl SearchProvider for IlikeSearch {
+    fn transactions(
+        &self,
+        conn: &mut DbConn,
+        user_id: i32,
+        query: &str,
+    ) -> Result<SearchGroup<Transaction>, AppError> {
+        Transaction::matching(conn, user_id, &like_pattern(query), GROUP_LIMIT).map(Into::into)
+    }
+
+    fn plans(
+        &self,
+        conn: &mut DbConn,
+        user_id: i32,
+        query: &str,
+    ) -> Result<SearchGroup<Plan>, AppError> {
+        Plan::matching(conn, user_id, &like_pattern(query), GROUP_LIMIT).map(Into::into)
+    }
+
+    fn notes(
+        &self,
+        conn: &mut DbConn,
+        user_id: i32,
+        query: &str,
+    ) -> Result<SearchGroup<PlanNote>, AppError> {
+        PlanNote::matching(conn, user_id, &like_pattern(query), GROUP_LIMIT).map(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+    use diesel::Connection;
+
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::{Account, AccountKind},
+            transactions::TransactionInput,
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    #[test]
+    fn test_like_pattern() {
+        assert_eq!(like_pattern("coffee"), "%coffee%");
+        assert_eq!(like_pattern("50%_off\\"), "%50\\%\\_off\\\\%");
+    }
+
+    #[test]
+    fn test_search_is_scoped_and_limited() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::default(conn).unwrap();
+        let other = User::new(conn, "other_user", "other_password").unwrap();
+        let occurred_at = NaiveDate::from_ymd_opt(2024, 7, 5).unwrap();
+        for user_id in [user.id(), other.id()] {
+            let account = Account::new(
+                conn,
+                user_id,
+                "Chequing",
+                &BigDecimal::from(0),
+                "CAD",
+                AccountKind::Asset,
+            )
+            .unwrap();
+            for _ in 0..12 {
+                let input = TransactionInput::new(
+                    BigDecimal::from_str("-4.50").unwrap(),
+                    "Morning COFFEE",
+                    occurred_at,
+                );
+                Transaction::new(conn, &account, &input, &Quotas::disabled()).unwrap();
+            }
+            let plan = Plan::new(conn, &format!("Coffee budget {user_id}"), user_id).unwrap();
+            PlanNote::new(conn, &plan, user_id, "Less coffee, more tea").unwrap();
+        }
+
+        let results = IlikeSearch.search(conn, user.id(), " coffee ").unwrap();
+        assert_eq!(results.transactions.total(), 12);
+        assert_eq!(results.transactions.items().len(), GROUP_LIMIT as usize);
+        assert_eq!(results.plans.total(), 1);
+        assert_eq!(
+            results.plans.items()[0].name(),
+            format!("Coffee budget {}", user.id())
+        );
+        assert_eq!(results.notes.total(), 1);
+
+        let results = IlikeSearch.search(conn, user.id(), "tea").unwrap();
+        assert_eq!(results.transactions.total(), 0);
+        assert_eq!(results.notes.total(), 1);
+
+        assert!(matches!(
+            IlikeSearch.search(conn, user.id(), " c "),
+            Err(AppError::InvalidInput(_))
+        ));
+    }
+}
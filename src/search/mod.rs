@@ -0,0 +1,121 @@
+pub mod ilike;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::database::{
+    connection::DbConn,
+    models::{plan_notes::PlanNote, plans::Plan, transactions::Transaction},
+};
+use crate::errors::AppError;
+
+/// Minimum number of characters in a search query
+pub const MIN_QUERY_LENGTH: usize = 2;
+
+/// Maximum number of results returned in each group
+pub const GROUP_LIMIT: i64 = 10;
+
+/// Results of a search in one domain
+#[derive(Debug, Serialize)]
+pub struct SearchGroup<T> {
+    /// Number of matches, which can be more than the results returned
+    total: i64,
+    /// Up to `GROUP_LIMIT` of the matches
+    items: Vec<T>,
+}
+
+impl<T> From<(i64, Vec<T>)> for SearchGroup<T> {
+    fn from((total, items): (i64, Vec<T>)) -> Self {
+        Self { total, items }
+    }
+}
+
+impl<T> SearchGroup<T> {
+    /// Get the number of matches
+    #[cfg(test)]
+    pub fn total(&self) -> i64 {
+        self.total
+    }
+
+    /// Get the matches returned
+    #[cfg(test)]
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+}
+
+/// Results of a search, grouped by domain
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResults {
+    /// Transactions whose description or payee matches
+    #[schema(value_type = Object)]
+    pub transactions: SearchGroup<Transaction>,
+    /// Plans whose name matches
+    #[schema(value_type = Object)]
+    pub plans: SearchGroup<Plan>,
+    /// Notes on plans whose body matches
+    #[schema(value_type = Object)]
+    pub notes: SearchGroup<PlanNote>,
+}
+
+/// A way of finding the records of a user matching a query
+///
+/// Each group is searched on its own so a provider backed by full-text search can replace the
+/// `ILIKE` one without changing the endpoint.
+pub trait SearchProvider {
+    /// Find the transactions of a user whose description or payee matches the query
+    fn transactions(
+        &self,
+        conn: &mut DbConn,
+        user_id: i32,
+        query: &str,
+    ) -> Result<SearchGroup<Transaction>, AppError>;
+
+    /// Find the plans of a user whose name matches the query
+    fn plans(
+        &self,
+        conn: &mut DbConn,
+        user_id: i32,
+        query: &str,
+    ) -> Result<SearchGroup<Plan>, AppError>;
+
+    /// Find the notes on the plans of a user whose body matches the query
+    fn notes(
+        &self,
+        conn: &mut DbConn,
+        user_id: i32,
+        query: &str,
+    ) -> Result<SearchGroup<PlanNote>, AppError>;
+
+    /// Search every group for the records of a user matching the query
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection to the database
+    /// * `user_id` - User ID
+    /// * `query` - The text to search for
+    ///
+    /// # Returns
+    ///
+    /// The grouped results, or `AppError::InvalidInput` if the query is shorter than
+    /// `MIN_QUERY_LENGTH` characters
+    fn search(
+        &self,
+        conn: &mut DbConn,
+        user_id: i32,
+        query: &str,
+    ) -> Result<SearchResults, AppError> {
+        let query = query.trim();
+        if query.chars().count() < MIN_QUERY_LENGTH {
+            return Err(AppError::InvalidInput(format!(
+                "A search query must be at least {MIN_QUERY_LENGTH} characters long"
+            )));
+        }
+
+        Ok(SearchResults {
+            transactions: self.transactions(conn, user_id, query)?,
+            plans: self.plans(conn, user_id, query)?,
+            notes: self.notes(conn, user_id, query)?,
+        })
+    }
+}
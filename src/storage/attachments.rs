@@ -0,0 +1,109 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::errors::AppError;
+
+/// Stores the files attached to transactions in the `attachments` directory of the data directory
+///
+/// Files are named after their transaction and the time they were stored, never after the name
+/// they were uploaded with.
+#[derive(Debug, Clone)]
+pub struct AttachmentStore {
+    /// Directory holding the files
+    dir: PathBuf,
+}
+
+impl AttachmentStore {
+    /// Create a store in a data directory, the directory is created when the first file is stored
+    ///
+    /// # Arguments
+    ///
+    /// * `data_dir` - Directory in which the server stores its data
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("attachments"),
+        }
+    }
+
+    /// Store a file attached to a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_id` - ID of the transaction the file is attached to
+    /// * `bytes` - Contents of the file
+    ///
+    /// # Returns
+    ///
+    /// The path of the stored file relative to the attachments directory
+    pub async fn write(&self, transaction_id: i32, bytes: &[u8]) -> Result<String, AppError> {
+        fs::create_dir_all(&self.dir).await?;
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let storage_path = format!("{transaction_id}-{nanos:x}");
+
+        // Never overwrite the file of another attachment
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.path(&storage_path))
+            .await?;
+        file.write_all(bytes).await?;
+        file.flush().await?;
+
+        Ok(storage_path)
+    }
+
+    /// Get the full path of a stored file
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_path` - Path of the file relative to the attachments directory
+    pub fn path(&self, storage_path: &str) -> PathBuf {
+        self.dir.join(storage_path)
+    }
+
+    /// List the stored files, with when each was last written
+    ///
+    /// # Returns
+    ///
+    /// The paths of the files relative to the attachments directory, none if no file was stored
+    /// yet
+    pub async fn files(&self) -> Result<Vec<(String, SystemTime)>, AppError> {
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if let (true, Some(name)) = (metadata.is_file(), entry.file_name().to_str()) {
+                files.push((name.to_string(), metadata.modified()?));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Remove a stored file, files that are already gone are ignored
+    ///
+    /// Failures are logged rather than returned, as the attachment is already deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_path` - Path of the file relative to the attachments directory
+    pub async fn remove(&self, storage_path: &str) {
+        match fs::remove_file(self.path(storage_path)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                tracing::error!("Failed removing attachment file {storage_path} ({e})");
+            }
+            _ => {}
+        }
+    }
+}
@@ -1 +1,3 @@
-pub mod serialization;
\ No newline at end of file
+pub mod money;
+pub mod sensitive;
+pub mod serialization;
@@ -0,0 +1,388 @@
+use std::fmt;
+use std::ops::Neg;
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, RoundingMode, Zero};
+use diesel::{
+    deserialize::Queryable,
+    expression::AsExpression,
+    pg::Pg,
+    serialize::{Output, ToSql},
+    sql_types::{Numeric, Text},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use utoipa::ToSchema;
+
+use crate::errors::AppError;
+
+/// Currencies without minor units, e.g. yens aren't divided
+const NO_MINOR_UNITS: [&str; 17] = [
+    "BIF", "CLP", "DJF", "GNF", "ISK", "JPY", "KMF", "KRW", "PYG", "RWF", "UGX", "UYI", "VND",
+    "VUV", "XAF", "XOF", "XPF",
+];
+
+/// Currencies with three decimals of minor units, e.g. a Bahraini dinar is 1000 fils
+const THREE_MINOR_UNITS: [&str; 7] = ["BHD", "IQD", "JOD", "KWD", "LYD", "OMR", "TND"];
+
+/// Why an amount of money couldn't be created or computed
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum MoneyError {
+    #[error("\"{0}\" is not an ISO 4217 currency code")]
+    InvalidCurrency(String),
+
+    #[error("\"{0}\" is not a decimal amount")]
+    InvalidAmount(String),
+
+    #[error("Can't combine amounts in {0} and {1}")]
+    MixedCurrencies(String, String),
+}
+
+impl From<MoneyError> for AppError {
+    fn from(e: MoneyError) -> Self {
+        AppError::InvalidInput(e.to_string())
+    }
+}
+
+/// Get the number of decimals of the minor units of a currency, e.g. 2 for cents
+///
+/// # Arguments
+///
+/// * `currency` - ISO 4217 code of the currency
+pub fn minor_units(currency: &str) -> i64 {
+    if NO_MINOR_UNITS.contains(&currency) {
+        0
+    } else if THREE_MINOR_UNITS.contains(&currency) {
+        3
+    } else {
+        2
+    }
+}
+
+/// An amount of money in a currency
+///
+/// Amounts are decimal, never floats, and amounts in different currencies can't be added or
+/// subtracted by mistake. It is serialized as `{"amount": "12.34", "currency": "CAD"}`.
+///
+/// In the database, the amount is a `NUMERIC` column and the currency a column of its own, so it
+/// is written as its amount and read from both columns.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, AsExpression, ToSchema)]
+#[diesel(sql_type = Numeric)]
+pub struct Money {
+    /// The amount, as a decimal string
+    #[schema(value_type = String, example = "12.34")]
+    amount: BigDecimal,
+    /// ISO 4217 code of the currency of the amount
+    #[schema(example = "CAD")]
+    currency: String,
+}
+
+impl Money {
+    /// Create an amount of money
+    ///
+    /// # Arguments
+    ///
+    /// * `amount` - The amount, kept as is, see `Money::round`
+    /// * `currency` - ISO 4217 code of the currency of the amount
+    ///
+    /// # Returns
+    ///
+    /// The money, or `MoneyError::InvalidCurrency` if the code isn't three uppercase letters
+    pub fn new(amount: BigDecimal, currency: &str) -> Result<Self, MoneyError> {
+        if currency.len() != 3 || !currency.bytes().all(|b| b.is_ascii_uppercase()) {
+            return Err(MoneyError::InvalidCurrency(currency.to_string()));
+        }
+        Ok(Self {
+            amount,
+            currency: currency.to_string(),
+        })
+    }
+
+    /// Create no money in a currency, to add amounts up from
+    pub fn zero(currency: &str) -> Result<Self, MoneyError> {
+        Self::new(BigDecimal::zero(), currency)
+    }
+
+    /// Get the amount
+    pub fn amount(&self) -> &BigDecimal {
+        &self.amount
+    }
+
+    /// Get the ISO 4217 code of the currency
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// Get the amount, dropping the currency
+    pub fn into_amount(self) -> BigDecimal {
+        self.amount
+    }
+
+    /// Whether the amount is below zero
+    pub fn is_negative(&self) -> bool {
+        self.amount < BigDecimal::zero()
+    }
+
+    /// Get the amount without its sign
+    pub fn abs(&self) -> Self {
+        Self {
+            amount: self.amount.abs(),
+            currency: self.currency.clone(),
+        }
+    }
+
+    /// Round the amount to the minor units of its currency, halves away from zero
+    ///
+    /// Cents for most currencies, e.g. `12.345 CAD` is `12.35 CAD`, but whole yens for `JPY` and
+    /// thousandths for `BHD`.
+    pub fn round(&self) -> Self {
+        Self {
+            amount: self
+                .amount
+                .with_scale_round(minor_units(&self.currency), RoundingMode::HalfUp),
+            currency: self.currency.clone(),
+        }
+    }
+
+    /// Add an amount in the same currency
+    ///
+    /// # Returns
+    ///
+    /// The sum, or `MoneyError::MixedCurrencies` if the currencies differ
+    pub fn checked_add(&self, other: &Money) -> Result<Self, MoneyError> {
+        self.same_currency(other)?;
+        Ok(Self {
+            amount: &self.amount + &other.amount,
+            currency: self.currency.clone(),
+        })
+    }
+
+    /// Subtract an amount in the same currency
+    ///
+    /// # Returns
+    ///
+    /// The difference, or `MoneyError::MixedCurrencies` if the currencies differ
+    pub fn checked_sub(&self, other: &Money) -> Result<Self, MoneyError> {
+        self.same_currency(other)?;
+        Ok(Self {
+            amount: &self.amount - &other.amount,
+            currency: self.currency.clone(),
+        })
+    }
+
+    /// Get another amount in the same currency, e.g. once converted into it
+    pub fn with_amount(&self, amount: BigDecimal) -> Self {
+        Self {
+            amount,
+            currency: self.currency.clone(),
+        }
+    }
+
+    fn same_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        if self.currency == other.currency {
+            Ok(())
+        } else {
+            Err(MoneyError::MixedCurrencies(
+                self.currency.clone(),
+                other.currency.clone(),
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money {
+            amount: -self.amount,
+            currency: self.currency,
+        }
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Fields<'a> {
+            amount: String,
+            currency: &'a str,
+        }
+
+        Fields {
+            amount: self.amount.to_string(),
+            currency: &self.currency,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    /// Amounts must be strings, numbers are rejected as they may have been floats
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Fields {
+            amount: String,
+            currency: String,
+        }
+
+        let Fields { amount, currency } = Fields::deserialize(deserializer)?;
+        let amount = BigDecimal::from_str(amount.trim())
+            .map_err(|_| serde::de::Error::custom(MoneyError::InvalidAmount(amount)))?;
+        Money::new(amount, &currency).map_err(serde::de::Error::custom)
+    }
+}
+
+impl ToSql<Numeric, Pg> for Money {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> diesel::serialize::Result {
+        <BigDecimal as ToSql<Numeric, Pg>>::to_sql(&self.amount, out)
+    }
+}
+
+impl Queryable<(Numeric, Text), Pg> for Money {
+    type Row = (BigDecimal, String);
+
+    fn build((amount, currency): Self::Row) -> diesel::deserialize::Result<Self> {
+        Ok(Money::new(amount, &currency)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{dsl::sql, IntoSql, RunQueryDsl};
+    use serde_json::json;
+
+    use super::*;
+    use crate::database::connection::DbPool;
+
+    fn money(amount: &str, currency: &str) -> Money {
+        Money::new(BigDecimal::from_str(amount).unwrap(), currency).unwrap()
+    }
+
+    #[test]
+    fn test_rounding() {
+        // Halves are rounded away from zero, to the minor units of the currency
+        for (amount, currency, rounded) in [
+            ("12.345", "CAD", "12.35"),
+            ("12.344", "CAD", "12.34"),
+            ("-12.345", "CAD", "-12.35"),
+            ("12", "CAD", "12.00"),
+            ("1234.5", "JPY", "1235"),
+            ("-1234.4", "JPY", "-1234"),
+            ("1.2345", "BHD", "1.235"),
+            ("-1.2344", "KWD", "-1.234"),
+            ("0.005", "USD", "0.01"),
+        ] {
+            let money = money(amount, currency).round();
+            assert_eq!(money.amount().to_string(), rounded, "{amount} {currency}");
+            assert_eq!(money.currency(), currency);
+        }
+        assert_eq!(minor_units("EUR"), 2);
+        assert_eq!(minor_units("JPY"), 0);
+        assert_eq!(minor_units("BHD"), 3);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let sum = money("10.50", "CAD")
+            .checked_add(&money("-20.75", "CAD"))
+            .unwrap();
+        assert_eq!(sum, money("-10.25", "CAD"));
+        assert!(sum.is_negative());
+        assert_eq!(sum.abs(), money("10.25", "CAD"));
+        assert_eq!(-sum.clone(), money("10.25", "CAD"));
+        assert_eq!(
+            sum.checked_sub(&money("-10.25", "CAD")).unwrap(),
+            Money::zero("CAD").unwrap()
+        );
+        assert_eq!(
+            Money::zero("USD")
+                .unwrap()
+                .with_amount(BigDecimal::from_str("13.567").unwrap()),
+            money("13.567", "USD")
+        );
+        assert_eq!(money("-3.5", "EUR").to_string(), "-3.5 EUR");
+    }
+
+    #[test]
+    fn test_mixed_currencies() {
+        let cad = money("10.00", "CAD");
+        let usd = money("10.00", "USD");
+        assert_eq!(
+            cad.checked_add(&usd),
+            Err(MoneyError::MixedCurrencies("CAD".into(), "USD".into()))
+        );
+        assert_eq!(
+            usd.checked_sub(&cad),
+            Err(MoneyError::MixedCurrencies("USD".into(), "CAD".into()))
+        );
+        assert_eq!(
+            cad.checked_add(&usd).unwrap_err().to_string(),
+            "Can't combine amounts in CAD and USD"
+        );
+
+        for currency in ["cad", "CA", "CADD", "C4D", ""] {
+            assert_eq!(
+                Money::zero(currency),
+                Err(MoneyError::InvalidCurrency(currency.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_serde() {
+        let value = money("-12.30", "CAD");
+        let json = serde_json::to_value(&value).unwrap();
+        assert_eq!(json, json!({"amount": "-12.30", "currency": "CAD"}));
+        assert_eq!(serde_json::from_value::<Money>(json).unwrap(), value);
+
+        let jpy: Money = serde_json::from_str(r#"{"amount": "1500", "currency": "JPY"}"#).unwrap();
+        assert_eq!(jpy, money("1500", "JPY"));
+
+        // Floats, amounts that aren't decimals and invalid codes are rejected
+        for body in [
+            json!({"amount": 12.34, "currency": "CAD"}),
+            json!({"amount": "twelve", "currency": "CAD"}),
+            json!({"amount": "12.34", "currency": "cad"}),
+            json!({"amount": "12.34"}),
+        ] {
+            assert!(
+                serde_json::from_value::<Money>(body.clone()).is_err(),
+                "{body}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sql() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+
+        let value = money("-1234.567", "BHD");
+        let amount: BigDecimal = diesel::select(value.clone().into_sql::<Numeric>())
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(amount, *value.amount());
+
+        let read: Money = diesel::select((sql::<Numeric>("-1234.567"), sql::<Text>("'BHD'")))
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(read, value);
+
+        // Currencies of the database are checked too
+        let read =
+            diesel::select((sql::<Numeric>("1"), sql::<Text>("'bhd'"))).get_result::<Money>(conn);
+        assert!(read.is_err());
+    }
+}
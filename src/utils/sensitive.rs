@@ -0,0 +1,55 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A value of a request, e.g. a password, printed as `***` so it never ends up in logs
+///
+/// It is serialized as the value it wraps, so the bodies of the API are unchanged.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Sensitive<T>(T);
+
+impl<T> Sensitive<T> {
+    /// Wrap a value
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Get the wrapped value, to use it rather than print it
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> fmt::Debug for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"***\"")
+    }
+}
+
+impl<T> fmt::Display for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sensitive() {
+        let password = Sensitive::new("hunter22".to_string());
+        assert_eq!(format!("{password:?}"), "\"***\"");
+        assert_eq!(password.to_string(), "***");
+        assert_eq!(password.expose(), "hunter22");
+
+        // The wire format is the wrapped value
+        let json = serde_json::to_string(&password).unwrap();
+        assert_eq!(json, "\"hunter22\"");
+        assert_eq!(
+            serde_json::from_str::<Sensitive<String>>(&json).unwrap(),
+            password
+        );
+    }
+}
@@ -1,7 +1,31 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime, SecondsFormat};
 use serde::{Deserialize, Deserializer, Serializer};
 
-/// Serialize NaiveDateTime as an ISO8601 string
+/// Format timestamps were serialized with before RFC 3339, still accepted for one release
+const LEGACY_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Format a NaiveDateTime, which is stored in UTC, as an RFC 3339 string, e.g.
+/// `2024-06-01T12:30:00Z`
+pub fn format(value: &NaiveDateTime) -> String {
+    value.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Parse a NaiveDateTime in UTC from an RFC 3339 string, or from the legacy format, e.g.
+/// `2024-06-01 12:30:00`, which is taken as UTC
+///
+/// # Returns
+///
+/// The parsed NaiveDateTime, or a message telling the expected format
+pub fn parse(value: &str) -> Result<NaiveDateTime, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|value| value.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, LEGACY_FORMAT))
+        .map_err(|_| {
+            format!("invalid timestamp `{value}`, expected RFC 3339, e.g. `2024-06-01T12:30:00Z`")
+        })
+}
+
+/// Serialize NaiveDateTime as an RFC 3339 string in UTC
 ///
 /// # Arguments
 ///
@@ -10,51 +34,122 @@ use serde::{Deserialize, Deserializer, Serializer};
 ///
 /// # Returns
 ///
-/// The serialized NaiveDateTime as an ISO8601 string
+/// The serialized NaiveDateTime as an RFC 3339 string in UTC
 pub fn serialize<S>(value: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let s = value.format("%Y-%m-%d %H:%M:%S").to_string();
-    serializer.serialize_str(&s)
+    serializer.serialize_str(&format(value))
 }
 
-// Deserialize NaiveDateTime from an ISO8601 string
+/// Deserialize NaiveDateTime from an RFC 3339 string, or from the legacy format
 pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S").map_err(serde::de::Error::custom)
+    parse(&s).map_err(serde::de::Error::custom)
+}
+
+/// The same for optional timestamps, serialized as null when there is none
+pub mod option {
+    use chrono::NaiveDateTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Serialize an optional NaiveDateTime as an RFC 3339 string in UTC, or null
+    pub fn serialize<S>(value: &Option<NaiveDateTime>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(value) => super::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Deserialize an optional NaiveDateTime from an RFC 3339 string, the legacy format, or null
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| super::parse(&s).map_err(serde::de::Error::custom))
+            .transpose()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use chrono::NaiveDate;
+    use serde::Serialize;
 
-    #[test]
-    fn test_serialize() {
-        let date = NaiveDate::from_ymd_opt(2021, 1, 1)
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Stamped {
+        #[serde(with = "crate::utils::serialization")]
+        at: NaiveDateTime,
+        #[serde(default, with = "crate::utils::serialization::option")]
+        until: Option<NaiveDateTime>,
+    }
+
+    fn date() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2021, 1, 1)
             .unwrap()
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-        let mut buf = Vec::new();
-        serialize(&date, &mut serde_json::Serializer::new(&mut buf)).unwrap();
-        let serialized = String::from_utf8(buf).unwrap();
-        assert_eq!(serialized, "\"2021-01-01 00:00:00\"");
+            .and_hms_opt(13, 5, 9)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let stamped = Stamped {
+            at: date(),
+            until: Some(date() + chrono::Duration::days(1)),
+        };
+        let json = serde_json::to_value(&stamped).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"at": "2021-01-01T13:05:09Z", "until": "2021-01-02T13:05:09Z"})
+        );
+        assert_eq!(serde_json::from_value::<Stamped>(json).unwrap(), stamped);
+    }
+
+    #[test]
+    fn test_option() {
+        let stamped = Stamped {
+            at: date(),
+            until: None,
+        };
+        let json = serde_json::to_value(&stamped).unwrap();
+        assert_eq!(json["until"], serde_json::Value::Null);
+        assert_eq!(serde_json::from_value::<Stamped>(json).unwrap(), stamped);
+
+        // A missing timestamp is none
+        let stamped: Stamped = serde_json::from_str(r#"{"at": "2021-01-01T13:05:09Z"}"#).unwrap();
+        assert_eq!(stamped.until, None);
     }
 
     #[test]
     fn test_deserialize() {
-        let date = NaiveDate::from_ymd_opt(2021, 1, 1)
-            .unwrap()
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-        let deserialized = deserialize(&mut serde_json::Deserializer::from_str(
-            "\"2021-01-01 00:00:00\"",
-        ))
+        // Offsets are converted to UTC
+        let stamped: Stamped = serde_json::from_str(
+            r#"{"at": "2021-01-01T15:05:09+02:00", "until": "2021-01-01T13:05:09.250Z"}"#,
+        )
         .unwrap();
-        assert_eq!(deserialized, date);
+        assert_eq!(stamped.at, date());
+        assert_eq!(
+            stamped.until,
+            Some(date() + chrono::Duration::milliseconds(250))
+        );
+
+        // The legacy format is still accepted, as UTC
+        let stamped: Stamped = serde_json::from_str(
+            r#"{"at": "2021-01-01 13:05:09", "until": "2021-01-01 13:05:09"}"#,
+        )
+        .unwrap();
+        assert_eq!(stamped.at, date());
+        assert_eq!(stamped.until, Some(date()));
+
+        let error = serde_json::from_str::<Stamped>(r#"{"at": "01/01/2021"}"#).unwrap_err();
+        assert!(error.to_string().contains("expected RFC 3339"), "{error}");
     }
 }
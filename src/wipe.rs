@@ -0,0 +1,272 @@
+use chrono::NaiveDate;
+use diesel::Connection;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::audit;
+use crate::database::{
+    connection::DbConn,
+    models::{
+        accounts::Account,
+        attachments::Attachment,
+        audit_events::{AuditEventKind, NewAuditEvent},
+        plans::Plan,
+        reconciliations::Reconciliation,
+        tags::Tag,
+        transactions::Transaction,
+    },
+};
+use crate::errors::AppError;
+
+/// The transactions a wipe deleted, or would delete on a dry run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct TransactionWipe {
+    /// Transactions, including the other legs of transfers
+    pub transactions: u64,
+    /// Splits of the transactions
+    pub splits: u64,
+    /// Tags on the transactions, a tag counting once per transaction it is on
+    pub tags: u64,
+    /// Files attached to the transactions
+    pub attachments: u64,
+}
+
+/// The data of a user a wipe deleted
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DataWipe {
+    /// Accounts, with their recurring transactions and reconciliations
+    pub accounts: u64,
+    /// Plans, with their budgets and notes
+    pub plans: u64,
+    /// The transactions on the accounts
+    #[serde(flatten)]
+    pub transactions: TransactionWipe,
+}
+
+/// Count what deleting some transactions removes, refusing if any of them is reconciled
+///
+/// # Returns
+///
+/// The counts and the attachments of the transactions, whose files go with them
+fn count(conn: &mut DbConn, ids: &[i32]) -> Result<(TransactionWipe, Vec<Attachment>), AppError> {
+    Reconciliation::ensure_unlocked(conn, ids)?;
+    let attachments = Attachment::of_transactions(conn, ids)?;
+    let counts = TransactionWipe {
+        transactions: ids.len() as u64,
+        splits: Transaction::count_splits(conn, ids)?,
+        tags: Tag::count_on(conn, ids)?,
+        attachments: attachments.len() as u64,
+    };
+    Ok((counts, attachments))
+}
+
+/// Delete the transactions of an account that occurred before a day
+///
+/// Transfers are deleted whole, so the other legs of the transfers among them go too. Nothing is
+/// deleted if any of the transactions was cleared in a finished reconciliation. A wipe that
+/// deletes something is recorded as an audit event with its counts.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `account` - The account
+/// * `before` - The first day whose transactions are kept
+/// * `dry_run` - Whether to only count what would be deleted
+///
+/// # Returns
+///
+/// What was deleted and the attachments whose files are left for the caller to remove, no
+/// attachments on a dry run
+pub fn wipe_transactions(
+    conn: &mut DbConn,
+    account: &Account,
+    before: NaiveDate,
+    dry_run: bool,
+) -> Result<(TransactionWipe, Vec<Attachment>), AppError> {
+    conn.transaction(|conn| {
+        let ids = Transaction::ids_before(conn, account, before)?;
+        let (counts, attachments) = count(conn, &ids)?;
+        if dry_run {
+            return Ok((counts, vec![]));
+        }
+
+        Transaction::delete_all(conn, &ids)?;
+        if counts.transactions > 0 {
+            audit::record(
+                conn,
+                NewAuditEvent::new(
+                    AuditEventKind::TransactionsWiped,
+                    Some(account.user_id()),
+                    serde_json::json!({
+                        "account_id": account.id(),
+                        "before": before,
+                        "counts": counts,
+                    }),
+                ),
+            );
+        }
+        Ok((counts, attachments))
+    })
+}
+
+/// Delete the accounts, transactions and plans of a user, keeping the user and their settings
+///
+/// Nothing is deleted if any of the transactions was cleared in a finished reconciliation. The
+/// wipe is recorded as an audit event with its counts.
+///
+/// # Arguments
+///
+/// * `conn` - Connection to the database
+/// * `user_id` - User ID
+///
+/// # Returns
+///
+/// What was deleted and the attachments whose files are left for the caller to remove
+pub fn wipe_data(conn: &mut DbConn, user_id: i32) -> Result<(DataWipe, Vec<Attachment>), AppError> {
+    conn.transaction(|conn| {
+        let ids = Transaction::ids_of_user(conn, user_id)?;
+        let (transactions, attachments) = count(conn, &ids)?;
+
+        Transaction::delete_all(conn, &ids)?;
+        let counts = DataWipe {
+            accounts: Account::delete_all_of_user(conn, user_id)?,
+            plans: Plan::delete_all_of_user(conn, user_id)?,
+            transactions,
+        };
+        audit::record(
+            conn,
+            NewAuditEvent::new(
+                AuditEventKind::DataWiped,
+                Some(user_id),
+                serde_json::json!({ "counts": counts }),
+            ),
+        );
+        Ok((counts, attachments))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        connection::DbPool,
+        models::{
+            accounts::AccountKind,
+            audit_events::{AuditEvent, AuditFilter},
+            categories::Category,
+            transactions::{SplitInput, TransactionInput},
+            transfers::{Transfer, TransferInput},
+            users::User,
+        },
+    };
+    use crate::quotas::Quotas;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn account(conn: &mut DbConn, user_id: i32, name: &str) -> Account {
+        Account::new(
+            conn,
+            user_id,
+            name,
+            &"0.00".parse().unwrap(),
+            "CAD",
+            AccountKind::Asset,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_wipe_transactions() {
+        let pool = DbPool::new_test();
+        let conn = &mut pool.get().unwrap();
+        conn.begin_test_transaction().unwrap();
+
+        let user = User::new(conn, "wipe_transactions", "password").unwrap();
+        let chequing = account(conn, user.id(), "Chequing");
+        let savings = account(conn, user.id(), "Savings");
+        let groceries = Category::new(conn, user.id(), "Groceries").unwrap();
+        let household = Category::new(conn, user.id(), "Household").unwrap();
+
+        let mut split =
+            TransactionInput::new("-50.00".parse().unwrap(), "Market", date(2024, 1, 5));
+        split.splits = vec![
+            SplitInput {
+                category_id: groceries.id(),
+                amount: "-30.00".parse().unwrap(),
+            },
+            SplitInput {
+                category_id: household.id(),
+                amount: "-20.00".parse().unwrap(),
+            },
+        ];
+        let split = Transaction::new(conn, &chequing, &split, &Quotas::disabled()).unwrap();
+        let tags =
+            Tag::find_or_create(conn, user.id(), &["food".to_string(), "weekly".to_string()])
+                .unwrap();
+        Tag::tag(conn, &split, &tags).unwrap();
+        Attachment::new(
+            conn,
+            &split,
+            "receipt.pdf",
+            "application/pdf",
+            1024,
+            "wipe-receipt",
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        Transfer::new(
+            conn,
+            &chequing,
+            &savings,
+            &TransferInput {
+                amount: "100.00".parse().unwrap(),
+                description: "Savings".to_string(),
+                occurred_at: date(2024, 1, 20),
+            },
+            &Quotas::disabled(),
+        )
+        .unwrap();
+        // Kept, it occurred on the day of the wipe
+        let kept = TransactionInput::new("-5.00".parse().unwrap(), "Coffee", date(2024, 2, 1));
+        Transaction::new(conn, &chequing, &kept, &Quotas::disabled()).unwrap();
+
+        let before = date(2024, 2, 1);
+        let (dry_run, attachments) = wipe_transactions(conn, &chequing, before, true).unwrap();
+        assert_eq!(
+            dry_run,
+            TransactionWipe {
+                transactions: 3,
+                splits: 2,
+                tags: 2,
+                attachments: 1,
+            }
+        );
+        assert!(attachments.is_empty());
+        assert_eq!(Transaction::count_of_user(conn, user.id()).unwrap(), 4);
+
+        let (wiped, attachments) = wipe_transactions(conn, &chequing, before, false).unwrap();
+        assert_eq!(wiped, dry_run);
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(Transaction::count_of_user(conn, user.id()).unwrap(), 1);
+
+        let events = AuditEvent::get_page(
+            conn,
+            &AuditFilter {
+                user_id: Some(user.id()),
+                event: Some(AuditEventKind::TransactionsWiped),
+                ..Default::default()
+            },
+            10,
+        )
+        .unwrap();
+        assert_eq!(events.len(), 1);
+        let event = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(event["metadata"]["counts"]["transactions"], 3);
+
+        // Nothing is left to wipe
+        let (wiped, _) = wipe_transactions(conn, &chequing, before, false).unwrap();
+        assert_eq!(wiped, TransactionWipe::default());
+    }
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::{factories, TestClient};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_login_authenticates_following_requests() {
+    let client = TestClient::new();
+    factories::user(&client, "alice");
+
+    let response = client.login("alice", factories::PASSWORD).await;
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json(), json!({"message": "Login successful"}));
+    assert!(client.cookie("token").is_some());
+
+    let response = client.get("/api/v1/plans").await;
+    assert_eq!(response.status, 200, "{:?}", response.body);
+}
+
+#[tokio::test]
+async fn test_logout_revokes_the_session() {
+    let client = TestClient::new();
+    factories::logged_in_user(&client, "alice").await;
+
+    let response = client.get("/api/v1/auth/logout").await;
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json(), json!({"message": "Logged out"}));
+
+    // The cookie is still sent, but its session is over
+    let response = client.get("/api/v1/plans").await;
+    assert_eq!(response.status, 401);
+}
+
+#[tokio::test]
+async fn test_wrong_credentials() {
+    let client = TestClient::new();
+    factories::user(&client, "alice");
+
+    let response = client.login("alice", "wrong_password").await;
+    assert_eq!(response.status, 401);
+    assert_eq!(response.code(), 40004);
+    assert!(client.cookie("token").is_none());
+
+    // Unknown users are refused the same way
+    let response = client.login("bob", factories::PASSWORD).await;
+    assert_eq!(response.status, 401);
+    assert_eq!(response.code(), 40004);
+    assert!(client.cookie("token").is_none());
+}
+
+#[tokio::test]
+async fn test_protected_routes_need_a_session() {
+    let client = TestClient::new();
+
+    let response = client.get("/api/v1/plans").await;
+    assert_eq!(response.status, 401);
+    assert_eq!(response.code(), 40005);
+    let response = client.get("/api/v1/auth/logout").await;
+    assert_eq!(response.status, 401);
+
+    client.set_cookie("token", "invalid");
+    let response = client.get("/api/v1/plans").await;
+    assert_eq!(response.status, 401);
+    assert_eq!(response.code(), 40005);
+}
@@ -0,0 +1,28 @@
+//! Data of the tests, created in the test database of a client rather than through the routes
+
+use finance_fusion_server::database::models::{plans::Plan, users::User};
+
+use super::TestClient;
+
+/// Password of the users created by the factories
+pub const PASSWORD: &str = "factory_password";
+
+/// Create a user with `PASSWORD`
+pub fn user(client: &TestClient, username: &str) -> User {
+    let mut conn = client.pool().get().unwrap();
+    User::new(&mut conn, username, PASSWORD).unwrap()
+}
+
+/// Create a user with `PASSWORD`, and log the client in as them
+pub async fn logged_in_user(client: &TestClient, username: &str) -> User {
+    let user = user(client, username);
+    let response = client.login(username, PASSWORD).await;
+    assert_eq!(response.status, 200, "{:?}", response.body);
+    user
+}
+
+/// Create a plan of a user
+pub fn plan(client: &TestClient, user: &User, name: &str) -> Plan {
+    let mut conn = client.pool().get().unwrap();
+    Plan::new(&mut conn, name, user.id()).unwrap()
+}
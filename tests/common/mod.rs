@@ -0,0 +1,233 @@
+//! Harness of the integration tests, serving the whole application against the test database
+//!
+//! Each `TestClient` has a pool of its own, holding a single connection that never leaves its test
+//! transaction, so tests see none of the data of the others and nothing is committed.
+
+// Every test binary includes the harness, but none uses all of it
+#![allow(dead_code)]
+
+pub mod factories;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use tower::ServiceExt;
+
+use finance_fusion_server::api::api::{app, HttpConfig, LiveConfig};
+use finance_fusion_server::api::state::AppState;
+use finance_fusion_server::clock::SystemClock;
+use finance_fusion_server::config::startup::Startup;
+use finance_fusion_server::database::connection::DbPool;
+use finance_fusion_server::database::models::sessions::keys::JwtKeys;
+use finance_fusion_server::events::EventBus;
+use finance_fusion_server::jobs::webhooks::{WebhookConfig, WebhookDispatcher};
+use finance_fusion_server::maintenance::Maintenance;
+use finance_fusion_server::metrics::Metrics;
+use finance_fusion_server::quotas::Quotas;
+use finance_fusion_server::rate_limit::RateLimitConfig;
+use finance_fusion_server::reports::cache::ReportCache;
+use finance_fusion_server::routes::vitals::Shutdown;
+use finance_fusion_server::storage::attachments::AttachmentStore;
+
+/// Number of clients created by this process, to give each its own data directory
+static CLIENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A client of the application backed by a test database, keeping the cookies it is sent
+///
+/// Requests go through every layer of the application, as they would on a listener. Cookies set
+/// by responses are sent with the following requests, so logging in authenticates the client.
+pub struct TestClient {
+    app: Router,
+    pool: Arc<DbPool>,
+    data_dir: PathBuf,
+    cookies: Mutex<BTreeMap<String, String>>,
+}
+
+impl TestClient {
+    /// Create the application without rate limits, with no cookies
+    pub fn new() -> Self {
+        let pool = Arc::new(DbPool::new_test_shared());
+        let data_dir = std::env::temp_dir().join(format!(
+            "finance-fusion-integration-{}-{}",
+            std::process::id(),
+            CLIENT_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let webhooks = WebhookDispatcher::new(WebhookConfig {
+            allow_insecure: true,
+        });
+        let http = HttpConfig {
+            rate: RateLimitConfig {
+                enabled: false,
+                ..RateLimitConfig::default()
+            },
+            ..HttpConfig::default()
+        };
+        let state = AppState {
+            pool: pool.clone(),
+            live: Arc::new(LiveConfig::new(&http)),
+            config: http,
+            jwt_keys: Arc::new(JwtKeys::from_secret(b"test-secret")),
+            events: Arc::new(EventBus::new()),
+            reports: Arc::new(ReportCache::default()),
+            quotas: Quotas::default(),
+            clock: Arc::new(SystemClock),
+        };
+
+        Self {
+            app: app(
+                state,
+                Arc::new(AttachmentStore::new(&data_dir)),
+                Arc::new(webhooks),
+                Arc::new(Shutdown::default()),
+                Arc::new(Startup::finished()),
+                Arc::new(Metrics::new(None)),
+                Arc::new(Maintenance::load(data_dir.join("maintenance.json")).unwrap()),
+            ),
+            pool,
+            data_dir,
+            cookies: Mutex::default(),
+        }
+    }
+
+    /// Get the pool of connections to the test database, for the factories
+    pub fn pool(&self) -> &Arc<DbPool> {
+        &self.pool
+    }
+
+    /// Get the value of a cookie the client was sent, if any
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies.lock().unwrap().get(name).cloned()
+    }
+
+    /// Set a cookie sent with the following requests, e.g. a token that isn't valid
+    pub fn set_cookie(&self, name: &str, value: &str) {
+        self.cookies
+            .lock()
+            .unwrap()
+            .insert(name.to_owned(), value.to_owned());
+    }
+
+    /// Forget the cookies the client was sent
+    pub fn clear_cookies(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    /// Log in, keeping the session cookie for the following requests
+    pub async fn login(&self, username: &str, password: &str) -> TestResponse {
+        self.post(
+            "/api/v1/auth/login",
+            serde_json::json!({"username": username, "password": password}),
+        )
+        .await
+    }
+
+    /// Send a `GET` request
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.request(Method::GET, uri, None).await
+    }
+
+    /// Send a `POST` request with a JSON body
+    pub async fn post(&self, uri: &str, body: Value) -> TestResponse {
+        self.request(Method::POST, uri, Some(body)).await
+    }
+
+    /// Send a `PUT` request with a JSON body
+    pub async fn put(&self, uri: &str, body: Value) -> TestResponse {
+        self.request(Method::PUT, uri, Some(body)).await
+    }
+
+    /// Send a `DELETE` request
+    pub async fn delete(&self, uri: &str) -> TestResponse {
+        self.request(Method::DELETE, uri, None).await
+    }
+
+    /// Send a request with a JSON body, if any
+    pub async fn request(&self, method: Method, uri: &str, body: Option<Value>) -> TestResponse {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        self.send(request).await
+    }
+
+    /// Send a request with the cookies of the client, and keep the cookies of its response
+    pub async fn send(&self, mut request: Request<Body>) -> TestResponse {
+        let cookies = self
+            .cookies
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if !cookies.is_empty() {
+            request
+                .headers_mut()
+                .insert(header::COOKIE, cookies.parse().unwrap());
+        }
+
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        for cookie in headers.get_all(header::SET_COOKIE) {
+            let pair = cookie.to_str().unwrap().split(';').next().unwrap();
+            if let Some((name, value)) = pair.split_once('=') {
+                self.set_cookie(name.trim(), value.trim());
+            }
+        }
+
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+}
+
+impl Drop for TestClient {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+/// A response of the application, with its body read
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    /// Get the body as JSON, panicking if it isn't
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| {
+            panic!(
+                "The body isn't JSON ({e}): {}",
+                String::from_utf8_lossy(&self.body)
+            )
+        })
+    }
+
+    /// Get the error code of an error response
+    pub fn code(&self) -> u64 {
+        self.json()["code"].as_u64().unwrap()
+    }
+}
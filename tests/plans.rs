@@ -0,0 +1,68 @@
+mod common;
+
+use common::{factories, TestClient};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_plans_of_logged_in_user() {
+    let client = TestClient::new();
+    let user = factories::logged_in_user(&client, "alice").await;
+
+    let response = client.post("/api/v1/plans/Retirement", json!(null)).await;
+    assert_eq!(response.status, 201);
+    assert_eq!(response.json()["name"], "Retirement");
+    assert_eq!(response.json()["user_id"], user.id());
+
+    let response = client.get("/api/v1/plans").await;
+    assert_eq!(response.status, 200);
+    let plans = response.json();
+    assert_eq!(plans["total"], 1);
+    assert_eq!(plans["items"][0]["name"], "Retirement");
+    assert_eq!(plans["items"][0]["user_id"], user.id());
+
+    let response = client.delete("/api/v1/plans/Retirement").await;
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json(), json!({"message": "Plan deleted"}));
+    let plans = client.get("/api/v1/plans").await.json();
+    assert_eq!(plans["items"], json!([]));
+    assert_eq!(plans["total_pages"], 0);
+}
+
+#[tokio::test]
+async fn test_plans_of_other_users_are_hidden() {
+    let client = TestClient::new();
+    let bob = factories::user(&client, "bob");
+    factories::plan(&client, &bob, "Boat");
+    let alice = factories::logged_in_user(&client, "alice").await;
+    factories::plan(&client, &alice, "House");
+
+    let plans = client.get("/api/v1/plans").await.json();
+    assert_eq!(plans["total"], 1);
+    assert_eq!(plans["items"][0]["name"], "House");
+
+    // Deleting a plan of another user leaves it be
+    client.delete("/api/v1/plans/Boat").await;
+    client.clear_cookies();
+    client.login("bob", factories::PASSWORD).await;
+    let plans = client.get("/api/v1/plans").await.json();
+    assert_eq!(plans["items"][0]["name"], "Boat");
+}
+
+#[tokio::test]
+async fn test_plans_need_a_session() {
+    let client = TestClient::new();
+    let user = factories::user(&client, "alice");
+    factories::plan(&client, &user, "House");
+
+    for response in [
+        client.get("/api/v1/plans").await,
+        client.post("/api/v1/plans/Boat", json!(null)).await,
+        client.delete("/api/v1/plans/House").await,
+    ] {
+        assert_eq!(response.status, 401);
+        assert_eq!(response.code(), 40005);
+    }
+
+    client.set_cookie("token", "invalid");
+    assert_eq!(client.get("/api/v1/plans").await.status, 401);
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::{factories, TestClient};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_user_lifecycle() {
+    let client = TestClient::new();
+
+    let response = client
+        .post(
+            "/api/v1/users",
+            json!({"name": "alice", "password": "alice_password"}),
+        )
+        .await;
+    assert_eq!(response.status, 201, "{:?}", response.body);
+    let created = response.json();
+    assert_eq!(created["username"], "alice");
+    let id = created["id"].as_i64().unwrap();
+
+    let response = client.get("/api/v1/users/username/alice").await;
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["id"], id);
+    assert!(response.json().get("pw_hash").is_none());
+
+    // Only the user can update or delete themselves
+    let update = json!({"name": "alicia"});
+    let response = client
+        .put(&format!("/api/v1/users/{id}"), update.clone())
+        .await;
+    assert_eq!(response.status, 401);
+    let response = client.delete(&format!("/api/v1/users/{id}")).await;
+    assert_eq!(response.status, 401);
+    assert_eq!(client.login("alice", "alice_password").await.status, 200);
+
+    // Passwords aren't changed with the user, only with the current password
+    let response = client
+        .put(
+            &format!("/api/v1/users/{id}"),
+            json!({"name": "alice", "password": "new_password"}),
+        )
+        .await;
+    assert_eq!(response.status, 422);
+    assert_eq!(response.code(), 40015);
+
+    let response = client.put(&format!("/api/v1/users/{id}"), update).await;
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.json(),
+        json!({"message": format!("Updated user {id} successfully")})
+    );
+    assert_eq!(client.get("/api/v1/users/username/alice").await.status, 404);
+    assert_eq!(client.login("alicia", "alice_password").await.status, 200);
+
+    let response = client.delete(&format!("/api/v1/users/{id}")).await;
+    assert_eq!(response.status, 200);
+    let response = client.get("/api/v1/users/username/alicia").await;
+    assert_eq!(response.status, 404);
+}
+
+#[tokio::test]
+async fn test_invalid_users_are_refused() {
+    let client = TestClient::new();
+
+    let response = client
+        .post(
+            "/api/v1/users",
+            json!({"name": " ", "password": "password"}),
+        )
+        .await;
+    assert_eq!(response.status, 400);
+    assert_eq!(response.code(), 40008);
+
+    let response = client
+        .post(
+            "/api/v1/users",
+            json!({"name": "alice", "password": "short"}),
+        )
+        .await;
+    assert_eq!(response.status, 400);
+    assert_eq!(response.code(), 40008);
+
+    let response = client.post("/api/v1/users", json!({"name": "alice"})).await;
+    assert_eq!(response.status, 422);
+    assert_eq!(response.code(), 40015);
+
+    assert_eq!(client.get("/api/v1/users/username/alice").await.status, 404);
+}
+
+#[tokio::test]
+async fn test_preferred_currency_of_the_logged_in_user() {
+    let client = TestClient::new();
+    let currency = json!({"currency": "EUR"});
+
+    let response = client
+        .put("/api/v1/users/me/preferred-currency", currency.clone())
+        .await;
+    assert_eq!(response.status, 401);
+
+    let user = factories::logged_in_user(&client, "alice").await;
+    let response = client
+        .put("/api/v1/users/me/preferred-currency", currency)
+        .await;
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["id"], user.id());
+    assert_eq!(response.json()["preferred_currency"], "EUR");
+
+    let response = client
+        .put(
+            "/api/v1/users/me/preferred-currency",
+            json!({"currency": "euro"}),
+        )
+        .await;
+    assert_eq!(response.status, 400);
+}